@@ -0,0 +1,84 @@
+//! The time of day and the weather, as last told by the server, with the
+//! clock kept going in between so the light changes smoothly.
+
+use std::time::Instant;
+
+use core_net::packet::Packet;
+use core_rules::ambiance::{self, Weather, MINUTES_PER_DAY};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ambiance {
+    /// The time of day when last told, in minutes since midnight
+    pub minute: u16,
+    /// How many seconds a day lasts; 0 stops the clock
+    pub day_length: u32,
+    pub weather: Weather,
+    /// When it was last told
+    pub since: Option<Instant>,
+}
+
+impl Default for Ambiance {
+    /// Clear noon, until the server says otherwise
+    fn default() -> Ambiance {
+        Ambiance { minute: 12 * 60, day_length: 0, weather: Weather::Clear, since: None }
+    }
+}
+
+impl Ambiance {
+    pub fn apply(&mut self, packet: &Packet) {
+        self.apply_at(packet, Instant::now());
+    }
+
+    /// `apply`, with the packet arriving at `now`
+    pub fn apply_at(&mut self, packet: &Packet, now: Instant) {
+        if let Packet::Ambiance { minute, day_length, weather } = *packet {
+            self.minute = minute % MINUTES_PER_DAY;
+            self.day_length = day_length;
+            // a weather this client doesn't know is drawn as clear
+            self.weather = Weather::from_code(weather).unwrap_or(Weather::Clear);
+            self.since = Some(now);
+        }
+    }
+
+    /// The time of day at `now`
+    pub fn minute_at(&self, now: Instant) -> u16 {
+        let elapsed = match self.since {
+            Some(since) if self.day_length > 0 => now.saturating_duration_since(since).as_millis() as u64,
+            _ => 0,
+        };
+        let passed = elapsed * MINUTES_PER_DAY as u64 / (self.day_length as u64 * 1000).max(1);
+        ((self.minute as u64 + passed) % MINUTES_PER_DAY as u64) as u16
+    }
+
+    /// The colour the map is multiplied by at `now`
+    pub fn tint(&self, now: Instant) -> [u8; 3] {
+        ambiance::tint(self.minute_at(now), self.weather)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[test]
+    fn test_clock() {
+        let start = Instant::now();
+        let mut ambiance = Ambiance::default();
+        assert_eq!(ambiance.minute_at(start + Duration::from_secs(60)), 12 * 60);
+        assert_eq!(ambiance.tint(start), [255, 255, 255]);
+
+        // a day of 24 seconds, an hour a second
+        ambiance.apply_at(&Packet::Ambiance { minute: 23 * 60, day_length: 24, weather: 1 }, start);
+        assert_eq!(ambiance.weather, Weather::Rain);
+        assert_eq!(ambiance.minute_at(start), 23 * 60);
+        assert_eq!(ambiance.minute_at(start + Duration::from_millis(500)), 23 * 60 + 30);
+        assert_eq!(ambiance.minute_at(start + Duration::from_secs(2)), 60);
+
+        ambiance.apply_at(&Packet::Ambiance { minute: 60, day_length: 24, weather: 9 }, start);
+        assert_eq!(ambiance.weather, Weather::Clear);
+        ambiance.apply_at(&Packet::Despawn { id: 1 }, start);
+        assert_eq!(ambiance.minute, 60);
+    }
+}
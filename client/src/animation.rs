@@ -0,0 +1,85 @@
+//! Playing an animation of an RMD file: the frame to draw at a time, and the
+//! markers of the frames gone by since the last redraw, so the footsteps and
+//! hit sounds play on the frame they belong to whatever the frame rate.
+
+use std::time::Instant;
+
+use core_compat::entity::rmd_animation::{Marker, RmdAnimation};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Playback {
+    /// How long every frame lasts, in milliseconds
+    pub delay: u32,
+    pub started: Instant,
+    /// How far into the animation the markers were handed out, in
+    /// milliseconds
+    played: u64,
+}
+
+impl Playback {
+    pub fn new(delay: u32, started: Instant) -> Playback {
+        Playback { delay, started, played: 0 }
+    }
+
+    /// Which of the frames of `animation` is drawn at `now`; it loops
+    pub fn frame_at(&self, animation: &RmdAnimation, now: Instant) -> Option<usize> {
+        let len = animation.frames().len() as u64;
+        if len == 0 {
+            return None;
+        }
+        Some((self.elapsed(now) / self.delay.max(1) as u64 % len) as usize)
+    }
+
+    /// The markers of the frames that started since the last call, the
+    /// first frame's included on the first call, in order
+    pub fn advance(&mut self, animation: &RmdAnimation, now: Instant) -> Vec<Marker> {
+        // the first frame starts at 0, so it has to be passed by a bit
+        let to = self.elapsed(now) + 1;
+        if to <= self.played {
+            return Vec::new();
+        }
+        let markers = animation.markers_between(self.played, to, self.delay);
+        self.played = to;
+        markers
+    }
+
+    fn elapsed(&self, now: Instant) -> u64 {
+        now.saturating_duration_since(self.started).as_millis() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[test]
+    fn test_playback() {
+        // a swing on the second of four frames and the impact on the third
+        let mut animation = RmdAnimation::new(4);
+        for frame in 0..4 {
+            animation.add_frame(frame);
+        }
+        animation.add_marker(0, Marker::Step);
+        animation.add_marker(1, Marker::Swing);
+        animation.add_marker(2, Marker::Impact);
+        assert_eq!(animation.marker_time(Marker::Impact, 100), Some(200));
+        assert_eq!(animation.markers_at(1).collect::<Vec<_>>(), vec![Marker::Swing]);
+
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut playback = Playback::new(100, start);
+        assert_eq!(playback.frame_at(&animation, at(250)), Some(2));
+        assert_eq!(playback.frame_at(&animation, at(450)), Some(0));
+
+        assert_eq!(playback.advance(&animation, start), vec![Marker::Step]);
+        assert_eq!(playback.advance(&animation, at(50)), vec![]);
+        assert_eq!(playback.advance(&animation, at(100)), vec![Marker::Swing]);
+        assert_eq!(playback.advance(&animation, at(100)), vec![]);
+        // a slow redraw misses no marker, and the animation loops
+        assert_eq!(playback.advance(&animation, at(420)), vec![Marker::Impact, Marker::Step]);
+        assert_eq!(playback.advance(&animation, at(1150)), vec![Marker::Swing, Marker::Impact, Marker::Step,
+                                                                Marker::Swing, Marker::Impact]);
+    }
+}
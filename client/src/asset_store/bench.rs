@@ -0,0 +1,161 @@
+//! Load latency and memory of an asset store.
+//!
+//! Memory is the resident set size of the process, so it's only known where
+//! there's `/proc` and the backends should be measured in a fresh process, or
+//! at least one after another and not side by side. The loaded assets are
+//! dropped right away, what stays is the memory of the store itself.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::asset_store::AssetStore;
+use crate::error::Error;
+
+pub struct Report {
+    pub backend: &'static str,
+    /// Time to open the store and list its keys
+    pub open: Duration,
+    pub assets: usize,
+    pub bytes: u64,
+    /// Load time of each asset, sorted
+    pub latencies: Vec<Duration>,
+    /// Growth of the resident set by opening the store
+    pub rss_open: Option<i64>,
+    /// Growth of the resident set after loading everything
+    pub rss_loaded: Option<i64>,
+}
+
+impl Report {
+    pub fn total(&self) -> Duration {
+        self.latencies.iter().sum()
+    }
+
+    /// Nearest-rank percentile of the load times
+    pub fn percentile(&self, p: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::from_secs(0);
+        }
+        let rank = (p / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let mib = |bytes: Option<i64>| match bytes {
+            Some(bytes) => format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0)),
+            None => "?".into(),
+        };
+        let total = self.total().as_secs_f64();
+        let throughput = if total > 0.0 { self.bytes as f64 / (1024.0 * 1024.0) / total } else { 0.0 };
+        writeln!(f, "{}:", self.backend)?;
+        writeln!(f, "  open     {:.1} ms", ms(self.open))?;
+        writeln!(f, "  loaded   {} assets, {} bytes in {:.1} ms ({:.1} MiB/s)",
+                 self.assets, self.bytes, ms(self.total()), throughput)?;
+        writeln!(f, "  latency  p50 {:.3} ms, p90 {:.3} ms, p99 {:.3} ms, max {:.3} ms",
+                 ms(self.percentile(50.0)), ms(self.percentile(90.0)),
+                 ms(self.percentile(99.0)), ms(self.percentile(100.0)))?;
+        writeln!(f, "  memory   {} after open, {} after loading", mib(self.rss_open), mib(self.rss_loaded))
+    }
+}
+
+/// Opens a store with `open` and loads every asset once
+pub fn measure<F>(open: F) -> Result<Report, Error>
+    where F: FnOnce() -> Result<Box<dyn AssetStore>, Error>
+{
+    let rss_before = rss_bytes();
+    let start = Instant::now();
+    let mut store = open()?;
+    let keys = store.keys()?;
+    let open_time = start.elapsed();
+    let rss_open = rss_bytes();
+
+    let mut latencies = Vec::with_capacity(keys.len());
+    let mut bytes = 0;
+    for key in keys.iter() {
+        let start = Instant::now();
+        let data = store.load(key)?;
+        latencies.push(start.elapsed());
+        bytes += data.len() as u64;
+    }
+    latencies.sort();
+    let rss_loaded = rss_bytes();
+
+    let growth = |after: Option<u64>| match (rss_before, after) {
+        (Some(before), Some(after)) => Some(after as i64 - before as i64),
+        _ => None,
+    };
+    Ok(Report {
+        backend: store.name(),
+        open: open_time,
+        assets: keys.len(),
+        bytes,
+        latencies,
+        rss_open: growth(rss_open),
+        rss_loaded: growth(rss_loaded),
+    })
+}
+
+/// The resident set size of the process, where the system tells
+pub fn rss_bytes() -> Option<u64> {
+    std::fs::read_to_string("/proc/self/status").ok().and_then(|status| parse_vm_rss(&status))
+}
+
+fn parse_vm_rss(status: &str) -> Option<u64> {
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib: u64 = line["VmRSS:".len()..].trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fake;
+
+    impl AssetStore for Fake {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        fn keys(&self) -> Result<Vec<String>, Error> {
+            Ok((0..10).map(|n| n.to_string()).collect())
+        }
+
+        fn load(&mut self, key: &str) -> Result<Vec<u8>, Error> {
+            Ok(vec![0; key.parse::<usize>().unwrap()])
+        }
+    }
+
+    #[test]
+    fn test_measure() {
+        let report = measure(|| Ok(Box::new(Fake))).unwrap();
+        assert_eq!((report.backend, report.assets, report.bytes), ("fake", 10, 45));
+        assert!(report.percentile(50.0) <= report.percentile(100.0));
+        assert!(format!("{}", report).starts_with("fake:\n"));
+    }
+
+    #[test]
+    fn test_percentile() {
+        let report = Report {
+            backend: "fake",
+            open: Duration::from_secs(0),
+            assets: 10,
+            bytes: 0,
+            latencies: (1..=10).map(Duration::from_millis).collect(),
+            rss_open: None,
+            rss_loaded: None,
+        };
+        assert_eq!(report.percentile(50.0), Duration::from_millis(5));
+        assert_eq!(report.percentile(99.0), Duration::from_millis(10));
+        assert_eq!(report.total(), Duration::from_millis(55));
+    }
+
+    #[test]
+    fn test_parse_vm_rss() {
+        let status = "Name:\tbench\nVmPeak:\t  20000 kB\nVmRSS:\t   1234 kB\nThreads:\t1\n";
+        assert_eq!(parse_vm_rss(status), Some(1234 * 1024));
+        assert_eq!(parse_vm_rss("Name:\tbench\n"), None);
+    }
+}
@@ -0,0 +1,24 @@
+//! Where the client gets the bytes of its data files from.
+//!
+//! Assets are addressed by their path below the data folder with `/`
+//! separators, e.g. `RLEs/Chr/chr00001.rle`. Besides reading the folder the
+//! way the original client ships it, the assets can be packed into a single
+//! archive or a SQLite database; `bench` compares the three.
+
+pub mod bench;
+pub mod packed;
+pub mod raw;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+use crate::error::Error;
+
+pub trait AssetStore {
+    /// Name of the backend, for reports
+    fn name(&self) -> &'static str;
+
+    /// The keys of all assets, sorted
+    fn keys(&self) -> Result<Vec<String>, Error>;
+
+    fn load(&mut self, key: &str) -> Result<Vec<u8>, Error>;
+}
@@ -0,0 +1,147 @@
+//! All assets in one file: an index up front, the data after it.
+//!
+//! [HEADER]
+//! 4 bytes magic `NVPK`
+//! u32 number of assets
+//! [INDEX] per asset, sorted by key
+//! u16 key length
+//! key, UTF-8
+//! u64 offset of the data from the start of the file
+//! u64 length of the data
+//! [DATA]
+//!
+//! Numbers are little endian. Opening reads only the index.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::asset_store::AssetStore;
+use crate::error::Error;
+
+const MAGIC: &[u8; 4] = b"NVPK";
+
+pub struct PackedArchive {
+    file: File,
+    /// Offset and length by key
+    index: BTreeMap<String, (u64, u64)>,
+}
+
+impl PackedArchive {
+    /// Writes every asset of `source` into a new archive at `path`
+    pub fn pack(source: &mut dyn AssetStore, path: &Path) -> Result<(), Error> {
+        let keys = source.keys()?;
+        let index_size: usize = keys.iter().map(|key| 2 + key.len() + 16).sum();
+        let mut offset = (8 + index_size) as u64;
+        let mut data = Vec::with_capacity(keys.len());
+        for key in keys.iter() {
+            let asset = source.load(key)?;
+            data.push((offset, asset.len() as u64));
+            offset += asset.len() as u64;
+        }
+
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&(keys.len() as u32).to_le_bytes())?;
+        for (key, &(offset, length)) in keys.iter().zip(data.iter()) {
+            out.write_all(&(key.len() as u16).to_le_bytes())?;
+            out.write_all(key.as_bytes())?;
+            out.write_all(&offset.to_le_bytes())?;
+            out.write_all(&length.to_le_bytes())?;
+        }
+        // loaded a second time rather than held, the Chr sheets add up
+        for key in keys.iter() {
+            out.write_all(&source.load(key)?)?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    pub fn open(path: &Path) -> Result<PackedArchive, Error> {
+        let file = File::open(path)?;
+        let mut reader = BufReader::new(file.try_clone()?);
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::Str(format!("`{}` isn't a packed archive", path.display())));
+        }
+        let count = read_u32(&mut reader)?;
+        let mut index = BTreeMap::new();
+        for _ in 0..count {
+            let mut length = [0u8; 2];
+            reader.read_exact(&mut length)?;
+            let mut key = vec![0u8; u16::from_le_bytes(length) as usize];
+            reader.read_exact(&mut key)?;
+            let key = String::from_utf8(key).map_err(|e| e.utf8_error())?;
+            index.insert(key, (read_u64(&mut reader)?, read_u64(&mut reader)?));
+        }
+        Ok(PackedArchive { file, index })
+    }
+}
+
+impl AssetStore for PackedArchive {
+    fn name(&self) -> &'static str {
+        "packed archive"
+    }
+
+    fn keys(&self) -> Result<Vec<String>, Error> {
+        Ok(self.index.keys().cloned().collect())
+    }
+
+    fn load(&mut self, key: &str) -> Result<Vec<u8>, Error> {
+        let (offset, length) = match self.index.get(key) {
+            Some(&entry) => entry,
+            None => return Err(Error::Str(format!("no asset `{}`", key))),
+        };
+        let mut data = vec![0u8; length as usize];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut data)?;
+        Ok(data)
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, Error> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, Error> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::asset_store::raw::RawFiles;
+
+    #[test]
+    fn test_pack_and_load() {
+        let dir = std::env::temp_dir().join(format!("novluno_packed_{}", std::process::id()));
+        let data = dir.join("data");
+        fs::create_dir_all(data.join("RLEs/Chr")).unwrap();
+        fs::write(data.join("RLEs/Chr/chr00001.rle"), vec![7u8; 1000]).unwrap();
+        fs::write(data.join("RLEs/chr.lst"), b"list").unwrap();
+        fs::write(data.join("empty"), b"").unwrap();
+
+        let mut raw = RawFiles::new(&data);
+        assert_eq!(raw.keys().unwrap(), vec!["RLEs/Chr/chr00001.rle", "RLEs/chr.lst", "empty"]);
+        let archive_path = dir.join("assets.pak");
+        PackedArchive::pack(&mut raw, &archive_path).unwrap();
+
+        let mut archive = PackedArchive::open(&archive_path).unwrap();
+        assert_eq!(archive.keys().unwrap(), raw.keys().unwrap());
+        for key in raw.keys().unwrap() {
+            assert_eq!(archive.load(&key).unwrap(), raw.load(&key).unwrap());
+        }
+        assert!(archive.load("missing").is_err());
+        assert!(PackedArchive::open(&data.join("RLEs/chr.lst")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
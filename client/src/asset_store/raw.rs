@@ -0,0 +1,48 @@
+//! The data folder as it is
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::asset_store::AssetStore;
+use crate::error::Error;
+
+pub struct RawFiles {
+    root: PathBuf,
+}
+
+impl RawFiles {
+    pub fn new(root: &Path) -> RawFiles {
+        RawFiles { root: root.into() }
+    }
+}
+
+impl AssetStore for RawFiles {
+    fn name(&self) -> &'static str {
+        "raw files"
+    }
+
+    fn keys(&self) -> Result<Vec<String>, Error> {
+        let mut keys = Vec::new();
+        collect_keys(&self.root, "", &mut keys)?;
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn load(&mut self, key: &str) -> Result<Vec<u8>, Error> {
+        Ok(fs::read(self.root.join(key))?)
+    }
+}
+
+fn collect_keys(folder: &Path, prefix: &str, keys: &mut Vec<String>) -> Result<(), Error> {
+    for entry in fs::read_dir(folder)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let key = format!("{}{}", prefix, name);
+        if entry.file_type()?.is_dir() {
+            collect_keys(&entry.path(), &format!("{}/", key), keys)?;
+        } else {
+            keys.push(key);
+        }
+    }
+    Ok(())
+}
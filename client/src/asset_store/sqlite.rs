@@ -0,0 +1,209 @@
+//! All assets in a SQLite database, one row per asset
+//!
+//! Writing takes the lock of the database (see
+//! `core_compat::utility::lock`) and opening checks it, so a viewer that
+//! only reads opens the database read only and can't be started in the
+//! middle of an import; it holds a shared lock meanwhile, which keeps the
+//! imports out until it's closed.
+//!
+//! Mods are databases of their own, attached to the base one when it's
+//! opened: an asset of a mod replaces the one of the same key of the base
+//! and of the mods before it. Turning a mod off is opening without it;
+//! nothing is merged or imported again. SQLite attaches up to 10 databases
+//! unless it was built for more.
+
+use std::path::{Path, PathBuf};
+
+use sql::{Connection, OpenFlags};
+
+use core_compat::utility::lock::{ReadLock, WriteLock};
+
+use crate::asset_store::AssetStore;
+use crate::error::Error;
+
+pub struct SqliteStore {
+    connection: Connection,
+    read_only: bool,
+    /// How many mods are attached, as `mod1`, `mod2`, ..
+    mods: usize,
+    /// The shared locks of the databases opened read only, held as long as
+    /// the store
+    locks: Vec<ReadLock>,
+}
+
+impl SqliteStore {
+    /// Writes every asset of `source` into a new database at `path`
+    pub fn create(source: &mut dyn AssetStore, path: &Path) -> Result<(), Error> {
+        let _lock = WriteLock::acquire(path)?;
+        let _ = std::fs::remove_file(path);
+        let mut connection = Connection::open(path)?;
+        connection.execute(
+            "CREATE TABLE asset (
+                key      TEXT PRIMARY KEY,
+                data     BLOB NOT NULL
+            )", [])?;
+        let transaction = connection.transaction()?;
+        for key in source.keys()? {
+            let data = source.load(&key)?;
+            transaction.execute("INSERT INTO asset (key, data) VALUES (?1, ?2)", (&key, &data))?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    pub fn open(path: &Path) -> Result<SqliteStore, Error> {
+        // only waits for the writers; `put` takes the write lock itself, so
+        // the shared one isn't kept
+        drop(ReadLock::acquire(path)?);
+        Ok(SqliteStore { connection: Connection::open(path)?, read_only: false, mods: 0, locks: Vec::new() })
+    }
+
+    /// Opens the database so nothing can be written through the handle;
+    /// `put` fails with `Error::ReadOnly`
+    pub fn open_read_only(path: &Path) -> Result<SqliteStore, Error> {
+        let lock = ReadLock::acquire(path)?;
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        Ok(SqliteStore { connection, read_only: true, mods: 0, locks: vec![lock] })
+    }
+
+    /// Opens the base database read only with the mods attached, the last
+    /// one taking precedence
+    pub fn open_with_mods(base: &Path, mods: &[&Path]) -> Result<SqliteStore, Error> {
+        let mut store = SqliteStore::open_read_only(base)?;
+        for (number, path) in mods.iter().enumerate() {
+            store.locks.push(ReadLock::acquire(path)?);
+            let path = path.to_str().ok_or_else(|| Error::Str(format!("`{}` isn't UTF-8", path.display())))?;
+            store.connection.execute(&format!("ATTACH DATABASE ?1 AS mod{}", number + 1), [path])?;
+            store.mods += 1;
+        }
+        Ok(store)
+    }
+
+    /// The schemas of the base and the mods, by precedence from low to high
+    fn schemas(&self) -> Vec<String> {
+        let mut schemas = vec!["main".to_string()];
+        schemas.extend((1..=self.mods).map(|number| format!("mod{}", number)));
+        schemas
+    }
+
+    /// Adds the asset, or replaces the one of the same key, holding the lock
+    /// of the database meanwhile
+    pub fn put(&mut self, key: &str, data: &[u8]) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::ReadOnly(format!("can't write `{}` to a database opened read only", key)));
+        }
+        let path = self.connection.path().map(PathBuf::from)
+            .ok_or_else(|| Error::Str("the database has no file to lock".into()))?;
+        let _lock = WriteLock::acquire(&path)?;
+        self.connection.execute("INSERT OR REPLACE INTO asset (key, data) VALUES (?1, ?2)", (key, data))?;
+        Ok(())
+    }
+}
+
+impl AssetStore for SqliteStore {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn keys(&self) -> Result<Vec<String>, Error> {
+        let selects: Vec<String> = self.schemas().iter().map(|schema| format!("SELECT key FROM {}.asset", schema)).collect();
+        let mut statement = self.connection.prepare(&format!("{} ORDER BY key", selects.join(" UNION ")))?;
+        let rows = statement.query_map([], |row| row.get(0))?;
+        let mut keys = Vec::new();
+        for key in rows {
+            keys.push(key?);
+        }
+        Ok(keys)
+    }
+
+    fn load(&mut self, key: &str) -> Result<Vec<u8>, Error> {
+        let selects: Vec<String> = self.schemas().iter().enumerate()
+            .map(|(layer, schema)| format!("SELECT data, {} AS layer FROM {}.asset WHERE key = ?1", layer, schema))
+            .collect();
+        let query = format!("SELECT data FROM ({}) ORDER BY layer DESC LIMIT 1", selects.join(" UNION ALL "));
+        let mut statement = self.connection.prepare_cached(&query)?;
+        let data = statement.query_row([key], |row| row.get(0))?;
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use core_compat::error::Error as CompatError;
+
+    use crate::asset_store::raw::RawFiles;
+
+    #[test]
+    fn test_create_and_load() {
+        let dir = std::env::temp_dir().join(format!("novluno_sqlite_{}", std::process::id()));
+        let data = dir.join("data");
+        fs::create_dir_all(data.join("DATAs")).unwrap();
+        fs::write(data.join("DATAs/tle.rmd"), vec![3u8; 500]).unwrap();
+        fs::write(data.join("DATAs/obj.rmd"), b"rmd").unwrap();
+
+        let mut raw = RawFiles::new(&data);
+        let database = dir.join("assets.sqlite");
+        SqliteStore::create(&mut raw, &database).unwrap();
+
+        let mut store = SqliteStore::open(&database).unwrap();
+        assert_eq!(store.keys().unwrap(), vec!["DATAs/obj.rmd", "DATAs/tle.rmd"]);
+        assert_eq!(store.load("DATAs/tle.rmd").unwrap(), vec![3u8; 500]);
+        assert!(store.load("missing").is_err());
+        store.put("DATAs/obj.rmd", b"new rmd").unwrap();
+        assert_eq!(store.load("DATAs/obj.rmd").unwrap(), b"new rmd");
+
+        let mut reader = SqliteStore::open_read_only(&database).unwrap();
+        assert_eq!(reader.load("DATAs/obj.rmd").unwrap(), b"new rmd");
+        assert!(matches!(reader.put("DATAs/obj.rmd", b"rmd"), Err(Error::ReadOnly(_))));
+        // nor is it written under a reader
+        assert!(matches!(store.put("DATAs/obj.rmd", b"rmd"), Err(Error::Rm(CompatError::Locked { .. }))));
+        drop(reader);
+
+        // nothing opens it while something writes to it, nor writes to it twice
+        let lock = WriteLock::acquire(&database).unwrap();
+        assert!(matches!(SqliteStore::open_read_only(&database), Err(Error::Rm(CompatError::Locked { .. }))));
+        assert!(matches!(store.put("DATAs/obj.rmd", b"rmd"), Err(Error::Rm(CompatError::Locked { .. }))));
+        assert!(matches!(SqliteStore::create(&mut RawFiles::new(&data), &database),
+                         Err(Error::Rm(CompatError::Locked { .. }))));
+        drop(lock);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_open_with_mods() {
+        let dir = std::env::temp_dir().join(format!("novluno_sqlite_mods_{}", std::process::id()));
+        let database = |name: &str, assets: &[(&str, &[u8])]| {
+            let data = dir.join(name);
+            for &(key, bytes) in assets.iter() {
+                fs::create_dir_all(data.join(key).parent().unwrap()).unwrap();
+                fs::write(data.join(key), bytes).unwrap();
+            }
+            let path = dir.join(format!("{}.sqlite", name));
+            SqliteStore::create(&mut RawFiles::new(&data), &path).unwrap();
+            path
+        };
+        let base = database("base", &[("RLEs/a.rle", b"base a"), ("RLEs/b.rle", b"base b")]);
+        let slimes = database("slimes", &[("RLEs/b.rle", b"slimes b"), ("RLEs/c.rle", b"slimes c")]);
+        let blue = database("blue", &[("RLEs/c.rle", b"blue c")]);
+
+        let mut store = SqliteStore::open_with_mods(&base, &[&slimes, &blue]).unwrap();
+        assert_eq!(store.keys().unwrap(), vec!["RLEs/a.rle", "RLEs/b.rle", "RLEs/c.rle"]);
+        assert_eq!(store.load("RLEs/a.rle").unwrap(), b"base a");
+        assert_eq!(store.load("RLEs/b.rle").unwrap(), b"slimes b");
+        assert_eq!(store.load("RLEs/c.rle").unwrap(), b"blue c");
+        assert!(store.load("RLEs/d.rle").is_err());
+        assert!(matches!(store.put("RLEs/a.rle", b"a"), Err(Error::ReadOnly(_))));
+
+        // without the blue mod
+        let mut store = SqliteStore::open_with_mods(&base, &[&slimes]).unwrap();
+        assert_eq!(store.load("RLEs/c.rle").unwrap(), b"slimes c");
+        let mut store = SqliteStore::open_with_mods(&base, &[]).unwrap();
+        assert_eq!(store.keys().unwrap(), vec!["RLEs/a.rle", "RLEs/b.rle"]);
+        assert_eq!(store.load("RLEs/b.rle").unwrap(), b"base b");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
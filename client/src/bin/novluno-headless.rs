@@ -0,0 +1,82 @@
+//! `novluno-headless --server <addr> --name <name> [--script <file>] [--record <file>]`
+//!
+//! Runs the client without a window: logs in, runs the script commands (see
+//! `client::headless::Command`), from stdin without a script, and prints the
+//! state the client ends up with. `--record` writes what the server sent to
+//! a session file, for `novluno-replay-profile`.
+
+extern crate client;
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use client::headless::{Command, HeadlessClient};
+
+static USAGE: &str = "usage: novluno-headless --server <addr> --name <name> [--script <file>] [--record <file>]";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (mut server, mut name, mut script, mut record) = (None, None, None, None);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match (arg.as_str(), iter.next()) {
+            ("--server", Some(value)) => server = Some(value.clone()),
+            ("--name", Some(value)) => name = Some(value.clone()),
+            ("--script", Some(value)) => script = Some(value.clone()),
+            ("--record", Some(value)) => record = Some(value.clone()),
+            _ => usage(),
+        }
+    }
+    let (server, name) = match (server, name) {
+        (Some(server), Some(name)) => (server, name),
+        _ => usage(),
+    };
+
+    let lines: Box<dyn BufRead> = match script {
+        Some(path) => match File::open(&path) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(error) => fail(&format!("reading `{}` failed with: `{}`", path, error)),
+        },
+        None => Box::new(BufReader::new(std::io::stdin())),
+    };
+
+    let connected = match record {
+        Some(_) => HeadlessClient::connect_recording(server.as_str(), &name),
+        None => HeadlessClient::connect(server.as_str(), &name),
+    };
+    let mut client = match connected {
+        Ok(client) => client,
+        Err(error) => fail(&format!("connecting failed with: {:?}", error)),
+    };
+    for (number, line) in lines.lines().enumerate() {
+        let line = line.unwrap_or_default();
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        match Command::parse(&line) {
+            Some(command) => {
+                if let Err(error) = client.run(&command) {
+                    fail(&format!("line {}: {:?}", number + 1, error));
+                }
+            }
+            None => fail(&format!("line {}: unknown command `{}`", number + 1, line)),
+        }
+    }
+    client.poll();
+    if let (Some(path), Some(session)) = (record, client.session()) {
+        if let Err(error) = std::fs::write(&path, session.to_bytes()) {
+            fail(&format!("writing `{}` failed with: `{}`", path, error));
+        }
+    }
+    println!("{:#?}", client.state);
+}
+
+fn usage() -> ! {
+    println!("{}", USAGE);
+    std::process::exit(2);
+}
+
+fn fail(message: &str) -> ! {
+    println!("{}", message);
+    std::process::exit(1);
+}
@@ -0,0 +1,58 @@
+//! `novluno-replay-profile <session> [--frame <ms>] [--csv <file>]`
+//!
+//! Replays a session recorded by `novluno-headless --record` without a
+//! window and reports the sprites, batches and texture uploads of its frames
+//! (see `client::replay`), to compare renderer changes against real play.
+//! `--csv` writes every frame's numbers as well.
+
+extern crate client;
+
+use client::replay::{replay, Session, FRAME_MS};
+
+static USAGE: &str = "usage: novluno-replay-profile <session> [--frame <ms>] [--csv <file>]";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (mut path, mut frame, mut csv) = (None, FRAME_MS, None);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match (arg.as_str(), iter.clone().next()) {
+            ("--frame", Some(value)) => {
+                frame = value.parse().unwrap_or_else(|_| usage());
+                iter.next();
+            }
+            ("--csv", Some(value)) => { csv = Some(value.clone()); iter.next(); }
+            (value, _) if path.is_none() && !value.starts_with("--") => path = Some(value.to_string()),
+            _ => usage(),
+        }
+    }
+    let path = match path {
+        Some(path) => path,
+        None => usage(),
+    };
+
+    let session = match std::fs::read(&path) {
+        Ok(data) => match Session::read(&data) {
+            Ok(session) => session,
+            Err(error) => fail(&format!("reading `{}` failed with: {:?}", path, error)),
+        },
+        Err(error) => fail(&format!("reading `{}` failed with: `{}`", path, error)),
+    };
+    let report = replay(&session, frame);
+    print!("{}", report);
+    if let Some(csv) = csv {
+        if let Err(error) = std::fs::write(&csv, report.csv()) {
+            fail(&format!("writing `{}` failed with: `{}`", csv, error));
+        }
+    }
+}
+
+fn usage() -> ! {
+    println!("{}", USAGE);
+    std::process::exit(2);
+}
+
+fn fail(message: &str) -> ! {
+    println!("{}", message);
+    std::process::exit(1);
+}
@@ -0,0 +1,87 @@
+//! `novluno-storage-bench <data folder> [--work <folder>] [--only <backend>]`
+//!
+//! Packs the data folder into each storage backend (see
+//! `client::asset_store`), loads every asset from each and reports the load
+//! latency and memory. The SQLite backend needs the `sqlite` feature.
+//!
+//! The first backend reads the files cold, later ones may find the raw files
+//! in the system's cache; run each backend on its own with `--only raw`,
+//! `--only packed` or `--only sqlite` for numbers that can be compared.
+
+extern crate client;
+
+use std::path::{Path, PathBuf};
+
+use client::asset_store::bench::measure;
+use client::asset_store::packed::PackedArchive;
+use client::asset_store::raw::RawFiles;
+use client::asset_store::AssetStore;
+use client::error::Error;
+
+static USAGE: &str = "usage: novluno-storage-bench <data folder> [--work <folder>] [--only raw|packed|sqlite]";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut data = None;
+    let mut work = std::env::temp_dir().join("novluno-storage-bench");
+    let mut only = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match (arg.as_str(), iter.clone().next()) {
+            ("--work", Some(value)) => { work = PathBuf::from(value); iter.next(); }
+            ("--only", Some(value)) => { only = Some(value.clone()); iter.next(); }
+            (path, _) if data.is_none() && !path.starts_with("--") => data = Some(PathBuf::from(path)),
+            _ => usage(),
+        }
+    }
+    let data = match data {
+        Some(data) => data,
+        None => usage(),
+    };
+    if let Err(error) = run(&data, &work, only.as_deref()) {
+        println!("benchmark failed with: {:?}", error);
+        std::process::exit(1);
+    }
+}
+
+fn run(data: &Path, work: &Path, only: Option<&str>) -> Result<(), Error> {
+    std::fs::create_dir_all(work)?;
+    let wanted = |backend: &str| only.is_none_or(|only| only == backend);
+    if only.is_some_and(|only| !["raw", "packed", "sqlite"].contains(&only)) {
+        usage();
+    }
+
+    if wanted("raw") {
+        print!("{}", measure(|| Ok(Box::new(RawFiles::new(data)) as Box<dyn AssetStore>))?);
+    }
+    if wanted("packed") {
+        let archive = work.join("assets.pak");
+        PackedArchive::pack(&mut RawFiles::new(data), &archive)?;
+        print!("{}", measure(|| Ok(Box::new(PackedArchive::open(&archive)?) as Box<dyn AssetStore>))?);
+    }
+    if wanted("sqlite") {
+        sqlite(data, work)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite(data: &Path, work: &Path) -> Result<(), Error> {
+    use client::asset_store::sqlite::SqliteStore;
+
+    let database = work.join("assets.sqlite");
+    SqliteStore::create(&mut RawFiles::new(data), &database)?;
+    print!("{}", measure(|| Ok(Box::new(SqliteStore::open_read_only(&database)?) as Box<dyn AssetStore>))?);
+    Ok(())
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn sqlite(_data: &Path, _work: &Path) -> Result<(), Error> {
+    println!("sqlite: skipped, built without the `sqlite` feature");
+    Ok(())
+}
+
+fn usage() -> ! {
+    println!("{}", USAGE);
+    std::process::exit(2);
+}
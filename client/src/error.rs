@@ -1,11 +1,20 @@
 use std::io;
 use std::str::Utf8Error;
 
+#[cfg(feature = "window")]
 use sdl2::IntegerOrSdlError;
+#[cfg(feature = "window")]
 use sdl2::video::WindowBuildError;
+#[cfg(feature = "window")]
 use sdl2::render::TextureValueError;
 
+use serde_json;
+
 use core_compat;
+use core_net;
+
+#[cfg(feature = "sqlite")]
+use sql;
 
 #[derive(Debug)]
 pub enum Error {
@@ -13,9 +22,16 @@ pub enum Error {
     MapLoad,
     DataLoad,
     Rm(core_compat::error::Error),
+    Net(core_net::error::Error),
+    #[cfg(feature = "sqlite")]
+    Sqlite(sql::Error),
     Io(io::Error),
     Utf8(Utf8Error),
+    Json(serde_json::Error),
     Str(String),
+    /// A write through a handle opened read only
+    ReadOnly(String),
+    Timeout,
     WindowBuildError,
     IntegerOrSdlError,
     TextureValueError,
@@ -27,6 +43,19 @@ impl From<core_compat::error::Error> for Error {
     }
 }
 
+impl From<core_net::error::Error> for Error {
+    fn from(err: core_net::error::Error) -> Error {
+        Error::Net(err)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<sql::Error> for Error {
+    fn from(err: sql::Error) -> Error {
+        Error::Sqlite(err)
+    }
+}
+
 impl From<String> for Error {
     fn from(err: String) -> Error {
         Error::Str(err)
@@ -45,18 +74,27 @@ impl From<Utf8Error> for Error {
     }
 }
 
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Error {
+        Error::Json(err)
+    }
+}
+
+#[cfg(feature = "window")]
 impl From<WindowBuildError> for Error {
     fn from(_: WindowBuildError) -> Error {
         Error::WindowBuildError
     }
 }
 
+#[cfg(feature = "window")]
 impl From<IntegerOrSdlError> for Error {
     fn from(_: IntegerOrSdlError) -> Error {
         Error::IntegerOrSdlError
     }
 }
 
+#[cfg(feature = "window")]
 impl From<TextureValueError> for Error {
     fn from(_: TextureValueError) -> Error { Error::TextureValueError }
 }
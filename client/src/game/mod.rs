@@ -5,6 +5,8 @@ use std::path::{ PathBuf };
 
 use core_compat::entity::sprite_type::SpriteType;
 use core_compat::entity::rmd_type::RmdType;
+use core_net::packet::Packet;
+use core_rules::pathfind::Tile;
 
 use crate::sdl::Sdl;
 
@@ -14,10 +16,18 @@ use crate::resource_manager::sprite_manager::SpriteManager;
 use crate::resource_manager::list_manager::ListManager;
 use crate::resource_manager::list_manager::ListType;
 
+use crate::ambiance::Ambiance;
 use crate::error::Error;
+use crate::group::Groups;
+use crate::route::{self, Route};
+use crate::text_input::TextInput;
+use crate::trade::Trading;
 
 use self::character::Player;
 
+/// The longest chat line
+const CHAT_MAX_CHARS: usize = 80;
+
 // public interface
 
 pub mod input;
@@ -26,6 +36,22 @@ pub struct State {
     pub player: character::Player,
     pub map: usize,
     pub map_off: (i32, i32),
+    /// Party and guild, from the server
+    pub groups: Groups,
+    pub trading: Trading,
+    /// Time of day and weather, from the server
+    pub ambiance: Ambiance,
+    /// Where the player walks to after a click on the map
+    pub route: Route,
+    /// Whether the left button was down the last update, so a click starts
+    /// a single route
+    pub clicking: bool,
+    /// The tile last right clicked, and whether the player sees it
+    pub target: Option<(Tile, bool)>,
+    /// Typed in after pressing enter, and sent with another
+    pub chat: TextInput,
+    /// Packets for the server, oldest first, taken by whatever sends them
+    pub outgoing: Vec<Packet>,
 }
 
 pub struct Game {
@@ -66,6 +92,14 @@ impl Game {
                 player: Player::new(),
                 map: 0,
                 map_off: (-24, -48),
+                groups: Groups::default(),
+                trading: Trading::default(),
+                ambiance: Ambiance::default(),
+                route: Route::default(),
+                clicking: false,
+                target: None,
+                chat: TextInput::new(CHAT_MAX_CHARS),
+                outgoing: Vec::new(),
             },
             input: input::Input::new(),
 
@@ -125,6 +159,35 @@ impl Game {
             // self.state.player_x += 1;
             self.state.player.position.0 += 5;
         }
+
+        // player movements ( click to move )
+        if self.input.mouse_left.pressed && !self.state.clicking {
+            if let Ok(map) = self.map_manager.get_map(self.state.map) {
+                let mouse = (self.input.mouse_x, self.input.mouse_y);
+                if let Some(to) = route::tile_at(&map, mouse, self.state.map_off) {
+                    self.state.route = Route::new(&map, self.player_tile(), to);
+                }
+            }
+        }
+        self.state.clicking = self.input.mouse_left.pressed;
+
+        // targeting
+        if self.input.mouse_right.pressed {
+            if let Ok(map) = self.map_manager.get_map(self.state.map) {
+                let mouse = (self.input.mouse_x, self.input.mouse_y);
+                self.state.target = route::tile_at(&map, mouse, self.state.map_off)
+                    .map(|to| (to, route::in_sight(&map, self.player_tile(), to)));
+            }
+        }
+        if let Some((x, y)) = self.state.route.step() {
+            self.state.player.position = (x as u32 * route::TILE_WIDTH as u32, y as u32 * route::TILE_HEIGHT as u32);
+        }
+    }
+
+    /// The tile the player stands on
+    fn player_tile(&self) -> Tile {
+        let position = self.state.player.position;
+        ((position.0 / route::TILE_WIDTH as u32) as u16, (position.1 / route::TILE_HEIGHT as u32) as u16)
     }
 
     pub fn load_chr(&mut self, _chr_number: usize, _sdl: &mut Sdl) -> Result<(), Error> {
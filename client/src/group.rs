@@ -0,0 +1,91 @@
+//! The player's party and guild, as last told by the server.
+
+use core_net::packet::Packet;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GuildInfo {
+    pub id: u32,
+    pub name: String,
+    pub leader: String,
+    /// By name
+    pub members: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Groups {
+    /// Party members, leader first; empty without a party
+    pub party: Vec<u32>,
+    pub guild: Option<GuildInfo>,
+    /// The last party invite, by leader id and name
+    pub party_invite: Option<(u32, String)>,
+    /// The last guild invite, by guild id and name
+    pub guild_invite: Option<(u32, String)>,
+}
+
+impl Groups {
+    pub fn apply(&mut self, packet: &Packet) {
+        match *packet {
+            Packet::PartyInvited { leader, ref name } => self.party_invite = Some((leader, name.clone())),
+            Packet::Party { ref members } => {
+                self.party = members.clone();
+                if !members.is_empty() {
+                    self.party_invite = None;
+                }
+            }
+            Packet::GuildInvited { guild, ref name } => self.guild_invite = Some((guild, name.clone())),
+            Packet::Guild { guild: 0, .. } => self.guild = None,
+            Packet::Guild { guild, ref name, ref leader, ref members } => {
+                self.guild = Some(GuildInfo { id: guild, name: name.clone(), leader: leader.clone(), members: members.clone() });
+                self.guild_invite = None;
+            }
+            _ => (),
+        }
+    }
+
+    /// The lines of the party panel, the leader marked with a star
+    pub fn party_lines<F>(&self, name: F) -> Vec<String>
+        where F: Fn(u32) -> String
+    {
+        self.party.iter().enumerate()
+            .map(|(i, &id)| if i == 0 { format!("* {}", name(id)) } else { format!("  {}", name(id)) })
+            .collect()
+    }
+
+    /// The lines of the guild panel: its name, then the members with the
+    /// leader marked with a star
+    pub fn guild_lines(&self) -> Vec<String> {
+        match self.guild {
+            Some(ref guild) => Some(guild.name.clone()).into_iter()
+                .chain(guild.members.iter().map(|member| {
+                    if *member == guild.leader { format!("* {}", member) } else { format!("  {}", member) }
+                }))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let mut groups = Groups::default();
+        groups.apply(&Packet::PartyInvited { leader: 1, name: "Philar".into() });
+        assert_eq!(groups.party_invite, Some((1, "Philar".into())));
+        groups.apply(&Packet::Party { members: vec![1, 2] });
+        assert_eq!(groups.party_invite, None);
+        assert_eq!(groups.party_lines(|id| format!("#{}", id)), vec!["* #1", "  #2"]);
+
+        groups.apply(&Packet::Guild {
+            guild: 4,
+            name: "Moonlight".into(),
+            leader: "Philar".into(),
+            members: vec!["Azlar".into(), "Philar".into()],
+        });
+        assert_eq!(groups.guild_lines(), vec!["Moonlight", "  Azlar", "* Philar"]);
+        groups.apply(&Packet::Guild { guild: 0, name: String::new(), leader: String::new(), members: Vec::new() });
+        assert!(groups.guild.is_none() && groups.guild_lines().is_empty());
+    }
+}
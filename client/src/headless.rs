@@ -0,0 +1,368 @@
+//! A client without renderer: it keeps the game state the server tells it
+//! about and lets a program or a script act on it.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use core_net::packet::Packet;
+use core_net::stream::PacketReader;
+
+use crate::ambiance::Ambiance;
+use crate::error::Error;
+use crate::group::Groups;
+use crate::replay::Session;
+use crate::trade::Trading;
+
+/// How long `connect` waits for the server to accept the login
+pub const LOGIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Another entity on the player's map
+#[derive(Debug, Clone, PartialEq)]
+pub struct Remote {
+    pub name: String,
+    pub x: u16,
+    pub y: u16,
+    pub hp: u16,
+}
+
+/// Items lying on the ground
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ground {
+    pub item: u32,
+    pub count: u32,
+    pub x: u16,
+    pub y: u16,
+}
+
+/// What the client knows of the game
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ClientState {
+    /// The player's id, once logged in
+    pub id: Option<u32>,
+    pub map: u32,
+    pub x: u16,
+    pub y: u16,
+    /// Everyone else, by id
+    pub entities: BTreeMap<u32, Remote>,
+    /// The items on the ground in view, by id
+    pub ground: BTreeMap<u32, Ground>,
+    /// Received chat lines, oldest first
+    pub chat: Vec<(u32, String)>,
+    pub groups: Groups,
+    pub trading: Trading,
+    /// Items and their counts
+    pub inventory: Vec<(u32, u32)>,
+    pub ambiance: Ambiance,
+    /// Why the server went down, once it said so
+    pub shutdown: Option<String>,
+}
+
+impl ClientState {
+    pub fn apply(&mut self, packet: &Packet) {
+        match *packet {
+            Packet::LoginOk { id, map, x, y } => {
+                self.id = Some(id);
+                self.map = map;
+                self.x = x;
+                self.y = y;
+            }
+            Packet::Spawn { id, ref name, x, y, hp } => {
+                self.entities.insert(id, Remote { name: name.clone(), x, y, hp });
+            }
+            Packet::GroundItem { id, item, count, x, y } => {
+                self.ground.insert(id, Ground { item, count, x, y });
+            }
+            Packet::Despawn { id } => {
+                self.entities.remove(&id);
+                self.ground.remove(&id);
+            }
+            Packet::Moved { id, x, y } => {
+                if Some(id) == self.id {
+                    self.x = x;
+                    self.y = y;
+                } else if let Some(remote) = self.entities.get_mut(&id) {
+                    remote.x = x;
+                    remote.y = y;
+                }
+            }
+            Packet::Damage { target, hp, .. } => {
+                if let Some(remote) = self.entities.get_mut(&target) {
+                    remote.hp = hp;
+                }
+            }
+            Packet::Chat { from, ref text } => self.chat.push((from, text.clone())),
+            Packet::Inventory { ref items } => self.inventory = items.clone(),
+            Packet::Shutdown { ref message } => self.shutdown = Some(message.clone()),
+            _ => {
+                self.groups.apply(packet);
+                self.trading.apply(packet);
+                self.ambiance.apply(packet);
+            }
+        }
+    }
+}
+
+pub struct HeadlessClient {
+    stream: TcpStream,
+    /// The packets and when they arrived
+    packets: Receiver<(Instant, Packet)>,
+    /// When the login was sent
+    started: Instant,
+    session: Option<Session>,
+    pub state: ClientState,
+}
+
+impl HeadlessClient {
+    /// Connects and logs in as `name`
+    pub fn connect<A: ToSocketAddrs>(server: A, name: &str) -> Result<HeadlessClient, Error> {
+        HeadlessClient::open(server, name, false)
+    }
+
+    /// Connects and logs in as `name`, recording every packet the server
+    /// sends from the login on, to replay them (see `crate::replay`)
+    pub fn connect_recording<A: ToSocketAddrs>(server: A, name: &str) -> Result<HeadlessClient, Error> {
+        HeadlessClient::open(server, name, true)
+    }
+
+    fn open<A: ToSocketAddrs>(server: A, name: &str, record: bool) -> Result<HeadlessClient, Error> {
+        let stream = TcpStream::connect(server)?;
+        let _ = stream.set_nodelay(true);
+        let reader = stream.try_clone()?;
+        let (sender, packets) = channel();
+        thread::spawn(move || {
+            let mut reader = PacketReader::new(reader);
+            while let Ok(Some(packet)) = reader.read_packet() {
+                if sender.send((Instant::now(), packet)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let session = if record { Some(Session::new()) } else { None };
+        let mut client = HeadlessClient {
+            stream,
+            packets,
+            started: Instant::now(),
+            session,
+            state: ClientState::default(),
+        };
+        client.send(&Packet::Login { name: name.into() })?;
+        if client.wait_for(|state| state.id.is_some(), LOGIN_TIMEOUT)? {
+            Ok(client)
+        } else {
+            Err(Error::Timeout)
+        }
+    }
+
+    pub fn send(&mut self, packet: &Packet) -> Result<(), Error> {
+        self.stream.write_all(&packet.encode())?;
+        Ok(())
+    }
+
+    pub fn walk(&mut self, x: u16, y: u16) -> Result<(), Error> {
+        self.send(&Packet::Walk { x, y })
+    }
+
+    pub fn attack(&mut self, target: u32) -> Result<(), Error> {
+        self.send(&Packet::Attack { target })
+    }
+
+    pub fn use_skill(&mut self, skill: u32, target: u32) -> Result<(), Error> {
+        self.send(&Packet::UseSkill { skill, target })
+    }
+
+    pub fn say(&mut self, text: &str) -> Result<(), Error> {
+        self.send(&Packet::Say { text: text.into() })
+    }
+
+    pub fn pick_up(&mut self, id: u32) -> Result<(), Error> {
+        self.send(&Packet::PickUp { id })
+    }
+
+    /// What was recorded so far, when connected with `connect_recording`
+    pub fn session(&self) -> Option<&Session> {
+        self.session.as_ref()
+    }
+
+    /// Applies the packets that arrived so far and returns them
+    pub fn poll(&mut self) -> Vec<Packet> {
+        let packets: Vec<(Instant, Packet)> = self.packets.try_iter().collect();
+        packets.into_iter()
+            .map(|(at, packet)| {
+                self.receive(at, &packet);
+                packet
+            })
+            .collect()
+    }
+
+    fn receive(&mut self, at: Instant, packet: &Packet) {
+        self.state.apply(packet);
+        if let Some(ref mut session) = self.session {
+            let at = at.saturating_duration_since(self.started).as_millis() as u64;
+            session.push(at, packet.clone());
+        }
+    }
+
+    /// Applies arriving packets until `condition` holds; returns whether it
+    /// did before the timeout.
+    pub fn wait_for<F>(&mut self, mut condition: F, timeout: Duration) -> Result<bool, Error>
+        where F: FnMut(&ClientState) -> bool
+    {
+        let deadline = Instant::now() + timeout;
+        self.poll();
+        while !condition(&self.state) {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(false);
+            }
+            match self.packets.recv_timeout(deadline - now) {
+                Ok((at, packet)) => self.receive(at, &packet),
+                Err(RecvTimeoutError::Timeout) => return Ok(false),
+                Err(RecvTimeoutError::Disconnected) => return Err(Error::Str("connection closed".into())),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Runs a script command
+    pub fn run(&mut self, command: &Command) -> Result<(), Error> {
+        match *command {
+            Command::Walk(x, y) => self.walk(x, y),
+            Command::Attack(target) => self.attack(target),
+            Command::Skill(skill, target) => self.use_skill(skill, target),
+            Command::Say(ref text) => self.say(text),
+            Command::PickUp(id) => self.pick_up(id),
+            Command::Wait(duration) => {
+                self.wait_for(|_| false, duration)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A line of a client script:
+///
+/// ```text
+/// walk <x> <y>
+/// attack <id>
+/// skill <skill> <id>
+/// say <text>
+/// pickup <id>
+/// wait <ms>
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Walk(u16, u16),
+    Attack(u32),
+    Skill(u32, u32),
+    Say(String),
+    PickUp(u32),
+    Wait(Duration),
+}
+
+impl Command {
+    /// `None` for lines that aren't a command
+    pub fn parse(line: &str) -> Option<Command> {
+        let line = line.trim();
+        let (word, rest) = match line.find(' ') {
+            Some(space) => (&line[..space], line[space + 1..].trim()),
+            None => (line, ""),
+        };
+        let numbers: Vec<u32> = rest.split_whitespace().filter_map(|n| n.parse().ok()).collect();
+        match (word, numbers.as_slice()) {
+            ("walk", &[x, y]) if x <= 0xFFFF && y <= 0xFFFF => Some(Command::Walk(x as u16, y as u16)),
+            ("attack", &[target]) => Some(Command::Attack(target)),
+            ("skill", &[skill, target]) => Some(Command::Skill(skill, target)),
+            ("say", _) if !rest.is_empty() => Some(Command::Say(rest.into())),
+            ("pickup", &[id]) => Some(Command::PickUp(id)),
+            ("wait", &[ms]) => Some(Command::Wait(Duration::from_millis(ms as u64))),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::TcpListener;
+
+    use core_rules::ambiance::Weather;
+
+    #[test]
+    fn test_apply() {
+        let mut state = ClientState::default();
+        for packet in [
+            Packet::LoginOk { id: 1, map: 3, x: 20, y: 20 },
+            Packet::Spawn { id: 2, name: "Belt".into(), x: 20, y: 21, hp: 100 },
+            Packet::Moved { id: 1, x: 21, y: 20 },
+            Packet::Moved { id: 2, x: 21, y: 21 },
+            Packet::Damage { attacker: 1, target: 2, amount: 10, hp: 90 },
+            Packet::Chat { from: 2, text: "ouch".into() },
+        ].iter() {
+            state.apply(packet);
+        }
+        assert_eq!((state.id, state.map, state.x, state.y), (Some(1), 3, 21, 20));
+        assert_eq!(state.entities[&2], Remote { name: "Belt".into(), x: 21, y: 21, hp: 90 });
+        assert_eq!(state.chat, vec![(2, "ouch".to_string())]);
+
+        state.apply(&Packet::Ambiance { minute: 20 * 60, day_length: 7200, weather: 3 });
+        assert_eq!((state.ambiance.minute, state.ambiance.weather), (20 * 60, Weather::Fog));
+
+        state.apply(&Packet::GroundItem { id: 3, item: 7, count: 30, x: 21, y: 21 });
+        assert_eq!(state.ground[&3], Ground { item: 7, count: 30, x: 21, y: 21 });
+        state.apply(&Packet::Despawn { id: 2 });
+        state.apply(&Packet::Despawn { id: 3 });
+        assert!(state.entities.is_empty() && state.ground.is_empty());
+
+        state.apply(&Packet::Shutdown { message: "back soon".into() });
+        assert_eq!(state.shutdown, Some("back soon".into()));
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(Command::parse("walk 3 4"), Some(Command::Walk(3, 4)));
+        assert_eq!(Command::parse(" attack 7 "), Some(Command::Attack(7)));
+        assert_eq!(Command::parse("skill 2 7"), Some(Command::Skill(2, 7)));
+        assert_eq!(Command::parse("say hello there"), Some(Command::Say("hello there".into())));
+        assert_eq!(Command::parse("wait 250"), Some(Command::Wait(Duration::from_millis(250))));
+        assert_eq!(Command::parse("pickup 12"), Some(Command::PickUp(12)));
+        assert_eq!(Command::parse("walk 3"), None);
+        assert_eq!(Command::parse("walk 70000 1"), None);
+        assert_eq!(Command::parse("say"), None);
+        assert_eq!(Command::parse("# comment"), None);
+    }
+
+    #[test]
+    fn test_connect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // a server that lets anyone in and echoes chat
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = PacketReader::new(stream);
+            while let Ok(Some(packet)) = reader.read_packet() {
+                let answer = match packet {
+                    Packet::Login { .. } => Packet::LoginOk { id: 5, map: 1, x: 2, y: 3 },
+                    Packet::Say { text } => Packet::Chat { from: 5, text },
+                    _ => continue,
+                };
+                writer.write_all(&answer.encode()).unwrap();
+            }
+        });
+
+        let mut client = HeadlessClient::connect_recording(addr, "Trica").unwrap();
+        assert_eq!((client.state.id, client.state.x, client.state.y), (Some(5), 2, 3));
+        client.run(&Command::Say("hi".into())).unwrap();
+        assert!(client.wait_for(|state| !state.chat.is_empty(), Duration::from_secs(5)).unwrap());
+        assert_eq!(client.state.chat, vec![(5, "hi".to_string())]);
+        let packets: Vec<&Packet> = client.session().unwrap().packets.iter().map(|(_, packet)| packet).collect();
+        assert_eq!(packets, vec![&Packet::LoginOk { id: 5, map: 1, x: 2, y: 3 },
+                                 &Packet::Chat { from: 5, text: "hi".into() }]);
+    }
+}
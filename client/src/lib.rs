@@ -0,0 +1,24 @@
+//! The parts of the client that run without a window: the headless client
+//! for bots, tests and CI, replaying the sessions it records, and the asset
+//! stores. The windowed client is the `client` binary.
+
+extern crate core_compat;
+extern crate core_net;
+extern crate core_rules;
+
+#[cfg(feature = "window")]
+extern crate sdl2;
+extern crate serde_json;
+#[cfg(feature = "sqlite")]
+extern crate rusqlite as sql;
+
+pub mod ambiance;
+pub mod animation;
+pub mod asset_store;
+pub mod error;
+pub mod group;
+pub mod headless;
+pub mod replay;
+pub mod route;
+pub mod text_input;
+pub mod trade;
@@ -1,17 +1,32 @@
 #![allow(dead_code)]
 
 extern crate core_compat;
+extern crate core_net;
+extern crate core_rules;
 extern crate geometry;
 
 extern crate sdl2;
+#[cfg(feature = "gl565")]
+extern crate gl;
 extern crate rusttype;
 #[macro_use]
 extern crate lazy_static;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+#[cfg(feature = "sqlite")]
+extern crate rusqlite as sql;
 
+mod ambiance;
 mod error;
 mod game;
+mod group;
 mod sdl;
 mod resource_manager;
+mod route;
+mod text_input;
+mod trade;
 
 use std::time::Instant;
 
@@ -0,0 +1,297 @@
+//! Recorded sessions, and replaying them without a window to see what the
+//! renderer is given to draw, frame by frame, in real play.
+//!
+//! A session is what the server sent a headless client, as recorded by
+//! `novluno-headless --record`: `SESSION_MAGIC`, then for every packet the
+//! milliseconds since the login as a little endian u64 followed by the
+//! packet's frame (see `core_net::packet`).
+//!
+//! The replay steps through the session a frame at a time, applies the
+//! packets that arrived by then and works out the frame's draw list, the
+//! ground items and then everyone in view from the back to the front. For
+//! every frame it counts the sprites drawn, the batches, a new one whenever
+//! the texture changes from one sprite to the next as SDL's renderer
+//! batches, and the bytes of the textures uploaded for sprites not drawn
+//! before. The map is left out: it's the same whatever the session, and
+//! the headless client doesn't load it.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use core_net::packet::{decode, Packet};
+
+use crate::error::Error;
+use crate::headless::ClientState;
+
+pub const SESSION_MAGIC: &[u8; 8] = b"NVLNREC1";
+
+/// How long a frame lasts by default, in milliseconds; about 60 a second
+pub const FRAME_MS: u64 = 16;
+
+/// How many tiles either way of the player are in view, the 800 by 600
+/// window being 48 by 24 pixels a tile
+pub const VIEW: (u16, u16) = (9, 13);
+
+/// The bytes of a character's texture, taken as 64 by 96 pixels of RGBA
+pub const CHARACTER_BYTES: u64 = 64 * 96 * 4;
+
+/// The bytes of an item's texture, taken as 32 by 32 pixels of RGBA
+pub const ITEM_BYTES: u64 = 32 * 32 * 4;
+
+/// The packets a headless client received, and when
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Session {
+    /// The packets and the milliseconds since the login they arrived at, in
+    /// order
+    pub packets: Vec<(u64, Packet)>,
+}
+
+impl Session {
+    pub fn new() -> Session {
+        Session::default()
+    }
+
+    pub fn push(&mut self, at: u64, packet: Packet) {
+        self.packets.push((at, packet));
+    }
+
+    /// When the last packet arrived, in milliseconds
+    pub fn length(&self) -> u64 {
+        self.packets.last().map_or(0, |&(at, _)| at)
+    }
+
+    pub fn read(data: &[u8]) -> Result<Session, Error> {
+        if !data.starts_with(SESSION_MAGIC) {
+            return Err(Error::Str("not a recorded session".into()));
+        }
+        let mut rest = &data[SESSION_MAGIC.len()..];
+        let mut session = Session::new();
+        while !rest.is_empty() {
+            let packet = match rest.get(8..) {
+                Some(frame) => decode(frame)?,
+                None => None,
+            };
+            let (packet, len) = match packet {
+                Some(packet) => packet,
+                None => return Err(Error::Str(format!("the session ends in the middle of packet {}",
+                                                      session.packets.len() + 1))),
+            };
+            let mut at = [0; 8];
+            at.copy_from_slice(&rest[..8]);
+            session.push(u64::from_le_bytes(at), packet);
+            rest = &rest[8 + len..];
+        }
+        Ok(session)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = SESSION_MAGIC.to_vec();
+        for &(at, ref packet) in self.packets.iter() {
+            data.extend_from_slice(&at.to_le_bytes());
+            data.extend_from_slice(&packet.encode());
+        }
+        data
+    }
+}
+
+/// What a sprite is drawn from; the sprites of one share a texture
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Texture {
+    /// Everyone of a name looks the same
+    Character(String),
+    Item(u32),
+}
+
+impl Texture {
+    /// The bytes uploaded to draw it the first time
+    pub fn bytes(&self) -> u64 {
+        match *self {
+            Texture::Character(_) => CHARACTER_BYTES,
+            Texture::Item(_) => ITEM_BYTES,
+        }
+    }
+}
+
+/// The sprites of a frame in the order they're drawn: the items on the
+/// ground in view, then everyone in view from the back row to the front
+pub fn draws(state: &ClientState) -> Vec<Texture> {
+    let in_view = |x: u16, y: u16| {
+        x.abs_diff(state.x) <= VIEW.0 && y.abs_diff(state.y) <= VIEW.1
+    };
+    let mut ground: Vec<_> = state.ground.values().filter(|ground| in_view(ground.x, ground.y)).collect();
+    ground.sort_by_key(|ground| (ground.y, ground.x));
+    let mut entities: Vec<_> = state.entities.values().filter(|remote| in_view(remote.x, remote.y)).collect();
+    entities.sort_by_key(|remote| (remote.y, remote.x));
+
+    ground.into_iter().map(|ground| Texture::Item(ground.item))
+        .chain(entities.into_iter().map(|remote| Texture::Character(remote.name.clone())))
+        .collect()
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FrameStats {
+    /// When the frame is drawn, in milliseconds into the session
+    pub at: u64,
+    pub sprites: usize,
+    pub batches: usize,
+    pub upload_bytes: u64,
+    /// Time to apply the frame's packets and work out its draw list
+    pub time: Duration,
+}
+
+pub struct Report {
+    /// How long a frame lasts, in milliseconds
+    pub frame: u64,
+    pub packets: usize,
+    /// How many textures were uploaded in all
+    pub textures: usize,
+    pub frames: Vec<FrameStats>,
+}
+
+impl Report {
+    /// Nearest-rank percentile of something of the frames
+    pub fn percentile<T, F>(&self, p: f64, of: F) -> T
+        where T: Ord + Copy + Default, F: Fn(&FrameStats) -> T
+    {
+        let mut values: Vec<T> = self.frames.iter().map(of).collect();
+        if values.is_empty() {
+            return T::default();
+        }
+        values.sort();
+        let rank = (p / 100.0 * values.len() as f64).ceil() as usize;
+        values[rank.clamp(1, values.len()) - 1]
+    }
+
+    pub fn upload_bytes(&self) -> u64 {
+        self.frames.iter().map(|frame| frame.upload_bytes).sum()
+    }
+
+    /// A line a frame, for a spreadsheet or a plot
+    pub fn csv(&self) -> String {
+        let mut csv = String::from("at_ms,sprites,batches,upload_bytes,time_us\n");
+        for frame in self.frames.iter() {
+            csv.push_str(&format!("{},{},{},{},{}\n", frame.at, frame.sprites, frame.batches,
+                                  frame.upload_bytes, frame.time.as_micros()));
+        }
+        csv
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let counts = |f: &mut fmt::Formatter, name: &str, of: &dyn Fn(&FrameStats) -> usize| {
+            writeln!(f, "  {:<8} p50 {}, p90 {}, p99 {}, max {}", name,
+                     self.percentile(50.0, of), self.percentile(90.0, of),
+                     self.percentile(99.0, of), self.percentile(100.0, of))
+        };
+        writeln!(f, "replay: {} frames of {} ms, {} packets", self.frames.len(), self.frame, self.packets)?;
+        counts(f, "sprites", &|frame| frame.sprites)?;
+        counts(f, "batches", &|frame| frame.batches)?;
+        writeln!(f, "  uploads  {} textures, {} bytes, at most {} bytes in a frame",
+                 self.textures, self.upload_bytes(), self.percentile(100.0, |frame| frame.upload_bytes))?;
+        writeln!(f, "  time     p50 {:.3} ms, p90 {:.3} ms, p99 {:.3} ms, max {:.3} ms",
+                 ms(self.percentile(50.0, |frame| frame.time)), ms(self.percentile(90.0, |frame| frame.time)),
+                 ms(self.percentile(99.0, |frame| frame.time)), ms(self.percentile(100.0, |frame| frame.time)))
+    }
+}
+
+/// Replays `session` in frames of `frame` milliseconds, up to the one with
+/// its last packet
+pub fn replay(session: &Session, frame: u64) -> Report {
+    let frame = frame.max(1);
+    let mut state = ClientState::default();
+    let mut uploaded = HashSet::new();
+    let mut frames = Vec::new();
+    let mut next = 0;
+    let mut at = 0;
+    loop {
+        let start = Instant::now();
+        while let Some(&(arrived, ref packet)) = session.packets.get(next) {
+            if arrived > at {
+                break;
+            }
+            state.apply(packet);
+            next += 1;
+        }
+        let draws = draws(&state);
+
+        let mut stats = FrameStats { at, sprites: draws.len(), ..FrameStats::default() };
+        let mut last = None;
+        for texture in draws.iter() {
+            if last != Some(texture) {
+                stats.batches += 1;
+                last = Some(texture);
+            }
+            if !uploaded.contains(texture) {
+                stats.upload_bytes += texture.bytes();
+                uploaded.insert(texture.clone());
+            }
+        }
+        stats.time = start.elapsed();
+        frames.push(stats);
+
+        if next == session.packets.len() {
+            break;
+        }
+        at += frame;
+    }
+    Report { frame, packets: session.packets.len(), textures: uploaded.len(), frames }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn(id: u32, name: &str, x: u16, y: u16) -> Packet {
+        Packet::Spawn { id, name: name.into(), x, y, hp: 100 }
+    }
+
+    fn session() -> Session {
+        let mut session = Session::new();
+        session.push(0, Packet::LoginOk { id: 1, map: 1, x: 50, y: 50 });
+        session.push(5, spawn(2, "Rat", 50, 52));
+        session.push(5, spawn(3, "Rat", 52, 52));
+        session.push(5, spawn(4, "Belt", 50, 51));
+        session.push(20, Packet::GroundItem { id: 5, item: 7, count: 1, x: 50, y: 53 });
+        // too far to be seen
+        session.push(40, spawn(6, "Wolf", 80, 50));
+        session.push(40, Packet::Moved { id: 4, x: 51, y: 52 });
+        session
+    }
+
+    #[test]
+    fn test_session() {
+        let session = session();
+        let data = session.to_bytes();
+        assert_eq!(Session::read(&data).unwrap(), session);
+        assert_eq!(session.length(), 40);
+        assert!(Session::read(&data[..data.len() - 1]).is_err());
+        assert!(Session::read(&data[1..]).is_err());
+        assert_eq!(Session::read(SESSION_MAGIC).unwrap(), Session::new());
+    }
+
+    #[test]
+    fn test_replay() {
+        let report = replay(&session(), 16);
+        let at: Vec<u64> = report.frames.iter().map(|frame| frame.at).collect();
+        assert_eq!(at, vec![0, 16, 32, 48]);
+        let counts: Vec<_> = report.frames.iter()
+            .map(|frame| (frame.sprites, frame.batches, frame.upload_bytes))
+            .collect();
+        assert_eq!(counts, vec![
+            (0, 0, 0),
+            // Belt is a row behind the rats
+            (3, 2, 2 * CHARACTER_BYTES),
+            (4, 3, ITEM_BYTES),
+            // and now between them
+            (4, 4, 0),
+        ]);
+        assert_eq!((report.packets, report.textures, report.upload_bytes()), (7, 3, 2 * CHARACTER_BYTES + ITEM_BYTES));
+        assert_eq!(report.percentile(50.0, |frame| frame.sprites), 3);
+        assert_eq!(report.percentile(100.0, |frame| frame.batches), 4);
+        assert!(report.csv().starts_with("at_ms,sprites,batches,upload_bytes,time_us\n0,0,0,0,"));
+        assert!(format!("{}", report).starts_with("replay: 4 frames of 16 ms, 7 packets\n"));
+    }
+}
@@ -115,11 +115,21 @@ impl DataManager {
 mod tests {
     use super::*;
 
+    use std::io::Write;
+
+    use core_compat::fixture::RmdFixture;
+
     #[test]
     fn test_load_tle_rmd_00001() {
-        let data_path = Path::new("../data/DATAs/");
+        // write a synthetic data file
+        let data_path = std::env::temp_dir().join("novluno_test_data_manager");
+        std::fs::create_dir_all(data_path.join("Tle")).unwrap();
+        File::create(data_path.join("Tle").join("tle00001.rmd")).unwrap()
+            .write_all(&RmdFixture::new().entry(&[&[3]]).build()).unwrap();
+
         let mut rmd = DataManager::new(&data_path);
         let _rmd_no = 1usize;
-        let _data = rmd.get_data(RmdType::Tile, 1).unwrap();
+        let data = rmd.get_data(RmdType::Tile, 1).unwrap();
+        assert_eq!(data.get_entry(0).unwrap().images()[0].image_id, vec![3]);
     }
 }
@@ -0,0 +1,74 @@
+//! Optional per-map light layer. The lights are stored in a JSON sidecar next
+//! to the map file (`Map00003.rmm` -> `Map00003.light.json`) so they can be
+//! edited by hand:
+//!
+//! ```json
+//! {
+//!     "ambient": [90, 90, 140],
+//!     "lights": [
+//!         { "x": 480, "y": 240, "radius": 120, "color": [255, 200, 120] }
+//!     ]
+//! }
+//! ```
+//!
+//! Light positions and radii are in map pixels.
+
+use serde_json;
+
+use crate::error::Error;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PointLight {
+    pub x: i32,
+    pub y: i32,
+    pub radius: u32,
+    pub color: [u8; 3],
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LightMap {
+    #[serde(default = "default_ambient")]
+    pub ambient: [u8; 3],
+    #[serde(default)]
+    pub lights: Vec<PointLight>,
+}
+
+impl Default for LightMap {
+    /// No lights and nothing dimmed
+    fn default() -> LightMap {
+        LightMap { ambient: default_ambient(), lights: Vec::new() }
+    }
+}
+
+fn default_ambient() -> [u8; 3] {
+    [0xFF, 0xFF, 0xFF]
+}
+
+pub fn parse_light_map(data: &str) -> Result<LightMap, Error> {
+    let light_map = serde_json::from_str(data)?;
+    Ok(light_map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_light_map() {
+        let data = r#"{
+            "ambient": [90, 90, 140],
+            "lights": [ { "x": 480, "y": 240, "radius": 120, "color": [255, 200, 120] } ]
+        }"#;
+        let light_map = parse_light_map(data).unwrap();
+        assert_eq!(light_map.ambient, [90, 90, 140]);
+        assert_eq!(light_map.lights.len(), 1);
+        assert_eq!(light_map.lights[0].radius, 120);
+    }
+
+    #[test]
+    fn test_parse_light_map_defaults() {
+        let light_map = parse_light_map("{}").unwrap();
+        assert_eq!(light_map.ambient, [0xFF, 0xFF, 0xFF]);
+        assert!(light_map.lights.is_empty());
+    }
+}
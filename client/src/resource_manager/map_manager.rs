@@ -9,10 +9,13 @@ use core_compat::entity::map::Map;
 use core_compat::parser::rmm::parse_rmm;
 
 use crate::error::Error;
+use crate::resource_manager::light_map::LightMap;
+use crate::resource_manager::light_map::parse_light_map;
 
 pub struct MapManager {
     data_path: PathBuf,
     maps: HashMap<usize, Rc<Map>>,
+    lights: HashMap<usize, Rc<LightMap>>,
 }
 
 impl MapManager {
@@ -21,6 +24,7 @@ impl MapManager {
         MapManager {
             data_path: data_path.into(),
             maps: HashMap::new(),
+            lights: HashMap::new(),
         }
     }
 
@@ -32,6 +36,10 @@ impl MapManager {
         Ok(map)
     }
 
+    pub fn get_lights(&self, number: usize) -> Option<Rc<LightMap>> {
+        self.lights.get(&number).cloned()
+    }
+
     pub fn load_map(&mut self, number: usize) -> Result<(), Error> {
         // generate correct path for the map
         let map_str = format!("Map{:05}.rmm", number);
@@ -51,6 +59,15 @@ impl MapManager {
         let map = parse_rmm(&data)?;
         self.maps.insert(number, Rc::new(map));
         println!("Loaded map: {}", &map_str);
+        // the light layer is optional and lives next to the map file
+        path.set_file_name(format!("Map{:05}.light.json", number));
+        if let Ok(mut file) = File::open(&path) {
+            let mut data = String::new();
+            file.read_to_string(&mut data)?;
+            let light_map = parse_light_map(&data)?;
+            self.lights.insert(number, Rc::new(light_map));
+            println!("Loaded light map: {:?}", &path);
+        }
         Ok(())
     }
 
@@ -63,15 +80,25 @@ impl MapManager {
 mod tests {
     use super::*;
 
+    use std::io::Write;
+
+    use core_compat::fixture::RmmFixture;
+
     #[test]
     fn test_load_map00001() {
+        // write a synthetic map file
+        let map_data_path = std::env::temp_dir().join("novluno_test_map_manager");
+        std::fs::create_dir_all(&map_data_path).unwrap();
+        File::create(map_data_path.join("Map00001.rmm")).unwrap()
+            .write_all(&RmmFixture::new(1, 4, 3).build()).unwrap();
+
         // load the map files
-        let map_data_path = Path::new("../data/DATAs/Map/");
         let mut map_man = MapManager::new(&map_data_path);
         let map_no = 1usize;
         map_man.load_map(map_no).unwrap();
         let map = map_man.maps.get(&1).unwrap();
         assert_eq!(map.number(), 1);
         assert_eq!((map.size_x() * map.size_y()) as usize, map.tile_count());
+        assert!(map_man.get_lights(1).is_none());
     }
 }
@@ -2,3 +2,4 @@ pub mod data_manager;
 pub mod map_manager;
 pub mod sprite_manager;
 pub mod list_manager;
+pub mod light_map;
@@ -5,19 +5,76 @@ use std::fs::File;
 use std::io::Read;
 
 use sdl2;
+use sdl2::rect::Rect;
 
 // use core_compat::entity::resource_file::ResourceFile;
 use core_compat::entity::entry::Entry;
 use core_compat::entity::sprite::Sprite;
 use core_compat::entity::sprite_type::SpriteType::{self, Bullet, Character, Interface, Icon, Tile, Object};
-use core_compat::parser::rle::parse_rle;
+use core_compat::parser::rle::{parse_rle_as, PixelFormat};
+use core_compat::utility::bleed::bleed_edges;
+use core_compat::utility::dice::dice_pixels;
 
 use crate::error::Error;
 use crate::sdl::Sdl;
 
+pub enum PartTexture {
+    Rgba(sdl2::render::Texture),
+    /// 565 pixels, drawn by `Sdl::gl565`
+    #[cfg(feature = "gl565")]
+    Packed(crate::sdl::render::gl565::PackedTexture),
+}
+
+/// A texture holding part of a sprite, placed at `rect` within it
+pub struct SpriteTexture {
+    pub rect: Rect,
+    pub texture: PartTexture,
+}
+
+/// A sprite and its textures; sprites bigger than `Sdl::max_texture_size`
+/// are split over several of them.
 pub struct SpriteEntry {
     pub sprite: Sprite,
-    pub texture: sdl2::render::Texture
+    pub textures: Vec<SpriteTexture>,
+}
+
+impl SpriteEntry {
+    /// Draws the `src` part of the sprite into `dst`, like `Canvas::copy`.
+    pub fn copy(&self, sdl: &mut Sdl, src: Rect, dst: Rect) {
+        for part in self.textures.iter() {
+            if let Some((part_src, part_dst)) = texture_rects(part.rect, src, dst) {
+                match part.texture {
+                    PartTexture::Rgba(ref texture) => {
+                        let _ = sdl.canvas.copy(texture, part_src, part_dst);
+                    }
+                    #[cfg(feature = "gl565")]
+                    PartTexture::Packed(ref texture) => {
+                        if let Some(ref mut gl565) = sdl.gl565 {
+                            gl565.copy(&sdl.canvas, texture, part_src, part_dst);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Maps a copy from `src` to `dst` onto the texture covering `rect`; returns
+/// the rectangles within that texture and on the canvas.
+fn texture_rects(rect: Rect, src: Rect, dst: Rect) -> Option<(Rect, Rect)> {
+    let part = src.intersection(rect)?;
+    let scale_x = |v: i32| (v as i64 * dst.width() as i64 / src.width() as i64) as i32;
+    let scale_y = |v: i32| (v as i64 * dst.height() as i64 / src.height() as i64) as i32;
+    let left = scale_x(part.x() - src.x());
+    let top = scale_y(part.y() - src.y());
+    let right = scale_x(part.right() - src.x());
+    let bottom = scale_y(part.bottom() - src.y());
+    if right <= left || bottom <= top {
+        return None;
+    }
+    let part_src = Rect::new(part.x() - rect.x(), part.y() - rect.y(), part.width(), part.height());
+    let part_dst = Rect::new(dst.x() + left, dst.y() + top, (right - left) as u32, (bottom - top) as u32);
+    Some((part_src, part_dst))
 }
 
 pub struct SpriteManager {
@@ -125,9 +182,20 @@ impl SpriteManager {
         };
         let mut data = Vec::<u8>::new();
         file.read_to_end(&mut data)?;
-        // parse rle file and insert into resource_manager
-        let resource_file = parse_rle(number, &data)?;
-        for resource in resource_file.resources {
+        // parse rle file and insert into resource_manager; the map tiles
+        // stay 565 if they can be drawn like that
+        #[cfg(feature = "gl565")]
+        let format = match sprite_type {
+            Tile if sdl.gl565.is_some() => PixelFormat::R5g6b5,
+            _ => PixelFormat::Rgba8,
+        };
+        #[cfg(not(feature = "gl565"))]
+        let format = PixelFormat::Rgba8;
+        let resource_file = parse_rle_as(number, &data, format)?;
+        for mut resource in resource_file.resources {
+            if sdl.clean_edges && format == PixelFormat::Rgba8 {
+                bleed_edges(resource.width as u32, resource.height as u32, &mut resource.image_raw);
+            }
             let entry = Entry::new(number, resource.index() );
             let sprite = Sprite {
                 class: sprite_type,
@@ -139,16 +207,33 @@ impl SpriteManager {
                 image_raw: resource.image_raw,
             };
 
-            let mut texture = sdl.texture_creator.create_texture(
-                Some(sdl2::pixels::PixelFormatEnum::ABGR8888),
-                sdl2::render::TextureAccess::Static,
-                resource.width as u32,
-                resource.height as u32)?;
-            texture.set_blend_mode(sdl2::render::BlendMode::Blend);
-            let pitch = resource.width as usize * 4;
-            texture.update(None, &sprite.image_raw, pitch).unwrap();
+            // one texture per tile, usually just the one
+            let mut textures = Vec::new();
+            let tiles = dice_pixels(resource.width as u32, resource.height as u32,
+                                    &sprite.image_raw, sdl.max_texture_size,
+                                    format.bytes_per_pixel() as u32);
+            for tile in tiles {
+                let rect = Rect::new(tile.x as i32, tile.y as i32, tile.width, tile.height);
+                #[cfg(feature = "gl565")]
+                {
+                    if let (PixelFormat::R5g6b5, Some(gl565)) = (format, sdl.gl565.as_mut()) {
+                        let texture = gl565.upload(tile.width, tile.height, &tile.image_raw);
+                        textures.push(SpriteTexture { rect, texture: PartTexture::Packed(texture) });
+                        continue;
+                    }
+                }
+                let mut texture = sdl.texture_creator.create_texture(
+                    Some(sdl2::pixels::PixelFormatEnum::ABGR8888),
+                    sdl2::render::TextureAccess::Static,
+                    tile.width,
+                    tile.height)?;
+                texture.set_blend_mode(sdl2::render::BlendMode::Blend);
+                let pitch = tile.width as usize * 4;
+                texture.update(None, &tile.image_raw, pitch).unwrap();
+                textures.push(SpriteTexture { rect, texture: PartTexture::Rgba(texture) });
+            }
 
-            let sprite_entry = Rc::new(SpriteEntry { sprite, texture });
+            let sprite_entry = Rc::new(SpriteEntry { sprite, textures });
 
             match sprite_type {
                 Bullet    => { self.bul_map.insert(entry, sprite_entry); },
@@ -171,3 +256,32 @@ impl SpriteManager {
         + self.int_map.len()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_texture_rects() {
+        let src = Rect::new(2, 0, 6, 4);
+        let dst = Rect::new(100, 50, 6, 4);
+
+        // left texture only covers part of the source
+        let left = texture_rects(Rect::new(0, 0, 4, 4), src, dst).unwrap();
+        assert_eq!(left, (Rect::new(2, 0, 2, 4), Rect::new(100, 50, 2, 4)));
+        // right texture picks up where it stops
+        let right = texture_rects(Rect::new(4, 0, 4, 4), src, dst).unwrap();
+        assert_eq!(right, (Rect::new(0, 0, 4, 4), Rect::new(102, 50, 4, 4)));
+        // and one outside isn't drawn at all
+        assert!(texture_rects(Rect::new(8, 0, 4, 4), src, dst).is_none());
+    }
+
+    #[test]
+    fn test_texture_rects_scaled() {
+        let src = Rect::new(0, 0, 4, 2);
+        let dst = Rect::new(0, 0, 8, 4);
+
+        let right = texture_rects(Rect::new(2, 0, 2, 2), src, dst).unwrap();
+        assert_eq!(right, (Rect::new(0, 0, 2, 2), Rect::new(4, 0, 4, 4)));
+    }
+}
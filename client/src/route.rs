@@ -0,0 +1,117 @@
+//! Click-to-move: the way from the player's tile to a clicked one over the
+//! walkable tiles of the map, walked a tile at a time. Targeting goes by
+//! the same tiles: what blocks walking blocks sight, as on the server.
+
+use std::collections::VecDeque;
+
+use core_compat::entity::map::Map;
+use core_rules::pathfind::{self, Tile};
+use core_rules::sight;
+
+/// Size of a map tile on screen, in pixels
+pub const TILE_WIDTH: i32 = 48;
+pub const TILE_HEIGHT: i32 = 24;
+
+/// The tile under a point of the screen, with the map drawn at `map_off`
+pub fn tile_at(map: &Map, screen: (i32, i32), map_off: (i32, i32)) -> Option<Tile> {
+    let x = (screen.0 - map_off.0).div_euclid(TILE_WIDTH);
+    let y = (screen.1 - map_off.1).div_euclid(TILE_HEIGHT);
+    if x < 0 || y < 0 || x >= map.size_x() as i32 || y >= map.size_y() as i32 {
+        None
+    } else {
+        Some((x as u16, y as u16))
+    }
+}
+
+/// Whether a tile of the map can be walked on; off the map can't
+pub fn is_passable(map: &Map, (x, y): Tile) -> bool {
+    (x as u32) < map.size_x()
+        && map.tiles().get(y as usize * map.size_x() as usize + x as usize).is_some_and(|tile| tile.is_passable())
+}
+
+/// Whether the player on `from` sees `to`, for aiming a skill at it
+pub fn in_sight(map: &Map, from: Tile, to: Tile) -> bool {
+    sight::line_of_sight(|tile| is_passable(map, tile), from, to)
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Route {
+    steps: VecDeque<Tile>,
+}
+
+impl Route {
+    /// The way from `from` to `to`, or as close to it as it gets
+    pub fn new(map: &Map, from: Tile, to: Tile) -> Route {
+        let limit = map.tiles().len();
+        let steps = pathfind::find_path(|tile| is_passable(map, tile), from, to, 0, limit);
+        Route { steps: steps.into() }
+    }
+
+    /// The next tile to step on, if still walking
+    pub fn step(&mut self) -> Option<Tile> {
+        self.steps.pop_front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core_compat::entity::entry::Entry;
+    use core_compat::entity::map_tile::MapTile;
+
+    /// A 4x3 map with the tiles of the middle column blocked but the last
+    fn map() -> Map {
+        let mut map = Map::new();
+        map.set_size_x(4);
+        map.set_size_y(3);
+        for idx in 0..12 {
+            let collision = if idx % 4 == 1 && idx / 4 < 2 { 1 } else { 0 };
+            map.add_tile(MapTile { obj_rmd_entry: Entry::new(0, 0), tle_rmd_entry: Entry::new(0, 0), warp: 0, collision });
+        }
+        map
+    }
+
+    #[test]
+    fn test_tile_at() {
+        let map = map();
+        assert_eq!(tile_at(&map, (0, 0), (0, 0)), Some((0, 0)));
+        assert_eq!(tile_at(&map, (100, 30), (0, 0)), Some((2, 1)));
+        // below the map when it's drawn further up
+        assert_eq!(tile_at(&map, (100, 30), (-24, -48)), None);
+        assert_eq!(tile_at(&map, (76, 30), (-24, 6)), Some((2, 1)));
+        assert_eq!(tile_at(&map, (-1, 0), (0, 0)), None);
+    }
+
+    #[test]
+    fn test_route() {
+        let map = map();
+        assert!(!is_passable(&map, (1, 0)) && is_passable(&map, (1, 2)) && !is_passable(&map, (4, 0)));
+        let mut route = Route::new(&map, (0, 0), (2, 0));
+        assert_eq!(route.len(), 6);
+        let mut tile = (0, 0);
+        while let Some(next) = route.step() {
+            assert!(is_passable(&map, next) && pathfind::distance(tile, next) == 1);
+            tile = next;
+        }
+        assert_eq!(tile, (2, 0));
+        assert!(Route::new(&map, (0, 0), (0, 0)).is_empty());
+    }
+
+    #[test]
+    fn test_in_sight() {
+        let map = map();
+        assert!(!in_sight(&map, (0, 0), (2, 0)) && !in_sight(&map, (2, 0), (0, 0)));
+        assert!(in_sight(&map, (0, 2), (3, 2)));
+        // past the end of the blocked column
+        assert!(in_sight(&map, (0, 1), (2, 2)) && in_sight(&map, (2, 2), (0, 1)));
+    }
+}
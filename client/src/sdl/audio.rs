@@ -0,0 +1,272 @@
+//! Sound mixing. Every sound plays on one of three buses with its own volume:
+//! the background music, the effects and the interface sounds. The volumes,
+//! how many voices of the same sound may play at once and which sounds duck
+//! the music are read from `data/audio.json`:
+//!
+//! ```json
+//! {
+//!     "bgm_volume": 0.6,
+//!     "sfx_volume": 1.0,
+//!     "ui_volume": 0.8,
+//!     "voices_per_sound": 3,
+//!     "ducking": { "sounds": [12, 40], "volume": 0.3 }
+//! }
+//! ```
+//!
+//! Every field is optional. Sounds are identified by their `snd.lst` id.
+
+use std::fs::File;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+use sdl2::audio::{AudioCallback, AudioCVT, AudioFormat, AudioSpecWAV};
+use sdl2::rwops::RWops;
+use serde_json;
+
+use crate::error::Error;
+
+pub const AUDIO_CONFIG_PATH: &str = "data/audio.json";
+pub const FREQUENCY: i32 = 44100;
+pub const CHANNELS: u8 = 2;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Bus {
+    Bgm,
+    Sfx,
+    Ui,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ducking {
+    /// The sounds that lower the music while they play
+    pub sounds: Vec<u32>,
+    /// The music's volume meanwhile, relative to its bus volume
+    #[serde(default = "default_ducked_volume")]
+    pub volume: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioConfig {
+    #[serde(default = "default_volume")]
+    pub bgm_volume: f32,
+    #[serde(default = "default_volume")]
+    pub sfx_volume: f32,
+    #[serde(default = "default_volume")]
+    pub ui_volume: f32,
+    #[serde(default = "default_voices_per_sound")]
+    pub voices_per_sound: usize,
+    #[serde(default)]
+    pub ducking: Option<Ducking>,
+}
+
+fn default_volume() -> f32 {
+    1.0
+}
+
+fn default_ducked_volume() -> f32 {
+    0.4
+}
+
+fn default_voices_per_sound() -> usize {
+    2
+}
+
+impl AudioConfig {
+    /// Loads the config; the defaults are used if there is no config file.
+    pub fn load(path: &Path) -> Result<AudioConfig, Error> {
+        let mut data = String::new();
+        match File::open(path) {
+            Ok(mut file) => { file.read_to_string(&mut data)?; },
+            Err(ref e) if e.kind() == ErrorKind::NotFound => data.push_str("{}"),
+            Err(e) => return Err(e.into()),
+        }
+        parse_audio_config(&data)
+    }
+
+    pub fn volume(&self, bus: Bus) -> f32 {
+        match bus {
+            Bus::Bgm => self.bgm_volume,
+            Bus::Sfx => self.sfx_volume,
+            Bus::Ui => self.ui_volume,
+        }
+    }
+}
+
+pub fn parse_audio_config(data: &str) -> Result<AudioConfig, Error> {
+    let config = serde_json::from_str(data)?;
+    Ok(config)
+}
+
+/// Decodes a wave and converts it to the mixer's sample format
+pub fn load_wave(data: &[u8]) -> Result<Vec<i16>, Error> {
+    let mut rwops = RWops::from_bytes(data)?;
+    let wave = AudioSpecWAV::load_wav_rw(&mut rwops)?;
+    let cvt = AudioCVT::new(wave.format, wave.channels, wave.freq,
+                            AudioFormat::s16_sys(), CHANNELS, FREQUENCY)?;
+    let bytes = cvt.convert(wave.buffer().to_vec());
+    Ok(bytes.chunks(2).map(|b| i16::from_ne_bytes([b[0], b[1]])).collect())
+}
+
+struct Voice {
+    sound: u32,
+    bus: Bus,
+    /// Interleaved, in the mixer's format
+    samples: Arc<Vec<i16>>,
+    position: usize,
+    looped: bool,
+}
+
+/// Runs on SDL's audio thread; reach it through `AudioDevice::lock`.
+pub struct Mixer {
+    config: AudioConfig,
+    voices: Vec<Voice>,
+}
+
+impl Mixer {
+    pub fn new(config: AudioConfig) -> Mixer {
+        Mixer { config, voices: Vec::new() }
+    }
+
+    pub fn set_volume(&mut self, bus: Bus, volume: f32) {
+        match bus {
+            Bus::Bgm => self.config.bgm_volume = volume,
+            Bus::Sfx => self.config.sfx_volume = volume,
+            Bus::Ui => self.config.ui_volume = volume,
+        }
+    }
+
+    /// Starts a sound. If the sound already plays on all the voices it may
+    /// use, its oldest voice is cut for the new one.
+    pub fn play(&mut self, bus: Bus, sound: u32, samples: Arc<Vec<i16>>, looped: bool) {
+        let limit = self.config.voices_per_sound;
+        if limit == 0 {
+            return;
+        }
+        if self.voices.iter().filter(|voice| voice.sound == sound).count() >= limit {
+            let oldest = self.voices.iter().position(|voice| voice.sound == sound).unwrap();
+            self.voices.remove(oldest);
+        }
+        self.voices.push(Voice { sound, bus, samples, position: 0, looped });
+    }
+
+    /// Stops everything on a bus, e.g. the music when changing maps
+    pub fn stop(&mut self, bus: Bus) {
+        self.voices.retain(|voice| voice.bus != bus);
+    }
+
+    pub fn playing(&self, sound: u32) -> usize {
+        self.voices.iter().filter(|voice| voice.sound == sound).count()
+    }
+
+    fn is_ducked(&self) -> bool {
+        match self.config.ducking {
+            Some(ref ducking) => self.voices.iter().any(|voice| ducking.sounds.contains(&voice.sound)),
+            None => false,
+        }
+    }
+
+    pub fn mix(&mut self, out: &mut [i16]) {
+        let mut bgm_volume = self.config.bgm_volume;
+        if self.is_ducked() {
+            bgm_volume *= self.config.ducking.as_ref().map_or(1.0, |ducking| ducking.volume);
+        }
+        let mut mixed = vec![0.0f32; out.len()];
+        for voice in self.voices.iter_mut() {
+            let volume = match voice.bus {
+                Bus::Bgm => bgm_volume,
+                bus => self.config.volume(bus),
+            };
+            for sample in mixed.iter_mut() {
+                if voice.position >= voice.samples.len() {
+                    if !voice.looped || voice.samples.is_empty() {
+                        break;
+                    }
+                    voice.position = 0;
+                }
+                *sample += voice.samples[voice.position] as f32 * volume;
+                voice.position += 1;
+            }
+        }
+        self.voices.retain(|voice| voice.looped || voice.position < voice.samples.len());
+        for (out, sample) in out.iter_mut().zip(mixed) {
+            *out = sample.max(i16::MIN as f32).min(i16::MAX as f32) as i16;
+        }
+    }
+}
+
+impl AudioCallback for Mixer {
+    type Channel = i16;
+
+    fn callback(&mut self, out: &mut [i16]) {
+        self.mix(out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AudioConfig {
+        parse_audio_config(r#"{
+            "bgm_volume": 0.5,
+            "voices_per_sound": 2,
+            "ducking": { "sounds": [7], "volume": 0.5 }
+        }"#).unwrap()
+    }
+
+    fn samples(value: i16, len: usize) -> Arc<Vec<i16>> {
+        Arc::new(vec![value; len])
+    }
+
+    #[test]
+    fn test_parse_audio_config_defaults() {
+        let config = parse_audio_config("{}").unwrap();
+        assert_eq!(config.volume(Bus::Sfx), 1.0);
+        assert_eq!(config.voices_per_sound, 2);
+        assert_eq!(config.ducking, None);
+    }
+
+    #[test]
+    fn test_mix_buses() {
+        let mut mixer = Mixer::new(config());
+        mixer.play(Bus::Bgm, 1, samples(1000, 8), true);
+        mixer.play(Bus::Sfx, 2, samples(100, 2), false);
+        let mut out = [0; 4];
+        mixer.mix(&mut out);
+        assert_eq!(out, [600, 600, 500, 500]);
+        // the effect is done, the looping music goes on
+        assert_eq!(mixer.playing(2), 0);
+        assert_eq!(mixer.playing(1), 1);
+
+        mixer.play(Bus::Sfx, 3, samples(i16::MAX, 4), false);
+        mixer.mix(&mut out);
+        assert_eq!(out, [i16::MAX; 4]);
+    }
+
+    #[test]
+    fn test_voice_limit() {
+        let mut mixer = Mixer::new(config());
+        for value in 1..4 {
+            mixer.play(Bus::Sfx, 5, samples(value, 4), false);
+        }
+        assert_eq!(mixer.playing(5), 2);
+        // the first voice was cut
+        let mut out = [0; 1];
+        mixer.mix(&mut out);
+        assert_eq!(out, [2 + 3]);
+    }
+
+    #[test]
+    fn test_ducking() {
+        let mut mixer = Mixer::new(config());
+        mixer.play(Bus::Bgm, 1, samples(1000, 4), true);
+        mixer.play(Bus::Sfx, 7, samples(0, 2), false);
+        let mut out = [0; 2];
+        mixer.mix(&mut out);
+        assert_eq!(out, [250, 250]);
+        mixer.mix(&mut out);
+        assert_eq!(out, [500, 500]);
+    }
+}
@@ -1,21 +1,31 @@
 extern crate geometry;
 
-mod render;
+pub mod audio;
+pub mod render;
 mod controller;
 
 use std::cell::RefCell;
+use std::path::Path;
 
 use sdl2;
 use sdl2::event::Event;
 use sdl2::event::WindowEvent;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
 
 use rusttype;
 
+use core_net::packet::Packet;
+
 use crate::error::Error;
 use crate::game::Game;
-use crate::game::input::Controller;
+use crate::game::input::{ButtonState, Controller};
 use crate::game::input::MAX_CONTROLLERS as MAX_CTL;
+use crate::text_input::Edit;
+
+use self::audio::{AudioConfig, Mixer};
+
+/// Texture size used when the renderer doesn't report a limit
+const DEFAULT_MAX_TEXTURE_SIZE: u32 = 4096;
 
 // setup Rusttype
 lazy_static! {
@@ -32,9 +42,21 @@ pub struct Sdl {
     pub video: sdl2::VideoSubsystem,
     pub canvas: sdl2::render::Canvas<sdl2::video::Window>,
     pub texture_creator: sdl2::render::TextureCreator<sdl2::video::WindowContext>,
+    /// Sprites wider or taller than this are diced into several textures;
+    /// defaults to the renderer's limit and may be lowered.
+    pub max_texture_size: u32,
+    /// Cleans the edges of the RGBA sprites before they're uploaded, for
+    /// when they're drawn scaled; see `core_compat::utility::bleed`. Off by
+    /// default.
+    pub clean_edges: bool,
+    /// Draws the map tiles from 565 textures; `None` when the renderer can't
+    #[cfg(feature = "gl565")]
+    pub gl565: Option<render::gl565::Gl565>,
+    pub light_textures: render::map::LightTextures,
     // audio
     pub audio: sdl2::AudioSubsystem,
     pub audio_spec: sdl2::audio::AudioSpecDesired,
+    pub audio_device: sdl2::audio::AudioDevice<Mixer>,
     // event handlers
     pub event_pump: RefCell<sdl2::EventPump>,
     // controllers
@@ -60,15 +82,34 @@ impl Sdl {
             .present_vsync()
             .build()?;
         let texture_creator = canvas.texture_creator();
+        // no IME until the chat is typed in
+        video.text_input().stop();
+        let info = canvas.info();
+        let max_texture_size = match info.max_texture_width.min(info.max_texture_height) {
+            0 => DEFAULT_MAX_TEXTURE_SIZE,
+            size => size,
+        };
+        #[cfg(feature = "gl565")]
+        let gl565 = match render::gl565::Gl565::new(&video, &canvas) {
+            Ok(gl565) => Some(gl565),
+            Err(error) => {
+                println!("drawing the map tiles as RGBA: {:?}", error);
+                None
+            }
+        };
+        let light_textures = render::map::LightTextures::new(&texture_creator)?;
         let controller = context.game_controller()?;
         let controllers = RefCell::new([None, None, None, None]);
         let event_pump = RefCell::new(context.event_pump()?);
         let audio = context.audio()?;
         let audio_spec = sdl2::audio::AudioSpecDesired {
-            freq: Some(44100),
-            channels: Some(2),
-            samples: Some(4),
+            freq: Some(audio::FREQUENCY),
+            channels: Some(audio::CHANNELS),
+            samples: Some(1024),
         };
+        let audio_config = AudioConfig::load(Path::new(audio::AUDIO_CONFIG_PATH))?;
+        let audio_device = audio.open_playback(None, &audio_spec, |_| Mixer::new(audio_config))?;
+        audio_device.resume();
 
         // -- Create SDL state object
         let sdl = Sdl {
@@ -76,9 +117,15 @@ impl Sdl {
             video,
             canvas,
             texture_creator,
+            max_texture_size,
+            clean_edges: false,
+            #[cfg(feature = "gl565")]
+            gl565,
+            light_textures,
             event_pump,
             audio,
             audio_spec,
+            audio_device,
             controller,
             controllers,
             controller_count: 0,
@@ -96,11 +143,24 @@ impl Sdl {
         while let Some(new_event) = event_pump.poll_event() {
             if last_event.is_none() || new_event != last_event.unwrap() {
                 match new_event {
+                    Event::KeyDown { keycode: Some(key), keymod, .. } if game.state.chat.is_focused() => {
+                        self.chat_key(game, key, keymod);
+                    }
+                    Event::TextEditing { ref text, start, .. } if game.state.chat.is_focused() => {
+                        game.state.chat.compose(text, start.max(0) as usize);
+                    }
+                    Event::TextInput { ref text, .. } if game.state.chat.is_focused() => {
+                        game.state.chat.commit(text);
+                    }
                     Event::Quit { .. }
                     | Event::KeyDown { keycode: Some(Keycode::Escape), .. }
                     => {
                         game.input.should_quit = true;
                     }
+                    Event::KeyDown { keycode: Some(Keycode::Return), repeat: false, .. } => {
+                        game.state.chat.focus();
+                        self.video.text_input().start();
+                    }
                     Event::KeyDown { keycode: Some(key), repeat, .. }
                     => {
                         let is_down = true;
@@ -178,6 +238,35 @@ impl Sdl {
         } // end while new SDL event
     }
 
+    /// A key going down while the chat has the keyboard; the keys the IME
+    /// is composing with are left to it
+    fn chat_key(&self, game: &mut Game, key: Keycode, keymod: Mod) {
+        let chat = &mut game.state.chat;
+        let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+        let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+        let edit = match key {
+            Keycode::Left => Edit::Left,
+            Keycode::Right => Edit::Right,
+            Keycode::Home => Edit::Home,
+            Keycode::End => Edit::End,
+            Keycode::Backspace => Edit::Backspace,
+            Keycode::Delete => Edit::Delete,
+            Keycode::A if ctrl => Edit::SelectAll,
+            Keycode::Return | Keycode::KpEnter | Keycode::Escape if !chat.is_composing() => {
+                if key != Keycode::Escape {
+                    if let Some(text) = chat.submit() {
+                        game.state.outgoing.push(Packet::Say { text });
+                    }
+                }
+                chat.blur();
+                self.video.text_input().stop();
+                return;
+            }
+            _ => return,
+        };
+        chat.edit(edit, shift);
+    }
+
     pub fn render(&mut self, game: &mut Game, _dt: f32) {
         // start frame
         self.canvas.set_draw_color(sdl2::pixels::Color::RGB(75, 100, 255));
@@ -195,8 +284,18 @@ impl Sdl {
         {
             render::chars::chars(self, game);
         }
+        // -- lighting, then the weather over it
+        {
+            render::map::lights(self, game);
+            render::weather::weather(self, game);
+        }
         // -- skill(s)
         // -- window(s)
+        {
+            render::groups::groups(self, game);
+            render::trade::trade(self, game);
+            render::text_input::chat(self, game);
+        }
         // -- interface(s)
         // -- window-chrome
 
@@ -211,6 +310,10 @@ impl Sdl {
                                       game.input.mouse_y);
             render::text::line(self, &map_name, 10, 10);
             render::text::line(self, &mouse_coord, 10, 34);
+            if let Some(((x, y), in_sight)) = game.state.target {
+                let target = format!("Target X:{}, Y:{} ({})", x, y, if in_sight { "in sight" } else { "blocked" });
+                render::text::line(self, &target, 10, 58);
+            }
         }
 
         // finish frame
@@ -223,23 +326,33 @@ fn process_keycode(
     is_down: bool,
     input: &mut Controller,
 ) {
+    // a key may go up after going down while the chat had the keyboard
+    if let Some(button) = key_button(key, input) {
+        if button.pressed != is_down {
+            button.key_press(is_down);
+        }
+    }
+}
+
+/// The button of the controller a key is bound to
+fn key_button(key: Keycode, input: &mut Controller) -> Option<&mut ButtonState> {
     match key {
-        Keycode::W     => input.move_up.key_press(is_down),
-        Keycode::A     => input.move_left.key_press(is_down),
-        Keycode::S     => input.move_down.key_press(is_down),
-        Keycode::D     => input.move_right.key_press(is_down),
-        Keycode::Q     => input.left_shoulder.key_press(is_down),
-        Keycode::E     => input.right_shoulder.key_press(is_down),
-        Keycode::Up    => input.action_up.key_press(is_down),
-        Keycode::Down  => input.action_down.key_press(is_down),
-        Keycode::Right => input.action_right.key_press(is_down),
-        Keycode::Left  => input.action_left.key_press(is_down),
-        Keycode::K     => input.player_up.key_press(is_down),
-        Keycode::J     => input.player_down.key_press(is_down),
-        Keycode::H     => input.player_right.key_press(is_down),
-        Keycode::L     => input.player_left.key_press(is_down),
-        Keycode::F     => (),
-        Keycode::Space => (),
-        _              => (),
+        Keycode::W     => Some(&mut input.move_up),
+        Keycode::A     => Some(&mut input.move_left),
+        Keycode::S     => Some(&mut input.move_down),
+        Keycode::D     => Some(&mut input.move_right),
+        Keycode::Q     => Some(&mut input.left_shoulder),
+        Keycode::E     => Some(&mut input.right_shoulder),
+        Keycode::Up    => Some(&mut input.action_up),
+        Keycode::Down  => Some(&mut input.action_down),
+        Keycode::Right => Some(&mut input.action_right),
+        Keycode::Left  => Some(&mut input.action_left),
+        Keycode::K     => Some(&mut input.player_up),
+        Keycode::J     => Some(&mut input.player_down),
+        Keycode::H     => Some(&mut input.player_right),
+        Keycode::L     => Some(&mut input.player_left),
+        Keycode::F     => None,
+        Keycode::Space => None,
+        _              => None,
     }
 }
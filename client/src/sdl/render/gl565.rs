@@ -0,0 +1,279 @@
+//! Drawing the sprites' 565 pixels without converting them first.
+//!
+//! The undecoded pixels (see `PixelFormat::R5g6b5`) are uploaded as `RG8`
+//! textures, the low byte in red and the high one in green, which is half the
+//! memory of RGBA8. A fragment shader puts the color back together and
+//! discards the color key, so the CPU never converts a pixel.
+//!
+//! SDL's renderer doesn't know about shaders, so this draws straight into the
+//! OpenGL context of the SDL renderer: the renderer's queued draws are flushed
+//! first, and the GL state changed here is put back afterwards. It only works
+//! with the `opengl` render driver; `Gl565::new` gives up on any other.
+//!
+//! The textures are sampled with `NEAREST`, interpolating the packed bytes
+//! would mix the bits of different channels.
+
+use std::ffi::CString;
+use std::os::raw::c_int;
+use std::ptr;
+
+use gl;
+use gl::types::{GLchar, GLenum, GLint, GLuint};
+
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::sys::SDL_Renderer;
+use sdl2::video::Window;
+
+use core_compat::parser::rle::COLOR_KEY_565;
+
+use crate::error::Error;
+
+extern "C" {
+    // SDL 2.0.10 and newer; not in the bindings of the sdl2 crate we're on
+    fn SDL_RenderFlush(renderer: *mut SDL_Renderer) -> c_int;
+}
+
+static VERTEX_SHADER: &str = "
+#version 120
+attribute vec2 corner;
+// x, y, width and height of the source in texture coordinates
+uniform vec4 source;
+// x, y, width and height of the destination in clip space
+uniform vec4 destination;
+varying vec2 position;
+
+void main() {
+    position = source.xy + corner * source.zw;
+    gl_Position = vec4(destination.xy + corner * destination.zw, 0.0, 1.0);
+}
+";
+
+static FRAGMENT_SHADER: &str = "
+#version 120
+uniform sampler2D pixels;
+uniform float color_key;
+varying vec2 position;
+
+void main() {
+    vec2 bytes = floor(texture2D(pixels, position).rg * 255.0 + 0.5);
+    float value = bytes.x + bytes.y * 256.0;
+    if (value == color_key) {
+        discard;
+    }
+    float r = floor(value / 2048.0);
+    float g = mod(floor(value / 32.0), 64.0);
+    float b = mod(value, 32.0);
+    gl_FragColor = vec4(r / 31.0, g / 63.0, b / 31.0, 1.0);
+}
+";
+
+/// A sprite texture holding 565 pixels
+pub struct PackedTexture {
+    id: GLuint,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Drop for PackedTexture {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteTextures(1, &self.id) };
+    }
+}
+
+pub struct Gl565 {
+    program: GLuint,
+    /// The unit square, as a triangle strip
+    corners: GLuint,
+    corner_attribute: GLuint,
+    source: GLint,
+    destination: GLint,
+}
+
+impl Gl565 {
+    /// Sets up the shader in the canvas' context; fails unless the canvas
+    /// renders with OpenGL.
+    pub fn new(video: &sdl2::VideoSubsystem, canvas: &Canvas<Window>) -> Result<Gl565, Error> {
+        if canvas.info().name != "opengl" {
+            return Err(Error::Str(format!("the `{}` render driver has no shaders", canvas.info().name)));
+        }
+        gl::load_with(|name| video.gl_get_proc_address(name) as *const _);
+
+        unsafe {
+            let vertex = compile(gl::VERTEX_SHADER, VERTEX_SHADER)?;
+            let fragment = compile(gl::FRAGMENT_SHADER, FRAGMENT_SHADER)?;
+            let program = gl::CreateProgram();
+            gl::AttachShader(program, vertex);
+            gl::AttachShader(program, fragment);
+            gl::LinkProgram(program);
+            gl::DeleteShader(vertex);
+            gl::DeleteShader(fragment);
+            let mut linked = 0;
+            gl::GetProgramiv(program, gl::LINK_STATUS, &mut linked);
+            if linked == 0 {
+                let log = info_log(program, gl::GetProgramiv, gl::GetProgramInfoLog);
+                gl::DeleteProgram(program);
+                return Err(Error::Str(format!("linking the 565 shader failed: {}", log)));
+            }
+
+            let corner = CString::new("corner").unwrap();
+            let corner_attribute = gl::GetAttribLocation(program, corner.as_ptr());
+            let source = uniform(program, "source");
+            let destination = uniform(program, "destination");
+            let pixels = uniform(program, "pixels");
+            let color_key = uniform(program, "color_key");
+
+            let mut previous_program = 0;
+            gl::GetIntegerv(gl::CURRENT_PROGRAM, &mut previous_program);
+            gl::UseProgram(program);
+            gl::Uniform1i(pixels, 0);
+            gl::Uniform1f(color_key, COLOR_KEY_565 as f32);
+            gl::UseProgram(previous_program as GLuint);
+
+            let square: [f32; 8] = [0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0];
+            let mut corners = 0;
+            let mut previous_buffer = 0;
+            gl::GetIntegerv(gl::ARRAY_BUFFER_BINDING, &mut previous_buffer);
+            gl::GenBuffers(1, &mut corners);
+            gl::BindBuffer(gl::ARRAY_BUFFER, corners);
+            gl::BufferData(gl::ARRAY_BUFFER, std::mem::size_of_val(&square) as isize,
+                           square.as_ptr() as *const _, gl::STATIC_DRAW);
+            gl::BindBuffer(gl::ARRAY_BUFFER, previous_buffer as GLuint);
+
+            Ok(Gl565 { program, corners, corner_attribute: corner_attribute as GLuint, source, destination })
+        }
+    }
+
+    /// Uploads `width` x `height` little endian 565 pixels
+    pub fn upload(&mut self, width: u32, height: u32, pixels: &[u8]) -> PackedTexture {
+        assert_eq!(pixels.len(), (width * height * 2) as usize);
+        let mut id = 0;
+        unsafe {
+            let mut previous = 0;
+            gl::GetIntegerv(gl::TEXTURE_BINDING_2D, &mut previous);
+            gl::GenTextures(1, &mut id);
+            gl::BindTexture(gl::TEXTURE_2D, id);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as GLint);
+            // rows of odd width aren't 4 byte aligned
+            let mut alignment = 0;
+            gl::GetIntegerv(gl::UNPACK_ALIGNMENT, &mut alignment);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RG8 as GLint, width as i32, height as i32, 0,
+                           gl::RG, gl::UNSIGNED_BYTE, pixels.as_ptr() as *const _);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, alignment);
+            gl::BindTexture(gl::TEXTURE_2D, previous as GLuint);
+        }
+        PackedTexture { id, width, height }
+    }
+
+    /// Draws the `src` part of `texture` into `dst` on the canvas, like
+    /// `Canvas::copy`.
+    pub fn copy(&mut self, canvas: &Canvas<Window>, texture: &PackedTexture, src: Rect, dst: Rect) {
+        let output = match canvas.output_size() {
+            Ok(output) => output,
+            Err(_) => return,
+        };
+        let source = texture_rect(src, (texture.width, texture.height));
+        let destination = clip_rect(dst, output);
+        unsafe {
+            SDL_RenderFlush(canvas.raw());
+
+            let (mut program, mut unit, mut bound, mut buffer) = (0, 0, 0, 0);
+            gl::GetIntegerv(gl::CURRENT_PROGRAM, &mut program);
+            gl::GetIntegerv(gl::ACTIVE_TEXTURE, &mut unit);
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::GetIntegerv(gl::TEXTURE_BINDING_2D, &mut bound);
+            gl::GetIntegerv(gl::ARRAY_BUFFER_BINDING, &mut buffer);
+
+            gl::UseProgram(self.program);
+            gl::BindTexture(gl::TEXTURE_2D, texture.id);
+            gl::Uniform4fv(self.source, 1, source.as_ptr());
+            gl::Uniform4fv(self.destination, 1, destination.as_ptr());
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.corners);
+            gl::EnableVertexAttribArray(self.corner_attribute);
+            gl::VertexAttribPointer(self.corner_attribute, 2, gl::FLOAT, gl::FALSE, 0, ptr::null());
+            gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
+
+            gl::DisableVertexAttribArray(self.corner_attribute);
+            gl::BindBuffer(gl::ARRAY_BUFFER, buffer as GLuint);
+            gl::BindTexture(gl::TEXTURE_2D, bound as GLuint);
+            gl::ActiveTexture(unit as GLenum);
+            gl::UseProgram(program as GLuint);
+        }
+    }
+}
+
+impl Drop for Gl565 {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.corners);
+            gl::DeleteProgram(self.program);
+        }
+    }
+}
+
+unsafe fn compile(kind: GLenum, source: &str) -> Result<GLuint, Error> {
+    let shader = gl::CreateShader(kind);
+    let source = CString::new(source).unwrap();
+    gl::ShaderSource(shader, 1, &source.as_ptr(), ptr::null());
+    gl::CompileShader(shader);
+    let mut compiled = 0;
+    gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut compiled);
+    if compiled == 0 {
+        let log = info_log(shader, gl::GetShaderiv, gl::GetShaderInfoLog);
+        gl::DeleteShader(shader);
+        return Err(Error::Str(format!("compiling the 565 shader failed: {}", log)));
+    }
+    Ok(shader)
+}
+
+unsafe fn uniform(program: GLuint, name: &str) -> GLint {
+    let name = CString::new(name).unwrap();
+    gl::GetUniformLocation(program, name.as_ptr())
+}
+
+unsafe fn info_log(
+    object: GLuint,
+    get: unsafe fn(GLuint, GLenum, *mut GLint),
+    log: unsafe fn(GLuint, i32, *mut i32, *mut GLchar),
+) -> String {
+    let mut length = 0;
+    get(object, gl::INFO_LOG_LENGTH, &mut length);
+    let mut buffer = vec![0u8; length.max(1) as usize];
+    log(object, length, ptr::null_mut(), buffer.as_mut_ptr() as *mut GLchar);
+    String::from_utf8_lossy(&buffer).trim_end_matches('\0').into()
+}
+
+/// `rect` in the texture coordinates of a texture of `size`
+fn texture_rect(rect: Rect, size: (u32, u32)) -> [f32; 4] {
+    let (width, height) = (size.0 as f32, size.1 as f32);
+    [rect.x() as f32 / width, rect.y() as f32 / height,
+     rect.width() as f32 / width, rect.height() as f32 / height]
+}
+
+/// `rect` on an output of `size` in clip space, where y points up
+fn clip_rect(rect: Rect, size: (u32, u32)) -> [f32; 4] {
+    let (width, height) = (size.0 as f32, size.1 as f32);
+    [rect.x() as f32 * 2.0 / width - 1.0, 1.0 - rect.y() as f32 * 2.0 / height,
+     rect.width() as f32 * 2.0 / width, -(rect.height() as f32) * 2.0 / height]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_texture_rect() {
+        assert_eq!(texture_rect(Rect::new(24, 0, 24, 12), (48, 24)), [0.5, 0.0, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn test_clip_rect() {
+        // the whole output, from the top left corner down
+        assert_eq!(clip_rect(Rect::new(0, 0, 800, 600), (800, 600)), [-1.0, 1.0, 2.0, -2.0]);
+        assert_eq!(clip_rect(Rect::new(400, 300, 200, 150), (800, 600)), [0.0, 0.0, 0.5, -0.5]);
+    }
+}
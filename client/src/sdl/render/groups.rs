@@ -0,0 +1,18 @@
+use crate::game::Game;
+use crate::sdl::Sdl;
+use crate::sdl::render::panel::{self, PANEL_MARGIN};
+
+const PANEL_WIDTH: u32 = 200;
+
+/// Draws the party and guild panels down the right side of the window,
+/// each only while there is one
+pub fn groups(sdl: &mut Sdl, game: &mut Game) {
+    let party = game.state.groups.party_lines(|id| format!("#{}", id));
+    let guild = game.state.groups.guild_lines();
+    let x = game.window.0 - PANEL_WIDTH as i32 - PANEL_MARGIN;
+    let mut y = 60;
+    for lines in [party, guild].iter().filter(|lines| !lines.is_empty()) {
+        let rect = panel::lines(sdl, game, x, y, PANEL_WIDTH, lines);
+        y = rect.bottom() + PANEL_MARGIN;
+    }
+}
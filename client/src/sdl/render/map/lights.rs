@@ -0,0 +1,115 @@
+use std::time::Instant;
+
+use sdl2::pixels::Color;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Texture, TextureCreator};
+use sdl2::video::WindowContext;
+
+use crate::error::Error;
+use crate::sdl::Sdl;
+use crate::game::Game;
+
+const LIGHT_TEXTURE_SIZE: u32 = 128;
+
+/// The textures of the tint pass, kept from one frame to the next: with
+/// `unsafe_textures` a texture isn't freed when it's dropped.
+pub struct LightTextures {
+    /// Radial fall-off used for every light, tinted with the light's colour
+    falloff: Texture,
+    /// The window sized target and its size, made again when the window is
+    /// resized
+    target: Option<(Texture, (u32, u32))>,
+}
+
+impl LightTextures {
+    pub fn new(texture_creator: &TextureCreator<WindowContext>) -> Result<LightTextures, Error> {
+        let size = LIGHT_TEXTURE_SIZE;
+        let mut falloff = texture_creator.create_texture(
+            Some(PixelFormatEnum::ABGR8888),
+            sdl2::render::TextureAccess::Static,
+            size,
+            size)?;
+        let mut pixels = Vec::<u8>::with_capacity((size * size * 4) as usize);
+        let half = size as f32 / 2.0;
+        for y in 0..size {
+            for x in 0..size {
+                let dx = x as f32 + 0.5 - half;
+                let dy = y as f32 + 0.5 - half;
+                let falloff = (1.0 - (dx * dx + dy * dy).sqrt() / half).max(0.0);
+                pixels.extend_from_slice(&[0xFF, 0xFF, 0xFF, (falloff * falloff * 255.0) as u8]);
+            }
+        }
+        falloff.update(None, &pixels, (size * 4) as usize).map_err(|error| Error::Str(error.to_string()))?;
+        falloff.set_blend_mode(BlendMode::Add);
+        Ok(LightTextures { falloff, target: None })
+    }
+
+    /// The fall-off and the target for a window of `size`; `None` if render
+    /// targets aren't supported
+    fn textures(&mut self, texture_creator: &TextureCreator<WindowContext>,
+                size: (u32, u32)) -> Option<(&mut Texture, &mut Texture)> {
+        if self.target.as_ref().is_some_and(|&(_, target_size)| target_size != size) {
+            if let Some((target, _)) = self.target.take() {
+                // nothing draws with it any more
+                unsafe { target.destroy(); }
+            }
+        }
+        if self.target.is_none() {
+            let mut target = texture_creator.create_texture_target(Some(PixelFormatEnum::ABGR8888), size.0, size.1).ok()?;
+            target.set_blend_mode(BlendMode::Mod);
+            self.target = Some((target, size));
+        }
+        let falloff = &mut self.falloff;
+        self.target.as_mut().map(|&mut (ref mut target, _)| (falloff, target))
+    }
+}
+
+/// Tint pass: the ambient colour and the additive point lights are drawn into
+/// a window sized target which is then multiplied over the rendered map. The
+/// ambient colour of the map is darkened by the time of day and the weather,
+/// so maps without lights are tinted too, by multiplying the colour straight
+/// over the map without the target.
+pub fn lights(sdl: &mut Sdl, game: &mut Game) {
+    let tint = game.state.ambiance.tint(Instant::now());
+    let light_map = game.map_manager.get_lights(game.state.map).unwrap_or_default();
+    if light_map.lights.is_empty() && light_map.ambient == [0xFF; 3] && tint == [0xFF; 3] {
+        return;
+    }
+    let [a_r, a_g, a_b] = [0, 1, 2].map(|idx| (light_map.ambient[idx] as u32 * tint[idx] as u32 / 0xFF) as u8);
+    if light_map.lights.is_empty() {
+        let blend_mode = sdl.canvas.blend_mode();
+        sdl.canvas.set_blend_mode(BlendMode::Mod);
+        sdl.canvas.set_draw_color(Color::RGB(a_r, a_g, a_b));
+        let _ = sdl.canvas.fill_rect(None);
+        sdl.canvas.set_blend_mode(blend_mode);
+        return;
+    }
+    let (off_x, off_y) = game.state.map_off;
+    let size = (game.window.0 as u32, game.window.1 as u32);
+
+    let (falloff, target) = match sdl.light_textures.textures(&sdl.texture_creator, size) {
+        Some(textures) => textures,
+        // render targets aren't supported: skip the tint pass
+        None => return,
+    };
+    let result = sdl.canvas.with_texture_canvas(target, |canvas| {
+        canvas.set_draw_color(Color::RGB(a_r, a_g, a_b));
+        canvas.clear();
+        for light in light_map.lights.iter() {
+            let [r, g, b] = light.color;
+            falloff.set_color_mod(r, g, b);
+            let radius = light.radius as i32;
+            let dst_rect = Rect::new(
+                light.x - radius + off_x,
+                light.y - radius + off_y,
+                light.radius * 2,
+                light.radius * 2);
+            let _ = canvas.copy(falloff, None, dst_rect);
+        }
+    });
+    if result.is_err() {
+        return;
+    }
+    let _ = sdl.canvas.copy(target, None, None);
+}
@@ -1,5 +1,6 @@
 mod tiles;
 mod objects;
+mod lights;
 
 /// Helper function to move to the next map tile in order.
 /// TODO: Probably fold into `map` entity iterator?
@@ -13,3 +14,4 @@ fn next_tile(x: &mut i32, y: &mut i32, stride: i32) {
 
 pub use self::tiles::tiles;
 pub use self::objects::objects;
+pub use self::lights::{lights, LightTextures};
@@ -67,7 +67,7 @@ pub fn objects(sdl: &mut Sdl, game: &mut Game) {
                             dst_rect.offset(img.dest_x, img.dest_y);
 
                             // render
-                            let _ = sdl.canvas.copy(&sprite.texture, src_rect, dst_rect);
+                            sprite.copy(sdl, src_rect, dst_rect);
 
                             // debug renders
                             // {
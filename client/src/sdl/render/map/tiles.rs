@@ -51,7 +51,7 @@ pub fn tiles(sdl: &mut Sdl, game: &mut Game) {
                             dst_rect.offset(game.state.map_off.0, game.state.map_off.1);
 
                             // render
-                            let _ = sdl.canvas.copy(&sprite.texture, src_rect, dst_rect);
+                            sprite.copy(sdl, src_rect, dst_rect);
 
                             // debug render
                             // {
@@ -1,3 +1,10 @@
 pub mod map;
 pub mod text;
 pub mod chars;
+pub mod groups;
+pub mod panel;
+pub mod text_input;
+pub mod trade;
+pub mod weather;
+#[cfg(feature = "gl565")]
+pub mod gl565;
@@ -0,0 +1,56 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+use core_compat::entity::entry::Entry;
+use core_compat::entity::sprite_type::SpriteType;
+
+use crate::game::Game;
+use crate::sdl::Sdl;
+use crate::sdl::render::text;
+
+/// Interface sprite the panels are drawn on, stretched to fit
+const PANEL_BACKGROUND: (u32, u32) = (0, 0);
+pub const PANEL_MARGIN: i32 = 10;
+pub const LINE_HEIGHT: i32 = 24;
+
+/// Draws `lines` on a panel at `x`, `y`; returns where the panel went.
+pub fn lines(sdl: &mut Sdl, game: &mut Game, x: i32, y: i32, width: u32, lines: &[String]) -> Rect {
+    let rect = panel_rect(x, y, width, lines.len());
+    background(sdl, game, rect);
+    for (i, line) in lines.iter().enumerate() {
+        text::line(sdl, line, x + PANEL_MARGIN, y + PANEL_MARGIN / 2 + i as i32 * LINE_HEIGHT);
+    }
+    rect
+}
+
+/// A panel holding `lines` lines of text at `x`, `y`
+fn panel_rect(x: i32, y: i32, width: u32, lines: usize) -> Rect {
+    Rect::new(x, y, width, (lines as i32 * LINE_HEIGHT + PANEL_MARGIN) as u32)
+}
+
+/// The background of a panel; a plain box when the sprite can't be loaded
+pub fn background(sdl: &mut Sdl, game: &mut Game, rect: Rect) {
+    let entry = Entry::new(PANEL_BACKGROUND.0, PANEL_BACKGROUND.1);
+    match game.sprite_manager.get_sprite_entry(&entry, SpriteType::Interface, sdl) {
+        Ok(background) => {
+            let src = Rect::new(0, 0, background.sprite.x_dim as u32, background.sprite.y_dim as u32);
+            background.copy(sdl, src, rect);
+        }
+        Err(_) => {
+            sdl.canvas.set_draw_color(Color::RGBA(20, 20, 40, 200));
+            let _ = sdl.canvas.fill_rect(rect);
+            sdl.canvas.set_draw_color(Color::RGB(120, 120, 160));
+            let _ = sdl.canvas.draw_rect(rect);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_panel_rect() {
+        assert_eq!(panel_rect(590, 60, 200, 3), Rect::new(590, 60, 200, 82));
+    }
+}
@@ -71,3 +71,14 @@ pub fn line(sdl: &mut Sdl, text: &str, x: i32, y: i32) {
     let _ = sdl.canvas.copy(&texture, src_rect, dst_rect);
 }
 
+/// How far `text` drawn by `line` reaches to the right, for putting a caret
+/// or a selection in it
+pub fn advance(text: &str) -> i32 {
+    let height: f32 = 24.0;
+    let scale = rusttype::Scale { x: height, y: height };
+    FONT.layout(text, scale, rusttype::point(0.0, 0.0))
+        .last()
+        .map_or(0.0, |glyph| glyph.position().x + glyph.unpositioned().h_metrics().advance_width)
+        .round() as i32
+}
+
@@ -0,0 +1,40 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+use crate::game::Game;
+use crate::sdl::Sdl;
+use crate::sdl::render::panel::{self, LINE_HEIGHT, PANEL_MARGIN};
+use crate::sdl::render::text;
+
+const CHAT_WIDTH: u32 = 400;
+
+/// Draws the chat box at the bottom left of the window while it's typed
+/// in: the selection under the text, what the IME is composing underlined,
+/// and the caret
+pub fn chat(sdl: &mut Sdl, game: &mut Game) {
+    if !game.state.chat.is_focused() {
+        return;
+    }
+    let shown = game.state.chat.shown();
+    let rect = Rect::new(PANEL_MARGIN, game.window.1 - LINE_HEIGHT - 2 * PANEL_MARGIN,
+                         CHAT_WIDTH, (LINE_HEIGHT + PANEL_MARGIN) as u32);
+    panel::background(sdl, game, rect);
+    let (x, y) = (rect.x() + PANEL_MARGIN, rect.y() + PANEL_MARGIN / 2);
+    let at = |bytes: usize| x + text::advance(&shown.text[..bytes]);
+
+    if let Some(ref selection) = shown.selection {
+        sdl.canvas.set_draw_color(Color::RGBA(80, 110, 200, 200));
+        let width = (at(selection.end) - at(selection.start)).max(1) as u32;
+        let _ = sdl.canvas.fill_rect(Rect::new(at(selection.start), y, width, LINE_HEIGHT as u32));
+    }
+    text::line(sdl, &shown.text, x, y);
+    sdl.canvas.set_draw_color(Color::RGB(255, 255, 255));
+    if let Some(ref composing) = shown.composing {
+        let _ = sdl.canvas.draw_line((at(composing.start), y + LINE_HEIGHT - 2), (at(composing.end), y + LINE_HEIGHT - 2));
+    }
+    let caret = at(shown.caret);
+    let _ = sdl.canvas.draw_line((caret, y + 2), (caret, y + LINE_HEIGHT - 2));
+
+    // the IME puts its candidate list next to this
+    sdl.video.text_input().set_rect(Rect::new(caret, y, 1, LINE_HEIGHT as u32));
+}
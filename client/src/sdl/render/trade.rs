@@ -0,0 +1,18 @@
+use crate::game::Game;
+use crate::sdl::Sdl;
+use crate::sdl::render::panel::{self, PANEL_MARGIN};
+
+const COLUMN_WIDTH: u32 = 180;
+
+/// Draws the trade window in the middle of the window while a trade is
+/// open: the own offer on the left, the other trader's on the right
+pub fn trade(sdl: &mut Sdl, game: &mut Game) {
+    let (mine, theirs) = match game.state.trading.trade {
+        Some(ref trade) => trade.columns(),
+        None => return,
+    };
+    let x = game.window.0 / 2 - COLUMN_WIDTH as i32 - PANEL_MARGIN / 2;
+    let y = game.window.1 / 3;
+    panel::lines(sdl, game, x, y, COLUMN_WIDTH, &mine);
+    panel::lines(sdl, game, x + COLUMN_WIDTH as i32 + PANEL_MARGIN, y, COLUMN_WIDTH, &theirs);
+}
@@ -0,0 +1,75 @@
+use std::time::Instant;
+
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::BlendMode;
+
+use core_rules::ambiance::Weather;
+
+use crate::game::Game;
+use crate::sdl::Sdl;
+
+/// Rain drops or snow flakes on screen at once
+const PARTICLES: u32 = 150;
+
+/// Draws the rain, snow or fog over the map; the darker sky is the tint of
+/// `map::lights`.
+pub fn weather(sdl: &mut Sdl, game: &mut Game) {
+    let ambiance = &game.state.ambiance;
+    let millis = ambiance.since.map_or(0, |since| Instant::now().saturating_duration_since(since).as_millis() as u64);
+    let window = (game.window.0 as u32, game.window.1 as u32);
+    let blend_mode = sdl.canvas.blend_mode();
+    sdl.canvas.set_blend_mode(BlendMode::Blend);
+    match ambiance.weather {
+        Weather::Clear => (),
+        Weather::Rain => {
+            sdl.canvas.set_draw_color(Color::RGBA(170, 180, 220, 150));
+            for (x, y) in particles(PARTICLES, millis, 900, window) {
+                let _ = sdl.canvas.draw_line(Point::new(x, y), Point::new(x - 3, y + 12));
+            }
+        }
+        Weather::Snow => {
+            sdl.canvas.set_draw_color(Color::RGBA(250, 250, 255, 220));
+            for (x, y) in particles(PARTICLES, millis, 60, window) {
+                // drifting from side to side as it falls
+                let drift = ((y as f32 / 40.0).sin() * 6.0) as i32;
+                let _ = sdl.canvas.fill_rect(Rect::new(x + drift, y, 3, 3));
+            }
+        }
+        Weather::Fog => {
+            sdl.canvas.set_draw_color(Color::RGBA(200, 200, 210, 90));
+            let _ = sdl.canvas.fill_rect(None);
+        }
+    }
+    sdl.canvas.set_blend_mode(blend_mode);
+}
+
+/// Where `count` particles falling `speed` pixels a second are after
+/// `millis`, in a window of `size`; each falls down its own column, from its
+/// own height, and comes back at the top once it's out at the bottom.
+fn particles(count: u32, millis: u64, speed: u64, size: (u32, u32)) -> impl Iterator<Item = (i32, i32)> {
+    let (width, height) = (size.0.max(1) as u64, size.1.max(1) as u64);
+    (0..count as u64).map(move |idx| {
+        let hash = idx.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 16;
+        let x = hash % width;
+        let y = (hash / width + millis * speed / 1000) % height;
+        (x as i32, y as i32)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_particles() {
+        let at = |millis| particles(20, millis, 100, (800, 600)).collect::<Vec<_>>();
+        let (start, later) = (at(0), at(500));
+        assert!(start.iter().all(|&(x, y)| (0..800).contains(&x) && (0..600).contains(&y)));
+        // spread over the window, and 50 pixels further down half a second on
+        assert!(start.iter().map(|&(x, _)| x / 100).collect::<std::collections::BTreeSet<_>>().len() > 4);
+        for (&(x, y), &(later_x, later_y)) in start.iter().zip(later.iter()) {
+            assert_eq!((later_x, later_y), (x, (y + 50) % 600));
+        }
+    }
+}
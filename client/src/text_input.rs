@@ -0,0 +1,260 @@
+//! The text box of the chat and of naming a character: the typed text, the
+//! caret and the selection, and what an IME is still composing. Korean is
+//! typed a syllable at a time; the syllable being put together is shown at
+//! the caret but isn't part of the text until the IME commits it, and the
+//! editing keys belong to the IME meanwhile.
+
+use std::ops::Range;
+
+/// The editing keys
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edit {
+    Left,
+    Right,
+    Home,
+    End,
+    Backspace,
+    Delete,
+    SelectAll,
+}
+
+/// The text box as it's drawn: the text with the composition in it, and
+/// where in that the caret, the composition and the selection are, in bytes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shown {
+    pub text: String,
+    pub caret: usize,
+    pub composing: Option<Range<usize>>,
+    pub selection: Option<Range<usize>>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TextInput {
+    text: String,
+    /// In bytes, on a character boundary
+    caret: usize,
+    /// Where the selection started; it runs to the caret
+    anchor: Option<usize>,
+    /// What the IME is composing, empty when it isn't
+    composition: String,
+    /// Where the IME's caret is in the composition, in characters
+    composition_caret: usize,
+    /// The most characters the text may have; 0 for no limit
+    max_chars: usize,
+    focused: bool,
+}
+
+impl TextInput {
+    pub fn new(max_chars: usize) -> TextInput {
+        TextInput { max_chars, ..TextInput::default() }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    pub fn is_composing(&self) -> bool {
+        !self.composition.is_empty()
+    }
+
+    /// Starts taking the typed text
+    pub fn focus(&mut self) {
+        self.focused = true;
+    }
+
+    /// Stops taking the typed text; what was being composed is dropped
+    pub fn blur(&mut self) {
+        self.focused = false;
+        self.anchor = None;
+        self.composition.clear();
+        self.composition_caret = 0;
+    }
+
+    /// The selected bytes of the text, if any
+    pub fn selection(&self) -> Option<Range<usize>> {
+        match self.anchor {
+            Some(anchor) if anchor < self.caret => Some(anchor..self.caret),
+            Some(anchor) if anchor > self.caret => Some(self.caret..anchor),
+            _ => None,
+        }
+    }
+
+    /// What the IME is composing now, its caret `cursor` characters into
+    /// it; nothing ends the composition without committing it
+    pub fn compose(&mut self, text: &str, cursor: usize) {
+        self.composition = text.to_string();
+        self.composition_caret = cursor.min(text.chars().count());
+    }
+
+    /// Puts what was typed or what the IME committed in place of the
+    /// selection, as much of it as the limit lets in
+    pub fn commit(&mut self, text: &str) {
+        self.composition.clear();
+        self.composition_caret = 0;
+        if let Some(selection) = self.selection() {
+            self.text.replace_range(selection.clone(), "");
+            self.caret = selection.start;
+        }
+        self.anchor = None;
+        let room = match self.max_chars {
+            0 => usize::MAX,
+            max => max.saturating_sub(self.text.chars().count()),
+        };
+        let end = text.char_indices().nth(room).map_or(text.len(), |(at, _)| at);
+        self.text.insert_str(self.caret, &text[..end]);
+        self.caret += end;
+    }
+
+    /// Applies an editing key, `extend` being whether shift is held; false
+    /// when it does nothing, as while the IME is composing
+    pub fn edit(&mut self, edit: Edit, extend: bool) -> bool {
+        if self.is_composing() {
+            return false;
+        }
+        let previous = self.text[..self.caret].chars().next_back().map(|c| self.caret - c.len_utf8());
+        let next = self.text[self.caret..].chars().next().map(|c| self.caret + c.len_utf8());
+        let moved = |input: &mut TextInput, to: Option<usize>| {
+            match (extend, input.anchor) {
+                (true, None) => input.anchor = Some(input.caret),
+                (false, _) => input.anchor = None,
+                _ => (),
+            }
+            if let Some(to) = to {
+                input.caret = to;
+            }
+        };
+        match edit {
+            Edit::Left => moved(self, previous),
+            Edit::Right => moved(self, next),
+            Edit::Home => moved(self, Some(0)),
+            Edit::End => {
+                let end = self.text.len();
+                moved(self, Some(end));
+            }
+            Edit::SelectAll => {
+                self.anchor = Some(0);
+                self.caret = self.text.len();
+            }
+            Edit::Backspace | Edit::Delete => {
+                let range = match (self.selection(), edit) {
+                    (Some(selection), _) => selection,
+                    (None, Edit::Backspace) => match previous {
+                        Some(previous) => previous..self.caret,
+                        None => return false,
+                    },
+                    (None, _) => match next {
+                        Some(next) => self.caret..next,
+                        None => return false,
+                    },
+                };
+                self.text.replace_range(range.clone(), "");
+                self.caret = range.start;
+                self.anchor = None;
+            }
+        }
+        true
+    }
+
+    /// Takes the text, to send it, leaving the box empty; `None` for a box
+    /// with nothing but spaces in it, which is emptied too
+    pub fn submit(&mut self) -> Option<String> {
+        let text = std::mem::take(&mut self.text);
+        self.caret = 0;
+        self.anchor = None;
+        if text.trim().is_empty() {
+            None
+        } else {
+            Some(text)
+        }
+    }
+
+    /// What to draw
+    pub fn shown(&self) -> Shown {
+        let mut text = self.text.clone();
+        text.insert_str(self.caret, &self.composition);
+        let caret = self.caret + self.composition.chars()
+            .take(self.composition_caret)
+            .map(char::len_utf8)
+            .sum::<usize>();
+        Shown {
+            text,
+            caret,
+            composing: if self.is_composing() {
+                Some(self.caret..self.caret + self.composition.len())
+            } else {
+                None
+            },
+            selection: if self.is_composing() { None } else { self.selection() },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_composition() {
+        let mut input = TextInput::new(0);
+        input.focus();
+        input.commit("ab");
+        input.edit(Edit::Left, false);
+
+        // 한 is put together from ㅎ, 하 and 한, then committed
+        input.compose("ㅎ", 1);
+        input.compose("하", 1);
+        assert!(!input.edit(Edit::Backspace, false));
+        input.compose("한", 1);
+        assert_eq!(input.text(), "ab");
+        assert_eq!(input.shown(), Shown { text: "a한b".into(), caret: 4, composing: Some(1..4), selection: None });
+        input.commit("한");
+        assert_eq!(input.shown(), Shown { text: "a한b".into(), caret: 4, composing: None, selection: None });
+
+        // the IME giving up leaves the text as it was
+        input.compose("ㄱ", 1);
+        input.compose("", 0);
+        assert!(!input.is_composing());
+        assert_eq!(input.text(), "a한b");
+
+        input.blur();
+        input.compose("ㄴ", 1);
+        input.blur();
+        assert_eq!(input.shown().text, "a한b");
+    }
+
+    #[test]
+    fn test_editing() {
+        let mut input = TextInput::new(5);
+        input.commit("가나다");
+        input.edit(Edit::Left, false);
+        input.edit(Edit::Left, true);
+        assert_eq!(input.selection(), Some(3..6));
+        // typing replaces the selection
+        input.commit("x");
+        assert_eq!(input.text(), "가x다");
+        input.edit(Edit::Backspace, false);
+        input.edit(Edit::Delete, false);
+        assert_eq!(input.text(), "가");
+        assert!(!input.edit(Edit::Delete, false));
+
+        // no more than the limit gets in
+        input.commit("abcdef");
+        assert_eq!(input.text(), "가abcd");
+        input.edit(Edit::SelectAll, false);
+        input.edit(Edit::Backspace, false);
+        assert_eq!(input.text(), "");
+        input.edit(Edit::Home, false);
+
+        input.commit(" ");
+        assert_eq!(input.submit(), None);
+        input.commit("hi");
+        input.edit(Edit::Home, true);
+        assert_eq!(input.shown().selection, Some(0..2));
+        assert_eq!(input.submit(), Some("hi".to_string()));
+        assert_eq!(input.shown(), Shown { text: String::new(), caret: 0, composing: None, selection: None });
+    }
+}
@@ -0,0 +1,71 @@
+//! The trade window's state, as last told by the server.
+
+use core_net::packet::Packet;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradeView {
+    /// The other trader
+    pub with: u32,
+    /// Offers as item and count
+    pub mine: Vec<(u32, u32)>,
+    pub theirs: Vec<(u32, u32)>,
+    pub confirmed: bool,
+    pub they_confirmed: bool,
+}
+
+impl TradeView {
+    /// The lines of the two columns of the trade window
+    pub fn columns(&self) -> (Vec<String>, Vec<String>) {
+        let column = |title: &str, offer: &[(u32, u32)], confirmed: bool| -> Vec<String> {
+            let title = if confirmed { format!("{} (ok)", title) } else { title.to_string() };
+            Some(title).into_iter()
+                .chain(offer.iter().map(|&(item, count)| format!("{} x{}", item, count)))
+                .collect()
+        };
+        (column("You", &self.mine, self.confirmed),
+         column(&format!("#{}", self.with), &self.theirs, self.they_confirmed))
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Trading {
+    /// The last trade request, by requester id and name
+    pub request: Option<(u32, String)>,
+    pub trade: Option<TradeView>,
+    /// How the last trade ended, whether it completed
+    pub last_completed: Option<bool>,
+}
+
+impl Trading {
+    pub fn apply(&mut self, packet: &Packet) {
+        match *packet {
+            Packet::TradeRequested { from, ref name } => self.request = Some((from, name.clone())),
+            Packet::Trade { with, ref mine, ref theirs, confirmed, they_confirmed } => {
+                self.request = None;
+                self.trade = Some(TradeView { with, mine: mine.clone(), theirs: theirs.clone(), confirmed, they_confirmed });
+            }
+            Packet::TradeClosed { completed } => {
+                self.trade = None;
+                self.last_completed = Some(completed);
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let mut trading = Trading::default();
+        trading.apply(&Packet::TradeRequested { from: 1, name: "Philar".into() });
+        trading.apply(&Packet::Trade { with: 1, mine: vec![(2, 1)], theirs: vec![(1, 40)], confirmed: false, they_confirmed: true });
+        assert_eq!(trading.request, None);
+        let columns = trading.trade.as_ref().unwrap().columns();
+        assert_eq!(columns, (vec!["You".into(), "2 x1".into()], vec!["#1 (ok)".into(), "1 x40".into()]));
+        trading.apply(&Packet::TradeClosed { completed: true });
+        assert_eq!((trading.trade.is_none(), trading.last_completed), (true, Some(true)));
+    }
+}
@@ -0,0 +1,14 @@
+/// What the pixels of a resource image add up to, as counted by
+/// `Resource::stats`; a pixel counts as opaque when it is painted at all.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ImageStats {
+    pub opaque_pixels: u32,
+    /// `opaque_pixels` over all the pixels of the image, 0 for an image
+    /// without any
+    pub opaque_ratio: f32,
+    /// The mean red, green and blue of the opaque pixels
+    pub average_color: Option<(u8, u8, u8)>,
+    /// The x, y, width and height of the smallest rectangle holding the
+    /// opaque pixels, from the top left of the image
+    pub bounds: Option<(i32, i32, i32, i32)>,
+}
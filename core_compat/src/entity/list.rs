@@ -1,6 +1,17 @@
 
+#[cfg(feature = "mmap")]
+use std::path::Path;
+use alloc::vec::Vec;
+
 use crate::entity::list_item::ListItem;
+#[cfg(feature = "mmap")]
+use crate::error::Error;
+#[cfg(feature = "mmap")]
+use crate::parser::lst::parse_lst;
+#[cfg(feature = "mmap")]
+use crate::utility::mmap::map_file;
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct List {
     pub items: Vec<ListItem>,
 }
@@ -12,6 +23,13 @@ impl List {
         }
     }
 
+    /// Parses the LST file at `path` straight from memory it's mapped into,
+    /// rather than reading a copy of it first
+    #[cfg(feature = "mmap")]
+    pub fn load_mmap(path: &Path, use_v2: bool) -> Result<List, Error> {
+        parse_lst(&map_file(path)?, use_v2)
+    }
+
     pub fn get_item(&self, index: usize) -> Option<&ListItem> {
         for item in self.items.iter() {
             if item.id as usize == index {
@@ -1,6 +1,9 @@
+use alloc::string::String;
+
 use crate::entity::entry::Entry;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ListItem {
     pub name: String,
     pub id: u32,
@@ -1,4 +1,6 @@
 
+use alloc::vec::Vec;
+
 use crate::entity::event::Event;
 use crate::entity::map_tile::MapTile;
 
@@ -80,6 +82,10 @@ impl Map {
         &self.tiles
     }
 
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
     pub fn tile_count(&self) -> usize {
         self.tiles.len()
     }
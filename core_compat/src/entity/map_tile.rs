@@ -8,6 +8,13 @@ pub struct MapTile {
     pub collision: u32,
 }
 
+impl MapTile {
+    /// A collision value of 0 means nothing is blocking the tile.
+    pub fn is_passable(&self) -> bool {
+        self.collision == 0
+    }
+}
+
 // NOTE: The `Entry` struct Looks something like :
 //       Entry {
 //           file_num: u32,
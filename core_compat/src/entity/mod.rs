@@ -1,11 +1,14 @@
 pub mod entry;
 pub mod event;
+pub mod image_stats;
 pub mod list;
 pub mod list_item;
 pub mod map;
 pub mod map_tile;
+pub mod raw_header_extras;
 pub mod resource;
 pub mod resource_file;
+pub mod resource_meta;
 pub mod rmd;
 pub mod rmd_animation;
 pub mod rmd_image;
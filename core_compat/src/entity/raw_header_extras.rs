@@ -0,0 +1,46 @@
+/// The four fields of a resource header nobody knows the meaning of yet,
+/// as read. Most resources have them all zero. The methods read them the
+/// ways suspected so far, so the guesses can be checked against the whole
+/// data set; they return `None` where a field doesn't look like the guess.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct RawHeaderExtras {
+    pub unknown_1: u32,
+    pub unknown_2: u32,
+    pub unknown_3: u32,
+    pub unknown_4: u32,
+}
+
+/// Farthest a shadow is taken to be drawn from its sprite, in pixels
+pub const MAX_SHADOW_OFFSET: i16 = 64;
+
+impl RawHeaderExtras {
+    pub fn is_zero(&self) -> bool {
+        *self == RawHeaderExtras::default()
+    }
+
+    /// `unknown_1` as a signed x (low half) and y (high half) offset of the
+    /// sprite's shadow, when it is set and both stay within
+    /// `MAX_SHADOW_OFFSET`
+    pub fn shadow_offset(&self) -> Option<(i16, i16)> {
+        let (x, y) = (self.unknown_1 as u16 as i16, (self.unknown_1 >> 16) as u16 as i16);
+        let near = |v: i16| v.checked_abs().is_some_and(|v| v <= MAX_SHADOW_OFFSET);
+        if self.unknown_1 != 0 && near(x) && near(y) {
+            Some((x, y))
+        } else {
+            None
+        }
+    }
+
+    /// `unknown_3` and `unknown_4` as the x and y of a point of the
+    /// `width`x`height` sprite it is anchored at, when they are set and
+    /// inside it
+    pub fn hotspot(&self, width: i32, height: i32) -> Option<(i32, i32)> {
+        let (x, y) = (self.unknown_3 as i32, self.unknown_4 as i32);
+        let inside = x >= 0 && y >= 0 && x < width && y < height;
+        if (x, y) != (0, 0) && inside {
+            Some((x, y))
+        } else {
+            None
+        }
+    }
+}
@@ -1,6 +1,24 @@
+#[cfg(feature = "image")]
+use core::convert::TryFrom;
+use core::hash::Hasher;
+#[cfg(feature = "image")]
+use std::path::Path;
+
+use alloc::vec::Vec;
+
+#[cfg(feature = "image")]
+use image::{ImageFormat, RgbaImage};
+use twox_hash::XxHash64;
+
+use crate::error::Error;
+use crate::entity::image_stats::ImageStats;
+use crate::entity::raw_header_extras::RawHeaderExtras;
+use crate::parser::rle::{convert_565_buffer, format_r5g6b5_norm, PixelFormat, COLOR_KEY_565};
 use crate::utility::pixel::Pixel;
+use crate::writer::rle::format_r5g6b5;
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Resource {
     pub file_num: Option<u32>,
     index: u32,
@@ -15,7 +33,11 @@ pub struct Resource {
     pub unknown_3: u32,
     pub unknown_4: u32,
     // pub image: Vec<Pixel>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::utility::serde_pixels"))]
     pub image_raw: Vec<u8>,
+    /// `content_hash`, when it was worked out while parsing
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hash: Option<u64>,
 }
 
 impl Resource {
@@ -34,6 +56,7 @@ impl Resource {
             unknown_3: 0,
             unknown_4: 0,
             image_raw: Vec::new(),
+            hash: None,
         }
     }
 
@@ -44,4 +67,238 @@ impl Resource {
     pub fn index(&self) -> u32 {
         self.index
     }
+
+    /// An xxHash of the size and the pixels of the image, the same for two
+    /// resources drawing the same thing anywhere in any file, for finding
+    /// the duplicates. Only resources decoded into the same `PixelFormat`
+    /// compare. Parsing with `DecodeOptions::hash` works it out beforehand;
+    /// `convert` and `trim` forget it, changes to `image_raw` by hand don't.
+    pub fn content_hash(&self) -> u64 {
+        self.hash.unwrap_or_else(|| self.hash_pixels())
+    }
+
+    /// Works out `content_hash` now, for later
+    pub(crate) fn cache_hash(&mut self) {
+        self.hash = Some(self.hash_pixels());
+    }
+
+    fn hash_pixels(&self) -> u64 {
+        let mut hasher = XxHash64::with_seed(0);
+        hasher.write(&self.width.to_le_bytes());
+        hasher.write(&self.height.to_le_bytes());
+        hasher.write(&self.image_raw);
+        hasher.finish()
+    }
+
+    /// The header fields of unknown meaning
+    pub fn extras(&self) -> RawHeaderExtras {
+        RawHeaderExtras {
+            unknown_1: self.unknown_1,
+            unknown_2: self.unknown_2,
+            unknown_3: self.unknown_3,
+            unknown_4: self.unknown_4,
+        }
+    }
+
+    /// Converts `image_raw` from `from`, the format it was decoded into, to
+    /// `to`. Going to `PixelFormat::R5g6b5` the colors are rounded to the
+    /// nearest 5,6,5 bit ones and pixels with an alpha below 0x80 become
+    /// `COLOR_KEY_565`; coming from it, `COLOR_KEY_565` becomes transparent.
+    pub fn convert(&mut self, from: PixelFormat, to: PixelFormat) -> Result<(), Error> {
+        let pixels = self.width.max(0) as usize * self.height.max(0) as usize;
+        if self.image_raw.len() != pixels * from.bytes_per_pixel() {
+            return Err(Error::UnencodableResource(self.index));
+        }
+        if from == to {
+            return Ok(());
+        }
+        self.hash = None;
+        if (from, to) == (PixelFormat::R5g6b5, PixelFormat::Rgba8) {
+            self.image_raw = convert_565_buffer(&self.image_raw);
+            return Ok(());
+        }
+        let mut image_raw = Vec::with_capacity(pixels * to.bytes_per_pixel());
+        for px in self.image_raw.chunks(from.bytes_per_pixel()) {
+            let rgba = match from {
+                PixelFormat::Rgba8 => [px[0], px[1], px[2], px[3]],
+                PixelFormat::Bgra8 => [px[2], px[1], px[0], px[3]],
+                PixelFormat::R5g6b5 => match u16::from_le_bytes([px[0], px[1]]) {
+                    COLOR_KEY_565 => [0; 4],
+                    color => {
+                        let (r, g, b) = format_r5g6b5_norm(color);
+                        [r, g, b, 0xFF]
+                    }
+                },
+            };
+            match to {
+                PixelFormat::Rgba8 => image_raw.extend_from_slice(&rgba),
+                PixelFormat::Bgra8 => image_raw.extend_from_slice(&[rgba[2], rgba[1], rgba[0], rgba[3]]),
+                PixelFormat::R5g6b5 if rgba[3] < 0x80 => image_raw.extend_from_slice(&COLOR_KEY_565.to_le_bytes()),
+                PixelFormat::R5g6b5 => {
+                    image_raw.extend_from_slice(&format_r5g6b5(rgba[0], rgba[1], rgba[2]).to_le_bytes());
+                }
+            }
+        }
+        self.image_raw = image_raw;
+        Ok(())
+    }
+
+    /// `convert`, into a copy of the image
+    pub fn converted(&self, from: PixelFormat, to: PixelFormat) -> Result<Vec<u8>, Error> {
+        let mut copy = Resource {
+            index: self.index,
+            width: self.width,
+            height: self.height,
+            image_raw: self.image_raw.clone(),
+            ..Resource::new()
+        };
+        copy.convert(from, to)?;
+        Ok(copy.image_raw)
+    }
+
+    /// Crops the fully transparent rows and columns off the edges of the
+    /// image, which is in `format`, and moves `offset_x` and `offset_y` so
+    /// the painted pixels are drawn where they were. An image without any
+    /// ends up 0x0; `len` is left as read.
+    pub fn trim(&mut self, format: PixelFormat) -> Result<(), Error> {
+        let (width, height) = (self.width.max(0) as usize, self.height.max(0) as usize);
+        let bytes_per_pixel = format.bytes_per_pixel();
+        if self.image_raw.len() != width * height * bytes_per_pixel {
+            return Err(Error::UnencodableResource(self.index));
+        }
+        self.hash = None;
+        let painted = |x: usize, y: usize| {
+            let px = &self.image_raw[(y * width + x) * bytes_per_pixel..];
+            match format {
+                PixelFormat::Rgba8 | PixelFormat::Bgra8 => px[3] != 0,
+                PixelFormat::R5g6b5 => u16::from_le_bytes([px[0], px[1]]) != COLOR_KEY_565,
+            }
+        };
+        let (mut left, mut top, mut right, mut bottom) = (width, height, 0, 0);
+        for y in 0..height {
+            for x in 0..width {
+                if painted(x, y) {
+                    left = left.min(x);
+                    top = top.min(y);
+                    right = right.max(x + 1);
+                    bottom = bottom.max(y + 1);
+                }
+            }
+        }
+        if left >= right {
+            self.width = 0;
+            self.height = 0;
+            self.image_raw = Vec::new();
+            return Ok(());
+        }
+        let row = (right - left) * bytes_per_pixel;
+        let mut image_raw = Vec::with_capacity(row * (bottom - top));
+        for y in top..bottom {
+            let start = (y * width + left) * bytes_per_pixel;
+            image_raw.extend_from_slice(&self.image_raw[start..start + row]);
+        }
+        self.offset_x += left as i32;
+        self.offset_y += top as i32;
+        self.width = (right - left) as i32;
+        self.height = (bottom - top) as i32;
+        self.image_raw = image_raw;
+        Ok(())
+    }
+
+    /// Counts the painted pixels of the image, which is in `format`, with
+    /// their average color and the rectangle around them; for telling the
+    /// empty, the mostly one color and the loosely cropped sprites apart.
+    pub fn stats(&self, format: PixelFormat) -> Result<ImageStats, Error> {
+        let (width, height) = (self.width.max(0) as usize, self.height.max(0) as usize);
+        let bytes_per_pixel = format.bytes_per_pixel();
+        if self.image_raw.len() != width * height * bytes_per_pixel {
+            return Err(Error::UnencodableResource(self.index));
+        }
+        let (mut opaque, mut sum) = (0u32, [0u64; 3]);
+        let (mut left, mut top, mut right, mut bottom) = (width, height, 0, 0);
+        for (i, px) in self.image_raw.chunks(bytes_per_pixel).enumerate() {
+            let (r, g, b) = match format {
+                PixelFormat::Rgba8 | PixelFormat::Bgra8 if px[3] == 0 => continue,
+                PixelFormat::Rgba8 => (px[0], px[1], px[2]),
+                PixelFormat::Bgra8 => (px[2], px[1], px[0]),
+                PixelFormat::R5g6b5 => match u16::from_le_bytes([px[0], px[1]]) {
+                    COLOR_KEY_565 => continue,
+                    value => format_r5g6b5_norm(value),
+                },
+            };
+            opaque += 1;
+            sum[0] += r as u64;
+            sum[1] += g as u64;
+            sum[2] += b as u64;
+            let (x, y) = (i % width, i / width);
+            left = left.min(x);
+            top = top.min(y);
+            right = right.max(x + 1);
+            bottom = bottom.max(y + 1);
+        }
+
+        let total = width * height;
+        let mean = |sum: u64| ((sum + opaque as u64 / 2) / opaque as u64) as u8;
+        Ok(ImageStats {
+            opaque_pixels: opaque,
+            opaque_ratio: if total == 0 { 0.0 } else { opaque as f32 / total as f32 },
+            average_color: if opaque == 0 { None } else { Some((mean(sum[0]), mean(sum[1]), mean(sum[2]))) },
+            bounds: if opaque == 0 {
+                None
+            } else {
+                Some((left as i32, top as i32, (right - left) as i32, (bottom - top) as i32))
+            },
+        })
+    }
+
+    /// `trim`, into a copy of the resource
+    pub fn trimmed(&self, format: PixelFormat) -> Result<Resource, Error> {
+        let mut copy = Resource {
+            file_num: self.file_num,
+            index: self.index,
+            offset: self.offset,
+            len: self.len,
+            offset_x: self.offset_x,
+            offset_y: self.offset_y,
+            width: self.width,
+            height: self.height,
+            unknown_1: self.unknown_1,
+            unknown_2: self.unknown_2,
+            unknown_3: self.unknown_3,
+            unknown_4: self.unknown_4,
+            image_raw: self.image_raw.clone(),
+            hash: self.hash,
+        };
+        copy.trim(format)?;
+        Ok(copy)
+    }
+}
+
+#[cfg(feature = "image")]
+impl Resource {
+    /// The image, which is in `format`, as an `image::RgbaImage`
+    pub fn to_rgba_image(&self, format: PixelFormat) -> Result<RgbaImage, Error> {
+        let rgba = self.converted(format, PixelFormat::Rgba8)?;
+        RgbaImage::from_raw(self.width.max(0) as u32, self.height.max(0) as u32, rgba)
+            .ok_or(Error::UnencodableResource(self.index))
+    }
+
+    /// Writes the image, decoded as `PixelFormat::Rgba8` like `parse_rle`
+    /// does, to a PNG file at `path`
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let image = RgbaImage::try_from(self)?;
+        image.save_with_format(path, ImageFormat::Png)?;
+        Ok(())
+    }
+}
+
+/// The image of a resource decoded as `PixelFormat::Rgba8`, like `parse_rle`
+/// does; see `Resource::to_rgba_image` for the other formats
+#[cfg(feature = "image")]
+impl<'a> TryFrom<&'a Resource> for RgbaImage {
+    type Error = Error;
+
+    fn try_from(resource: &'a Resource) -> Result<RgbaImage, Error> {
+        resource.to_rgba_image(PixelFormat::Rgba8)
+    }
 }
@@ -1,9 +1,96 @@
+use core::slice;
+#[cfg(feature = "mmap")]
+use std::path::Path;
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+
 use crate::entity::resource::Resource;
+use crate::error::Error;
+use crate::parser::rle::{LazyResourceFile, PixelFormat, RleVersion};
+#[cfg(feature = "mmap")]
+use crate::parser::rle::parse_rle;
+#[cfg(feature = "mmap")]
+use crate::utility::mmap::map_file;
+use crate::writer::rle::write_rle;
+
+/// What the file has at a resource index
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ResourceSlot {
+    /// A null offset placeholder
+    Empty,
+    /// A decoded resource, by its position in `ResourceFile::resources`
+    Resource(usize),
+    /// A resource that is in the file but wasn't decoded (e.g. a broken size)
+    Undecoded,
+}
+
+/// Something odd the parser ran into while reading the file or decoding a
+/// resource
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RleWarning {
+    /// The header counts `declared` resources, but the offset table runs
+    /// into the end of the file or the first resource after `present`; the
+    /// indices past those are left out.
+    TruncatedOffsetTable { declared: u32, present: u32 },
+    /// `len` bytes from `at` on are after the end of the last resource, and
+    /// were passed over
+    TrailingData { at: u64, len: u64 },
+    /// A `0x02` move by an odd number of bytes; it's rounded toward zero.
+    OddMove { index: u32, bytes: i32 },
+    /// A `0x01` run painting `pixels` pixels outside the image, starting at
+    /// `(x, y)`; those pixels are dropped.
+    PixelsOutOfBounds { index: u32, x: i32, y: i32, pixels: u32 },
+}
+
+/// A rule of `parser::rle::DecodeOptions::strict` a resource breaks
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RleViolation {
+    /// A `0x01` run paints `pixels` pixels outside the image, starting at
+    /// `(x, y)`
+    PixelsOutOfBounds { x: i32, y: i32, pixels: u32 },
+    /// A `0x02` move puts the column at `x`, outside `0..=width`
+    MoveOutOfBounds { x: i32 },
+    /// A `0x03` moves down to line `y`, past the line under the image
+    LineOutOfBounds { y: i32 },
+    /// The pixel runs take `actual` bytes, the end marker included, where
+    /// the header says `declared`
+    LengthMismatch { declared: u32, actual: u32 },
+}
 
+/// A resource `parser::rle::parse_rle_lenient` gave up on
+#[derive(Debug)]
+pub struct ResourceFailure {
+    pub index: u32,
+    /// Where the resource starts in the file
+    pub offset: u32,
+    pub reason: Error,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ResourceFile {
     pub name: String,
     pub file_number: u32,
+    /// The header layout the file was parsed with; files are written as
+    /// `RleVersion::Standard`
+    pub version: RleVersion,
     pub resources: Vec<Resource>,
+    /// One entry per index of the file's offset table, so the original index
+    /// layout can be written back; list files reference resources by index.
+    pub slots: Vec<ResourceSlot>,
+    pub warnings: Vec<RleWarning>,
+    /// The resources that couldn't be decoded, when parsed leniently; their
+    /// slots are `ResourceSlot::Undecoded`. Not serialized, the errors
+    /// being of no use to other tools.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub failures: Vec<ResourceFailure>,
 }
 
 impl ResourceFile {
@@ -11,7 +98,78 @@ impl ResourceFile {
         ResourceFile {
             name: String::new(),
             file_number: 0,
+            version: RleVersion::Standard,
             resources: Vec::new(),
+            slots: Vec::new(),
+            warnings: Vec::new(),
+            failures: Vec::new(),
+        }
+    }
+
+    /// Reads only the header and offset table of an RLE file; the resources
+    /// are decoded as they're asked for, with `LazyResourceFile::get`.
+    pub fn open_lazy<D: AsRef<[u8]>>(file_number: u32, data: D) -> Result<LazyResourceFile<D>, Error> {
+        LazyResourceFile::open(file_number, data, PixelFormat::Rgba8)
+    }
+
+    /// Parses the RLE file at `path` straight from memory it's mapped into,
+    /// rather than reading a copy of it first
+    #[cfg(feature = "mmap")]
+    pub fn load_mmap(file_number: u32, path: &Path) -> Result<ResourceFile, Error> {
+        parse_rle(file_number, &map_file(path)?)
+    }
+
+    /// `open_lazy` of the RLE file at `path` mapped into memory; the pages
+    /// holding a resource are only read once it's decoded
+    #[cfg(feature = "mmap")]
+    pub fn open_lazy_mmap(file_number: u32, path: &Path) -> Result<LazyResourceFile<Mmap>, Error> {
+        ResourceFile::open_lazy(file_number, map_file(path)?)
+    }
+
+    /// The RLE file of the resources as decoded by `parser::rle::parse_rle`,
+    /// with the same index layout
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        write_rle(self, PixelFormat::Rgba8)
+    }
+
+    /// The decoded resource at a resource index
+    pub fn get(&self, index: u32) -> Option<&Resource> {
+        match self.slots.get(index as usize) {
+            Some(&ResourceSlot::Resource(pos)) => self.resources.get(pos),
+            _ => None,
         }
     }
+
+    /// The decoded resources, in index order
+    pub fn iter(&self) -> slice::Iter<'_, Resource> {
+        self.resources.iter()
+    }
+
+    /// The decoded resources by resource index, `None` for every other slot
+    pub fn layout(&self) -> Vec<Option<&Resource>> {
+        self.slots.iter()
+            .map(|slot| match *slot {
+                ResourceSlot::Resource(pos) => self.resources.get(pos),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl IntoIterator for ResourceFile {
+    type Item = Resource;
+    type IntoIter = vec::IntoIter<Resource>;
+
+    fn into_iter(self) -> vec::IntoIter<Resource> {
+        self.resources.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ResourceFile {
+    type Item = &'a Resource;
+    type IntoIter = slice::Iter<'a, Resource>;
+
+    fn into_iter(self) -> slice::Iter<'a, Resource> {
+        self.resources.iter()
+    }
 }
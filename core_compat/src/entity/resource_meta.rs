@@ -0,0 +1,32 @@
+use crate::entity::raw_header_extras::RawHeaderExtras;
+
+/// The header of a resource in a RLE file, without its pixels; see
+/// `parser::rle::scan_rle`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ResourceMeta {
+    /// The index of the resource in its file
+    pub index: u32,
+    /// Where the resource starts in its file
+    pub offset: u32,
+    pub len: u32,
+    pub offset_x: i32,
+    pub offset_y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub unknown_1: u32,
+    pub unknown_2: u32,
+    pub unknown_3: u32,
+    pub unknown_4: u32,
+}
+
+impl ResourceMeta {
+    /// The header fields of unknown meaning
+    pub fn extras(&self) -> RawHeaderExtras {
+        RawHeaderExtras {
+            unknown_1: self.unknown_1,
+            unknown_2: self.unknown_2,
+            unknown_3: self.unknown_3,
+            unknown_4: self.unknown_4,
+        }
+    }
+}
@@ -1,3 +1,6 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
 use crate::entity::rmd_type::RmdType;
 use crate::entity::rmd_animation::RmdAnimation;
 use crate::entity::rmd_entry::RmdEntry;
@@ -45,6 +48,10 @@ impl Rmd {
         &self.animations
     }
 
+    pub fn animation_mut(&mut self, index: usize) -> Option<&mut RmdAnimation> {
+        self.animations.get_mut(index)
+    }
+
     pub fn set_animation_parts(&mut self, parts: i32) {
         self.animation_parts = parts;
     }
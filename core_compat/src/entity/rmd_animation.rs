@@ -1,8 +1,41 @@
+use alloc::vec::Vec;
+
+/// Something happening on a frame of an animation, for the client to play a
+/// sound or an effect and for the server to time the hits by. The RMD files
+/// don't have them; they come from the animation TOML of `data_converter`.
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy)]
+pub enum Marker {
+    /// A foot touches the ground
+    Step,
+    /// A weapon starts to swing
+    Swing,
+    /// The blow lands
+    Impact,
+}
+
+impl Marker {
+    pub const ALL: [Marker; 3] = [Marker::Step, Marker::Swing, Marker::Impact];
+
+    /// How it's written in the animation TOML
+    pub fn name(self) -> &'static str {
+        match self {
+            Marker::Step => "step",
+            Marker::Swing => "swing",
+            Marker::Impact => "impact",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Marker> {
+        Marker::ALL.iter().cloned().find(|marker| marker.name() == name)
+    }
+}
 
 #[derive(Debug)]
 pub struct RmdAnimation {
     frame_count: i32,
-    frames: Vec<i16> // Rmd row pointer
+    frames: Vec<i16>, // Rmd row pointer
+    /// The frames, as indices into `frames`, and what happens on them
+    markers: Vec<(usize, Marker)>,
 }
 
 impl RmdAnimation {
@@ -11,6 +44,7 @@ impl RmdAnimation {
         RmdAnimation {
             frame_count,
             frames: Vec::new(),
+            markers: Vec::new(),
         }
     }
 
@@ -25,4 +59,51 @@ impl RmdAnimation {
     pub fn frames(&self) -> &[i16] {
         &self.frames
     }
+
+    /// Marks the `frame`th frame of the animation
+    pub fn add_marker(&mut self, frame: usize, marker: Marker) {
+        self.markers.push((frame, marker));
+    }
+
+    pub fn clear_markers(&mut self) {
+        self.markers.clear();
+    }
+
+    pub fn markers(&self) -> &[(usize, Marker)] {
+        &self.markers
+    }
+
+    /// What happens on the `frame`th frame
+    pub fn markers_at<'a>(&'a self, frame: usize) -> impl Iterator<Item = Marker> + 'a {
+        self.markers.iter().filter(move |&&(at, _)| at == frame).map(|&(_, marker)| marker)
+    }
+
+    /// When the first frame with `marker` starts, in milliseconds from the
+    /// start of the animation, every frame lasting `delay`
+    pub fn marker_time(&self, marker: Marker, delay: u32) -> Option<u32> {
+        self.markers.iter()
+            .filter(|&&(_, m)| m == marker)
+            .map(|&(frame, _)| frame as u32 * delay)
+            .min()
+    }
+
+    /// The markers of the frames starting from `from` and before `to`,
+    /// milliseconds into the animation, in order; a client drawing it calls
+    /// this with the times of the last and this redraw, so no marker is
+    /// missed or played twice however many frames go by in between. The
+    /// animation loops, so `to` may be any number of rounds later.
+    pub fn markers_between(&self, from: u64, to: u64, delay: u32) -> Vec<Marker> {
+        let (len, delay) = (self.frames.len() as u64, delay.max(1) as u64);
+        let mut passed = Vec::new();
+        if len == 0 {
+            return passed;
+        }
+        // the first frame starting at or after `from`, counted over the loops
+        let mut frame = from.div_ceil(delay);
+        while frame * delay < to {
+            passed.extend(self.markers_at((frame % len) as usize));
+            frame += 1;
+        }
+        passed
+    }
 }
@@ -1,3 +1,5 @@
+use alloc::vec::Vec;
+
 use crate::entity::rmd_animation::RmdAnimation;
 use crate::entity::rmd_image::RmdImage;
 
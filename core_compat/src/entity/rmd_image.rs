@@ -1,3 +1,5 @@
+use alloc::vec::Vec;
+
 use geometry::rectangle::Rectangle;
 use geometry::size::Size;
 use geometry::point::Point;
@@ -1,3 +1,5 @@
+use alloc::vec::Vec;
+
 use crate::utility::pixel::Pixel;
 use crate::entity::entry::Entry;
 use crate::entity::sprite_type::SpriteType;
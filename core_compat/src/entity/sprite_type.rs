@@ -1,5 +1,5 @@
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
 pub enum SpriteType {
     Bullet,
     Icon,
@@ -1,25 +1,82 @@
+use core::str::Utf8Error;
+#[cfg(feature = "std")]
 use std::io;
-use std::str::Utf8Error;
-use std::string::FromUtf16Error;
-use std::string::FromUtf8Error;
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+
+use alloc::string::FromUtf16Error;
+use alloc::string::FromUtf8Error;
+use alloc::string::String;
+
+use crate::entity::entry::Entry;
+use crate::entity::resource_file::RleViolation;
 
 #[derive(Debug)]
 pub enum Error {
+    /// The buffer the resource at this index of the file is decoded into
+    /// isn't the `needed` bytes long; see `LazyResourceFile::decode_into`
+    BufferTooSmall { file: u32, index: u32, needed: usize },
     FromUtf16(FromUtf16Error),
     FromUtf8(FromUtf8Error),
+    /// An image couldn't be encoded or written; see `Resource::save_png`
+    #[cfg(feature = "image")]
+    Image(image::ImageError),
+    InvalidMapTileAt(u64),
+    /// The resource at this index of the file breaks a rule of
+    /// `DecodeOptions::strict`; `at` is where the run breaking it starts, or
+    /// the resource for a `LengthMismatch`.
+    InvalidResource { file: u32, index: u32, at: u64, violation: RleViolation },
+    #[cfg(feature = "std")]
     Io(io::Error),
+    /// Something writes to the database, or reads it when writing; `holder`
+    /// is the writer's process id, empty for readers. See `utility::lock`.
+    #[cfg(feature = "std")]
+    Locked { lock: PathBuf, holder: String },
     MissingMapIdentifier,
     MissingRleIdentifier,
+    /// The file has no resource at this index: it's past the end of the
+    /// offset table, a null offset or a resource with a broken size
+    MissingResource { file: u32, index: u32 },
+    /// The resource at this index of the file is larger than
+    /// `DecodeOptions::max_dimensions` allow
+    OversizedResource { file: u32, index: u32, width: i32, height: i32 },
+    /// A WebAssembly plugin couldn't be loaded or failed; see `utility::wasm`
+    Plugin(String),
+    /// The name of the list item with this id is longer than 255 bytes
+    UnencodableListItem(u32),
+    UnencodableMapEntry(Entry),
+    /// The image of the resource at this index doesn't match its size
+    UnencodableResource(u32),
+    /// A read of `needed` bytes at `at` runs past the end of the data; see
+    /// `utility::bin_reader`
+    UnexpectedEnd { at: u64, needed: usize },
+    UnexpectedEndOfList,
+    UnexpectedEndOfMap,
+    /// The resource at this index of the file runs past the end of the file;
+    /// `at` is where the header or run that's cut short starts.
+    UnexpectedEndOfResource { file: u32, index: u32, at: u64 },
+    UnknownListVersion(String),
     UnknownOffsetTypeAt(u64),
+    /// A pixel run of the resource at this index of the file has a type
+    /// other than the four known; `at` is where the run starts.
+    UnknownResourceRunAt { file: u32, index: u32, at: u64 },
     Utf8(Utf8Error),
 }
 
+#[cfg(feature = "std")]
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Error {
         Error::Io(err)
     }
 }
 
+#[cfg(feature = "image")]
+impl From<image::ImageError> for Error {
+    fn from(err: image::ImageError) -> Error {
+        Error::Image(err)
+    }
+}
+
 impl From<Utf8Error> for Error {
     fn from(err: Utf8Error) -> Error {
         Error::Utf8(err)
@@ -0,0 +1,262 @@
+//! Builders for small synthetic game files.
+//!
+//! The client data can't be redistributed, so the tests build the files they
+//! need with these instead. The builders only write what the parsers read;
+//! every field they don't know about is zeroed.
+
+use byteorder::WriteBytesExt;
+use byteorder::LittleEndian as LE;
+
+use crate::entity::entry::Entry;
+use crate::writer::rle::write_raw_rle;
+use crate::writer::rmm::encode_tile_entries;
+
+fn push_string(data: &mut Vec<u8>, string: &str) {
+    data.push(string.len() as u8);
+    data.extend_from_slice(string.as_bytes());
+}
+
+/// A RLE resource file; see `parser::rle`.
+#[derive(Default)]
+pub struct RleFixture {
+    resources: Vec<Option<Vec<u8>>>,
+}
+
+impl RleFixture {
+    pub fn new() -> RleFixture {
+        RleFixture { resources: Vec::new() }
+    }
+
+    pub fn resource(mut self, resource: ResourceFixture) -> RleFixture {
+        self.resources.push(Some(resource.encode()));
+        self
+    }
+
+    /// Adds a null offset placeholder
+    pub fn null(mut self) -> RleFixture {
+        self.resources.push(None);
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let resources: Vec<Option<&[u8]>> = self.resources.iter()
+            .map(|r| r.as_ref().map(|r| r.as_slice()))
+            .collect();
+        write_raw_rle(&resources)
+    }
+}
+
+/// A single RLE resource, built from its pixel runs.
+pub struct ResourceFixture {
+    offset_x: i32,
+    offset_y: i32,
+    width: i32,
+    height: i32,
+    extras: [u32; 4],
+    runs: Vec<u8>,
+}
+
+impl ResourceFixture {
+    pub fn new(width: i32, height: i32) -> ResourceFixture {
+        ResourceFixture { offset_x: 0, offset_y: 0, width, height, extras: [0; 4], runs: Vec::new() }
+    }
+
+    pub fn offset(mut self, x: i32, y: i32) -> ResourceFixture {
+        self.offset_x = x;
+        self.offset_y = y;
+        self
+    }
+
+    /// Sets the four header fields of unknown meaning, zero otherwise
+    pub fn extras(mut self, extras: [u32; 4]) -> ResourceFixture {
+        self.extras = extras;
+        self
+    }
+
+    /// Paints r5g6b5 pixels at the current position (`0x01`)
+    pub fn pixels(mut self, pixels: &[u16]) -> ResourceFixture {
+        self.runs.push(0x01);
+        self.runs.write_u32::<LE>(pixels.len() as u32).unwrap();
+        for &pixel in pixels {
+            self.runs.write_u16::<LE>(pixel).unwrap();
+        }
+        self
+    }
+
+    /// Moves the current position by a number of pixels (`0x02`)
+    pub fn skip(mut self, pixels: i32) -> ResourceFixture {
+        self.runs.push(0x02);
+        self.runs.write_i32::<LE>(pixels * 2).unwrap();
+        self
+    }
+
+    /// Moves to the next line (`0x03`); the column is kept.
+    pub fn next_line(mut self) -> ResourceFixture {
+        self.runs.push(0x03);
+        self
+    }
+
+    /// The resource header followed by the pixel runs and the end marker
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.write_u32::<LE>(self.runs.len() as u32 + 1).unwrap();
+        data.write_i32::<LE>(self.offset_x).unwrap();
+        data.write_i32::<LE>(self.offset_y).unwrap();
+        data.write_i32::<LE>(self.width).unwrap();
+        data.write_i32::<LE>(self.height).unwrap();
+        for &extra in self.extras.iter() {
+            data.write_u32::<LE>(extra).unwrap();
+        }
+        data.extend_from_slice(&self.runs);
+        data.push(0x00);
+        data
+    }
+}
+
+/// A LST list file; see `parser::lst`.
+pub struct LstFixture {
+    version: &'static str,
+    items: Vec<(String, u32, Entry)>,
+}
+
+impl LstFixture {
+    /// `version` is either "1.0" or "1.2"
+    pub fn new(version: &'static str) -> LstFixture {
+        LstFixture { version, items: Vec::new() }
+    }
+
+    pub fn item(mut self, name: &str, id: u32, entry: Entry) -> LstFixture {
+        self.items.push((name.into(), id, entry));
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        push_string(&mut data, "RedMoon Lst File");
+        push_string(&mut data, self.version);
+        let next_free_id = self.items.iter().map(|i| i.1 + 1).max().unwrap_or(0);
+        data.write_u32::<LE>(next_free_id).unwrap();
+        data.write_u32::<LE>(self.items.len() as u32).unwrap();
+        for &(ref name, id, entry) in self.items.iter() {
+            push_string(&mut data, name);
+            data.write_u32::<LE>(id).unwrap();
+            data.write_u32::<LE>(entry.file()).unwrap();
+            data.write_u32::<LE>(entry.index()).unwrap();
+            if self.version == "1.2" {
+                data.write_u32::<LE>(0).unwrap();
+            }
+        }
+        data
+    }
+}
+
+/// A RMD data file; see `parser::rmd`. Every image only carries its list ids.
+#[derive(Default)]
+pub struct RmdFixture {
+    entries: Vec<Vec<Vec<i32>>>,
+    animations: Vec<Vec<i16>>,
+}
+
+impl RmdFixture {
+    pub fn new() -> RmdFixture {
+        RmdFixture { entries: Vec::new(), animations: Vec::new() }
+    }
+
+    /// Adds an entry with one image per slice of list ids
+    pub fn entry(mut self, images: &[&[i32]]) -> RmdFixture {
+        self.entries.push(images.iter().map(|ids| ids.to_vec()).collect());
+        self
+    }
+
+    /// Adds an animation with the given entry indices as frames
+    pub fn animation(mut self, frames: &[i16]) -> RmdFixture {
+        self.animations.push(frames.to_vec());
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        push_string(&mut data, "");
+        data.extend_from_slice(&[0; 12]);                  // file number, padding
+        push_string(&mut data, "");
+        data.write_i32::<LE>(0).unwrap();                  // animation parts
+        data.write_i32::<LE>(self.animations.len() as i32).unwrap();
+        push_string(&mut data, "");
+        data.write_i32::<LE>(self.entries.len() as i32).unwrap();
+        for images in self.entries.iter() {
+            data.write_i32::<LE>(images.len() as i32).unwrap();
+            for ids in images.iter() {
+                data.extend_from_slice(&[0; 10 * 4]);      // source, dest, z, draw type
+                data.write_i32::<LE>(ids.len() as i32).unwrap();
+                for &id in ids.iter() {
+                    data.write_i32::<LE>(id).unwrap();
+                }
+            }
+        }
+        data.write_i32::<LE>(self.animations.len() as i32).unwrap();
+        for frames in self.animations.iter() {
+            data.write_i32::<LE>(frames.len() as i32).unwrap();
+            for &frame in frames.iter() {
+                data.write_i16::<LE>(frame).unwrap();
+            }
+        }
+        data
+    }
+}
+
+/// A RMM map file; see `parser::rmm`. All tiles start out empty.
+pub struct RmmFixture {
+    number: u32,
+    size_x: u32,
+    size_y: u32,
+    events: Vec<(u16, [u32; 4])>,
+    tiles: Vec<[u8; 8]>,
+}
+
+impl RmmFixture {
+    pub fn new(number: u32, size_x: u32, size_y: u32) -> RmmFixture {
+        RmmFixture {
+            number,
+            size_x,
+            size_y,
+            events: Vec::new(),
+            tiles: vec![[0; 8]; (size_x * size_y) as usize],
+        }
+    }
+
+    /// Adds an event rectangle (left, top, right, bottom)
+    pub fn event(mut self, number: u16, rect: [u32; 4]) -> RmmFixture {
+        self.events.push((number, rect));
+        self
+    }
+
+    /// Sets a tile; an odd object index needs a collision value that isn't a
+    /// multiple of 24.
+    pub fn tile(mut self, x: u32, y: u32, obj: Entry, tle: Entry, warp: u8, collision: u8) -> RmmFixture {
+        let tile = &mut self.tiles[(y * self.size_x + x) as usize];
+        tile[4] = warp;
+        tile[6] = collision;
+        encode_tile_entries(tile, obj, tle).unwrap();
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        push_string(&mut data, "RedMoon MapData 1.0");
+        data.write_u32::<LE>(self.size_x).unwrap();
+        data.write_u32::<LE>(self.size_y).unwrap();
+        data.push(0);                                      // id list
+        data.write_u32::<LE>(self.number).unwrap();
+        data.write_u32::<LE>(self.events.len() as u32).unwrap();
+        for &(number, rect) in self.events.iter() {
+            data.write_u16::<LE>(number).unwrap();
+            for &val in rect.iter() {
+                data.write_u32::<LE>(val).unwrap();
+            }
+        }
+        for tile in self.tiles.iter() {
+            data.extend_from_slice(tile);
+        }
+        data
+    }
+}
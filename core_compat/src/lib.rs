@@ -1,13 +1,36 @@
 #![allow(dead_code, unused_variables, unused_imports)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
+// the parser core only needs `core` and `alloc`, see the `std` feature;
+// `no_std` brings in `core` by itself, with std it has to be named
+#[cfg(feature = "std")]
+extern crate core;
+#[cfg_attr(not(feature = "std"), macro_use)]
+extern crate alloc;
 // local
 extern crate geometry;
 extern crate cp949;
 // external
 extern crate byteorder;
+extern crate twox_hash;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+#[cfg(feature = "wasm")]
+extern crate wasmtime;
+#[cfg(feature = "image")]
+extern crate image;
+#[cfg(feature = "serde")]
+extern crate serde;
 
 pub mod error;
 pub mod utility;
 pub mod parser;
 pub mod entity;
+#[cfg(feature = "std")]
+pub mod loader;
+pub mod writer;
+#[cfg(feature = "std")]
+pub mod fixture;
 
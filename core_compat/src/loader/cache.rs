@@ -0,0 +1,195 @@
+//! Decoded resources kept for reuse, so a viewer going back and forth over
+//! the same sprites doesn't decode their RLE files again and again. The
+//! cache holds up to its capacity of resources and drops the least recently
+//! used one to make room; it's shared between threads as it is, every
+//! resource behind an `Arc`.
+//!
+//! The decoding is left to whoever asks, the cache only knowing the
+//! resources by their sprite type, file number and index:
+//!
+//! ```ignore
+//! let lazy = LazyResourceFile::open(42, data, PixelFormat::Rgba8)?;
+//! let sprite = cache.get_or_load((SpriteType::Character, 42, 7), || lazy.get(7))?;
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::entity::resource::Resource;
+use crate::entity::sprite_type::SpriteType;
+use crate::error::Error;
+
+/// The sprite type, the file number and the index of a resource in its file
+pub type AssetKey = (SpriteType, u32, u32);
+
+pub struct AssetCache {
+    capacity: usize,
+    inner: Mutex<Lru>,
+}
+
+struct Lru {
+    /// The resources and when they were last used
+    entries: HashMap<AssetKey, (Arc<Resource>, u64)>,
+    /// The keys by when they were last used, the oldest first
+    order: BTreeMap<u64, AssetKey>,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl Lru {
+    fn touch(&mut self, key: AssetKey) -> Option<Arc<Resource>> {
+        self.clock += 1;
+        let clock = self.clock;
+        let (resource, used) = self.entries.get_mut(&key)?;
+        self.order.remove(&*used);
+        self.order.insert(clock, key);
+        *used = clock;
+        Some(resource.clone())
+    }
+}
+
+impl AssetCache {
+    /// A cache of up to `capacity` resources; 0 keeps nothing
+    pub fn new(capacity: usize) -> AssetCache {
+        let lru = Lru { entries: HashMap::new(), order: BTreeMap::new(), clock: 0, hits: 0, misses: 0 };
+        AssetCache { capacity, inner: Mutex::new(lru) }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// How many resources it holds
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// How many times a resource was asked for and there, and not
+    pub fn stats(&self) -> (u64, u64) {
+        let lru = self.lock();
+        (lru.hits, lru.misses)
+    }
+
+    /// The resource at `key`, if it's held; it's then the most recently used
+    pub fn get(&self, key: AssetKey) -> Option<Arc<Resource>> {
+        let mut lru = self.lock();
+        let resource = lru.touch(key);
+        match resource {
+            Some(_) => lru.hits += 1,
+            None => lru.misses += 1,
+        }
+        resource
+    }
+
+    /// Holds `resource` at `key`, dropping the least recently used ones over
+    /// the capacity; if another thread got there first its resource is kept
+    /// and returned instead, so all of them share one
+    pub fn insert(&self, key: AssetKey, resource: Resource) -> Arc<Resource> {
+        let mut lru = self.lock();
+        if let Some(held) = lru.touch(key) {
+            return held;
+        }
+        let resource = Arc::new(resource);
+        if self.capacity == 0 {
+            return resource;
+        }
+        while lru.entries.len() >= self.capacity {
+            let oldest = match lru.order.pop_first() {
+                Some((_, oldest)) => oldest,
+                None => break,
+            };
+            lru.entries.remove(&oldest);
+        }
+        lru.clock += 1;
+        let clock = lru.clock;
+        lru.entries.insert(key, (resource.clone(), clock));
+        lru.order.insert(clock, key);
+        resource
+    }
+
+    /// The resource at `key`, decoded by `load` if it isn't held yet; `None`
+    /// when `load` finds nothing there, which isn't kept. The lock isn't
+    /// held while decoding, so the threads decode different resources at
+    /// the same time.
+    pub fn get_or_load<F>(&self, key: AssetKey, load: F) -> Result<Option<Arc<Resource>>, Error>
+        where F: FnOnce() -> Result<Option<Resource>, Error>
+    {
+        if let Some(resource) = self.get(key) {
+            return Ok(Some(resource));
+        }
+        Ok(load()?.map(|resource| self.insert(key, resource)))
+    }
+
+    /// Drops every resource; the ones still in use live on in their `Arc`s
+    pub fn clear(&self) {
+        let mut lru = self.lock();
+        lru.entries.clear();
+        lru.order.clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Lru> {
+        // a thread panicking while holding the lock can't have left it half
+        // updated in a way that matters to a cache
+        self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    fn resource(width: i32) -> Resource {
+        let mut resource = Resource::new();
+        resource.width = width;
+        resource
+    }
+
+    #[test]
+    fn test_asset_cache() {
+        let cache = AssetCache::new(2);
+        let key = |index| (SpriteType::Character, 42, index);
+        let first = cache.insert(key(0), resource(1));
+        cache.insert(key(1), resource(2));
+        // using the first makes the second the least recently used one
+        assert!(Arc::ptr_eq(&cache.get(key(0)).unwrap(), &first));
+        cache.insert(key(2), resource(3));
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(key(1)).is_none());
+        assert_eq!(cache.get(key(2)).unwrap().width, 3);
+        // the type is part of the key
+        assert!(cache.get((SpriteType::Object, 42, 0)).is_none());
+        assert_eq!(cache.stats(), (2, 2));
+
+        // what's held isn't loaded again, and nothing found isn't held
+        let loaded = cache.get_or_load(key(0), || panic!("loaded again")).unwrap().unwrap();
+        assert!(Arc::ptr_eq(&loaded, &first));
+        assert!(cache.get_or_load(key(9), || Ok(None)).unwrap().is_none());
+        assert!(cache.get_or_load(key(9), || Err(Error::UnexpectedEnd { at: 0, needed: 1 })).is_err());
+        assert_eq!(cache.len(), 2);
+
+        // the threads share the resources
+        let cache = Arc::new(AssetCache::new(8));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let cache = cache.clone();
+                thread::spawn(move || (0..8).map(|index| cache.get_or_load(key(index), || Ok(Some(resource(index as i32)))).unwrap().unwrap())
+                    .collect::<Vec<_>>())
+            })
+            .collect();
+        let loaded: Vec<Vec<Arc<Resource>>> = threads.into_iter().map(|thread| thread.join().unwrap()).collect();
+        for index in 0..8 {
+            let held = cache.get(key(index as u32)).unwrap();
+            assert!(loaded.iter().all(|resources| Arc::ptr_eq(&resources[index], &held)));
+        }
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!(AssetCache::new(0).insert(key(0), resource(1)).width, 1);
+    }
+}
@@ -0,0 +1,127 @@
+//! The character sprites: the `Chr` folder has a folder of RLE files per
+//! character set (`C00`, `C01`, .., `Etc`), where the flat folders of the
+//! other sprites have the files right in them.
+//!
+//! The file number is the last five digits of the name, so `c0000042.rle`
+//! and `c0100042.rle` are both file 42, of their own set. Files without a
+//! number or not ending in `.rle` are left alone.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::entity::resource_file::ResourceFile;
+use crate::error::Error;
+use crate::parser::rle::{parse_rle_lenient, PixelFormat};
+
+/// The RLE files of a folder of the tree
+#[derive(Debug)]
+pub struct CharacterSet {
+    /// The folder, relative to the root and with `/` between the names;
+    /// empty for the root itself
+    pub name: String,
+    /// By file number; of two files with the same number the first one in
+    /// name order
+    pub files: BTreeMap<u32, ResourceFile>,
+    /// The files that couldn't be read or parsed at all; the resources that
+    /// couldn't be decoded are in the `failures` of their file.
+    pub failures: Vec<(PathBuf, Error)>,
+}
+
+/// Every folder under `root`, `root` included, holding RLE files, as a set
+/// in name order; the resources are decoded leniently as `Rgba8`.
+pub fn load_all(root: &Path) -> Result<Vec<CharacterSet>, Error> {
+    let mut sets = Vec::new();
+    load_folder(root, String::new(), &mut sets)?;
+    Ok(sets)
+}
+
+fn load_folder(folder: &Path, name: String, sets: &mut Vec<CharacterSet>) -> Result<(), Error> {
+    // `read_dir` has no defined order
+    let mut paths: Vec<PathBuf> = fs::read_dir(folder)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+
+    let mut set = CharacterSet { name: name.clone(), files: BTreeMap::new(), failures: Vec::new() };
+    let mut folders = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            folders.push(path);
+            continue;
+        }
+        let number = match file_number(&path) {
+            Some(number) if !set.files.contains_key(&number) => number,
+            _ => continue,
+        };
+        match fs::read(&path).map_err(Error::from).and_then(|data| parse_rle_lenient(number, &data, PixelFormat::Rgba8)) {
+            Ok(file) => { set.files.insert(number, file); },
+            Err(e) => set.failures.push((path, e)),
+        }
+    }
+    if !set.files.is_empty() || !set.failures.is_empty() {
+        sets.push(set);
+    }
+
+    for path in folders {
+        let folder_name = path.file_name().map_or_else(String::new, |n| n.to_string_lossy().into_owned());
+        let name = if name.is_empty() { folder_name } else { format!("{}/{}", name, folder_name) };
+        load_folder(&path, name, sets)?;
+    }
+    Ok(())
+}
+
+/// The number of an RLE file named like `c0000042.rle`: its last five
+/// digits
+pub fn file_number(path: &Path) -> Option<u32> {
+    let is_rle = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("rle"));
+    let digits: String = path.file_stem()?.to_str()?.chars().filter(|c| c.is_ascii_digit()).collect();
+    if !is_rle || digits.is_empty() {
+        return None;
+    }
+    digits[digits.len().saturating_sub(5)..].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fixture::{ResourceFixture, RleFixture};
+
+    #[test]
+    fn test_file_number() {
+        assert_eq!(file_number(Path::new("C00/c0000042.rle")), Some(42));
+        assert_eq!(file_number(Path::new("C01/c0100042.RLE")), Some(42));
+        assert_eq!(file_number(Path::new("obj00012.rle")), Some(12));
+        assert_eq!(file_number(Path::new("c00.lst")), None);
+        assert_eq!(file_number(Path::new("readme.rle")), None);
+    }
+
+    #[test]
+    fn test_load_all() {
+        let root = std::env::temp_dir().join(format!("novluno_chr_{}", std::process::id()));
+        let rle = |color| RleFixture::new().resource(ResourceFixture::new(1, 1).pixels(&[color])).build();
+        fs::create_dir_all(root.join("C00")).unwrap();
+        fs::create_dir_all(root.join("C01").join("Old")).unwrap();
+        fs::create_dir_all(root.join("Empty")).unwrap();
+        fs::write(root.join("C00").join("c0000042.rle"), rle(0xF800)).unwrap();
+        fs::write(root.join("C00").join("c0000007.rle"), rle(0x07E0)).unwrap();
+        fs::write(root.join("C01").join("c0100042.rle"), rle(0x001F)).unwrap();
+        fs::write(root.join("C01").join("c0100043.rle"), b"not a sprite").unwrap();
+        fs::write(root.join("C01").join("Old").join("c0100001.rle"), rle(0xFFFF)).unwrap();
+        fs::write(root.join("c00.lst"), b"a list").unwrap();
+
+        let sets = load_all(&root).unwrap();
+        let names: Vec<&str> = sets.iter().map(|set| set.name.as_str()).collect();
+        assert_eq!(names, vec!["C00", "C01", "C01/Old"]);
+        assert_eq!(sets[0].files.keys().cloned().collect::<Vec<_>>(), vec![7, 42]);
+        assert_eq!(sets[0].files[&42].resources[0].file_num, Some(42));
+        assert_eq!(sets[0].files[&42].resources[0].image_raw, vec![0xFF, 0, 0, 0xFF]);
+        assert_eq!(sets[1].files[&42].resources[0].image_raw, vec![0, 0, 0xFF, 0xFF]);
+        assert_eq!(sets[1].failures.len(), 1);
+        assert_eq!(sets[1].failures[0].0, root.join("C01").join("c0100043.rle"));
+        assert_eq!(sets[2].files.keys().cloned().collect::<Vec<_>>(), vec![1]);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,5 @@
+//! Loads folders of data files, for what needs more than one file at once.
+//! The parsers only ever see bytes; finding and reading the files is here.
+
+pub mod cache;
+pub mod chr;
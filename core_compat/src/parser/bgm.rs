@@ -0,0 +1,73 @@
+//! Background music files
+//!
+//! The format of the client's music files isn't documented, so rather than
+//! assume one the parser recognises the common containers (waves, MIDI, Ogg
+//! Vorbis and MP3) by their magic, whatever the file's extension.
+
+use byteorder::BigEndian as BE;
+use byteorder::ByteOrder;
+
+use crate::parser::snd::parse_wave;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MusicFormat {
+    Midi,
+    Mp3,
+    Ogg,
+    Wave,
+}
+
+impl MusicFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            MusicFormat::Midi => "midi",
+            MusicFormat::Mp3 => "mp3",
+            MusicFormat::Ogg => "ogg",
+            MusicFormat::Wave => "wave",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Music {
+    pub format: MusicFormat,
+    /// Only known for waves
+    pub duration_ms: Option<u64>,
+    /// Only known for MIDI
+    pub tracks: Option<u16>,
+}
+
+/// Identifies a music file; `None` if it isn't in a known format.
+pub fn parse_bgm(data: &[u8]) -> Option<Music> {
+    let music = |format, duration_ms, tracks| Some(Music { format, duration_ms, tracks });
+    match data.get(0..4)? {
+        b"RIFF" => {
+            let wave = parse_wave(data)?;
+            music(MusicFormat::Wave, Some(wave.duration_ms()), None)
+        }
+        b"MThd" => music(MusicFormat::Midi, None, Some(BE::read_u16(data.get(10..12)?))),
+        b"OggS" => music(MusicFormat::Ogg, None, None),
+        // either a ID3 tag or the sync bits of the first frame
+        magic if &magic[0..3] == b"ID3" || (magic[0] == 0xFF && magic[1] & 0xE0 == 0xE0) =>
+            music(MusicFormat::Mp3, None, None),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bgm() {
+        let midi = b"MThd\x00\x00\x00\x06\x00\x01\x00\x03\x01\xe0";
+        assert_eq!(parse_bgm(midi), Some(Music { format: MusicFormat::Midi, duration_ms: None, tracks: Some(3) }));
+        assert_eq!(parse_bgm(b"OggS\x00\x02").unwrap().format, MusicFormat::Ogg);
+        assert_eq!(parse_bgm(b"ID3\x03\x00").unwrap().format, MusicFormat::Mp3);
+        assert_eq!(parse_bgm(b"\xff\xfb\x90\x64").unwrap().format, MusicFormat::Mp3);
+        // a truncated wave or header
+        assert_eq!(parse_bgm(b"RIFF\x24\x00\x00\x00WAVE"), None);
+        assert_eq!(parse_bgm(b"MThd\x00\x00"), None);
+        assert_eq!(parse_bgm(b"RedMoon"), None);
+    }
+}
@@ -1,76 +1,49 @@
-use std::str::from_utf8;
-use std::io::Cursor;
-use std::io::Seek;
-use std::io::SeekFrom;
-
-use byteorder::ReadBytesExt;
-use byteorder::LittleEndian as LE;
+use core::str::from_utf8;
 
 use crate::error::Error;
 use crate::entity::entry::Entry;
 use crate::entity::list::List;
 use crate::entity::list_item::ListItem;
+use crate::utility::bin_reader::BinReader;
 
 pub fn parse_lst(data: &[u8], use_v2: bool) -> Result<List, Error> {
-    let mut cursor = Cursor::new(data);
+    let mut reader = BinReader::new(data);
     // filetype len prefixed string:
     //  - needs to equal "RedMoon Lst File"
-    let string_length = cursor.read_u8()?;
-    let mut string = Vec::<u8>::new();
-    for _ in 0..string_length {
-        let chr = cursor.read_u8()?;
-        string.push(chr);
-    }
     {
-        let file_type: &str = from_utf8(&string)?;
+        let file_type: &str = from_utf8(reader.read_prefixed()?)?;
         // println!("{:?}", &file_type);
     }
     // file version length prefixed string
-    let version: &str;
-    {
-        let version_length = cursor.read_u8()?;
-        string.clear();
-        for _ in 0..version_length {
-            let chr = cursor.read_u8()?;
-            string.push(chr);
-        }
-        version = from_utf8(&string)?;
-        // println!("{:?}", &version);
-    }
+    let version: &str = from_utf8(reader.read_prefixed()?)?;
+    // println!("{:?}", &version);
 
     if use_v2 {
-        load_1_2(&mut cursor)
+        load_1_2(&mut reader)
     } else {
         match version {
-            "1.0" => load_1_0(&mut cursor),
-            "1.2" => load_1_2(&mut cursor),
-            _ => panic!("Unknown version type: {:?}", version),
+            "1.0" => load_1_0(&mut reader),
+            "1.2" => load_1_2(&mut reader),
+            _ => Err(Error::UnknownListVersion(version.into())),
         }
     }
 }
 
 /// The 1.0 format is used in most of the list files
-fn load_1_0(cursor: &mut Cursor<&[u8]>) -> Result<List, Error> {
+fn load_1_0(reader: &mut BinReader) -> Result<List, Error> {
     let mut list = List::new();
-    let mut string = Vec::<u8>::new();
 
     // Unknown u32
-    let next_free_id = cursor.read_u32::<LE>()?;
+    let next_free_id = reader.read_u32()?;
     // list Entry counts
-    let entry_count = cursor.read_u32::<LE>()?;
+    let entry_count = reader.read_u32()?;
     // read entries
     for _ in 0..entry_count {
         // entry name
-        let name_length = cursor.read_u8()?;
-        string.clear();
-        for _ in 0..name_length {
-            let chr = cursor.read_u8()?;
-            string.push(chr);
-        }
-        let name = from_utf8(&string).unwrap_or("wrong encoding?!").into();
-        let id = cursor.read_u32::<LE>()?;
-        let file_number = cursor.read_u32::<LE>()?;
-        let index = cursor.read_u32::<LE>()?;
+        let name = from_utf8(reader.read_prefixed()?).unwrap_or("wrong encoding?!").into();
+        let id = reader.read_u32()?;
+        let file_number = reader.read_u32()?;
+        let index = reader.read_u32()?;
         let entry = Entry::new(file_number, index);
         // rest of entry info
         let item = ListItem { name, id, entry};
@@ -80,31 +53,24 @@ fn load_1_0(cursor: &mut Cursor<&[u8]>) -> Result<List, Error> {
 }
 
 /// The 1.2 format seems to only be used in the `Obj` rle list file
-fn load_1_2(cursor: &mut Cursor<&[u8]>) -> Result<List, Error> {
+fn load_1_2(reader: &mut BinReader) -> Result<List, Error> {
     let mut list = List::new();
-    let mut string = Vec::<u8>::new();
 
     // Unknown u32 -- assumed to be the next free ID
-    let next_free_id = cursor.read_u32::<LE>()?;
+    let next_free_id = reader.read_u32()?;
     // list Entry counts
-    let entry_count = cursor.read_u32::<LE>()?;
+    let entry_count = reader.read_u32()?;
     // read entries
     for _ in 0..entry_count {
         // entry name
-        let name_length = cursor.read_u8()?;
-        string.clear();
-        for _ in 0..name_length {
-            let chr = cursor.read_u8()?;
-            string.push(chr);
-        }
-        let name = from_utf8(&string).unwrap_or("wrong encoding?!").into();
-        let id = cursor.read_u32::<LE>()?;
-        let file_number = cursor.read_u32::<LE>()?;
-        let index = cursor.read_u32::<LE>()?;
+        let name = from_utf8(reader.read_prefixed()?).unwrap_or("wrong encoding?!").into();
+        let id = reader.read_u32()?;
+        let file_number = reader.read_u32()?;
+        let index = reader.read_u32()?;
         let entry = Entry::new(file_number, index);
         // I'm sort of assuming that we're trying to link to the "next id?"
         // here in the newer format with `unknown_2`?
-        let unknown_2 = cursor.read_u32::<LE>()?;
+        let unknown_2 = reader.read_u32()?;
         // rest of entry info
         let item = ListItem { name, id, entry };
         list.items.push(item);
@@ -112,54 +78,52 @@ fn load_1_2(cursor: &mut Cursor<&[u8]>) -> Result<List, Error> {
     Ok(list)
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_lst_bul() {
-        let data = include_bytes!("../../../data/RLEs/bul.lst");
-        let list = parse_lst(data, false).unwrap();
-    }
+    use crate::fixture::LstFixture;
 
     #[test]
-    fn test_lst_ico() {
-        let data = include_bytes!("../../../data/RLEs/ico.lst");
-        let list = parse_lst(data, false).unwrap();
+    fn test_lst_1_0() {
+        let data = LstFixture::new("1.0")
+            .item("first", 0, Entry::new(0, 0))
+            .item("second", 4, Entry::new(2, 13))
+            .build();
+        let list = parse_lst(&data, false).unwrap();
+
+        assert_eq!(list.items.len(), 2);
+        assert_eq!(list.items[1].name, "second");
+        assert_eq!(list.items[1].entry, Entry::new(2, 13));
+        assert_eq!(list.get_item(4).unwrap().name, "second");
+        assert!(list.get_item(1).is_none());
     }
 
     #[test]
-    fn test_lst_int() {
-        let data = include_bytes!("../../../data/RLEs/int.lst");
-        let list = parse_lst(data, false).unwrap();
+    // NOTE: the 1.2 version is used by the `obj` list
+    fn test_lst_1_2() {
+        let data = LstFixture::new("1.2")
+            .item("tree", 1, Entry::new(3, 1))
+            .item("house", 2, Entry::new(3, 2))
+            .build();
+
+        let list = parse_lst(&data, false).unwrap();
+        assert_eq!(list.items.len(), 2);
+        assert_eq!(list.items[1].entry, Entry::new(3, 2));
+
+        let list = parse_lst(&data, true).unwrap();
+        assert_eq!(list.items[0].name, "tree");
     }
 
     #[test]
-    fn test_lst_tle() {
-        let data = include_bytes!("../../../data/RLEs/tle.lst");
-        let list = parse_lst(data, false).unwrap();
+    fn test_lst_empty() {
+        let data = LstFixture::new("1.0").build();
+        assert!(parse_lst(&data, false).unwrap().items.is_empty());
     }
 
-    // #[test]
-    // // NOTE: This is the only one to fail on the 1.0 version
-    // fn test_lst_snd() {
-    //     let data = include_bytes!("../../data/RLEs/snd.lst");
-    //     println!("data.len() = 0x{:X} bytes", data.len());
-    //     let list = List::load(data, false);
-    //     let list = match list {
-    //         Ok(_) => list,
-    //         Err(_) => {
-    //             // maybe it really is in version 1.2 format?
-    //             List::load(data, true)
-    //         }
-    //     };
-    //     list.unwrap();
-    // }
-
     #[test]
-    // NOTE: This uses the version 1.2 of the lst file
-    fn test_lst_obj() {
-        let data = include_bytes!("../../../data/RLEs/obj.lst");
-        let list = parse_lst(data, false).unwrap();
+    fn test_lst_truncated() {
+        let data = LstFixture::new("1.0").item("first", 0, Entry::new(0, 0)).build();
+        assert!(parse_lst(&data[..data.len() - 1], false).is_err());
     }
 }
@@ -1,5 +1,10 @@
+#[cfg(feature = "std")]
+pub mod bgm;
 pub mod lst;
 pub mod rle;
 pub mod rmd;
 pub mod rmm;
+#[cfg(feature = "std")]
 pub mod rmi;
+#[cfg(feature = "std")]
+pub mod snd;
@@ -1,182 +1,1582 @@
 //! This module has the methods for decoding the Redmoon Online RLE files and
 //! storing / exporting them into various formats.
 
-use std::str::from_utf8;
-use std::io::Cursor;
-use std::io::Seek;
-use std::io::SeekFrom;
+#[cfg(feature = "std")]
+use std::io::{Read, Seek, SeekFrom};
 
-use byteorder::ReadBytesExt;
+use alloc::vec::Vec;
+
+use byteorder::ByteOrder;
 use byteorder::LittleEndian as LE;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 use crate::error::Error;
+use crate::utility::bin_reader::BinReader;
 use crate::utility::pixel::Pixel;
 use crate::entity::resource::Resource;
-use crate::entity::resource_file::ResourceFile;
+use crate::entity::resource_file::{ResourceFailure, ResourceFile, ResourceSlot, RleViolation, RleWarning};
+use crate::entity::resource_meta::ResourceMeta;
+
+/// Resources this wide or tall, or more, are taken to have a broken header
+/// unless `DecodeOptions::max_dimensions` says otherwise. The image is
+/// allocated before its first run is read, so this stays low enough for a
+/// broken header not to ask for gigabytes; callers expecting larger resources
+/// raise it. Anything that decodes but doesn't fit in a single texture is
+/// diced by the renderer; see `utility::dice`.
+pub const MAX_RESOURCE_SIZE: i32 = 8000;
+
+/// Unpainted pixels of `PixelFormat::R5g6b5` images: magenta, which the
+/// images already use as their alpha colour now and then.
+pub const COLOR_KEY_565: u16 = 0xF81F;
+
+/// Layout of `Resource::image_raw`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// R, G, B and A bytes; unpainted pixels are transparent
+    Rgba8,
+    /// `Rgba8` with the R and B bytes swapped, as some GPUs and window
+    /// systems want them
+    Bgra8,
+    /// The file's own little endian 5,6,5 bit colors, not converted at all;
+    /// unpainted pixels are `COLOR_KEY_565`, and so are painted magenta ones.
+    R5g6b5,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgba8 | PixelFormat::Bgra8 => 4,
+            PixelFormat::R5g6b5 => 2,
+        }
+    }
+}
+
+/// How the images are decoded
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DecodeOptions {
+    pub format: PixelFormat,
+    /// Whether pixels painted in the `COLOR_KEY_565` magenta are left
+    /// transparent, like the unpainted ones; `PixelFormat::R5g6b5` images
+    /// can't tell them apart either way.
+    pub magenta_is_transparent: bool,
+    /// The width and height a resource has to stay under, `MAX_RESOURCE_SIZE`
+    /// by default; larger ones fail with `Error::OversizedResource`.
+    pub max_dimensions: (i32, i32),
+    /// Whether the pixel runs are checked against the size in the header:
+    /// no pixel painted outside the image, no move past either side of it,
+    /// no line past the one under it, and `Resource::len` matching the runs.
+    /// A resource that isn't fails with `Error::InvalidResource`, where it
+    /// would otherwise decode with warnings or unnoticed; for the integrity
+    /// checks.
+    pub strict: bool,
+    /// Whether `Resource::content_hash` is worked out while parsing, for
+    /// the tools hashing every resource anyway. Not for a resource decoded
+    /// into a caller's buffer, which has no image of its own.
+    pub hash: bool,
+}
+
+impl Default for DecodeOptions {
+    /// `PixelFormat::Rgba8`, magenta painted as it is
+    fn default() -> DecodeOptions {
+        DecodeOptions {
+            format: PixelFormat::Rgba8,
+            magenta_is_transparent: false,
+            max_dimensions: (MAX_RESOURCE_SIZE, MAX_RESOURCE_SIZE),
+            strict: false,
+            hash: false,
+        }
+    }
+}
+
+/// Layout of the file header, as found by `detect_version`. The resource
+/// offsets are from the start of the file in all of them, and the resources
+/// themselves are the same.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RleVersion {
+    /// `Resource File\0`, a 4 byte field that looks like the next free
+    /// offset, the resource count and the offset table; what the writer
+    /// writes
+    Standard,
+    /// The identifier followed right away by the count and the offsets
+    NoFreeOffset,
+    /// Without the identifier: the next free offset, which is the length of
+    /// the file, the count and the offsets
+    Headerless,
+}
+
+impl RleVersion {
+    /// Where the resource count is; the offset table follows it
+    fn count_at(self) -> usize {
+        match self {
+            RleVersion::Standard => RLE_IDENTIFIER.len() + 4,
+            RleVersion::NoFreeOffset => RLE_IDENTIFIER.len(),
+            RleVersion::Headerless => 4,
+        }
+    }
+
+    /// Where an offset table of `count` resources ends
+    fn table_end(self, count: usize) -> u64 {
+        (self.count_at() + 4) as u64 + 4 * count as u64
+    }
+}
+
+const RLE_IDENTIFIER: &[u8] = b"Resource File\0";
+
+impl From<PixelFormat> for DecodeOptions {
+    fn from(format: PixelFormat) -> DecodeOptions {
+        DecodeOptions { format, ..DecodeOptions::default() }
+    }
+}
 
 pub fn parse_rle(file_number: u32, data: &[u8]) -> Result<ResourceFile, Error> {
-    let mut cursor = Cursor::new(data);
+    parse_rle_as(file_number, data, PixelFormat::Rgba8)
+}
+
+/// Like `parse_rle`, with the images decoded into `format`
+pub fn parse_rle_as(file_number: u32, data: &[u8], format: PixelFormat) -> Result<ResourceFile, Error> {
+    parse_resources(file_number, data, format.into(), false)
+}
+
+/// Like `parse_rle`, with the images decoded as `options` say
+pub fn parse_rle_with(file_number: u32, data: &[u8], options: DecodeOptions) -> Result<ResourceFile, Error> {
+    parse_resources(file_number, data, options, false)
+}
+
+/// Like `parse_rle_as`, but a resource that can't be decoded is left
+/// undecoded and listed in `ResourceFile::failures` instead of failing the
+/// whole file; only a broken header or offset table still does.
+pub fn parse_rle_lenient(file_number: u32, data: &[u8], format: PixelFormat) -> Result<ResourceFile, Error> {
+    parse_resources(file_number, data, format.into(), true)
+}
+
+/// Like `parse_rle_as`, with the resources decoded on all the cores. They're
+/// all decoded even if one fails; the error is that of the first one failing.
+#[cfg(feature = "parallel")]
+pub fn parse_rle_parallel(file_number: u32, data: &[u8], format: PixelFormat) -> Result<ResourceFile, Error> {
+    let mut warnings = Vec::new();
+    let (version, offsets) = read_header_with_warnings(data, &mut warnings)?;
+    let decoded: Vec<Decoded> = offsets.par_iter()
+        .enumerate()
+        .map(|(idx, &offset)| decode_offset(data, file_number, idx as u32, offset, format.into()))
+        .collect();
+    let mut resource_file = assemble(file_number, version, decoded, false)?;
+    file_warnings(data.len() as u64, content_end(data, version, &offsets), warnings, &mut resource_file);
+    Ok(resource_file)
+}
+
+/// Like `parse_rle`, reading the file from `reader` rather than having all
+/// of it in memory: the header and then one resource at a time, each up to
+/// where the next one starts. For big files, or ones in an archive or
+/// coming over the network; the file doesn't have to be read from its
+/// start, it's the bytes from where `reader` is to its end.
+#[cfg(feature = "std")]
+pub fn parse_rle_reader<R: Read + Seek>(file_number: u32, mut reader: R) -> Result<ResourceFile, Error> {
+    let start = reader.stream_position()?;
+    let len = reader.seek(SeekFrom::End(0))?.saturating_sub(start);
+    reader.seek(SeekFrom::Start(start))?;
+    let head = read_head(&mut reader, len)?;
+    let mut warnings = Vec::new();
+    let (version, offsets) = header(&head, len, &mut warnings)?;
+
+    let mut starts: Vec<u64> = offsets.iter().filter(|&&offset| offset != 0).map(|&offset| offset as u64).collect();
+    starts.sort_unstable();
+    starts.dedup();
+    let mut end = version.table_end(offsets.len());
+    let mut window = Vec::new();
+    let mut decoded = Vec::with_capacity(offsets.len());
+    for (idx, &offset) in offsets.iter().enumerate() {
+        if offset == 0 {
+            decoded.push((idx as u32, offset, None));
+            continue;
+        }
+        let next = match starts.binary_search(&(offset as u64 + 1)) {
+            Ok(i) | Err(i) => starts.get(i).cloned().unwrap_or(len),
+        };
+        window.resize(next.saturating_sub(offset as u64) as usize, 0);
+        reader.seek(SeekFrom::Start(start + offset as u64))?;
+        reader.read_exact(&mut window)?;
+
+        let mut resource_warnings = Vec::new();
+        let resource = decode_window(BinReader::windowed(&window, offset as u64), file_number, idx as u32, offset,
+                                     PixelFormat::Rgba8.into(), None, &mut resource_warnings);
+        if let Ok(resource_end) = resource_end(BinReader::windowed(&window, offset as u64), offset) {
+            end = end.max(resource_end);
+        }
+        decoded.push((idx as u32, offset, Some((resource, resource_warnings))));
+    }
+    let mut resource_file = assemble(file_number, version, decoded, false)?;
+    file_warnings(len, end, warnings, &mut resource_file);
+    Ok(resource_file)
+}
+
+/// The first bytes `reader` reads, up to the end of the longest offset
+/// table the file of `len` bytes can have
+#[cfg(feature = "std")]
+fn read_head<R: Read>(reader: &mut R, len: u64) -> Result<Vec<u8>, Error> {
+    let mut head = Vec::new();
+    reader.by_ref().take(RleVersion::Standard.count_at() as u64 + 4).read_to_end(&mut head)?;
+    let table_end = [RleVersion::Standard, RleVersion::NoFreeOffset, RleVersion::Headerless].iter()
+        .filter_map(|&version| {
+            let mut head = BinReader::new(&head);
+            head.set_position(version.count_at() as u64);
+            head.read_u32().ok().map(|count| version.table_end(count as usize))
+        })
+        .max()
+        .unwrap_or(0)
+        .min(len);
+    reader.by_ref().take(table_end.saturating_sub(head.len() as u64)).read_to_end(&mut head)?;
+    Ok(head)
+}
+
+/// Decodes only the resource at `index`, reading the one entry of the offset
+/// table it needs and none of the other resources; for random access into
+/// big files. A file without a resource there fails with
+/// `Error::MissingResource`.
+pub fn parse_rle_single(file_number: u32, data: &[u8], index: u32) -> Result<Resource, Error> {
+    let mut reader = BinReader::new(data);
+    reader.set_position(detect_version(data)?.count_at() as u64);
+    let total_resources = reader.read_u32()?;
+    if index >= total_resources {
+        return Err(Error::MissingResource { file: file_number, index });
+    }
+    reader.skip(index as usize * 4)?;
+    let offset = reader.read_u32()?;
+    if offset == 0 {
+        return Err(Error::MissingResource { file: file_number, index });
+    }
+    decode_resource(data, file_number, index, offset, PixelFormat::Rgba8.into(), None, &mut Vec::new())?
+        .ok_or(Error::MissingResource { file: file_number, index })
+}
+
+/// A resource of the offset table as decoded, with its index, offset and
+/// warnings; `None` for a null offset
+type Decoded = (u32, u32, Option<(Result<Option<Resource>, Error>, Vec<RleWarning>)>);
+
+fn decode_offset(data: &[u8], file_number: u32, idx: u32, offset: u32, options: DecodeOptions) -> Decoded {
+    if offset == 0 {
+        return (idx, offset, None);
+    }
+    let mut warnings = Vec::new();
+    let resource = decode_resource(data, file_number, idx, offset, options, None, &mut warnings);
+    (idx, offset, Some((resource, warnings)))
+}
+
+fn parse_resources(file_number: u32, data: &[u8], options: DecodeOptions, lenient: bool) -> Result<ResourceFile, Error> {
+    let mut warnings = Vec::new();
+    let (version, offsets) = read_header_with_warnings(data, &mut warnings)?;
+    let decoded = offsets.iter()
+        .enumerate()
+        .map(|(idx, &offset)| decode_offset(data, file_number, idx as u32, offset, options));
+    let mut resource_file = assemble(file_number, version, decoded, lenient)?;
+    file_warnings(data.len() as u64, content_end(data, version, &offsets), warnings, &mut resource_file);
+    Ok(resource_file)
+}
+
+/// Where the offset table and the last of the resources end
+fn content_end(data: &[u8], version: RleVersion, offsets: &[u32]) -> u64 {
+    // the resources that failed to decode don't say where they end
+    offsets.iter()
+        .filter(|&&offset| offset != 0)
+        .filter_map(|&offset| resource_end(BinReader::new(data), offset).ok())
+        .fold(version.table_end(offsets.len()), u64::max)
+}
+
+/// Puts the warnings about the offset table before those of the resources,
+/// and adds one for the bytes of the `len` long file past the `end` of its
+/// content
+fn file_warnings(len: u64, end: u64, mut warnings: Vec<RleWarning>, resource_file: &mut ResourceFile) {
+    if end < len {
+        warnings.push(RleWarning::TrailingData { at: end, len: len - end });
+    }
+    warnings.append(&mut resource_file.warnings);
+    resource_file.warnings = warnings;
+}
+
+/// The file of the decoded resources, in index order
+fn assemble(file_number: u32, version: RleVersion, decoded: impl IntoIterator<Item = Decoded>,
+            lenient: bool) -> Result<ResourceFile, Error> {
     let mut resource_file = ResourceFile::new();
+    resource_file.file_number = file_number;
+    resource_file.version = version;
+    for (idx, offset, decoded) in decoded {
+        let (resource, mut warnings) = match decoded {
+            Some(decoded) => decoded,
+            None => {
+                // we'll skip 0 (null) offsets as I think they are just placeholders in the file
+                // but we can't ignore them in the resource offset list because the index of the
+                // resource is important
+                resource_file.slots.push(ResourceSlot::Empty);
+                continue;
+            }
+        };
+        match resource {
+            Ok(Some(resource)) => {
+                resource_file.slots.push(ResourceSlot::Resource(resource_file.resources.len()));
+                resource_file.resources.push(resource);
+            }
+            Ok(None) => resource_file.slots.push(ResourceSlot::Undecoded),
+            Err(reason) if lenient => {
+                // what was found before the failure is of no use
+                warnings.clear();
+                resource_file.slots.push(ResourceSlot::Undecoded);
+                resource_file.failures.push(ResourceFailure { index: idx, offset, reason });
+            }
+            Err(error) => return Err(error),
+        }
+        resource_file.warnings.extend(warnings);
+    }
+    Ok(resource_file)
+}
 
-    // file type string: needs to equal "Resource File\n"
-    let (file_type, _rest) = if data.len() >= 14 {
-        data.split_at(14)
-    } else {
-        return Err(Error::MissingRleIdentifier);
-    };
-    let file_type: &str = from_utf8(file_type)?;
+/// An RLE file of which only the header and the offset table were read; the
+/// resources are decoded one at a time, as they're asked for. Made by
+/// `ResourceFile::open_lazy`.
+pub struct LazyResourceFile<D> {
+    data: D,
+    file_number: u32,
+    format: PixelFormat,
+    offsets: Vec<u32>,
+}
 
-    if file_type != "Resource File\0" {
-        return Err(Error::MissingRleIdentifier);
+impl<D: AsRef<[u8]>> LazyResourceFile<D> {
+    /// Reads the header and offset table of `data`, with the images to be
+    /// decoded into `format`
+    pub fn open(file_number: u32, data: D, format: PixelFormat) -> Result<LazyResourceFile<D>, Error> {
+        let (_, offsets) = read_header(data.as_ref())?;
+        Ok(LazyResourceFile { data, file_number, format, offsets })
     }
 
-    // start reading after the "Resource file string"
-    cursor.seek(SeekFrom::Start(14u64))?;
+    /// How many resource indices the file has, null offsets included
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
 
-    // unknown_1: 4 Unknown bytes; (next free offset?)
-    let tmp = cursor.read_u32::<LE>()?;
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
 
-    // total_resources: 4 bytes (u32)
-    let total_resources = cursor.read_u32::<LE>()?;
+    /// Whether the file has something other than a null offset at `index`
+    pub fn has(&self, index: u32) -> bool {
+        self.offsets.get(index as usize).is_some_and(|&offset| offset != 0)
+    }
 
-    // resource_offsets: [total_resources; u32]
-    let mut resource_offsets = Vec::<u32>::new();
-    for idx in 0..total_resources {
-        let val = cursor.read_u32::<LE>()?;
-        resource_offsets.push(val);
+    /// Decodes the resource at `index`; `None` for a null offset, an index
+    /// past the end, or a resource with a broken size
+    pub fn get(&self, index: u32) -> Result<Option<Resource>, Error> {
+        self.get_with_warnings(index).map(|(resource, _)| resource)
     }
 
-    // println!("Loading {} resources at offsets:{:?}", total_resources, resource_offsets);
+    /// `get`, with what the decoder ran into on the way
+    pub fn get_with_warnings(&self, index: u32) -> Result<(Option<Resource>, Vec<RleWarning>), Error> {
+        let mut warnings = Vec::new();
+        let resource = match self.offsets.get(index as usize) {
+            Some(&offset) if offset != 0 => {
+                decode_resource(self.data.as_ref(), self.file_number, index, offset, self.format.into(), None, &mut warnings)?
+            }
+            _ => None,
+        };
+        Ok((resource, warnings))
+    }
 
-    for (idx, offset) in resource_offsets.iter().enumerate() {
+    /// Decodes the resource at `index` straight into `target`, its top left
+    /// pixel at the start and `stride` bytes from one row to the next, as
+    /// into an atlas; the resource is returned without an `image_raw`. Only
+    /// the painted pixels are written, the target is cleared beforehand (to
+    /// `COLOR_KEY_565` for `PixelFormat::R5g6b5`). `scan_rle` has the sizes to
+    /// make room for. Nothing is written for a null offset, an index past the
+    /// end, or a resource with a broken size; a target too small for the
+    /// resource fails with `Error::BufferTooSmall`.
+    pub fn decode_into(&self, index: u32, target: &mut [u8], stride: usize) -> Result<(Option<Resource>, Vec<RleWarning>), Error> {
+        let mut warnings = Vec::new();
+        let resource = match self.offsets.get(index as usize) {
+            Some(&offset) if offset != 0 => {
+                decode_resource(self.data.as_ref(), self.file_number, index, offset, self.format.into(),
+                                Some((target, stride)), &mut warnings)?
+            }
+            _ => None,
+        };
+        Ok((resource, warnings))
+    }
+}
 
-        let offset = *offset;
+/// Decodes the resources of an RLE file one at a time as it walks the offset
+/// table, so a caller can stop or skip ahead without decoding the rest.
+/// Null offsets and resources with a broken size are passed over; once a
+/// resource fails to decode its error is the last item.
+pub struct RleResourceIter<'a> {
+    data: &'a [u8],
+    file_number: u32,
+    format: PixelFormat,
+    offsets: BinReader<'a>,
+    index: u32,
+    total_resources: u32,
+}
 
+impl<'a> RleResourceIter<'a> {
+    /// Checks the header of `data`, with the images to be decoded into
+    /// `format`
+    pub fn new(file_number: u32, data: &'a [u8], format: PixelFormat) -> Result<RleResourceIter<'a>, Error> {
+        let mut offsets = BinReader::new(data);
+        offsets.set_position(detect_version(data)?.count_at() as u64);
+        let total_resources = offsets.read_u32()?;
+        Ok(RleResourceIter { data, file_number, format, offsets, index: 0, total_resources })
+    }
+}
+
+impl<'a> Iterator for RleResourceIter<'a> {
+    type Item = Result<Resource, Error>;
+
+    fn next(&mut self) -> Option<Result<Resource, Error>> {
+        while self.index < self.total_resources {
+            let idx = self.index;
+            self.index += 1;
+            let offset = match self.offsets.read_u32() {
+                Ok(offset) => offset,
+                Err(error) => {
+                    self.index = self.total_resources;
+                    return Some(Err(error));
+                }
+            };
+            if offset == 0 {
+                continue;
+            }
+            match decode_resource(self.data, self.file_number, idx, offset, self.format.into(), None, &mut Vec::new()) {
+                Ok(Some(resource)) => return Some(Ok(resource)),
+                Ok(None) => (),
+                Err(error) => {
+                    self.index = self.total_resources;
+                    return Some(Err(error));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Reads only the headers of the resources in the file, for the sizes and
+/// offsets without decoding or copying any pixels. The null offset
+/// placeholders are left out, the indices of the others are in the headers.
+pub fn scan_rle(data: &[u8]) -> Result<Vec<ResourceMeta>, Error> {
+    let (_, offsets) = read_header(data)?;
+    let mut reader = BinReader::new(data);
+    let mut resources = Vec::new();
+    for (index, offset) in offsets.into_iter().enumerate() {
+        let index = index as u32;
         if offset == 0 {
-            // we'll skip 0 (null) offsets as I think they are just placeholders in the file
-            // but we can't ignore them in the resource offset list because the index of the
-            // resource is important
             continue;
         }
+        reader.set_position(offset as u64);
+        resources.push(ResourceMeta {
+            index,
+            offset,
+            len: reader.read_u32()?,
+            offset_x: reader.read_i32()?,
+            offset_y: reader.read_i32()?,
+            width: reader.read_i32()?,
+            height: reader.read_i32()?,
+            unknown_1: reader.read_u32()?,
+            unknown_2: reader.read_u32()?,
+            unknown_3: reader.read_u32()?,
+            unknown_4: reader.read_u32()?,
+        });
+    }
+    Ok(resources)
+}
 
-        let mut resource = Resource::new();
-        cursor.seek(SeekFrom::Start(offset as u64))?;
-
-        // resource id's
-        resource.file_num = Some(file_number);
-        resource.set_index(idx as u32);
-        resource.offset = offset;
-
-        // read the resource header
-        resource.len = cursor.read_u32::<LE>()?;
-        resource.offset_x = cursor.read_i32::<LE>()?;
-        resource.offset_y = cursor.read_i32::<LE>()?;
-        resource.width = cursor.read_i32::<LE>()?;
-        resource.height = cursor.read_i32::<LE>()?;
-        resource.unknown_1 = cursor.read_u32::<LE>()?;
-        resource.unknown_2 = cursor.read_u32::<LE>()?;
-        resource.unknown_3 = cursor.read_u32::<LE>()?;
-        resource.unknown_4 = cursor.read_u32::<LE>()?;
-
-        // Pre-fill the image buffer with 0's
-        if resource.width < 8000 && resource.width > 0
-            && resource.height < 8000 && resource.height > 0 {
-            let total_px = resource.width * resource.height * 4 /* bytes / pixel */;
-            for _ in 0..total_px {
-                resource.image_raw.push(0x0);
-            }
-        } else {
-            println!("wrongly sized resource: ({}, {})", resource.width, resource.height);
-            // oversized resource
-            resource.image_raw.push(0xFF); // R
-            resource.image_raw.push(0xFF); // G
-            resource.image_raw.push(0xFF); // B
-            resource.image_raw.push(0xFF); // A
-            continue;
+/// Finds the header layout of the file: with the identifier, the first of
+/// `Standard` and `NoFreeOffset` whose offset table fits the file, else
+/// `Standard`, to fail on the way; without it `Headerless`, if its table
+/// fits and its first field is the length of the file.
+pub fn detect_version(data: &[u8]) -> Result<RleVersion, Error> {
+    detect(data, data.len() as u64)
+}
+
+/// `detect_version` for a file `len` bytes long starting with `head`, which
+/// holds at least its offset table
+fn detect(head: &[u8], len: u64) -> Result<RleVersion, Error> {
+    // older tools wrote the identifier with a line feed for the terminator
+    let identified = head.len() >= RLE_IDENTIFIER.len()
+        && (head.starts_with(RLE_IDENTIFIER) || head.starts_with(b"Resource File\n"));
+    if identified {
+        let standard = offset_table(head, len, RleVersion::Standard);
+        let no_free_offset = offset_table(head, len, RleVersion::NoFreeOffset);
+        return Ok(match (standard, no_free_offset) {
+            // read as Standard, a NoFreeOffset file's first offset is taken
+            // for the count, and a null one makes an empty table, which
+            // always fits; the other way round, a Standard file's free
+            // offset is its length, never a count that fits
+            (Some(ref standard), Some(ref other)) if standard.is_empty() && !other.is_empty() => {
+                RleVersion::NoFreeOffset
+            }
+            (None, Some(_)) => RleVersion::NoFreeOffset,
+            _ => RleVersion::Standard,
+        });
+    }
+    let free_offset = BinReader::new(head).read_u32().ok();
+    match offset_table(head, len, RleVersion::Headerless) {
+        Some(_) if free_offset.map(u64::from) == Some(len) => Ok(RleVersion::Headerless),
+        _ => Err(Error::MissingRleIdentifier),
+    }
+}
+
+/// The offset table of the file read as `version`, unless it runs past the
+/// end of the file or an offset points into the header or past the end
+fn offset_table(head: &[u8], len: u64, version: RleVersion) -> Option<Vec<u32>> {
+    let mut reader = BinReader::new(head);
+    reader.set_position(version.count_at() as u64);
+    let count = reader.read_u32().ok()? as usize;
+    let table = reader.take(count.checked_mul(4)?).ok()?;
+    let table_end = reader.position();
+    let offsets: Vec<u32> = table.chunks(4).map(LE::read_u32).collect();
+    let inside = |&offset: &u32| offset == 0 || (offset as u64 >= table_end && (offset as u64) < len);
+    if offsets.iter().all(inside) {
+        Some(offsets)
+    } else {
+        None
+    }
+}
+
+/// Finds the header layout and returns the resource offsets, 0 for the null
+/// offset placeholders.
+fn read_header(data: &[u8]) -> Result<(RleVersion, Vec<u32>), Error> {
+    read_header_with_warnings(data, &mut Vec::new())
+}
+
+/// `read_header`; an offset table counting more resources than it has room
+/// for is cut short where the file or the first resource starts, with a
+/// `RleWarning::TruncatedOffsetTable`.
+fn read_header_with_warnings(data: &[u8], warnings: &mut Vec<RleWarning>) -> Result<(RleVersion, Vec<u32>), Error> {
+    header(data, data.len() as u64, warnings)
+}
+
+/// `read_header_with_warnings` for a file `len` bytes long starting with
+/// `head`, which holds at least its offset table or, if that's broken, as
+/// much of it as the file has
+fn header(head: &[u8], len: u64, warnings: &mut Vec<RleWarning>) -> Result<(RleVersion, Vec<u32>), Error> {
+    let version = detect(head, len)?;
+    if let Some(offsets) = offset_table(head, len, version) {
+        return Ok((version, offsets));
+    }
+
+    // a broken table: read the entries before the end of the file or the
+    // first resource found so far, whichever comes first
+    let mut reader = BinReader::new(head);
+    reader.set_position(version.count_at() as u64);
+    let total_resources = reader.read_u32()?;
+    let mut resource_offsets = Vec::<u32>::new();
+    let mut end = len;
+    while (resource_offsets.len() as u32) < total_resources && reader.position() + 4 <= end {
+        let offset = reader.read_u32()?;
+        if offset as u64 >= reader.position() && (offset as u64) < len {
+            end = end.min(offset as u64);
         }
+        resource_offsets.push(offset);
+    }
+    if (resource_offsets.len() as u32) < total_resources {
+        warnings.push(RleWarning::TruncatedOffsetTable { declared: total_resources, present: resource_offsets.len() as u32 });
+    }
+    Ok((version, resource_offsets))
+}
 
-        // read the rest of the image data
-        let mut x = 0i32;
-        let mut y = 0i32;
-        'image: loop {
-            let entry_type = cursor.read_u8().unwrap();
-            // println!("RLE Entry Type:{} @ offset: `{}`",
-            //          entry_type,
-            //          cursor.position());
-            match entry_type {
-                0x00 => {
-                    /* End resource marker */
-                    break 'image;
-                }
-                0x01 => {
-                    /* Paint pixels */
-                    let pixels = cursor.read_u32::<LE>()?;
-                    for p in 0..pixels {
-                        let data = cursor.read_u16::<LE>()?;
-                        let (r, g, b) = format_r5g6b5_norm(data);
-                        let _y = y * 4 * resource.width as i32;
-                        let _x = x * 4;
-                        let idx: usize = _y as usize + _x as usize;
-                        resource.image_raw[idx]   = r;
-                        resource.image_raw[idx+1] = g;
-                        resource.image_raw[idx+2] = b;
-                        resource.image_raw[idx+3] = 0xFF;
+/// Decodes the resource at `offset`, which is index `idx` of the file;
+/// `None` if its size is broken. The errors say where in the file decoding
+/// stopped. With a target, a buffer and the bytes from one row of it to the
+/// next, the pixels are painted there and the resource's `image_raw` stays
+/// empty.
+fn decode_resource(data: &[u8], file_number: u32, idx: u32, offset: u32, options: DecodeOptions,
+                   target: Option<(&mut [u8], usize)>, warnings: &mut Vec<RleWarning>) -> Result<Option<Resource>, Error> {
+    decode_window(BinReader::new(data), file_number, idx, offset, options, target, warnings)
+}
+
+/// `decode_resource` reading the file through `reader`, which may only have
+/// a window of it
+fn decode_window(reader: BinReader, file_number: u32, idx: u32, offset: u32, options: DecodeOptions,
+                 target: Option<(&mut [u8], usize)>, warnings: &mut Vec<RleWarning>) -> Result<Option<Resource>, Error> {
+    let mut at = offset as u64;
+    decode_runs(reader, file_number, idx, offset, options, target, warnings, &mut at).map_err(|error| match error {
+        Error::UnexpectedEnd { .. } => {
+            Error::UnexpectedEndOfResource { file: file_number, index: idx, at }
+        }
+        error => error,
+    })
+}
+
+/// `decode_resource`, keeping in `at` where the header or the run being read
+/// starts
+#[allow(clippy::too_many_arguments)]
+fn decode_runs(mut reader: BinReader, file_number: u32, idx: u32, offset: u32, options: DecodeOptions,
+               target: Option<(&mut [u8], usize)>, warnings: &mut Vec<RleWarning>, at: &mut u64)
+               -> Result<Option<Resource>, Error> {
+    let format = options.format;
+    let bytes_per_pixel = format.bytes_per_pixel();
+    let mut resource = Resource::new();
+    reader.set_position(offset as u64);
+
+    // resource id's
+    resource.file_num = Some(file_number);
+    resource.set_index(idx);
+    resource.offset = offset;
+
+    // read the resource header
+    resource.len = reader.read_u32()?;
+    resource.offset_x = reader.read_i32()?;
+    resource.offset_y = reader.read_i32()?;
+    resource.width = reader.read_i32()?;
+    resource.height = reader.read_i32()?;
+    resource.unknown_1 = reader.read_u32()?;
+    resource.unknown_2 = reader.read_u32()?;
+    resource.unknown_3 = reader.read_u32()?;
+    resource.unknown_4 = reader.read_u32()?;
+
+    // a resource without a size has nothing to decode
+    if resource.width <= 0 || resource.height <= 0 {
+        return Ok(None);
+    }
+    let (max_width, max_height) = options.max_dimensions;
+    if resource.width >= max_width || resource.height >= max_height {
+        let (width, height) = (resource.width, resource.height);
+        return Err(Error::OversizedResource { file: file_number, index: idx, width, height });
+    }
 
+    let (width, height) = (resource.width, resource.height);
+    let row = width as usize * bytes_per_pixel;
+    let mut image_raw = Vec::new();
+    let (pixels, stride) = match target {
+        Some((target, stride)) => {
+            let needed = (height as usize - 1) * stride + row;
+            if stride < row || target.len() < needed {
+                return Err(Error::BufferTooSmall { file: file_number, index: idx, needed });
+            }
+            (target, stride)
+        }
+        None => {
+            // Pre-fill the image buffer with unpainted pixels
+            let total_px = width as usize * height as usize;
+            image_raw = match format {
+                PixelFormat::Rgba8 | PixelFormat::Bgra8 => vec![0x0; total_px * 4],
+                PixelFormat::R5g6b5 => COLOR_KEY_565.to_le_bytes().repeat(total_px),
+            };
+            (&mut image_raw[..], row)
+        }
+    };
+
+    // read the rest of the image data
+    let mut x = 0i32;
+    let mut y = 0i32;
+    'image: loop {
+        *at = reader.position();
+        let entry_type = reader.read_u8()?;
+        match entry_type {
+            0x00 => {
+                /* End resource marker */
+                let actual = (reader.position() - (offset as u64 + 36)) as u32;
+                if options.strict && actual != resource.len {
+                    let violation = RleViolation::LengthMismatch { declared: resource.len, actual };
+                    return Err(Error::InvalidResource { file: file_number, index: idx, at: offset as u64, violation });
+                }
+                break 'image;
+            }
+            0x01 => {
+                /* Paint pixels */
+                let count = reader.read_u32()?;
+                let (start_x, mut dropped) = (x, 0);
+                for _ in 0..count {
+                    let data = reader.read_u16()?;
+                    // pixels outside the image are dropped instead of
+                    // spilling into the neighbouring line
+                    if x < 0 || x >= width || y >= height {
+                        dropped += 1;
                         x += 1;
+                        continue;
+                    }
+                    let idx = y as usize * stride + x as usize * bytes_per_pixel;
+                    match format {
+                        PixelFormat::Rgba8 | PixelFormat::Bgra8
+                            if options.magenta_is_transparent && data == COLOR_KEY_565 => {
+                            pixels[idx..idx + 4].copy_from_slice(&[0; 4]);
+                        }
+                        PixelFormat::Rgba8 => {
+                            let (r, g, b) = format_r5g6b5_norm(data);
+                            pixels[idx]   = r;
+                            pixels[idx+1] = g;
+                            pixels[idx+2] = b;
+                            pixels[idx+3] = 0xFF;
+                        }
+                        PixelFormat::Bgra8 => {
+                            let (r, g, b) = format_r5g6b5_norm(data);
+                            pixels[idx]   = b;
+                            pixels[idx+1] = g;
+                            pixels[idx+2] = r;
+                            pixels[idx+3] = 0xFF;
+                        }
+                        PixelFormat::R5g6b5 => {
+                            pixels[idx..idx + 2].copy_from_slice(&data.to_le_bytes());
+                        }
                     }
+
+                    x += 1;
+                }
+                if dropped > 0 && options.strict {
+                    let violation = RleViolation::PixelsOutOfBounds { x: start_x, y, pixels: dropped };
+                    return Err(Error::InvalidResource { file: file_number, index: idx, at: *at, violation });
                 }
-                0x02 => {
-                    /* Move `x` pos */
-                    let pixels = cursor.read_i32::<LE>()?;
-                    x += pixels / 2; // NOTE: the two is probaby a u16 jump?
+                if dropped > 0 {
+                    warnings.push(RleWarning::PixelsOutOfBounds { index: idx, x: start_x, y, pixels: dropped });
                 }
-                0x03 => {
-                    /* Next line */
-                    y += 1;
+            }
+            0x02 => {
+                /* Move `x` pos */
+                // The move is in bytes of the r5g6b5 line, so two per
+                // pixel. It is negative to go back on the line, which is
+                // common after `0x03` as that keeps the column. The
+                // position may leave the image; see `0x01`.
+                let bytes = reader.read_i32()?;
+                if bytes % 2 != 0 {
+                    warnings.push(RleWarning::OddMove { index: idx, bytes });
+                }
+                x = x.saturating_add(bytes / 2);
+                if options.strict && (x < 0 || x > width) {
+                    let violation = RleViolation::MoveOutOfBounds { x };
+                    return Err(Error::InvalidResource { file: file_number, index: idx, at: *at, violation });
                 }
-                _ => {
-                    return Err(Error::UnknownOffsetTypeAt(cursor.position()));
+            }
+            0x03 => {
+                /* Next line */
+                y += 1;
+                if options.strict && y > height {
+                    let violation = RleViolation::LineOutOfBounds { y };
+                    return Err(Error::InvalidResource { file: file_number, index: idx, at: *at, violation });
                 }
             }
+            _ => {
+                return Err(Error::UnknownResourceRunAt { file: file_number, index: idx, at: *at });
+            }
         }
-        resource_file.resources.push(resource);
     }
-    Ok(resource_file)
+    resource.image_raw = image_raw;
+    if options.hash && !resource.image_raw.is_empty() {
+        resource.cache_hash();
+    }
+    Ok(Some(resource))
+}
+
+/// Returns the undecoded bytes (resource header and pixel runs) of every
+/// resource in the file, keeping `None` for the null offset placeholders so the
+/// position in the returned `Vec` is still the resource index.
+pub fn raw_resources(data: &[u8]) -> Result<Vec<Option<&[u8]>>, Error> {
+    let mut resources = Vec::new();
+    for offset in read_header(data)?.1 {
+        if offset == 0 {
+            resources.push(None);
+            continue;
+        }
+        let end = resource_end(BinReader::new(data), offset)?;
+        match data.get(offset as usize..end as usize) {
+            Some(bytes) => resources.push(Some(bytes)),
+            None => return Err(Error::UnknownOffsetTypeAt(end)),
+        }
+    }
+    Ok(resources)
+}
+
+/// Where the resource at `offset` ends, found by walking its pixel runs
+/// without decoding them
+fn resource_end(mut reader: BinReader, offset: u32) -> Result<u64, Error> {
+    // skip the 9 field resource header and walk the pixel runs
+    reader.set_position(offset as u64 + 36);
+    loop {
+        match reader.read_u8()? {
+            0x00 => break,
+            0x01 => {
+                let pixels = reader.read_u32()?;
+                reader.skip(pixels as usize * 2)?;
+            }
+            0x02 => {
+                reader.skip(4)?;
+            }
+            0x03 => (),
+            _ => return Err(Error::UnknownOffsetTypeAt(reader.position())),
+        }
+    }
+    Ok(reader.position())
 }
 
 /// The pixels in the RLE files are saved as normalized 5,6,5 bit normalized RGB colors.
 /// Magenta is sometimes used in the images as an alpha colour but it is relatively rare; it is
 /// usually just enough to set the default colour to be transparent and "paint" over the pixels
 /// with the actual colour.
-// TODO: There is probably a quicker way to do this conversion without the FP mult & div ...
-// TODO: Create type for r5g6b5 normalized colors and don't convert (OpenGL & DX can do this)
-fn format_r5g6b5_norm(d: u16) -> (u8, u8, u8) {
-    let b = ((d & 0x1F) as f32 / 31.0) * 255.0;
-    let g = (((d >> 5) & 0x3F) as f32 / 63.0) * 255.0;
-    let r = (((d >> 11) & 0x1F) as f32 / 31.0) * 255.0;
-    (r as u8, g as u8, b as u8)
+pub(crate) fn format_r5g6b5_norm(d: u16) -> (u8, u8, u8) {
+    (EXPAND_5[(d >> 11) as usize & 0x1F], EXPAND_6[(d >> 5) as usize & 0x3F], EXPAND_5[d as usize & 0x1F])
 }
 
+/// The 8 bit values of the 5 and 6 bit channels, `v * 255 / max` rounded
+/// down, the same as the float math they replace
+const EXPAND_5: [u8; 32] = expand_table();
+const EXPAND_6: [u8; 64] = expand_table();
 
-#[cfg(test)]
+const fn expand_table<const N: usize>() -> [u8; N] {
+    let mut table = [0; N];
+    let mut v = 0;
+    while v < N {
+        table[v] = (v * 255 / (N - 1)) as u8;
+        v += 1;
+    }
+    table
+}
+
+/// The little endian r5g6b5 pixels of a `PixelFormat::R5g6b5` image as
+/// `PixelFormat::Rgba8`, `COLOR_KEY_565` as transparent; a last odd byte is
+/// left out
+pub fn convert_565_buffer(data: &[u8]) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity(data.len() / 2 * 4);
+    for px in data.chunks_exact(2) {
+        match u16::from_le_bytes([px[0], px[1]]) {
+            COLOR_KEY_565 => rgba.extend_from_slice(&[0; 4]),
+            color => {
+                let (r, g, b) = format_r5g6b5_norm(color);
+                rgba.extend_from_slice(&[r, g, b, 0xFF]);
+            }
+        }
+    }
+    rgba
+}
+
+
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
 
+    use std::io::Cursor;
+
+    use crate::entity::raw_header_extras::RawHeaderExtras;
+    use crate::fixture::{RleFixture, ResourceFixture};
+
+    const RED: u16 = 0xF800;
+    const BLUE: u16 = 0x001F;
+
+    #[test]
+    fn test_parse_rle() {
+        let data = RleFixture::new()
+            .resource(ResourceFixture::new(2, 2).offset(-3, 5)
+                .pixels(&[RED, BLUE])
+                .next_line().skip(-1)
+                .pixels(&[RED]))
+            .build();
+        let rle = parse_rle(7, &data).unwrap();
+
+        assert_eq!(rle.file_number, 7);
+        assert_eq!(rle.resources.len(), 1);
+        let res = &rle.resources[0];
+        assert_eq!(res.file_num, Some(7));
+        assert_eq!(res.index(), 0);
+        assert_eq!((res.offset_x, res.offset_y, res.width, res.height), (-3, 5, 2, 2));
+        assert_eq!(&res.image_raw[0..8], &[0xFF, 0, 0, 0xFF, 0, 0, 0xFF, 0xFF]);
+        assert_eq!(&res.image_raw[8..16], &[0, 0, 0, 0, 0xFF, 0, 0, 0xFF]);
+    }
+
+    #[test]
+    fn test_parse_rle_single() {
+        let data = RleFixture::new()
+            .resource(ResourceFixture::new(1, 1).pixels(&[RED]))
+            .null()
+            .resource(ResourceFixture::new(2, 1).offset(4, -2).pixels(&[RED, BLUE]))
+            .build();
+        let rle = parse_rle(3, &data).unwrap();
+
+        let res = parse_rle_single(3, &data, 2).unwrap();
+        assert_eq!((res.file_num, res.index(), res.offset_x, res.offset_y), (Some(3), 2, 4, -2));
+        assert_eq!(res.image_raw, rle.get(2).unwrap().image_raw);
+        for &index in [1, 3].iter() {
+            match parse_rle_single(3, &data, index) {
+                Err(Error::MissingResource { file: 3, index: missing }) => assert_eq!(missing, index),
+                other => panic!("{:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_rle_reader() {
+        let data = RleFixture::new()
+            .resource(ResourceFixture::new(1, 1).pixels(&[RED]))
+            .null()
+            .resource(ResourceFixture::new(2, 1).offset(4, -2).pixels(&[RED, BLUE]))
+            .build();
+        let rle = parse_rle(3, &data).unwrap();
+        let streamed = parse_rle_reader(3, Cursor::new(&data)).unwrap();
+        assert_eq!((streamed.file_number, &streamed.slots), (3, &rle.slots));
+        let resources = |rle: &ResourceFile| -> Vec<(u32, u32, i32, Vec<u8>)> {
+            rle.iter().map(|r| (r.index(), r.offset, r.offset_x, r.image_raw.clone())).collect()
+        };
+        assert_eq!(resources(&streamed), resources(&rle));
+        assert!(streamed.warnings.is_empty());
+
+        // from the middle of a stream, with bytes past the file
+        let mut stream = b"head".to_vec();
+        stream.extend_from_slice(&data);
+        stream.extend_from_slice(&[0xAB; 3]);
+        let mut cursor = Cursor::new(stream);
+        cursor.set_position(4);
+        let streamed = parse_rle_reader(3, cursor).unwrap();
+        assert_eq!(resources(&streamed), resources(&rle));
+        assert_eq!(streamed.warnings, vec![RleWarning::TrailingData { at: data.len() as u64, len: 3 }]);
+
+        // the errors say where in the file, as parse_rle's
+        let cut = &data[..data.len() - 3];
+        let expected = match parse_rle(3, cut) {
+            Err(Error::UnexpectedEndOfResource { index: 2, at, .. }) => at,
+            _ => panic!("parse_rle decoded a cut file"),
+        };
+        assert!(matches!(parse_rle_reader(3, Cursor::new(cut)), Err(Error::UnexpectedEndOfResource { file: 3, index: 2, at })
+                         if at == expected));
+        assert!(matches!(parse_rle_reader(3, Cursor::new(&b"no resource file"[..])), Err(Error::MissingRleIdentifier)));
+    }
+
+    #[test]
+    fn test_parse_rle_565() {
+        let data = RleFixture::new()
+            .resource(ResourceFixture::new(2, 2)
+                .pixels(&[RED, BLUE])
+                .next_line().skip(-1)
+                .pixels(&[COLOR_KEY_565]))
+            .build();
+        let rle = parse_rle_as(7, &data, PixelFormat::R5g6b5).unwrap();
+
+        let pixels: Vec<u16> = rle.resources[0].image_raw.chunks(2)
+            .map(|px| u16::from_le_bytes([px[0], px[1]]))
+            .collect();
+        // painted magenta can't be told from unpainted
+        assert_eq!(pixels, vec![RED, BLUE, COLOR_KEY_565, COLOR_KEY_565]);
+    }
+
+    #[test]
+    fn test_parse_rle_bgra() {
+        let data = RleFixture::new()
+            .resource(ResourceFixture::new(2, 1).pixels(&[RED, BLUE]))
+            .build();
+        let rle = parse_rle_as(7, &data, PixelFormat::Bgra8).unwrap();
+        assert_eq!(rle.resources[0].image_raw, vec![0, 0, 0xFF, 0xFF, 0xFF, 0, 0, 0xFF]);
+    }
+
+    #[test]
+    fn test_format_r5g6b5_norm() {
+        // every color comes out as the float math used to make it
+        for color in 0..=0xFFFFu16 {
+            let b = ((color & 0x1F) as f32 / 31.0) * 255.0;
+            let g = (((color >> 5) & 0x3F) as f32 / 63.0) * 255.0;
+            let r = (((color >> 11) & 0x1F) as f32 / 31.0) * 255.0;
+            assert_eq!(format_r5g6b5_norm(color), (r as u8, g as u8, b as u8));
+        }
+    }
+
+    #[test]
+    fn test_convert_565_buffer() {
+        let mut data = Vec::new();
+        for &color in [RED, COLOR_KEY_565, 0x8410].iter() {
+            data.extend_from_slice(&color.to_le_bytes());
+        }
+        data.push(0xAB);
+        assert_eq!(convert_565_buffer(&data), vec![0xFF, 0, 0, 0xFF, 0, 0, 0, 0, 131, 129, 131, 0xFF]);
+    }
+
+    #[test]
+    fn test_convert_resource() {
+        let data = RleFixture::new()
+            .resource(ResourceFixture::new(2, 2).pixels(&[RED, BLUE]).next_line().skip(-2).pixels(&[0x1234]))
+            .build();
+        let rgba = parse_rle(0, &data).unwrap();
+        let mut resource = parse_rle_as(0, &data, PixelFormat::R5g6b5).unwrap().resources.remove(0);
+
+        // the 565 bytes convert to what decoding into the other formats gives
+        assert_eq!(resource.converted(PixelFormat::R5g6b5, PixelFormat::Rgba8).unwrap(), rgba.resources[0].image_raw);
+        let bgra = parse_rle_as(0, &data, PixelFormat::Bgra8).unwrap();
+        resource.convert(PixelFormat::R5g6b5, PixelFormat::Bgra8).unwrap();
+        assert_eq!(resource.image_raw, bgra.resources[0].image_raw);
+        resource.convert(PixelFormat::Bgra8, PixelFormat::R5g6b5).unwrap();
+        assert_eq!(resource.image_raw, parse_rle_as(0, &data, PixelFormat::R5g6b5).unwrap().resources[0].image_raw);
+        assert!(matches!(resource.convert(PixelFormat::Rgba8, PixelFormat::R5g6b5), Err(Error::UnencodableResource(0))));
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_rgba_image() {
+        use core::convert::TryFrom;
+        use image::RgbaImage;
+
+        let data = RleFixture::new().resource(ResourceFixture::new(2, 1).pixels(&[RED])).build();
+        let rgba = parse_rle(0, &data).unwrap();
+        let image = RgbaImage::try_from(&rgba.resources[0]).unwrap();
+        assert_eq!(image.dimensions(), (2, 1));
+        assert_eq!(image.get_pixel(0, 0).0, [0xFF, 0, 0, 0xFF]);
+        assert_eq!(image.get_pixel(1, 0).0, [0; 4]);
+        let r5g6b5 = parse_rle_as(0, &data, PixelFormat::R5g6b5).unwrap();
+        assert_eq!(r5g6b5.resources[0].to_rgba_image(PixelFormat::R5g6b5).unwrap(), image);
+        assert!(matches!(RgbaImage::try_from(&r5g6b5.resources[0]), Err(Error::UnencodableResource(0))));
+
+        let path = std::env::temp_dir().join(format!("novluno_png_{}.png", std::process::id()));
+        rgba.resources[0].save_png(&path).unwrap();
+        let saved = image::open(&path).unwrap().to_rgba8();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(saved, image);
+    }
+
+    #[test]
+    fn test_decode_into() {
+        let data = RleFixture::new()
+            .null()
+            .resource(ResourceFixture::new(2, 2).offset(3, 4).pixels(&[RED, BLUE]).next_line().skip(-1).pixels(&[RED]))
+            .resource(ResourceFixture::new(1, 1).pixels(&[BLUE, BLUE]))
+            .build();
+        let lazy = ResourceFile::open_lazy(5, &data[..]).unwrap();
+
+        // both into a 4x3 atlas, the first one at (1, 1), the second at (3, 0)
+        let stride = 4 * 4;
+        let mut atlas = vec![0u8; stride * 3];
+        let (first, warnings) = lazy.decode_into(1, &mut atlas[stride + 4..], stride).unwrap();
+        let first = first.unwrap();
+        assert_eq!((first.index(), first.offset_x, first.offset_y, first.width, first.height), (1, 3, 4, 2, 2));
+        assert!(first.image_raw.is_empty() && warnings.is_empty());
+        let (_, warnings) = lazy.decode_into(2, &mut atlas[12..], stride).unwrap();
+        assert_eq!(warnings.len(), 1);
+
+        let decoded = lazy.get(1).unwrap().unwrap().image_raw;
+        for y in 0..2 {
+            let start = (y + 1) * stride + 4;
+            assert_eq!(atlas[start..start + 8], decoded[y * 8..y * 8 + 8]);
+        }
+        assert_eq!(atlas[12..16], [0, 0, 0xFF, 0xFF]);
+        // the rest of the atlas is as it was
+        assert_eq!(atlas[..12], [0; 12]);
+        assert_eq!(atlas[stride..stride + 4], [0; 4]);
+
+        // nothing at a null offset or past the end
+        assert!(lazy.decode_into(0, &mut atlas, stride).unwrap().0.is_none());
+        assert!(lazy.decode_into(9, &mut atlas, stride).unwrap().0.is_none());
+        // the last row needs only the width
+        assert!(lazy.decode_into(1, &mut vec![0; stride + 8], stride).is_ok());
+        assert!(matches!(lazy.decode_into(1, &mut vec![0; stride + 7], stride),
+                         Err(Error::BufferTooSmall { file: 5, index: 1, needed: 24 })));
+        assert!(matches!(lazy.decode_into(1, &mut atlas, 4), Err(Error::BufferTooSmall { .. })));
+    }
+
+    #[test]
+    fn test_trim_resource() {
+        // a 4x4 sprite painted in its middle 2x2, and one not painted at all
+        let data = RleFixture::new()
+            .resource(ResourceFixture::new(4, 4).offset(10, -20)
+                .next_line().skip(1).pixels(&[RED])
+                .next_line().skip(-1).pixels(&[BLUE, RED]))
+            .resource(ResourceFixture::new(3, 2))
+            .build();
+        for &format in [PixelFormat::Rgba8, PixelFormat::Bgra8, PixelFormat::R5g6b5].iter() {
+            let file = parse_rle_as(0, &data, format).unwrap();
+            let trimmed = file.resources[0].trimmed(format).unwrap();
+            assert_eq!((trimmed.offset_x, trimmed.offset_y, trimmed.width, trimmed.height), (11, -19, 2, 2));
+            assert_eq!(trimmed.index(), 0);
+
+            // every pixel stays where it was drawn
+            let bpp = format.bytes_per_pixel();
+            let original = &file.resources[0];
+            for y in 0..2 {
+                for x in 0..2 {
+                    let at = (y * 2 + x) * bpp;
+                    let was = ((y + 1) * 4 + x + 1) * bpp;
+                    assert_eq!(trimmed.image_raw[at..at + bpp], original.image_raw[was..was + bpp]);
+                }
+            }
+
+            let empty = file.resources[1].trimmed(format).unwrap();
+            assert_eq!((empty.width, empty.height, empty.image_raw.len()), (0, 0, 0));
+        }
+        let mut broken = parse_rle(0, &data).unwrap().resources.remove(0);
+        broken.image_raw.pop();
+        assert!(matches!(broken.trim(PixelFormat::Rgba8), Err(Error::UnencodableResource(0))));
+    }
+
+    #[test]
+    fn test_resource_stats() {
+        // three pixels in the middle of a 4x4 sprite, and one not painted
+        let data = RleFixture::new()
+            .resource(ResourceFixture::new(4, 4)
+                .next_line().skip(1).pixels(&[RED])
+                .next_line().skip(-1).pixels(&[RED, BLUE]))
+            .resource(ResourceFixture::new(3, 2))
+            .build();
+        for &format in [PixelFormat::Rgba8, PixelFormat::Bgra8, PixelFormat::R5g6b5].iter() {
+            let file = parse_rle_as(0, &data, format).unwrap();
+            let stats = file.resources[0].stats(format).unwrap();
+            assert_eq!(stats.opaque_pixels, 3);
+            assert_eq!(stats.opaque_ratio, 3.0 / 16.0);
+            assert_eq!(stats.average_color, Some((170, 0, 85)));
+            assert_eq!(stats.bounds, Some((1, 1, 2, 2)));
+
+            let empty = file.resources[1].stats(format).unwrap();
+            assert_eq!((empty.opaque_pixels, empty.opaque_ratio), (0, 0.0));
+            assert_eq!((empty.average_color, empty.bounds), (None, None));
+        }
+        let mut broken = parse_rle(0, &data).unwrap().resources.remove(0);
+        broken.image_raw.pop();
+        assert!(matches!(broken.stats(PixelFormat::Rgba8), Err(Error::UnencodableResource(0))));
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let data = RleFixture::new()
+            .resource(ResourceFixture::new(2, 1).offset(5, 5).pixels(&[RED, BLUE]))
+            .null()
+            .resource(ResourceFixture::new(2, 1).pixels(&[RED, BLUE]))
+            .resource(ResourceFixture::new(1, 2).pixels(&[RED]).next_line().skip(-1).pixels(&[BLUE]))
+            .build();
+        let options = DecodeOptions { hash: true, ..DecodeOptions::default() };
+        let hashed = parse_rle_with(0, &data, options).unwrap();
+        let file = parse_rle(1, &data).unwrap();
+
+        // the same pixels anywhere; the same pixels in another shape aren't
+        let hashes: Vec<u64> = file.iter().map(|r| r.content_hash()).collect();
+        assert_eq!(hashes[0], hashes[1]);
+        assert_ne!(hashes[0], hashes[2]);
+        assert_eq!(hashed.iter().map(|r| r.content_hash()).collect::<Vec<_>>(), hashes);
+
+        // a converted image is hashed again
+        let mut resource = hashed.resources.into_iter().next().unwrap();
+        resource.convert(PixelFormat::Rgba8, PixelFormat::R5g6b5).unwrap();
+        assert_ne!(resource.content_hash(), hashes[0]);
+        assert_eq!(resource.content_hash(), parse_rle_as(0, &data, PixelFormat::R5g6b5).unwrap().resources[0].content_hash());
+    }
+
+    #[test]
+    fn test_parse_rle_with() {
+        let data = RleFixture::new()
+            .resource(ResourceFixture::new(2, 1).pixels(&[COLOR_KEY_565, RED]))
+            .build();
+        let options = DecodeOptions { magenta_is_transparent: true, ..DecodeOptions::default() };
+        let rle = parse_rle_with(7, &data, options).unwrap();
+        assert_eq!(rle.resources[0].image_raw, vec![0, 0, 0, 0, 0xFF, 0, 0, 0xFF]);
+
+        // painted as it is by default
+        let rle = parse_rle_with(7, &data, DecodeOptions::default()).unwrap();
+        assert_eq!(rle.resources[0].image_raw[..4], [0xFF, 0, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn test_parse_rle_null_offsets() {
+        let resource = || ResourceFixture::new(1, 1).pixels(&[RED]);
+        let data = RleFixture::new().null().resource(resource()).null().resource(resource()).build();
+        let rle = parse_rle(0, &data).unwrap();
+
+        let indices: Vec<u32> = rle.resources.iter().map(|r| r.index()).collect();
+        assert_eq!(indices, vec![1, 3]);
+        assert_eq!(rle.slots, vec![ResourceSlot::Empty, ResourceSlot::Resource(0),
+                                   ResourceSlot::Empty, ResourceSlot::Resource(1)]);
+        assert_eq!(rle.get(3).map(|r| r.index()), Some(3));
+        assert!(rle.get(2).is_none());
+        assert!(rle.get(4).is_none());
+    }
+
     #[test]
-    fn test_c0000000_rle() {
-        let data = include_bytes!("../../../data/RLEs/Chr/C00/c0000000.rle");
-        let rle = parse_rle(0, data).unwrap();
+    fn test_open_lazy() {
+        let resource = |color| ResourceFixture::new(1, 1).pixels(&[color]);
+        let data = RleFixture::new()
+            .null().resource(resource(RED)).resource(ResourceFixture::new(0, 0)).resource(resource(BLUE))
+            .build();
+        let eager = parse_rle(7, &data).unwrap();
+        let lazy = ResourceFile::open_lazy(7, &data[..]).unwrap();
+
+        assert_eq!(lazy.len(), 4);
+        assert!(!lazy.has(0) && lazy.has(2) && !lazy.has(4));
+        for index in 0..5 {
+            let resource = lazy.get(index).unwrap();
+            assert_eq!(resource.as_ref().map(|r| (r.index(), &r.image_raw)),
+                       eager.get(index).map(|r| (r.index(), &r.image_raw)));
+        }
     }
 
     #[test]
-    fn test_c0000042_rle() {
-        let data = include_bytes!("../../../data/RLEs/Chr/C00/c0000042.rle");
-        let rle = parse_rle(42, data).unwrap();
+    fn test_open_lazy_broken_resource() {
+        let resource = || ResourceFixture::new(1, 1).pixels(&[RED]);
+        let mut data = RleFixture::new().resource(resource()).resource(resource()).build();
+        // an unknown pixel run in place of the last end marker
+        let end = data.len() - 1;
+        data[end] = 0x07;
+        assert!(parse_rle(0, &data).is_err());
+
+        // only the broken resource fails, and only once it's asked for
+        let lazy = ResourceFile::open_lazy(0, data).unwrap();
+        assert!(lazy.get(0).unwrap().is_some());
+        assert!(lazy.get(1).is_err());
+        assert!(ResourceFile::open_lazy(0, &b"Resource"[..]).is_err());
+    }
+
+    #[test]
+    fn test_parse_rle_lenient() {
+        let resource = || ResourceFixture::new(1, 1).pixels(&[RED]);
+        let broken = ResourceFixture::new(4, 1).skip(-5).pixels(&[BLUE]);
+        let mut data = RleFixture::new().resource(resource()).resource(broken).resource(resource()).build();
+        // an unknown pixel run in place of the end marker of the middle one
+        let raw = raw_resources(&data).unwrap();
+        let offset = 14 + 4 + 4 + 3 * 4 + raw[0].unwrap().len();
+        let end = offset + raw[1].unwrap().len() - 1;
+        data[end] = 0x07;
+        assert!(matches!(parse_rle(0, &data), Err(Error::UnknownResourceRunAt { index: 1, .. })));
+
+        let rle = parse_rle_lenient(0, &data, PixelFormat::Rgba8).unwrap();
+        assert_eq!(rle.slots, vec![ResourceSlot::Resource(0), ResourceSlot::Undecoded, ResourceSlot::Resource(1)]);
+        assert_eq!(rle.get(2).map(|r| r.index()), Some(2));
+        assert_eq!(rle.failures.len(), 1);
+        assert_eq!((rle.failures[0].index, rle.failures[0].offset), (1, offset as u32));
+        assert!(matches!(rle.failures[0].reason, Error::UnknownResourceRunAt { at, .. } if at == end as u64));
+        // the warnings of the broken resource go with it
+        assert!(rle.warnings.is_empty());
+
+        // the file itself still has to be one
+        assert!(parse_rle_lenient(0, b"Resource", PixelFormat::Rgba8).is_err());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parse_rle_parallel() {
+        let mut fixture = RleFixture::new();
+        for index in 0..200 {
+            fixture = match index % 7 {
+                3 => fixture.null(),
+                _ => fixture.resource(ResourceFixture::new(index % 5 + 1, 2).skip(index % 3).pixels(&[RED, BLUE])),
+            };
+        }
+        let mut data = fixture.build();
+        let eager = parse_rle_as(4, &data, PixelFormat::R5g6b5).unwrap();
+        let parallel = parse_rle_parallel(4, &data, PixelFormat::R5g6b5).unwrap();
+        assert_eq!((parallel.file_number, &parallel.slots), (4, &eager.slots));
+        assert_eq!(parallel.warnings, eager.warnings);
+        let images = |file: &ResourceFile| -> Vec<(u32, Vec<u8>)> {
+            file.resources.iter().map(|r| (r.index(), r.image_raw.clone())).collect()
+        };
+        assert_eq!(images(&parallel), images(&eager));
+
+        // an unknown pixel run in place of the last end marker
+        let last = data.len() - 1;
+        data[last] = 0x07;
+        assert!(matches!(parse_rle_parallel(4, &data, PixelFormat::Rgba8),
+                         Err(Error::UnknownResourceRunAt { index: 198, .. })));
+    }
+
+    #[test]
+    fn test_parse_rle_skips() {
+        let data = RleFixture::new()
+            .resource(ResourceFixture::new(4, 1).skip(3).pixels(&[BLUE]))
+            .build();
+        let rle = parse_rle(0, &data).unwrap();
+
+        let alpha: Vec<u8> = rle.resources[0].image_raw.chunks(4).map(|px| px[3]).collect();
+        assert_eq!(alpha, vec![0, 0, 0, 0xFF]);
+    }
+
+    #[test]
+    fn test_parse_rle_empty_resources() {
+        let data = RleFixture::new()
+            .resource(ResourceFixture::new(2, 1))
+            .resource(ResourceFixture::new(0, 0))
+            .build();
+        let rle = parse_rle(0, &data).unwrap();
+
+        // a resource without pixel runs is fully transparent ...
+        assert_eq!(rle.resources[0].image_raw, vec![0; 8]);
+        // ... and one without a size isn't decoded at all, but keeps its index
+        assert_eq!(rle.resources.len(), 1);
+        assert_eq!(rle.slots, vec![ResourceSlot::Resource(0), ResourceSlot::Undecoded]);
+    }
+
+    #[test]
+    fn test_parse_rle_negative_moves() {
+        let data = RleFixture::new()
+            .resource(ResourceFixture::new(3, 2)
+                .skip(2).pixels(&[RED])        // (2, 0)
+                .skip(-3).pixels(&[BLUE])      // back to (0, 0)
+                .next_line().skip(1).pixels(&[RED])) // column kept: (2, 1)
+            .build();
+        let rle = parse_rle(0, &data).unwrap();
+
+        let colors: Vec<&[u8]> = rle.resources[0].image_raw.chunks(4).collect();
+        assert_eq!(colors[0], &[0, 0, 0xFF, 0xFF]);
+        assert_eq!(colors[2], &[0xFF, 0, 0, 0xFF]);
+        assert_eq!(colors[5], &[0xFF, 0, 0, 0xFF]);
+        assert!(rle.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_parse_rle_odd_moves() {
+        // moves are rounded toward zero, whichever the direction
+        let mut resource = ResourceFixture::new(4, 1).skip(2).encode();
+        let end = resource.len() - 1;
+        resource.truncate(end);
+        resource.extend_from_slice(&[0x02, 3, 0, 0, 0]);               // +1.5
+        resource.extend_from_slice(&[0x02, 0xFD, 0xFF, 0xFF, 0xFF]);   // -1.5
+        resource.extend_from_slice(&[0x01, 1, 0, 0, 0, 0x00, 0xF8, 0x00]);
+        let data = crate::writer::rle::write_raw_rle(&[Some(&resource)]);
+        let rle = parse_rle(0, &data).unwrap();
+
+        let alpha: Vec<u8> = rle.resources[0].image_raw.chunks(4).map(|px| px[3]).collect();
+        assert_eq!(alpha, vec![0, 0, 0xFF, 0]);
+        assert_eq!(rle.warnings, vec![
+            RleWarning::OddMove { index: 0, bytes: 3 },
+            RleWarning::OddMove { index: 0, bytes: -3 },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_rle_moves_out_of_bounds() {
+        let data = RleFixture::new()
+            .resource(ResourceFixture::new(2, 2)
+                .skip(-1).pixels(&[RED, BLUE])   // first pixel left of the image
+                .skip(1).pixels(&[RED])          // right of the image
+                .next_line().next_line().skip(-4).pixels(&[RED])) // below it
+            .build();
+        let rle = parse_rle(0, &data).unwrap();
+
+        let alpha: Vec<u8> = rle.resources[0].image_raw.chunks(4).map(|px| px[3]).collect();
+        assert_eq!(alpha, vec![0xFF, 0, 0, 0]);
+        assert_eq!(rle.warnings, vec![
+            RleWarning::PixelsOutOfBounds { index: 0, x: -1, y: 0, pixels: 1 },
+            RleWarning::PixelsOutOfBounds { index: 0, x: 2, y: 0, pixels: 1 },
+            RleWarning::PixelsOutOfBounds { index: 0, x: -1, y: 2, pixels: 1 },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_rle_strict() {
+        let strict = DecodeOptions { strict: true, ..DecodeOptions::default() };
+        let check = |resource: ResourceFixture| {
+            let data = RleFixture::new().resource(resource).build();
+            assert!(parse_rle(0, &data).is_ok());
+            match parse_rle_with(0, &data, strict) {
+                Err(Error::InvalidResource { file: 0, index: 0, at, violation }) => Some((at - 26, violation)),
+                Ok(_) => None,
+                Err(error) => panic!("{:?}", error),
+            }
+        };
+
+        assert_eq!(check(ResourceFixture::new(2, 2).pixels(&[RED, BLUE]).next_line().skip(-1).pixels(&[RED])), None);
+        assert_eq!(check(ResourceFixture::new(2, 1).skip(1).pixels(&[RED, RED])),
+                   Some((36 + 5, RleViolation::PixelsOutOfBounds { x: 1, y: 0, pixels: 1 })));
+        assert_eq!(check(ResourceFixture::new(2, 1).skip(3)),
+                   Some((36, RleViolation::MoveOutOfBounds { x: 3 })));
+        assert_eq!(check(ResourceFixture::new(1, 1).next_line().next_line()),
+                   Some((36 + 1, RleViolation::LineOutOfBounds { y: 2 })));
+
+        // the header's length of the runs
+        let mut data = RleFixture::new().resource(ResourceFixture::new(1, 1).pixels(&[RED])).build();
+        data[26..30].copy_from_slice(&99u32.to_le_bytes());
+        assert!(matches!(parse_rle_with(0, &data, strict), Err(Error::InvalidResource {
+            at: 26, violation: RleViolation::LengthMismatch { declared: 99, actual: 8 }, .. })));
     }
 
     #[test]
-    fn test_ico_00000_rle() {
-        let data = include_bytes!("../../../data/RLEs/Ico/ico00000.rle");
-        let rle = parse_rle(0, data).unwrap();
+    fn test_parse_rle_oversized() {
+        // wider than a texture on most GPUs, still decoded
+        let wide = MAX_RESOURCE_SIZE - 1;
+        let data = RleFixture::new()
+            .resource(ResourceFixture::new(wide, 1).skip(wide - 1).pixels(&[RED]))
+            .resource(ResourceFixture::new(9000, 1).skip(8999).pixels(&[RED]))
+            .build();
+        assert!(matches!(parse_rle(4, &data),
+                         Err(Error::OversizedResource { file: 4, index: 1, width: 9000, height: 1 })));
+        let rle = parse_rle_lenient(4, &data, PixelFormat::Rgba8).unwrap();
+        assert_eq!(rle.resources.len(), 1);
+        assert_eq!(&rle.resources[0].image_raw[(wide as usize - 1) * 4..], &[0xFF, 0, 0, 0xFF]);
+        assert_eq!(rle.slots[1], ResourceSlot::Undecoded);
+
+        // a broken header doesn't get its image allocated
+        let mut broken = RleFixture::new().resource(ResourceFixture::new(1, 1).pixels(&[RED])).build();
+        broken[38..42].copy_from_slice(&0x7FFFi32.to_le_bytes());
+        broken[42..46].copy_from_slice(&0x7FFFi32.to_le_bytes());
+        assert!(matches!(parse_rle(4, &broken), Err(Error::OversizedResource { width: 0x7FFF, height: 0x7FFF, .. })));
+
+        // the limit is up to the caller
+        let options = DecodeOptions { max_dimensions: (9000, 2), ..DecodeOptions::default() };
+        assert!(matches!(parse_rle_with(4, &data, options),
+                         Err(Error::OversizedResource { index: 1, width: 9000, .. })));
+        let options = DecodeOptions { max_dimensions: (9001, 2), ..DecodeOptions::default() };
+        let rle = parse_rle_with(4, &data[..], options).unwrap();
+        assert_eq!(rle.resources.len(), 2);
+        assert_eq!(&rle.resources[1].image_raw[8999 * 4..], &[0xFF, 0, 0, 0xFF]);
+    }
+
+    #[test]
+    fn test_parse_rle_missing_identifier() {
+        let mut data = RleFixture::new().build();
+        data[0] = b'r';
+        assert!(parse_rle(0, &data).is_err());
+        assert!(parse_rle(0, b"Resource").is_err());
+    }
+
+    #[test]
+    fn test_parse_rle_unknown_run_type() {
+        let mut data = RleFixture::new().resource(ResourceFixture::new(1, 1)).build();
+        let last = data.len() - 1;
+        data[last] = 0x07;
+        assert!(matches!(parse_rle(9, &data), Err(Error::UnknownResourceRunAt { file: 9, index: 0, at })
+                         if at == last as u64));
+        assert!(raw_resources(&data).is_err());
+    }
+
+    #[test]
+    fn test_parse_rle_truncated() {
+        let data = RleFixture::new().resource(ResourceFixture::new(2, 1).pixels(&[RED, BLUE])).build();
+        let offset = 14 + 4 + 4 + 4;
+        // in the pixel run, which starts after the 36 byte header
+        let cut = &data[..data.len() - 3];
+        assert!(matches!(parse_rle(9, cut), Err(Error::UnexpectedEndOfResource { file: 9, index: 0, at })
+                         if at == offset as u64 + 36));
+        // in the header
+        let cut = &data[..offset + 10];
+        assert!(matches!(parse_rle(9, cut), Err(Error::UnexpectedEndOfResource { at, .. }) if at == offset as u64));
+        // every length short of the whole file fails without a panic
+        for len in offset..data.len() {
+            assert!(parse_rle(9, &data[..len]).is_err(), "{}", len);
+        }
+    }
+
+    #[test]
+    fn test_parse_rle_truncated_table() {
+        let mut data = RleFixture::new()
+            .resource(ResourceFixture::new(1, 1).pixels(&[RED]))
+            .resource(ResourceFixture::new(2, 1).pixels(&[RED, BLUE]))
+            .build();
+        let end = data.len() as u64;
+        // five resources counted, but the first one starts after two offsets
+        data[18..22].copy_from_slice(&5u32.to_le_bytes());
+        data.extend_from_slice(&[0xAB; 5]);
+
+        let rle = parse_rle(4, &data).unwrap();
+        assert_eq!(rle.iter().map(|r| r.width).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(rle.warnings, vec![RleWarning::TruncatedOffsetTable { declared: 5, present: 2 },
+                                      RleWarning::TrailingData { at: end, len: 5 }]);
+
+        // a table running into the end of the file keeps the whole entries
+        let mut data = RleFixture::new().null().null().build();
+        data[18..22].copy_from_slice(&3u32.to_le_bytes());
+        data.push(0);
+        let rle = parse_rle(4, &data).unwrap();
+        assert_eq!(rle.slots.len(), 2);
+        assert_eq!(rle.warnings, vec![RleWarning::TruncatedOffsetTable { declared: 3, present: 2 },
+                                      RleWarning::TrailingData { at: 30, len: 1 }]);
+    }
+
+    #[test]
+    fn test_raw_resources() {
+        let resource = ResourceFixture::new(1, 1).pixels(&[RED]);
+        let encoded = resource.encode();
+        let data = RleFixture::new().null().resource(resource).build();
+        let raw = raw_resources(&data).unwrap();
+
+        assert_eq!(raw, vec![None, Some(&encoded[..])]);
+    }
+
+    #[test]
+    fn test_rle_resource_iter() {
+        let data = RleFixture::new()
+            .null()
+            .resource(ResourceFixture::new(1, 1).pixels(&[RED]))
+            .resource(ResourceFixture::new(0, 0))
+            .resource(ResourceFixture::new(2, 1).pixels(&[RED, RED]))
+            .build();
+        let indices: Vec<u32> = RleResourceIter::new(3, &data, PixelFormat::Rgba8).unwrap()
+            .map(|resource| resource.unwrap().index())
+            .collect();
+        assert_eq!(indices, vec![1, 3]);
+        let file = parse_rle(3, &data).unwrap();
+        assert_eq!(file.iter().map(|r| r.index()).collect::<Vec<_>>(), indices);
+        assert_eq!((&file).into_iter().count(), 2);
+        assert_eq!(file.into_iter().map(|r| r.width).collect::<Vec<_>>(), vec![1, 2]);
+
+        // the resources past the one asked for aren't decoded, the broken one
+        // ends the walk
+        let broken = &data[..data.len() - 3];
+        let mut resources = RleResourceIter::new(3, broken, PixelFormat::Rgba8).unwrap();
+        assert_eq!(resources.next().unwrap().unwrap().index(), 1);
+        assert!(resources.next().unwrap().is_err());
+        assert!(resources.next().is_none());
+        assert!(RleResourceIter::new(3, b"Resource", PixelFormat::Rgba8).is_err());
+    }
+
+    #[test]
+    fn test_scan_rle() {
+        let data = RleFixture::new()
+            .null()
+            .resource(ResourceFixture::new(2, 1).offset(3, -4).pixels(&[RED, RED]))
+            .resource(ResourceFixture::new(1, 5).pixels(&[RED]))
+            .build();
+        let metas = scan_rle(&data).unwrap();
+        let headers: Vec<_> = metas.iter().map(|m| (m.index, m.offset, m.len, m.offset_x, m.offset_y, m.width, m.height)).collect();
+        assert_eq!(headers, vec![(1, 34, 10, 3, -4, 2, 1), (2, 80, 8, 0, 0, 1, 5)]);
+
+        // the pixels aren't read at all
+        assert_eq!(scan_rle(&data[..80 + 36]).unwrap(), metas);
+        assert!(scan_rle(&data[..80 + 35]).is_err());
+        assert!(scan_rle(b"Resource").is_err());
+    }
+
+    #[test]
+    fn test_detect_version() {
+        let data = RleFixture::new().null().resource(ResourceFixture::new(2, 1).pixels(&[RED, RED])).build();
+        let standard = parse_rle(3, &data).unwrap();
+        assert_eq!(standard.version, RleVersion::Standard);
+
+        // the same resource behind the other two headers
+        let resource = raw_resources(&data).unwrap()[1].unwrap().to_vec();
+        let relayout = |header: &[u8]| {
+            let table_end = header.len() + 4 + 8;
+            let mut out = header.to_vec();
+            out.extend_from_slice(&2u32.to_le_bytes());
+            out.extend_from_slice(&0u32.to_le_bytes());
+            out.extend_from_slice(&(table_end as u32).to_le_bytes());
+            out.extend_from_slice(&resource);
+            out
+        };
+        let no_free_offset = relayout(b"Resource File\0");
+        let headerless = relayout(&((4 + 4 + 8 + resource.len()) as u32).to_le_bytes());
+        for &(ref data, version) in [(no_free_offset, RleVersion::NoFreeOffset), (headerless.clone(), RleVersion::Headerless)].iter() {
+            assert_eq!(detect_version(data).unwrap(), version);
+            let file = parse_rle(3, data).unwrap();
+            assert_eq!(file.version, version);
+            assert_eq!(file.slots, standard.slots);
+            assert_eq!(file.resources[0].image_raw, standard.resources[0].image_raw);
+            assert_eq!(scan_rle(data).unwrap().len(), 1);
+            assert_eq!(RleResourceIter::new(3, data, PixelFormat::Rgba8).unwrap().count(), 1);
+        }
+
+        let mut line_feed = data.clone();
+        line_feed[13] = b'\n';
+        assert_eq!(parse_rle(3, &line_feed).unwrap().version, RleVersion::Standard);
+        // without the identifier, only the length in the first field makes a header
+        let mut unknown = headerless.clone();
+        unknown[0] ^= 1;
+        match parse_rle(3, &unknown) {
+            Err(Error::MissingRleIdentifier) => (),
+            other => panic!("{:?}", other.map(|file| file.version)),
+        }
+    }
+
+    #[test]
+    fn test_header_extras() {
+        // a shadow 3 left of and 2 below the sprite, anchored at (1, 4)
+        let shadow = (-3i16 as u16 as u32) | 2 << 16;
+        let data = RleFixture::new()
+            .resource(ResourceFixture::new(2, 5).extras([shadow, 7, 1, 4]).pixels(&[RED]))
+            .resource(ResourceFixture::new(2, 5).extras([0x0100_0000, 0, 2, 4]).pixels(&[RED]))
+            .resource(ResourceFixture::new(1, 1).pixels(&[RED]))
+            .build();
+        let extras: Vec<_> = scan_rle(&data).unwrap().iter().map(|m| m.extras()).collect();
+        let decoded: Vec<_> = parse_rle(0, &data).unwrap().resources.iter().map(|r| r.extras()).collect();
+        assert_eq!(extras, decoded);
+        assert_eq!(extras[0], RawHeaderExtras { unknown_1: shadow, unknown_2: 7, unknown_3: 1, unknown_4: 4 });
+
+        assert_eq!(extras[0].shadow_offset(), Some((-3, 2)));
+        assert_eq!(extras[0].hotspot(2, 5), Some((1, 4)));
+        // too far for a shadow, outside of the sprite
+        assert_eq!(extras[1].shadow_offset(), None);
+        assert_eq!(extras[1].hotspot(2, 5), None);
+        assert!(extras[2].is_zero());
+        assert_eq!((extras[2].shadow_offset(), extras[2].hotspot(1, 1)), (None, None));
     }
 }
@@ -40,13 +40,7 @@
 //! [RMD Animation - Frame]
 //! int RMDRowPointer (points to a row of the RMD)
 
-use std::str::from_utf8;
-use std::io::Cursor;
-use std::io::Seek;
-use std::io::SeekFrom;
-
-use byteorder::ReadBytesExt;
-use byteorder::LittleEndian as LE;
+use core::str::from_utf8;
 
 use crate::entity::rmd::Rmd;
 use crate::entity::rmd_animation::RmdAnimation;
@@ -54,72 +48,76 @@ use crate::entity::rmd_entry::RmdEntry;
 use crate::entity::rmd_type::RmdType;
 use crate::entity::rmd_image::RmdImage;
 use crate::error::Error;
+use crate::utility::bin_reader::BinReader;
 use crate::utility::parsing::{parse_string, parse_cp949, parse_u8_vec};
 
 pub fn parse_rmd(kind: RmdType, data: &[u8]) -> Result<Rmd, Error> {
-    let mut cursor = Cursor::new(data);
+    let mut reader = BinReader::new(data);
     let mut rmd = Rmd::new(kind);
 
     // filetype string: Equal to ""
-    let string_1 = parse_string(&mut cursor)?;
+    let string_1 = parse_string(&mut reader)?;
     // println!("{:?}", string_1);
 
-    let file_number = cursor.read_u32::<LE>()?; // 4
+    let file_number = reader.read_u32()?; // 4
     // println!("file_number: {}", file_number);
 
     // 8 empty bytes
-    let padding = cursor.read_u32::<LE>()?; // 8
+    // there's nowhere to print to without std
+    let padding = reader.read_u32()?; // 8
+    #[cfg(feature = "std")]
     if padding != 0 { println!("p2: {}", padding); }
-    let padding = cursor.read_u32::<LE>()?; // 12
+    let padding = reader.read_u32()?; // 12
+    #[cfg(feature = "std")]
     if padding != 0 { println!("p3: {}", padding); }
 
-    // let string = parse_string(&mut cursor)?;
-    // let string = parse_u8_vec(&mut cursor)?;
-    let string = parse_cp949(&mut cursor)?;
+    // let string = parse_string(&mut reader)?;
+    // let string = parse_u8_vec(&mut reader)?;
+    let string = parse_cp949(&mut reader)?;
     // println!("str 1: `{:?}`", string);
 
-    rmd.set_animation_parts(cursor.read_i32::<LE>()?);
-    rmd.set_animation_entry_count(cursor.read_i32::<LE>()?);
+    rmd.set_animation_parts(reader.read_i32()?);
+    rmd.set_animation_entry_count(reader.read_i32()?);
 
-    // let string = parse_u8_vec(&mut cursor)?;
-    let string = parse_cp949(&mut cursor)?;
+    // let string = parse_u8_vec(&mut reader)?;
+    let string = parse_cp949(&mut reader)?;
     // println!("str 2: `{:?}`", string);
 
-    rmd.set_entry_count(cursor.read_i32::<LE>()?);
+    rmd.set_entry_count(reader.read_i32()?);
 
-    // println!("end header offset: `{}`", cursor.position());
+    // println!("end header offset: `{}`", reader.position());
 
     // read the Rmd rows
     for _ in 0..rmd.entry_count() {
         let mut entry = RmdEntry::new();
-        entry.set_image_count(cursor.read_i32::<LE>()?);
+        entry.set_image_count(reader.read_i32()?);
         for _ in 0..entry.image_count() {
             let mut img = RmdImage::new();
-            img.source_x1 = cursor.read_i32::<LE>()?;
-            img.source_y1 = cursor.read_i32::<LE>()?;
-            img.source_x2 = cursor.read_i32::<LE>()?;
-            img.source_y2 = cursor.read_i32::<LE>()?;
-            img.empty_1   = cursor.read_i32::<LE>()?;
-            img.empty_2   = cursor.read_i32::<LE>()?;
-            img.dest_x    = cursor.read_i32::<LE>()?;
-            img.dest_y    = cursor.read_i32::<LE>()?;
-            img.render_z  = cursor.read_i32::<LE>()?;
-            img.draw_type = cursor.read_i32::<LE>()?;
-            img.image_id_count = cursor.read_i32::<LE>()?;
+            img.source_x1 = reader.read_i32()?;
+            img.source_y1 = reader.read_i32()?;
+            img.source_x2 = reader.read_i32()?;
+            img.source_y2 = reader.read_i32()?;
+            img.empty_1   = reader.read_i32()?;
+            img.empty_2   = reader.read_i32()?;
+            img.dest_x    = reader.read_i32()?;
+            img.dest_y    = reader.read_i32()?;
+            img.render_z  = reader.read_i32()?;
+            img.draw_type = reader.read_i32()?;
+            img.image_id_count = reader.read_i32()?;
             for _ in 0..img.image_id_count {
-                img.image_id.push(cursor.read_i32::<LE>()?);
+                img.image_id.push(reader.read_i32()?);
             }
             entry.add_image(img);
         }
         rmd.add_entry(entry);
     }
 
-    rmd.set_animation_count(cursor.read_i32::<LE>()?);
+    rmd.set_animation_count(reader.read_i32()?);
 
     for _ in 0..rmd.animation_count() {
-        let mut ani = RmdAnimation::new(cursor.read_i32::<LE>()?);
+        let mut ani = RmdAnimation::new(reader.read_i32()?);
         for _ in 0..ani.frame_count() {
-            ani.add_frame(cursor.read_i16::<LE>()?);
+            ani.add_frame(reader.read_i16()?);
         }
         rmd.add_animation(ani);
     }
@@ -128,47 +126,33 @@ pub fn parse_rmd(kind: RmdType, data: &[u8]) -> Result<Rmd, Error> {
     Ok(rmd)
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
 
-    fn print_ani_info(rmd: &Rmd) {
-        println!();
-        println!("rmd.animation_count: {:?}", &rmd.animation_count());
-        println!("rmd.image_count:     {:?}", &rmd.entry_count());
-        assert!(false);
-    }
-
-    #[test]
-    fn test_tle_00001() {
-        let data = include_bytes!("../../../data/DATAs/Tle/tle00001.rmd");
-        let rmd = parse_rmd(RmdType::Tile, data).unwrap();
-        // assert!(rmd.row_count() as usize == rmd.rows.len());
-        // assert!(rmd.animation_count() as usize == rmd.animations.len());
-    }
-
-    #[test]
-    fn test_obj_00001() {
-        let data = include_bytes!("../../../data/DATAs/Obj/obj00001.rmd");
-        let rmd = parse_rmd(RmdType::Object, data).unwrap();
-        // assert!(rmd.row_count as usize == rmd.rows.len());
-        // assert!(rmd.animation_count as usize == rmd.animations.len());
-    }
+    use crate::fixture::RmdFixture;
 
     #[test]
-    fn test_chr_00001() {
-        let data = include_bytes!("../../../data/DATAs/Chr/chr00001.rmd");
-        let rmd = parse_rmd(RmdType::Character, data).unwrap();
-        // print_ani_info(&rmd);
-        // assert!(rmd.row_count as usize == rmd.rows.len());
-        // assert!(rmd.animation_count as usize == rmd.animations.len());
+    fn test_rmd() {
+        let data = RmdFixture::new()
+            .entry(&[&[1], &[2, 3]])
+            .entry(&[])
+            .animation(&[0, 1, 0])
+            .build();
+        let rmd = parse_rmd(RmdType::Object, &data).unwrap();
+
+        assert_eq!(rmd.entry_count(), 2);
+        let entry = rmd.get_entry(0).unwrap();
+        assert_eq!(entry.image_count(), 2);
+        assert_eq!(entry.images()[1].image_id, vec![2, 3]);
+        assert!(rmd.get_entry(1).unwrap().images().is_empty());
+        assert_eq!(rmd.animation_count(), 1);
+        assert_eq!(rmd.animations()[0].frame_count(), 3);
     }
 
     #[test]
-    fn test_chr_00042() {
-        let data = include_bytes!("../../../data/DATAs/Chr/chr00042.rmd");
-        let rmd = parse_rmd(RmdType::Character, data).unwrap();
-        // assert!(rmd.row_count as usize == rmd.rows.len());
-        // assert!(rmd.animation_count as usize == rmd.animations.len());
+    fn test_rmd_truncated() {
+        let data = RmdFixture::new().entry(&[&[1]]).build();
+        assert!(parse_rmd(RmdType::Tile, &data[..data.len() - 6]).is_err());
     }
 }
@@ -1,86 +1,83 @@
 //! "RedMoon EventInfo File 1.0"
 
 use std::str::from_utf8;
-use std::io::Cursor;
-
-use byteorder::ReadBytesExt;
-use byteorder::LittleEndian as LE;
 
 use crate::entity::rmi::Rmi;
 use crate::error::Error;
+use crate::utility::bin_reader::BinReader;
 use crate::utility::parsing::{parse_string, parse_cp949, parse_u8_vec};
 
 const ITEM_INFO_HDR: &str = "RedMoon ItemInfo File 1.0";
 const EVENT_INFO_HDR: &str = "RedMoon EventInfo File 1.0";
 
 pub fn parse_rmi(data: &[u8]) -> Result<Rmi, Error> {
-    let mut cursor = Cursor::new(data);
+    let mut reader = BinReader::new(data);
     let rmi = Rmi::new();
 
     // -- header
-    let file_type_string = parse_string(&mut cursor).unwrap();
+    let file_type_string = parse_string(&mut reader)?;
     println!("file_type_string: {:?}", file_type_string);
 
-    let count = cursor.read_i32::<LE>()?;
+    let count = reader.read_i32()?;
     println!("count?: {:?}", count);
 
     for idx in 0..count {
-        let _ = parse_event_entry(&mut cursor, idx).unwrap();
+        parse_event_entry(&mut reader, idx)?;
     }
 
     Ok(rmi)
 }
 
 fn parse_event_entry(
-    cursor: &mut Cursor<&[u8]>,
+    reader: &mut BinReader,
     idx: i32)
     -> Result<(), Error>
 {
     println!("-- Entry {}", idx);
-    println!("-- Cursor Start @ 0x{:x}", cursor.position());
+    println!("-- Cursor Start @ 0x{:x}", reader.position());
 
-    let event_type = cursor.read_u16::<LE>()?;
+    let event_type = reader.read_u16()?;
     println!("event_type: {:?}", event_type);
     if event_type != 0x44 || event_type != 0x60EA {
         println!("unknown event type {:?}", event_type);
     }
 
-    let event_unknown = cursor.read_i32::<LE>()?;
+    let event_unknown = reader.read_i32()?;
     println!("event_unknown: {:?}", event_unknown);
 
-    let event_count = cursor.read_i32::<LE>()?;
+    let event_count = reader.read_i32()?;
     println!("event_count: {:?}", event_count);
 
     for e_idx in 0..event_count {
         println!("{{");
         println!("    idx: {:?}", e_idx);
 
-        let action_timeout = cursor.read_i32::<LE>()?;
+        let action_timeout = reader.read_i32()?;
         println!("    action_timout: {:?}", action_timeout);
 
-        let trigger_string = parse_cp949(cursor).unwrap();
+        let trigger_string = parse_cp949(reader)?;
         println!("    trigger_string: {:?}", trigger_string);
 
-        let pos = cursor.position();
-        let byte = cursor.read_u8()?;
+        let pos = reader.position();
+        let byte = reader.read_u8()?;
         if byte != 0 {
-            cursor.set_position(pos);
+            reader.set_position(pos);
             println!("    -- byte value 0x{:x} @ 0x{:x}", byte, pos);
         }
 
         let mut cont = true;
         while cont {
-            let action_string = parse_cp949(cursor).unwrap();
+            let action_string = parse_cp949(reader)?;
             println!("    action_string: {:?}", action_string);
-            let pos = cursor.position();
-            let byte = cursor.read_u8()?;
+            let pos = reader.position();
+            let byte = reader.read_u8()?;
             println!("    -- byte value 0x{:x} @ 0x{:x}", byte, pos);
             if byte <= 1
             || byte == 0x44
             || byte == 0x60 {
                 cont = false;
             }
-            cursor.set_position(pos);
+            reader.set_position(pos);
         }
 
         println!("}}");
@@ -93,10 +90,24 @@ fn parse_event_entry(
 mod tests {
     use super::*;
 
+    use byteorder::{LittleEndian as LE, WriteBytesExt};
+
     #[test]
-    fn test_rmi_event00() {
-        let data = include_bytes!("../../../data/DATAs/Info/event00.rmi");
-        let rmi = parse_rmi(data);
+    fn test_rmi_event() {
+        let mut data = Vec::new();
+        data.push(EVENT_INFO_HDR.len() as u8);
+        data.extend_from_slice(EVENT_INFO_HDR.as_bytes());
+        data.write_i32::<LE>(1).unwrap();       // entry count
+        data.write_u16::<LE>(0x44).unwrap();    // event type
+        data.write_i32::<LE>(0).unwrap();
+        data.write_i32::<LE>(1).unwrap();       // event count
+        data.write_i32::<LE>(100).unwrap();     // action timeout
+        data.push(4);
+        data.extend_from_slice(b"talk");        // trigger
+        data.push(4);
+        data.extend_from_slice(b"warp");        // action
+        data.push(0);
+        let rmi = parse_rmi(&data);
         rmi.unwrap();
     }
 }
@@ -27,32 +27,21 @@
 //!            left top collision, right bottom collision)
 
 
-use std::str::from_utf8;
-use std::io::Cursor;
-use std::io::Seek;
-use std::io::SeekFrom;
-
-use byteorder::ReadBytesExt;
-use byteorder::LittleEndian as LE;
+use core::str::from_utf8;
 
 use crate::error::Error;
 use crate::entity::map::Map;
 use crate::entity::map_tile::MapTile;
 use crate::entity::event::Event;
 use crate::entity::entry::Entry;
+use crate::utility::bin_reader::BinReader;
 
 pub fn parse_rmm(data: &[u8]) -> Result<Map, Error> {
-    let mut cursor = Cursor::new(data);
+    let mut reader = BinReader::new(data);
     let mut map = Map::new();
 
     // filetype string: needs to equal "Redmoon MapData 1.0"
-    let string_length = cursor.read_u8()?;
-    let mut string = Vec::<u8>::new();
-    for _ in 0..string_length {
-        let chr = cursor.read_u8()?;
-        string.push(chr);
-    }
-    let file_type: &str = from_utf8(&string)?;
+    let file_type: &str = from_utf8(reader.read_prefixed()?)?;
 
     if file_type != "RedMoon MapData 1.0" {
         // println!("{:?}", file_type);
@@ -60,28 +49,28 @@ pub fn parse_rmm(data: &[u8]) -> Result<Map, Error> {
     }
 
     // map size (x, y) in number of tiles
-    map.set_size_x(cursor.read_u32::<LE>()?);
-    map.set_size_y(cursor.read_u32::<LE>()?);
+    map.set_size_x(reader.read_u32()?);
+    map.set_size_y(reader.read_u32()?);
 
     // Map String (name?)
-    map.set_id_count(cursor.read_u8()?);
+    map.set_id_count(reader.read_u8()?);
     for idx in 0..(map.id_count()) {
-        map.add_id_list_val(cursor.read_u8()?);
+        map.add_id_list_val(reader.read_u8()?);
     }
 
     // the map number described by this file...
-    map.set_map_number(cursor.read_u32::<LE>()?);
-    map.set_event_count(cursor.read_u32::<LE>()?);
+    map.set_map_number(reader.read_u32()?);
+    map.set_event_count(reader.read_u32()?);
 
     // NOTE: This is an array of event rectangles for interactions with
     //       things like mailboxes and the like
     for _ in 0..map.event_count() {
         let event = Event {
-            number: cursor.read_u16::<LE>()?,
-            left: cursor.read_u32::<LE>()?,
-            top: cursor.read_u32::<LE>()?,
-            right: cursor.read_u32::<LE>()?,
-            bottom: cursor.read_u32::<LE>()?,
+            number: reader.read_u16()?,
+            left: reader.read_u32()?,
+            top: reader.read_u32()?,
+            right: reader.read_u32()?,
+            bottom: reader.read_u32()?,
         };
         if event.number != 0 {
             map.add_event(event);
@@ -89,26 +78,30 @@ pub fn parse_rmm(data: &[u8]) -> Result<Map, Error> {
     }
 
     // read in the tile values...
-    let count = map.size_x() * map.size_y();
+    let count = map.size_x() as u64 * map.size_y() as u64;
     for tile in 0..count {
-        let tile = parse_v1(&mut cursor)?;
+        let tile = parse_v1(&mut reader)?;
         map.add_tile(tile);
     }
 
     Ok(map)
 }
 
-fn parse_v1(cursor: &mut Cursor<&[u8]>) -> Result<MapTile, Error> {
-    let b_0: u32 = cursor.read_u8()? as u32;
-    let b_1: u32 = cursor.read_u8()? as u32;
-    let b_2: u32 = cursor.read_u8()? as u32;
-    let b_3: u32 = cursor.read_u8()? as u32;
-    let b_4: u32 = cursor.read_u8()? as u32;
-    let b_5: u32 = cursor.read_u8()? as u32;
-    let b_6: u32 = cursor.read_u8()? as u32;
-    let b_7: u32 = cursor.read_u8()? as u32;
-
-    assert_eq!(b_0 & 0x2, 0);
+fn parse_v1(reader: &mut BinReader) -> Result<MapTile, Error> {
+    let position = reader.position();
+    let b_0: u32 = reader.read_u8()? as u32;
+    let b_1: u32 = reader.read_u8()? as u32;
+    let b_2: u32 = reader.read_u8()? as u32;
+    let b_3: u32 = reader.read_u8()? as u32;
+    let b_4: u32 = reader.read_u8()? as u32;
+    let b_5: u32 = reader.read_u8()? as u32;
+    let b_6: u32 = reader.read_u8()? as u32;
+    let b_7: u32 = reader.read_u8()? as u32;
+
+    // the bit between the collision flag and the object file is never set
+    if b_0 & 0x2 != 0 {
+        return Err(Error::InvalidMapTileAt(position));
+    }
 
     let obj_file_num = (b_0 / 4) + (b_1 % 32) * 64;
     let tle_file_idx = ((b_2 % 128) * 8) + (b_1 / 32);
@@ -132,15 +125,15 @@ fn parse_v1(cursor: &mut Cursor<&[u8]>) -> Result<MapTile, Error> {
 }
 
 // NOTE: This was a test to see if pulling out the bit fields could be made a little better
-fn parse_v2(cursor: &mut Cursor<&[u8]>) -> Result<MapTile, Error> {
-    let b_0: u32 = cursor.read_u8()? as u32;
-    let b_1: u32 = cursor.read_u8()? as u32;
-    let b_2: u32 = cursor.read_u8()? as u32;
-    let b_3: u32 = cursor.read_u8()? as u32;
-    let b_4: u32 = cursor.read_u8()? as u32;
-    let b_5: u32 = cursor.read_u8()? as u32;
-    let b_6: u32 = cursor.read_u8()? as u32;
-    let b_7: u32 = cursor.read_u8()? as u32;
+fn parse_v2(reader: &mut BinReader) -> Result<MapTile, Error> {
+    let b_0: u32 = reader.read_u8()? as u32;
+    let b_1: u32 = reader.read_u8()? as u32;
+    let b_2: u32 = reader.read_u8()? as u32;
+    let b_3: u32 = reader.read_u8()? as u32;
+    let b_4: u32 = reader.read_u8()? as u32;
+    let b_5: u32 = reader.read_u8()? as u32;
+    let b_6: u32 = reader.read_u8()? as u32;
+    let b_7: u32 = reader.read_u8()? as u32;
 
     assert_eq!(b_0 & 0x2, 0);
 
@@ -166,28 +159,42 @@ fn parse_v2(cursor: &mut Cursor<&[u8]>) -> Result<MapTile, Error> {
     Ok(tile)
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
 
+    use crate::fixture::RmmFixture;
+
     #[test]
-    fn test_map00001_rmm() {
-        let data = include_bytes!("../../../data/DATAs/Map/Map00001.rmm");
-        let map = parse_rmm(data).unwrap();
+    fn test_rmm() {
+        let data = RmmFixture::new(5, 3, 2)
+            .event(0, [0; 4])
+            .event(7, [1, 2, 3, 4])
+            .tile(2, 1, Entry::new(12, 5), Entry::new(3, 40), 16, 1)
+            .build();
+        let map = parse_rmm(&data).unwrap();
+
+        assert_eq!(map.number(), 5);
         assert_eq!((map.size_x() * map.size_y()) as usize, map.tile_count());
+        // events with number 0 are skipped
+        assert_eq!(map.events().len(), 1);
+        let tile = &map.tiles()[5];
+        assert_eq!(tile.obj_rmd_entry, Entry::new(12, 5));
+        assert_eq!(tile.tle_rmd_entry, Entry::new(3, 40));
+        assert_eq!((tile.warp, tile.collision), (16, 1));
+        assert_eq!(map.tiles()[0].obj_rmd_entry, Entry::new(0, 0));
     }
 
     #[test]
-    fn test_map00005_rmm() {
-        let data = include_bytes!("../../../data/DATAs/Map/Map00005.rmm");
-        let map = parse_rmm(data).unwrap();
-        assert_eq!((map.size_x() * map.size_y()) as usize, map.tile_count());
+    fn test_rmm_missing_identifier() {
+        let mut data = RmmFixture::new(1, 1, 1).build();
+        data[1] = b'r';
+        assert!(parse_rmm(&data).is_err());
     }
 
     #[test]
-    fn test_map00003_rmm() {
-        let data = include_bytes!("../../../data/DATAs/Map/Map00003.rmm");
-        let map = parse_rmm(data).unwrap();
-        assert_eq!((map.size_x() * map.size_y()) as usize, map.tile_count());
+    fn test_rmm_truncated() {
+        let data = RmmFixture::new(1, 2, 2).build();
+        assert!(parse_rmm(&data[..data.len() - 1]).is_err());
     }
 }
@@ -0,0 +1,121 @@
+//! Sound files in `RLEs/Snd`
+//!
+//! The container around the sounds isn't worked out yet. The parser assumes
+//! the sounds are stored as plain RIFF WAVE data and finds them by scanning
+//! for RIFF headers; the n-th wave found is taken as resource n of the file,
+//! which is what the entries of `snd.lst` point at.
+
+use byteorder::ByteOrder;
+use byteorder::LittleEndian as LE;
+
+#[derive(Debug, PartialEq)]
+pub struct Wave<'a> {
+    /// The complete RIFF data, playable as it is
+    pub data: &'a [u8],
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub bits_per_sample: u16,
+    /// Length of the samples in bytes
+    pub sample_bytes: u32,
+}
+
+impl<'a> Wave<'a> {
+    pub fn duration_ms(&self) -> u64 {
+        let bytes_per_second = self.sample_rate as u64 * self.channels as u64 * (self.bits_per_sample as u64 / 8);
+        if bytes_per_second == 0 {
+            return 0;
+        }
+        self.sample_bytes as u64 * 1000 / bytes_per_second
+    }
+}
+
+/// Finds the waves in a sound file, in file order. Truncated or malformed
+/// waves are skipped.
+pub fn parse_snd(data: &[u8]) -> Vec<Wave<'_>> {
+    let mut waves = Vec::new();
+    let mut offset = 0;
+    while offset + 12 <= data.len() {
+        match parse_wave(&data[offset..]) {
+            Some(wave) => {
+                offset += wave.data.len();
+                waves.push(wave);
+            }
+            None => offset += 1,
+        }
+    }
+    waves
+}
+
+/// Parses the wave at the start of `data`
+pub fn parse_wave(data: &[u8]) -> Option<Wave<'_>> {
+    if data.get(0..4)? != b"RIFF" || data.get(8..12)? != b"WAVE" {
+        return None;
+    }
+    let end = 8 + LE::read_u32(&data[4..8]) as usize;
+    let data = data.get(..end)?;
+
+    let mut format = None;
+    let mut sample_bytes = None;
+    let mut chunk = 12;
+    while chunk + 8 <= data.len() {
+        let size = LE::read_u32(&data[chunk + 4..chunk + 8]) as usize;
+        let body = data.get(chunk + 8..chunk + 8 + size)?;
+        match &data[chunk..chunk + 4] {
+            b"fmt " if size >= 16 => format = Some((
+                LE::read_u16(&body[2..4]),
+                LE::read_u32(&body[4..8]),
+                LE::read_u16(&body[14..16]),
+            )),
+            b"data" => sample_bytes = Some(size as u32),
+            _ => (),
+        }
+        // chunks are padded to an even length
+        chunk += 8 + size + (size & 1);
+    }
+
+    let (channels, sample_rate, bits_per_sample) = format?;
+    Some(Wave { data, channels, sample_rate, bits_per_sample, sample_bytes: sample_bytes? })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wave(samples: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"RIFF");
+        data.extend_from_slice(&(4 + 24 + 8 + samples.len() as u32).to_le_bytes());
+        data.extend_from_slice(b"WAVEfmt ");
+        data.extend_from_slice(&16u32.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&22050u32.to_le_bytes());
+        data.extend_from_slice(&22050u32.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&8u16.to_le_bytes());
+        data.extend_from_slice(b"data");
+        data.extend_from_slice(&(samples.len() as u32).to_le_bytes());
+        data.extend_from_slice(samples);
+        data
+    }
+
+    #[test]
+    fn test_parse_snd() {
+        let first = wave(&[0x80; 2205]);
+        let second = wave(&[0x80; 22050]);
+        let mut data = vec![1, 2, 3, 4];
+        data.extend_from_slice(&first);
+        data.extend_from_slice(b"junk");
+        data.extend_from_slice(&second);
+        // a truncated wave at the end isn't a sound
+        data.extend_from_slice(&first[..40]);
+
+        let waves = parse_snd(&data);
+        assert_eq!(waves.len(), 2);
+        assert_eq!(waves[0].data, &first[..]);
+        assert_eq!(waves[1].data, &second[..]);
+        assert_eq!((waves[0].channels, waves[0].sample_rate, waves[0].bits_per_sample), (1, 22050, 8));
+        assert_eq!(waves[0].duration_ms(), 100);
+        assert_eq!(waves[1].duration_ms(), 1000);
+    }
+}
@@ -0,0 +1,189 @@
+//! Packs decoded sprites into texture atlas pages, so a renderer can draw
+//! many of them from one texture.
+//!
+//! Under bilinear filtering and mipmapping the texels next to a sprite leak
+//! into its edges. Every sprite is therefore surrounded by `extrude` pixels
+//! repeating its outermost ones and then by `padding` transparent pixels,
+//! which the UV rects of the sprites leave out.
+//!
+//! The sprites go on shelves, the tallest first, and a page is filled before
+//! the next one is started. Pages are `max_size` wide and as tall as their
+//! shelves.
+
+/// How the atlas is packed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AtlasConfig {
+    /// Width and most height of a page
+    pub max_size: u32,
+    /// Transparent pixels around every sprite, outside of the extrusion
+    pub padding: u32,
+    /// Pixels the edges of every sprite are repeated for
+    pub extrude: u32,
+}
+
+impl Default for AtlasConfig {
+    /// 2048 pixel pages, the edges extruded by a pixel and padded by another
+    fn default() -> AtlasConfig {
+        AtlasConfig { max_size: 2048, padding: 1, extrude: 1 }
+    }
+}
+
+/// Where a sprite ended up
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Placement {
+    pub page: usize,
+    /// The sprite itself in the page, without its extrusion and padding
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// The same rect in texture coordinates: left, top, right, bottom
+    pub uv: [f32; 4],
+}
+
+/// A RGBA8 page of the atlas
+#[derive(Debug, Clone, PartialEq)]
+pub struct AtlasPage {
+    pub width: u32,
+    pub height: u32,
+    pub image_raw: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Atlas {
+    pub pages: Vec<AtlasPage>,
+    /// By sprite, in the order they were given; `None` for the sprites too
+    /// big for a page, which have to be diced first (see `utility::dice`)
+    pub placements: Vec<Option<Placement>>,
+}
+
+/// Packs RGBA8 sprites, given as `(width, height, pixels)`
+pub fn pack(sprites: &[(u32, u32, &[u8])], config: &AtlasConfig) -> Atlas {
+    for &(width, height, image_raw) in sprites.iter() {
+        assert_eq!(image_raw.len(), (width * height * 4) as usize);
+    }
+    let border = config.extrude + config.padding;
+    let cell = |width: u32, height: u32| (width + 2 * border, height + 2 * border);
+
+    let mut order: Vec<usize> = (0..sprites.len()).collect();
+    order.sort_by_key(|&index| std::cmp::Reverse(sprites[index].1));
+
+    // the cells of every page: where they are and which sprite they hold
+    let mut pages: Vec<Vec<(u32, u32, usize)>> = Vec::new();
+    let mut heights: Vec<u32> = Vec::new();
+    let (mut shelf_x, mut shelf_y, mut shelf_height) = (0, 0, 0);
+    let mut placements = vec![None; sprites.len()];
+    for index in order {
+        let (width, height) = cell(sprites[index].0, sprites[index].1);
+        if width > config.max_size || height > config.max_size || sprites[index].0 == 0 || sprites[index].1 == 0 {
+            continue;
+        }
+        if pages.is_empty() || shelf_x + width > config.max_size {
+            // the next shelf
+            shelf_y += shelf_height;
+            shelf_x = 0;
+            shelf_height = height;
+        }
+        if pages.is_empty() || shelf_y + height > config.max_size {
+            pages.push(Vec::new());
+            heights.push(0);
+            shelf_x = 0;
+            shelf_y = 0;
+            shelf_height = height;
+        }
+        let page = pages.len() - 1;
+        pages[page].push((shelf_x, shelf_y, index));
+        heights[page] = heights[page].max(shelf_y + height);
+        placements[index] = Some(Placement {
+            page,
+            x: shelf_x + border,
+            y: shelf_y + border,
+            width: sprites[index].0,
+            height: sprites[index].1,
+            uv: [0.0; 4],
+        });
+        shelf_x += width;
+    }
+
+    let mut atlas = Atlas { pages: Vec::new(), placements };
+    for (page, cells) in pages.iter().enumerate() {
+        let (page_width, page_height) = (config.max_size, heights[page]);
+        let mut image_raw = vec![0; (page_width * page_height * 4) as usize];
+        for &(_, _, index) in cells.iter() {
+            let (width, height, pixels) = sprites[index];
+            let placement = atlas.placements[index].as_mut().unwrap();
+            // the sprite and its extrusion, each pixel outside of the
+            // sprite a copy of the nearest one in it
+            let extrude = config.extrude as i64;
+            for y in -extrude..height as i64 + extrude {
+                for x in -extrude..width as i64 + extrude {
+                    let from_x = x.clamp(0, width as i64 - 1) as u32;
+                    let from_y = y.clamp(0, height as i64 - 1) as u32;
+                    let from = ((from_y * width + from_x) * 4) as usize;
+                    let to_x = (placement.x as i64 + x) as u32;
+                    let to_y = (placement.y as i64 + y) as u32;
+                    let to = ((to_y * page_width + to_x) * 4) as usize;
+                    image_raw[to..to + 4].copy_from_slice(&pixels[from..from + 4]);
+                }
+            }
+            placement.uv = [
+                placement.x as f32 / page_width as f32,
+                placement.y as f32 / page_height as f32,
+                (placement.x + width) as f32 / page_width as f32,
+                (placement.y + height) as f32 / page_height as f32,
+            ];
+        }
+        atlas.pages.push(AtlasPage { width: page_width, height: page_height, image_raw });
+    }
+    atlas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every pixel holds its own coordinates and the sprite it's from
+    fn image(width: u32, height: u32, sprite: u8) -> Vec<u8> {
+        let mut raw = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                raw.extend_from_slice(&[x as u8, y as u8, sprite, 0xFF]);
+            }
+        }
+        raw
+    }
+
+    fn pixel(page: &AtlasPage, x: u32, y: u32) -> &[u8] {
+        let at = ((y * page.width + x) * 4) as usize;
+        &page.image_raw[at..at + 4]
+    }
+
+    #[test]
+    fn test_pack() {
+        let (small, tall, wide) = (image(2, 2, 1), image(3, 4, 2), image(5, 1, 3));
+        let sprites = [(2, 2, &small[..]), (3, 4, &tall[..]), (5, 1, &wide[..]), (0, 0, &[][..]), (20, 1, &[0; 80][..])];
+        let config = AtlasConfig { max_size: 16, padding: 1, extrude: 2 };
+        let atlas = pack(&sprites, &config);
+
+        // the tallest first, a border of three pixels around each; the small
+        // one fits neither next to nor below the tall one
+        let rects: Vec<_> = atlas.placements.iter().map(|p| p.map(|p| (p.page, p.x, p.y, p.width, p.height))).collect();
+        assert_eq!(rects, vec![Some((1, 3, 3, 2, 2)), Some((0, 3, 3, 3, 4)), Some((1, 3, 11, 5, 1)), None, None]);
+        let sizes: Vec<_> = atlas.pages.iter().map(|page| (page.width, page.height)).collect();
+        assert_eq!(sizes, vec![(16, 10), (16, 15)]);
+        assert_eq!(atlas.placements[1].unwrap().uv, [3.0 / 16.0, 3.0 / 10.0, 6.0 / 16.0, 7.0 / 10.0]);
+
+        let page = &atlas.pages[0];
+        // the sprite in place, the edges extruded, the corners too
+        assert_eq!(pixel(page, 4, 5), &[1, 2, 2, 0xFF]);
+        assert_eq!(pixel(page, 1, 5), &[0, 2, 2, 0xFF]);
+        assert_eq!(pixel(page, 7, 8), &[2, 3, 2, 0xFF]);
+        assert_eq!(pixel(page, 1, 1), &[0, 0, 2, 0xFF]);
+        // and the padding left transparent
+        assert_eq!(pixel(page, 0, 5), &[0, 0, 0, 0]);
+        assert_eq!(pixel(page, 8, 5), &[0, 0, 0, 0]);
+        assert_eq!(pixel(page, 4, 9), &[0, 0, 0, 0]);
+        assert_eq!(pixel(&atlas.pages[1], 3, 3), &[0, 0, 1, 0xFF]);
+        assert_eq!(pixel(&atlas.pages[1], 7, 11), &[4, 0, 3, 0xFF]);
+    }
+}
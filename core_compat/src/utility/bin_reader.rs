@@ -0,0 +1,175 @@
+//! Reads the fields of the binary formats out of a byte slice. Every read is
+//! bounds checked and fails with `Error::UnexpectedEnd`, which says where the
+//! data ran out, rather than with a bare io error. The byte order is a type
+//! parameter; the game's files are all little endian, which `new` reads.
+//!
+//! A reader can also be given a window of a file, the bytes from some
+//! position on, and reads it with the positions of the whole file; so the
+//! parsers can decode a part of a file read from a stream and still say
+//! where in the file things are.
+
+use core::marker::PhantomData;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use crate::error::Error;
+
+pub struct BinReader<'a, E: ByteOrder = LittleEndian> {
+    data: &'a [u8],
+    /// Where in the file `data` starts
+    base: u64,
+    position: u64,
+    order: PhantomData<E>,
+}
+
+impl<'a> BinReader<'a, LittleEndian> {
+    /// A little endian reader at the start of `data`
+    pub fn new(data: &'a [u8]) -> BinReader<'a, LittleEndian> {
+        BinReader::with_order(data)
+    }
+
+    /// A little endian reader of the bytes of a file from `base` on, at
+    /// `base`; the positions are those of the file
+    pub fn windowed(data: &'a [u8], base: u64) -> BinReader<'a, LittleEndian> {
+        BinReader { data, base, position: base, order: PhantomData }
+    }
+}
+
+impl<'a> BinReader<'a, BigEndian> {
+    /// A big endian reader at the start of `data`
+    pub fn new_be(data: &'a [u8]) -> BinReader<'a, BigEndian> {
+        BinReader::with_order(data)
+    }
+}
+
+impl<'a, E: ByteOrder> BinReader<'a, E> {
+    pub fn with_order(data: &'a [u8]) -> BinReader<'a, E> {
+        BinReader { data, base: 0, position: 0, order: PhantomData }
+    }
+
+    /// The whole of the data, whatever has been read; for a window, from
+    /// where it starts
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Where the next read starts
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Moves to `position`; past the end is allowed, only the reads from
+    /// there fail
+    pub fn set_position(&mut self, position: u64) {
+        self.position = position;
+    }
+
+    /// How many bytes are left to read
+    pub fn remaining(&self) -> usize {
+        // nothing before a window can be read
+        if self.position < self.base {
+            return 0;
+        }
+        (self.base + self.data.len() as u64).saturating_sub(self.position) as usize
+    }
+
+    /// The next `len` bytes, without moving past them
+    pub fn peek(&self, len: usize) -> Result<&'a [u8], Error> {
+        let start = self.position;
+        let end = start.checked_add(len as u64).filter(|&end| start >= self.base && end <= self.base + self.data.len() as u64);
+        match end {
+            Some(end) => Ok(&self.data[(start - self.base) as usize..(end - self.base) as usize]),
+            None => Err(Error::UnexpectedEnd { at: start, needed: len }),
+        }
+    }
+
+    /// The next `len` bytes
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let bytes = self.peek(len)?;
+        self.position += len as u64;
+        Ok(bytes)
+    }
+
+    /// Moves past the next `len` bytes, which have to be there
+    pub fn skip(&mut self, len: usize) -> Result<(), Error> {
+        self.take(len).map(|_| ())
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, Error> {
+        Ok(E::read_u16(self.take(2)?))
+    }
+
+    pub fn read_i16(&mut self) -> Result<i16, Error> {
+        Ok(E::read_i16(self.take(2)?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, Error> {
+        Ok(E::read_u32(self.take(4)?))
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32, Error> {
+        Ok(E::read_i32(self.take(4)?))
+    }
+
+    /// The bytes of a string led by its one byte length, as most strings of
+    /// the formats are
+    pub fn read_prefixed(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.read_u8()? as usize;
+        self.take(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bin_reader() {
+        let data = [0x01, 0x02, 0x03, 0x04, 0x02, b'h', b'i'];
+        let mut reader = BinReader::new(&data);
+        assert_eq!(reader.peek(2).unwrap(), &[0x01, 0x02]);
+        assert_eq!(reader.read_u16().unwrap(), 0x0201);
+        assert_eq!(reader.position(), 2);
+        reader.skip(2).unwrap();
+        assert_eq!(reader.read_prefixed().unwrap(), b"hi");
+        assert_eq!(reader.remaining(), 0);
+
+        // the failed read says where and takes nothing
+        match reader.read_u32() {
+            Err(Error::UnexpectedEnd { at: 7, needed: 4 }) => (),
+            other => panic!("{:?}", other),
+        }
+        reader.set_position(4);
+        assert!(reader.read_u32().is_err());
+        assert_eq!(reader.position(), 4);
+        // the last four bytes fit exactly
+        reader.set_position(3);
+        assert_eq!(reader.read_u32().unwrap(), u32::from_le_bytes([0x04, 0x02, b'h', b'i']));
+        assert_eq!(reader.remaining(), 0);
+        reader.set_position(100);
+        assert_eq!(reader.remaining(), 0);
+        assert!(reader.take(0).is_err());
+
+        let mut reader = BinReader::new_be(&data);
+        assert_eq!(reader.read_u32().unwrap(), 0x01020304);
+        reader.set_position(0);
+        assert_eq!(reader.read_i16().unwrap(), 0x0102);
+
+        // a window of a file read from position 10 on
+        let mut reader = BinReader::windowed(&data[..4], 10);
+        assert_eq!(reader.position(), 10);
+        assert_eq!(reader.read_u16().unwrap(), 0x0201);
+        assert_eq!(reader.remaining(), 2);
+        match reader.read_u32() {
+            Err(Error::UnexpectedEnd { at: 12, needed: 4 }) => (),
+            other => panic!("{:?}", other),
+        }
+        reader.set_position(2);
+        assert_eq!(reader.remaining(), 0);
+        assert!(reader.read_u8().is_err());
+    }
+}
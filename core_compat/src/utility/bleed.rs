@@ -0,0 +1,82 @@
+//! Cleans up the edges of decoded sprites before they're scaled or filtered.
+//!
+//! The RLE files have no alpha: unpainted pixels decode to transparent black,
+//! and now and then a sprite paints its background in the magenta color key
+//! instead of leaving it out. Drawn as they are both are fine, but a linear
+//! filter blends the color of the transparent pixels into the edges, giving
+//! the sprites a dark or magenta fringe. `bleed_edges` makes the magenta
+//! pixels transparent and gives the transparent pixels around the sprite the
+//! color of their nearest painted neighbor, so only the alpha fades out.
+
+/// The color key as decoded to RGBA8
+pub const COLOR_KEY_RGB: [u8; 3] = [0xFF, 0x00, 0xFF];
+
+/// Neighbors by distance: the sides before the corners
+const NEIGHBORS: [(i32, i32); 8] = [(-1, 0), (1, 0), (0, -1), (0, 1), (-1, -1), (1, -1), (-1, 1), (1, 1)];
+
+/// Cleans the edges of a RGBA8 image in place, see the module
+pub fn bleed_edges(width: u32, height: u32, rgba: &mut [u8]) {
+    let (width, height) = (width as i32, height as i32);
+    assert_eq!(rgba.len(), (width * height * 4) as usize);
+
+    for pixel in rgba.chunks_mut(4) {
+        if pixel[..3] == COLOR_KEY_RGB {
+            pixel[3] = 0;
+        }
+    }
+
+    // the colors come from the painted pixels only, so they don't spread
+    // further than a pixel
+    let painted: Vec<bool> = rgba.chunks(4).map(|pixel| pixel[3] != 0).collect();
+    for y in 0..height {
+        for x in 0..width {
+            let at = (y * width + x) as usize;
+            if painted[at] {
+                continue;
+            }
+            let nearest = NEIGHBORS.iter()
+                .map(|&(dx, dy)| (x + dx, y + dy))
+                .filter(|&(nx, ny)| nx >= 0 && ny >= 0 && nx < width && ny < height)
+                .map(|(nx, ny)| (ny * width + nx) as usize)
+                .find(|&neighbor| painted[neighbor]);
+            match nearest {
+                Some(neighbor) => rgba.copy_within(neighbor * 4..neighbor * 4 + 3, at * 4),
+                None => rgba[at * 4..at * 4 + 3].copy_from_slice(&[0, 0, 0]),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [0xFF, 0, 0, 0xFF];
+    const BLUE: [u8; 4] = [0, 0, 0xFF, 0xFF];
+    const KEY: [u8; 4] = [0xFF, 0, 0xFF, 0xFF];
+    const NONE: [u8; 4] = [0, 0, 0, 0];
+
+    #[test]
+    fn test_bleed_edges() {
+        let mut rgba = [
+            KEY, KEY, NONE, NONE,
+            KEY, RED, NONE, NONE,
+            NONE, NONE, BLUE, NONE,
+        ].concat();
+        bleed_edges(4, 3, &mut rgba);
+        let transparent = |[r, g, b, _]: [u8; 4]| [r, g, b, 0];
+        assert_eq!(rgba, [
+            // the magenta is gone, red takes its place
+            transparent(RED), transparent(RED), transparent(RED), NONE,
+            transparent(RED), RED, transparent(RED), transparent(BLUE),
+            // the sides come before the corners
+            transparent(RED), transparent(BLUE), BLUE, transparent(BLUE),
+        ].concat());
+
+        // painted pixels stay as they are
+        let mut rgba = [RED, BLUE].concat();
+        bleed_edges(2, 1, &mut rgba);
+        assert_eq!(rgba, [RED, BLUE].concat());
+        bleed_edges(0, 0, &mut []);
+    }
+}
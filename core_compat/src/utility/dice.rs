@@ -0,0 +1,120 @@
+//! Splits decoded images that are too big for a single GPU texture into a
+//! grid of smaller tiles. Each tile keeps its grid position and its offset in
+//! the original image, so the renderer can put the pieces back together.
+
+/// One piece of a diced image
+#[derive(Debug, Clone, PartialEq)]
+pub struct DicedTile {
+    pub col: u32,
+    pub row: u32,
+    /// Offset of the tile's top left corner in the original image
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Pixels in the format of the diced image, row by row
+    pub image_raw: Vec<u8>,
+}
+
+/// Whether an image of the given size has to be diced to fit `max_size`
+pub fn needs_dicing(width: u32, height: u32, max_size: u32) -> bool {
+    width > max_size || height > max_size
+}
+
+/// Cuts a RGBA8 image into tiles of at most `max_size` x `max_size` pixels,
+/// row by row. Images that already fit are returned as a single tile.
+pub fn dice(width: u32, height: u32, image_raw: &[u8], max_size: u32) -> Vec<DicedTile> {
+    dice_pixels(width, height, image_raw, max_size, 4)
+}
+
+/// Like `dice`, for images of `bytes_per_pixel`
+pub fn dice_pixels(width: u32, height: u32, image_raw: &[u8], max_size: u32, bytes_per_pixel: u32) -> Vec<DicedTile> {
+    assert!(max_size > 0, "max_size must not be 0");
+    assert_eq!(image_raw.len(), (width * height * bytes_per_pixel) as usize);
+
+    let mut tiles = Vec::new();
+    let cols = width.div_ceil(max_size);
+    let rows = height.div_ceil(max_size);
+    for row in 0..rows {
+        for col in 0..cols {
+            let x = col * max_size;
+            let y = row * max_size;
+            let tile_width = max_size.min(width - x);
+            let tile_height = max_size.min(height - y);
+            let mut tile_raw = Vec::with_capacity((tile_width * tile_height * bytes_per_pixel) as usize);
+            for line in y..y + tile_height {
+                let start = ((line * width + x) * bytes_per_pixel) as usize;
+                tile_raw.extend_from_slice(&image_raw[start..start + (tile_width * bytes_per_pixel) as usize]);
+            }
+            tiles.push(DicedTile {
+                col,
+                row,
+                x,
+                y,
+                width: tile_width,
+                height: tile_height,
+                image_raw: tile_raw,
+            });
+        }
+    }
+    tiles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every pixel holds its own coordinates
+    fn image(width: u32, height: u32) -> Vec<u8> {
+        let mut raw = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                raw.extend_from_slice(&[x as u8, y as u8, 0, 0xFF]);
+            }
+        }
+        raw
+    }
+
+    #[test]
+    fn test_dice_fits() {
+        let raw = image(3, 2);
+        let tiles = dice(3, 2, &raw, 4);
+        assert!(!needs_dicing(3, 2, 4));
+        assert_eq!(tiles.len(), 1);
+        assert_eq!((tiles[0].width, tiles[0].height), (3, 2));
+        assert_eq!(tiles[0].image_raw, raw);
+    }
+
+    #[test]
+    fn test_dice_grid() {
+        let raw = image(5, 3);
+        let tiles = dice(5, 3, &raw, 2);
+        assert!(needs_dicing(5, 3, 2));
+
+        let layout: Vec<_> = tiles.iter()
+            .map(|t| (t.col, t.row, t.x, t.y, t.width, t.height))
+            .collect();
+        assert_eq!(layout, vec![
+            (0, 0, 0, 0, 2, 2), (1, 0, 2, 0, 2, 2), (2, 0, 4, 0, 1, 2),
+            (0, 1, 0, 2, 2, 1), (1, 1, 2, 2, 2, 1), (2, 1, 4, 2, 1, 1),
+        ]);
+
+        // pixels end up at their original position
+        for tile in tiles.iter() {
+            for (i, px) in tile.image_raw.chunks(4).enumerate() {
+                let i = i as u32;
+                assert_eq!(px[0] as u32, tile.x + i % tile.width);
+                assert_eq!(px[1] as u32, tile.y + i / tile.width);
+            }
+        }
+    }
+
+    #[test]
+    fn test_dice_565() {
+        let raw: Vec<u8> = (0..3 * 2).flat_map(|px| [px as u8, 0xF8]).collect();
+        let tiles = dice_pixels(3, 2, &raw, 2, 2);
+        assert_eq!(tiles.len(), 2);
+        assert_eq!(tiles[0].image_raw, vec![0, 0xF8, 1, 0xF8, 3, 0xF8, 4, 0xF8]);
+        assert_eq!(tiles[1].image_raw, vec![2, 0xF8, 5, 0xF8]);
+    }
+}
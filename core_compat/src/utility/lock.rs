@@ -0,0 +1,132 @@
+//! Advisory locks on the databases the tools share, taken by the system on a
+//! `<database>.lock` file next to it. A program writing to a database holds
+//! the exclusive lock for as long as it writes, with its process id in the
+//! file; the readers hold a shared one while they read, so a viewer doesn't
+//! start on a half imported database and a second import doesn't write into
+//! the first one's, nor under a reader.
+//!
+//! The system drops the locks of a process that ends, however it ends; the
+//! lock file stays behind, and a crashed import's doesn't keep anything out.
+//! Nothing stops a program that doesn't take the locks.
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+
+/// The exclusive lock of a database, released when dropped
+#[derive(Debug)]
+pub struct WriteLock {
+    file: File,
+}
+
+impl WriteLock {
+    /// Takes the lock of `database`, or fails with `Error::Locked` if it's
+    /// written or read already
+    pub fn acquire(database: &Path) -> Result<WriteLock, Error> {
+        let path = lock_path(database);
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+        match file.try_lock() {
+            Ok(()) => {
+                // what an earlier holder left in it is of no use
+                file.set_len(0)?;
+                file.write_all(std::process::id().to_string().as_bytes())?;
+                Ok(WriteLock { file })
+            }
+            Err(TryLockError::WouldBlock) => Err(locked(path, &mut file)),
+            Err(TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+}
+
+impl Drop for WriteLock {
+    fn drop(&mut self) {
+        // the lock file isn't deleted, another process may be waiting on it
+        let _ = self.file.set_len(0);
+    }
+}
+
+/// A shared lock of a database, released when dropped; any number of
+/// readers hold it at once, but never while something writes
+#[derive(Debug)]
+pub struct ReadLock {
+    file: Option<File>,
+}
+
+impl ReadLock {
+    /// Takes a shared lock of `database`, or fails with `Error::Locked`
+    /// while something writes to it. Where the lock file can't be created,
+    /// as next to a database on read only media, nothing can write the
+    /// database either and the lock holds nothing.
+    pub fn acquire(database: &Path) -> Result<ReadLock, Error> {
+        let path = lock_path(database);
+        let mut file = match OpenOptions::new().read(true).append(true).create(true).open(&path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == ErrorKind::PermissionDenied || e.kind() == ErrorKind::ReadOnlyFilesystem => {
+                return Ok(ReadLock { file: None });
+            }
+            Err(e) => return Err(e.into()),
+        };
+        match file.try_lock_shared() {
+            Ok(()) => Ok(ReadLock { file: Some(file) }),
+            Err(TryLockError::WouldBlock) => Err(locked(path, &mut file)),
+            Err(TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+}
+
+/// `<database>.lock`
+pub fn lock_path(database: &Path) -> PathBuf {
+    let mut path = database.as_os_str().to_owned();
+    path.push(".lock");
+    PathBuf::from(path)
+}
+
+/// The holder is the writer's process id, and empty when readers hold the
+/// lock
+fn locked(path: PathBuf, file: &mut File) -> Error {
+    let mut holder = String::new();
+    let _ = file.read_to_string(&mut holder);
+    Error::Locked { lock: path, holder: holder.trim().to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    #[test]
+    fn test_write_lock() {
+        let database = std::env::temp_dir().join(format!("novluno_lock_{}.sqlite", std::process::id()));
+        assert_eq!(lock_path(&database).file_name().unwrap().to_str().unwrap(),
+                   format!("novluno_lock_{}.sqlite.lock", std::process::id()));
+
+        drop(ReadLock::acquire(&database).unwrap());
+        let lock = WriteLock::acquire(&database).unwrap();
+        match WriteLock::acquire(&database) {
+            Err(Error::Locked { lock, holder }) => {
+                assert_eq!(lock, lock_path(&database));
+                assert_eq!(holder, std::process::id().to_string());
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(ReadLock::acquire(&database), Err(Error::Locked { .. })));
+        drop(lock);
+
+        // the readers share it, and keep the writers out
+        let (first, second) = (ReadLock::acquire(&database).unwrap(), ReadLock::acquire(&database).unwrap());
+        match WriteLock::acquire(&database) {
+            Err(Error::Locked { holder, .. }) => assert_eq!(holder, ""),
+            other => panic!("{:?}", other),
+        }
+        drop((first, second));
+
+        // a lock file left behind by a process that's gone holds nothing
+        fs::write(lock_path(&database), b"4000000").unwrap();
+        assert!(ReadLock::acquire(&database).is_ok());
+        drop(WriteLock::acquire(&database).unwrap());
+        fs::remove_file(lock_path(&database)).unwrap();
+    }
+}
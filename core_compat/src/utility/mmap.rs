@@ -0,0 +1,58 @@
+//! Maps data files into memory, for the parsers to read them in place of a
+//! copy. Only with the `mmap` feature.
+
+use std::fs::File;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::error::Error;
+
+/// The file at `path`, mapped read only
+pub fn map_file(path: &Path) -> Result<Mmap, Error> {
+    let file = File::open(path)?;
+    // SAFETY: the data files aren't written while the game or the tools run;
+    // one truncated under the map would fault on the pages past its end.
+    let map = unsafe { Mmap::map(&file)? };
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::entity::entry::Entry;
+    use crate::entity::list::List;
+    use crate::entity::resource_file::ResourceFile;
+    use crate::fixture::{LstFixture, ResourceFixture, RleFixture};
+    use crate::parser::lst::parse_lst;
+    use crate::parser::rle::parse_rle;
+
+    #[test]
+    fn test_load_mmap() {
+        let folder = std::env::temp_dir().join(format!("novluno_mmap_{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+
+        let rle = RleFixture::new().null().resource(ResourceFixture::new(2, 1).pixels(&[0xF800, 0x001F])).build();
+        let rle_path = folder.join("obj00007.rle");
+        fs::write(&rle_path, &rle).unwrap();
+        let mapped = ResourceFile::load_mmap(7, &rle_path).unwrap();
+        let read = parse_rle(7, &rle).unwrap();
+        assert_eq!(mapped.slots, read.slots);
+        assert_eq!(mapped.get(1).map(|r| &r.image_raw), read.get(1).map(|r| &r.image_raw));
+        let lazy = ResourceFile::open_lazy_mmap(7, &rle_path).unwrap();
+        assert_eq!(lazy.get(1).unwrap().map(|r| r.image_raw), read.get(1).map(|r| r.image_raw.clone()));
+
+        let lst = LstFixture::new("1.0").item("Rat", 3, Entry::new(7, 1)).build();
+        let lst_path = folder.join("obj.lst");
+        fs::write(&lst_path, &lst).unwrap();
+        let list = List::load_mmap(&lst_path, false).unwrap();
+        assert_eq!(list.items.len(), parse_lst(&lst, false).unwrap().items.len());
+        assert_eq!(list.get_item(3).map(|item| (item.name.as_str(), item.entry)), Some(("Rat", Entry::new(7, 1))));
+
+        assert!(matches!(List::load_mmap(&folder.join("missing.lst"), true), Err(Error::Io(_))));
+        let _ = fs::remove_dir_all(&folder);
+    }
+}
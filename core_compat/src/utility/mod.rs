@@ -1,2 +1,21 @@
 pub mod pixel;
 pub mod parsing;
+pub mod bin_reader;
+#[cfg(feature = "std")]
+pub mod dice;
+#[cfg(feature = "std")]
+pub mod atlas;
+#[cfg(feature = "std")]
+pub mod bleed;
+#[cfg(feature = "std")]
+pub mod recover;
+#[cfg(feature = "std")]
+pub mod transform;
+#[cfg(feature = "std")]
+pub mod lock;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+#[cfg(feature = "serde")]
+pub mod serde_pixels;
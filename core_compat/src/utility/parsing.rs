@@ -1,43 +1,25 @@
-use std::io::Cursor;
-
-use byteorder::ReadBytesExt;
-use byteorder::LittleEndian as LE;
+use alloc::string::String;
+use alloc::vec::Vec;
 
 use cp949::cp949_to_utf8;
 
 use crate::error::Error;
+use crate::utility::bin_reader::BinReader;
 
-pub fn parse_string(cursor: &mut Cursor<&[u8]>) -> Result<String, Error> {
-    let string_length = cursor.read_u8()?;
-    let mut str_vec = Vec::<u8>::new();
-    for _ in 0..string_length {
-        let chr = cursor.read_u8()?;
-        str_vec.push(chr);
-    }
-    let string = String::from_utf8(str_vec)?;
+pub fn parse_string(reader: &mut BinReader) -> Result<String, Error> {
+    let string = String::from_utf8(reader.read_prefixed()?.to_vec())?;
     Ok(string)
 }
 
-pub fn parse_cp949(cursor: &mut Cursor<&[u8]>) -> Result<String, Error> {
-    let string_length = cursor.read_u8()?;
-    // println!("string_length: {:?}", string_length);
-    let mut str_vec = Vec::<u8>::new();
-    for _ in 0..string_length {
-        let chr = cursor.read_u8()?;
-        if chr != 0 {
-            str_vec.push(chr);
-        }
-    }
+pub fn parse_cp949(reader: &mut BinReader) -> Result<String, Error> {
+    let str_vec: Vec<u8> = reader.read_prefixed()?.iter()
+        .cloned()
+        .filter(|&chr| chr != 0)
+        .collect();
     let string = cp949_to_utf8(&str_vec);
     Ok(string)
 }
 
-pub fn parse_u8_vec(cursor: &mut Cursor<&[u8]>) -> Result<Vec<u8>, Error> {
-    let string_length = cursor.read_u8()?;
-    let mut vec = Vec::<u8>::new();
-    for _ in 0..string_length {
-        let chr = cursor.read_u8()?;
-        vec.push(chr);
-    }
-    Ok(vec)
+pub fn parse_u8_vec(reader: &mut BinReader) -> Result<Vec<u8>, Error> {
+    Ok(reader.read_prefixed()?.to_vec())
 }
@@ -0,0 +1,144 @@
+//! Guesses list items for resources no list item points at.
+//!
+//! The list ids mostly run in step with the resource indices of a file, so an
+//! unlisted resource gets the id its closest listed neighbours in the same
+//! file predict. The name follows the neighbour's name when that ends in a
+//! number (`tree_07` next to `tree_08`).
+
+use crate::entity::entry::Entry;
+use crate::entity::list::List;
+use crate::entity::list_item::ListItem;
+
+/// Both neighbours predict the same id
+pub const CONFIDENCE_AGREE: f32 = 0.9;
+/// Only one neighbour in the file is listed
+pub const CONFIDENCE_SINGLE: f32 = 0.6;
+/// The neighbours disagree; the one below wins
+pub const CONFIDENCE_DISAGREE: f32 = 0.3;
+
+#[derive(Debug, Clone)]
+pub struct InferredItem {
+    pub item: ListItem,
+    /// Between 0 and 1; see the `CONFIDENCE_*` values
+    pub confidence: f32,
+}
+
+#[derive(Debug, Default)]
+pub struct Recovery {
+    pub inferred: Vec<InferredItem>,
+    /// Resources without a listed neighbour, or whose predicted id is taken
+    pub unresolved: Vec<Entry>,
+}
+
+/// Infers list items for the resources in `resources` that `list` doesn't
+/// reference, in the order given.
+pub fn recover_list_ids(list: &List, resources: &[Entry]) -> Recovery {
+    let mut recovery = Recovery::default();
+    let mut used: Vec<u32> = list.items.iter().map(|item| item.id).collect();
+
+    for &entry in resources {
+        if list.items.iter().any(|item| item.entry == entry) {
+            continue;
+        }
+        let (below, above) = neighbours(list, entry);
+        let from_below = below.map(|item| predict(item, entry));
+        let from_above = above.map(|item| predict(item, entry));
+        let guess = match (from_below, from_above) {
+            (Some(b), Some(a)) if b.0 == a.0 => Some((b, CONFIDENCE_AGREE)),
+            (Some(b), Some(_)) => Some((b, CONFIDENCE_DISAGREE)),
+            (Some(b), None) => Some((b, CONFIDENCE_SINGLE)),
+            (None, Some(a)) => Some((a, CONFIDENCE_SINGLE)),
+            (None, None) => None,
+        };
+        match guess {
+            Some(((Some(id), name), confidence)) if !used.contains(&id) => {
+                used.push(id);
+                recovery.inferred.push(InferredItem {
+                    item: ListItem { name, id, entry },
+                    confidence,
+                });
+            }
+            _ => recovery.unresolved.push(entry),
+        }
+    }
+    recovery
+}
+
+/// The closest listed items below and above `entry` in the same file
+fn neighbours(list: &List, entry: Entry) -> (Option<&ListItem>, Option<&ListItem>) {
+    let mut below: Option<&ListItem> = None;
+    let mut above: Option<&ListItem> = None;
+    for item in list.items.iter().filter(|item| item.entry.file() == entry.file()) {
+        let index = item.entry.index();
+        if index < entry.index() && below.is_none_or(|b| index > b.entry.index()) {
+            below = Some(item);
+        }
+        if index > entry.index() && above.is_none_or(|a| index < a.entry.index()) {
+            above = Some(item);
+        }
+    }
+    (below, above)
+}
+
+/// The id and name `entry` would have if it followed `item`'s numbering
+fn predict(item: &ListItem, entry: Entry) -> (Option<u32>, String) {
+    let delta = entry.index() as i64 - item.entry.index() as i64;
+    let id = item.id as i64 + delta;
+    let id = if id >= 0 && id <= u32::MAX as i64 { Some(id as u32) } else { None };
+
+    // `name_012` -> `name_013`, keeping the width of the number
+    let digits = item.name.chars().rev().take_while(|c| c.is_ascii_digit()).count();
+    let (prefix, number) = item.name.split_at(item.name.len() - digits);
+    let name = match number.parse::<i64>() {
+        Ok(number) if number + delta >= 0 => format!("{}{:0width$}", prefix, number + delta, width = digits),
+        _ => String::new(),
+    };
+    (id, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(items: &[(&str, u32, u32, u32)]) -> List {
+        let mut list = List::new();
+        for &(name, id, file, index) in items {
+            list.items.push(ListItem { name: name.into(), id, entry: Entry::new(file, index) });
+        }
+        list
+    }
+
+    #[test]
+    fn test_recover_list_ids() {
+        let list = list(&[
+            ("tree_08", 20, 1, 0),
+            ("tree_10", 22, 1, 2),
+            ("rock", 40, 1, 5),
+            ("bush", 50, 2, 0),
+        ]);
+        let resources = [Entry::new(1, 0), Entry::new(1, 1), Entry::new(1, 3), Entry::new(2, 1), Entry::new(3, 0)];
+        let recovery = recover_list_ids(&list, &resources);
+
+        let inferred: Vec<_> = recovery.inferred.iter()
+            .map(|i| (i.item.id, i.item.name.as_str(), i.item.entry, i.confidence))
+            .collect();
+        assert_eq!(inferred, vec![
+            (21, "tree_09", Entry::new(1, 1), CONFIDENCE_AGREE),
+            (23, "tree_11", Entry::new(1, 3), CONFIDENCE_DISAGREE),
+            (51, "", Entry::new(2, 1), CONFIDENCE_SINGLE),
+        ]);
+        // nothing listed in file 3 to go by
+        assert_eq!(recovery.unresolved, vec![Entry::new(3, 0)]);
+    }
+
+    #[test]
+    fn test_recover_list_ids_taken() {
+        let list = list(&[("a", 1, 1, 0), ("b", 2, 2, 0)]);
+        let recovery = recover_list_ids(&list, &[Entry::new(1, 1), Entry::new(1, 2)]);
+
+        // id 2 is used by another file, so only the second resource gets one
+        assert_eq!(recovery.inferred.len(), 1);
+        assert_eq!(recovery.inferred[0].item.id, 3);
+        assert_eq!(recovery.unresolved, vec![Entry::new(1, 1)]);
+    }
+}
@@ -0,0 +1,202 @@
+//! How `Resource::image_raw` is serialized, with the `serde` feature: as
+//! bytes in the binary formats like MessagePack and bincode, as base64 in
+//! the text ones like JSON, and as nothing at all inside `WithoutPixels`,
+//! for dumping only what's known about the resources. Nothing reads back as
+//! an empty image.
+
+#[cfg(feature = "std")]
+use std::cell::Cell;
+use core::fmt;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[cfg(feature = "std")]
+thread_local! {
+    /// Whether a `WithoutPixels` is being serialized on this thread
+    static WITHOUT_PIXELS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Serializes a `Resource`, a `ResourceFile` or anything holding them with
+/// the images left out
+#[cfg(feature = "std")]
+pub struct WithoutPixels<'a, T: 'a>(pub &'a T);
+
+#[cfg(feature = "std")]
+impl<'a, T: Serialize> Serialize for WithoutPixels<'a, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // put back as it was even if the serializer panics
+        struct Restore(bool);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                WITHOUT_PIXELS.with(|without| without.set(self.0));
+            }
+        }
+        let _restore = Restore(WITHOUT_PIXELS.with(|without| without.replace(true)));
+        self.0.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "std")]
+fn without_pixels() -> bool {
+    WITHOUT_PIXELS.with(|without| without.get())
+}
+
+#[cfg(not(feature = "std"))]
+fn without_pixels() -> bool {
+    false
+}
+
+/// The pixels as bytes or base64, for `Option` to mark them left out
+struct Pixels<'a>(&'a [u8]);
+
+impl<'a> Serialize for Pixels<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&encode(self.0))
+        } else {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+}
+
+struct PixelBuf(Vec<u8>);
+
+impl<'de> Deserialize<'de> for PixelBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<PixelBuf, D::Error> {
+        if deserializer.is_human_readable() {
+            let text = String::deserialize(deserializer)?;
+            decode(&text).map(PixelBuf).ok_or_else(|| de::Error::custom("the pixels aren't base64"))
+        } else {
+            deserializer.deserialize_byte_buf(BytesVisitor).map(PixelBuf)
+        }
+    }
+}
+
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("the bytes of the pixels")
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+        Ok(bytes.to_vec())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<Vec<u8>, E> {
+        Ok(bytes)
+    }
+
+    // some formats write the bytes as a sequence
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(bytes)
+    }
+}
+
+pub fn serialize<S: Serializer>(pixels: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    if without_pixels() {
+        serializer.serialize_none()
+    } else {
+        serializer.serialize_some(&Pixels(pixels))
+    }
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    Ok(Option::<PixelBuf>::deserialize(deserializer)?.map_or_else(Vec::new, |pixels| pixels.0))
+}
+
+/// Standard base64, padded
+pub fn encode(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| bits | ((byte as u32) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[((bits >> (18 - 6 * i)) & 0x3F) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+/// The bytes of standard base64, padded or not; `None` for anything else
+pub fn decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=');
+    // a single character left over can't make a byte
+    if text.len() % 4 == 1 {
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut count) = (0u32, 0);
+    for chr in text.bytes() {
+        bits = (bits << 6) | ALPHABET.iter().position(|&a| a == chr)? as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+            bits &= (1 << count) - 1;
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate serde_json;
+
+    use crate::entity::resource::Resource;
+    use crate::fixture::{RleFixture, ResourceFixture};
+    use crate::parser::rle::parse_rle;
+    use crate::entity::resource_file::ResourceFile;
+
+    #[test]
+    fn test_base64() {
+        for (bytes, text) in [(&b""[..], ""), (&b"f"[..], "Zg=="), (&b"fo"[..], "Zm8="), (&b"foo"[..], "Zm9v"),
+                               (&b"\xFF\xFE\x00\x01"[..], "//4AAQ==")].iter() {
+            assert_eq!(encode(bytes), *text);
+            assert_eq!(decode(text).unwrap(), *bytes);
+        }
+        assert_eq!(decode("Zm8").unwrap(), b"fo");
+        assert!(decode("Zm9v!").is_none());
+        assert!(decode("Z").is_none());
+    }
+
+    #[test]
+    fn test_serde_json() {
+        let data = RleFixture::new().resource(ResourceFixture::new(2, 1).offset(3, -4).pixels(&[0xF800])).build();
+        let file = parse_rle(7, &data).unwrap();
+
+        let json = serde_json::to_string(&file).unwrap();
+        assert!(json.contains(&format!("\"image_raw\":\"{}\"", encode(&file.resources[0].image_raw))));
+        let read: ResourceFile = serde_json::from_str(&json).unwrap();
+        assert_eq!((read.file_number, read.slots, read.warnings), (7, file.slots.clone(), file.warnings.clone()));
+        let (resource, original) = (&read.resources[0], &file.resources[0]);
+        assert_eq!((resource.offset_x, resource.offset_y, resource.width, resource.index()), (3, -4, 2, 0));
+        assert_eq!(resource.image_raw, original.image_raw);
+        assert_eq!(resource.content_hash(), original.content_hash());
+
+        let json = serde_json::to_string(&WithoutPixels(&file.resources[0])).unwrap();
+        assert!(json.contains("\"image_raw\":null"));
+        let read: Resource = serde_json::from_str(&json).unwrap();
+        assert!(read.image_raw.is_empty());
+        assert_eq!(read.width, 2);
+        // only inside the wrapper
+        assert!(!serde_json::to_string(&file.resources[0]).unwrap().contains("\"image_raw\":null"));
+    }
+}
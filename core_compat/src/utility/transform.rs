@@ -0,0 +1,133 @@
+//! Hooks for changing the resources on their way from the parser to wherever
+//! they're stored, e.g. custom trims, watermarks or format conversions,
+//! without touching the importers and exporters themselves.
+//!
+//! A pipeline runs the `pre` transforms of its `ResourceHooks` on every
+//! resource as it's decoded, before any of its own steps, and the `post`
+//! transforms on every resource it's about to store. Transforms are trait
+//! objects; a closure taking a `&mut Resource` is one too.
+
+use crate::entity::resource::Resource;
+use crate::entity::resource_file::ResourceFile;
+use crate::error::Error;
+use crate::utility::bleed::bleed_edges;
+
+/// Something done to every resource passing through a pipeline
+pub trait ResourceTransform {
+    fn apply(&self, resource: &mut Resource) -> Result<(), Error>;
+}
+
+impl<F: Fn(&mut Resource) -> Result<(), Error>> ResourceTransform for F {
+    fn apply(&self, resource: &mut Resource) -> Result<(), Error> {
+        self(resource)
+    }
+}
+
+/// Where in a pipeline the transforms run
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stage {
+    /// Right after decoding
+    Pre,
+    /// Right before storing
+    Post,
+}
+
+/// The transforms of a pipeline, run in the order they were added
+#[derive(Default)]
+pub struct ResourceHooks {
+    pre: Vec<Box<dyn ResourceTransform>>,
+    post: Vec<Box<dyn ResourceTransform>>,
+}
+
+impl ResourceHooks {
+    pub fn new() -> ResourceHooks {
+        ResourceHooks::default()
+    }
+
+    /// Adds a transform run right after decoding
+    pub fn pre<T: ResourceTransform + 'static>(mut self, transform: T) -> ResourceHooks {
+        self.pre.push(Box::new(transform));
+        self
+    }
+
+    /// Adds a transform run right before storing
+    pub fn post<T: ResourceTransform + 'static>(mut self, transform: T) -> ResourceHooks {
+        self.post.push(Box::new(transform));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pre.is_empty() && self.post.is_empty()
+    }
+
+    /// Runs the transforms of `stage` on the resource; the first one failing
+    /// stops the others
+    pub fn run(&self, stage: Stage, resource: &mut Resource) -> Result<(), Error> {
+        let transforms = match stage {
+            Stage::Pre => &self.pre,
+            Stage::Post => &self.post,
+        };
+        for transform in transforms.iter() {
+            transform.apply(resource)?;
+        }
+        Ok(())
+    }
+
+    /// `run` on every decoded resource of the file
+    pub fn run_file(&self, stage: Stage, file: &mut ResourceFile) -> Result<(), Error> {
+        for resource in file.resources.iter_mut() {
+            self.run(stage, resource)?;
+        }
+        Ok(())
+    }
+}
+
+/// `bleed_edges` as a transform, for the RGBA8 resources
+pub struct BleedEdges;
+
+impl ResourceTransform for BleedEdges {
+    fn apply(&self, resource: &mut Resource) -> Result<(), Error> {
+        if resource.image_raw.len() == resource.width.max(0) as usize * resource.height.max(0) as usize * 4 {
+            bleed_edges(resource.width as u32, resource.height as u32, &mut resource.image_raw);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fixture::{ResourceFixture, RleFixture};
+    use crate::parser::rle::parse_rle;
+
+    #[test]
+    fn test_resource_hooks() {
+        let data = RleFixture::new()
+            .resource(ResourceFixture::new(2, 1).offset(1, 1).skip(1).pixels(&[0xF800]))
+            .resource(ResourceFixture::new(1, 1).pixels(&[0xF81F]))
+            .build();
+        let mut file = parse_rle(1, &data).unwrap();
+        let shift = |resource: &mut Resource| {
+            resource.offset_x += 10;
+            Ok(())
+        };
+        let hooks = ResourceHooks::new()
+            .pre(BleedEdges)
+            .pre(shift)
+            .post(|resource: &mut Resource| match resource.index() {
+                1 => Err(Error::UnencodableResource(1)),
+                _ => Ok(()),
+            });
+        assert!(ResourceHooks::new().is_empty());
+
+        hooks.run_file(Stage::Pre, &mut file).unwrap();
+        assert_eq!(file.resources[0].offset_x, 11);
+        // the color key is made transparent, the red bled next to it
+        assert_eq!(file.resources[0].image_raw, vec![0xFF, 0, 0, 0, 0xFF, 0, 0, 0xFF]);
+        assert_eq!(file.resources[1].image_raw[3], 0);
+
+        hooks.run(Stage::Post, &mut file.resources[0]).unwrap();
+        assert!(hooks.run_file(Stage::Post, &mut file).is_err());
+    }
+}
@@ -0,0 +1,234 @@
+//! Parsers and transforms compiled to WebAssembly, so they can be passed
+//! around and run without trusting native code. Each call runs in a sandbox
+//! of its own: the plugin is given no imports, so it can't reach anything
+//! outside its memory, and it has a fuel and memory budget, so it can't hang
+//! or exhaust the machine either.
+//!
+//! A plugin exports
+//!
+//! - `memory`,
+//! - `alloc(len: i32) -> i32`, the address of `len` free bytes in `memory`,
+//!
+//! and, for a `WasmTransform`, run as a `ResourceTransform` (see
+//! `utility::transform`),
+//!
+//! - `transform(ptr: i32, width: i32, height: i32) -> i32`, changing the
+//!   RGBA8 pixels at `ptr` in place and returning 0, or anything else when it
+//!   failed,
+//!
+//! or, for a `WasmParser`, reading a format of its own,
+//!
+//! - `parse(ptr: i32, len: i32) -> i64`, converting the `len` bytes of the
+//!   file at `ptr` into an RLE file and returning where it is, its address
+//!   in the high 32 bits and its length in the low ones, or -1 when it
+//!   failed. The RLE file is then parsed as usual, outside the sandbox.
+//!
+//! Every resource or file gets a new instance of the plugin, nothing is kept
+//! from one to the next. Resources that aren't RGBA8 are passed over.
+
+use std::fs;
+use std::path::Path;
+
+use wasmtime::{Config, Engine, Instance, Memory, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use crate::entity::resource::Resource;
+use crate::entity::resource_file::ResourceFile;
+use crate::error::Error;
+use crate::parser::rle::parse_rle;
+use crate::utility::transform::ResourceTransform;
+
+/// How much a plugin may do for a resource or file, in wasmtime fuel (about an
+/// instruction each)
+pub const DEFAULT_FUEL: u64 = 1 << 30;
+
+/// How much memory a plugin instance may grow to
+pub const MAX_MEMORY: usize = 64 << 20;
+
+/// A compiled plugin, with the budget of each of its instances
+struct Plugin {
+    engine: Engine,
+    module: Module,
+    fuel: u64,
+}
+
+impl Plugin {
+    fn new(wasm: &[u8]) -> Result<Plugin, Error> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(plugin_error)?;
+        let module = Module::new(&engine, wasm).map_err(plugin_error)?;
+        Ok(Plugin { engine, module, fuel: DEFAULT_FUEL })
+    }
+
+    /// A new instance with `data` copied into its memory, at the address
+    /// given back
+    fn instantiate(&self, data: &[u8]) -> Result<(Store<StoreLimits>, Instance, Memory, i32), Error> {
+        let limits = StoreLimitsBuilder::new().memory_size(MAX_MEMORY).instances(1).build();
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits: &mut StoreLimits| limits);
+        store.set_fuel(self.fuel).map_err(plugin_error)?;
+
+        let instance = Instance::new(&mut store, &self.module, &[]).map_err(plugin_error)?;
+        let memory = instance.get_memory(&mut store, "memory")
+            .ok_or_else(|| Error::Plugin("the plugin has no `memory`".into()))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc").map_err(plugin_error)?;
+        let ptr = alloc.call(&mut store, data.len() as i32).map_err(plugin_error)?;
+        memory.write(&mut store, ptr as u32 as usize, data).map_err(|error| Error::Plugin(error.to_string()))?;
+        Ok((store, instance, memory, ptr))
+    }
+}
+
+pub struct WasmTransform {
+    plugin: Plugin,
+}
+
+impl WasmTransform {
+    /// Compiles a plugin from its binary or text format
+    pub fn new(wasm: &[u8]) -> Result<WasmTransform, Error> {
+        Ok(WasmTransform { plugin: Plugin::new(wasm)? })
+    }
+
+    pub fn load(path: &Path) -> Result<WasmTransform, Error> {
+        WasmTransform::new(&fs::read(path)?)
+    }
+
+    /// The fuel the plugin gets for every resource, `DEFAULT_FUEL` unless set
+    pub fn fuel(mut self, fuel: u64) -> WasmTransform {
+        self.plugin.fuel = fuel;
+        self
+    }
+}
+
+impl ResourceTransform for WasmTransform {
+    fn apply(&self, resource: &mut Resource) -> Result<(), Error> {
+        let len = resource.image_raw.len();
+        if len == 0 || len != resource.width.max(0) as usize * resource.height.max(0) as usize * 4 {
+            return Ok(());
+        }
+        let (mut store, instance, memory, ptr) = self.plugin.instantiate(&resource.image_raw)?;
+        let transform = instance.get_typed_func::<(i32, i32, i32), i32>(&mut store, "transform")
+            .map_err(plugin_error)?;
+        match transform.call(&mut store, (ptr, resource.width, resource.height)).map_err(plugin_error)? {
+            0 => (),
+            status => return Err(Error::Plugin(format!("`transform` failed with {}", status))),
+        }
+        memory.read(&store, ptr as u32 as usize, &mut resource.image_raw)
+            .map_err(|error| Error::Plugin(error.to_string()))
+    }
+}
+
+/// Reads files of a format the crate doesn't know into resources, by way of
+/// a plugin converting them to RLE
+pub struct WasmParser {
+    plugin: Plugin,
+}
+
+impl WasmParser {
+    /// Compiles a plugin from its binary or text format
+    pub fn new(wasm: &[u8]) -> Result<WasmParser, Error> {
+        Ok(WasmParser { plugin: Plugin::new(wasm)? })
+    }
+
+    pub fn load(path: &Path) -> Result<WasmParser, Error> {
+        WasmParser::new(&fs::read(path)?)
+    }
+
+    /// The fuel the plugin gets for every file, `DEFAULT_FUEL` unless set
+    pub fn fuel(mut self, fuel: u64) -> WasmParser {
+        self.plugin.fuel = fuel;
+        self
+    }
+
+    /// Like `parse_rle`, for a file of the plugin's format
+    pub fn parse(&self, file_number: u32, data: &[u8]) -> Result<ResourceFile, Error> {
+        let (mut store, instance, memory, ptr) = self.plugin.instantiate(data)?;
+        let parse = instance.get_typed_func::<(i32, i32), i64>(&mut store, "parse").map_err(plugin_error)?;
+        let found = parse.call(&mut store, (ptr, data.len() as i32)).map_err(plugin_error)?;
+        if found < 0 {
+            return Err(Error::Plugin(format!("`parse` failed with {}", found)));
+        }
+        let (rle_ptr, rle_len) = ((found >> 32) as usize, (found & 0xFFFF_FFFF) as usize);
+        let rle = memory.data(&store).get(rle_ptr..rle_ptr + rle_len)
+            .ok_or_else(|| Error::Plugin("`parse` returned bytes outside of `memory`".into()))?;
+        parse_rle(file_number, rle)
+    }
+}
+
+fn plugin_error(error: wasmtime::Error) -> Error {
+    Error::Plugin(format!("{:#}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fixture::{ResourceFixture, RleFixture};
+    use crate::parser::rle::parse_rle;
+
+    // halves the alpha of every pixel
+    const HALF_ALPHA: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 16))
+          (func (export "transform") (param $ptr i32) (param $width i32) (param $height i32) (result i32)
+            (local $end i32)
+            (local.set $end (i32.add (local.get $ptr) (i32.mul (i32.mul (local.get $width) (local.get $height)) (i32.const 4))))
+            (block $done
+              (loop $next
+                (br_if $done (i32.ge_u (local.get $ptr) (local.get $end)))
+                (i32.store8 offset=3 (local.get $ptr) (i32.shr_u (i32.load8_u offset=3 (local.get $ptr)) (i32.const 1)))
+                (local.set $ptr (i32.add (local.get $ptr) (i32.const 4)))
+                (br $next)))
+            (i32.const 0)))
+    "#;
+
+    const SPIN: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 0))
+          (func (export "transform") (param i32 i32 i32) (result i32) (loop $forever (br $forever)) (i32.const 0)))
+    "#;
+
+    // a "format" made of an RLE file after a 4 bytes header
+    const SKIP_HEADER: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 16))
+          (func (export "parse") (param $ptr i32) (param $len i32) (result i64)
+            (if (i32.lt_u (local.get $len) (i32.const 4)) (then (return (i64.const -1))))
+            (i64.or
+              (i64.shl (i64.extend_i32_u (i32.add (local.get $ptr) (i32.const 4))) (i64.const 32))
+              (i64.extend_i32_u (i32.sub (local.get $len) (i32.const 4))))))
+    "#;
+
+    fn resource() -> Resource {
+        let data = RleFixture::new().resource(ResourceFixture::new(2, 1).pixels(&[0xF800, 0x001F])).build();
+        parse_rle(1, &data).unwrap().into_iter().next().unwrap()
+    }
+
+    #[test]
+    fn test_wasm_transform() {
+        let mut resource = resource();
+        WasmTransform::new(HALF_ALPHA.as_bytes()).unwrap().apply(&mut resource).unwrap();
+        assert_eq!(resource.image_raw, vec![0xFF, 0, 0, 0x7F, 0, 0, 0xFF, 0x7F]);
+
+        // a plugin running away is stopped, one wanting imports isn't run
+        let spin = WasmTransform::new(SPIN.as_bytes()).unwrap().fuel(10_000);
+        assert!(matches!(spin.apply(&mut resource), Err(Error::Plugin(_))));
+        let imports = WasmTransform::new(br#"(module (import "env" "open" (func)))"#).unwrap();
+        assert!(matches!(imports.apply(&mut resource), Err(Error::Plugin(_))));
+        assert!(WasmTransform::new(b"not wasm").is_err());
+        assert_eq!(resource.image_raw, vec![0xFF, 0, 0, 0x7F, 0, 0, 0xFF, 0x7F]);
+    }
+
+    #[test]
+    fn test_wasm_parser() {
+        let mut data = b"OBSC".to_vec();
+        data.extend(RleFixture::new().resource(ResourceFixture::new(2, 1).pixels(&[0xF800, 0x001F])).build());
+        let parser = WasmParser::new(SKIP_HEADER.as_bytes()).unwrap();
+        let file = parser.parse(1, &data).unwrap();
+        assert_eq!(file.resources.len(), 1);
+        assert_eq!(file.resources[0].image_raw, resource().image_raw);
+        assert!(matches!(parser.parse(1, b"OB"), Err(Error::Plugin(_))));
+    }
+}
@@ -0,0 +1,175 @@
+//! Rewrites the entries of a LST list file in place or appends new ones;
+//! every other byte of the file is kept as it is. See `parser::lst` for the
+//! layout.
+
+use std::io::Cursor;
+use std::io::Write;
+
+use byteorder::ReadBytesExt;
+use byteorder::WriteBytesExt;
+use byteorder::LittleEndian as LE;
+
+use crate::error::Error;
+use crate::entity::entry::Entry;
+use crate::entity::list_item::ListItem;
+
+/// Calls `remap` with the id and the RLE entry of every list item and writes
+/// the returned values back into a copy of `data`.
+pub fn remap_lst_items<F>(data: &[u8], use_v2: bool, mut remap: F) -> Result<Vec<u8>, Error>
+    where F: FnMut(u32, Entry) -> (u32, Entry)
+{
+    let mut out = data.to_vec();
+    let mut cursor = Cursor::new(data);
+
+    // file type and version strings
+    let string_length = cursor.read_u8()?;
+    cursor.set_position(cursor.position() + string_length as u64);
+    let version_length = cursor.read_u8()? as usize;
+    let version_start = cursor.position() as usize;
+    let version = data.get(version_start..version_start + version_length)
+        .ok_or(Error::UnexpectedEndOfList)?;
+    cursor.set_position((version_start + version_length) as u64);
+    let use_v2 = use_v2 || version == b"1.2";
+
+    let _next_free_id = cursor.read_u32::<LE>()?;
+    let entry_count = cursor.read_u32::<LE>()?;
+    for _ in 0..entry_count {
+        let name_length = cursor.read_u8()?;
+        cursor.set_position(cursor.position() + name_length as u64);
+        let item_start = cursor.position() as usize;
+        let id = cursor.read_u32::<LE>()?;
+        let file_number = cursor.read_u32::<LE>()?;
+        let index = cursor.read_u32::<LE>()?;
+        if use_v2 {
+            let _unknown_2 = cursor.read_u32::<LE>()?;
+        }
+
+        let (new_id, new_entry) = remap(id, Entry::new(file_number, index));
+        let mut item = Cursor::new(&mut out[item_start..item_start + 12]);
+        item.write_u32::<LE>(new_id)?;
+        item.write_u32::<LE>(new_entry.file())?;
+        item.write_u32::<LE>(new_entry.index())?;
+        item.flush()?;
+    }
+    Ok(out)
+}
+
+/// Appends `items` to a copy of the list in `data`, after its last item. The
+/// item count is updated and the next free id is raised past the new ids.
+pub fn append_lst_items(data: &[u8], use_v2: bool, items: &[ListItem]) -> Result<Vec<u8>, Error> {
+    let mut cursor = Cursor::new(data);
+
+    let string_length = cursor.read_u8()?;
+    cursor.set_position(cursor.position() + string_length as u64);
+    let version_length = cursor.read_u8()? as usize;
+    let version_start = cursor.position() as usize;
+    let version = data.get(version_start..version_start + version_length)
+        .ok_or(Error::UnexpectedEndOfList)?;
+    let use_v2 = use_v2 || version == b"1.2";
+    let id_start = version_start + version_length;
+    cursor.set_position(id_start as u64);
+
+    let next_free_id = cursor.read_u32::<LE>()?;
+    let entry_count = cursor.read_u32::<LE>()?;
+    for _ in 0..entry_count {
+        let name_length = cursor.read_u8()?;
+        cursor.set_position(cursor.position() + name_length as u64 + if use_v2 { 16 } else { 12 });
+    }
+    let end = cursor.position() as usize;
+    if end > data.len() {
+        return Err(Error::UnexpectedEndOfList);
+    }
+
+    let mut out = data[..end].to_vec();
+    for item in items {
+        if item.name.len() > u8::MAX as usize {
+            return Err(Error::UnencodableListItem(item.id));
+        }
+        out.write_u8(item.name.len() as u8)?;
+        out.extend_from_slice(item.name.as_bytes());
+        out.write_u32::<LE>(item.id)?;
+        out.write_u32::<LE>(item.entry.file())?;
+        out.write_u32::<LE>(item.entry.index())?;
+        if use_v2 {
+            out.write_u32::<LE>(0)?;
+        }
+    }
+    out.extend_from_slice(&data[end..]);
+
+    let next_free_id = items.iter().map(|item| item.id.saturating_add(1)).fold(next_free_id, u32::max);
+    let mut header = Cursor::new(&mut out[id_start..id_start + 8]);
+    header.write_u32::<LE>(next_free_id)?;
+    header.write_u32::<LE>(entry_count + items.len() as u32)?;
+    Ok(out)
+}
+
+/// Overwrites the next free id field of the list header in `data`.
+pub fn set_next_free_id(data: &mut [u8], id: u32) -> Result<(), Error> {
+    let mut cursor = Cursor::new(&*data);
+    let string_length = cursor.read_u8()?;
+    cursor.set_position(cursor.position() + string_length as u64);
+    let version_length = cursor.read_u8()?;
+    let id_start = cursor.position() as usize + version_length as usize;
+    data.get_mut(id_start..id_start + 4)
+        .ok_or(Error::UnexpectedEndOfList)?
+        .write_u32::<LE>(id)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::parser::lst::parse_lst;
+    use crate::fixture::LstFixture;
+
+    #[test]
+    fn test_remap_lst_items() {
+        let data = LstFixture::new("1.0")
+            .item("a", 1, Entry::new(10, 0))
+            .item("b", 2, Entry::new(10, 1))
+            .build();
+
+        let out = remap_lst_items(&data, false, |id, entry| {
+            if entry == Entry::new(10, 1) { (id + 5, Entry::new(11, 0)) } else { (id, entry) }
+        }).unwrap();
+
+        assert_eq!(out.len(), data.len());
+        let list = parse_lst(&out, false).unwrap();
+        assert_eq!(list.items[0].entry, Entry::new(10, 0));
+        assert_eq!(list.items[1].id, 7);
+        assert_eq!(list.items[1].entry, Entry::new(11, 0));
+    }
+
+    #[test]
+    fn test_append_lst_items() {
+        let data = LstFixture::new("1.2")
+            .item("a", 1, Entry::new(10, 0))
+            .build();
+        let items = [
+            ListItem { name: "\u{e9}".into(), id: 0xE9, entry: Entry::new(10, 1) },
+            ListItem { name: "b".into(), id: 0, entry: Entry::new(10, 2) },
+        ];
+
+        let out = append_lst_items(&data, false, &items).unwrap();
+        let list = parse_lst(&out, false).unwrap();
+        assert_eq!(list.items.len(), 3);
+        assert_eq!(list.items[0].name, "a");
+        assert_eq!(list.get_item(0xE9).unwrap().name, "\u{e9}");
+        assert_eq!(list.items[2].entry, Entry::new(10, 2));
+        assert_eq!(&out[21..25], &[0xEA, 0, 0, 0]);
+
+        let long = [ListItem { name: "x".repeat(256), id: 3, entry: Entry::new(10, 3) }];
+        assert!(matches!(append_lst_items(&data, false, &long), Err(Error::UnencodableListItem(3))));
+        assert!(append_lst_items(&data[..data.len() - 1], false, &items).is_err());
+    }
+
+    #[test]
+    fn test_set_next_free_id() {
+        let mut data = LstFixture::new("1.0").build();
+
+        set_next_free_id(&mut data, 12).unwrap();
+        assert_eq!(&data[data.len() - 8..data.len() - 4], &[12, 0, 0, 0]);
+        assert!(set_next_free_id(&mut data[..20], 12).is_err());
+    }
+}
@@ -0,0 +1,7 @@
+#[cfg(feature = "std")]
+pub mod lst;
+pub mod rle;
+#[cfg(feature = "std")]
+pub mod rmd;
+#[cfg(feature = "std")]
+pub mod rmm;
@@ -0,0 +1,260 @@
+//! Writes RLE resource files. See `parser::rle` for the layout.
+
+use alloc::vec::Vec;
+
+use crate::entity::resource::Resource;
+use crate::entity::resource_file::{ResourceFile, ResourceSlot};
+use crate::error::Error;
+use crate::parser::rle::{PixelFormat, COLOR_KEY_565};
+
+const RLE_IDENTIFIER: &[u8] = b"Resource File\0";
+
+/// Builds a resource file from undecoded resources as returned by
+/// `parser::rle::raw_resources`; `None` entries are written as null offsets so
+/// the index of every resource is kept.
+pub fn write_raw_rle(resources: &[Option<&[u8]>]) -> Vec<u8> {
+    let header_len = RLE_IDENTIFIER.len() + 4 + 4 + 4 * resources.len();
+    let data_len: usize = resources.iter().map(|r| r.map_or(0, |r| r.len())).sum();
+
+    let mut out = Vec::<u8>::with_capacity(header_len + data_len);
+    out.extend_from_slice(RLE_IDENTIFIER);
+    // unknown_1: we assume it's the next free offset in the file
+    out.extend_from_slice(&((header_len + data_len) as u32).to_le_bytes());
+    out.extend_from_slice(&(resources.len() as u32).to_le_bytes());
+
+    let mut offset = header_len;
+    for resource in resources {
+        match *resource {
+            Some(bytes) => {
+                out.extend_from_slice(&(offset as u32).to_le_bytes());
+                offset += bytes.len();
+            }
+            None => out.extend_from_slice(&0u32.to_le_bytes()),
+        }
+    }
+    for resource in resources.iter().filter_map(|r| *r) {
+        out.extend_from_slice(resource);
+    }
+    out
+}
+
+/// Builds a resource file with the index layout `file` was parsed with. Empty
+/// slots are written as null offsets; the bytes of every other slot come from
+/// `encode`, which is called with the resource index.
+pub fn write_rle_layout<F>(file: &ResourceFile, mut encode: F) -> Vec<u8>
+    where F: FnMut(u32) -> Vec<u8>
+{
+    let encoded: Vec<Option<Vec<u8>>> = file.slots.iter().enumerate()
+        .map(|(index, slot)| match *slot {
+            ResourceSlot::Empty => None,
+            _ => Some(encode(index as u32)),
+        })
+        .collect();
+    let resources: Vec<Option<&[u8]>> = encoded.iter()
+        .map(|r| r.as_ref().map(|r| r.as_slice()))
+        .collect();
+    write_raw_rle(&resources)
+}
+
+/// Builds a resource file from decoded resources, in `format`, with the
+/// index layout `file` was parsed with. Undecoded slots are written as
+/// resources without a size, which aren't decoded either.
+pub fn write_rle(file: &ResourceFile, format: PixelFormat) -> Result<Vec<u8>, Error> {
+    let mut encoded = Vec::with_capacity(file.slots.len());
+    for (index, slot) in file.slots.iter().enumerate() {
+        encoded.push(match *slot {
+            ResourceSlot::Empty => None,
+            ResourceSlot::Resource(pos) => match file.resources.get(pos) {
+                Some(resource) => Some(encode_resource(resource, format)?),
+                None => return Err(Error::UnencodableResource(index as u32)),
+            },
+            ResourceSlot::Undecoded => Some(encode_runs(&Resource::new(), Vec::new())),
+        });
+    }
+    let resources: Vec<Option<&[u8]>> = encoded.iter()
+        .map(|r| r.as_ref().map(|r| r.as_slice()))
+        .collect();
+    Ok(write_raw_rle(&resources))
+}
+
+/// The resource header and pixel runs of a decoded resource, the inverse of
+/// `parser::rle::parse_rle_as` with the same `format`.
+///
+/// Every line is painted left to right, a `0x01` run for each stretch of
+/// painted pixels and a `0x02` move over each unpainted one. A pixel of
+/// `PixelFormat::Rgba8` or `Bgra8` counts as painted from an alpha of 0x80
+/// up, as the files have no alpha; its color is rounded to the nearest
+/// r5g6b5 one, which decodes back to the same bytes.
+pub fn encode_resource(resource: &Resource, format: PixelFormat) -> Result<Vec<u8>, Error> {
+    let (width, height) = (resource.width.max(0) as usize, resource.height.max(0) as usize);
+    let bytes_per_pixel = format.bytes_per_pixel();
+    if width == 0 || height == 0 || resource.image_raw.len() != width * height * bytes_per_pixel {
+        return Err(Error::UnencodableResource(resource.index()));
+    }
+    let pixel = |at: usize| -> Option<u16> {
+        let bytes = &resource.image_raw[at * bytes_per_pixel..(at + 1) * bytes_per_pixel];
+        match format {
+            PixelFormat::Rgba8 if bytes[3] >= 0x80 => Some(format_r5g6b5(bytes[0], bytes[1], bytes[2])),
+            PixelFormat::Bgra8 if bytes[3] >= 0x80 => Some(format_r5g6b5(bytes[2], bytes[1], bytes[0])),
+            PixelFormat::Rgba8 | PixelFormat::Bgra8 => None,
+            PixelFormat::R5g6b5 => Some(u16::from_le_bytes([bytes[0], bytes[1]])).filter(|&px| px != COLOR_KEY_565),
+        }
+    };
+
+    let mut runs = Vec::new();
+    // the column is kept from one line to the next
+    let mut x = 0;
+    let mut lines = 0;
+    for y in 0..height {
+        let mut start = 0;
+        while start < width {
+            if pixel(y * width + start).is_none() {
+                start += 1;
+                continue;
+            }
+            let end = (start..width).find(|&end| pixel(y * width + end).is_none()).unwrap_or(width);
+            runs.resize(runs.len() + (y - lines), 0x03);
+            lines = y;
+            if start != x {
+                runs.push(0x02);
+                runs.extend_from_slice(&((start as i32 - x as i32) * 2).to_le_bytes());
+            }
+            runs.push(0x01);
+            runs.extend_from_slice(&((end - start) as u32).to_le_bytes());
+            for at in start..end {
+                runs.extend_from_slice(&pixel(y * width + at).unwrap_or(0).to_le_bytes());
+            }
+            x = end;
+            start = end;
+        }
+    }
+    Ok(encode_runs(resource, runs))
+}
+
+/// The resource header of `resource` followed by `runs` and the end marker;
+/// the length in the header is the one of the runs and the marker.
+fn encode_runs(resource: &Resource, mut runs: Vec<u8>) -> Vec<u8> {
+    runs.push(0x00);
+    let mut out = Vec::with_capacity(36 + runs.len());
+    out.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+    for field in [resource.offset_x, resource.offset_y, resource.width, resource.height].iter() {
+        out.extend_from_slice(&field.to_le_bytes());
+    }
+    for field in [resource.unknown_1, resource.unknown_2, resource.unknown_3, resource.unknown_4].iter() {
+        out.extend_from_slice(&field.to_le_bytes());
+    }
+    out.extend_from_slice(&runs);
+    out
+}
+
+/// Inverse of `parser::rle::format_r5g6b5_norm`; every channel is
+/// `v * max / 255` rounded to the nearest, in integers so it needs no `std`
+pub(crate) fn format_r5g6b5(r: u8, g: u8, b: u8) -> u16 {
+    let round = |v: u8, max: u32| ((v as u32 * max * 2 + 255) / 510) as u16;
+    (round(r, 31) << 11) | (round(g, 63) << 5) | round(b, 31)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    use crate::parser::rle::{parse_rle, parse_rle_as};
+    use crate::parser::rle::raw_resources;
+    use crate::fixture::{RleFixture, ResourceFixture};
+
+    /// A 2x2 resource with red pixels at (1, 0) and (0, 1)
+    fn raw_resource() -> Vec<u8> {
+        ResourceFixture::new(2, 2)
+            .skip(1).pixels(&[0xF800])
+            .next_line().skip(-2).pixels(&[0xF800])
+            .encode()
+    }
+
+    #[test]
+    fn test_write_raw_rle_round_trip() {
+        let res = raw_resource();
+        let data = write_raw_rle(&[Some(&res), None, Some(&res)]);
+
+        let raw = raw_resources(&data).unwrap();
+        assert_eq!(raw.len(), 3);
+        assert_eq!(raw[0], Some(&res[..]));
+        assert_eq!(raw[1], None);
+        assert_eq!(raw[2], Some(&res[..]));
+
+        let rle = parse_rle(1, &data).unwrap();
+        assert_eq!(rle.resources.len(), 2);
+        assert_eq!(rle.resources[1].index(), 2);
+        assert_eq!(&rle.resources[1].image_raw[4..8], &[0xFF, 0, 0, 0xFF]);
+        assert_eq!(&rle.resources[1].image_raw[8..12], &[0xFF, 0, 0, 0xFF]);
+    }
+
+    #[test]
+    fn test_write_rle_layout() {
+        // null slots in front, between and behind, plus one undecodable resource
+        let data = RleFixture::new()
+            .null()
+            .resource(ResourceFixture::new(1, 1).pixels(&[0x001F]))
+            .null()
+            .resource(ResourceFixture::new(0, 0))
+            .resource(ResourceFixture::new(2, 2))
+            .null()
+            .build();
+        let rle = parse_rle(0, &data).unwrap();
+        let raw = raw_resources(&data).unwrap();
+
+        let written = write_rle_layout(&rle, |index| raw[index as usize].unwrap().to_vec());
+        assert_eq!(written, data);
+    }
+
+    #[test]
+    fn test_encode_resource() {
+        let data = write_raw_rle(&[Some(&raw_resource())]);
+        let rle = parse_rle(0, &data).unwrap();
+        assert_eq!(encode_resource(&rle.resources[0], PixelFormat::Rgba8).unwrap(), raw_resource());
+        assert_eq!(rle.encode().unwrap(), data);
+
+        let mut rle = parse_rle_as(0, &data, PixelFormat::R5g6b5).unwrap();
+        assert_eq!(write_rle(&rle, PixelFormat::R5g6b5).unwrap(), data);
+
+        rle.resources[0].image_raw.pop();
+        assert!(matches!(encode_resource(&rle.resources[0], PixelFormat::R5g6b5), Err(Error::UnencodableResource(0))));
+    }
+
+    #[test]
+    fn test_write_rle_round_trip() {
+        let data = RleFixture::new()
+            .null()
+            .resource(ResourceFixture::new(4, 3).offset(-2, 7)
+                .skip(3).pixels(&[0x001F])
+                .next_line().next_line().skip(-4).pixels(&[0xF800, 0x07E0, 0x1234]))
+            .resource(ResourceFixture::new(0, 0))
+            .resource(ResourceFixture::new(2, 2))
+            .build();
+        let rle = parse_rle(3, &data).unwrap();
+
+        let written = rle.encode().unwrap();
+        let again = parse_rle(3, &written).unwrap();
+        assert_eq!(again.slots, rle.slots);
+        for (resource, decoded) in rle.resources.iter().zip(again.resources.iter()) {
+            assert_eq!((decoded.offset_x, decoded.offset_y, decoded.width, decoded.height),
+                       (resource.offset_x, resource.offset_y, resource.width, resource.height));
+            assert_eq!(decoded.image_raw, resource.image_raw);
+        }
+        assert_eq!(again.encode().unwrap(), written);
+    }
+
+    #[test]
+    fn test_format_r5g6b5() {
+        // every color decodes and encodes back to itself
+        for color in 0..=0xFFFFu16 {
+            let data = write_raw_rle(&[Some(&ResourceFixture::new(1, 1).pixels(&[color]).encode())]);
+            let rgba = &parse_rle(0, &data).unwrap().resources[0].image_raw;
+            assert_eq!(format_r5g6b5(rgba[0], rgba[1], rgba[2]), color);
+        }
+        // the integer rounding is that of the floats
+        for v in 0..=255u8 {
+            let round = |max: f32| (v as f32 * max / 255.0).round() as u16;
+            assert_eq!(format_r5g6b5(v, v, v), (round(31.0) << 11) | (round(63.0) << 5) | round(31.0));
+        }
+    }
+}
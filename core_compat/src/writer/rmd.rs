@@ -0,0 +1,72 @@
+//! Rewrites the list ids referenced by the images of a RMD file in place;
+//! every other byte of the file is kept as it is. See `parser::rmd` for the
+//! layout.
+
+use std::io::Cursor;
+
+use byteorder::ReadBytesExt;
+use byteorder::WriteBytesExt;
+use byteorder::LittleEndian as LE;
+
+use crate::error::Error;
+
+/// Size of the image fields in front of the image id count.
+const IMAGE_FIELDS_SIZE: u64 = 10 * 4;
+
+/// Calls `remap` with every image id (a list item id) of every RMD entry and
+/// writes the returned ids back into a copy of `data`.
+pub fn remap_rmd_image_ids<F>(data: &[u8], mut remap: F) -> Result<Vec<u8>, Error>
+    where F: FnMut(i32) -> i32
+{
+    let mut cursor = Cursor::new(data);
+
+    // header: string, file number, 8 empty bytes, string, animation parts and
+    // rows, string
+    skip_string(&mut cursor)?;
+    cursor.set_position(cursor.position() + 12);
+    skip_string(&mut cursor)?;
+    cursor.set_position(cursor.position() + 8);
+    skip_string(&mut cursor)?;
+
+    let mut out = data.to_vec();
+    let entry_count = cursor.read_i32::<LE>()?;
+    for _ in 0..entry_count {
+        let image_count = cursor.read_i32::<LE>()?;
+        for _ in 0..image_count {
+            cursor.set_position(cursor.position() + IMAGE_FIELDS_SIZE);
+            let id_count = cursor.read_i32::<LE>()?;
+            for _ in 0..id_count {
+                let id_start = cursor.position() as usize;
+                let id = cursor.read_i32::<LE>()?;
+                (&mut out[id_start..id_start + 4]).write_i32::<LE>(remap(id))?;
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn skip_string(cursor: &mut Cursor<&[u8]>) -> Result<(), Error> {
+    let string_length = cursor.read_u8()?;
+    cursor.set_position(cursor.position() + string_length as u64);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::entity::rmd_type::RmdType;
+    use crate::parser::rmd::parse_rmd;
+    use crate::fixture::RmdFixture;
+
+    #[test]
+    fn test_remap_rmd_image_ids() {
+        let data = RmdFixture::new().entry(&[&[7, 9]]).animation(&[0]).build();
+
+        let out = remap_rmd_image_ids(&data, |id| if id == 9 { 3 } else { id }).unwrap();
+
+        assert_eq!(out.len(), data.len());
+        let rmd = parse_rmd(RmdType::Object, &out).unwrap();
+        assert_eq!(rmd.get_entry(0).unwrap().images()[0].image_id, vec![7, 3]);
+    }
+}
@@ -0,0 +1,104 @@
+//! Rewrites the RMD references of the tiles in a RMM map file in place; every
+//! other byte of the file is kept as it is. See `parser::rmm` for the layout.
+
+use std::io::Cursor;
+
+use byteorder::ReadBytesExt;
+use byteorder::LittleEndian as LE;
+
+use crate::error::Error;
+use crate::entity::entry::Entry;
+use crate::entity::rmd_type::RmdType;
+
+const TILE_SIZE: usize = 8;
+
+/// Calls `remap` with the object and the tile entry of every map tile and
+/// writes the returned entries back into a copy of `data`.
+pub fn remap_rmm_entries<F>(data: &[u8], mut remap: F) -> Result<Vec<u8>, Error>
+    where F: FnMut(RmdType, Entry) -> Entry
+{
+    let mut cursor = Cursor::new(data);
+
+    // skip the header
+    let string_length = cursor.read_u8()?;
+    cursor.set_position(1 + string_length as u64);
+    let size_x = cursor.read_u32::<LE>()?;
+    let size_y = cursor.read_u32::<LE>()?;
+    let id_count = cursor.read_u8()?;
+    cursor.set_position(cursor.position() + id_count as u64);
+    let _map_number = cursor.read_u32::<LE>()?;
+    let event_count = cursor.read_u32::<LE>()?;
+    let event_size = 2 + 4 * 4;
+    let tile_start = cursor.position() as usize + event_count as usize * event_size;
+
+    let tile_count = size_x as usize * size_y as usize;
+    if tile_start + tile_count * TILE_SIZE > data.len() {
+        return Err(Error::UnexpectedEndOfMap);
+    }
+
+    let mut out = data.to_vec();
+    for tile in 0..tile_count {
+        let start = tile_start + tile * TILE_SIZE;
+        let bytes = &mut out[start..start + TILE_SIZE];
+        let (obj, tle) = decode_tile_entries(bytes);
+        let new_obj = remap(RmdType::Object, obj);
+        let new_tle = remap(RmdType::Tile, tle);
+        if new_obj != obj || new_tle != tle {
+            encode_tile_entries(bytes, new_obj, new_tle)?;
+        }
+    }
+    Ok(out)
+}
+
+/// Same bit layout as `parser::rmm::parse_v2`
+fn decode_tile_entries(b: &[u8]) -> (Entry, Entry) {
+    let (b_0, b_1, b_2, b_3) = (b[0] as u32, b[1] as u32, b[2] as u32, b[3] as u32);
+    let (b_6, b_7) = (b[6] as u32, b[7] as u32);
+    let obj_file_num = (b_0 >> 2) + ((b_1 & 0x1F) << 6);
+    let tle_file_idx = (b_1 >> 5) + ((b_2 & 0x7F) << 3);
+    let tle_file_num = (b_2 >> 7) + (b_3 << 1);
+    let obj_file_idx = (b_7 << 1) + if b_6.is_multiple_of(24) { 0 } else { 1 };
+    (Entry::new(obj_file_num, obj_file_idx), Entry::new(tle_file_num, tle_file_idx))
+}
+
+/// Inverse of `decode_tile_entries`. The lowest bit of the object index is
+/// implied by the collision byte, so it can't be changed here.
+pub(crate) fn encode_tile_entries(b: &mut [u8], obj: Entry, tle: Entry) -> Result<(), Error> {
+    let parity = if b[6].is_multiple_of(24) { 0 } else { 1 };
+    if obj.file() >= 1 << 11 || obj.index() >= 1 << 9 || obj.index() & 1 != parity {
+        return Err(Error::UnencodableMapEntry(obj));
+    }
+    if tle.file() >= 1 << 9 || tle.index() >= 1 << 10 {
+        return Err(Error::UnencodableMapEntry(tle));
+    }
+    b[0] = (b[0] & 0x03) | ((obj.file() & 0x3F) << 2) as u8;
+    b[1] = ((obj.file() >> 6) & 0x1F) as u8 | ((tle.index() & 0x07) << 5) as u8;
+    b[2] = ((tle.index() >> 3) & 0x7F) as u8 | ((tle.file() & 0x01) << 7) as u8;
+    b[3] = (tle.file() >> 1) as u8;
+    b[7] = (obj.index() >> 1) as u8;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tile_entry_round_trip() {
+        let mut bytes = [0x01, 0, 0, 0, 0x10, 0, 0x03, 0];
+        encode_tile_entries(&mut bytes, Entry::new(1234, 201), Entry::new(300, 777)).unwrap();
+        let (obj, tle) = decode_tile_entries(&bytes);
+        assert_eq!(obj, Entry::new(1234, 201));
+        assert_eq!(tle, Entry::new(300, 777));
+        // the untouched bits survive
+        assert_eq!(bytes[0] & 0x03, 0x01);
+        assert_eq!(bytes[4], 0x10);
+    }
+
+    #[test]
+    fn test_tile_entry_wrong_parity() {
+        let mut bytes = [0u8; 8];
+        let result = encode_tile_entries(&mut bytes, Entry::new(1, 3), Entry::new(0, 0));
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,67 @@
+//! Runs the inputs in `tests/corpus/` through every parser; see the README
+//! there. The rmi parser and the lazy files need the `std` feature.
+
+#![cfg(feature = "std")]
+
+extern crate core_compat;
+
+use std::fs::{read, read_dir};
+use std::panic::catch_unwind;
+use std::path::PathBuf;
+
+use core_compat::entity::resource_file::ResourceFile;
+use core_compat::entity::rmd_type::RmdType;
+use core_compat::parser::lst::parse_lst;
+use core_compat::parser::rle::{parse_rle, raw_resources};
+use core_compat::parser::rmd::parse_rmd;
+use core_compat::parser::rmi::parse_rmi;
+use core_compat::parser::rmm::parse_rmm;
+
+/// Returns whether each parser accepted the data, by parser folder name
+fn parse_all(data: &[u8]) -> Vec<(&'static str, bool)> {
+    // every resource decoded lazily too, so that path can't panic either
+    let lazy = ResourceFile::open_lazy(0, data)
+        .is_ok_and(|lazy| (0..lazy.len() as u32).all(|index| lazy.get(index).is_ok()));
+    vec![
+        ("rle", parse_rle(0, data).is_ok() && raw_resources(data).is_ok() && lazy),
+        ("lst", parse_lst(data, false).is_ok() || parse_lst(data, true).is_ok()),
+        ("rmd", parse_rmd(RmdType::Object, data).is_ok()),
+        ("rmm", parse_rmm(data).is_ok()),
+        ("rmi", parse_rmi(data).is_ok()),
+    ]
+}
+
+fn corpus() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for folder in read_dir("tests/corpus").unwrap() {
+        let folder = folder.unwrap().path();
+        if folder.is_dir() {
+            for file in read_dir(&folder).unwrap() {
+                paths.push(file.unwrap().path());
+            }
+        }
+    }
+    paths.sort();
+    paths
+}
+
+#[test]
+fn test_fuzz_corpus() {
+    let paths = corpus();
+    assert!(!paths.is_empty());
+
+    let mut failures = Vec::new();
+    for path in paths.iter() {
+        let data = read(path).unwrap();
+        let parser = path.parent().unwrap().file_name().unwrap().to_str().unwrap();
+        match catch_unwind(|| parse_all(&data)) {
+            Ok(results) => {
+                if results.iter().any(|&(name, ok)| name == parser && ok) {
+                    failures.push(format!("{:?}: accepted by the {} parser", path, parser));
+                }
+            }
+            Err(_) => failures.push(format!("{:?}: panicked", path)),
+        }
+    }
+    assert!(failures.is_empty(), "{:#?}", failures);
+}
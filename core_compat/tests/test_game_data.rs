@@ -0,0 +1,56 @@
+//! Parses files of the original client data. The data can't be shipped with
+//! the repository, so these only run with `cargo test -- --ignored` after it
+//! has been copied to `../data/`.
+
+extern crate core_compat;
+
+use std::fs::File;
+use std::io::Read;
+
+use core_compat::entity::rmd_type::RmdType;
+use core_compat::parser::lst::parse_lst;
+use core_compat::parser::rle::parse_rle;
+use core_compat::parser::rmd::parse_rmd;
+use core_compat::parser::rmm::parse_rmm;
+
+fn load(path: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    File::open(format!("../data/{}", path))
+        .and_then(|mut file| file.read_to_end(&mut data))
+        .expect("the game data needs to be in `../data/`");
+    data
+}
+
+#[test]
+#[ignore]
+fn test_game_data_rmm() {
+    for path in ["DATAs/Map/Map00001.rmm", "DATAs/Map/Map00003.rmm", "DATAs/Map/Map00005.rmm"].iter() {
+        let map = parse_rmm(&load(path)).unwrap();
+        assert_eq!((map.size_x() * map.size_y()) as usize, map.tile_count());
+    }
+}
+
+#[test]
+#[ignore]
+fn test_game_data_rmd() {
+    parse_rmd(RmdType::Tile, &load("DATAs/Tle/tle00001.rmd")).unwrap();
+    parse_rmd(RmdType::Object, &load("DATAs/Obj/obj00001.rmd")).unwrap();
+    parse_rmd(RmdType::Character, &load("DATAs/Chr/chr00001.rmd")).unwrap();
+    parse_rmd(RmdType::Character, &load("DATAs/Chr/chr00042.rmd")).unwrap();
+}
+
+#[test]
+#[ignore]
+fn test_game_data_lst() {
+    for path in ["RLEs/bul.lst", "RLEs/ico.lst", "RLEs/int.lst", "RLEs/tle.lst", "RLEs/obj.lst"].iter() {
+        parse_lst(&load(path), false).unwrap();
+    }
+}
+
+#[test]
+#[ignore]
+fn test_game_data_rle() {
+    parse_rle(0, &load("RLEs/Chr/C00/c0000000.rle")).unwrap();
+    parse_rle(42, &load("RLEs/Chr/C00/c0000042.rle")).unwrap();
+    parse_rle(0, &load("RLEs/Ico/ico00000.rle")).unwrap();
+}
@@ -0,0 +1,22 @@
+use std::io;
+use std::string::FromUtf8Error;
+
+#[derive(Debug)]
+pub enum Error {
+    FromUtf8(FromUtf8Error),
+    Io(io::Error),
+    PacketLengthMismatch(u8),
+    UnknownOpcode(u8),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<FromUtf8Error> for Error {
+    fn from(err: FromUtf8Error) -> Error {
+        Error::FromUtf8(err)
+    }
+}
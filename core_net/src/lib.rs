@@ -0,0 +1,6 @@
+// external
+extern crate byteorder;
+
+pub mod error;
+pub mod packet;
+pub mod stream;
@@ -0,0 +1,472 @@
+//! Packets between the server emulator and its clients.
+//!
+//! The original protocol isn't decoded yet (see `server::packet` for the
+//! tooling), so the emulator speaks its own. Every packet is framed as
+//!
+//! [FRAME]
+//! u16 length of the opcode and payload
+//! u8 opcode
+//! payload
+//!
+//! Numbers are little endian, strings a u16 length followed by UTF-8. The
+//! opcodes of packets the server sends have the high bit set.
+
+use std::io::Cursor;
+use std::io::Read;
+
+use byteorder::ReadBytesExt;
+use byteorder::WriteBytesExt;
+use byteorder::LittleEndian as LE;
+
+use crate::error::Error;
+
+/// Length of the frame header
+pub const HEADER_SIZE: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Packet {
+    // client -> server
+    Login { name: String },
+    Walk { x: u16, y: u16 },
+    Attack { target: u32 },
+    Say { text: String },
+    /// Answered with a `Pong` on the next tick
+    Ping { seq: u32 },
+    PartyInvite { target: u32 },
+    PartyAccept { leader: u32 },
+    PartyLeave,
+    GuildCreate { name: String },
+    GuildInvite { target: u32 },
+    GuildAccept { guild: u32 },
+    GuildLeave,
+    TradeRequest { target: u32 },
+    TradeAccept { from: u32 },
+    /// How many of an item to offer in total; 0 takes it off the offer
+    TradeOffer { item: u32, count: u32 },
+    TradeConfirm,
+    TradeCancel,
+    /// Picks up the items on the ground `id`
+    PickUp { id: u32 },
+    /// Uses a skill with a range on `target`, answered by a `Damage`
+    UseSkill { skill: u32, target: u32 },
+    // server -> client
+    LoginOk { id: u32, map: u32, x: u16, y: u16 },
+    Spawn { id: u32, name: String, x: u16, y: u16, hp: u16 },
+    Despawn { id: u32 },
+    Moved { id: u32, x: u16, y: u16 },
+    Damage { attacker: u32, target: u32, amount: u16, hp: u16 },
+    Chat { from: u32, text: String },
+    /// `tick_overruns` counts the ticks that took longer than the tick rate
+    Pong { seq: u32, tick: u64, tick_overruns: u32 },
+    PartyInvited { leader: u32, name: String },
+    /// The player's party, leader first; no members once it's left
+    Party { members: Vec<u32> },
+    GuildInvited { guild: u32, name: String },
+    /// The player's guild by member name, as guilds outlast the sessions;
+    /// guild 0 once it's left
+    Guild { guild: u32, name: String, leader: String, members: Vec<String> },
+    TradeRequested { from: u32, name: String },
+    /// The open trade with `with`, offers as item and count; sent when it
+    /// opens and on every change
+    Trade { with: u32, mine: Vec<(u32, u32)>, theirs: Vec<(u32, u32)>, confirmed: bool, they_confirmed: bool },
+    TradeClosed { completed: bool },
+    /// The player's items and their counts
+    Inventory { items: Vec<(u32, u32)> },
+    /// Items on the ground came into view; they go with a `Despawn` like
+    /// the other entities
+    GroundItem { id: u32, item: u32, count: u32, x: u16, y: u16 },
+    /// The time of day in minutes since midnight, the seconds a day lasts so
+    /// the client keeps the clock going, and the weather, as in
+    /// `core_rules::ambiance`
+    Ambiance { minute: u16, day_length: u32, weather: u8 },
+    /// The server is going down, for the reason given; the connection
+    /// closes right after
+    Shutdown { message: String },
+}
+
+impl Packet {
+    pub fn opcode(&self) -> u8 {
+        match *self {
+            Packet::Login { .. } => 0x01,
+            Packet::Walk { .. } => 0x02,
+            Packet::Attack { .. } => 0x03,
+            Packet::Say { .. } => 0x04,
+            Packet::Ping { .. } => 0x05,
+            Packet::PartyInvite { .. } => 0x06,
+            Packet::PartyAccept { .. } => 0x07,
+            Packet::PartyLeave => 0x08,
+            Packet::GuildCreate { .. } => 0x09,
+            Packet::GuildInvite { .. } => 0x0A,
+            Packet::GuildAccept { .. } => 0x0B,
+            Packet::GuildLeave => 0x0C,
+            Packet::TradeRequest { .. } => 0x0D,
+            Packet::TradeAccept { .. } => 0x0E,
+            Packet::TradeOffer { .. } => 0x0F,
+            Packet::TradeConfirm => 0x10,
+            Packet::TradeCancel => 0x11,
+            Packet::PickUp { .. } => 0x12,
+            Packet::UseSkill { .. } => 0x13,
+            Packet::LoginOk { .. } => 0x81,
+            Packet::Spawn { .. } => 0x82,
+            Packet::Despawn { .. } => 0x83,
+            Packet::Moved { .. } => 0x84,
+            Packet::Damage { .. } => 0x85,
+            Packet::Chat { .. } => 0x86,
+            Packet::Pong { .. } => 0x87,
+            Packet::PartyInvited { .. } => 0x88,
+            Packet::Party { .. } => 0x89,
+            Packet::GuildInvited { .. } => 0x8A,
+            Packet::Guild { .. } => 0x8B,
+            Packet::TradeRequested { .. } => 0x8C,
+            Packet::Trade { .. } => 0x8D,
+            Packet::TradeClosed { .. } => 0x8E,
+            Packet::Inventory { .. } => 0x8F,
+            Packet::GroundItem { .. } => 0x90,
+            Packet::Ambiance { .. } => 0x91,
+            Packet::Shutdown { .. } => 0x92,
+        }
+    }
+
+    /// The framed packet
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = vec![self.opcode()];
+        match *self {
+            Packet::Login { ref name } => write_string(&mut body, name),
+            Packet::Walk { x, y } => {
+                body.write_u16::<LE>(x).unwrap();
+                body.write_u16::<LE>(y).unwrap();
+            }
+            Packet::Attack { target } => body.write_u32::<LE>(target).unwrap(),
+            Packet::Say { ref text } => write_string(&mut body, text),
+            Packet::Ping { seq } => body.write_u32::<LE>(seq).unwrap(),
+            Packet::PartyInvite { target } | Packet::GuildInvite { target } => body.write_u32::<LE>(target).unwrap(),
+            Packet::PartyAccept { leader } => body.write_u32::<LE>(leader).unwrap(),
+            Packet::PartyLeave | Packet::GuildLeave => (),
+            Packet::GuildCreate { ref name } => write_string(&mut body, name),
+            Packet::GuildAccept { guild } => body.write_u32::<LE>(guild).unwrap(),
+            Packet::TradeRequest { target } => body.write_u32::<LE>(target).unwrap(),
+            Packet::TradeAccept { from } => body.write_u32::<LE>(from).unwrap(),
+            Packet::TradeOffer { item, count } => {
+                body.write_u32::<LE>(item).unwrap();
+                body.write_u32::<LE>(count).unwrap();
+            }
+            Packet::TradeConfirm | Packet::TradeCancel => (),
+            Packet::PickUp { id } => body.write_u32::<LE>(id).unwrap(),
+            Packet::UseSkill { skill, target } => {
+                body.write_u32::<LE>(skill).unwrap();
+                body.write_u32::<LE>(target).unwrap();
+            }
+            Packet::LoginOk { id, map, x, y } => {
+                body.write_u32::<LE>(id).unwrap();
+                body.write_u32::<LE>(map).unwrap();
+                body.write_u16::<LE>(x).unwrap();
+                body.write_u16::<LE>(y).unwrap();
+            }
+            Packet::Spawn { id, ref name, x, y, hp } => {
+                body.write_u32::<LE>(id).unwrap();
+                write_string(&mut body, name);
+                body.write_u16::<LE>(x).unwrap();
+                body.write_u16::<LE>(y).unwrap();
+                body.write_u16::<LE>(hp).unwrap();
+            }
+            Packet::Despawn { id } => body.write_u32::<LE>(id).unwrap(),
+            Packet::Moved { id, x, y } => {
+                body.write_u32::<LE>(id).unwrap();
+                body.write_u16::<LE>(x).unwrap();
+                body.write_u16::<LE>(y).unwrap();
+            }
+            Packet::Damage { attacker, target, amount, hp } => {
+                body.write_u32::<LE>(attacker).unwrap();
+                body.write_u32::<LE>(target).unwrap();
+                body.write_u16::<LE>(amount).unwrap();
+                body.write_u16::<LE>(hp).unwrap();
+            }
+            Packet::Chat { from, ref text } => {
+                body.write_u32::<LE>(from).unwrap();
+                write_string(&mut body, text);
+            }
+            Packet::Pong { seq, tick, tick_overruns } => {
+                body.write_u32::<LE>(seq).unwrap();
+                body.write_u64::<LE>(tick).unwrap();
+                body.write_u32::<LE>(tick_overruns).unwrap();
+            }
+            Packet::PartyInvited { leader, ref name } => {
+                body.write_u32::<LE>(leader).unwrap();
+                write_string(&mut body, name);
+            }
+            Packet::Party { ref members } => {
+                body.write_u16::<LE>(members.len() as u16).unwrap();
+                for &member in members.iter() {
+                    body.write_u32::<LE>(member).unwrap();
+                }
+            }
+            Packet::GuildInvited { guild, ref name } => {
+                body.write_u32::<LE>(guild).unwrap();
+                write_string(&mut body, name);
+            }
+            Packet::Guild { guild, ref name, ref leader, ref members } => {
+                body.write_u32::<LE>(guild).unwrap();
+                write_string(&mut body, name);
+                write_string(&mut body, leader);
+                body.write_u16::<LE>(members.len() as u16).unwrap();
+                for member in members.iter() {
+                    write_string(&mut body, member);
+                }
+            }
+            Packet::TradeRequested { from, ref name } => {
+                body.write_u32::<LE>(from).unwrap();
+                write_string(&mut body, name);
+            }
+            Packet::Trade { with, ref mine, ref theirs, confirmed, they_confirmed } => {
+                body.write_u32::<LE>(with).unwrap();
+                write_items(&mut body, mine);
+                write_items(&mut body, theirs);
+                body.write_u8(confirmed as u8).unwrap();
+                body.write_u8(they_confirmed as u8).unwrap();
+            }
+            Packet::TradeClosed { completed } => body.write_u8(completed as u8).unwrap(),
+            Packet::Inventory { ref items } => write_items(&mut body, items),
+            Packet::GroundItem { id, item, count, x, y } => {
+                body.write_u32::<LE>(id).unwrap();
+                body.write_u32::<LE>(item).unwrap();
+                body.write_u32::<LE>(count).unwrap();
+                body.write_u16::<LE>(x).unwrap();
+                body.write_u16::<LE>(y).unwrap();
+            }
+            Packet::Ambiance { minute, day_length, weather } => {
+                body.write_u16::<LE>(minute).unwrap();
+                body.write_u32::<LE>(day_length).unwrap();
+                body.write_u8(weather).unwrap();
+            }
+            Packet::Shutdown { ref message } => write_string(&mut body, message),
+        }
+        let mut frame = Vec::with_capacity(HEADER_SIZE + body.len());
+        frame.write_u16::<LE>(body.len() as u16).unwrap();
+        frame.extend_from_slice(&body);
+        frame
+    }
+}
+
+/// Decodes the packet at the start of `data`; returns the packet and the
+/// length of its frame, or `None` if the frame isn't complete yet.
+pub fn decode(data: &[u8]) -> Result<Option<(Packet, usize)>, Error> {
+    if data.len() < HEADER_SIZE {
+        return Ok(None);
+    }
+    let length = (data[0] as usize) | (data[1] as usize) << 8;
+    let body = match data.get(HEADER_SIZE..HEADER_SIZE + length) {
+        Some(body) => body,
+        None => return Ok(None),
+    };
+    let mut cursor = Cursor::new(body);
+    let opcode = cursor.read_u8()?;
+    let packet = match opcode {
+        0x01 => Packet::Login { name: read_string(&mut cursor)? },
+        0x02 => Packet::Walk { x: cursor.read_u16::<LE>()?, y: cursor.read_u16::<LE>()? },
+        0x03 => Packet::Attack { target: cursor.read_u32::<LE>()? },
+        0x04 => Packet::Say { text: read_string(&mut cursor)? },
+        0x05 => Packet::Ping { seq: cursor.read_u32::<LE>()? },
+        0x06 => Packet::PartyInvite { target: cursor.read_u32::<LE>()? },
+        0x07 => Packet::PartyAccept { leader: cursor.read_u32::<LE>()? },
+        0x08 => Packet::PartyLeave,
+        0x09 => Packet::GuildCreate { name: read_string(&mut cursor)? },
+        0x0A => Packet::GuildInvite { target: cursor.read_u32::<LE>()? },
+        0x0B => Packet::GuildAccept { guild: cursor.read_u32::<LE>()? },
+        0x0C => Packet::GuildLeave,
+        0x0D => Packet::TradeRequest { target: cursor.read_u32::<LE>()? },
+        0x0E => Packet::TradeAccept { from: cursor.read_u32::<LE>()? },
+        0x0F => Packet::TradeOffer { item: cursor.read_u32::<LE>()?, count: cursor.read_u32::<LE>()? },
+        0x10 => Packet::TradeConfirm,
+        0x11 => Packet::TradeCancel,
+        0x12 => Packet::PickUp { id: cursor.read_u32::<LE>()? },
+        0x13 => Packet::UseSkill { skill: cursor.read_u32::<LE>()?, target: cursor.read_u32::<LE>()? },
+        0x81 => Packet::LoginOk {
+            id: cursor.read_u32::<LE>()?,
+            map: cursor.read_u32::<LE>()?,
+            x: cursor.read_u16::<LE>()?,
+            y: cursor.read_u16::<LE>()?,
+        },
+        0x82 => Packet::Spawn {
+            id: cursor.read_u32::<LE>()?,
+            name: read_string(&mut cursor)?,
+            x: cursor.read_u16::<LE>()?,
+            y: cursor.read_u16::<LE>()?,
+            hp: cursor.read_u16::<LE>()?,
+        },
+        0x83 => Packet::Despawn { id: cursor.read_u32::<LE>()? },
+        0x84 => Packet::Moved {
+            id: cursor.read_u32::<LE>()?,
+            x: cursor.read_u16::<LE>()?,
+            y: cursor.read_u16::<LE>()?,
+        },
+        0x85 => Packet::Damage {
+            attacker: cursor.read_u32::<LE>()?,
+            target: cursor.read_u32::<LE>()?,
+            amount: cursor.read_u16::<LE>()?,
+            hp: cursor.read_u16::<LE>()?,
+        },
+        0x86 => Packet::Chat { from: cursor.read_u32::<LE>()?, text: read_string(&mut cursor)? },
+        0x87 => Packet::Pong {
+            seq: cursor.read_u32::<LE>()?,
+            tick: cursor.read_u64::<LE>()?,
+            tick_overruns: cursor.read_u32::<LE>()?,
+        },
+        0x88 => Packet::PartyInvited { leader: cursor.read_u32::<LE>()?, name: read_string(&mut cursor)? },
+        0x89 => {
+            let count = cursor.read_u16::<LE>()?;
+            let mut members = Vec::new();
+            for _ in 0..count {
+                members.push(cursor.read_u32::<LE>()?);
+            }
+            Packet::Party { members }
+        }
+        0x8A => Packet::GuildInvited { guild: cursor.read_u32::<LE>()?, name: read_string(&mut cursor)? },
+        0x8B => {
+            let guild = cursor.read_u32::<LE>()?;
+            let name = read_string(&mut cursor)?;
+            let leader = read_string(&mut cursor)?;
+            let count = cursor.read_u16::<LE>()?;
+            let mut members = Vec::new();
+            for _ in 0..count {
+                members.push(read_string(&mut cursor)?);
+            }
+            Packet::Guild { guild, name, leader, members }
+        }
+        0x8C => Packet::TradeRequested { from: cursor.read_u32::<LE>()?, name: read_string(&mut cursor)? },
+        0x8D => Packet::Trade {
+            with: cursor.read_u32::<LE>()?,
+            mine: read_items(&mut cursor)?,
+            theirs: read_items(&mut cursor)?,
+            confirmed: cursor.read_u8()? != 0,
+            they_confirmed: cursor.read_u8()? != 0,
+        },
+        0x8E => Packet::TradeClosed { completed: cursor.read_u8()? != 0 },
+        0x8F => Packet::Inventory { items: read_items(&mut cursor)? },
+        0x90 => Packet::GroundItem {
+            id: cursor.read_u32::<LE>()?,
+            item: cursor.read_u32::<LE>()?,
+            count: cursor.read_u32::<LE>()?,
+            x: cursor.read_u16::<LE>()?,
+            y: cursor.read_u16::<LE>()?,
+        },
+        0x91 => Packet::Ambiance {
+            minute: cursor.read_u16::<LE>()?,
+            day_length: cursor.read_u32::<LE>()?,
+            weather: cursor.read_u8()?,
+        },
+        0x92 => Packet::Shutdown { message: read_string(&mut cursor)? },
+        _ => return Err(Error::UnknownOpcode(opcode)),
+    };
+    if cursor.position() as usize != length {
+        return Err(Error::PacketLengthMismatch(opcode));
+    }
+    Ok(Some((packet, HEADER_SIZE + length)))
+}
+
+fn write_string(body: &mut Vec<u8>, string: &str) {
+    body.write_u16::<LE>(string.len() as u16).unwrap();
+    body.extend_from_slice(string.as_bytes());
+}
+
+fn read_string(cursor: &mut Cursor<&[u8]>) -> Result<String, Error> {
+    let length = cursor.read_u16::<LE>()? as usize;
+    let mut data = vec![0u8; length];
+    cursor.read_exact(&mut data)?;
+    Ok(String::from_utf8(data)?)
+}
+
+/// Items as item and count, after their number
+fn write_items(body: &mut Vec<u8>, items: &[(u32, u32)]) {
+    body.write_u16::<LE>(items.len() as u16).unwrap();
+    for &(item, count) in items.iter() {
+        body.write_u32::<LE>(item).unwrap();
+        body.write_u32::<LE>(count).unwrap();
+    }
+}
+
+fn read_items(cursor: &mut Cursor<&[u8]>) -> Result<Vec<(u32, u32)>, Error> {
+    let length = cursor.read_u16::<LE>()?;
+    let mut items = Vec::new();
+    for _ in 0..length {
+        items.push((cursor.read_u32::<LE>()?, cursor.read_u32::<LE>()?));
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let packets = vec![
+            Packet::Login { name: "Lunarena".into() },
+            Packet::Walk { x: 10, y: 300 },
+            Packet::Attack { target: 7 },
+            Packet::Say { text: "안녕".into() },
+            Packet::Ping { seq: 9 },
+            Packet::LoginOk { id: 1, map: 3, x: 20, y: 30 },
+            Packet::Spawn { id: 2, name: "Kitara".into(), x: 1, y: 2, hp: 100 },
+            Packet::Despawn { id: 2 },
+            Packet::Moved { id: 1, x: 21, y: 30 },
+            Packet::Damage { attacker: 1, target: 2, amount: 10, hp: 90 },
+            Packet::Chat { from: 1, text: "hi".into() },
+            Packet::Pong { seq: 9, tick: 1 << 40, tick_overruns: 2 },
+            Packet::PartyInvite { target: 2 },
+            Packet::PartyAccept { leader: 1 },
+            Packet::PartyLeave,
+            Packet::GuildCreate { name: "달빛".into() },
+            Packet::GuildInvite { target: 2 },
+            Packet::GuildAccept { guild: 4 },
+            Packet::GuildLeave,
+            Packet::PartyInvited { leader: 1, name: "Lunarena".into() },
+            Packet::Party { members: vec![1, 2, 3] },
+            Packet::GuildInvited { guild: 4, name: "달빛".into() },
+            Packet::Guild { guild: 4, name: "달빛".into(), leader: "Lunarena".into(), members: vec!["Lunarena".into(), "Kitara".into()] },
+            Packet::Guild { guild: 0, name: String::new(), leader: String::new(), members: Vec::new() },
+            Packet::TradeRequest { target: 2 },
+            Packet::TradeAccept { from: 1 },
+            Packet::TradeOffer { item: 7, count: 300 },
+            Packet::TradeConfirm,
+            Packet::TradeCancel,
+            Packet::TradeRequested { from: 1, name: "Lunarena".into() },
+            Packet::Trade { with: 2, mine: vec![(7, 300), (9, 1)], theirs: Vec::new(), confirmed: true, they_confirmed: false },
+            Packet::TradeClosed { completed: true },
+            Packet::Inventory { items: vec![(7, 1000)] },
+            Packet::PickUp { id: 12 },
+            Packet::UseSkill { skill: 3, target: 12 },
+            Packet::GroundItem { id: 12, item: 7, count: 30, x: 4, y: 5 },
+            Packet::Ambiance { minute: 1439, day_length: 7200, weather: 2 },
+            Packet::Shutdown { message: "back soon".into() },
+        ];
+        let mut stream = Vec::new();
+        for packet in packets.iter() {
+            stream.extend_from_slice(&packet.encode());
+        }
+        let mut decoded = Vec::new();
+        let mut offset = 0;
+        while let Some((packet, length)) = decode(&stream[offset..]).unwrap() {
+            decoded.push(packet);
+            offset += length;
+        }
+        assert_eq!(decoded, packets);
+        assert_eq!(offset, stream.len());
+    }
+
+    #[test]
+    fn test_decode_partial_and_invalid() {
+        let frame = Packet::Walk { x: 1, y: 2 }.encode();
+        assert_eq!(frame, vec![5, 0, 0x02, 1, 0, 2, 0]);
+        assert!(decode(&frame[..1]).unwrap().is_none());
+        assert!(decode(&frame[..6]).unwrap().is_none());
+
+        match decode(&[1, 0, 0x7F]) {
+            Err(Error::UnknownOpcode(0x7F)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match decode(&[6, 0, 0x02, 1, 0, 2, 0, 9]) {
+            Err(Error::PacketLengthMismatch(0x02)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! Reading packets off a byte stream
+
+use std::io::Read;
+
+use crate::error::Error;
+use crate::packet::{decode, Packet};
+
+pub struct PacketReader<R> {
+    inner: R,
+    /// Received bytes that don't make a whole packet yet
+    buffer: Vec<u8>,
+}
+
+impl<R: Read> PacketReader<R> {
+    pub fn new(inner: R) -> PacketReader<R> {
+        PacketReader { inner, buffer: Vec::new() }
+    }
+
+    /// The next packet; `None` once the stream is closed
+    pub fn read_packet(&mut self) -> Result<Option<Packet>, Error> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            if let Some((packet, length)) = decode(&self.buffer)? {
+                self.buffer.drain(..length);
+                return Ok(Some(packet));
+            }
+            let bytes = self.inner.read(&mut chunk)?;
+            if bytes == 0 {
+                return Ok(None);
+            }
+            self.buffer.extend_from_slice(&chunk[..bytes]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hands out the data a few bytes at a time
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let bytes = self.0.len().min(buf.len()).min(3);
+            buf[..bytes].copy_from_slice(&self.0[..bytes]);
+            self.0 = &self.0[bytes..];
+            Ok(bytes)
+        }
+    }
+
+    #[test]
+    fn test_read_packet() {
+        let mut data = Packet::Login { name: "Canon".into() }.encode();
+        data.extend_from_slice(&Packet::Ping { seq: 1 }.encode());
+        let mut reader = PacketReader::new(Trickle(&data));
+        assert_eq!(reader.read_packet().unwrap(), Some(Packet::Login { name: "Canon".into() }));
+        assert_eq!(reader.read_packet().unwrap(), Some(Packet::Ping { seq: 1 }));
+        assert_eq!(reader.read_packet().unwrap(), None);
+    }
+}
@@ -0,0 +1,160 @@
+//! The time of day and the weather the whole world shares: the server keeps
+//! them and its clients tint the map by them, so everyone sees the same.
+//!
+//! A day has `MINUTES_PER_DAY` minutes. Daylight goes through the keyframes
+//! of `DAYLIGHT`, blending from each to the next, and the weather dims it and
+//! takes some of its colour away.
+
+use crate::random::Rng;
+
+pub const MINUTES_PER_DAY: u16 = 24 * 60;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Weather {
+    Clear,
+    Rain,
+    Snow,
+    Fog,
+}
+
+impl Weather {
+    pub const ALL: [Weather; 4] = [Weather::Clear, Weather::Rain, Weather::Snow, Weather::Fog];
+
+    /// The number it goes by on the wire
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_code(code: u8) -> Option<Weather> {
+        Weather::ALL.get(code as usize).cloned()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Weather::Clear => "clear",
+            Weather::Rain => "rain",
+            Weather::Snow => "snow",
+            Weather::Fog => "fog",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Weather> {
+        Weather::ALL.iter().cloned().find(|weather| weather.name() == name)
+    }
+
+    /// One of `Weather::ALL`, each as likely as its weight; clear if all the
+    /// weights are 0
+    pub fn roll(weights: &[u32; 4], rng: &mut Rng) -> Weather {
+        let total: u32 = weights.iter().sum();
+        if total == 0 {
+            return Weather::Clear;
+        }
+        let mut roll = rng.below(total);
+        for (&weather, &weight) in Weather::ALL.iter().zip(weights.iter()) {
+            if roll < weight {
+                return weather;
+            }
+            roll -= weight;
+        }
+        unreachable!()
+    }
+}
+
+/// The minutes daylight passes through and its colour there, which the map
+/// is multiplied by
+const DAYLIGHT: [(u16, [u8; 3]); 7] = [
+    (0, [60, 70, 130]),
+    (5 * 60, [60, 70, 130]),
+    (7 * 60, [255, 200, 170]),
+    (9 * 60, [255, 255, 255]),
+    (17 * 60, [255, 255, 255]),
+    (19 * 60, [255, 170, 130]),
+    (21 * 60, [60, 70, 130]),
+];
+
+/// The colour of daylight at `minute` of the day
+pub fn daylight(minute: u16) -> [u8; 3] {
+    let minute = minute % MINUTES_PER_DAY;
+    let next = DAYLIGHT.iter().position(|&(start, _)| start > minute).unwrap_or(DAYLIGHT.len());
+    let (start, from) = DAYLIGHT[next - 1];
+    let (end, to) = DAYLIGHT.get(next).cloned().unwrap_or((MINUTES_PER_DAY, DAYLIGHT[0].1));
+    let (done, span) = ((minute - start) as i32, (end - start) as i32);
+    let mut colour = [0; 3];
+    for (channel, (&from, &to)) in colour.iter_mut().zip(from.iter().zip(to.iter())) {
+        *channel = (from as i32 + (to as i32 - from as i32) * done / span) as u8;
+    }
+    colour
+}
+
+/// The colour the map is multiplied by at `minute` of the day in `weather`
+pub fn tint(minute: u16, weather: Weather) -> [u8; 3] {
+    // how much light gets through, and how much of its colour is greyed out
+    let (light, grey) = match weather {
+        Weather::Clear => (255, 0),
+        Weather::Rain => (190, 30),
+        Weather::Snow => (235, 40),
+        Weather::Fog => (215, 60),
+    };
+    let colour = daylight(minute);
+    let mean = colour.iter().map(|&channel| channel as u32).sum::<u32>() / 3;
+    let mut tint = [0; 3];
+    for (channel, &daylight) in tint.iter_mut().zip(colour.iter()) {
+        let greyed = (daylight as u32 * (100 - grey) + mean * grey) / 100;
+        *channel = (greyed * light / 255) as u8;
+    }
+    tint
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_daylight() {
+        assert_eq!(daylight(0), [60, 70, 130]);
+        assert_eq!(daylight(12 * 60), [255, 255, 255]);
+        // halfway from night to dawn
+        assert_eq!(daylight(6 * 60), [157, 135, 150]);
+        // past midnight again, blending back into the night
+        assert_eq!(daylight(MINUTES_PER_DAY + 12 * 60), [255, 255, 255]);
+        assert_eq!(daylight(MINUTES_PER_DAY - 1), [60, 70, 130]);
+        // no jumps from a minute to the next
+        for minute in 0..MINUTES_PER_DAY {
+            let (a, b) = (daylight(minute), daylight(minute + 1));
+            assert!(a.iter().zip(b.iter()).all(|(&a, &b)| a.abs_diff(b) <= 2), "{}", minute);
+        }
+    }
+
+    #[test]
+    fn test_tint() {
+        assert_eq!(tint(12 * 60, Weather::Clear), [255, 255, 255]);
+        assert_eq!(tint(12 * 60, Weather::Rain), [190, 190, 190]);
+        for &weather in Weather::ALL[1..].iter() {
+            for &minute in [0, 6 * 60, 12 * 60, 19 * 60].iter() {
+                let brightness = |tint: [u8; 3]| tint.iter().map(|&channel| channel as u32).sum::<u32>();
+                assert!(brightness(tint(minute, weather)) <= brightness(tint(minute, Weather::Clear)));
+            }
+        }
+        // fog takes colour away
+        let fog = tint(0, Weather::Fog);
+        assert!(fog[2] - fog[0] < 130 - 60);
+    }
+
+    #[test]
+    fn test_weather() {
+        for &weather in Weather::ALL.iter() {
+            assert_eq!(Weather::from_code(weather.code()), Some(weather));
+            assert_eq!(Weather::from_name(weather.name()), Some(weather));
+        }
+        assert_eq!(Weather::from_code(4), None);
+        assert_eq!(Weather::from_name("hail"), None);
+
+        let mut rng = Rng::new(3);
+        let mut counts = [0; 4];
+        for _ in 0..1000 {
+            counts[Weather::roll(&[3, 1, 0, 0], &mut rng).code() as usize] += 1;
+        }
+        assert!(counts[2] == 0 && counts[3] == 0 && (650..850).contains(&counts[0]));
+        assert_eq!(Weather::roll(&[0; 4], &mut rng), Weather::Clear);
+    }
+}
@@ -0,0 +1,65 @@
+//! `novluno-combat <tables> <attacker> <defender>`
+//!
+//! Works out a fight from the tables in the folder `<tables>` (see
+//! `core_rules::table`), with the same formulas as the server. The sides are
+//! given as `monster:<id>` or `level:<level>[+<item>..]`, a player of that
+//! level wearing the items, e.g. `level:5+1+2`.
+
+extern crate core_rules;
+
+use std::path::Path;
+
+use core_rules::combat::{self, CombatParams, Combatant};
+use core_rules::error::Error;
+use core_rules::table::Tables;
+
+static USAGE: &str = "usage: novluno-combat <tables> <attacker> <defender>";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(true) => (),
+        Ok(false) => {
+            println!("{}", USAGE);
+            std::process::exit(2);
+        }
+        Err(error) => {
+            println!("{:?}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<bool, Error> {
+    let (folder, attacker, defender) = match args {
+        [folder, attacker, defender] => (folder, attacker, defender),
+        _ => return Ok(false),
+    };
+    let tables = Tables::load(Path::new(folder))?;
+    let (attacker, defender) = match (side(&tables, attacker), side(&tables, defender)) {
+        (Some(attacker), Some(defender)) => (attacker, defender),
+        _ => return Ok(false),
+    };
+    let params = CombatParams::default();
+    let (low, high) = combat::damage_range(&attacker.0, &defender.0, &params);
+    println!("hit chance:      {}%", combat::hit_chance(&attacker.0, &defender.0, &params));
+    println!("damage:          {}..={}", low, high);
+    println!("expected damage: {:.2}", combat::expected_damage(&attacker.0, &defender.0, &params));
+    println!("strikes to kill: {:.1}", combat::strikes_to_kill(&attacker.0, &defender.0, &params, defender.1));
+    Ok(true)
+}
+
+/// A side of the fight and its hit points
+fn side(tables: &Tables, spec: &str) -> Option<(Combatant, u32)> {
+    if let Some(id) = spec.strip_prefix("monster:") {
+        let monster = tables.monsters.get(&id.parse().ok()?)?;
+        return Some((Combatant::monster(monster), monster.hp));
+    }
+    let mut parts = spec.strip_prefix("level:")?.split('+');
+    let level = tables.level(parts.next()?.parse().ok()?)?;
+    let mut items = Vec::new();
+    for item in parts {
+        items.push(tables.items.get(&item.parse().ok()?)?);
+    }
+    Some((Combatant::player(level, &items), level.hp))
+}
@@ -0,0 +1,104 @@
+//! `novluno-planner <tables> <level> [--item <item>].. [--skill <skill>].. [--to <level>] [--against <monster>]`
+//!
+//! Works out a character build from the tables in the folder `<tables>` (see
+//! `core_rules::plan`): the stats of a player of `<level>` wearing the items
+//! and with the skills learned, each given by id or name. With `--to` it
+//! lists the build at every level up to that one instead, and with
+//! `--against` how it fares against a monster of the monster table.
+
+extern crate core_rules;
+
+use std::path::Path;
+
+use core_rules::combat::{self, CombatParams, Combatant};
+use core_rules::error::Error;
+use core_rules::plan::{self, Build, Plan};
+use core_rules::table::{Stats, Tables};
+
+static USAGE: &str = "usage: novluno-planner <tables> <level> [--item <item>].. [--skill <skill>].. [--to <level>] [--against <monster>]";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(true) => (),
+        Ok(false) => {
+            println!("{}", USAGE);
+            std::process::exit(2);
+        }
+        Err(error) => {
+            println!("{:?}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<bool, Error> {
+    let (folder, level) = match (args.first(), args.get(1).and_then(|level| level.parse().ok())) {
+        (Some(folder), Some(level)) => (folder, level),
+        _ => return Ok(false),
+    };
+    let (mut items, mut skills, mut to, mut against) = (Vec::new(), Vec::new(), None, None);
+    let mut iter = args[2..].iter();
+    while let Some(arg) = iter.next() {
+        match (arg.as_str(), iter.next()) {
+            ("--item", Some(item)) => items.push(item.as_str()),
+            ("--skill", Some(skill)) => skills.push(skill.as_str()),
+            ("--to", Some(level)) => match level.parse() {
+                Ok(level) => to = Some(level),
+                Err(_) => return Ok(false),
+            },
+            ("--against", Some(monster)) => match monster.parse() {
+                Ok(monster) => against = Some(monster),
+                Err(_) => return Ok(false),
+            },
+            _ => return Ok(false),
+        }
+    }
+
+    let tables = Tables::load(Path::new(folder))?;
+    let build = Build::new(&tables, level, &items, &skills)?;
+    let monster = match against {
+        Some(id) => match tables.monsters.get(&id) {
+            Some(monster) => Some(monster),
+            None => return Ok(false),
+        },
+        None => None,
+    };
+    match to {
+        Some(to) => {
+            let plans = plan::progression(&tables, &build, to)?;
+            println!("level  exp      hp     {}", stat_header());
+            for plan in plans {
+                println!("{:<6} {:<8} {:<6} {}", plan.level, plan.exp, plan.hp, stat_columns(plan.total()));
+            }
+        }
+        None => print_plan(&plan::plan(&tables, &build)?),
+    }
+    if let Some(monster) = monster {
+        let player = plan::plan(&tables, &Build { level: to.unwrap_or(level), ..build })?.combatant();
+        let (monster_side, params) = (Combatant::monster(monster), CombatParams::default());
+        println!();
+        println!("against {} (level {}):", monster.name, monster.level);
+        println!("hit chance:      {}%", combat::hit_chance(&player, &monster_side, &params));
+        println!("strikes to kill: {:.1}", combat::strikes_to_kill(&player, &monster_side, &params, monster.hp));
+        println!("its hit chance:  {}%", combat::hit_chance(&monster_side, &player, &params));
+    }
+    Ok(true)
+}
+
+fn print_plan(plan: &Plan) {
+    println!("level {} (exp {}), hp {}", plan.level, plan.exp, plan.hp);
+    println!("        {}", stat_header());
+    println!("level   {}", stat_columns(plan.base));
+    println!("items   {}", stat_columns(plan.items));
+    println!("skills  {}", stat_columns(plan.skills));
+    println!("total   {}", stat_columns(plan.total()));
+}
+
+fn stat_header() -> String {
+    format!("{:<8} {:<8} {:<8} {}", "attack", "defense", "accuracy", "evasion")
+}
+
+fn stat_columns(stats: Stats) -> String {
+    format!("{:<8} {:<8} {:<8} {}", stats.attack, stats.defense, stats.accuracy, stats.evasion)
+}
@@ -0,0 +1,165 @@
+//! Hit, damage and defense.
+//!
+//! A strike first rolls to hit, then for damage:
+//!
+//! * The hit chance, in percent, is `base_hit`, plus `accuracy_weight` for
+//!   every point of accuracy over the defender's evasion, plus
+//!   `level_weight` for every level over the defender's; clamped to
+//!   `min_hit..=max_hit` so nothing always or never hits.
+//! * The damage before the roll is `attack² / (attack + defense)`: equal
+//!   attack and defense halve it and defense alone never brings it to 0. At
+//!   least `min_damage` is dealt.
+//! * The damage roll then spreads it by `variance` percent either way.
+//!
+//! The rolls are passed in, so the same rolls always give the same result.
+
+use crate::random::Rng;
+use crate::table::{ItemStats, LevelStats, MonsterStats, Stats};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CombatParams {
+    /// Percent
+    pub base_hit: i32,
+    pub accuracy_weight: i32,
+    pub level_weight: i32,
+    pub min_hit: i32,
+    pub max_hit: i32,
+    /// Percent
+    pub variance: u32,
+    pub min_damage: u32,
+}
+
+impl Default for CombatParams {
+    fn default() -> CombatParams {
+        CombatParams {
+            base_hit: 80,
+            accuracy_weight: 1,
+            level_weight: 2,
+            min_hit: 5,
+            max_hit: 95,
+            variance: 10,
+            min_damage: 1,
+        }
+    }
+}
+
+/// The side of a fight
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Combatant {
+    pub level: u32,
+    pub stats: Stats,
+}
+
+impl Combatant {
+    /// A player of a level, wearing `equipment`
+    pub fn player(level: &LevelStats, equipment: &[&ItemStats]) -> Combatant {
+        let stats = equipment.iter().fold(level.stats, |stats, item| stats + item.stats);
+        Combatant { level: level.level, stats }
+    }
+
+    pub fn monster(monster: &MonsterStats) -> Combatant {
+        Combatant { level: monster.level, stats: monster.stats }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Strike {
+    Miss,
+    Hit(u32),
+}
+
+/// The chance of `attacker` hitting `defender`, in percent
+pub fn hit_chance(attacker: &Combatant, defender: &Combatant, params: &CombatParams) -> u32 {
+    let accuracy = attacker.stats.accuracy as i32 - defender.stats.evasion as i32;
+    let levels = attacker.level as i32 - defender.level as i32;
+    let chance = params.base_hit + accuracy * params.accuracy_weight + levels * params.level_weight;
+    chance.clamp(params.min_hit, params.max_hit) as u32
+}
+
+/// The least and most damage of a hit
+pub fn damage_range(attacker: &Combatant, defender: &Combatant, params: &CombatParams) -> (u32, u32) {
+    let (attack, defense) = (attacker.stats.attack as u64, defender.stats.defense as u64);
+    let base = (attack * attack).checked_div(attack + defense).unwrap_or(0);
+    let spread = base * params.variance as u64 / 100;
+    let low = (base - spread).max(params.min_damage as u64);
+    let high = (base + spread).max(low);
+    (low.min(u32::MAX as u64) as u32, high.min(u32::MAX as u64) as u32)
+}
+
+/// A strike with the rolls given: `hit_roll` in `0..100`, hitting below the
+/// hit chance, and `damage_roll` picking the damage from the range.
+pub fn strike_with(
+    attacker: &Combatant,
+    defender: &Combatant,
+    params: &CombatParams,
+    hit_roll: u32,
+    damage_roll: u32,
+) -> Strike {
+    if hit_roll >= hit_chance(attacker, defender, params) {
+        return Strike::Miss;
+    }
+    let (low, high) = damage_range(attacker, defender, params);
+    Strike::Hit(low + damage_roll % (high - low + 1))
+}
+
+pub fn strike(attacker: &Combatant, defender: &Combatant, params: &CombatParams, rng: &mut Rng) -> Strike {
+    let hit_roll = rng.below(100);
+    let damage_roll = rng.next_u32();
+    strike_with(attacker, defender, params, hit_roll, damage_roll)
+}
+
+/// The average damage of a strike, misses included
+pub fn expected_damage(attacker: &Combatant, defender: &Combatant, params: &CombatParams) -> f64 {
+    let (low, high) = damage_range(attacker, defender, params);
+    hit_chance(attacker, defender, params) as f64 / 100.0 * (low as f64 + high as f64) / 2.0
+}
+
+/// How many strikes take `hp` on average
+pub fn strikes_to_kill(attacker: &Combatant, defender: &Combatant, params: &CombatParams, hp: u32) -> f64 {
+    hp as f64 / expected_damage(attacker, defender, params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn combatant(level: u32, attack: u32, defense: u32, accuracy: u32, evasion: u32) -> Combatant {
+        Combatant { level, stats: Stats { attack, defense, accuracy, evasion } }
+    }
+
+    #[test]
+    fn test_hit_chance() {
+        let params = CombatParams::default();
+        let even = combatant(1, 10, 10, 10, 10);
+        assert_eq!(hit_chance(&even, &even, &params), 80);
+        // 5 accuracy and 2 levels over
+        assert_eq!(hit_chance(&combatant(3, 10, 10, 15, 10), &even, &params), 89);
+        assert_eq!(hit_chance(&combatant(50, 10, 10, 99, 0), &even, &params), 95);
+        assert_eq!(hit_chance(&even, &combatant(50, 10, 10, 10, 99), &params), 5);
+    }
+
+    #[test]
+    fn test_damage() {
+        let params = CombatParams::default();
+        // 20² / (20 + 20) = 10, spread by 1 either way
+        let (attacker, defender) = (combatant(1, 20, 0, 0, 0), combatant(1, 0, 20, 0, 0));
+        assert_eq!(damage_range(&attacker, &defender, &params), (9, 11));
+        assert_eq!(damage_range(&attacker, &combatant(1, 0, 0, 0, 0), &params), (18, 22));
+        // without attack there's still the least damage
+        assert_eq!(damage_range(&defender, &defender, &params), (1, 1));
+
+        assert_eq!(strike_with(&attacker, &defender, &params, 79, 0), Strike::Hit(9));
+        assert_eq!(strike_with(&attacker, &defender, &params, 79, 5), Strike::Hit(11));
+        assert_eq!(strike_with(&attacker, &defender, &params, 80, 0), Strike::Miss);
+        assert!((expected_damage(&attacker, &defender, &params) - 8.0).abs() < 1e-9);
+        assert!((strikes_to_kill(&attacker, &defender, &params, 80) - 10.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_player_equipment() {
+        let level = LevelStats { level: 2, exp: 50, hp: 120, stats: Stats { attack: 12, defense: 1, accuracy: 11, evasion: 5 } };
+        let sword = ItemStats { id: 1, name: "Short Sword".into(), stats: Stats { attack: 5, defense: 0, accuracy: 2, evasion: 0 } };
+        let player = Combatant::player(&level, &[&sword]);
+        assert_eq!(player, combatant(2, 17, 1, 13, 5));
+    }
+}
@@ -0,0 +1,266 @@
+//! What changed between two versions of the tables, line by line and field
+//! by field, so a reload can say what it did.
+//!
+//! Lines are matched by what identifies them: the id of an item, monster or
+//! skill, the level, the map and tile of a warp, the monster and item of a
+//! drop, and the monster and tiles of a spawn region. Lines that are the same
+//! twice are matched in order.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::table::{DropEntry, ItemStats, LevelStats, MonsterStats, SkillStats, SpawnEntry, Stats, Tables, WarpEntry};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Added { table: &'static str, line: String },
+    Removed { table: &'static str, line: String },
+    /// The fields that changed, with their old and new values
+    Changed { table: &'static str, line: String, fields: Vec<(&'static str, String, String)> },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Change::Added { table, ref line } => write!(f, "{} {}: added", table, line),
+            Change::Removed { table, ref line } => write!(f, "{} {}: removed", table, line),
+            Change::Changed { table, ref line, ref fields } => {
+                let fields: Vec<String> = fields.iter().map(|(name, old, new)| format!("{} {} -> {}", name, old, new)).collect();
+                write!(f, "{} {}: {}", table, line, fields.join(", "))
+            }
+        }
+    }
+}
+
+/// Every change from `old` to `new`, table by table
+pub fn diff(old: &Tables, new: &Tables) -> Vec<Change> {
+    let mut changes = Vec::new();
+    changes.extend(diff_lines("items", old.items.values(), new.items.values()));
+    changes.extend(diff_lines("monsters", old.monsters.values(), new.monsters.values()));
+    changes.extend(diff_lines("levels", old.levels.iter(), new.levels.iter()));
+    changes.extend(diff_lines("spawns", old.spawns.iter(), new.spawns.iter()));
+    changes.extend(diff_lines("drops", old.drops.iter(), new.drops.iter()));
+    changes.extend(diff_lines("skills", old.skills.values(), new.skills.values()));
+    changes.extend(diff_lines("warps", old.warps.iter(), new.warps.iter()));
+    changes
+}
+
+/// A line of a table
+trait Line {
+    /// What identifies the line
+    fn key(&self) -> Vec<u32>;
+    /// The key as shown
+    fn label(&self) -> String;
+    fn fields(&self) -> Vec<(&'static str, String)>;
+}
+
+fn diff_lines<'a, L: Line + 'a>(table: &'static str, old: impl Iterator<Item = &'a L>, new: impl Iterator<Item = &'a L>)
+                               -> Vec<Change> {
+    let (old, new) = (by_key(old), by_key(new));
+    let mut changes = Vec::new();
+    for (key, line) in old.iter() {
+        match new.get(key) {
+            None => changes.push(Change::Removed { table, line: line.label() }),
+            Some(new_line) => {
+                let fields: Vec<(&'static str, String, String)> = line.fields().into_iter()
+                    .zip(new_line.fields())
+                    .filter(|&((_, ref old), (_, ref new))| old != new)
+                    .map(|((name, old), (_, new))| (name, old, new))
+                    .collect();
+                if !fields.is_empty() {
+                    changes.push(Change::Changed { table, line: line.label(), fields });
+                }
+            }
+        }
+    }
+    for (key, line) in new.iter() {
+        if !old.contains_key(key) {
+            changes.push(Change::Added { table, line: line.label() });
+        }
+    }
+    changes
+}
+
+/// The lines by key, the ones with the same key told apart by their order
+fn by_key<'a, L: Line + 'a>(lines: impl Iterator<Item = &'a L>) -> BTreeMap<Vec<u32>, &'a L> {
+    let mut seen: BTreeMap<Vec<u32>, u32> = BTreeMap::new();
+    lines
+        .map(|line| {
+            let mut key = line.key();
+            let count = seen.entry(key.clone()).or_insert(0);
+            key.push(*count);
+            *count += 1;
+            (key, line)
+        })
+        .collect()
+}
+
+fn stat_fields(stats: &Stats) -> Vec<(&'static str, String)> {
+    vec![
+        ("attack", stats.attack.to_string()),
+        ("defense", stats.defense.to_string()),
+        ("accuracy", stats.accuracy.to_string()),
+        ("evasion", stats.evasion.to_string()),
+    ]
+}
+
+impl Line for ItemStats {
+    fn key(&self) -> Vec<u32> {
+        vec![self.id]
+    }
+
+    fn label(&self) -> String {
+        format!("{} ({})", self.id, self.name)
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![("name", self.name.clone())];
+        fields.extend(stat_fields(&self.stats));
+        fields
+    }
+}
+
+impl Line for MonsterStats {
+    fn key(&self) -> Vec<u32> {
+        vec![self.id]
+    }
+
+    fn label(&self) -> String {
+        format!("{} ({})", self.id, self.name)
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("name", self.name.clone()),
+            ("level", self.level.to_string()),
+            ("hp", self.hp.to_string()),
+            ("exp", self.exp.to_string()),
+        ];
+        fields.extend(stat_fields(&self.stats));
+        let behavior = self.behavior;
+        fields.extend(vec![
+            ("aggressive", (behavior.aggressive as u32).to_string()),
+            ("aggro", behavior.aggro_radius.to_string()),
+            ("leash", behavior.leash_radius.to_string()),
+            ("wander", behavior.wander_radius.to_string()),
+        ]);
+        fields
+    }
+}
+
+impl Line for LevelStats {
+    fn key(&self) -> Vec<u32> {
+        vec![self.level]
+    }
+
+    fn label(&self) -> String {
+        self.level.to_string()
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![("exp", self.exp.to_string()), ("hp", self.hp.to_string())];
+        fields.extend(stat_fields(&self.stats));
+        fields
+    }
+}
+
+impl Line for SpawnEntry {
+    fn key(&self) -> Vec<u32> {
+        vec![self.map, self.monster, self.left as u32, self.top as u32, self.right as u32, self.bottom as u32]
+    }
+
+    fn label(&self) -> String {
+        format!("monster {} on map {} at {},{}-{},{}", self.monster, self.map, self.left, self.top, self.right, self.bottom)
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("count", self.count.to_string()),
+            ("respawn", self.respawn.to_string()),
+            ("jitter", self.jitter.to_string()),
+        ]
+    }
+}
+
+impl Line for DropEntry {
+    fn key(&self) -> Vec<u32> {
+        vec![self.monster, self.item]
+    }
+
+    fn label(&self) -> String {
+        format!("item {} from monster {}", self.item, self.monster)
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![("chance", self.chance.to_string()), ("min", self.min.to_string()), ("max", self.max.to_string())]
+    }
+}
+
+impl Line for SkillStats {
+    fn key(&self) -> Vec<u32> {
+        vec![self.id]
+    }
+
+    fn label(&self) -> String {
+        format!("{} ({})", self.id, self.name)
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![("name", self.name.clone()), ("level", self.level.to_string())];
+        fields.extend(stat_fields(&self.stats));
+        fields.push(("range", self.range.to_string()));
+        fields
+    }
+}
+
+impl Line for WarpEntry {
+    fn key(&self) -> Vec<u32> {
+        vec![self.map, self.x as u32, self.y as u32]
+    }
+
+    fn label(&self) -> String {
+        format!("map {} at {},{}", self.map, self.x, self.y)
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![("to_map", self.to_map.to_string()), ("to_x", self.to_x.to_string()), ("to_y", self.to_y.to_string())]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::table::{parse_drops, parse_items, parse_monsters, parse_spawns};
+
+    #[test]
+    fn test_diff() {
+        let items = "id\tname\tattack\tdefense\taccuracy\tevasion\n";
+        let monsters = "id\tname\tlevel\thp\texp\tattack\tdefense\taccuracy\tevasion\n";
+        let old = Tables {
+            items: parse_items(&format!("{}1\tShort Sword\t5\t0\t0\t0\n2\tShield\t0\t3\t0\t0\n", items)).unwrap(),
+            monsters: parse_monsters(&format!("{}1\tRat\t1\t10\t5\t1\t0\t0\t0\n", monsters)).unwrap(),
+            drops: parse_drops("monster\titem\tchance\tmin\tmax\n1\t2\t100\t1\t1\n").unwrap(),
+            ..Tables::default()
+        };
+        assert!(diff(&old, &old.clone()).is_empty());
+
+        let new = Tables {
+            items: parse_items(&format!("{}1\tLong Sword\t8\t0\t0\t0\n3\tBow\t4\t0\t0\t0\n", items)).unwrap(),
+            monsters: parse_monsters(&format!("{}1\tRat\t1\t12\t5\t1\t0\t0\t0\n", monsters)).unwrap(),
+            drops: parse_drops("monster\titem\tchance\tmin\tmax\n1\t2\t100\t1\t1\n1\t2\t100\t1\t1\n").unwrap(),
+            spawns: parse_spawns("map\tleft\ttop\tright\tbottom\tmonster\tcount\trespawn\n3\t1\t2\t5\t6\t1\t4\t30\n").unwrap(),
+            ..Tables::default()
+        };
+        let changes: Vec<String> = diff(&old, &new).iter().map(|change| change.to_string()).collect();
+        assert_eq!(changes, vec![
+            "items 1 (Short Sword): name Short Sword -> Long Sword, attack 5 -> 8",
+            "items 2 (Shield): removed",
+            "items 3 (Bow): added",
+            "monsters 1 (Rat): hp 10 -> 12",
+            "spawns monster 1 on map 3 at 1,2-5,6: added",
+            // the same line twice is another line
+            "drops item 2 from monster 1: added",
+        ]);
+    }
+}
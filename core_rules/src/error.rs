@@ -0,0 +1,21 @@
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    DuplicateId(u32),
+    /// The line and column of a field that isn't what the column holds
+    InvalidField(usize, String),
+    Io(io::Error),
+    MissingColumn(String),
+    /// A skill needs a higher level than the build's
+    SkillLocked(u32),
+    UnknownItem(String),
+    UnknownLevel(u32),
+    UnknownSkill(String),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
@@ -0,0 +1,13 @@
+//! Game rules shared by the server emulator and the offline tools, so both
+//! compute the same numbers from the same tables.
+
+pub mod ambiance;
+pub mod combat;
+pub mod diff;
+pub mod error;
+pub mod loot;
+pub mod pathfind;
+pub mod plan;
+pub mod random;
+pub mod sight;
+pub mod table;
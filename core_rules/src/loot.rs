@@ -0,0 +1,50 @@
+//! What a monster leaves behind.
+//!
+//! Every line of the drop table for the monster is rolled on its own, so a
+//! monster can drop several items at once or nothing at all.
+
+use crate::random::Rng;
+use crate::table::{DropEntry, CHANCE_SCALE};
+
+/// The items and their counts dropped by `monster`, in the order of the
+/// drop table
+pub fn roll(drops: &[DropEntry], monster: u32, rng: &mut Rng) -> Vec<(u32, u32)> {
+    drops.iter()
+        .filter(|drop| drop.monster == monster)
+        .filter_map(|drop| if rng.below(CHANCE_SCALE) < drop.chance {
+            Some((drop.item, drop.min + rng.below(drop.max - drop.min + 1)))
+        } else {
+            None
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drop(monster: u32, item: u32, chance: u32, min: u32, max: u32) -> DropEntry {
+        DropEntry { monster, item, chance, min, max }
+    }
+
+    #[test]
+    fn test_roll() {
+        let drops = vec![drop(1, 7, 1000, 2, 4), drop(1, 8, 0, 1, 1), drop(2, 9, 1000, 1, 1)];
+        let mut rng = Rng::new(3);
+        for _ in 0..100 {
+            match roll(&drops, 1, &mut rng)[..] {
+                [(7, count)] => assert!((2..=4).contains(&count)),
+                ref other => panic!("unexpected drops: {:?}", other),
+            }
+        }
+        assert!(roll(&drops, 3, &mut rng).is_empty());
+    }
+
+    #[test]
+    fn test_chance() {
+        let drops = vec![drop(1, 7, 250, 1, 1)];
+        let mut rng = Rng::new(5);
+        let dropped = (0..4000).filter(|_| !roll(&drops, 1, &mut rng).is_empty()).count();
+        assert!((900..1100).contains(&dropped));
+    }
+}
@@ -0,0 +1,157 @@
+//! Finding the way over a grid of walkable tiles, for the monsters of the
+//! server and click-to-move in the client.
+//!
+//! A* over the eight neighbours of each tile. Every step costs the same,
+//! diagonals included, since walking takes as long in every direction; but
+//! as in the original client a diagonal step can't cut a corner, both tiles
+//! it passes between have to be walkable too.
+//!
+//! The search gives up after `limit` tiles, so a goal behind a long wall
+//! costs no more than a goal nearby; the path then leads to the tile closest
+//! to the goal it found.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+
+pub type Tile = (u16, u16);
+
+/// Steps between two tiles, diagonals counting as one
+pub fn distance(a: Tile, b: Tile) -> u16 {
+    a.0.abs_diff(b.0).max(a.1.abs_diff(b.1))
+}
+
+/// The steps from `from` to a tile at most `reach` tiles from `goal`, `from`
+/// left out; empty if already there or there's no way to get closer.
+pub fn find_path<F: Fn(Tile) -> bool>(passable: F, from: Tile, goal: Tile, reach: u16, limit: usize) -> Vec<Tile> {
+    let remaining = |tile: Tile| distance(tile, goal).saturating_sub(reach) as u32;
+    if remaining(from) == 0 {
+        return Vec::new();
+    }
+    // how each tile was reached, and in how many steps
+    let mut came_from: HashMap<Tile, (Tile, u32)> = HashMap::new();
+    came_from.insert(from, (from, 0));
+    // by estimated length, then nearest the goal, then first found
+    let mut open = BinaryHeap::new();
+    open.push(Reverse((remaining(from), remaining(from), 0usize, from)));
+    let mut found = 1;
+    let mut best = from;
+    while let Some(Reverse((_, left, _, tile))) = open.pop() {
+        let steps = came_from[&tile].1;
+        if left < remaining(best) {
+            best = tile;
+            if left == 0 {
+                break;
+            }
+        }
+        for neighbour in neighbours(&passable, tile) {
+            let known = came_from.get(&neighbour).map(|&(_, known)| known);
+            if known.is_some_and(|known| known <= steps + 1) || (known.is_none() && found >= limit) {
+                continue;
+            }
+            if known.is_none() {
+                found += 1;
+            }
+            came_from.insert(neighbour, (tile, steps + 1));
+            open.push(Reverse((steps + 1 + remaining(neighbour), remaining(neighbour), found, neighbour)));
+        }
+    }
+    let mut path = Vec::new();
+    let mut tile = best;
+    while tile != from {
+        path.push(tile);
+        tile = came_from[&tile].0;
+    }
+    path.reverse();
+    path
+}
+
+/// The first step of `find_path`
+pub fn next_step<F: Fn(Tile) -> bool>(passable: F, from: Tile, goal: Tile, reach: u16, limit: usize) -> Option<Tile> {
+    find_path(passable, from, goal, reach, limit).first().cloned()
+}
+
+/// The tiles a step away that can be walked to
+fn neighbours<'a, F: Fn(Tile) -> bool>(passable: &'a F, tile: Tile) -> impl Iterator<Item = Tile> + 'a {
+    const OFFSETS: [(i32, i32); 8] = [(0, -1), (1, 0), (0, 1), (-1, 0), (1, -1), (1, 1), (-1, 1), (-1, -1)];
+    let offset = move |dx: i32, dy: i32| {
+        let x = tile.0 as i32 + dx;
+        let y = tile.1 as i32 + dy;
+        if x < 0 || y < 0 || x > u16::MAX as i32 || y > u16::MAX as i32 {
+            None
+        } else {
+            Some((x as u16, y as u16))
+        }
+    };
+    OFFSETS.iter().filter_map(move |&(dx, dy)| {
+        let next = offset(dx, dy).filter(|&next| passable(next))?;
+        if dx != 0 && dy != 0 {
+            // no squeezing between two blocked tiles, or past the corner of one
+            offset(dx, 0).filter(|&side| passable(side))?;
+            offset(0, dy).filter(|&side| passable(side))?;
+        }
+        Some(next)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A map from rows of `.` for walkable and `#` for blocked tiles; off the
+    /// map is blocked
+    fn grid(rows: &[&str]) -> impl Fn(Tile) -> bool {
+        let rows: Vec<Vec<bool>> = rows.iter().map(|row| row.chars().map(|c| c == '.').collect()).collect();
+        move |(x, y): Tile| rows.get(y as usize).and_then(|row| row.get(x as usize)).cloned().unwrap_or(false)
+    }
+
+    #[test]
+    fn test_open_field() {
+        let open = |_: Tile| true;
+        assert_eq!(find_path(open, (5, 5), (9, 9), 0, 100), vec![(6, 6), (7, 7), (8, 8), (9, 9)]);
+        assert_eq!(find_path(open, (5, 5), (9, 7), 0, 100).len(), 4);
+        assert!(find_path(open, (5, 5), (6, 6), 1, 100).is_empty());
+        assert_eq!(next_step(open, (0, 0), (0, 5), 1, 100), Some((0, 1)));
+    }
+
+    #[test]
+    fn test_no_corner_cutting() {
+        let map = grid(&[
+            "..#",
+            ".#.",
+            "...",
+        ]);
+        // every diagonal passes the block in the middle, so it's the long way
+        let path = find_path(&map, (0, 0), (2, 1), 0, 100);
+        assert_eq!(path, vec![(0, 1), (0, 2), (1, 2), (2, 2), (2, 1)]);
+        assert!(!neighbours(&map, (1, 0)).any(|tile| tile == (2, 1)));
+    }
+
+    #[test]
+    fn test_around_a_wall() {
+        let map = grid(&[
+            "........",
+            "..####..",
+            "........",
+        ]);
+        // round the left end of the wall, squarely at both of its corners
+        let path = find_path(&map, (3, 0), (3, 2), 0, 100);
+        assert_eq!(path, vec![(2, 0), (1, 0), (1, 1), (1, 2), (2, 2), (3, 2)]);
+    }
+
+    #[test]
+    fn test_unreachable_and_limit() {
+        let map = grid(&[
+            "..#..",
+            "..#..",
+            "..#..",
+        ]);
+        // it gets as close as it can
+        assert_eq!(find_path(&map, (0, 1), (4, 1), 0, 100), vec![(1, 1)]);
+        assert!(find_path(&map, (1, 1), (4, 1), 0, 100).is_empty());
+        // nothing closer among the first tiles looked at
+        let open = grid(&["......", "......", "......"]);
+        assert!(find_path(&open, (0, 1), (5, 1), 0, 1).is_empty());
+        assert_eq!(find_path(&open, (0, 1), (5, 1), 0, 100).len(), 5);
+    }
+}
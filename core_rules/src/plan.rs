@@ -0,0 +1,178 @@
+//! Character builds: what a player of some level has, wearing some items and
+//! with some passive skills learned.
+//!
+//! Items and skills are given by id or by name, case ignored, so builds can
+//! be written down the way players talk about them. A build's stats are the
+//! level's, plus the items', plus the passive skills'; the same sum the
+//! combat formulas see. Skills with a range only count when used.
+
+use crate::combat::Combatant;
+use crate::error::Error;
+use crate::table::{Stats, Tables};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Build {
+    pub level: u32,
+    /// Item ids
+    pub items: Vec<u32>,
+    /// Skill ids
+    pub skills: Vec<u32>,
+}
+
+impl Build {
+    /// A build with the items and skills named or numbered
+    pub fn new(tables: &Tables, level: u32, items: &[&str], skills: &[&str]) -> Result<Build, Error> {
+        let items = items.iter()
+            .map(|&item| find(tables.items.iter().map(|(&id, i)| (id, i.name.as_str())), item)
+                .ok_or_else(|| Error::UnknownItem(item.into())))
+            .collect::<Result<_, _>>()?;
+        let skills = skills.iter()
+            .map(|&skill| find(tables.skills.iter().map(|(&id, s)| (id, s.name.as_str())), skill)
+                .ok_or_else(|| Error::UnknownSkill(skill.into())))
+            .collect::<Result<_, _>>()?;
+        Ok(Build { level, items, skills })
+    }
+}
+
+/// Where a build's stats come from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    pub level: u32,
+    /// Experience needed for the level
+    pub exp: u32,
+    pub hp: u32,
+    pub base: Stats,
+    pub items: Stats,
+    pub skills: Stats,
+}
+
+impl Plan {
+    pub fn total(&self) -> Stats {
+        self.base + self.items + self.skills
+    }
+
+    /// The build as a side of a fight (see `combat`)
+    pub fn combatant(&self) -> Combatant {
+        Combatant { level: self.level, stats: self.total() }
+    }
+}
+
+/// The stats of a build; every skill must be learnable at its level.
+pub fn plan(tables: &Tables, build: &Build) -> Result<Plan, Error> {
+    let level = tables.level(build.level).ok_or(Error::UnknownLevel(build.level))?;
+    let mut items = Stats::default();
+    for &id in build.items.iter() {
+        items = items + tables.items.get(&id).ok_or_else(|| Error::UnknownItem(id.to_string()))?.stats;
+    }
+    let mut skills = Stats::default();
+    for &id in build.skills.iter() {
+        let skill = tables.skills.get(&id).ok_or_else(|| Error::UnknownSkill(id.to_string()))?;
+        if skill.level > build.level {
+            return Err(Error::SkillLocked(id));
+        }
+        if skill.range == 0 {
+            skills = skills + skill.stats;
+        }
+    }
+    Ok(Plan { level: level.level, exp: level.exp, hp: level.hp, base: level.stats, items, skills })
+}
+
+/// The build at every level from its own up to `to`, each skill counting
+/// from the level it's learnable at
+pub fn progression(tables: &Tables, build: &Build, to: u32) -> Result<Vec<Plan>, Error> {
+    (build.level..=to)
+        .map(|level| {
+            let skills = build.skills.iter()
+                .filter(|id| tables.skills.get(id).is_none_or(|skill| skill.level <= level))
+                .cloned()
+                .collect();
+            plan(tables, &Build { level, items: build.items.clone(), skills })
+        })
+        .collect()
+}
+
+/// The id of an entry named or numbered `key`
+fn find<'a, I: Iterator<Item = (u32, &'a str)>>(mut entries: I, key: &str) -> Option<u32> {
+    let number = key.parse::<u32>().ok();
+    entries.find(|&(id, name)| Some(id) == number || name.eq_ignore_ascii_case(key))
+        .map(|(id, _)| id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::table::{parse_items, parse_levels, parse_skills};
+
+    fn tables() -> Tables {
+        let levels = "level\texp\thp\tattack\tdefense\taccuracy\tevasion\n\
+                      1\t0\t100\t10\t0\t10\t5\n\
+                      2\t50\t120\t12\t1\t11\t5\n\
+                      3\t150\t140\t14\t2\t12\t6\n";
+        let items = "id\tname\tattack\tdefense\taccuracy\tevasion\n\
+                     1\tShort Sword\t5\t0\t2\t0\n\
+                     2\tLeather Armor\t0\t4\t0\t1\n";
+        let skills = "id\tname\tlevel\tattack\tdefense\taccuracy\tevasion\trange\n\
+                      1\tFocus\t1\t0\t0\t5\t0\t0\n\
+                      2\tPower\t3\t3\t0\t0\t0\t0\n\
+                      3\tArrow\t1\t6\t0\t0\t0\t4\n";
+        Tables {
+            levels: parse_levels(levels).unwrap(),
+            items: parse_items(items).unwrap(),
+            skills: parse_skills(skills).unwrap(),
+            ..Tables::default()
+        }
+    }
+
+    fn stats(attack: u32, defense: u32, accuracy: u32, evasion: u32) -> Stats {
+        Stats { attack, defense, accuracy, evasion }
+    }
+
+    #[test]
+    fn test_build_by_name_or_id() {
+        let tables = tables();
+        let build = Build::new(&tables, 2, &["short sword", "2"], &["Focus"]).unwrap();
+        assert_eq!(build, Build { level: 2, items: vec![1, 2], skills: vec![1] });
+        match Build::new(&tables, 2, &["Long Sword"], &[]) {
+            Err(Error::UnknownItem(ref item)) if item == "Long Sword" => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match Build::new(&tables, 2, &[], &["9"]) {
+            Err(Error::UnknownSkill(ref skill)) if skill == "9" => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let tables = tables();
+        let plan = plan(&tables, &Build { level: 2, items: vec![1, 2], skills: vec![1] }).unwrap();
+        assert_eq!((plan.exp, plan.hp), (50, 120));
+        assert_eq!(plan.items, stats(5, 4, 2, 1));
+        assert_eq!(plan.total(), stats(17, 5, 18, 6));
+        assert_eq!(plan.combatant(), Combatant { level: 2, stats: stats(17, 5, 18, 6) });
+        // a ranged skill only counts when used
+        let ranged = super::plan(&tables, &Build { level: 2, items: vec![1, 2], skills: vec![1, 3] }).unwrap();
+        assert_eq!(ranged.total(), plan.total());
+
+        match super::plan(&tables, &Build { level: 2, items: Vec::new(), skills: vec![2] }) {
+            Err(Error::SkillLocked(2)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match super::plan(&tables, &Build { level: 4, items: Vec::new(), skills: Vec::new() }) {
+            Err(Error::UnknownLevel(4)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_progression() {
+        let tables = tables();
+        let build = Build { level: 1, items: vec![1], skills: vec![1, 2] };
+        let plans = progression(&tables, &build, 3).unwrap();
+        let attack: Vec<u32> = plans.iter().map(|plan| plan.total().attack).collect();
+        // Power comes in at 3
+        assert_eq!(attack, vec![15, 17, 22]);
+        assert!(progression(&tables, &build, 4).is_err());
+    }
+}
@@ -0,0 +1,39 @@
+//! A small seeded generator for the rolls of the rules, so a seed replays
+//! the same fights.
+
+/// xorshift64*
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // the state must never be zero
+        Rng(seed ^ 0x9E37_79B9_7F4A_7C15 | 1)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 32) as u32
+    }
+
+    /// A roll in `0..n`
+    pub fn below(&mut self, n: u32) -> u32 {
+        if n == 0 { 0 } else { self.next_u32() % n }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay() {
+        let (mut a, mut b) = (Rng::new(7), Rng::new(7));
+        let rolls: Vec<u32> = (0..16).map(|_| a.below(100)).collect();
+        assert!(rolls.iter().all(|&roll| roll < 100));
+        assert_eq!(rolls, (0..16).map(|_| b.below(100)).collect::<Vec<_>>());
+        assert_ne!(Rng::new(8).next_u32(), Rng::new(7).next_u32());
+    }
+}
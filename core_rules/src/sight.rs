@@ -0,0 +1,113 @@
+//! Line of sight over a grid of tiles, for ranged skills: the client shows
+//! what can be targeted and the server checks it again.
+//!
+//! The line between two tiles is Bresenham's, always drawn from the lesser
+//! of the two so that either end sees the other or neither does. The tiles
+//! between the ends must not block; the ends themselves may, since whoever
+//! stands there is seen. A diagonal step of the line may graze the corner of
+//! a blocking tile but not pass between two of them.
+
+use crate::pathfind::{distance, Tile};
+
+/// The tiles from `a` to `b`, both included
+pub fn line(a: Tile, b: Tile) -> Vec<Tile> {
+    if b < a {
+        let mut tiles = line(b, a);
+        tiles.reverse();
+        return tiles;
+    }
+    let (x1, y1, x2, y2) = (a.0 as i32, a.1 as i32, b.0 as i32, b.1 as i32);
+    let (dx, dy) = ((x2 - x1).abs(), -(y2 - y1).abs());
+    let (sx, sy) = ((x2 - x1).signum(), (y2 - y1).signum());
+    let mut tiles = Vec::with_capacity(distance(a, b) as usize + 1);
+    let (mut x, mut y, mut error) = (x1, y1, dx + dy);
+    loop {
+        tiles.push((x as u16, y as u16));
+        if (x, y) == (x2, y2) {
+            return tiles;
+        }
+        let double = 2 * error;
+        if double >= dy {
+            error += dy;
+            x += sx;
+        }
+        if double <= dx {
+            error += dx;
+            y += sy;
+        }
+    }
+}
+
+/// Whether `a` and `b` see each other, with `clear` telling the tiles that
+/// don't block sight
+pub fn line_of_sight<F: Fn(Tile) -> bool>(clear: F, a: Tile, b: Tile) -> bool {
+    let tiles = line(a, b);
+    let between_clear = tiles.iter().skip(1).take(tiles.len().saturating_sub(2)).all(|&tile| clear(tile));
+    between_clear && tiles.windows(2).all(|step| {
+        let (from, to) = (step[0], step[1]);
+        from.0 == to.0 || from.1 == to.1 || clear((to.0, from.1)) || clear((from.0, to.1))
+    })
+}
+
+/// Whether a skill reaching `range` tiles can be used from `from` on `to`
+pub fn in_reach<F: Fn(Tile) -> bool>(clear: F, from: Tile, to: Tile, range: u16) -> bool {
+    distance(from, to) <= range && line_of_sight(clear, from, to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::random::Rng;
+
+    #[test]
+    fn test_line() {
+        assert_eq!(line((0, 0), (3, 0)), vec![(0, 0), (1, 0), (2, 0), (3, 0)]);
+        assert_eq!(line((2, 2), (2, 2)), vec![(2, 2)]);
+        assert_eq!(line((0, 0), (4, 2)), vec![(0, 0), (1, 1), (2, 1), (3, 2), (4, 2)]);
+        assert_eq!(line((4, 2), (0, 0)), vec![(4, 2), (3, 2), (2, 1), (1, 1), (0, 0)]);
+    }
+
+    #[test]
+    fn test_blocking() {
+        let wall = |(x, y): Tile| !(x == 2 && y < 3);
+        assert!(!line_of_sight(wall, (0, 1), (4, 1)));
+        assert!(line_of_sight(wall, (0, 4), (4, 4)));
+        // standing on a blocking tile doesn't hide anyone
+        assert!(line_of_sight(wall, (2, 2), (2, 5)) && !line_of_sight(wall, (2, 0), (2, 5)));
+        assert!(line_of_sight(wall, (0, 0), (2, 2)) && line_of_sight(wall, (1, 3), (3, 3)));
+
+        // a diagonal between two blocking tiles is closed, past one corner open
+        let pillars = |tile: Tile| tile != (1, 0) && tile != (0, 1);
+        assert!(!line_of_sight(pillars, (0, 0), (1, 1)));
+        let pillar = |tile: Tile| tile != (1, 0);
+        assert!(line_of_sight(pillar, (0, 0), (1, 1)));
+
+        assert!(in_reach(pillar, (0, 0), (3, 3), 3));
+        assert!(!in_reach(pillar, (0, 0), (3, 4), 3));
+        assert!(!in_reach(pillars, (0, 0), (1, 1), 3));
+    }
+
+    /// On random grids and random pairs: lines are contiguous and end where
+    /// asked, the same both ways round, and so is the sight along them.
+    #[test]
+    fn test_properties() {
+        let mut rng = Rng::new(7);
+        for _ in 0..50 {
+            let blocked: Vec<bool> = (0..32 * 32).map(|_| rng.below(4) == 0).collect();
+            let clear = |(x, y): Tile| !blocked[y as usize * 32 + x as usize];
+            for _ in 0..100 {
+                let a = (rng.below(32) as u16, rng.below(32) as u16);
+                let b = (rng.below(32) as u16, rng.below(32) as u16);
+                let tiles = line(a, b);
+                assert_eq!((tiles[0], tiles[tiles.len() - 1]), (a, b));
+                assert_eq!(tiles.len(), distance(a, b) as usize + 1);
+                assert!(tiles.windows(2).all(|step| distance(step[0], step[1]) == 1));
+                let mut back = line(b, a);
+                back.reverse();
+                assert_eq!(tiles, back);
+                assert_eq!(line_of_sight(clear, a, b), line_of_sight(clear, b, a), "{:?} {:?}", a, b);
+            }
+        }
+    }
+}
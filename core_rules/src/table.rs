@@ -0,0 +1,514 @@
+//! The item, monster and level tables the rules are computed from.
+//!
+//! Each table is a tab-separated text file; the first line names the
+//! columns, lines starting with `#` are comments. With the tabs shown as
+//! spaces:
+//!
+//! ```text
+//! # items.tsv
+//! id  name         attack  defense  accuracy  evasion
+//! 1   Short Sword  5       0        2         0
+//! ```
+//!
+//! Columns can be in any order and unknown ones are ignored, so a table can
+//! carry more than the rules look at. The level table lists every level from
+//! 1 up, with the total experience needed to reach it.
+//!
+//! The monster table may also set how the monsters behave, in the optional
+//! columns `aggressive` (0 or 1), `aggro`, `leash` and `wander`; see
+//! `Behavior` for what they mean and their defaults.
+//!
+//! The spawn table, which may be missing, says which monsters live where:
+//! `count` of `monster` in the tiles `left..=right` and `top..=bottom` of
+//! `map`, each coming back `respawn` seconds after it died, give or take the
+//! optional `jitter` percent.
+//!
+//! The drop table, which may be missing too, gives what monsters leave
+//! behind: each line is rolled on its own, dropping `min..=max` of `item`
+//! with `chance` in thousandths (see `loot`).
+//!
+//! The skill table, also optional, lists the passive skills: the stats each
+//! adds and the `level` a player needs to learn it (see `plan`). A skill with
+//! a `range` is used on a target up to that many tiles away in sight (see
+//! `sight`) instead, its stats adding to that strike only.
+//!
+//! The warp table, optional as well, says where the warp tiles of the maps
+//! lead, since the map files only mark them: stepping on `x`, `y` of `map`
+//! takes a player to `to_x`, `to_y` of `to_map`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::ops::Add;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::error::Error;
+
+pub const ITEMS_FILE: &str = "items.tsv";
+pub const MONSTERS_FILE: &str = "monsters.tsv";
+pub const LEVELS_FILE: &str = "levels.tsv";
+pub const SPAWNS_FILE: &str = "spawns.tsv";
+pub const DROPS_FILE: &str = "drops.tsv";
+pub const SKILLS_FILE: &str = "skills.tsv";
+pub const WARPS_FILE: &str = "warps.tsv";
+
+/// The most a drop's `chance` can be
+pub const CHANCE_SCALE: u32 = 1000;
+
+/// Spread of the respawn delays, in percent, when the spawn table doesn't say
+pub const DEFAULT_JITTER: u32 = 20;
+
+/// What items add to and what monsters and levels have of the combat stats
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Stats {
+    pub attack: u32,
+    pub defense: u32,
+    pub accuracy: u32,
+    pub evasion: u32,
+}
+
+impl Add for Stats {
+    type Output = Stats;
+
+    fn add(self, other: Stats) -> Stats {
+        Stats {
+            attack: self.attack + other.attack,
+            defense: self.defense + other.defense,
+            accuracy: self.accuracy + other.accuracy,
+            evasion: self.evasion + other.evasion,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemStats {
+    pub id: u32,
+    pub name: String,
+    pub stats: Stats,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkillStats {
+    pub id: u32,
+    pub name: String,
+    /// The level needed to learn it
+    pub level: u32,
+    pub stats: Stats,
+    /// Tiles it reaches when used on a target; 0 for a passive skill
+    pub range: u16,
+}
+
+/// How a kind of monster acts, distances in tiles
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Behavior {
+    /// Whether it goes after players it sees, or only the ones hitting it
+    pub aggressive: bool,
+    /// How close a player has to come to be noticed
+    pub aggro_radius: u16,
+    /// How far from home it chases before giving up and going back
+    pub leash_radius: u16,
+    /// How far from home it strolls while idle
+    pub wander_radius: u16,
+}
+
+impl Default for Behavior {
+    fn default() -> Behavior {
+        Behavior { aggressive: true, aggro_radius: 5, leash_radius: 15, wander_radius: 4 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonsterStats {
+    pub id: u32,
+    pub name: String,
+    pub level: u32,
+    pub hp: u32,
+    /// Experience for killing it
+    pub exp: u32,
+    pub stats: Stats,
+    pub behavior: Behavior,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelStats {
+    pub level: u32,
+    /// Total experience needed to reach the level
+    pub exp: u32,
+    pub hp: u32,
+    pub stats: Stats,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpawnEntry {
+    pub map: u32,
+    pub left: u16,
+    pub top: u16,
+    pub right: u16,
+    pub bottom: u16,
+    pub monster: u32,
+    /// Most of them alive at once
+    pub count: u32,
+    /// Seconds
+    pub respawn: u32,
+    /// Percent
+    pub jitter: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DropEntry {
+    pub monster: u32,
+    pub item: u32,
+    /// Thousandths
+    pub chance: u32,
+    pub min: u32,
+    pub max: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WarpEntry {
+    pub map: u32,
+    pub x: u16,
+    pub y: u16,
+    pub to_map: u32,
+    pub to_x: u16,
+    pub to_y: u16,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Tables {
+    pub items: BTreeMap<u32, ItemStats>,
+    pub monsters: BTreeMap<u32, MonsterStats>,
+    /// By level, starting at 1
+    pub levels: Vec<LevelStats>,
+    pub spawns: Vec<SpawnEntry>,
+    pub drops: Vec<DropEntry>,
+    pub skills: BTreeMap<u32, SkillStats>,
+    pub warps: Vec<WarpEntry>,
+}
+
+impl Tables {
+    /// Loads the tables from the files in `folder`
+    pub fn load(folder: &Path) -> Result<Tables, Error> {
+        let read = |file: &str| fs::read_to_string(folder.join(file));
+        // a missing optional table is an empty one
+        let read_optional = |file: &str| match read(file) {
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+            other => other,
+        };
+        Ok(Tables {
+            items: parse_items(&read(ITEMS_FILE)?)?,
+            monsters: parse_monsters(&read(MONSTERS_FILE)?)?,
+            levels: parse_levels(&read(LEVELS_FILE)?)?,
+            spawns: parse_spawns(&read_optional(SPAWNS_FILE)?)?,
+            drops: parse_drops(&read_optional(DROPS_FILE)?)?,
+            skills: parse_skills(&read_optional(SKILLS_FILE)?)?,
+            warps: parse_warps(&read_optional(WARPS_FILE)?)?,
+        })
+    }
+
+    pub fn level(&self, level: u32) -> Option<&LevelStats> {
+        self.levels.get((level as usize).checked_sub(1)?)
+    }
+
+    /// The level reached with `exp` experience
+    pub fn level_for_exp(&self, exp: u32) -> u32 {
+        self.levels.iter().take_while(|level| level.exp <= exp).count().max(1) as u32
+    }
+}
+
+pub fn parse_items(text: &str) -> Result<BTreeMap<u32, ItemStats>, Error> {
+    let mut items = BTreeMap::new();
+    for row in Table::parse(text).rows() {
+        let item = ItemStats { id: row.get("id")?, name: row.get("name")?, stats: row.stats()? };
+        if items.insert(item.id, item.clone()).is_some() {
+            return Err(Error::DuplicateId(item.id));
+        }
+    }
+    Ok(items)
+}
+
+pub fn parse_skills(text: &str) -> Result<BTreeMap<u32, SkillStats>, Error> {
+    let mut skills = BTreeMap::new();
+    for row in Table::parse(text).rows() {
+        let skill = SkillStats {
+            id: row.get("id")?,
+            name: row.get("name")?,
+            level: row.get("level")?,
+            stats: row.stats()?,
+            range: row.get_or("range", 0)?,
+        };
+        if skills.insert(skill.id, skill.clone()).is_some() {
+            return Err(Error::DuplicateId(skill.id));
+        }
+    }
+    Ok(skills)
+}
+
+pub fn parse_monsters(text: &str) -> Result<BTreeMap<u32, MonsterStats>, Error> {
+    let mut monsters = BTreeMap::new();
+    for row in Table::parse(text).rows() {
+        let monster = MonsterStats {
+            id: row.get("id")?,
+            name: row.get("name")?,
+            level: row.get("level")?,
+            hp: row.get("hp")?,
+            exp: row.get("exp")?,
+            stats: row.stats()?,
+            behavior: row.behavior()?,
+        };
+        if monsters.insert(monster.id, monster.clone()).is_some() {
+            return Err(Error::DuplicateId(monster.id));
+        }
+    }
+    Ok(monsters)
+}
+
+pub fn parse_levels(text: &str) -> Result<Vec<LevelStats>, Error> {
+    let mut levels: Vec<LevelStats> = Vec::new();
+    for row in Table::parse(text).rows() {
+        let level = LevelStats { level: row.get("level")?, exp: row.get("exp")?, hp: row.get("hp")?, stats: row.stats()? };
+        // every level once, in order, needing more experience than the last
+        let follows = level.level as usize == levels.len() + 1
+            && levels.last().is_none_or(|last| level.exp > last.exp);
+        if !follows {
+            return Err(Error::InvalidField(row.line, "level".into()));
+        }
+        levels.push(level);
+    }
+    Ok(levels)
+}
+
+pub fn parse_spawns(text: &str) -> Result<Vec<SpawnEntry>, Error> {
+    let mut spawns = Vec::new();
+    for row in Table::parse(text).rows() {
+        let spawn = SpawnEntry {
+            map: row.get("map")?,
+            left: row.get("left")?,
+            top: row.get("top")?,
+            right: row.get("right")?,
+            bottom: row.get("bottom")?,
+            monster: row.get("monster")?,
+            count: row.get("count")?,
+            respawn: row.get("respawn")?,
+            jitter: row.get_or("jitter", DEFAULT_JITTER)?,
+        };
+        if spawn.left > spawn.right || spawn.top > spawn.bottom {
+            return Err(Error::InvalidField(row.line, "right".into()));
+        }
+        spawns.push(spawn);
+    }
+    Ok(spawns)
+}
+
+pub fn parse_drops(text: &str) -> Result<Vec<DropEntry>, Error> {
+    let mut drops = Vec::new();
+    for row in Table::parse(text).rows() {
+        let drop = DropEntry {
+            monster: row.get("monster")?,
+            item: row.get("item")?,
+            chance: row.get("chance")?,
+            min: row.get_or("min", 1)?,
+            max: row.get_or("max", 1)?,
+        };
+        if drop.chance > CHANCE_SCALE {
+            return Err(Error::InvalidField(row.line, "chance".into()));
+        }
+        if drop.min == 0 || drop.min > drop.max {
+            return Err(Error::InvalidField(row.line, "max".into()));
+        }
+        drops.push(drop);
+    }
+    Ok(drops)
+}
+
+pub fn parse_warps(text: &str) -> Result<Vec<WarpEntry>, Error> {
+    let mut warps = Vec::new();
+    for row in Table::parse(text).rows() {
+        warps.push(WarpEntry {
+            map: row.get("map")?,
+            x: row.get("x")?,
+            y: row.get("y")?,
+            to_map: row.get("to_map")?,
+            to_x: row.get("to_x")?,
+            to_y: row.get("to_y")?,
+        });
+    }
+    Ok(warps)
+}
+
+struct Row<'a> {
+    line: usize,
+    columns: &'a [&'a str],
+    fields: &'a [&'a str],
+}
+
+impl<'a> Row<'a> {
+    fn get<T: FromStr>(&self, column: &str) -> Result<T, Error> {
+        let index = self.columns.iter().position(|&c| c == column)
+            .ok_or_else(|| Error::MissingColumn(column.into()))?;
+        self.fields.get(index)
+            .and_then(|field| field.trim().parse().ok())
+            .ok_or_else(|| Error::InvalidField(self.line, column.into()))
+    }
+
+    /// The field of a column the table may leave out
+    fn get_or<T: FromStr>(&self, column: &str, default: T) -> Result<T, Error> {
+        match self.get(column) {
+            Err(Error::MissingColumn(_)) => Ok(default),
+            other => other,
+        }
+    }
+
+    fn behavior(&self) -> Result<Behavior, Error> {
+        let default = Behavior::default();
+        Ok(Behavior {
+            aggressive: self.get_or("aggressive", default.aggressive as u8)? != 0,
+            aggro_radius: self.get_or("aggro", default.aggro_radius)?,
+            leash_radius: self.get_or("leash", default.leash_radius)?,
+            wander_radius: self.get_or("wander", default.wander_radius)?,
+        })
+    }
+
+    fn stats(&self) -> Result<Stats, Error> {
+        Ok(Stats {
+            attack: self.get("attack")?,
+            defense: self.get("defense")?,
+            accuracy: self.get("accuracy")?,
+            evasion: self.get("evasion")?,
+        })
+    }
+}
+
+/// A table split into its fields
+struct Table<'a> {
+    columns: Vec<&'a str>,
+    /// The fields of each row, after its line number
+    rows: Vec<(usize, Vec<&'a str>)>,
+}
+
+impl<'a> Table<'a> {
+    fn parse(text: &'a str) -> Table<'a> {
+        let mut lines = text.lines().enumerate()
+            .filter(|&(_, line)| !line.trim().is_empty() && !line.starts_with('#'));
+        let columns = match lines.next() {
+            Some((_, header)) => header.split('\t').map(str::trim).collect(),
+            None => Vec::new(),
+        };
+        let rows = lines.map(|(number, line)| (number + 1, line.split('\t').collect())).collect();
+        Table { columns, rows }
+    }
+
+    fn rows(&self) -> impl Iterator<Item = Row<'_>> {
+        self.rows.iter().map(move |&(line, ref fields)| Row { line, columns: &self.columns, fields })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static ITEMS: &str = "# weapons first\nid\tname\tattack\tdefense\taccuracy\tevasion\tprice\n\
+                          1\tShort Sword\t5\t0\t2\t0\t100\n\
+                          2\tLeather Armor\t0\t4\t0\t1\t80\n";
+
+    #[test]
+    fn test_items() {
+        let items = parse_items(ITEMS).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[&1].name, "Short Sword");
+        assert_eq!(items[&2].stats, Stats { attack: 0, defense: 4, accuracy: 0, evasion: 1 });
+
+        match parse_items("id\tname\tattack\n1\tSword\t5\n") {
+            Err(Error::MissingColumn(ref column)) if column == "defense" => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match parse_items("id\tname\tattack\tdefense\taccuracy\tevasion\n1\tSword\tfive\t0\t0\t0\n") {
+            Err(Error::InvalidField(2, ref column)) if column == "attack" => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_monster_behavior() {
+        let text = "id\tname\tlevel\thp\texp\tattack\tdefense\taccuracy\tevasion\taggressive\tleash\n\
+                    1\tRat\t1\t30\t5\t6\t2\t5\t5\t0\t8\n";
+        let monsters = parse_monsters(text).unwrap();
+        let expected = Behavior { aggressive: false, leash_radius: 8, ..Behavior::default() };
+        assert_eq!(monsters[&1].behavior, expected);
+        assert_eq!(monsters[&1].exp, 5);
+    }
+
+    #[test]
+    fn test_spawns() {
+        let text = "map\tleft\ttop\tright\tbottom\tmonster\tcount\trespawn\n3\t10\t10\t20\t15\t1\t5\t30\n";
+        let spawns = parse_spawns(text).unwrap();
+        assert_eq!(spawns[0].count, 5);
+        assert_eq!(spawns[0].jitter, DEFAULT_JITTER);
+        match parse_spawns("map\tleft\ttop\tright\tbottom\tmonster\tcount\trespawn\n3\t20\t10\t10\t15\t1\t5\t30\n") {
+            Err(Error::InvalidField(2, _)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_skills() {
+        let text = "id\tname\tlevel\tattack\tdefense\taccuracy\tevasion\n1\tFocus\t3\t0\t0\t5\t0\n";
+        let skills = parse_skills(text).unwrap();
+        let focus = SkillStats { id: 1, name: "Focus".into(), level: 3, stats: Stats { accuracy: 5, ..Stats::default() }, range: 0 };
+        assert_eq!(skills[&1], focus);
+        let ranged = parse_skills("id\tname\tlevel\tattack\tdefense\taccuracy\tevasion\trange\n2\tShot\t1\t4\t0\t0\t0\t5\n").unwrap();
+        assert_eq!(ranged[&2].range, 5);
+        match parse_skills(&format!("{}1\tFocus\t3\t0\t0\t5\t0\n", text)) {
+            Err(Error::DuplicateId(1)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_drops() {
+        let text = "monster\titem\tchance\tmax\n1\t7\t500\t3\n1\t8\t1000\t1\n";
+        let drops = parse_drops(text).unwrap();
+        assert_eq!(drops[0], DropEntry { monster: 1, item: 7, chance: 500, min: 1, max: 3 });
+        assert_eq!(drops[1].chance, CHANCE_SCALE);
+        assert!(parse_drops("").unwrap().is_empty());
+        match parse_drops("monster\titem\tchance\n1\t7\t1001\n") {
+            Err(Error::InvalidField(2, ref column)) if column == "chance" => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match parse_drops("monster\titem\tchance\tmin\tmax\n1\t7\t10\t3\t2\n") {
+            Err(Error::InvalidField(2, ref column)) if column == "max" => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_warps() {
+        let text = "map\tx\ty\tto_map\tto_x\tto_y\n1\t5\t0\t2\t5\t63\n";
+        let warps = parse_warps(text).unwrap();
+        assert_eq!(warps, vec![WarpEntry { map: 1, x: 5, y: 0, to_map: 2, to_x: 5, to_y: 63 }]);
+        match parse_warps("map\tx\ty\tto_map\tto_x\n1\t5\t0\t2\t5\n") {
+            Err(Error::MissingColumn(ref column)) if column == "to_y" => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_levels() {
+        let text = "level\texp\thp\tattack\tdefense\taccuracy\tevasion\n\
+                    1\t0\t100\t10\t0\t10\t5\n\
+                    2\t50\t120\t12\t1\t11\t5\n\
+                    3\t150\t140\t14\t2\t12\t6\n";
+        let tables = Tables { levels: parse_levels(text).unwrap(), ..Tables::default() };
+        assert_eq!(tables.level(2).unwrap().hp, 120);
+        assert!(tables.level(0).is_none() && tables.level(4).is_none());
+        assert_eq!(tables.level_for_exp(0), 1);
+        assert_eq!(tables.level_for_exp(149), 2);
+        assert_eq!(tables.level_for_exp(1000), 3);
+
+        match parse_levels("level\texp\thp\tattack\tdefense\taccuracy\tevasion\n2\t0\t100\t10\t0\t10\t5\n") {
+            Err(Error::InvalidField(2, _)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -1,10 +1,10 @@
-extern crate byteorder;
+#![no_std]
 
-pub mod character_table;
+extern crate alloc;
 
-use std::io::Cursor;
+pub mod character_table;
 
-use byteorder::ReadBytesExt;
+use alloc::string::String;
 
 use crate::character_table::CP949_TABLE;
 
@@ -22,27 +22,21 @@ fn lookup_949_char(input: u16) -> u32 {
 pub fn cp949_to_utf8(input: &[u8]) -> String {
 
     let mut output = String::new();
-    let mut cursor = Cursor::new(input);
-    let input_len = input.len() as u64;
-
-    while cursor.position() < input_len {
-        let idx = cursor.position();
-        let uni_code_point: u32 = match cursor.read_u8().unwrap() as u32 {
-            val @ 0x00 ... 0x7F => val as u32,
-            0x80 | 0xFF         => REPLACEMENT_CHARACTER, // undefined values
-            val @ 0x81 ... 0xFE => {
+    let mut bytes = input.iter().cloned();
+
+    while let Some(byte) = bytes.next() {
+        let uni_code_point: u32 = match byte {
+            val @ 0x00..=0x7F => val as u32,
+            0x80 | 0xFF       => REPLACEMENT_CHARACTER, // undefined values
+            val @ 0x81..=0xFE => {
                 // lead byte encountered
-                if idx + 1 < input_len {
-                    let next = cursor.read_u8().unwrap() as u16;
-                    let mut c: u16 = ((val as u16) << 8) + next;
-                    lookup_949_char(c) as u32
-                } else {
-                    REPLACEMENT_CHARACTER
+                match bytes.next() {
+                    Some(next) => lookup_949_char(((val as u16) << 8) + next as u16),
+                    None => REPLACEMENT_CHARACTER,
                 }
             },
-            _ => REPLACEMENT_CHARACTER
         };
-        let c = std::char::from_u32(uni_code_point).unwrap();
+        let c = core::char::from_u32(uni_code_point).unwrap();
         output.push(c);
     }
 
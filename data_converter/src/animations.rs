@@ -0,0 +1,283 @@
+//! `data_converter animations <rmd file> [--corrections <file.toml>] [-o <out.toml>]`
+//!
+//! The animations of a character RMD file grouped into actions and
+//! directions, as a TOML file of `[[animation]]` tables. The RMD files only
+//! list the animations; the grouping is guessed: eight animations in a row
+//! with as many frames are the eight directions of one action, going round
+//! in the order they're in, and any other animation is an action of its own.
+//! Every frame lasts 100 milliseconds.
+//!
+//! The `markers` of an animation say what happens on which of its frames,
+//! counted from 0: a `step`, a `swing` or an `impact` (see
+//! `core_compat::entity::rmd_animation::Marker`). Nothing can be guessed
+//! about them, so they're only ever added by the corrections.
+//!
+//! Where the guess is wrong, the tables of a `--corrections` file fix it:
+//! every one names an animation by its `index` and sets any of `action`,
+//! `direction`, `frames`, `delay` and `markers`, which win over the guess. The output
+//! can be used as the corrections as it is, so the way to make them is to
+//! write the guess to a file and edit it:
+//!
+//! ```toml
+//! [[animation]]
+//! index = 8
+//! action = "attack"
+//! direction = 0
+//! frames = [40, 41, 42, 43]
+//! delay = 80
+//! markers = [[1, "swing"], [3, "impact"]]
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use core_compat::entity::rmd::Rmd;
+use core_compat::entity::rmd_animation::Marker;
+use core_compat::entity::rmd_type::RmdType;
+
+use crate::error::Error;
+use crate::toml::{parse_tables, Value};
+use super::{load_rmd_data, RMD_ENTRIES};
+
+static USAGE: &str = "usage: animations <rmd file> [--corrections <file.toml>] [-o <out.toml>]";
+
+/// The directions a character can face
+pub const DIRECTIONS: usize = 8;
+
+/// How long a frame lasts unless a correction says otherwise, in
+/// milliseconds
+pub const DEFAULT_DELAY: u32 = 100;
+
+/// An animation of the RMD file and the action and direction it shows
+#[derive(Debug, Clone, PartialEq)]
+pub struct Animation {
+    /// Where it is in the RMD file
+    pub index: usize,
+    pub action: String,
+    /// From 0 to `DIRECTIONS - 1`
+    pub direction: u32,
+    /// The entries of the RMD file it shows, in order
+    pub frames: Vec<i16>,
+    /// How long every frame lasts, in milliseconds
+    pub delay: u32,
+    /// The frames, as indices into `frames`, and what happens on them
+    pub markers: Vec<(usize, Marker)>,
+}
+
+pub fn animations(args: &[String]) -> Result<(), Error> {
+    let (mut rmd_file, mut corrections, mut output) = (None, None, None);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--corrections" => corrections = iter.next(),
+            "-o" => output = iter.next(),
+            _ if rmd_file.is_none() => rmd_file = arg.parse::<u32>().ok(),
+            _ => return Err(Error::Args(USAGE.into())),
+        }
+    }
+    let rmd_file = rmd_file.ok_or_else(|| Error::Args(USAGE.into()))?;
+
+    let &(_, chr_short, chr_dir, _) = RMD_ENTRIES.iter().find(|e| e.3 == RmdType::Character).unwrap();
+    let rmd_path = Path::new(chr_dir).join(format!("{}{:05}.rmd", chr_short, rmd_file));
+    let rmd = load_rmd_data(&rmd_path, RmdType::Character)?;
+    let mut model = auto_group(&rmd);
+    if let Some(corrections) = corrections {
+        let mut text = String::new();
+        File::open(corrections)?.read_to_string(&mut text)?;
+        apply_corrections(&mut model, &text)
+            .map_err(|problems| Error::Validation(problems.iter().map(|p| format!("{}:{}", corrections, p)).collect()))?;
+    }
+    let missing: Vec<String> = model.iter()
+        .flat_map(|animation| animation.frames.iter().map(move |&frame| (animation.index, frame)))
+        .filter(|&(_, frame)| frame < 0 || rmd.get_entry(frame as usize).is_none())
+        .map(|(index, frame)| format!("animation {} shows entry {}, which {:?} doesn't have", index, frame, rmd_path))
+        .collect();
+    if !missing.is_empty() {
+        return Err(Error::Validation(missing));
+    }
+
+    let text = to_toml(&model);
+    match output {
+        Some(output) => {
+            File::create(output)?.write_all(text.as_bytes())?;
+            println!("wrote {} animations to {}", model.len(), output);
+        }
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+/// Guesses the action and direction of every animation of `rmd`; see the
+/// top of the module
+pub fn auto_group(rmd: &Rmd) -> Vec<Animation> {
+    let animations = rmd.animations();
+    let mut model = Vec::new();
+    let (mut index, mut action) = (0, 0);
+    while index < animations.len() {
+        let frame_count = animations[index].frames().len();
+        let run = animations[index..].iter()
+            .take(DIRECTIONS)
+            .take_while(|animation| animation.frames().len() == frame_count)
+            .count();
+        let directions = if run == DIRECTIONS { DIRECTIONS } else { 1 };
+        for direction in 0..directions {
+            model.push(Animation {
+                index: index + direction,
+                action: format!("action_{}", action),
+                direction: direction as u32,
+                frames: animations[index + direction].frames().to_vec(),
+                delay: DEFAULT_DELAY,
+                markers: Vec::new(),
+            });
+        }
+        index += directions;
+        action += 1;
+    }
+    model
+}
+
+/// Applies the `[[animation]]` tables of a corrections file to the guessed
+/// `model`, or returns what's wrong with it as `<line>: <problem>`
+pub fn apply_corrections(model: &mut [Animation], text: &str) -> Result<(), Vec<String>> {
+    let (tables, mut problems) = parse_tables(text, "animation");
+    for table in tables {
+        if let Err(problem) = correct(model, &table.fields) {
+            problems.push(format!("{}: {}", table.line, problem));
+        }
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems)
+    }
+}
+
+fn correct(model: &mut [Animation], fields: &BTreeMap<String, Value>) -> Result<(), String> {
+    let whole = |value: &Value, max: f64| match *value {
+        Value::Number(n) if n.fract() == 0.0 && n >= 0.0 && n <= max => Some(n),
+        _ => None,
+    };
+    let index = match fields.get("index").map(|index| whole(index, usize::MAX as f64)) {
+        Some(Some(index)) => index as usize,
+        Some(None) => return Err("`index` has to be a whole number".into()),
+        None => return Err("a correction needs the `index` of its animation".into()),
+    };
+    let animation = match model.iter_mut().find(|animation| animation.index == index) {
+        Some(animation) => animation,
+        None => return Err(format!("there's no animation {}", index)),
+    };
+    for (key, value) in fields.iter() {
+        match (key.as_str(), value) {
+            ("index", _) => (),
+            ("action", Value::Str(action)) => animation.action = action.clone(),
+            ("direction", value) => match whole(value, (DIRECTIONS - 1) as f64) {
+                Some(direction) => animation.direction = direction as u32,
+                None => return Err(format!("`direction` has to be from 0 to {}", DIRECTIONS - 1)),
+            },
+            ("frames", Value::Array(frames)) => {
+                let frames: Option<Vec<i16>> = frames.iter()
+                    .map(|frame| whole(frame, i16::MAX as f64).map(|frame| frame as i16))
+                    .collect();
+                animation.frames = frames.ok_or("the frames have to be entries of the RMD file")?;
+            }
+            ("delay", value) => match whole(value, u32::MAX as f64) {
+                Some(delay) => animation.delay = delay as u32,
+                None => return Err("`delay` has to be a whole number of milliseconds".into()),
+            },
+            ("markers", Value::Array(markers)) => {
+                animation.markers = markers.iter()
+                    .map(|marker| match *marker {
+                        Value::Array(ref pair) => match (pair.first().and_then(|frame| whole(frame, usize::MAX as f64)), pair.get(1)) {
+                            (Some(frame), Some(Value::Str(name))) if pair.len() == 2 => Marker::from_name(name)
+                                .map(|marker| (frame as usize, marker))
+                                .ok_or_else(|| format!("unknown marker `{}`", name)),
+                            _ => Err("a marker is written `[<frame>, \"<name>\"]`".into()),
+                        },
+                        _ => Err("a marker is written `[<frame>, \"<name>\"]`".into()),
+                    })
+                    .collect::<Result<_, String>>()?;
+            }
+            ("action", _) | ("frames", _) | ("markers", _) => return Err(format!("`{}` has the wrong kind of value", key)),
+            _ => return Err(format!("unknown key `{}`", key)),
+        }
+    }
+    // the frames may have been corrected as well
+    if let Some(&(frame, marker)) = animation.markers.iter().find(|&&(frame, _)| frame >= animation.frames.len()) {
+        return Err(format!("the {} marker is on frame {}, but the animation has {} frames",
+                           marker.name(), frame, animation.frames.len()));
+    }
+    Ok(())
+}
+
+/// The model as `[[animation]]` tables, which read back as corrections
+pub fn to_toml(model: &[Animation]) -> String {
+    let mut text = String::new();
+    for animation in model.iter() {
+        let frames: Vec<String> = animation.frames.iter().map(|frame| frame.to_string()).collect();
+        let markers: Vec<String> = animation.markers.iter()
+            .map(|&(frame, marker)| format!("[{}, {:?}]", frame, marker.name()))
+            .collect();
+        text.push_str(&format!("[[animation]]\nindex = {}\naction = {:?}\ndirection = {}\nframes = [{}]\ndelay = {}\nmarkers = [{}]\n\n",
+                               animation.index, animation.action, animation.direction, frames.join(", "), animation.delay,
+                               markers.join(", ")));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core_compat::fixture::RmdFixture;
+    use core_compat::parser::rmd::parse_rmd;
+
+    #[test]
+    fn test_animations() {
+        // eight directions of two frames, then one of three
+        let mut fixture = RmdFixture::new().entry(&[&[1]]);
+        for _ in 0..DIRECTIONS {
+            fixture = fixture.animation(&[0, 0]);
+        }
+        let rmd = parse_rmd(RmdType::Character, &fixture.animation(&[0, 0, 0]).build()).unwrap();
+
+        let mut model = auto_group(&rmd);
+        assert_eq!(model.len(), 9);
+        assert_eq!((model[3].action.as_str(), model[3].direction), ("action_0", 3));
+        assert_eq!((model[8].action.as_str(), model[8].direction, model[8].frames.len()), ("action_1", 0, 3));
+        assert!(model.iter().all(|animation| animation.delay == DEFAULT_DELAY));
+
+        // the written model reads back as it is
+        let guessed = model.clone();
+        apply_corrections(&mut model, &to_toml(&guessed)).unwrap();
+        assert_eq!(model, guessed);
+
+        // the corrections win
+        apply_corrections(&mut model, "[[animation]]\nindex = 8\naction = \"die \\\"hard\\\"\"\nframes = [0]\ndelay = 250\n").unwrap();
+        assert_eq!(model[8], Animation { index: 8, action: "die \"hard\"".into(), direction: 0, frames: vec![0], delay: 250, markers: vec![] });
+        assert!(to_toml(&model).contains("action = \"die \\\"hard\\\"\""));
+
+        // so do the markers, which read back too
+        apply_corrections(&mut model, "[[animation]]\nindex = 3\nmarkers = [[0, \"swing\"], [1, \"impact\"]]\n").unwrap();
+        assert_eq!(model[3].markers, vec![(0, Marker::Swing), (1, Marker::Impact)]);
+        let marked = model.clone();
+        apply_corrections(&mut model, &to_toml(&marked)).unwrap();
+        assert_eq!(model, marked);
+
+        let problems = apply_corrections(&mut model, "[[animation]]\naction = \"idle\"\n\
+                                                      [[animation]]\nindex = 9\n\
+                                                      [[animation]]\nindex = 0\ndirection = 8\n\
+                                                      [[animation]]\nindex = 0\nspeed = 2\n\
+                                                      [[animation]]\nindex = 0\nmarkers = [[2, \"step\"]]\n\
+                                                      [[animation]]\nindex = 0\nmarkers = [[0, \"jump\"]]\n").unwrap_err();
+        assert_eq!(problems, vec![
+            "1: a correction needs the `index` of its animation",
+            "3: there's no animation 9",
+            "5: `direction` has to be from 0 to 7",
+            "8: unknown key `speed`",
+            "11: the step marker is on frame 2, but the animation has 2 frames",
+            "14: unknown marker `jump`",
+        ]);
+    }
+}
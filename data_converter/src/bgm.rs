@@ -0,0 +1,178 @@
+//! `data_converter bgm <folder> -o <out.m3u|out.json> [--maps <table>]`
+//!
+//! Collects the client's background music into a playlist, as M3U for
+//! external players or JSON for the client. Files in an unknown format are
+//! reported and left out.
+//!
+//! The map files don't say which music they play, so that comes from an
+//! optional table: each line is `<map number> <music file name>`, and `#`
+//! starts a comment. Every file named in the table must be in the folder.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::fs::read_dir;
+use std::io::Read;
+use std::io::Write;
+use std::path::PathBuf;
+
+use core_compat::parser::bgm::{parse_bgm, Music};
+
+use crate::error::Error;
+use super::json_string;
+
+static USAGE: &str = "usage: bgm <folder> -o <out.m3u|out.json> [--maps <table>]";
+
+#[derive(Debug, PartialEq)]
+struct Track {
+    path: PathBuf,
+    music: Music,
+    /// The maps that play it, by number
+    maps: Vec<u32>,
+}
+
+pub fn bgm(args: &[String]) -> Result<(), Error> {
+    let mut folder = None;
+    let mut output = None;
+    let mut table_path = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" => output = Some(iter.next().ok_or_else(|| Error::Args(USAGE.into()))?),
+            "--maps" => table_path = Some(iter.next().ok_or_else(|| Error::Args(USAGE.into()))?),
+            _ if folder.is_none() => folder = Some(arg),
+            _ => return Err(Error::Args(USAGE.into())),
+        }
+    }
+    let (folder, output) = match (folder, output) {
+        (Some(folder), Some(output)) => (folder, output),
+        _ => return Err(Error::Args(USAGE.into())),
+    };
+
+    let mut paths: Vec<PathBuf> = read_dir(folder)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+    let mut tracks = Vec::new();
+    for path in paths {
+        let mut data = Vec::new();
+        File::open(&path)?.read_to_end(&mut data)?;
+        match parse_bgm(&data) {
+            Some(music) => tracks.push(Track { path, music, maps: Vec::new() }),
+            None => println!("skipping {:?}: unknown format", path),
+        }
+    }
+
+    if let Some(table_path) = table_path {
+        let mut table = String::new();
+        File::open(table_path)?.read_to_string(&mut table)?;
+        assign_maps(&mut tracks, &parse_table(&table)?)?;
+    }
+
+    let playlist = if output.ends_with(".json") { to_json(&tracks) } else { to_m3u(&tracks) };
+    File::create(output)?.write_all(playlist.as_bytes())?;
+    println!("wrote {} tracks to {}", tracks.len(), output);
+    Ok(())
+}
+
+/// The music file names by map number
+fn parse_table(table: &str) -> Result<BTreeMap<u32, String>, Error> {
+    let mut maps = BTreeMap::new();
+    for (line_num, line) in table.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let bad_line = || Error::Args(format!("line {}: expected `<map number> <music file>`", line_num + 1));
+        let mut fields = line.splitn(2, char::is_whitespace);
+        let map = fields.next().and_then(|map| map.parse().ok()).ok_or_else(bad_line)?;
+        let file = fields.next().map(str::trim).ok_or_else(bad_line)?;
+        maps.insert(map, file.to_string());
+    }
+    Ok(maps)
+}
+
+fn assign_maps(tracks: &mut [Track], maps: &BTreeMap<u32, String>) -> Result<(), Error> {
+    let mut problems = Vec::new();
+    for (&map, file) in maps.iter() {
+        let track = tracks.iter_mut()
+            .find(|track| track.path.file_name().is_some_and(|name| name.eq_ignore_ascii_case(file.as_str())));
+        match track {
+            Some(track) => track.maps.push(map),
+            None => problems.push(format!("map {}: no music file {:?}", map, file)),
+        }
+    }
+    if problems.is_empty() { Ok(()) } else { Err(Error::Validation(problems)) }
+}
+
+fn to_m3u(tracks: &[Track]) -> String {
+    let mut m3u = String::from("#EXTM3U\n");
+    for track in tracks {
+        let seconds = track.music.duration_ms.map_or(-1, |ms| (ms / 1000) as i64);
+        let name = track.path.file_stem().unwrap_or_default().to_string_lossy();
+        let maps: Vec<String> = track.maps.iter().map(|map| map.to_string()).collect();
+        if maps.is_empty() {
+            m3u.push_str(&format!("#EXTINF:{},{}\n", seconds, name));
+        } else {
+            m3u.push_str(&format!("#EXTINF:{},{} (maps {})\n", seconds, name, maps.join(", ")));
+        }
+        m3u.push_str(&format!("{}\n", track.path.display()));
+    }
+    m3u
+}
+
+fn to_json(tracks: &[Track]) -> String {
+    let mut json = String::from("[\n");
+    for (idx, track) in tracks.iter().enumerate() {
+        let duration = track.music.duration_ms.map_or("null".into(), |ms| ms.to_string());
+        let maps: Vec<String> = track.maps.iter().map(|map| map.to_string()).collect();
+        json.push_str(&format!(
+            "  {{\"file\": {}, \"format\": \"{}\", \"duration_ms\": {}, \"maps\": [{}]}}",
+            json_string(&track.path.to_string_lossy()), track.music.format.name(), duration, maps.join(", ")));
+        json.push_str(if idx + 1 < tracks.len() { ",\n" } else { "\n" });
+    }
+    json.push_str("]\n");
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core_compat::parser::bgm::MusicFormat;
+
+    fn tracks() -> Vec<Track> {
+        vec![
+            Track { path: "bgm/Town.wav".into(), maps: Vec::new(),
+                    music: Music { format: MusicFormat::Wave, duration_ms: Some(95_500), tracks: None } },
+            Track { path: "bgm/field.mid".into(), maps: Vec::new(),
+                    music: Music { format: MusicFormat::Midi, duration_ms: None, tracks: Some(4) } },
+        ]
+    }
+
+    #[test]
+    fn test_assign_maps() {
+        let table = parse_table("# map music\n1 town.wav\n3 field.mid\n4 field.mid # cave\n").unwrap();
+        let mut tracks = tracks();
+        assign_maps(&mut tracks, &table).unwrap();
+        assert_eq!(tracks[0].maps, vec![1]);
+        assert_eq!(tracks[1].maps, vec![3, 4]);
+
+        assert!(parse_table("town.wav\n").is_err());
+        let table = parse_table("2 missing.mid").unwrap();
+        assert!(assign_maps(&mut tracks, &table).is_err());
+    }
+
+    #[test]
+    fn test_playlist_formats() {
+        let mut tracks = tracks();
+        tracks[0].maps = vec![1, 2];
+        assert_eq!(to_m3u(&tracks), "#EXTM3U\n\
+                                     #EXTINF:95,Town (maps 1, 2)\nbgm/Town.wav\n\
+                                     #EXTINF:-1,field\nbgm/field.mid\n");
+        assert_eq!(to_json(&tracks), "[\n  \
+            {\"file\": \"bgm/Town.wav\", \"format\": \"wave\", \"duration_ms\": 95500, \"maps\": [1, 2]},\n  \
+            {\"file\": \"bgm/field.mid\", \"format\": \"midi\", \"duration_ms\": null, \"maps\": []}\n]\n");
+    }
+}
@@ -0,0 +1,250 @@
+//! `data_converter card <tables> <query> -o <out.png> [--sprites <rm.sqlite>]`
+//!
+//! Renders a card for chat bot embeds: the sprite, name and stats of the item
+//! or monster of the rule tables in `<tables>` best matching `<query>` (see
+//! `search`). The match is printed as `<kind> <id> <name>`, followed by the
+//! other matches so a bot can offer them.
+//!
+//! The sprites come from the database of `rle2sqlite` as for `wiki`, and the
+//! text is set in the client's font.
+
+use std::fs;
+use std::path::Path;
+
+use rusttype::{point, Font, FontCollection, Scale};
+
+use core_rules::table::{Stats, Tables};
+
+use crate::error::Error;
+use crate::ora::{composite, thumbnail, Layer};
+use crate::search::{Hit, Index, Kind};
+use crate::wiki::{open_sprites, Sprite, SpriteLookup};
+use super::encode_png;
+
+static USAGE: &str = "usage: card <tables> <query> -o <out.png> [--sprites <rm.sqlite>]";
+
+pub static FONT: &[u8] = include_bytes!("../../client/static/noto_font/NotoMono-Regular.ttf");
+
+const CARD_WIDTH: u32 = 360;
+const PADDING: u32 = 8;
+/// Sprites are scaled down to fit a square this big
+const ICON_SIZE: u32 = 64;
+const TITLE_SIZE: f32 = 20.0;
+const TEXT_SIZE: f32 = 14.0;
+/// Space between lines of text
+const LEADING: u32 = 4;
+/// How many other matches are printed
+const MAX_OTHERS: usize = 5;
+
+const BACKGROUND: [u8; 3] = [32, 35, 42];
+const TITLE_COLOR: [u8; 3] = [255, 255, 255];
+const TEXT_COLOR: [u8; 3] = [190, 195, 205];
+
+pub fn card(args: &[String]) -> Result<(), Error> {
+    let mut positional = Vec::new();
+    let mut output = None;
+    let mut sprites = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" => output = Some(iter.next().ok_or_else(|| Error::Args(USAGE.into()))?),
+            "--sprites" => sprites = Some(iter.next().ok_or_else(|| Error::Args(USAGE.into()))?),
+            _ => positional.push(arg),
+        }
+    }
+    let (tables, query, output) = match (positional.as_slice(), output) {
+        (&[tables, query], Some(output)) => (tables, query, output),
+        _ => return Err(Error::Args(USAGE.into())),
+    };
+
+    let tables = Tables::load(Path::new(tables))?;
+    let index = Index::new(&tables);
+    let (hits, png) = match sprites {
+        Some(path) => render(&tables, &index, query, &mut open_sprites(Path::new(path))?)?,
+        None => render(&tables, &index, query, &mut |_: &str| Ok(None))?,
+    };
+    let png = match png {
+        Some(png) => png,
+        None => return Err(Error::Args(format!("nothing matches `{}`", query))),
+    };
+    fs::write(output, png)?;
+    for hit in hits.iter().take(MAX_OTHERS + 1) {
+        println!("{} {} {}", kind_name(hit.kind), hit.id, hit.name);
+    }
+    Ok(())
+}
+
+/// The matches of `query` and the card of the best one as PNG, if any
+pub fn render(
+    tables: &Tables,
+    index: &Index,
+    query: &str,
+    sprites: &mut SpriteLookup,
+) -> Result<(Vec<Hit>, Option<Vec<u8>>), Error> {
+    let hits = index.search(query);
+    let hit = match hits.first() {
+        Some(hit) => hit,
+        None => return Ok((hits, None)),
+    };
+    let lines = match hit.kind {
+        Kind::Item => {
+            let item = &tables.items[&hit.id];
+            let mut lines = vec![format!("Item {}", item.id)];
+            lines.extend(stat_lines(item.stats));
+            lines
+        }
+        Kind::Monster => {
+            let monster = &tables.monsters[&hit.id];
+            let mut lines = vec![
+                format!("Monster {}, level {}", monster.id, monster.level),
+                format!("HP {}  Exp {}", monster.hp, monster.exp),
+            ];
+            lines.extend(stat_lines(monster.stats));
+            lines
+        }
+    };
+    let sprite = sprites(&hit.name)?;
+    let image = compose(&hit.name, &lines, sprite.as_ref())?;
+    let png = encode_png(image.width, image.height, &image.rgba)?;
+    Ok((hits, Some(png)))
+}
+
+fn kind_name(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Item => "item",
+        Kind::Monster => "monster",
+    }
+}
+
+fn stat_lines(stats: Stats) -> Vec<String> {
+    vec![
+        format!("Attack {}  Defense {}", stats.attack, stats.defense),
+        format!("Accuracy {}  Evasion {}", stats.accuracy, stats.evasion),
+    ]
+}
+
+/// The card: the sprite on the left, the title and lines of text next to it
+fn compose(title: &str, lines: &[String], sprite: Option<&Sprite>) -> Result<Sprite, Error> {
+    let font = FontCollection::from_bytes(FONT)
+        .and_then(|collection| collection.into_font())
+        .map_err(|error| Error::Args(format!("can't read the font: {}", error)))?;
+    let mut texts = vec![text(&font, title, TITLE_SIZE, TITLE_COLOR)];
+    texts.extend(lines.iter().map(|line| text(&font, line, TEXT_SIZE, TEXT_COLOR)));
+    let text_height: u32 = texts.iter().map(|text| text.height + LEADING).sum::<u32>() - LEADING;
+    let height = text_height.max(ICON_SIZE) + 2 * PADDING;
+
+    let background: Vec<u8> = (0..CARD_WIDTH * height)
+        .flat_map(|_| [BACKGROUND[0], BACKGROUND[1], BACKGROUND[2], 255])
+        .collect();
+    let mut layers = vec![layer("background", 0, 0, CARD_WIDTH, height, &background)];
+
+    let icon = sprite.map(|sprite| thumbnail(sprite.width, sprite.height, &sprite.rgba, ICON_SIZE));
+    if let Some((width, height, ref rgba)) = icon {
+        let x = PADDING + (ICON_SIZE - width) / 2;
+        let y = PADDING + (ICON_SIZE - height) / 2;
+        layers.push(layer("sprite", x, y, width, height, rgba));
+    }
+
+    let mut y = PADDING;
+    for text in texts.iter() {
+        layers.push(layer("text", 2 * PADDING + ICON_SIZE, y, text.width, text.height, &text.rgba));
+        y += text.height + LEADING;
+    }
+    let rgba = composite(&layers, 0, 0, CARD_WIDTH, height);
+    Ok(Sprite { width: CARD_WIDTH, height, rgba })
+}
+
+fn layer<'a>(name: &str, x: u32, y: u32, width: u32, height: u32, rgba: &'a [u8]) -> Layer<'a> {
+    Layer { name: name.into(), x: x as i32, y: y as i32, width, height, rgba, visible: true }
+}
+
+/// A line of text, blended over the background already since `composite`
+/// doesn't blend
+fn text(font: &Font, text: &str, size: f32, color: [u8; 3]) -> Sprite {
+    let scale = Scale::uniform(size);
+    let glyphs: Vec<_> = font.layout(text, scale, point(0.0, font.v_metrics(scale).ascent)).collect();
+    let width = glyphs.last()
+        .map_or(0.0, |glyph| glyph.position().x + glyph.unpositioned().h_metrics().advance_width)
+        .ceil().max(1.0) as u32;
+    let height = size.ceil() as u32;
+    let mut rgba = vec![0u8; (width * height * 4) as usize];
+    for glyph in glyphs.iter() {
+        let bounds = match glyph.pixel_bounding_box() {
+            Some(bounds) => bounds,
+            None => continue,
+        };
+        glyph.draw(|x, y, coverage| {
+            let x = x as i32 + bounds.min.x;
+            let y = y as i32 + bounds.min.y;
+            if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 || coverage <= 0.0 {
+                return;
+            }
+            let start = ((y as u32 * width + x as u32) * 4) as usize;
+            for channel in 0..3 {
+                let (from, to) = (BACKGROUND[channel] as f32, color[channel] as f32);
+                rgba[start + channel] = (from + (to - from) * coverage.min(1.0)).round() as u8;
+            }
+            rgba[start + 3] = 255;
+        });
+    }
+    Sprite { width, height, rgba }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core_rules::table::{parse_items, parse_monsters};
+
+    fn tables() -> Tables {
+        Tables {
+            items: parse_items("id\tname\tattack\tdefense\taccuracy\tevasion\n1\tRat Tail\t0\t0\t0\t0\n").unwrap(),
+            monsters: parse_monsters("id\tname\tlevel\thp\texp\tattack\tdefense\taccuracy\tevasion\n\
+                                      1\tRat\t1\t30\t5\t4\t0\t5\t0\n").unwrap(),
+            ..Tables::default()
+        }
+    }
+
+    fn pixel(image: &Sprite, x: u32, y: u32) -> &[u8] {
+        let start = ((y * image.width + x) * 4) as usize;
+        &image.rgba[start..start + 4]
+    }
+
+    #[test]
+    fn test_compose() {
+        // a red sprite twice the icon size ends up as a full icon
+        let sprite = Sprite { width: 128, height: 128, rgba: [255, 0, 0, 255].repeat(128 * 128) };
+        let lines = vec!["HP 30  Exp 5".to_string()];
+        let image = compose("Rat", &lines, Some(&sprite)).unwrap();
+        assert_eq!((image.width, image.height), (CARD_WIDTH, ICON_SIZE + 2 * PADDING));
+        assert_eq!(pixel(&image, 0, 0), &[32, 35, 42, 255]);
+        assert_eq!(pixel(&image, PADDING, PADDING), &[255, 0, 0, 255]);
+        assert_eq!(pixel(&image, PADDING + ICON_SIZE - 1, PADDING + ICON_SIZE - 1), &[255, 0, 0, 255]);
+        assert_eq!(pixel(&image, PADDING + ICON_SIZE, PADDING), &[32, 35, 42, 255]);
+        // there's text right of the icon, and every pixel is opaque
+        let text_start = (2 * PADDING + ICON_SIZE) as usize;
+        assert!(image.rgba.chunks(4).enumerate()
+            .any(|(idx, px)| idx % CARD_WIDTH as usize >= text_start && px[0] > 128 && px[1] > 128));
+        assert!(image.rgba.chunks(4).all(|px| px[3] == 255));
+
+        let lines: Vec<String> = (0..6).map(|line| line.to_string()).collect();
+        assert!(compose("Rat", &lines, None).unwrap().height > ICON_SIZE + 2 * PADDING);
+    }
+
+    #[test]
+    fn test_render() {
+        let tables = tables();
+        let index = Index::new(&tables);
+        let mut looked_up = Vec::new();
+        let (hits, png) = render(&tables, &index, "rat", &mut |name: &str| {
+            looked_up.push(name.to_string());
+            Ok(None)
+        }).unwrap();
+        assert_eq!(hits.iter().map(|hit| hit.name.as_str()).collect::<Vec<_>>(), vec!["Rat", "Rat Tail"]);
+        assert_eq!(looked_up, vec!["Rat"]);
+        assert!(png.unwrap().starts_with(b"\x89PNG"));
+
+        let (hits, png) = render(&tables, &index, "dragon", &mut |_: &str| Ok(None)).unwrap();
+        assert!(hits.is_empty() && png.is_none());
+    }
+}
@@ -0,0 +1,211 @@
+//! `data_converter codegen <list> <ids> -o <out.rs> [--include-bytes]`
+//!
+//! Writes a Rust module embedding a handful of decoded sprites, so small
+//! tools and examples can use them without the game data. Every sprite gets a
+//! `pub static` and a variant of the `Sprite` enum indexing them.
+//!
+//! With `--include-bytes` the pixels are written as `<list>_<id>.rgba` files
+//! next to the module and pulled in with `include_bytes!` instead of being
+//! spelled out as arrays.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use core_compat::entity::resource::Resource;
+
+use crate::error::Error;
+use crate::rle::parse_indices;
+use super::RLE_ENTRIES;
+use super::{find_rle_file, load_list_data, load_rle_data};
+
+static USAGE: &str = "usage: codegen <list> <id,id,..> -o <out.rs> [--include-bytes]";
+
+/// A sprite to embed: the list id and name plus the decoded resource
+struct EmbeddedSprite<'a> {
+    id: u32,
+    name: &'a str,
+    resource: &'a Resource,
+}
+
+pub fn codegen(args: &[String]) -> Result<(), Error> {
+    let mut positional = Vec::new();
+    let mut output = None;
+    let mut include_bytes = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" => output = iter.next(),
+            "--include-bytes" => include_bytes = true,
+            _ => positional.push(arg),
+        }
+    }
+    let (short, ids, output) = match (positional.as_slice(), output) {
+        (&[short, ids], Some(output)) => (short, parse_indices(ids)?, Path::new(output)),
+        _ => return Err(Error::Args(USAGE.into())),
+    };
+
+    let &(_, short, folder, list_path, use_v2) = RLE_ENTRIES.iter()
+        .find(|e| e.1 == short.as_str())
+        .ok_or_else(|| Error::Args(format!("unknown list `{}`", short)))?;
+    let list = load_list_data(Path::new(list_path), use_v2)?;
+
+    // load every resource first so all missing ids are reported at once
+    let mut problems = Vec::new();
+    let mut loaded = Vec::new();
+    for &id in ids.iter() {
+        let item = match list.get_item(id) {
+            Some(item) => item,
+            None => { problems.push(format!("{}: no list item {}", short, id)); continue },
+        };
+        let resource = match find_rle_file(folder, item.entry.file())? {
+            Some(path) => load_rle_data(&path)?.resources.into_iter()
+                .find(|r| r.index() == item.entry.index()),
+            None => None,
+        };
+        match resource {
+            Some(resource) => loaded.push((item, resource)),
+            None => problems.push(format!("{}: no resource {}:{} for list item {}",
+                                          short, item.entry.file(), item.entry.index(), id)),
+        }
+    }
+    if !problems.is_empty() {
+        return Err(Error::Validation(problems));
+    }
+
+    let sprites: Vec<EmbeddedSprite> = loaded.iter()
+        .map(|&(item, ref resource)| EmbeddedSprite { id: item.id, name: &item.name, resource })
+        .collect();
+    let (source, blobs) = generate_module(short, &sprites, include_bytes);
+
+    let out_dir = output.parent().unwrap_or_else(|| Path::new(""));
+    for (file_name, bytes) in blobs {
+        File::create(out_dir.join(file_name))?.write_all(bytes)?;
+    }
+    File::create(output)?.write_all(source.as_bytes())?;
+    println!("wrote {} sprites to {:?}", sprites.len(), output);
+    Ok(())
+}
+
+/// Returns the module source and, with `include_bytes`, the files it includes.
+fn generate_module<'a>(
+    short: &str,
+    sprites: &[EmbeddedSprite<'a>],
+    include_bytes: bool
+) -> (String, Vec<(String, &'a [u8])>) {
+    let mut src = String::new();
+    let mut blobs = Vec::new();
+
+    src.push_str("//! Sprites embedded by `data_converter codegen`; do not edit by hand.\n\n");
+    src.push_str("pub struct EmbeddedSprite {\n");
+    src.push_str("    pub id: u32,\n");
+    src.push_str("    pub name: &'static str,\n");
+    src.push_str("    pub offset_x: i32,\n");
+    src.push_str("    pub offset_y: i32,\n");
+    src.push_str("    pub width: u32,\n");
+    src.push_str("    pub height: u32,\n");
+    src.push_str("    /// RGBA8 pixels, row by row\n");
+    src.push_str("    pub rgba: &'static [u8],\n");
+    src.push_str("}\n\n");
+
+    // typed index
+    src.push_str("#[derive(Debug, Copy, Clone, PartialEq, Eq)]\n");
+    src.push_str("pub enum Sprite {\n");
+    for sprite in sprites {
+        src.push_str(&format!("    {},\n", variant_name(short, sprite.id)));
+    }
+    src.push_str("}\n\n");
+    src.push_str("impl Sprite {\n");
+    src.push_str("    pub fn sprite(self) -> &'static EmbeddedSprite {\n");
+    src.push_str("        match self {\n");
+    for sprite in sprites {
+        src.push_str(&format!("            Sprite::{} => &{},\n",
+                              variant_name(short, sprite.id), static_name(short, sprite.id)));
+    }
+    src.push_str("        }\n");
+    src.push_str("    }\n");
+    src.push_str("}\n\n");
+
+    src.push_str(&format!("pub static SPRITES: [&EmbeddedSprite; {}] = [\n", sprites.len()));
+    for sprite in sprites {
+        src.push_str(&format!("    &{},\n", static_name(short, sprite.id)));
+    }
+    src.push_str("];\n");
+
+    for sprite in sprites {
+        let res = sprite.resource;
+        src.push_str(&format!("\npub static {}: EmbeddedSprite = EmbeddedSprite {{\n",
+                              static_name(short, sprite.id)));
+        src.push_str(&format!("    id: {},\n", sprite.id));
+        src.push_str(&format!("    name: {:?},\n", sprite.name));
+        src.push_str(&format!("    offset_x: {},\n", res.offset_x));
+        src.push_str(&format!("    offset_y: {},\n", res.offset_y));
+        src.push_str(&format!("    width: {},\n", res.width));
+        src.push_str(&format!("    height: {},\n", res.height));
+        if include_bytes {
+            let file_name = format!("{}_{}.rgba", short, sprite.id);
+            src.push_str(&format!("    rgba: include_bytes!({:?}),\n", file_name));
+            blobs.push((file_name, res.image_raw.as_slice()));
+        } else {
+            src.push_str("    rgba: &[\n");
+            for row in res.image_raw.chunks(16) {
+                let bytes: Vec<String> = row.iter().map(|b| format!("0x{:02X},", b)).collect();
+                src.push_str(&format!("        {}\n", bytes.join(" ")));
+            }
+            src.push_str("    ],\n");
+        }
+        src.push_str("};\n");
+    }
+    (src, blobs)
+}
+
+fn static_name(short: &str, id: u32) -> String {
+    format!("{}_{}", short.to_uppercase(), id)
+}
+
+fn variant_name(short: &str, id: u32) -> String {
+    let mut chars = short.chars();
+    match chars.next() {
+        Some(first) => format!("{}{}{}", first.to_uppercase(), chars.as_str(), id),
+        None => format!("Sprite{}", id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resource() -> Resource {
+        let mut res = Resource::new();
+        res.width = 1;
+        res.height = 1;
+        res.offset_x = -3;
+        res.image_raw = vec![0x10, 0x20, 0x30, 0xFF];
+        res
+    }
+
+    #[test]
+    fn test_generate_module() {
+        let res = resource();
+        let sprites = [EmbeddedSprite { id: 12, name: "tree \"big\"", resource: &res }];
+        let (src, blobs) = generate_module("obj", &sprites, false);
+
+        assert!(blobs.is_empty());
+        assert!(src.contains("    Obj12,\n"));
+        assert!(src.contains("Sprite::Obj12 => &OBJ_12,"));
+        assert!(src.contains("pub static SPRITES: [&EmbeddedSprite; 1]"));
+        assert!(src.contains("name: \"tree \\\"big\\\"\","));
+        assert!(src.contains("offset_x: -3,"));
+        assert!(src.contains("0x10, 0x20, 0x30, 0xFF,"));
+    }
+
+    #[test]
+    fn test_generate_module_include_bytes() {
+        let res = resource();
+        let sprites = [EmbeddedSprite { id: 4, name: "", resource: &res }];
+        let (src, blobs) = generate_module("ch0", &sprites, true);
+
+        assert!(src.contains("rgba: include_bytes!(\"ch0_4.rgba\"),"));
+        assert_eq!(blobs, vec![("ch0_4.rgba".to_string(), &res.image_raw[..])]);
+    }
+}
@@ -0,0 +1,499 @@
+//! `data_converter deepzoom <map> -o <out folder> [--threads <n>]`
+//!
+//! Renders a whole map, tiles and then objects the way the client draws
+//! them, as a Deep Zoom image a web viewer (e.g. OpenSeadragon) can pan and
+//! zoom without loading one enormous PNG: `Map<n>.dzi` describes the image
+//! and `Map<n>_files/<level>/<column>_<row>.png` are its tiles. The last
+//! level is the map at full size, every level before it half the size of
+//! the next, down to a single pixel.
+//!
+//! The tiles of a level are made on `--threads` threads, all the cores by
+//! default: the ones of the last level are rendered straight from the
+//! sprites, the others scaled down from the four tiles under them, so a
+//! level only needs the one after it on disk and never the whole map in
+//! memory. Sprites that can't be found are reported and left out.
+
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use core_compat::entity::list::List;
+use core_compat::entity::map::Map;
+use core_compat::entity::resource::Resource;
+use core_compat::entity::resource_file::ResourceFile;
+use core_compat::entity::rmd::Rmd;
+use core_compat::entity::rmd_type::RmdType;
+
+use crate::error::Error;
+use crate::export::{scale, Image};
+use super::{RLE_ENTRIES, RMD_ENTRIES, RMM_ENTRY};
+use super::{encode_png, find_rle_file, load_list_data, load_rle_data, load_rmd_data, load_rmm_data};
+
+static USAGE: &str = "usage: deepzoom <map> -o <out folder> [--threads <n>]";
+
+/// Side of the square tiles of every level
+pub const TILE_SIZE: u32 = 256;
+
+/// Size of a map tile, in pixels
+const MAP_TILE_WIDTH: i32 = 48;
+const MAP_TILE_HEIGHT: i32 = 24;
+
+pub fn deepzoom(args: &[String]) -> Result<(), Error> {
+    let mut positional = Vec::new();
+    let (mut output, mut threads) = (None, thread::available_parallelism().map_or(1, |n| n.get()));
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" => output = iter.next(),
+            "--threads" => threads = iter.next()
+                .and_then(|n| n.parse().ok())
+                .filter(|&n| n > 0)
+                .ok_or_else(|| Error::Args(USAGE.into()))?,
+            _ => positional.push(arg),
+        }
+    }
+    let (number, output) = match (positional.as_slice(), output) {
+        (&[number], Some(output)) => match number.parse::<u32>() {
+            Ok(number) => (number, output),
+            Err(_) => return Err(Error::Args(USAGE.into())),
+        },
+        _ => return Err(Error::Args(USAGE.into())),
+    };
+
+    let map = load_rmm_data(&Path::new(RMM_ENTRY.1).join(format!("Map{:05}.rmm", number)))?;
+    let mut problems = Vec::new();
+    let tiles = SpriteSource::load(RmdType::Tile, "tle", &map, &mut problems)?;
+    let objects = SpriteSource::load(RmdType::Object, "obj", &map, &mut problems)?;
+    let scene = Scene::new(&map, &tiles, &objects, &mut problems);
+    for problem in problems.iter() {
+        println!("{}", problem);
+    }
+
+    let name = format!("Map{:05}", number);
+    let levels = write_pyramid(&scene, Path::new(output), &name, threads)?;
+    println!("wrote {}x{} pixels in {} levels to {}", scene.width, scene.height, levels, output);
+    Ok(())
+}
+
+/// The RMD files of one kind the map uses, with their list and sprites
+pub struct SpriteSource {
+    kind: RmdType,
+    list: List,
+    rmds: HashMap<u32, Rmd>,
+    files: HashMap<u32, ResourceFile>,
+}
+
+impl SpriteSource {
+    /// Loads whatever the tiles of the map draw of `kind`; what can't be
+    /// found is added to `problems`, once.
+    pub fn load(kind: RmdType, short: &str, map: &Map, problems: &mut Vec<String>) -> Result<SpriteSource, Error> {
+        let &(_, _, folder, list_path, use_v2) = RLE_ENTRIES.iter().find(|e| e.1 == short).unwrap();
+        let &(_, rmd_short, rmd_dir, _) = RMD_ENTRIES.iter().find(|e| e.3 == kind).unwrap();
+        let mut source = SpriteSource {
+            kind,
+            list: load_list_data(Path::new(list_path), use_v2)?,
+            rmds: HashMap::new(),
+            files: HashMap::new(),
+        };
+        let mut missing = Vec::new();
+        for tile in map.tiles().iter() {
+            let entry = match kind {
+                RmdType::Tile => tile.tle_rmd_entry,
+                _ => tile.obj_rmd_entry,
+            };
+            if entry.file() == 0 || source.rmds.contains_key(&entry.file()) || missing.contains(&entry.file()) {
+                continue;
+            }
+            let path = Path::new(rmd_dir).join(format!("{}{:05}.rmd", rmd_short, entry.file()));
+            match load_rmd_data(&path, kind) {
+                Ok(rmd) => { source.rmds.insert(entry.file(), rmd); },
+                Err(_) => {
+                    problems.push(format!("{}: no RMD file {}", rmd_short, entry.file()));
+                    missing.push(entry.file());
+                }
+            }
+        }
+
+        let mut ids: Vec<i32> = source.rmds.values()
+            .flat_map(|rmd| (0..rmd.entry_count().max(0) as usize).filter_map(move |idx| rmd.get_entry(idx)))
+            .flat_map(|entry| entry.images().iter().flat_map(|img| img.image_id.iter().cloned()))
+            .collect();
+        ids.sort();
+        ids.dedup();
+        for id in ids {
+            let file = match source.list.get_item(id as usize) {
+                Some(item) => item.entry.file(),
+                None => continue,
+            };
+            if source.files.contains_key(&file) || missing.contains(&file) {
+                continue;
+            }
+            match find_rle_file(folder, file)? {
+                Some(path) => { source.files.insert(file, load_rle_data(&path)?); },
+                None => {
+                    problems.push(format!("{}: no RLE file {}", short, file));
+                    missing.push(file);
+                }
+            }
+        }
+        Ok(source)
+    }
+}
+
+/// A sprite where it's drawn on the map: its top left pixel goes to `x`,
+/// `y`, and only the pixels in `clip` (left, top, right, bottom, in the
+/// sprite) are drawn.
+#[derive(Clone)]
+pub struct Placed<'a> {
+    pub x: i32,
+    pub y: i32,
+    pub clip: (i32, i32, i32, i32),
+    pub res: &'a Resource,
+}
+
+impl<'a> Placed<'a> {
+    /// What the map shows of the sprite: left, top, right and bottom
+    fn bounds(&self) -> (i32, i32, i32, i32) {
+        let (left, top, right, bottom) = self.clip;
+        (self.x + left, self.y + top, self.x + right, self.y + bottom)
+    }
+}
+
+/// Everything drawn on a map, bottom to top, with the sprites of every
+/// `TILE_SIZE` square of it at hand
+pub struct Scene<'a> {
+    pub width: u32,
+    pub height: u32,
+    sprites: Vec<Placed<'a>>,
+    /// The indices into `sprites` of those showing in each square, row by
+    /// row, in drawing order
+    squares: Vec<Vec<usize>>,
+}
+
+impl<'a> Scene<'a> {
+    /// The tiles of the map, then its objects, each in the order of the map
+    pub fn new(map: &Map, tiles: &'a SpriteSource, objects: &'a SpriteSource, problems: &mut Vec<String>) -> Scene<'a> {
+        let mut sprites = Vec::new();
+        for &source in [tiles, objects].iter() {
+            for (idx, tile) in map.tiles().iter().enumerate() {
+                let entry = match source.kind {
+                    RmdType::Tile => tile.tle_rmd_entry,
+                    _ => tile.obj_rmd_entry,
+                };
+                let rmd_entry = match source.rmds.get(&entry.file()) {
+                    Some(rmd) => match rmd.get_entry(entry.index() as usize) {
+                        Some(rmd_entry) => rmd_entry,
+                        None => {
+                            problems.push(format!("no entry {} in {:?} file {}", entry.index(), source.kind, entry.file()));
+                            continue
+                        }
+                    },
+                    None => continue,
+                };
+                let tile_x = (idx as u32 % map.size_x()) as i32 * MAP_TILE_WIDTH;
+                let tile_y = (idx as u32 / map.size_x()) as i32 * MAP_TILE_HEIGHT;
+                for img in rmd_entry.images() {
+                    for &id in img.image_id.iter() {
+                        let res = source.list.get_item(id as usize)
+                            .and_then(|item| source.files.get(&item.entry.file()).and_then(|file| file.get(item.entry.index())));
+                        let res = match res {
+                            Some(res) => res,
+                            None => continue,
+                        };
+                        let placed = match source.kind {
+                            // the source rectangle of the sheet fills the map tile
+                            RmdType::Tile => Placed {
+                                x: tile_x - img.source_x1,
+                                y: tile_y - img.source_y1,
+                                clip: (img.source_x1, img.source_y1,
+                                       img.source_x2.min(img.source_x1 + MAP_TILE_WIDTH),
+                                       img.source_y2.min(img.source_y1 + MAP_TILE_HEIGHT)),
+                                res,
+                            },
+                            // the source rectangle is relative to the sprite's offset
+                            _ => Placed {
+                                x: tile_x + img.dest_x - img.source_x1 + res.offset_x,
+                                y: tile_y + img.dest_y - img.source_y1 + res.offset_y,
+                                clip: (img.source_x1 - res.offset_x, img.source_y1 - res.offset_y,
+                                       img.source_x2 - res.offset_x, img.source_y2 - res.offset_y),
+                                res,
+                            },
+                        };
+                        sprites.push(placed);
+                    }
+                }
+            }
+        }
+        let width = map.size_x() * MAP_TILE_WIDTH as u32;
+        let height = map.size_y() * MAP_TILE_HEIGHT as u32;
+        Scene::from_sprites(width, height, sprites)
+    }
+
+    /// A scene of `width` by `height` pixels, the sprites clipped to their
+    /// images and sorted into the squares they show in
+    pub fn from_sprites(width: u32, height: u32, mut sprites: Vec<Placed<'a>>) -> Scene<'a> {
+        let (columns, rows) = (tiles_across(width), tiles_across(height));
+        let mut squares = vec![Vec::new(); (columns * rows) as usize];
+        for (idx, sprite) in sprites.iter_mut().enumerate() {
+            let (left, top, right, bottom) = sprite.clip;
+            sprite.clip = (left.max(0), top.max(0), right.min(sprite.res.width), bottom.min(sprite.res.height));
+            let (left, top, right, bottom) = sprite.bounds();
+            if left >= right || top >= bottom || right <= 0 || bottom <= 0 || left >= width as i32 || top >= height as i32 {
+                continue;
+            }
+            let square = |at: i32, count: u32| (at.max(0) as u32 / TILE_SIZE).min(count - 1);
+            for row in square(top, rows)..=square(bottom - 1, rows) {
+                for column in square(left, columns)..=square(right - 1, columns) {
+                    squares[(row * columns + column) as usize].push(idx);
+                }
+            }
+        }
+        Scene { width, height, sprites, squares }
+    }
+
+    /// The pixels of the scene in the rectangle; it has to lie within a
+    /// single `TILE_SIZE` square. The sprites are either fully opaque or
+    /// fully transparent, so they're drawn over each other unblended.
+    pub fn render(&self, left: u32, top: u32, width: u32, height: u32) -> Vec<u8> {
+        let mut out = vec![0u8; (width * height * 4) as usize];
+        let square = (top / TILE_SIZE) * tiles_across(self.width) + left / TILE_SIZE;
+        for &idx in self.squares.get(square as usize).map_or(&[][..], |square| square.as_slice()) {
+            let sprite = &self.sprites[idx];
+            let (clip_left, clip_top, clip_right, clip_bottom) = sprite.clip;
+            for sprite_y in clip_top..clip_bottom {
+                let y = sprite.y + sprite_y - top as i32;
+                if y < 0 || y >= height as i32 {
+                    continue;
+                }
+                for sprite_x in clip_left..clip_right {
+                    let x = sprite.x + sprite_x - left as i32;
+                    if x < 0 || x >= width as i32 {
+                        continue;
+                    }
+                    let from = ((sprite_y * sprite.res.width + sprite_x) * 4) as usize;
+                    let px = &sprite.res.image_raw[from..from + 4];
+                    if px[3] != 0 {
+                        let to = ((y as u32 * width + x as u32) * 4) as usize;
+                        out[to..to + 4].copy_from_slice(px);
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// The pixels of the scene in any rectangle, put together from the
+    /// `TILE_SIZE` squares it covers; past the edges of the scene it's
+    /// transparent.
+    pub fn render_area(&self, left: u32, top: u32, width: u32, height: u32) -> Vec<u8> {
+        let mut out = vec![0u8; (width * height * 4) as usize];
+        let (right, bottom) = ((left + width).min(self.width), (top + height).min(self.height));
+        let mut y = top;
+        while y < bottom {
+            let rows = (TILE_SIZE - y % TILE_SIZE).min(bottom - y);
+            let mut x = left;
+            while x < right {
+                let columns = (TILE_SIZE - x % TILE_SIZE).min(right - x);
+                let part = self.render(x, y, columns, rows);
+                let len = (columns * 4) as usize;
+                for row in 0..rows {
+                    let to = (((y - top + row) * width + x - left) * 4) as usize;
+                    out[to..to + len].copy_from_slice(&part[(row * columns * 4) as usize..][..len]);
+                }
+                x += columns;
+            }
+            y += rows;
+        }
+        out
+    }
+}
+
+/// How many tiles it takes to cover `length` pixels
+fn tiles_across(length: u32) -> u32 {
+    length.div_ceil(TILE_SIZE).max(1)
+}
+
+/// Writes the Deep Zoom image of the scene to `folder`, as `<name>.dzi` and
+/// `<name>_files`; returns how many levels it has
+pub fn write_pyramid(scene: &Scene, folder: &Path, name: &str, threads: usize) -> Result<u32, Error> {
+    let (width, height) = (scene.width.max(1), scene.height.max(1));
+    let last = last_level(width, height);
+    let size = |level: u32| {
+        let shift = last - level;
+        (((width as u64 + (1 << shift) - 1) >> shift) as u32, ((height as u64 + (1 << shift) - 1) >> shift) as u32)
+    };
+    let files = folder.join(format!("{}_files", name));
+    for level in (0..=last).rev() {
+        let level_folder = files.join(level.to_string());
+        fs::create_dir_all(&level_folder)?;
+        let (level_width, level_height) = size(level);
+        let columns = tiles_across(level_width);
+        let tile = |idx: usize| -> Result<(), Error> {
+            let (column, row) = (idx as u32 % columns, idx as u32 / columns);
+            let (left, top) = (column * TILE_SIZE, row * TILE_SIZE);
+            let (tile_width, tile_height) = (TILE_SIZE.min(level_width - left), TILE_SIZE.min(level_height - top));
+            let rgba = if level == last {
+                scene.render(left, top, tile_width, tile_height)
+            } else {
+                scale_down(&files.join((level + 1).to_string()), column, row, size(level + 1))?
+            };
+            let path = level_folder.join(format!("{}_{}.png", column, row));
+            fs::write(path, encode_png(tile_width, tile_height, &rgba)?)?;
+            Ok(())
+        };
+        in_parallel((columns * tiles_across(level_height)) as usize, threads, tile)?;
+    }
+
+    let dzi = format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                       <Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" Format=\"png\" Overlap=\"0\" TileSize=\"{}\">\n  \
+                       <Size Width=\"{}\" Height=\"{}\"/>\n\
+                       </Image>\n", TILE_SIZE, width, height);
+    fs::write(folder.join(format!("{}.dzi", name)), dzi)?;
+    Ok(last + 1)
+}
+
+/// The level showing an image of `width` by `height` at full size; at
+/// level 0 it's a single pixel
+pub fn last_level(width: u32, height: u32) -> u32 {
+    32 - (width.max(height).max(1) - 1).leading_zeros()
+}
+
+/// The tile at `column`, `row` of a level, from the four tiles under it in
+/// `folder`, the next level of `size`
+fn scale_down(folder: &Path, column: u32, row: u32, (width, height): (u32, u32)) -> Result<Vec<u8>, Error> {
+    let (left, top) = (column * 2 * TILE_SIZE, row * 2 * TILE_SIZE);
+    let (area_width, area_height) = ((2 * TILE_SIZE).min(width - left), (2 * TILE_SIZE).min(height - top));
+    let mut area = vec![0u8; (area_width * area_height * 4) as usize];
+    for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter().cloned() {
+        if (dx * TILE_SIZE >= area_width) || (dy * TILE_SIZE >= area_height) {
+            continue;
+        }
+        let path = folder.join(format!("{}_{}.png", column * 2 + dx, row * 2 + dy));
+        let (tile_width, tile_height, rgba) = decode_png(&path)?;
+        for y in 0..tile_height {
+            let from = (y * tile_width * 4) as usize;
+            let to = (((dy * TILE_SIZE + y) * area_width + dx * TILE_SIZE) * 4) as usize;
+            area[to..to + (tile_width * 4) as usize].copy_from_slice(&rgba[from..from + (tile_width * 4) as usize]);
+        }
+    }
+    let image = Image { width: area_width, height: area_height, offset_x: 0, offset_y: 0, rgba: area };
+    Ok(scale(&image, 0.5).rgba)
+}
+
+fn decode_png(path: &Path) -> Result<(u32, u32, Vec<u8>), Error> {
+    let (info, mut reader) = png::Decoder::new(File::open(path)?).read_info().map_err(std::io::Error::from)?;
+    let mut rgba = vec![0; info.buffer_size()];
+    reader.next_frame(&mut rgba).map_err(std::io::Error::from)?;
+    Ok((info.width, info.height, rgba))
+}
+
+/// Runs `job` for every index below `count` on up to `threads` threads; the
+/// first error stops the others from starting new jobs
+fn in_parallel<F>(count: usize, threads: usize, job: F) -> Result<(), Error>
+    where F: Fn(usize) -> Result<(), Error> + Sync
+{
+    let next = AtomicUsize::new(0);
+    let failed = Mutex::new(None);
+    thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, count.max(1)) {
+            scope.spawn(|| loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                if idx >= count {
+                    break;
+                }
+                if let Err(error) = job(idx) {
+                    next.store(count, Ordering::Relaxed);
+                    failed.lock().unwrap().get_or_insert(error);
+                    break;
+                }
+            });
+        }
+    });
+    match failed.into_inner().unwrap() {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+
+
+    fn sprite(width: i32, height: i32, rgba: [u8; 4]) -> Resource {
+        let mut resource = Resource::new();
+        resource.width = width;
+        resource.height = height;
+        resource.image_raw = rgba.repeat((width * height) as usize);
+        resource
+    }
+
+    const RED: [u8; 4] = [0xFF, 0, 0, 0xFF];
+    const BLUE: [u8; 4] = [0, 0, 0xFF, 0xFF];
+
+    #[test]
+    fn test_scene_render() {
+        let (red, blue) = (sprite(4, 4, RED), sprite(2, 2, BLUE));
+        let scene = Scene::from_sprites(300, 10, vec![
+            // the bottom right quarter, across the first two squares
+            Placed { x: 253, y: 2, clip: (2, 2, 8, 8), res: &red },
+            // over it, in the second square only
+            Placed { x: 256, y: 3, clip: (0, 0, 2, 2), res: &blue },
+            // off the scene
+            Placed { x: -10, y: 0, clip: (0, 0, 2, 2), res: &blue },
+        ]);
+        assert_eq!(scene.squares, vec![vec![0], vec![0, 1]]);
+
+        let pixel = |rgba: &[u8], width: u32, x: u32, y: u32| rgba[((y * width + x) * 4) as usize..][..4].to_vec();
+        let first = scene.render(0, 0, 256, 10);
+        assert_eq!(pixel(&first, 256, 255, 4), RED);
+        assert_eq!(pixel(&first, 256, 254, 4), [0; 4]);
+        assert_eq!(pixel(&first, 256, 255, 3), [0; 4]);
+        let second = scene.render(256, 0, 44, 10);
+        assert_eq!(pixel(&second, 44, 0, 4), BLUE);
+        assert_eq!(pixel(&second, 44, 1, 3), BLUE);
+        assert_eq!(pixel(&second, 44, 0, 5), RED);
+        assert_eq!(pixel(&second, 44, 0, 6), [0; 4]);
+        assert_eq!(pixel(&second, 44, 2, 4), [0; 4]);
+
+        // across the squares and past the edge of the scene
+        let area = scene.render_area(250, 0, 60, 12);
+        assert_eq!(pixel(&area, 60, 5, 4), RED);
+        assert_eq!(pixel(&area, 60, 6, 4), BLUE);
+        assert_eq!(pixel(&area, 60, 6, 5), RED);
+        assert_eq!(pixel(&area, 60, 7, 5), [0; 4]);
+        assert_eq!(pixel(&area, 60, 59, 11), [0; 4]);
+    }
+
+    #[test]
+    fn test_write_pyramid() {
+        let red = sprite(4, 4, RED);
+        let scene = Scene::from_sprites(600, 300, vec![Placed { x: 510, y: 256, clip: (0, 0, 4, 4), res: &red }]);
+        let folder = env::temp_dir().join(format!("deepzoom-{}", std::process::id()));
+        assert_eq!(write_pyramid(&scene, &folder, "map", 3).unwrap(), 11);
+
+        let dzi = fs::read_to_string(folder.join("map.dzi")).unwrap();
+        assert!(dzi.contains("TileSize=\"256\""));
+        assert!(dzi.contains("<Size Width=\"600\" Height=\"300\"/>"));
+        let files = folder.join("map_files");
+        let tile = |level: u32, name: &str| decode_png(&files.join(level.to_string()).join(name)).unwrap();
+        // the last level is the full size, in 3x2 tiles
+        assert_eq!(tile(10, "2_1.png").0, 600 - 512);
+        assert_eq!(tile(10, "1_1.png").1, 300 - 256);
+        assert_eq!(tile(10, "1_1.png").2[254 * 4..][..4], RED);
+        // the one before it half of it, the red square down to 2x2 pixels
+        let (width, height, rgba) = tile(9, "0_0.png");
+        assert_eq!((width, height), (256, 150));
+        assert_eq!(rgba[(128 * 256 + 255) * 4..][..4], RED);
+        assert_eq!(rgba[(128 * 256 + 254) * 4..][..4], [0; 4]);
+        assert_eq!(tile(9, "1_0.png").0, 300 - 256);
+        assert_eq!(tile(0, "0_0.png").0, 1);
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,224 @@
+//! `data_converter doctor`
+//!
+//! Checks the environment the tools expect and prints a fix for everything
+//! that is off. Most first-run failures come from the setup rather than the
+//! code: the client data in the wrong place, unreadable files, a full disk or
+//! data from a client version the parsers don't know.
+//!
+//! The SQLite checks live in `rle2sqlite --doctor`, which links the SQLite
+//! build that matters.
+
+use std::fs::File;
+use std::fs::read_dir;
+use std::io::Read;
+use std::path::Path;
+use std::process::Command;
+
+use crate::error::Error;
+use super::{OUTPUT_PATH, RLE_ENTRIES, RMD_ENTRIES, RMM_ENTRY};
+
+/// The decoded sprites take about this many times the space of the RLE files
+const DATABASE_SIZE_FACTOR: u64 = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, PartialEq)]
+struct Check {
+    status: Status,
+    what: String,
+    fix: Option<String>,
+}
+
+impl Check {
+    fn ok(what: String) -> Check {
+        Check { status: Status::Ok, what, fix: None }
+    }
+
+    fn warn(what: String, fix: &str) -> Check {
+        Check { status: Status::Warn, what, fix: Some(fix.into()) }
+    }
+
+    fn fail(what: String, fix: &str) -> Check {
+        Check { status: Status::Fail, what, fix: Some(fix.into()) }
+    }
+}
+
+pub fn doctor(args: &[String]) -> Result<(), Error> {
+    if !args.is_empty() {
+        return Err(Error::Args("usage: doctor".into()));
+    }
+    println!("checking from {:?}", std::env::current_dir()?);
+
+    let mut checks = Vec::new();
+    let mut rle_bytes = 0;
+    for &(kind, _, folder, list, use_v2) in RLE_ENTRIES.iter() {
+        let (check, bytes) = check_folder(Path::new(folder), kind);
+        checks.push(check);
+        rle_bytes += bytes;
+        let expected_version = if use_v2 { "1.2" } else { "1.0" };
+        checks.push(check_list(Path::new(list), expected_version));
+    }
+    for &(kind, _, folder, _) in RMD_ENTRIES.iter() {
+        checks.push(check_folder(Path::new(folder), kind).0);
+    }
+    let (kind, folder) = RMM_ENTRY;
+    checks.push(check_folder(Path::new(folder), kind).0);
+    checks.push(check_disk_space(rle_bytes * DATABASE_SIZE_FACTOR));
+
+    for check in checks.iter() {
+        let status = match check.status {
+            Status::Ok => "ok  ",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+        };
+        println!("{} {}", status, check.what);
+        if let Some(ref fix) = check.fix {
+            println!("       fix: {}", fix);
+        }
+    }
+
+    let failures = checks.iter().filter(|c| c.status == Status::Fail).count();
+    if failures == 0 {
+        println!("no problems found");
+        Ok(())
+    } else {
+        Err(Error::Validation(vec![format!("{} checks failed", failures)]))
+    }
+}
+
+/// Checks that `folder` exists and every file in it can be read; returns the
+/// total size of the files as well.
+fn check_folder(folder: &Path, kind: &str) -> (Check, u64) {
+    let entries = match read_dir(folder) {
+        Ok(entries) => entries,
+        Err(_) => return (Check::fail(
+            format!("{}: folder {:?} is missing", kind, folder),
+            "copy the `RLEs` and `DATAs` folders of the client into `../data/`, \
+             next to the folder the tools are run from"), 0),
+    };
+    let mut files = 0;
+    let mut bytes = 0;
+    let mut unreadable = Vec::new();
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
+        }
+        match File::open(&path).and_then(|file| file.metadata()) {
+            Ok(meta) => { files += 1; bytes += meta.len(); },
+            Err(_) => unreadable.push(path),
+        }
+    }
+    let check = if !unreadable.is_empty() {
+        Check::fail(format!("{}: {} unreadable files in {:?}, e.g. {:?}", kind, unreadable.len(), folder, unreadable[0]),
+                    "make the data readable for your user, e.g. `chmod -R u+rX ../data`")
+    } else if files == 0 {
+        Check::warn(format!("{}: folder {:?} is empty", kind, folder),
+                    "the client copy is incomplete; copy the folder again from an installed client")
+    } else {
+        Check::ok(format!("{}: {} files in {:?}", kind, files, folder))
+    };
+    (check, bytes)
+}
+
+/// Checks that a list file is readable and in the format version this list
+/// uses in the supported clients.
+fn check_list(path: &Path, expected_version: &str) -> Check {
+    let mut data = Vec::new();
+    if File::open(path).and_then(|mut file| file.read_to_end(&mut data)).is_err() {
+        return Check::fail(format!("list {:?} is missing or unreadable", path),
+                           "copy the `.lst` files of the client's `RLEs` folder into `../data/RLEs/`");
+    }
+    match list_version(&data) {
+        Some(ref version) if version == expected_version =>
+            Check::ok(format!("list {:?} has version {}", path, version)),
+        Some(version) => Check::fail(
+            format!("list {:?} has version {:?}, expected {}", path, version, expected_version),
+            "this data comes from a client version the parsers don't support; \
+             use the data of a supported client"),
+        None => Check::fail(format!("list {:?} isn't a list file", path),
+                            "the file is damaged; copy it again from the client"),
+    }
+}
+
+/// The version string of a list file, see `parser::lst`
+pub fn list_version(data: &[u8]) -> Option<String> {
+    let type_len = *data.first()? as usize;
+    if data.get(1..1 + type_len)? != b"RedMoon Lst File" {
+        return None;
+    }
+    let version_len = *data.get(1 + type_len)? as usize;
+    let start = 2 + type_len;
+    let version = data.get(start..start + version_len)?;
+    Some(String::from_utf8_lossy(version).into_owned())
+}
+
+fn check_disk_space(needed: u64) -> Check {
+    let path = if Path::new(OUTPUT_PATH).exists() { OUTPUT_PATH } else { "." };
+    let free = Command::new("df").args(["-Pk", path]).output().ok()
+        .and_then(|out| parse_df(&String::from_utf8_lossy(&out.stdout)));
+    match free {
+        Some(free) if free >= needed => Check::ok(
+            format!("{} MiB free for the output, about {} MiB needed", free >> 20, needed >> 20)),
+        Some(free) => Check::fail(
+            format!("{} MiB free for the output, about {} MiB needed", free >> 20, needed >> 20),
+            "free some disk space or run the tools from a folder on another disk"),
+        None => Check::warn("couldn't check the free disk space".into(),
+                            "make sure there is room for about four times the size of `RLEs`"),
+    }
+}
+
+/// The available bytes from the output of `df -Pk`
+fn parse_df(output: &str) -> Option<u64> {
+    let line = output.lines().nth(1)?;
+    let available: u64 = line.split_whitespace().nth(3)?.parse().ok()?;
+    Some(available * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{create_dir_all, remove_dir_all};
+    use std::io::Write;
+
+    #[test]
+    fn test_check_folder() {
+        let dir = std::env::temp_dir().join("novluno_test_doctor");
+        let _ = remove_dir_all(&dir);
+        assert_eq!(check_folder(&dir, "obj").0.status, Status::Fail);
+
+        create_dir_all(&dir).unwrap();
+        assert_eq!(check_folder(&dir, "obj").0.status, Status::Warn);
+
+        File::create(dir.join("obj00001.rle")).unwrap().write_all(&[0; 10]).unwrap();
+        let (check, bytes) = check_folder(&dir, "obj");
+        assert_eq!(check.status, Status::Ok);
+        assert_eq!(bytes, 10);
+        let _ = remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_list_version() {
+        let mut data = vec![16];
+        data.extend_from_slice(b"RedMoon Lst File");
+        data.push(3);
+        data.extend_from_slice(b"1.2");
+        assert_eq!(list_version(&data), Some("1.2".into()));
+        assert_eq!(list_version(&data[..data.len() - 1]), None);
+        assert_eq!(list_version(b"\x03abc\x031.0"), None);
+    }
+
+    #[test]
+    fn test_parse_df() {
+        let output = "Filesystem     1024-blocks      Used Available Capacity Mounted on\n\
+                      /dev/sda1        102400000  51200000  40960000      56% /\n";
+        assert_eq!(parse_df(output), Some(40960000 * 1024));
+        assert_eq!(parse_df(""), None);
+    }
+}
@@ -0,0 +1,337 @@
+//! `data_converter dupes <list> [--distance <bits>] [--json] [-o <out>]`
+//!
+//! Finds the animations several character RMD files share, so a modder
+//! knows which sprite sets are edited once for all of the characters and
+//! monsters using them. Every frame of every animation is composed the way
+//! `preview` composes it, from the sprites of `<list>`, and fingerprinted
+//! twice:
+//!
+//! - `exact`: a hash of the pixels; animations whose frames all hash the
+//!   same are `identical`,
+//! - `shape`: a 64 bit difference hash of the frame shrunk to 9x8, which
+//!   survives recolors and small touch-ups; animations of as many frames
+//!   whose frames are each at most `--distance` bits apart (6 by default)
+//!   are `similar`.
+//!
+//! The frames are fingerprinted on their own canvas, so an animation drawn
+//! at another place of the entries still matches. Animations without frames
+//! are left out.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::File;
+use std::fs::read_dir;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::Path;
+
+use core_compat::entity::resource_file::ResourceFile;
+use core_compat::entity::rmd_type::RmdType;
+
+use crate::error::Error;
+use crate::ora::{bounds, composite, entry_layers, load_entry_files, Layer};
+use super::{RLE_ENTRIES, RMD_ENTRIES};
+use super::{json_string, load_list_data, load_rmd_data};
+
+static USAGE: &str = "usage: dupes <list> [--distance <bits>] [--json] [-o <out>]";
+
+const DEFAULT_DISTANCE: u32 = 6;
+
+/// The fingerprints of a composed frame
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Frame {
+    exact: u64,
+    shape: u64,
+}
+
+/// An animation of a character RMD file
+#[derive(Debug, PartialEq)]
+struct Animation {
+    rmd: u32,
+    index: usize,
+    frames: Vec<Frame>,
+}
+
+impl Animation {
+    fn describe(&self) -> String {
+        format!("rmd {} animation {}", self.rmd, self.index)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Kind {
+    Identical,
+    Similar,
+}
+
+/// Animations sharing their frames, as indexes into the animations
+#[derive(Debug, PartialEq)]
+struct Group {
+    kind: Kind,
+    members: Vec<usize>,
+}
+
+pub fn dupes(args: &[String]) -> Result<(), Error> {
+    let mut positional = Vec::new();
+    let (mut output, mut distance, mut json) = (None, DEFAULT_DISTANCE, false);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" => output = Some(iter.next().ok_or_else(|| Error::Args(USAGE.into()))?),
+            "--distance" => distance = iter.next().and_then(|n| n.parse().ok()).ok_or_else(|| Error::Args(USAGE.into()))?,
+            "--json" => json = true,
+            _ => positional.push(arg),
+        }
+    }
+    let short = match positional.as_slice() {
+        &[short] => short,
+        _ => return Err(Error::Args(USAGE.into())),
+    };
+
+    let animations = load_animations(short)?;
+    let groups = find_duplicates(&animations, distance);
+    let report = if json { to_json(&animations, &groups) } else { to_csv(&animations, &groups) };
+    match output {
+        Some(output) => {
+            File::create(output)?.write_all(report.as_bytes())?;
+            println!("wrote {} groups of {} animations to {}", groups.len(), animations.len(), output);
+        }
+        None => print!("{}", report),
+    }
+    Ok(())
+}
+
+/// Fingerprints the animations of every character RMD file; the ones with
+/// missing entries or sprites are reported and left out.
+fn load_animations(short: &str) -> Result<Vec<Animation>, Error> {
+    let &(_, _, folder, list_path, use_v2) = RLE_ENTRIES.iter()
+        .find(|e| e.1 == short)
+        .ok_or_else(|| Error::Args(format!("unknown list `{}`", short)))?;
+    let list = load_list_data(Path::new(list_path), use_v2)?;
+    let &(_, chr_short, chr_dir, _) = RMD_ENTRIES.iter().find(|e| e.3 == RmdType::Character).unwrap();
+
+    let mut rmds = Vec::new();
+    for entry in read_dir(chr_dir)? {
+        let path = entry?.path();
+        let number = match path.file_stem().and_then(|s| s.to_str()) {
+            Some(stem) if stem.starts_with(chr_short) => stem[chr_short.len()..].parse::<u32>().ok(),
+            _ => None,
+        };
+        if let Some(number) = number {
+            match load_rmd_data(&path, RmdType::Character) {
+                Ok(rmd) => rmds.push((number, rmd)),
+                Err(e) => eprintln!("{:?}: {:?}", path, e),
+            }
+        }
+    }
+    rmds.sort_by_key(|&(number, _)| number);
+
+    let mut files: HashMap<u32, ResourceFile> = HashMap::new();
+    let mut animations = Vec::new();
+    for &(number, ref rmd) in rmds.iter() {
+        for (index, animation) in rmd.animations().iter().enumerate() {
+            let mut problems = Vec::new();
+            let entries: Vec<_> = animation.frames().iter()
+                .filter_map(|&frame| {
+                    let entry = rmd.get_entry(frame as usize);
+                    if entry.is_none() {
+                        problems.push(format!("no entry {}", frame));
+                    }
+                    entry
+                })
+                .collect();
+            for entry in entries.iter() {
+                load_entry_files(entry, &list, short, folder, &mut files, &mut problems)?;
+            }
+            let layers: Vec<_> = entries.iter()
+                .map(|entry| entry_layers(entry, &list, short, &files, 0, &mut problems))
+                .collect();
+            if !problems.is_empty() {
+                eprintln!("skipping rmd {} animation {}: {}", number, index, problems.join(", "));
+            } else if !layers.is_empty() {
+                animations.push(Animation { rmd: number, index, frames: layers.iter().map(|l| fingerprint(l)).collect() });
+            }
+        }
+    }
+    Ok(animations)
+}
+
+/// Composes the layers of a frame on their bounding box and fingerprints it
+fn fingerprint(layers: &[Layer]) -> Frame {
+    let visible: Vec<Layer> = layers.iter().filter(|l| l.visible).cloned().collect();
+    let (left, top, width, height) = bounds(&visible);
+    let rgba = composite(&visible, left, top, width, height);
+
+    let mut hasher = DefaultHasher::new();
+    (width, height).hash(&mut hasher);
+    rgba.hash(&mut hasher);
+    Frame { exact: hasher.finish(), shape: difference_hash(width, height, &rgba) }
+}
+
+/// Shrinks the image to 9x8 grays, transparent pixels black, and sets a bit
+/// for every gray brighter than its right neighbour
+fn difference_hash(width: u32, height: u32, rgba: &[u8]) -> u64 {
+    let gray = |x: u32, y: u32| {
+        // the pixel at the center of the cell
+        let (px, py) = ((2 * x + 1) * width / 18, (2 * y + 1) * height / 16);
+        let at = ((py * width + px) * 4) as usize;
+        match rgba.get(at..at + 4) {
+            Some(px) => (px[0] as u32 * 299 + px[1] as u32 * 587 + px[2] as u32 * 114) * px[3] as u32,
+            None => 0,
+        }
+    };
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash = hash << 1 | (gray(x, y) > gray(x + 1, y)) as u64;
+        }
+    }
+    hash
+}
+
+/// Groups the animations with the same frames, then the groups with frames
+/// close enough; groups are ordered by their first member, as are their
+/// members.
+fn find_duplicates(animations: &[Animation], distance: u32) -> Vec<Group> {
+    let mut identical: Vec<Vec<usize>> = Vec::new();
+    let mut by_frames: HashMap<Vec<u64>, usize> = HashMap::new();
+    for (idx, animation) in animations.iter().enumerate() {
+        let exact: Vec<u64> = animation.frames.iter().map(|f| f.exact).collect();
+        let group = *by_frames.entry(exact).or_insert_with(|| {
+            identical.push(Vec::new());
+            identical.len() - 1
+        });
+        identical[group].push(idx);
+    }
+
+    // the similar groups join identical groups, through their first member
+    let close = |a: &Animation, b: &Animation| a.frames.len() == b.frames.len()
+        && a.frames.iter().zip(b.frames.iter()).all(|(a, b)| (a.shape ^ b.shape).count_ones() <= distance);
+    let mut joined: Vec<usize> = (0..identical.len()).collect();
+    for a in 0..identical.len() {
+        for b in a + 1..identical.len() {
+            if joined[b] == b && close(&animations[identical[a][0]], &animations[identical[b][0]]) {
+                joined[b] = joined[a];
+            }
+        }
+    }
+
+    let mut groups = Vec::new();
+    for root in 0..identical.len() {
+        if joined[root] != root {
+            continue;
+        }
+        let parts: Vec<usize> = (0..identical.len()).filter(|&g| joined[g] == root).collect();
+        if parts.len() > 1 {
+            let mut members: Vec<usize> = parts.iter().flat_map(|&g| identical[g].iter().cloned()).collect();
+            members.sort();
+            groups.push(Group { kind: Kind::Similar, members });
+        } else if identical[root].len() > 1 {
+            groups.push(Group { kind: Kind::Identical, members: identical[root].clone() });
+        }
+    }
+    groups
+}
+
+fn kind_name(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Identical => "identical",
+        Kind::Similar => "similar",
+    }
+}
+
+fn to_csv(animations: &[Animation], groups: &[Group]) -> String {
+    let mut csv = String::from("group,kind,frames,animation\n");
+    for (idx, group) in groups.iter().enumerate() {
+        for &member in group.members.iter() {
+            let animation = &animations[member];
+            csv.push_str(&format!("{},{},{},{}\n", idx, kind_name(group.kind), animation.frames.len(), animation.describe()));
+        }
+    }
+    csv
+}
+
+fn to_json(animations: &[Animation], groups: &[Group]) -> String {
+    let mut json = String::from("[\n");
+    for (idx, group) in groups.iter().enumerate() {
+        let members: Vec<String> = group.members.iter().map(|&m| json_string(&animations[m].describe())).collect();
+        json.push_str(&format!("  {{\"kind\": \"{}\", \"frames\": {}, \"animations\": [{}]}}",
+                               kind_name(group.kind), animations[group.members[0]].frames.len(), members.join(", ")));
+        json.push_str(if idx + 1 < groups.len() { ",\n" } else { "\n" });
+    }
+    json.push_str("]\n");
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 9x8 frame of a bright square on a dark one, the square at `shift`
+    fn frame(shift: u32, bright: u8) -> Vec<u8> {
+        let mut rgba = Vec::new();
+        for y in 0..8 {
+            for x in 0..9 {
+                let lit = x >= shift && x < shift + 4 && y < 4;
+                rgba.extend_from_slice(&if lit { [bright, bright, bright, 255] } else { [20, 20, 20, 255] });
+            }
+        }
+        rgba
+    }
+
+    fn layer(rgba: &[u8], x: i32) -> Layer<'_> {
+        Layer { name: String::new(), x, y: 0, width: 9, height: 8, rgba, visible: true }
+    }
+
+    fn animation(rmd: u32, frames: &[&[u8]], x: i32) -> Animation {
+        let frames = frames.iter().map(|rgba| fingerprint(&[layer(rgba, x)])).collect();
+        Animation { rmd, index: 0, frames }
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let (a, b) = (frame(0, 250), frame(3, 250));
+        assert_eq!(fingerprint(&[layer(&a, 0)]), fingerprint(&[layer(&a, 12)]));
+        assert_ne!(fingerprint(&[layer(&a, 0)]).exact, fingerprint(&[layer(&b, 0)]).exact);
+        // hidden layers don't count
+        let hidden = Layer { visible: false, ..layer(&b, 0) };
+        assert_eq!(fingerprint(&[layer(&a, 0), hidden]), fingerprint(&[layer(&a, 0)]));
+        // a slightly different color doesn't change the shape
+        let (dim, bright) = (fingerprint(&[layer(&frame(0, 240), 0)]), fingerprint(&[layer(&a, 0)]));
+        assert_ne!(dim.exact, bright.exact);
+        assert_eq!(dim.shape, bright.shape);
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let (a, b, c) = (frame(0, 250), frame(3, 250), frame(0, 240));
+        let animations = vec![
+            animation(1, &[&a, &b], 0),
+            animation(2, &[&b, &a], 0),
+            animation(3, &[&a, &b], 5),
+            animation(4, &[&b, &a], 0),
+            animation(5, &[&c, &b], 0),
+            animation(6, &[&a], 0),
+        ];
+        let groups = find_duplicates(&animations, DEFAULT_DISTANCE);
+        assert_eq!(groups, vec![
+            Group { kind: Kind::Similar, members: vec![0, 2, 4] },
+            Group { kind: Kind::Identical, members: vec![1, 3] },
+        ]);
+        // a recolor keeps the shape, so it is similar even without any distance
+        let groups = find_duplicates(&animations, 0);
+        assert_eq!(groups.iter().map(|g| g.kind).collect::<Vec<_>>(), vec![Kind::Similar, Kind::Identical]);
+    }
+
+    #[test]
+    fn test_report_formats() {
+        let (a, b) = (frame(0, 250), frame(3, 250));
+        let animations = vec![animation(1, &[&a, &b], 0), animation(7, &[&a, &b], 0)];
+        let groups = find_duplicates(&animations, DEFAULT_DISTANCE);
+        assert_eq!(to_csv(&animations, &groups), "group,kind,frames,animation\n\
+                                                  0,identical,2,rmd 1 animation 0\n\
+                                                  0,identical,2,rmd 7 animation 0\n");
+        assert_eq!(to_json(&animations, &groups), "[\n  \
+            {\"kind\": \"identical\", \"frames\": 2, \"animations\": [\"rmd 1 animation 0\", \"rmd 7 animation 0\"]}\n]\n");
+    }
+}
@@ -0,0 +1,43 @@
+use std::io;
+
+use core_compat;
+use core_rules;
+#[cfg(feature = "sqlite")]
+use sql;
+
+#[derive(Debug)]
+pub enum Error {
+    Args(String),
+    Io(io::Error),
+    Rm(core_compat::error::Error),
+    Rules(core_rules::error::Error),
+    #[cfg(feature = "sqlite")]
+    Sqlite(sql::Error),
+    Template(String),
+    Validation(Vec<String>),
+}
+
+impl From<core_compat::error::Error> for Error {
+    fn from(err: core_compat::error::Error) -> Error {
+        Error::Rm(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<core_rules::error::Error> for Error {
+    fn from(err: core_rules::error::Error) -> Error {
+        Error::Rules(err)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<sql::Error> for Error {
+    fn from(err: sql::Error) -> Error {
+        Error::Sqlite(err)
+    }
+}
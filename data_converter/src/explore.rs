@@ -0,0 +1,259 @@
+//! `data_converter explore <tables> <tiles folder> [--addr <host:port>]`
+//!
+//! Serves a world browser over HTTP: a Leaflet page panning and zooming
+//! through the maps rendered by `deepzoom` into `<tiles folder>`, with the
+//! warps and monster spawns of the rule tables in `<tables>` (see
+//! `core_rules::table`) as markers on them. A warp marker leads to the map
+//! it warps to. The address is 127.0.0.1:8080 unless `--addr` says
+//! otherwise.
+//!
+//! - `/` is the page,
+//! - `/maps.json` the maps found in the folder, with their size in pixels
+//!   and tiles, their last level and their markers,
+//! - `/tiles/..` the files of the folder.
+//!
+//! Leaflet comes from its CDN; everything else is served from here.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use core_rules::table::Tables;
+
+use crate::deepzoom::last_level;
+use crate::error::Error;
+use super::json_string;
+
+static USAGE: &str = "usage: explore <tables> <tiles folder> [--addr <host:port>]";
+
+static PAGE: &str = include_str!("../templates/explore.html");
+
+const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+
+/// The longest a connection may take to send its request or read the answer
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Size of a map tile, in pixels, as `deepzoom` draws them
+const MAP_TILE_WIDTH: u32 = 48;
+const MAP_TILE_HEIGHT: u32 = 24;
+
+pub fn explore(args: &[String]) -> Result<(), Error> {
+    let mut positional = Vec::new();
+    let mut addr = DEFAULT_ADDR;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--addr" => addr = iter.next().map(String::as_str).ok_or_else(|| Error::Args(USAGE.into()))?,
+            _ => positional.push(arg),
+        }
+    }
+    let (tables, tiles) = match positional.as_slice() {
+        &[tables, tiles] => (Tables::load(Path::new(tables))?, PathBuf::from(tiles)),
+        _ => return Err(Error::Args(USAGE.into())),
+    };
+
+    let explorer = Arc::new(Explorer { tables, tiles });
+    let listener = TcpListener::bind(addr)?;
+    println!("exploring {} maps on http://{}/", explorer.maps()?.len(), listener.local_addr()?);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        let explorer = explorer.clone();
+        thread::spawn(move || explorer.answer(stream));
+    }
+    Ok(())
+}
+
+pub struct Explorer {
+    pub tables: Tables,
+    /// Where `deepzoom` wrote the maps
+    pub tiles: PathBuf,
+}
+
+/// What a request gets: the status line, the content type and the body
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    fn ok(content_type: &'static str, body: Vec<u8>) -> Response {
+        Response { status: "200 OK", content_type, body }
+    }
+
+    fn not_found() -> Response {
+        Response { status: "404 Not Found", content_type: "text/plain", body: b"not found".to_vec() }
+    }
+}
+
+impl Explorer {
+    /// Reads the request head and answers it; only GET is understood
+    fn answer(&self, mut stream: TcpStream) {
+        let _ = stream.set_read_timeout(Some(TIMEOUT));
+        let _ = stream.set_write_timeout(Some(TIMEOUT));
+        let mut request = Vec::new();
+        let mut buffer = [0u8; 1024];
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < 8192 {
+            match stream.read(&mut buffer) {
+                Ok(count) if count > 0 => request.extend_from_slice(&buffer[..count]),
+                _ => break,
+            }
+        }
+        let head = String::from_utf8_lossy(&request);
+        let mut words = head.split_whitespace();
+        let response = match (words.next(), words.next()) {
+            (Some("GET"), Some(path)) => self.get(path),
+            _ => Response { status: "405 Method Not Allowed", content_type: "text/plain", body: Vec::new() },
+        };
+        let head = format!("HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                           response.status, response.content_type, response.body.len());
+        let _ = stream.write_all(head.as_bytes()).and_then(|_| stream.write_all(&response.body));
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+
+    /// The response to a GET of `path`; the query is ignored
+    pub fn get(&self, path: &str) -> Response {
+        let path = path.split('?').next().unwrap_or("");
+        match path {
+            "/" | "/index.html" => Response::ok("text/html; charset=utf-8", PAGE.as_bytes().to_vec()),
+            "/maps.json" => match self.maps() {
+                Ok(maps) => Response::ok("application/json", self.maps_json(&maps).into_bytes()),
+                Err(_) => Response::not_found(),
+            },
+            _ if path.starts_with("/tiles/") => self.tile(&path["/tiles/".len()..]),
+            _ => Response::not_found(),
+        }
+    }
+
+    /// A file of the tiles folder; paths leaving it aren't found
+    fn tile(&self, path: &str) -> Response {
+        let relative = Path::new(path);
+        if path.is_empty() || relative.components().any(|component| !matches!(component, Component::Normal(_))) {
+            return Response::not_found();
+        }
+        let content_type = match relative.extension().and_then(|ext| ext.to_str()) {
+            Some("png") => "image/png",
+            Some("dzi") => "application/xml",
+            _ => return Response::not_found(),
+        };
+        match fs::read(self.tiles.join(relative)) {
+            Ok(body) => Response::ok(content_type, body),
+            Err(_) => Response::not_found(),
+        }
+    }
+
+    /// The maps with a `.dzi` in the tiles folder, by number, with their size
+    /// in pixels
+    fn maps(&self) -> Result<BTreeMap<u32, (u32, u32)>, Error> {
+        let mut maps = BTreeMap::new();
+        for entry in fs::read_dir(&self.tiles)? {
+            let path = entry?.path();
+            let number = path.file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("Map"))
+                .and_then(|name| name.strip_suffix(".dzi"))
+                .and_then(|number| number.parse::<u32>().ok());
+            let number = match number {
+                Some(number) => number,
+                None => continue,
+            };
+            let dzi = fs::read_to_string(&path)?;
+            match (attribute(&dzi, "Width"), attribute(&dzi, "Height")) {
+                (Some(width), Some(height)) => { maps.insert(number, (width, height)); },
+                _ => println!("skipping {:?}: no size", path),
+            }
+        }
+        Ok(maps)
+    }
+
+    fn maps_json(&self, maps: &BTreeMap<u32, (u32, u32)>) -> String {
+        let monster_name = |id: u32| self.tables.monsters.get(&id).map_or_else(|| format!("Monster {}", id), |m| m.name.clone());
+        let maps: Vec<String> = maps.iter()
+            .map(|(&number, &(width, height))| {
+                let warps: Vec<String> = self.tables.warps.iter()
+                    .filter(|warp| warp.map == number)
+                    .map(|warp| format!("{{\"x\": {}, \"y\": {}, \"to_map\": {}, \"to_x\": {}, \"to_y\": {}}}",
+                                        warp.x, warp.y, warp.to_map, warp.to_x, warp.to_y))
+                    .collect();
+                let spawns: Vec<String> = self.tables.spawns.iter()
+                    .filter(|spawn| spawn.map == number)
+                    .map(|spawn| format!("{{\"left\": {}, \"top\": {}, \"right\": {}, \"bottom\": {}, \
+                                          \"monster\": {}, \"name\": {}, \"count\": {}}}",
+                                         spawn.left, spawn.top, spawn.right, spawn.bottom,
+                                         spawn.monster, json_string(&monster_name(spawn.monster)), spawn.count))
+                    .collect();
+                format!("{{\"map\": {}, \"name\": \"Map{:05}\", \"width\": {}, \"height\": {}, \"last_level\": {}, \
+                         \"warps\": [{}], \"spawns\": [{}]}}",
+                        number, number, width, height, last_level(width, height), warps.join(", "), spawns.join(", "))
+            })
+            .collect();
+        format!("{{\"tile_width\": {}, \"tile_height\": {}, \"maps\": [{}]}}",
+                MAP_TILE_WIDTH, MAP_TILE_HEIGHT, maps.join(", "))
+    }
+}
+
+/// The number in `name="..."` of the XML
+fn attribute(xml: &str, name: &str) -> Option<u32> {
+    let start = xml.find(&format!("{}=\"", name))? + name.len() + 2;
+    let end = start + xml[start..].find('"')?;
+    xml[start..end].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+
+    use core_rules::table::{SpawnEntry, WarpEntry};
+
+    fn explorer(folder: &Path) -> Explorer {
+        let mut tables = Tables::default();
+        tables.warps.push(WarpEntry { map: 3, x: 10, y: 4, to_map: 7, to_x: 1, to_y: 2 });
+        tables.warps.push(WarpEntry { map: 7, x: 1, y: 2, to_map: 3, to_x: 10, to_y: 4 });
+        tables.spawns.push(SpawnEntry {
+            map: 3, left: 0, top: 0, right: 5, bottom: 5, monster: 9, count: 4, respawn: 30, jitter: 0,
+        });
+        fs::create_dir_all(folder.join("Map00003_files").join("0")).unwrap();
+        fs::write(folder.join("Map00003.dzi"), "<Image TileSize=\"256\"><Size Width=\"480\" Height=\"240\"/></Image>").unwrap();
+        fs::write(folder.join("Map00003_files").join("0").join("0_0.png"), b"png").unwrap();
+        fs::write(folder.join("notes.txt"), b"not a map").unwrap();
+        Explorer { tables, tiles: folder.to_path_buf() }
+    }
+
+    #[test]
+    fn test_explore_routes() {
+        let folder = env::temp_dir().join(format!("explore-{}", std::process::id()));
+        let explorer = explorer(&folder);
+
+        let page = explorer.get("/");
+        assert_eq!(page.status, "200 OK");
+        assert!(String::from_utf8(page.body).unwrap().contains("/maps.json"));
+
+        let json = String::from_utf8(explorer.get("/maps.json?fresh").body).unwrap();
+        assert_eq!(json, "{\"tile_width\": 48, \"tile_height\": 24, \"maps\": [{\"map\": 3, \"name\": \"Map00003\", \
+                          \"width\": 480, \"height\": 240, \"last_level\": 9, \
+                          \"warps\": [{\"x\": 10, \"y\": 4, \"to_map\": 7, \"to_x\": 1, \"to_y\": 2}], \
+                          \"spawns\": [{\"left\": 0, \"top\": 0, \"right\": 5, \"bottom\": 5, \
+                          \"monster\": 9, \"name\": \"Monster 9\", \"count\": 4}]}]}");
+
+        assert_eq!(explorer.get("/tiles/Map00003_files/0/0_0.png"), Response::ok("image/png", b"png".to_vec()));
+        assert_eq!(explorer.get("/tiles/Map00003.dzi").content_type, "application/xml");
+        // nothing outside the folder, nor what isn't a tile
+        assert_eq!(explorer.get("/tiles/../explore.png"), Response::not_found());
+        assert_eq!(explorer.get("/tiles//etc/passwd.png"), Response::not_found());
+        assert_eq!(explorer.get("/tiles/notes.txt"), Response::not_found());
+        assert_eq!(explorer.get("/tiles/Map00004.dzi"), Response::not_found());
+        assert_eq!(explorer.get("/elsewhere"), Response::not_found());
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,481 @@
+//! `data_converter export <profile> [<list>..] [-o <out>] [--profiles <file>]`
+//!
+//! Exports the sprites of the lists, all of them unless some are given by
+//! short name, the way an export profile says. A profile bundles the format,
+//! scale, trim, padding and naming of the files, so an asset set can be made
+//! again with one command and comes out the same: the sprites go in list and
+//! id order, and nothing depends on the time or the machine.
+//!
+//! The profiles are `[[profile]]` tables of a TOML file (see the `toml`
+//! module), `--profiles`, or else the built-in ones:
+//!
+//! ```toml
+//! [[profile]]
+//! name = "web-thumbs"
+//! format = "png"    # a PNG per sprite, or "atlas" for atlas pages
+//! scale = 0.5       # nearest pixel up, averaged down; 1 by default
+//! trim = true       # crop the transparent borders, false by default
+//! padding = 0       # transparent pixels around every sprite
+//! naming = "{list}/{id}.png"
+//! ```
+//!
+//! The PNG files are named after `{list}`, `{id}`, `{name}` (the list name,
+//! without the characters file names can't have), `{file}` and `{index}`,
+//! the RLE file and index of the sprite. An atlas, packed by
+//! `core_compat::utility::atlas`, also takes `extrude` and `max_size` and
+//! names its pages after `{list}` and `{page}`. Either way `<list>.json`
+//! says where every sprite went, with its offsets updated for the trim,
+//! scale and padding, and its UV rect in an atlas.
+//!
+//! Files go to `<out>`, `<OUTPUT_PATH>/export/<profile>` by default.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use core_compat::entity::list::List;
+use core_compat::entity::resource_file::ResourceFile;
+use core_compat::utility::atlas::{pack, AtlasConfig};
+
+use crate::error::Error;
+use crate::toml::{parse_tables, Value};
+use super::{OUTPUT_PATH, RLE_ENTRIES};
+use super::{encode_png, json_string, load_list_data, load_rle_folder};
+
+static USAGE: &str = "usage: export <profile> [<list>..] [-o <out>] [--profiles <file>]";
+
+static BUILT_IN_PROFILES: &str = r#"
+[[profile]]
+name = "web-thumbs"
+scale = 0.5
+trim = true
+naming = "{list}/{id}.png"
+
+[[profile]]
+name = "hd-2x"
+scale = 2
+padding = 2
+naming = "{list}/{id}@2x.png"
+
+[[profile]]
+name = "engine-atlas"
+format = "atlas"
+trim = true
+padding = 1
+extrude = 1
+naming = "{list}_{page}.png"
+"#;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    Atlas,
+    Png,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub format: Format,
+    pub scale: f64,
+    pub trim: bool,
+    pub padding: u32,
+    /// Atlas only
+    pub extrude: u32,
+    pub max_size: u32,
+    pub naming: String,
+}
+
+/// A sprite as it's being exported: RGBA pixels and the offset the game
+/// draws them at
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub offset_x: i32,
+    pub offset_y: i32,
+    pub rgba: Vec<u8>,
+}
+
+pub fn export(args: &[String]) -> Result<(), Error> {
+    let (mut name, mut lists, mut output, mut profiles_path) = (None, Vec::new(), None, None);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" => output = Some(iter.next().ok_or_else(|| Error::Args(USAGE.into()))?),
+            "--profiles" => profiles_path = Some(iter.next().ok_or_else(|| Error::Args(USAGE.into()))?),
+            _ if arg.starts_with('-') => return Err(Error::Args(USAGE.into())),
+            _ if name.is_none() => name = Some(arg.as_str()),
+            _ => lists.push(arg.as_str()),
+        }
+    }
+    let name = name.ok_or_else(|| Error::Args(USAGE.into()))?;
+    for short in lists.iter() {
+        if !RLE_ENTRIES.iter().any(|e| e.1 == *short) {
+            return Err(Error::Args(format!("unknown list `{}`\n{}", short, USAGE)));
+        }
+    }
+    let profiles = match profiles_path {
+        Some(path) => parse_profiles(&fs::read_to_string(path)?)
+            .map_err(|problems| Error::Validation(problems.iter().map(|p| format!("{}:{}", path, p)).collect()))?,
+        None => parse_profiles(BUILT_IN_PROFILES).map_err(Error::Validation)?,
+    };
+    let profile = profiles.iter().find(|profile| profile.name == name).ok_or_else(|| {
+        let names: Vec<&str> = profiles.iter().map(|profile| profile.name.as_str()).collect();
+        Error::Args(format!("no profile `{}`; there are {}", name, names.join(", ")))
+    })?;
+    let output = match output {
+        Some(output) => PathBuf::from(output),
+        None => Path::new(OUTPUT_PATH).join("export").join(&profile.name),
+    };
+
+    let mut written = 0;
+    for &(_, short, folder, list_path, use_v2) in RLE_ENTRIES.iter() {
+        if !lists.is_empty() && !lists.contains(&short) {
+            continue;
+        }
+        let list = load_list_data(Path::new(list_path), use_v2)?;
+        let files = load_rle_folder(folder)?;
+        for (path, data) in export_list(profile, short, &list, &files)? {
+            let path = output.join(path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, data)?;
+            written += 1;
+        }
+    }
+    println!("wrote {} files to {:?}", written, output);
+    Ok(())
+}
+
+/// The files of a list exported with `profile`, by path
+pub fn export_list(profile: &Profile, short: &str, list: &List, files: &HashMap<u32, ResourceFile>)
+                   -> Result<BTreeMap<String, Vec<u8>>, Error> {
+    let mut items: Vec<_> = list.items.iter().collect();
+    items.sort_by_key(|item| item.id);
+    let mut sprites = Vec::new();
+    for item in items {
+        let (file, index) = (item.entry.file(), item.entry.index());
+        let resource = match files.get(&file).and_then(|f| f.get(index)) {
+            Some(resource) => resource,
+            None => continue,
+        };
+        let mut image = Image {
+            width: resource.width as u32,
+            height: resource.height as u32,
+            offset_x: resource.offset_x,
+            offset_y: resource.offset_y,
+            rgba: resource.image_raw.clone(),
+        };
+        if profile.trim {
+            image = trim(&image);
+        }
+        if profile.scale != 1.0 {
+            image = scale(&image, profile.scale);
+        }
+        sprites.push((item, image));
+    }
+
+    let mut out = BTreeMap::new();
+    let mut entries = Vec::new();
+    match profile.format {
+        Format::Png => {
+            for (item, image) in sprites.iter() {
+                let image = pad(image, profile.padding);
+                let path = profile.naming
+                    .replace("{list}", short)
+                    .replace("{id}", &item.id.to_string())
+                    .replace("{name}", &file_name(&item.name))
+                    .replace("{file}", &item.entry.file().to_string())
+                    .replace("{index}", &item.entry.index().to_string());
+                entries.push(format!("{{\"id\":{},\"name\":{},\"file\":{},\"x_offset\":{},\"y_offset\":{},\"width\":{},\"height\":{}}}",
+                                     item.id, json_string(&item.name), json_string(&path),
+                                     image.offset_x, image.offset_y, image.width, image.height));
+                out.insert(path, encode_png(image.width, image.height, &image.rgba)?);
+            }
+        }
+        Format::Atlas => {
+            let config = AtlasConfig { max_size: profile.max_size, padding: profile.padding, extrude: profile.extrude };
+            let packed: Vec<(u32, u32, &[u8])> = sprites.iter()
+                .map(|(_, image)| (image.width, image.height, image.rgba.as_slice()))
+                .collect();
+            let atlas = pack(&packed, &config);
+            let page_name = |page: usize| profile.naming.replace("{list}", short).replace("{page}", &page.to_string());
+            for ((item, image), placement) in sprites.iter().zip(atlas.placements.iter()) {
+                match *placement {
+                    Some(placement) => {
+                        let uv: Vec<String> = placement.uv.iter().map(|uv| uv.to_string()).collect();
+                        entries.push(format!("{{\"id\":{},\"name\":{},\"file\":{},\"x\":{},\"y\":{},\"x_offset\":{},\"y_offset\":{},\
+                                              \"width\":{},\"height\":{},\"uv\":[{}]}}",
+                                             item.id, json_string(&item.name), json_string(&page_name(placement.page)),
+                                             placement.x, placement.y, image.offset_x, image.offset_y,
+                                             placement.width, placement.height, uv.join(",")));
+                    }
+                    None => println!("{} {}: {}x{} doesn't fit an atlas page", short, item.id, image.width, image.height),
+                }
+            }
+            for (number, page) in atlas.pages.iter().enumerate() {
+                out.insert(page_name(number), encode_png(page.width, page.height, &page.image_raw)?);
+            }
+        }
+    }
+    let manifest = format!("{{\"profile\":{},\"sprites\":[\n{}\n]}}\n", json_string(&profile.name), entries.join(",\n"));
+    out.insert(format!("{}.json", short), manifest.into_bytes());
+    Ok(out)
+}
+
+/// The image cropped to its painted pixels; a single transparent pixel if
+/// it has none
+pub fn trim(image: &Image) -> Image {
+    let painted = |x: u32, y: u32| image.rgba[((y * image.width + x) * 4 + 3) as usize] != 0;
+    let (mut left, mut top, mut right, mut bottom) = (image.width, image.height, 0, 0);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            if painted(x, y) {
+                left = left.min(x);
+                top = top.min(y);
+                right = right.max(x + 1);
+                bottom = bottom.max(y + 1);
+            }
+        }
+    }
+    if left >= right {
+        return Image { width: 1, height: 1, rgba: vec![0; 4], ..image.clone() };
+    }
+    let mut rgba = Vec::with_capacity(((right - left) * (bottom - top) * 4) as usize);
+    for y in top..bottom {
+        let start = ((y * image.width + left) * 4) as usize;
+        rgba.extend_from_slice(&image.rgba[start..start + ((right - left) * 4) as usize]);
+    }
+    Image {
+        width: right - left,
+        height: bottom - top,
+        offset_x: image.offset_x + left as i32,
+        offset_y: image.offset_y + top as i32,
+        rgba,
+    }
+}
+
+/// The image scaled by `factor`: the nearest pixel when growing, the
+/// average of the pixels covered, weighted by their alpha, when shrinking
+pub fn scale(image: &Image, factor: f64) -> Image {
+    let size = |length: u32| ((length as f64 * factor).round() as u32).max(1);
+    let (width, height) = (size(image.width), size(image.height));
+    // the source pixels a target pixel covers, at least one
+    let span = |target: u32, length: u32| {
+        let start = ((target as f64 / factor) as u32).min(length - 1);
+        let end = (((target + 1) as f64 / factor) as u32).clamp(start + 1, length);
+        start..end
+    };
+    let mut rgba = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let (mut sum, mut alpha, mut count) = ([0u64; 3], 0u64, 0u64);
+            for source_y in span(y, image.height) {
+                for source_x in span(x, image.width) {
+                    let at = ((source_y * image.width + source_x) * 4) as usize;
+                    let pixel = &image.rgba[at..at + 4];
+                    for channel in 0..3 {
+                        sum[channel] += pixel[channel] as u64 * pixel[3] as u64;
+                    }
+                    alpha += pixel[3] as u64;
+                    count += 1;
+                }
+            }
+            for channel in sum.iter() {
+                rgba.push((channel + alpha / 2).checked_div(alpha).unwrap_or(0) as u8);
+            }
+            rgba.push(((alpha + count / 2) / count) as u8);
+        }
+    }
+    let offset = |offset: i32| (offset as f64 * factor).round() as i32;
+    Image { width, height, offset_x: offset(image.offset_x), offset_y: offset(image.offset_y), rgba }
+}
+
+/// The image with `padding` transparent pixels around it
+pub fn pad(image: &Image, padding: u32) -> Image {
+    let width = image.width + 2 * padding;
+    let mut rgba = vec![0; (width * (image.height + 2 * padding) * 4) as usize];
+    for y in 0..image.height {
+        let from = ((y * image.width) * 4) as usize;
+        let to = (((y + padding) * width + padding) * 4) as usize;
+        rgba[to..to + (image.width * 4) as usize].copy_from_slice(&image.rgba[from..from + (image.width * 4) as usize]);
+    }
+    Image {
+        width,
+        height: image.height + 2 * padding,
+        offset_x: image.offset_x - padding as i32,
+        offset_y: image.offset_y - padding as i32,
+        rgba,
+    }
+}
+
+/// A list name as part of a file name
+fn file_name(name: &str) -> String {
+    name.chars().map(|chr| if chr.is_alphanumeric() || chr == '-' { chr } else { '_' }).collect()
+}
+
+/// The profiles of a profile file, or what's wrong with it as
+/// `<line>: <problem>`
+pub fn parse_profiles(text: &str) -> Result<Vec<Profile>, Vec<String>> {
+    let (tables, mut problems) = parse_tables(text, "profile");
+    let mut profiles: Vec<Profile> = Vec::new();
+    for table in tables {
+        match profile(&table.fields) {
+            Ok(profile) if profiles.iter().any(|p| p.name == profile.name) => {
+                problems.push(format!("{}: there's another profile `{}`", table.line, profile.name));
+            }
+            Ok(profile) => profiles.push(profile),
+            Err(problem) => problems.push(format!("{}: {}", table.line, problem)),
+        }
+    }
+    if problems.is_empty() {
+        Ok(profiles)
+    } else {
+        Err(problems)
+    }
+}
+
+fn profile(fields: &BTreeMap<String, Value>) -> Result<Profile, String> {
+    let mut profile = Profile {
+        name: String::new(),
+        format: Format::Png,
+        scale: 1.0,
+        trim: false,
+        padding: 0,
+        extrude: 0,
+        max_size: AtlasConfig::default().max_size,
+        naming: String::new(),
+    };
+    let whole = |value: f64, key: &str| -> Result<u32, String> {
+        if value.fract() == 0.0 && value >= 0.0 && value <= u16::MAX as f64 {
+            Ok(value as u32)
+        } else {
+            Err(format!("`{}` has to be a whole number", key))
+        }
+    };
+    for (key, value) in fields.iter() {
+        match (key.as_str(), value) {
+            ("name", Value::Str(name)) if !name.is_empty() => profile.name = name.clone(),
+            ("format", Value::Str(format)) => profile.format = match format.as_str() {
+                "atlas" => Format::Atlas,
+                "png" => Format::Png,
+                _ => return Err(format!("unknown format `{}`", format)),
+            },
+            ("scale", &Value::Number(scale)) if scale > 0.0 && scale <= 16.0 => profile.scale = scale,
+            ("trim", &Value::Bool(trim)) => profile.trim = trim,
+            ("padding", &Value::Number(padding)) => profile.padding = whole(padding, key)?,
+            ("extrude", &Value::Number(extrude)) => profile.extrude = whole(extrude, key)?,
+            ("max_size", &Value::Number(max_size)) if max_size >= 1.0 => profile.max_size = whole(max_size, key)?,
+            ("naming", Value::Str(naming)) => profile.naming = naming.clone(),
+            ("name", _) | ("format", _) | ("scale", _) | ("trim", _) | ("padding", _) | ("extrude", _)
+            | ("max_size", _) | ("naming", _) => return Err(format!("`{}` has the wrong kind of value", key)),
+            _ => return Err(format!("unknown key `{}`", key)),
+        }
+    }
+    if profile.name.is_empty() {
+        return Err("a profile needs a `name`".into());
+    }
+    if profile.naming.is_empty() {
+        profile.naming = match profile.format {
+            Format::Atlas => "{list}_{page}.png".into(),
+            Format::Png => "{list}_{id}.png".into(),
+        };
+    }
+    if profile.format == Format::Atlas && !profile.naming.contains("{page}") {
+        return Err("the pages of an atlas need `{page}` in their `naming`".into());
+    }
+    if profile.format == Format::Png && !["{id}", "{name}", "{index}"].iter().any(|part| profile.naming.contains(part)) {
+        return Err("the sprites need `{id}`, `{name}` or `{index}` in their `naming`".into());
+    }
+    Ok(profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core_compat::entity::entry::Entry;
+    use core_compat::fixture::{LstFixture, ResourceFixture, RleFixture};
+    use core_compat::parser::lst::parse_lst;
+    use core_compat::parser::rle::parse_rle;
+
+    fn image(width: u32, height: u32, rgba: &[[u8; 4]]) -> Image {
+        Image { width, height, offset_x: 10, offset_y: 20, rgba: rgba.concat() }
+    }
+
+    const RED: [u8; 4] = [0xFF, 0, 0, 0xFF];
+    const BLUE: [u8; 4] = [0, 0, 0xFF, 0xFF];
+    const NONE: [u8; 4] = [0, 0, 0, 0];
+
+    #[test]
+    fn test_built_in_profiles() {
+        let profiles = parse_profiles(BUILT_IN_PROFILES).unwrap();
+        let names: Vec<&str> = profiles.iter().map(|profile| profile.name.as_str()).collect();
+        assert_eq!(names, vec!["web-thumbs", "hd-2x", "engine-atlas"]);
+        assert_eq!((profiles[2].format, profiles[2].extrude, profiles[2].max_size), (Format::Atlas, 1, 2048));
+
+        let problems = parse_profiles("[[profile]]\nname = \"a\"\n[[profile]]\nname = \"a\"\n\
+                                       [[profile]]\nname = \"b\"\nformat = \"atlas\"\nnaming = \"{list}.png\"\n\
+                                       [[profile]]\nname = \"c\"\nscale = 0\n").unwrap_err();
+        assert_eq!(problems, vec![
+            "3: there's another profile `a`",
+            "5: the pages of an atlas need `{page}` in their `naming`",
+            "9: `scale` has the wrong kind of value",
+        ]);
+    }
+
+    #[test]
+    fn test_images() {
+        let trimmed = trim(&image(3, 3, &[NONE, NONE, NONE, NONE, RED, BLUE, NONE, NONE, NONE]));
+        assert_eq!(trimmed, Image { width: 2, height: 1, offset_x: 11, offset_y: 21, rgba: [RED, BLUE].concat() });
+        assert_eq!(trim(&image(1, 1, &[NONE])).width, 1);
+
+        let grown = scale(&trimmed, 2.0);
+        assert_eq!((grown.width, grown.height, grown.offset_x, grown.offset_y), (4, 2, 22, 42));
+        assert_eq!(grown.rgba, [RED, RED, BLUE, BLUE, RED, RED, BLUE, BLUE].concat());
+        // the transparent pixel doesn't darken the red
+        let shrunk = scale(&image(2, 1, &[RED, NONE]), 0.5);
+        assert_eq!((shrunk.width, shrunk.offset_x), (1, 5));
+        assert_eq!(shrunk.rgba, vec![0xFF, 0, 0, 0x80]);
+
+        let padded = pad(&trimmed, 1);
+        assert_eq!((padded.width, padded.height, padded.offset_x, padded.offset_y), (4, 3, 10, 20));
+        assert_eq!(padded.rgba, [NONE, NONE, NONE, NONE, NONE, RED, BLUE, NONE, NONE, NONE, NONE, NONE].concat());
+    }
+
+    #[test]
+    fn test_export_list() {
+        let list = parse_lst(&LstFixture::new("1.0")
+            .item("Red Slime", 2, Entry::new(4, 1))
+            .item("Blue/Slime", 1, Entry::new(4, 0))
+            .item("missing", 3, Entry::new(5, 0))
+            .build(), false).unwrap();
+        let rle = RleFixture::new()
+            .resource(ResourceFixture::new(2, 1).skip(1).pixels(&[0x001F]))
+            .resource(ResourceFixture::new(1, 1).pixels(&[0xF800]))
+            .build();
+        let mut files = HashMap::new();
+        files.insert(4, parse_rle(4, &rle).unwrap());
+
+        let profiles = parse_profiles(BUILT_IN_PROFILES).unwrap();
+        let thumbs = Profile { naming: "{list}/{id}_{name}.png".into(), ..profiles[0].clone() };
+        let out = export_list(&thumbs, "obj", &list, &files).unwrap();
+        let paths: Vec<&str> = out.keys().map(|path| path.as_str()).collect();
+        assert_eq!(paths, vec!["obj.json", "obj/1_Blue_Slime.png", "obj/2_Red_Slime.png"]);
+        let manifest = String::from_utf8(out["obj.json"].clone()).unwrap();
+        assert!(manifest.contains("\"id\":1,\"name\":\"Blue/Slime\",\"file\":\"obj/1_Blue_Slime.png\",\
+                                   \"x_offset\":1,\"y_offset\":0,\"width\":1,\"height\":1"), "{}", manifest);
+        // the same every time
+        assert_eq!(export_list(&thumbs, "obj", &list, &files).unwrap(), out);
+
+        let out = export_list(&profiles[2], "obj", &list, &files).unwrap();
+        let paths: Vec<&str> = out.keys().map(|path| path.as_str()).collect();
+        assert_eq!(paths, vec!["obj.json", "obj_0.png"]);
+        let manifest = String::from_utf8(out["obj.json"].clone()).unwrap();
+        assert!(manifest.contains("\"file\":\"obj_0.png\",\"x\":2,\"y\":2,\"x_offset\":1"), "{}", manifest);
+        assert!(manifest.contains("\"uv\":[0.0009765625,"), "{}", manifest);
+    }
+}
@@ -0,0 +1,360 @@
+//! `data_converter font <font.lst> <font.rle> --chars <set>[,<set>..] [--ttf <font.ttf>] -o <out folder>`
+//!
+//! Adds glyphs rendered from a TrueType font to a bitmap font, so translated
+//! text doesn't show missing glyphs. A bitmap font is a list and an RLE file,
+//! see `doc/formats.md`: every list item is a character, with its code point
+//! as the id and the character as the name, and its entry is the glyph.
+//!
+//! The new glyphs are rendered at the metrics of the ones already there. The
+//! capital letters and digits give the baseline and the cap height the
+//! TrueType font is scaled to, every glyph is a cell as tall as the line, and
+//! if the glyphs are all as wide the new ones are too. They're painted in the
+//! color the font uses most, without anti-aliasing, as the files have no
+//! alpha. The glyphs and list items already there are kept byte for byte and
+//! the new ones go after them; characters the font already has, or the
+//! TrueType font hasn't, are skipped.
+//!
+//! `--chars` takes `latin` (U+00A0 to U+017F), `cyrillic` (U+0400 to
+//! U+045F), `U+XXXX` and `U+XXXX-U+YYYY`. The TrueType font is the bundled
+//! NotoMono unless `--ttf` is given. Both files are written to the output
+//! folder with the names they have.
+
+use std::collections::HashMap;
+use std::fs::{create_dir_all, File};
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+
+use rusttype::{point, Font, FontCollection, Scale};
+
+use core_compat::entity::list::List;
+use core_compat::entity::list_item::ListItem;
+use core_compat::entity::entry::Entry;
+use core_compat::entity::resource::Resource;
+use core_compat::entity::resource_file::ResourceFile;
+use core_compat::parser::lst::parse_lst;
+use core_compat::parser::rle::{parse_rle_as, raw_resources, PixelFormat};
+use core_compat::writer::lst::append_lst_items;
+use core_compat::writer::rle::{encode_resource, write_raw_rle};
+
+use crate::card::FONT;
+use crate::error::Error;
+use super::file_number;
+
+static USAGE: &str = "usage: font <font.lst> <font.rle> --chars <set>[,<set>..] [--ttf <font.ttf>] -o <out folder>
+sets: latin, cyrillic, U+XXXX, U+XXXX-U+YYYY";
+
+/// Where the glyphs of a bitmap font sit, in pixels down from the origin the
+/// text is drawn at
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Metrics {
+    /// The `offset_y` of the topmost glyph
+    top: i32,
+    line_height: i32,
+    baseline: i32,
+    cap_height: i32,
+    /// The width of every glyph, if they're all as wide
+    advance: Option<i32>,
+    color: [u8; 3],
+}
+
+/// The font with the glyphs added, and the characters added and skipped
+struct Extended {
+    list: Vec<u8>,
+    rle: Vec<u8>,
+    added: Vec<char>,
+    /// Not in the TrueType font
+    skipped: Vec<char>,
+}
+
+pub fn font(args: &[String]) -> Result<(), Error> {
+    let (mut paths, mut chars, mut ttf, mut output) = (Vec::new(), None, None, None);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--chars" => chars = iter.next(),
+            "--ttf" => ttf = iter.next(),
+            "-o" => output = iter.next(),
+            _ if paths.len() < 2 && !arg.starts_with("--") => paths.push(Path::new(arg)),
+            _ => return Err(Error::Args(USAGE.into())),
+        }
+    }
+    let (list_path, rle_path, chars, output) = match (paths.as_slice(), chars, output) {
+        (&[list_path, rle_path], Some(chars), Some(output)) => (list_path, rle_path, chars, Path::new(output)),
+        _ => return Err(Error::Args(USAGE.into())),
+    };
+    let chars = parse_chars(chars).ok_or_else(|| Error::Args(USAGE.into()))?;
+
+    let ttf = match ttf {
+        Some(path) => read(Path::new(path))?,
+        None => FONT.to_vec(),
+    };
+    let ttf = FontCollection::from_bytes(ttf)
+        .and_then(|collection| collection.into_font())
+        .map_err(|error| Error::Args(format!("can't read the font: {}", error)))?;
+
+    let extended = extend(&read(list_path)?, &read(rle_path)?, file_number(rle_path), &ttf, &chars)?;
+    create_dir_all(output)?;
+    for &(path, data) in [(list_path, &extended.list), (rle_path, &extended.rle)].iter() {
+        let name = path.file_name().ok_or_else(|| Error::Args(USAGE.into()))?;
+        File::create(output.join(name))?.write_all(data)?;
+    }
+    println!("added {} glyphs to {:?}", extended.added.len(), output);
+    if !extended.skipped.is_empty() {
+        let skipped: Vec<String> = extended.skipped.iter().map(|c| format!("U+{:04X}", *c as u32)).collect();
+        println!("not in the TrueType font: {}", skipped.join(" "));
+    }
+    Ok(())
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// The characters of a `--chars` value, sorted; `None` if a set is neither a
+/// known one nor code points. Control characters are left out.
+fn parse_chars(spec: &str) -> Option<Vec<char>> {
+    let mut chars = Vec::new();
+    for set in spec.split(',') {
+        let (from, to) = match set {
+            "latin" => (0xA0, 0x17F),
+            "cyrillic" => (0x400, 0x45F),
+            _ => match set.find('-') {
+                Some(at) => (code_point(&set[..at])?, code_point(&set[at + 1..])?),
+                None => (code_point(set)?, code_point(set)?),
+            },
+        };
+        chars.extend((from..=to).filter_map(std::char::from_u32).filter(|c| !c.is_control()));
+    }
+    chars.sort();
+    chars.dedup();
+    Some(chars)
+}
+
+fn code_point(text: &str) -> Option<u32> {
+    let digits = text.strip_prefix("U+").or_else(|| text.strip_prefix("u+"))?;
+    u32::from_str_radix(digits, 16).ok()
+}
+
+/// Renders the characters of `chars` the font doesn't have yet and appends
+/// them to its list and RLE file. The glyphs are in the file the list items
+/// point to, `file_number` if there are none.
+fn extend(list_data: &[u8], rle_data: &[u8], file_number: u32, ttf: &Font, chars: &[char]) -> Result<Extended, Error> {
+    let list = parse_lst(list_data, false)?;
+    let file_number = list.items.first().map_or(file_number, |item| item.entry.file());
+    let file = parse_rle_as(file_number, rle_data, PixelFormat::Rgba8)?;
+    let metrics = measure(&list, &file)
+        .ok_or_else(|| Error::Validation(vec!["the font has no painted glyph to measure".into()]))?;
+
+    let mut raw = raw_resources(rle_data)?;
+    let (mut glyphs, mut items, mut added, mut skipped) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    for &c in chars.iter().filter(|&&c| list.get_item(c as usize).is_none()) {
+        let index = (raw.len() + glyphs.len()) as u32;
+        match render(ttf, c, &metrics) {
+            Some(mut glyph) => {
+                glyph.set_index(index);
+                glyphs.push(encode_resource(&glyph, PixelFormat::Rgba8)?);
+                items.push(ListItem { name: c.to_string(), id: c as u32, entry: Entry::new(file_number, index) });
+                added.push(c);
+            }
+            None => skipped.push(c),
+        }
+    }
+    raw.extend(glyphs.iter().map(|glyph| Some(glyph.as_slice())));
+    Ok(Extended {
+        list: append_lst_items(list_data, false, &items)?,
+        rle: write_raw_rle(&raw),
+        added,
+        skipped,
+    })
+}
+
+/// The first and past the last painted line of a glyph, counted from the
+/// origin; `None` if nothing is painted
+fn painted_lines(glyph: &Resource) -> Option<(i32, i32)> {
+    let width = glyph.width.max(0) as usize;
+    let painted = |y: usize| glyph.image_raw[y * width * 4..(y + 1) * width * 4].chunks(4).any(|px| px[3] >= 0x80);
+    let height = glyph.height.max(0) as usize;
+    let first = (0..height).find(|&y| painted(y))?;
+    let last = (0..height).rev().find(|&y| painted(y))?;
+    Some((glyph.offset_y + first as i32, glyph.offset_y + last as i32 + 1))
+}
+
+fn measure(list: &List, file: &ResourceFile) -> Option<Metrics> {
+    let glyphs: Vec<(&ListItem, &Resource)> = list.items.iter()
+        .filter(|item| item.entry.file() == file.file_number)
+        .filter_map(|item| file.get(item.entry.index()).map(|glyph| (item, glyph)))
+        .filter(|&(_, glyph)| glyph.image_raw.len() == (glyph.width.max(0) * glyph.height.max(0) * 4) as usize)
+        .collect();
+    let top = glyphs.iter().map(|&(_, glyph)| glyph.offset_y).min()?;
+    let line_height = glyphs.iter().map(|&(_, glyph)| glyph.offset_y + glyph.height).max()? - top;
+
+    // capitals and digits have no descenders, any painted glyph does if there
+    // are none of them
+    let capital = |name: &str| {
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => c.is_ascii_uppercase() || c.is_ascii_digit(),
+            _ => false,
+        }
+    };
+    let mut lines: Vec<(i32, i32)> = glyphs.iter()
+        .filter(|&&(item, _)| capital(&item.name))
+        .filter_map(|&(_, glyph)| painted_lines(glyph))
+        .collect();
+    if lines.is_empty() {
+        lines = glyphs.iter().filter_map(|&(_, glyph)| painted_lines(glyph)).collect();
+    }
+    let mut tops: Vec<i32> = lines.iter().map(|&(top, _)| top).collect();
+    let mut bottoms: Vec<i32> = lines.iter().map(|&(_, bottom)| bottom).collect();
+    tops.sort();
+    bottoms.sort();
+    let baseline = *bottoms.get(bottoms.len() / 2)?;
+    let cap_height = (baseline - tops[tops.len() / 2]).max(1);
+
+    let advance = glyphs.first().map(|&(_, glyph)| glyph.width)
+        .filter(|&width| glyphs.iter().all(|&(_, glyph)| glyph.width == width));
+
+    let mut colors = HashMap::<[u8; 3], usize>::new();
+    for &(_, glyph) in glyphs.iter() {
+        for px in glyph.image_raw.chunks(4).filter(|px| px[3] >= 0x80) {
+            *colors.entry([px[0], px[1], px[2]]).or_insert(0) += 1;
+        }
+    }
+    let color = colors.into_iter().max_by_key(|&(color, count)| (count, color)).map_or([0xFF; 3], |(color, _)| color);
+
+    Some(Metrics { top, line_height, baseline, cap_height, advance, color })
+}
+
+/// The glyph of `c` as an RGBA resource in a cell of the line; `None` if the
+/// TrueType font hasn't got it
+fn render(ttf: &Font, c: char, metrics: &Metrics) -> Option<Resource> {
+    let glyph = ttf.glyph(c);
+    if glyph.id().0 == 0 {
+        return None;
+    }
+    // the size the TrueType capitals are as tall as the bitmap ones at
+    let unit = Scale::uniform(1.0);
+    let cap_height = ttf.glyph('H').scaled(unit).exact_bounding_box()
+        .map(|bounds| -bounds.min.y)
+        .filter(|&height| height > 0.0)
+        .unwrap_or_else(|| ttf.v_metrics(unit).ascent);
+    let glyph = glyph.scaled(Scale::uniform(metrics.cap_height as f32 / cap_height));
+
+    let glyph_advance = glyph.h_metrics().advance_width;
+    let (width, x) = match metrics.advance {
+        Some(advance) => (advance, (advance as f32 - glyph_advance) / 2.0),
+        None => (glyph_advance.round().max(1.0) as i32, 0.0),
+    };
+    let height = metrics.line_height;
+    let glyph = glyph.positioned(point(x, (metrics.baseline - metrics.top) as f32));
+
+    let mut image_raw = vec![0u8; (width * height * 4) as usize];
+    if let Some(bounds) = glyph.pixel_bounding_box() {
+        glyph.draw(|x, y, coverage| {
+            let x = x as i32 + bounds.min.x;
+            let y = y as i32 + bounds.min.y;
+            if x < 0 || y < 0 || x >= width || y >= height || coverage < 0.5 {
+                return;
+            }
+            let start = ((y * width + x) * 4) as usize;
+            image_raw[start..start + 3].copy_from_slice(&metrics.color);
+            image_raw[start + 3] = 0xFF;
+        });
+    }
+
+    let mut resource = Resource::new();
+    resource.offset_y = metrics.top;
+    resource.width = width;
+    resource.height = height;
+    resource.image_raw = image_raw;
+    Some(resource)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core_compat::fixture::{LstFixture, RleFixture, ResourceFixture};
+
+    const RED: u16 = 0xF800;
+
+    /// A 6x10 glyph with a bar from line 2 to 8
+    fn bar() -> ResourceFixture {
+        (0..6).fold(ResourceFixture::new(6, 10).next_line().next_line().skip(1), |glyph, _| {
+            glyph.pixels(&[RED]).skip(-1).next_line()
+        })
+    }
+
+    /// "H" and "1" as bars in `font00007.rle`
+    fn bitmap_font() -> (Vec<u8>, Vec<u8>) {
+        let list = LstFixture::new("1.0")
+            .item("H", 'H' as u32, Entry::new(7, 0))
+            .item("1", '1' as u32, Entry::new(7, 1))
+            .build();
+        let rle = RleFixture::new().resource(bar()).resource(bar()).build();
+        (list, rle)
+    }
+
+    fn ttf() -> Font<'static> {
+        FontCollection::from_bytes(FONT).unwrap().into_font().unwrap()
+    }
+
+    #[test]
+    fn test_parse_chars() {
+        assert_eq!(parse_chars("U+41-U+43,U+0042,u+e9").unwrap(), vec!['A', 'B', 'C', '\u{e9}']);
+        assert_eq!(parse_chars("latin").unwrap().len(), 0x17F - 0xA0 + 1);
+        assert_eq!(parse_chars("cyrillic").unwrap().first(), Some(&'\u{400}'));
+        // no control characters
+        assert!(parse_chars("U+0-U+1F").unwrap().is_empty());
+        assert!(parse_chars("greek").is_none());
+        assert!(parse_chars("U+41-").is_none());
+    }
+
+    #[test]
+    fn test_measure() {
+        let (list, rle) = bitmap_font();
+        let list = parse_lst(&list, false).unwrap();
+        let file = parse_rle_as(7, &rle, PixelFormat::Rgba8).unwrap();
+        let metrics = measure(&list, &file).unwrap();
+        assert_eq!(metrics.top, 0);
+        assert_eq!(metrics.line_height, 10);
+        assert_eq!(metrics.baseline, 8);
+        assert_eq!(metrics.cap_height, 6);
+        assert_eq!(metrics.advance, Some(6));
+        assert_eq!(&metrics.color[..], &file.resources[0].image_raw[4 * 13..4 * 13 + 3]);
+
+        // glyphs from another file aren't measured
+        let file = parse_rle_as(8, &rle, PixelFormat::Rgba8).unwrap();
+        assert!(measure(&list, &file).is_none());
+    }
+
+    #[test]
+    fn test_extend() {
+        let (list, rle) = bitmap_font();
+        let chars = ['H', '\u{e9}', '\u{428}', '\u{4e00}'];
+        let extended = extend(&list, &rle, 0xFFFF, &ttf(), &chars).unwrap();
+        assert_eq!(extended.added, vec!['\u{e9}', '\u{428}']);
+        assert_eq!(extended.skipped, vec!['\u{4e00}']);
+
+        let extended_list = parse_lst(&extended.list, false).unwrap();
+        assert_eq!(extended_list.items.len(), 4);
+        // the items after the header are kept
+        assert_eq!(&extended.list[29..list.len()], &list[29..]);
+        let sha = extended_list.get_item(0x428).unwrap();
+        assert_eq!(sha.name, "\u{428}");
+        assert_eq!(sha.entry, Entry::new(7, 3));
+
+        let raw = raw_resources(&extended.rle).unwrap();
+        assert_eq!(&raw[..2], &raw_resources(&rle).unwrap()[..]);
+        let file = parse_rle_as(7, &extended.rle, PixelFormat::Rgba8).unwrap();
+        let glyph = file.get(3).unwrap();
+        assert_eq!((glyph.offset_y, glyph.width, glyph.height), (0, 6, 10));
+        // standing on the baseline, as tall as the capitals
+        assert_eq!(painted_lines(glyph), Some((2, 8)));
+        let color = &file.get(0).unwrap().image_raw[4 * 13..4 * 13 + 4];
+        assert!(glyph.image_raw.chunks(4).all(|px| px[3] == 0 || px == color));
+    }
+}
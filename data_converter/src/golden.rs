@@ -0,0 +1,171 @@
+//! Golden image tests: a fixed set of synthetic sprites and maps is rendered
+//! through the decode, convert and render paths and compared with the PNGs
+//! checked in under `goldens/`, so a refactor can't change what comes out
+//! without a golden changing along with it.
+//!
+//! The comparison allows for rounding: two pixels match when their
+//! perceptual distance (`distance`) stays under `TOLERANCE`. A mismatching
+//! image is saved next to the system's temporary files for a look. Running
+//! the tests with `UPDATE_GOLDENS=1` writes the goldens instead, for when
+//! the output is meant to change; the new PNGs are then part of the commit.
+
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::path::PathBuf;
+
+use core_compat::entity::entry::Entry;
+use core_compat::entity::map::Map;
+use core_compat::entity::map_tile::MapTile;
+use core_compat::entity::resource::Resource;
+use core_compat::fixture::{ResourceFixture, RleFixture};
+use core_compat::parser::rle::{parse_rle, parse_rle_as, parse_rle_with, DecodeOptions, PixelFormat};
+
+use crate::export::{scale, Image};
+use crate::minimap::minimap;
+use crate::ora::{bounds, composite, Layer};
+use super::encode_png;
+
+/// Largest distance between two pixels still taken as the same; a channel
+/// or two off by one are
+const TOLERANCE: f64 = 4.0;
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("goldens").join(format!("{}.png", name))
+}
+
+/// Panics unless the image matches the golden `name`, or writes it as the
+/// golden with `UPDATE_GOLDENS` set
+fn assert_golden(name: &str, width: u32, height: u32, rgba: &[u8]) {
+    let path = golden_path(name);
+    if env::var_os("UPDATE_GOLDENS").is_some() {
+        fs::write(&path, encode_png(width, height, rgba).unwrap()).unwrap();
+        return;
+    }
+    let (golden_width, golden_height, golden) = match File::open(&path) {
+        Ok(file) => decode_png(file),
+        Err(_) => panic!("no golden {}, run the tests with UPDATE_GOLDENS=1 to write it", path.display()),
+    };
+
+    let mismatch = if (width, height) != (golden_width, golden_height) {
+        Some(format!("{}x{} instead of {}x{}", width, height, golden_width, golden_height))
+    } else {
+        let different: Vec<usize> = (0..(width * height) as usize)
+            .filter(|&at| distance(&rgba[at * 4..at * 4 + 4], &golden[at * 4..at * 4 + 4]) > TOLERANCE)
+            .collect();
+        different.first().map(|&at| format!("{} pixels differ, the first at ({}, {}): {:?} instead of {:?}",
+                                            different.len(), at as u32 % width, at as u32 / width,
+                                            &rgba[at * 4..at * 4 + 4], &golden[at * 4..at * 4 + 4]))
+    };
+    if let Some(mismatch) = mismatch {
+        let actual = env::temp_dir().join(format!("{}.actual.png", name));
+        fs::write(&actual, encode_png(width, height, rgba).unwrap()).unwrap();
+        panic!("{} doesn't match its golden: {}; the image is in {}", name, mismatch, actual.display());
+    }
+}
+
+fn decode_png(file: File) -> (u32, u32, Vec<u8>) {
+    let (info, mut reader) = png::Decoder::new(file).read_info().unwrap();
+    assert_eq!((info.color_type, info.bit_depth), (png::ColorType::RGBA, png::BitDepth::Eight),
+               "goldens are 8 bit RGBA");
+    let mut rgba = vec![0; info.buffer_size()];
+    reader.next_frame(&mut rgba).unwrap();
+    (info.width, info.height, rgba)
+}
+
+/// The "redmean" approximation of how far apart two colors look, of the
+/// colors over black so a transparent pixel's color doesn't count, plus how
+/// far apart the alphas are
+fn distance(a: &[u8], b: &[u8]) -> f64 {
+    let over_black = |px: &[u8], channel: usize| px[channel] as f64 * px[3] as f64 / 255.0;
+    let mean_red = (over_black(a, 0) + over_black(b, 0)) / 2.0;
+    let (red, green, blue) = (over_black(a, 0) - over_black(b, 0),
+                              over_black(a, 1) - over_black(b, 1),
+                              over_black(a, 2) - over_black(b, 2));
+    let color = ((2.0 + mean_red / 256.0) * red * red
+        + 4.0 * green * green
+        + (2.0 + (255.0 - mean_red) / 256.0) * blue * blue).sqrt();
+    color + (a[3] as f64 - b[3] as f64).abs()
+}
+
+const RED: u16 = 0xF800;
+const GREEN: u16 = 0x07E0;
+const BLUE: u16 = 0x001F;
+const WHITE: u16 = 0xFFFF;
+const GRAY: u16 = 0x8410;
+const MAGENTA: u16 = 0xF81F;
+
+/// A 4x3 sprite of every kind of run: a full line, a move back on the next
+/// one, painted magenta and unpainted pixels
+fn sprite_rle() -> Vec<u8> {
+    RleFixture::new()
+        .resource(ResourceFixture::new(4, 3)
+            .pixels(&[RED, GREEN, BLUE, WHITE])
+            .next_line().skip(-3)
+            .pixels(&[MAGENTA, GRAY])
+            .next_line().skip(-2)
+            .pixels(&[GRAY]))
+        .resource(ResourceFixture::new(2, 2)
+            .pixels(&[WHITE, RED])
+            .next_line().skip(-2)
+            .pixels(&[RED, WHITE]))
+        .build()
+}
+
+fn sprite(format: PixelFormat) -> Resource {
+    parse_rle_as(0, &sprite_rle(), format).unwrap().resources.remove(0)
+}
+
+#[test]
+fn test_golden_sprite() {
+    let resource = sprite(PixelFormat::Rgba8);
+    assert_golden("sprite", 4, 3, &resource.image_raw);
+
+    let bgra = sprite(PixelFormat::Bgra8).converted(PixelFormat::Bgra8, PixelFormat::Rgba8).unwrap();
+    assert_golden("sprite", 4, 3, &bgra);
+
+    // R5g6b5 can't tell painted magenta from unpainted either
+    let options = DecodeOptions { magenta_is_transparent: true, ..DecodeOptions::default() };
+    let resource = parse_rle_with(0, &sprite_rle(), options).unwrap().resources.remove(0);
+    assert_golden("sprite_clear_magenta", 4, 3, &resource.image_raw);
+    let r5g6b5 = sprite(PixelFormat::R5g6b5).converted(PixelFormat::R5g6b5, PixelFormat::Rgba8).unwrap();
+    assert_golden("sprite_clear_magenta", 4, 3, &r5g6b5);
+}
+
+#[test]
+fn test_golden_sprite_scaled() {
+    let resource = sprite(PixelFormat::Rgba8);
+    let image = Image { width: 4, height: 3, offset_x: 0, offset_y: 0, rgba: resource.image_raw };
+    let scaled = scale(&image, 2.0);
+    assert_golden("sprite_2x", scaled.width, scaled.height, &scaled.rgba);
+}
+
+#[test]
+fn test_golden_composite() {
+    let file = parse_rle(0, &sprite_rle()).unwrap();
+    fn layer(res: &Resource, x: i32, y: i32) -> Layer<'_> {
+        let (width, height) = (res.width as u32, res.height as u32);
+        Layer { name: String::new(), x, y, width, height, rgba: &res.image_raw, visible: true }
+    }
+    let layers = vec![layer(&file.resources[0], -1, -1), layer(&file.resources[1], 2, 1)];
+    let (left, top, width, height) = bounds(&layers);
+    assert_golden("composite", width, height, &composite(&layers, left, top, width, height));
+}
+
+#[test]
+fn test_golden_minimap() {
+    // a 4x2 map: a warp in the top left corner, the right column blocked
+    let mut map = Map::new();
+    map.set_size_x(4);
+    map.set_size_y(2);
+    for idx in 0..8 {
+        let (warp, collision) = match idx {
+            0 => (16, 0),
+            3 | 7 => (0, 1),
+            _ => (0, 0),
+        };
+        map.add_tile(MapTile { obj_rmd_entry: Entry::new(0, 0), tle_rmd_entry: Entry::new(0, 0), warp, collision });
+    }
+    let (width, height, rgba) = minimap(&map, 8);
+    assert_golden("minimap", width, height, &rgba);
+}
@@ -1,30 +1,75 @@
 #![allow(dead_code, unused_variables)]
 
 extern crate core_compat;
+extern crate core_rules;
+extern crate byteorder;
 extern crate png;
+extern crate rusttype;
 extern crate xml_writer;
+#[cfg(feature = "sound")]
+extern crate rodio;
+#[cfg(feature = "sqlite")]
+extern crate rusqlite as sql;
 
+use std::collections::HashMap;
 use std::path::Path;
 use std::path::PathBuf;
 use std::fs::File;
 use std::fs::read_dir;
+use std::io;
 use std::io::Read;
-// use std::io::Write;
+use std::io::Write;
 use std::io::BufWriter;
 
 use png::HasParameters;
 
+mod animations;
+mod bgm;
+mod card;
+mod codegen;
+mod deepzoom;
+mod doctor;
+mod dupes;
+mod error;
+mod explore;
+mod export;
+mod font;
+#[cfg(test)]
+mod golden;
+mod minimap;
+mod ora;
+mod orphans;
+mod patch;
+mod play;
+mod preview;
+mod provenance;
+mod recolor;
+mod remap;
+mod renumber;
+mod rle;
+mod scenes;
+mod search;
+mod server_map;
+mod template;
+mod toml;
+mod wiki;
+mod world;
+mod xref;
+
 use core_compat::entity::resource_file::ResourceFile;
 use core_compat::entity::resource::Resource;
 use core_compat::entity::rmd::Rmd;
 use core_compat::entity::rmd_type::RmdType;
 use core_compat::entity::map::Map;
 use core_compat::entity::list::List;
-use core_compat::error::Error;
-use core_compat::parser::rle::parse_rle;
+use core_compat::parser::rle::{parse_rle_lenient, PixelFormat};
 use core_compat::parser::rmd::parse_rmd;
 use core_compat::parser::rmm::parse_rmm;
 use core_compat::parser::lst::parse_lst;
+use core_compat::utility::transform::{ResourceHooks, Stage};
+
+use error::Error;
+use server_map::encode_server_map;
 
 static OUTPUT_PATH: &'static str = "../temp/";
 
@@ -54,6 +99,10 @@ static RLE_ENTRIES: [(&'static str, &'static str, &'static str, &'static str, bo
 static RMM_ENTRY: (&'static str, &'static str) =
     ("maps", "../data/DATAs/Map");
 
+// The sounds have their own file format, see `parser::snd`
+static SND_ENTRY: (&'static str, &'static str) =
+    ("../data/RLEs/Snd", "../data/RLEs/snd.lst");
+
 static RMD_ENTRIES: [(&'static str, &'static str, &'static str, RmdType); 5] = [
     ("bullet", "bul", "../data/DATAs/Bul", RmdType::Bullet),
     ("char",   "chr", "../data/DATAs/Chr", RmdType::Character),
@@ -63,6 +112,21 @@ static RMD_ENTRIES: [(&'static str, &'static str, &'static str, RmdType); 5] = [
 ];
 
 fn main() {
+    // tool commands: `data_converter <command> [args..]`
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        match run_command(&args) {
+            Ok(_) => (),
+            Err(Error::Args(usage)) => println!("{}", usage),
+            Err(e) => {
+                println!("{:?}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // no command: run the data conversion
     println!("Starting from directory: {:?}", ::std::env::current_dir().unwrap());
     // create directory - print errors...
     let root_out_dir = Path::new(OUTPUT_PATH);
@@ -73,7 +137,7 @@ fn main() {
     }
 
     // parse the list file and insert them into the database
-    convert_rle_data();
+    convert_rle_data(&ResourceHooks::new());
 
     // convert the maps ...
     // convert_rmm_data();
@@ -81,9 +145,88 @@ fn main() {
     // ... and rmd files
     // convert_rmd_data();
 
+    // ... and the compact maps for the server
+    // convert_server_map_data();
+
     println!("finished!");
 }
 
+fn run_command(args: &[String]) -> Result<(), Error> {
+    match args[0].as_str() {
+        "animations" => animations::animations(&args[1..]),
+        "apply-official-patch" => patch::apply_official_patch(&args[1..]),
+        "bgm" => bgm::bgm(&args[1..]),
+        "card" => card::card(&args[1..]),
+        "codegen" => codegen::codegen(&args[1..]),
+        "deepzoom" => deepzoom::deepzoom(&args[1..]),
+        "doctor" => doctor::doctor(&args[1..]),
+        "dupes" => dupes::dupes(&args[1..]),
+        "explore" => explore::explore(&args[1..]),
+        "export" => export::export(&args[1..]),
+        "font" => font::font(&args[1..]),
+        "ora" => ora::ora(&args[1..]),
+        "orphans" => orphans::orphans(&args[1..]),
+        "play" => play::play(&args[1..]),
+        "preview" => preview::preview(&args[1..]),
+        "provenance" => provenance::provenance(&args[1..]),
+        "recolor" => recolor::recolor(&args[1..]),
+        "remap" => remap::remap(&args[1..]),
+        "renumber" => renumber::renumber(&args[1..]),
+        "rle" => rle::rle(&args[1..]),
+        "scenes" => scenes::scenes(&args[1..]),
+        "wiki" => wiki::wiki(&args[1..]),
+        "world" => world::world(&args[1..]),
+        "xref" => xref::xref(&args[1..]),
+        _ => Err(Error::Args(USAGE.into())),
+    }
+}
+
+static USAGE: &str = "usage: data_converter [command]
+commands:
+    animations <rmd> [--corrections <file.toml>] [-o <out.toml>]
+                                 group a character's animations into actions and directions
+    apply-official-patch <patch folder> [--dry-run] [--force]
+                                 overlay an extracted official update on the data
+    bgm <folder> -o <out.m3u|out.json> [--maps <table>]
+                                 export the background music as a playlist
+    card <tables> <query> -o <out.png> [--sprites <rm.sqlite>]
+                                 render an item or monster card for chat bots
+    codegen <list> <ids> -o <out.rs> [--include-bytes]
+                                 embed sprites in a Rust module
+    deepzoom <map> -o <out folder> [--threads <n>]
+                                 render a map as Deep Zoom tiles for web viewers
+    doctor                       check the data layout and environment
+    dupes <list> [--distance <bits>] [--json] [-o <out>]
+                                 report characters sharing the same animations
+    explore <tables> <tiles folder> [--addr <host:port>]
+                                 browse the deepzoom maps with their warps and spawns
+    export <profile> [<list>..] [-o <out>] [--profiles <file>]
+                                 export the sprites the way a profile says
+    font <font.lst> <font.rle> --chars <set>[,<set>..] [--ttf <font.ttf>] -o <out folder>
+                                 add glyphs rendered from a TrueType font to a bitmap font
+    ora <list> <rmd> <entry> -o <out.ora> [--clean-edges]
+                                 export a character as layered OpenRaster
+    orphans [--json] [-o <out>]  report unused sprites and dangling references
+    play --sound <id> [--loop] [--volume <v>]
+                                 play a sound from the data
+    preview <list> <rmd> <animation> -o <out.png> [--variant <n>] [--delay <ms>] [--clean-edges]
+                                 render a character animation as an animated PNG
+    provenance --db <rm.sqlite> <gid>..
+                                 trace derived sprites back to their originals
+    recolor <rules> --db <rm.sqlite> --file-number <n> [--insert] [-o <out.rle>]
+                                 make recolored copies of sprites for mods
+    remap <table> [--dry-run]    rewrite map and list references
+    renumber <list> [args..]     renumber list ids and their references
+    rle <command> [args..]       split or merge RLE files
+    scenes <scenes.toml> -o <out folder>
+                                 render the scenes of a script as PNGs for docs
+    wiki <tables> -o <out> [--sprites <rm.sqlite>] [--templates <folder>]
+                                 render wiki pages for the items, monsters and maps
+    world <tables> -o <out.dot|out.graphml|out.html> [--maps <folder>]
+                                 export how the maps connect through their warps
+    xref [<list>..] [--json] [-o <out>]
+                                 report list items and resources that don't match up";
+
 fn convert_rmd_data() {
     // create the output directory if it doesn't exist yet
     let mut data_out_dir = PathBuf::new();
@@ -235,7 +378,46 @@ fn convert_rmm_data() {
     }
 }
 
-fn convert_rle_data() {
+fn convert_server_map_data() {
+    // create the output directory if it doesn't exist yet
+    let mut map_out_dir = PathBuf::new();
+    map_out_dir.push(OUTPUT_PATH);
+    map_out_dir.push("server_map");
+    println!("Creating directory: {:?}", map_out_dir);
+    match std::fs::create_dir(map_out_dir) {
+        Ok(_) => (),
+        Err(e) => println!("{:?}", e),
+    }
+
+    let (kind, path) = RMM_ENTRY;
+    let map_file_paths = read_dir(Path::new(path)).unwrap();
+
+    for entry in map_file_paths {
+        let path = entry.unwrap().path();
+        let map: Map = match load_rmm_data(&path) {
+            Ok(map) => map,
+            Err(e) => {
+                println!("{:?}", e);
+                println!("{:?}", path);
+                continue
+            }
+        };
+
+        // NOTE: spawn regions aren't part of the original map files
+        let bytes = encode_server_map(&map, &[]);
+
+        let mut path_buf = PathBuf::new();
+        path_buf.push(OUTPUT_PATH);
+        path_buf.push("server_map");
+        path_buf.push(format!("{}_{:03}.bin", kind, map.number()));
+        let mut file = File::create(&path_buf).unwrap();
+        file.write_all(&bytes).unwrap();
+    }
+}
+
+/// Exports the sprites of every list as PNG files; `hooks` can change the
+/// resources as they're decoded and before they're written out
+fn convert_rle_data(hooks: &ResourceHooks) {
     for &(kind, short_kind, folder, list, use_v2) in RLE_ENTRIES.iter() {
         println!("file: {:?}", &kind);
 
@@ -265,13 +447,18 @@ fn convert_rle_data() {
             let entry = entry.unwrap();
             let path = entry.path();
 
-            let res_file: ResourceFile = load_rle_data(&path).unwrap();
+            let mut res_file: ResourceFile = load_rle_data(&path).unwrap();
+            hooks.run_file(Stage::Pre, &mut res_file).unwrap();
 
             for resource in res_file.resources {
                 resources.push(resource);
             }
         }
 
+        for resource in resources.iter_mut() {
+            hooks.run(Stage::Post, resource).unwrap();
+        }
+
         // Commit all of the sprite objects in one transaction
         let mut combi_entries: Vec<RleCombiEntry> = Vec::new();
         let mut matches = 0;
@@ -359,21 +546,21 @@ fn load_rmd_data(path: &Path, kind: RmdType) -> Result<Rmd, Error> {
     let mut file = File::open(path)?;
     let mut bytes = Vec::<u8>::new();
     file.read_to_end(&mut bytes)?;
-    parse_rmd(kind, &bytes)
+    Ok(parse_rmd(kind, &bytes)?)
 }
 
 fn load_rmm_data(path: &Path) -> Result<Map, Error> {
     let mut file = File::open(path)?;
     let mut bytes = Vec::<u8>::new();
     file.read_to_end(&mut bytes)?;
-    parse_rmm(&bytes)
+    Ok(parse_rmm(&bytes)?)
 }
 
 fn load_list_data(path: &Path, use_v2: bool) -> Result<List, Error> {
     let mut file = File::open(path)?;
     let mut bytes = Vec::<u8>::new();
     file.read_to_end(&mut bytes)?;
-    parse_lst(&bytes, use_v2)
+    Ok(parse_lst(&bytes, use_v2)?)
 }
 
 fn load_rle_data(path: &Path) -> Result<ResourceFile, Error> {
@@ -382,7 +569,72 @@ fn load_rle_data(path: &Path) -> Result<ResourceFile, Error> {
     let mut bytes = Vec::<u8>::new();
     file.read_to_end(&mut bytes)?;
 
-    // parse the file number
+    // parse && append results, going past the resources that are broken
+    let resource_file = parse_rle_lenient(file_number(path), &bytes, PixelFormat::Rgba8)?;
+    for warning in resource_file.warnings.iter() {
+        println!("{:?}: {:?}", path, warning);
+    }
+    for failure in resource_file.failures.iter() {
+        println!("{:?}: skipped resource {} at {}: {:?}", path, failure.index, failure.offset, failure.reason);
+    }
+    Ok(resource_file)
+}
+
+/// Loads every RLE file in `folder` by file number. Files that don't parse
+/// are left out with a message.
+fn load_rle_folder(folder: &str) -> Result<HashMap<u32, ResourceFile>, Error> {
+    let mut files = HashMap::new();
+    for entry in read_dir(folder)? {
+        let path = entry?.path();
+        match load_rle_data(&path) {
+            Ok(file) => { files.insert(file_number(&path), file); },
+            Err(e) => eprintln!("{:?}: {:?}", path, e),
+        }
+    }
+    Ok(files)
+}
+
+/// quotes a string for the JSON reports
+fn json_string(string: &str) -> String {
+    let mut out = String::from("\"");
+    for chr in string.chars() {
+        match chr {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            chr if (chr as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", chr as u32)),
+            chr => out.push(chr),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// An RGBA image as PNG; an empty one becomes a single clear pixel
+fn encode_png(width: u32, height: u32, rgba: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut out, width.max(1), height.max(1));
+        encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(io::Error::from)?;
+        if width == 0 || height == 0 {
+            writer.write_image_data(&[0; 4]).map_err(io::Error::from)?;
+        } else {
+            writer.write_image_data(rgba).map_err(io::Error::from)?;
+        }
+    }
+    Ok(out)
+}
+
+/// find the RLE file with the given file number in `folder`
+fn find_rle_file(folder: &str, file: u32) -> Result<Option<PathBuf>, Error> {
+    Ok(read_dir(folder)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| file_number(path) == file))
+}
+
+/// parse the file number from a name like `obj00012.rle` or `c0000042.rle`
+fn file_number(path: &Path) -> u32 {
     let mut file_num = 0xFFFF;
     if let Some(stem) = path.file_stem() {
         if let Some(stem) = stem.to_str() {
@@ -392,9 +644,7 @@ fn load_rle_data(path: &Path) -> Result<ResourceFile, Error> {
             file_num = file_num % 99_999;
         }
     }
-
-    // parse && append results
-    parse_rle(file_num, &mut bytes)
+    file_num
 }
 
 struct RleCombiEntry {
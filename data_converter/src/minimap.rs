@@ -0,0 +1,71 @@
+//! Small overview images of maps, drawn from the tile flags alone so they
+//! need no sprites: walkable ground, blocked tiles and warps each get a
+//! colour.
+
+use core_compat::entity::map::Map;
+
+const GROUND: [u8; 4] = [170, 190, 140, 255];
+const BLOCKED: [u8; 4] = [60, 70, 60, 255];
+const WARP: [u8; 4] = [220, 50, 50, 255];
+
+/// The map scaled, nearest neighbour, so its longer side is `size` pixels
+pub fn minimap(map: &Map, size: u32) -> (u32, u32, Vec<u8>) {
+    let (size_x, size_y) = (map.size_x(), map.size_y());
+    if size_x == 0 || size_y == 0 || map.tiles().len() < (size_x * size_y) as usize {
+        return (1, 1, vec![0; 4]);
+    }
+    let scale = size_x.max(size_y) as f32 / size as f32;
+    let width = ((size_x as f32 / scale) as u32).max(1);
+    let height = ((size_y as f32 / scale) as u32).max(1);
+    let mut out = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let tile_x = ((x as f32 * scale) as u32).min(size_x - 1);
+            let tile_y = ((y as f32 * scale) as u32).min(size_y - 1);
+            let tile = &map.tiles()[(tile_y * size_x + tile_x) as usize];
+            let color = if tile.warp != 0 {
+                WARP
+            } else if tile.is_passable() {
+                GROUND
+            } else {
+                BLOCKED
+            };
+            out.extend_from_slice(&color);
+        }
+    }
+    (width, height, out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core_compat::entity::entry::Entry;
+    use core_compat::entity::map_tile::MapTile;
+
+    #[test]
+    fn test_minimap() {
+        // a 4x2 map: a warp in the top left corner, the right column blocked
+        let mut map = Map::new();
+        map.set_size_x(4);
+        map.set_size_y(2);
+        for idx in 0..8 {
+            let (warp, collision) = match idx {
+                0 => (16, 0),
+                3 | 7 => (0, 1),
+                _ => (0, 0),
+            };
+            map.add_tile(MapTile { obj_rmd_entry: Entry::new(0, 0), tle_rmd_entry: Entry::new(0, 0), warp, collision });
+        }
+        let (width, height, rgba) = minimap(&map, 8);
+        assert_eq!((width, height), (8, 4));
+        let pixel = |x: u32, y: u32| &rgba[((y * width + x) * 4) as usize..][..4];
+        assert_eq!(pixel(1, 1), &WARP);
+        assert_eq!(pixel(2, 0), &GROUND);
+        assert_eq!(pixel(7, 3), &BLOCKED);
+
+        let (width, height, _) = minimap(&map, 2);
+        assert_eq!((width, height), (2, 1));
+        assert_eq!(minimap(&Map::new(), 8), (1, 1, vec![0; 4]));
+    }
+}
@@ -0,0 +1,394 @@
+//! `data_converter ora <list> <rmd file> <entry> -o <out.ora> [--clean-edges]`
+//!
+//! Exports a composed character, one entry of a character RMD file, as an
+//! OpenRaster image. Every sprite of the entry (body, hair, equipment, ...)
+//! gets its own named layer at the offset the game draws it at, so artists
+//! can repaint a piece and keep it aligned. When an image lists several
+//! sprites (e.g. one per weapon) the extra ones are added as hidden layers.
+//! `--clean-edges` takes the magenta color key out of the sprites and bleeds
+//! their colors into the transparent pixels around them (see
+//! `core_compat::utility::bleed`), for layers that get scaled.
+//!
+//! OpenRaster is a zip file; the entries are written uncompressed since the
+//! layers are PNG files already.
+
+use std::collections::HashMap;
+use std::collections::hash_map;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use byteorder::WriteBytesExt;
+use byteorder::LittleEndian as LE;
+
+use core_compat::entity::list::List;
+use core_compat::entity::resource::Resource;
+use core_compat::entity::resource_file::ResourceFile;
+use core_compat::entity::rmd_entry::RmdEntry;
+use core_compat::entity::rmd_image::RmdImage;
+use core_compat::entity::rmd_type::RmdType;
+use core_compat::utility::transform::{BleedEdges, ResourceHooks, Stage};
+
+use crate::error::Error;
+use super::{RLE_ENTRIES, RMD_ENTRIES};
+use super::{encode_png, find_rle_file, load_list_data, load_rle_data, load_rmd_data};
+
+static USAGE: &str = "usage: ora <list> <rmd file> <entry> -o <out.ora> [--clean-edges]";
+
+/// Longest side of the thumbnail required by the format
+const THUMBNAIL_SIZE: u32 = 256;
+
+#[derive(Clone)]
+pub struct Layer<'a> {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub rgba: &'a [u8],
+    pub visible: bool,
+}
+
+pub fn ora(args: &[String]) -> Result<(), Error> {
+    let mut positional = Vec::new();
+    let (mut output, mut clean) = (None, false);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" => output = iter.next(),
+            "--clean-edges" => clean = true,
+            _ => positional.push(arg),
+        }
+    }
+    let (short, rmd_file, entry_index, output) = match (positional.as_slice(), output) {
+        (&[short, rmd_file, entry], Some(output)) => match (rmd_file.parse::<u32>(), entry.parse::<usize>()) {
+            (Ok(rmd_file), Ok(entry)) => (short, rmd_file, entry, output),
+            _ => return Err(Error::Args(USAGE.into())),
+        },
+        _ => return Err(Error::Args(USAGE.into())),
+    };
+
+    let &(_, _, folder, list_path, use_v2) = RLE_ENTRIES.iter()
+        .find(|e| e.1 == short.as_str())
+        .ok_or_else(|| Error::Args(format!("unknown list `{}`", short)))?;
+    let list = load_list_data(Path::new(list_path), use_v2)?;
+    let &(_, chr_short, chr_dir, _) = RMD_ENTRIES.iter().find(|e| e.3 == RmdType::Character).unwrap();
+    let rmd_path = Path::new(chr_dir).join(format!("{}{:05}.rmd", chr_short, rmd_file));
+    let rmd = load_rmd_data(&rmd_path, RmdType::Character)?;
+    let entry = rmd.get_entry(entry_index)
+        .ok_or_else(|| Error::Validation(vec![format!("no entry {} in {:?}", entry_index, rmd_path)]))?;
+
+    let mut files: HashMap<u32, ResourceFile> = HashMap::new();
+    let mut problems = Vec::new();
+    load_entry_files(entry, &list, short, folder, &mut files, &mut problems)?;
+    if clean {
+        clean_edges(&mut files)?;
+    }
+    let layers = entry_layers(entry, &list, short, &files, 0, &mut problems);
+    if !problems.is_empty() {
+        return Err(Error::Validation(problems));
+    }
+
+    File::create(output)?.write_all(&write_ora(&layers)?)?;
+    println!("wrote {} layers to {}", layers.len(), output);
+    Ok(())
+}
+
+/// Runs `bleed_edges` over every sprite of the files
+pub fn clean_edges(files: &mut HashMap<u32, ResourceFile>) -> Result<(), Error> {
+    let hooks = ResourceHooks::new().pre(BleedEdges);
+    for file in files.values_mut() {
+        hooks.run_file(Stage::Pre, file)?;
+    }
+    Ok(())
+}
+
+/// Loads the RLE files of every sprite of the entry that aren't in `files`
+/// yet; what can't be found is added to `problems`.
+pub fn load_entry_files(entry: &RmdEntry, list: &List, short: &str, folder: &str,
+                        files: &mut HashMap<u32, ResourceFile>, problems: &mut Vec<String>) -> Result<(), Error> {
+    for img in entry.images() {
+        for &id in img.image_id.iter() {
+            let item = match list.get_item(id as usize) {
+                Some(item) => item,
+                None => { problems.push(format!("{}: no list item {}", short, id)); continue },
+            };
+            let file = item.entry.file();
+            if let hash_map::Entry::Vacant(slot) = files.entry(file) {
+                match find_rle_file(folder, file)? {
+                    Some(path) => { slot.insert(load_rle_data(&path)?); },
+                    None => problems.push(format!("{}: no RLE file {}", short, file)),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The sprites of the entry as layers, bottom to top: by render z, then as
+/// listed in the entry. Of an image listing several sprites, the one at
+/// `shown` is visible, or the first one if there are fewer.
+pub fn entry_layers<'a>(entry: &RmdEntry, list: &List, short: &str, files: &'a HashMap<u32, ResourceFile>,
+                        shown: usize, problems: &mut Vec<String>) -> Vec<Layer<'a>> {
+    let mut layers = Vec::new();
+    for img in entry.images() {
+        let shown = if shown < img.image_id.len() { shown } else { 0 };
+        for (variant, &id) in img.image_id.iter().enumerate() {
+            let item = match list.get_item(id as usize) {
+                Some(item) => item,
+                None => continue,
+            };
+            let (file, index) = (item.entry.file(), item.entry.index());
+            match files.get(&file).map(|file| file.get(index)) {
+                Some(Some(res)) => {
+                    let name = format!("{} ({})", item.name, item.id);
+                    layers.push((img.render_z, sprite_layer(img, res, name, variant == shown)));
+                }
+                Some(None) => problems.push(format!("{}: no resource {}:{}", short, file, index)),
+                None => (),
+            }
+        }
+    }
+    layers.sort_by_key(|&(render_z, _)| render_z);
+    layers.into_iter().map(|(_, layer)| layer).collect()
+}
+
+/// Places the sprite so the source rectangle of the image ends up at its
+/// destination, the same way the map renderer does.
+fn sprite_layer<'a>(img: &RmdImage, res: &'a Resource, name: String, visible: bool) -> Layer<'a> {
+    Layer {
+        name,
+        x: img.dest_x + res.offset_x - img.source_x1,
+        y: img.dest_y + res.offset_y - img.source_y1,
+        width: res.width.max(0) as u32,
+        height: res.height.max(0) as u32,
+        rgba: &res.image_raw,
+        visible,
+    }
+}
+
+/// `layers` are given bottom to top; the canvas is the bounding box of all of
+/// them.
+fn write_ora(layers: &[Layer]) -> Result<Vec<u8>, Error> {
+    let (left, top, width, height) = bounds(layers);
+
+    let mut zip = ZipWriter::new();
+    // the mimetype has to come first and uncompressed
+    zip.add("mimetype", b"image/openraster");
+    zip.add("stack.xml", &stack_xml(layers, left, top, width, height)?);
+    for (idx, layer) in layers.iter().enumerate() {
+        zip.add(&format!("data/layer{}.png", idx), &encode_png(layer.width, layer.height, layer.rgba)?);
+    }
+    let merged = composite(layers, left, top, width, height);
+    zip.add("mergedimage.png", &encode_png(width, height, &merged)?);
+    let (thumb_width, thumb_height, thumb) = thumbnail(width, height, &merged, THUMBNAIL_SIZE);
+    zip.add("Thumbnails/thumbnail.png", &encode_png(thumb_width, thumb_height, &thumb)?);
+    Ok(zip.finish())
+}
+
+/// The bounding box of the layers: left, top, width and height
+pub fn bounds(layers: &[Layer]) -> (i32, i32, u32, u32) {
+    let left = layers.iter().map(|l| l.x).min().unwrap_or(0);
+    let top = layers.iter().map(|l| l.y).min().unwrap_or(0);
+    let right = layers.iter().map(|l| l.x + l.width as i32).max().unwrap_or(1);
+    let bottom = layers.iter().map(|l| l.y + l.height as i32).max().unwrap_or(1);
+    (left, top, (right - left) as u32, (bottom - top) as u32)
+}
+
+fn stack_xml(layers: &[Layer], left: i32, top: i32, width: u32, height: u32) -> Result<Vec<u8>, Error> {
+    let mut xml = xml_writer::XmlWriter::new(Vec::new());
+    xml.dtd("UTF-8")?;
+    xml.begin_elem("image")?;
+    xml.attr("version", "0.0.5")?;
+    xml.attr("w", &width.to_string())?;
+    xml.attr("h", &height.to_string())?;
+    xml.begin_elem("stack")?;
+    // the first layer of the stack is the top one
+    for (idx, layer) in layers.iter().enumerate().rev() {
+        xml.begin_elem("layer")?;
+        xml.attr_esc("name", &layer.name)?;
+        xml.attr("src", &format!("data/layer{}.png", idx))?;
+        xml.attr("x", &(layer.x - left).to_string())?;
+        xml.attr("y", &(layer.y - top).to_string())?;
+        xml.attr("visibility", if layer.visible { "visible" } else { "hidden" })?;
+        xml.end_elem()?;
+    }
+    xml.end_elem()?;
+    xml.end_elem()?;
+    xml.close()?;
+    xml.flush()?;
+    Ok(xml.into_inner())
+}
+
+/// Draws the visible layers over each other; the sprites are either fully
+/// opaque or fully transparent so there's no blending to do.
+pub fn composite(layers: &[Layer], left: i32, top: i32, width: u32, height: u32) -> Vec<u8> {
+    let mut out = vec![0u8; (width * height * 4) as usize];
+    for layer in layers.iter().filter(|l| l.visible) {
+        for (idx, px) in layer.rgba.chunks(4).enumerate() {
+            if px.len() < 4 || px[3] == 0 {
+                continue;
+            }
+            let x = (layer.x - left) as u32 + idx as u32 % layer.width;
+            let y = (layer.y - top) as u32 + idx as u32 / layer.width;
+            if x < width && y < height {
+                let start = ((y * width + x) * 4) as usize;
+                out[start..start + 4].copy_from_slice(px);
+            }
+        }
+    }
+    out
+}
+
+/// Nearest neighbour scale down to fit `size`
+pub fn thumbnail(width: u32, height: u32, rgba: &[u8], size: u32) -> (u32, u32, Vec<u8>) {
+    let scale = (width.max(height) as f32 / size as f32).max(1.0);
+    let thumb_width = ((width as f32 / scale) as u32).max(1);
+    let thumb_height = ((height as f32 / scale) as u32).max(1);
+    let mut out = Vec::with_capacity((thumb_width * thumb_height * 4) as usize);
+    for y in 0..thumb_height {
+        for x in 0..thumb_width {
+            let src_x = ((x as f32 * scale) as u32).min(width - 1);
+            let src_y = ((y as f32 * scale) as u32).min(height - 1);
+            let start = ((src_y * width + src_x) * 4) as usize;
+            out.extend_from_slice(&rgba[start..start + 4]);
+        }
+    }
+    (thumb_width, thumb_height, out)
+}
+
+/// Just enough of the zip format to store files without compression
+struct ZipWriter {
+    out: Vec<u8>,
+    // name, crc, size, offset of the local header
+    entries: Vec<(String, u32, u32, u32)>,
+}
+
+impl ZipWriter {
+    fn new() -> ZipWriter {
+        ZipWriter { out: Vec::new(), entries: Vec::new() }
+    }
+
+    fn add(&mut self, name: &str, data: &[u8]) {
+        let crc = crc32(data);
+        let offset = self.out.len() as u32;
+        self.out.write_u32::<LE>(0x0403_4b50).unwrap(); // local file header
+        self.write_common_fields(name, crc, data.len() as u32);
+        self.out.extend_from_slice(name.as_bytes());
+        self.out.extend_from_slice(data);
+        self.entries.push((name.into(), crc, data.len() as u32, offset));
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let directory_start = self.out.len() as u32;
+        let entries = std::mem::take(&mut self.entries);
+        for &(ref name, crc, size, offset) in entries.iter() {
+            self.out.write_u32::<LE>(0x0201_4b50).unwrap(); // central directory header
+            self.out.write_u16::<LE>(20).unwrap();          // made by
+            self.write_common_fields(name, crc, size);
+            self.out.write_u16::<LE>(0).unwrap();           // comment length
+            self.out.write_u16::<LE>(0).unwrap();           // disk number
+            self.out.write_u16::<LE>(0).unwrap();           // internal attributes
+            self.out.write_u32::<LE>(0).unwrap();           // external attributes
+            self.out.write_u32::<LE>(offset).unwrap();
+            self.out.extend_from_slice(name.as_bytes());
+        }
+        let directory_size = self.out.len() as u32 - directory_start;
+        self.out.write_u32::<LE>(0x0605_4b50).unwrap();     // end of central directory
+        self.out.write_u16::<LE>(0).unwrap();               // disk number
+        self.out.write_u16::<LE>(0).unwrap();               // disk with the directory
+        self.out.write_u16::<LE>(entries.len() as u16).unwrap();
+        self.out.write_u16::<LE>(entries.len() as u16).unwrap();
+        self.out.write_u32::<LE>(directory_size).unwrap();
+        self.out.write_u32::<LE>(directory_start).unwrap();
+        self.out.write_u16::<LE>(0).unwrap();               // comment length
+        self.out
+    }
+
+    /// The fields shared by the local and the central directory headers
+    fn write_common_fields(&mut self, name: &str, crc: u32, size: u32) {
+        self.out.write_u16::<LE>(20).unwrap();              // version needed
+        self.out.write_u16::<LE>(0).unwrap();               // flags
+        self.out.write_u16::<LE>(0).unwrap();               // stored
+        self.out.write_u16::<LE>(0).unwrap();               // time
+        self.out.write_u16::<LE>(0x21).unwrap();            // date: 1980-01-01
+        self.out.write_u32::<LE>(crc).unwrap();
+        self.out.write_u32::<LE>(size).unwrap();            // compressed size
+        self.out.write_u32::<LE>(size).unwrap();
+        self.out.write_u16::<LE>(name.len() as u16).unwrap();
+        self.out.write_u16::<LE>(0).unwrap();               // extra field length
+    }
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [0xFF, 0, 0, 0xFF];
+    const BLUE: [u8; 4] = [0, 0, 0xFF, 0xFF];
+
+    fn layer<'a>(name: &str, x: i32, y: i32, rgba: &'a [u8], visible: bool) -> Layer<'a> {
+        Layer { name: name.into(), x, y, width: 1, height: 1, rgba, visible }
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_sprite_layer_offsets() {
+        let mut img = RmdImage::new();
+        img.source_x1 = -20;
+        img.source_y1 = -40;
+        img.dest_x = 5;
+        let mut res = Resource::new();
+        res.offset_x = -18;
+        res.offset_y = -40;
+        let layer = sprite_layer(&img, &res, "body".into(), true);
+        assert_eq!((layer.x, layer.y), (7, 0));
+    }
+
+    #[test]
+    fn test_composite() {
+        let layers = [layer("body", 0, 0, &RED, true),
+                      layer("hair", 1, 0, &BLUE, true),
+                      layer("sword", 0, 0, &BLUE, false)];
+        let merged = composite(&layers, 0, 0, 2, 1);
+        assert_eq!(&merged[0..4], &RED);
+        assert_eq!(&merged[4..8], &BLUE);
+    }
+
+    #[test]
+    fn test_stack_xml() {
+        let layers = [layer("body", -2, 3, &RED, true), layer("a \"hat\"", 0, 3, &BLUE, false)];
+        let xml = String::from_utf8(stack_xml(&layers, -2, 3, 3, 1).unwrap()).unwrap();
+        // top layer first
+        let hat = xml.find("data/layer1.png").unwrap();
+        let body = xml.find("data/layer0.png").unwrap();
+        assert!(hat < body);
+        assert!(xml.contains("x=\"2\""));
+        assert!(xml.contains("visibility=\"hidden\""));
+        assert!(xml.contains("&quot;hat&quot;"));
+    }
+
+    #[test]
+    fn test_write_ora_starts_with_mimetype() {
+        let layers = [layer("body", 0, 0, &RED, true)];
+        let ora = write_ora(&layers).unwrap();
+        assert_eq!(&ora[0..4], &[0x50, 0x4b, 0x03, 0x04]);
+        assert_eq!(&ora[30..38], b"mimetype");
+        assert_eq!(&ora[38..54], b"image/openraster");
+    }
+}
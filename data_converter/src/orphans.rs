@@ -0,0 +1,331 @@
+//! `data_converter orphans [--json] [-o <out>]`
+//!
+//! Walks the references from the maps down to the sprites, to find
+//!
+//! - `unused`: object and tile list items and resources nothing reaches,
+//!   candidates for removal from slim asset packs,
+//! - `dangling`: references on the way that point at nothing, which are bugs
+//!   in the data or in the parsers.
+//!
+//! The graph is map tile -> RMD entry -> list item -> RLE resource. Only the
+//! maps are roots so far, so only the object and tile sprites are checked;
+//! the other sprites are reached through items and monsters, which aren't
+//! parsed yet.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs::File;
+use std::fs::read_dir;
+use std::io::Write;
+use std::path::Path;
+
+use core_compat::entity::entry::Entry;
+use core_compat::entity::list::List;
+use core_compat::entity::map::Map;
+use core_compat::entity::resource_file::ResourceFile;
+use core_compat::entity::rmd::Rmd;
+use core_compat::entity::rmd_entry::RmdEntry;
+use core_compat::entity::rmd_type::RmdType;
+
+use crate::error::Error;
+use super::{RLE_ENTRIES, RMD_ENTRIES, RMM_ENTRY};
+use super::{json_string, load_list_data, load_rle_folder, load_rmd_data, load_rmm_data};
+
+static USAGE: &str = "usage: orphans [--json] [-o <out>]";
+
+/// The RMD kinds the map tiles point at, with the list their images use
+static MAP_SPRITES: [(RmdType, &str); 2] = [
+    (RmdType::Object, "obj"),
+    (RmdType::Tile, "tle"),
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum Node {
+    Map(u32),
+    Rmd(RmdType, Entry),
+    Item(&'static str, u32),
+    Resource(&'static str, Entry),
+}
+
+impl Node {
+    fn describe(&self) -> String {
+        match *self {
+            Node::Map(number) => format!("map {}", number),
+            Node::Rmd(kind, entry) => format!("{:?} rmd {}:{}", kind, entry.file(), entry.index()),
+            Node::Item(list, id) => format!("{} item {}", list, id),
+            Node::Resource(list, entry) => format!("{} resource {}:{}", list, entry.file(), entry.index()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Finding {
+    Unused(Node),
+    /// A reference from the first node to the missing second one
+    Dangling(Node, Node),
+}
+
+/// Everything the graph is built from
+struct Assets {
+    maps: Vec<Map>,
+    rmds: HashMap<(RmdType, u32), Rmd>,
+    lists: HashMap<&'static str, List>,
+    files: HashMap<&'static str, HashMap<u32, ResourceFile>>,
+}
+
+pub fn orphans(args: &[String]) -> Result<(), Error> {
+    let mut output = None;
+    let mut json = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" => output = Some(iter.next().ok_or_else(|| Error::Args(USAGE.into()))?),
+            "--json" => json = true,
+            _ => return Err(Error::Args(USAGE.into())),
+        }
+    }
+
+    let findings = find_orphans(&load_assets()?);
+    let report = if json { to_json(&findings) } else { to_csv(&findings) };
+    match output {
+        Some(output) => {
+            File::create(output)?.write_all(report.as_bytes())?;
+            println!("wrote {} findings to {}", findings.len(), output);
+        }
+        None => print!("{}", report),
+    }
+    Ok(())
+}
+
+fn load_assets() -> Result<Assets, Error> {
+    let mut assets = Assets {
+        maps: Vec::new(),
+        rmds: HashMap::new(),
+        lists: HashMap::new(),
+        files: HashMap::new(),
+    };
+    let (_, map_dir) = RMM_ENTRY;
+    for entry in read_dir(map_dir)? {
+        let path = entry?.path();
+        match load_rmm_data(&path) {
+            Ok(map) => assets.maps.push(map),
+            Err(e) => eprintln!("{:?}: {:?}", path, e),
+        }
+    }
+    for &(kind, list) in MAP_SPRITES.iter() {
+        let &(_, short, rmd_dir, _) = RMD_ENTRIES.iter().find(|e| e.3 == kind).unwrap();
+        for entry in read_dir(rmd_dir)? {
+            let path = entry?.path();
+            let number = match path.file_stem().and_then(|s| s.to_str()) {
+                Some(stem) if stem.starts_with(short) => stem[short.len()..].parse().ok(),
+                _ => None,
+            };
+            if let Some(number) = number {
+                match load_rmd_data(&path, kind) {
+                    Ok(rmd) => { assets.rmds.insert((kind, number), rmd); },
+                    Err(e) => eprintln!("{:?}: {:?}", path, e),
+                }
+            }
+        }
+        let &(_, short, folder, list_path, use_v2) = RLE_ENTRIES.iter().find(|e| e.1 == list).unwrap();
+        assets.lists.insert(short, load_list_data(Path::new(list_path), use_v2)?);
+        assets.files.insert(short, load_rle_folder(folder)?);
+    }
+    Ok(assets)
+}
+
+/// Returns the dangling references in the order they are found, followed by
+/// the unused items and resources.
+fn find_orphans(assets: &Assets) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut reached = HashSet::new();
+    let mut dangling = HashSet::new();
+
+    // walk the graph down from the maps, depth first
+    let mut pending: Vec<Node> = assets.maps.iter().rev().map(|map| Node::Map(map.number())).collect();
+    while let Some(node) = pending.pop() {
+        if !reached.insert(node) {
+            continue;
+        }
+        let (edges, missing): (Vec<Node>, Vec<Node>) = references(assets, node).into_iter()
+            .partition(|&to| exists(assets, to));
+        for to in missing {
+            if dangling.insert((node, to)) {
+                findings.push(Finding::Dangling(node, to));
+            }
+        }
+        pending.extend(edges.into_iter().rev().filter(|to| !reached.contains(to)));
+    }
+
+    // anything left over isn't used by any map
+    for &(_, list) in MAP_SPRITES.iter() {
+        if let Some(items) = assets.lists.get(list) {
+            for item in items.items.iter() {
+                if !reached.contains(&Node::Item(list, item.id)) {
+                    findings.push(Finding::Unused(Node::Item(list, item.id)));
+                }
+            }
+        }
+        if let Some(files) = assets.files.get(list) {
+            let mut numbers: Vec<&u32> = files.keys().collect();
+            numbers.sort();
+            for &number in numbers {
+                for resource in files[&number].resources.iter() {
+                    let node = Node::Resource(list, Entry::new(number, resource.index()));
+                    if !reached.contains(&node) {
+                        findings.push(Finding::Unused(node));
+                    }
+                }
+            }
+        }
+    }
+    findings
+}
+
+/// The nodes `node` points at
+fn references(assets: &Assets, node: Node) -> Vec<Node> {
+    let mut refs = Vec::new();
+    match node {
+        Node::Map(number) => {
+            for map in assets.maps.iter().filter(|m| m.number() == number) {
+                for tile in map.tiles() {
+                    // file 0 is an empty layer
+                    if tile.obj_rmd_entry.file() != 0 {
+                        refs.push(Node::Rmd(RmdType::Object, tile.obj_rmd_entry));
+                    }
+                    if tile.tle_rmd_entry.file() != 0 {
+                        refs.push(Node::Rmd(RmdType::Tile, tile.tle_rmd_entry));
+                    }
+                }
+            }
+        }
+        Node::Rmd(kind, entry) => {
+            let list = MAP_SPRITES.iter().find(|s| s.0 == kind).unwrap().1;
+            if let Some(rmd_entry) = rmd_entry(assets, kind, entry) {
+                for img in rmd_entry.images() {
+                    refs.extend(img.image_id.iter().map(|&id| Node::Item(list, id as u32)));
+                }
+            }
+        }
+        Node::Item(list, id) => {
+            if let Some(item) = assets.lists.get(list).and_then(|l| l.get_item(id as usize)) {
+                refs.push(Node::Resource(list, item.entry));
+            }
+        }
+        Node::Resource(..) => (),
+    }
+    // a map repeats the same few entries on most of its tiles
+    let mut seen = HashSet::new();
+    refs.retain(|node| seen.insert(*node));
+    refs
+}
+
+fn exists(assets: &Assets, node: Node) -> bool {
+    match node {
+        Node::Map(_) => true,
+        Node::Rmd(kind, entry) => rmd_entry(assets, kind, entry).is_some(),
+        Node::Item(list, id) => assets.lists.get(list).and_then(|l| l.get_item(id as usize)).is_some(),
+        Node::Resource(list, entry) => assets.files.get(list)
+            .and_then(|files| files.get(&entry.file()))
+            .and_then(|file| file.get(entry.index()))
+            .is_some(),
+    }
+}
+
+fn rmd_entry(assets: &Assets, kind: RmdType, entry: Entry) -> Option<&RmdEntry> {
+    assets.rmds.get(&(kind, entry.file())).and_then(|rmd| rmd.get_entry(entry.index() as usize))
+}
+
+fn to_csv(findings: &[Finding]) -> String {
+    let mut csv = String::from("problem,node,referenced_by\n");
+    for finding in findings {
+        match *finding {
+            Finding::Unused(node) => csv.push_str(&format!("unused,{},\n", node.describe())),
+            Finding::Dangling(from, to) => csv.push_str(&format!("dangling,{},{}\n", to.describe(), from.describe())),
+        }
+    }
+    csv
+}
+
+fn to_json(findings: &[Finding]) -> String {
+    let mut json = String::from("[\n");
+    for (idx, finding) in findings.iter().enumerate() {
+        let (problem, node, from) = match *finding {
+            Finding::Unused(node) => ("unused", node, None),
+            Finding::Dangling(from, to) => ("dangling", to, Some(from)),
+        };
+        let from = from.map_or("null".into(), |from| json_string(&from.describe()));
+        json.push_str(&format!("  {{\"problem\": \"{}\", \"node\": {}, \"referenced_by\": {}}}",
+                               problem, json_string(&node.describe()), from));
+        json.push_str(if idx + 1 < findings.len() { ",\n" } else { "\n" });
+    }
+    json.push_str("]\n");
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core_compat::fixture::{LstFixture, RleFixture, ResourceFixture, RmdFixture, RmmFixture};
+    use core_compat::parser::lst::parse_lst;
+    use core_compat::parser::rle::parse_rle;
+    use core_compat::parser::rmd::parse_rmd;
+    use core_compat::parser::rmm::parse_rmm;
+
+    /// One map using object entry 1:0 on two tiles, and tile entry 1:5 which
+    /// doesn't exist
+    fn assets() -> Assets {
+        let map = RmmFixture::new(3, 2, 1)
+            .tile(0, 0, Entry::new(1, 0), Entry::new(0, 0), 0, 0)
+            .tile(1, 0, Entry::new(1, 0), Entry::new(1, 5), 0, 0)
+            .build();
+        // entry 0 shows items 1 and 7 (missing); entry 1 isn't on any map
+        let obj_rmd = RmdFixture::new().entry(&[&[1, 7]]).entry(&[&[2]]).build();
+        let obj_list = LstFixture::new("1.2")
+            .item("used", 1, Entry::new(4, 0))
+            .item("unused", 2, Entry::new(4, 1))
+            .build();
+        let resource = || ResourceFixture::new(1, 1).pixels(&[0xF800]);
+        let obj_rle = RleFixture::new().resource(resource()).resource(resource()).resource(resource()).build();
+
+        let mut assets = Assets {
+            maps: vec![parse_rmm(&map).unwrap()],
+            rmds: HashMap::new(),
+            lists: HashMap::new(),
+            files: HashMap::new(),
+        };
+        assets.rmds.insert((RmdType::Object, 1), parse_rmd(RmdType::Object, &obj_rmd).unwrap());
+        assets.lists.insert("obj", parse_lst(&obj_list, false).unwrap());
+        let mut files = HashMap::new();
+        files.insert(4, parse_rle(4, &obj_rle).unwrap());
+        assets.files.insert("obj", files);
+        assets
+    }
+
+    #[test]
+    fn test_find_orphans() {
+        let findings = find_orphans(&assets());
+        assert_eq!(findings, vec![
+            Finding::Dangling(Node::Map(3), Node::Rmd(RmdType::Tile, Entry::new(1, 5))),
+            Finding::Dangling(Node::Rmd(RmdType::Object, Entry::new(1, 0)), Node::Item("obj", 7)),
+            Finding::Unused(Node::Item("obj", 2)),
+            Finding::Unused(Node::Resource("obj", Entry::new(4, 1))),
+            Finding::Unused(Node::Resource("obj", Entry::new(4, 2))),
+        ]);
+    }
+
+    #[test]
+    fn test_report_formats() {
+        let findings = vec![
+            Finding::Dangling(Node::Map(3), Node::Rmd(RmdType::Tile, Entry::new(1, 5))),
+            Finding::Unused(Node::Item("obj", 2)),
+        ];
+        assert_eq!(to_csv(&findings), "problem,node,referenced_by\n\
+                                       dangling,Tile rmd 1:5,map 3\n\
+                                       unused,obj item 2,\n");
+        assert_eq!(to_json(&findings), "[\n  \
+            {\"problem\": \"dangling\", \"node\": \"Tile rmd 1:5\", \"referenced_by\": \"map 3\"},\n  \
+            {\"problem\": \"unused\", \"node\": \"obj item 2\", \"referenced_by\": null}\n]\n");
+    }
+}
@@ -0,0 +1,398 @@
+//! `data_converter apply-official-patch <patch folder> [--dry-run] [--force]`
+//!
+//! Overlays an official update on the data. The updates shipped as patch
+//! archives the client's updater unpacked over its `RLEs` and `DATAs`
+//! folders; their container layout is unknown (see `doc/formats.md`), so the
+//! patch is given as the folder the updater extracted, with the files laid
+//! out as in the client: `RLEs/obj.lst`, `RLEs/Chr/C00/chr00001.rle`,
+//! `DATAs/Map/map00012.rmm` and so on. The names are matched regardless of
+//! case, as the Windows client did.
+//!
+//! Nothing is written unless every file of the patch
+//!
+//! - goes into one of the folders or lists of the data the tools know,
+//! - parses, the lists with the format version the data uses,
+//! - and doesn't leave a list item without a resource that had one before,
+//!   as `xref` would report it; `--force` applies the patch anyway.
+//!
+//! The files the patch replaces are kept in
+//! `<OUTPUT_PATH>/patch-backup/<patch folder name>/` first. A file already
+//! backed up there is kept as it is, so applying a patch twice doesn't lose
+//! the original.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::fs::read_dir;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use core_compat::entity::list::List;
+use core_compat::entity::rmd_type::RmdType;
+use core_compat::parser::lst::parse_lst;
+use core_compat::parser::rle::{parse_rle_lenient, PixelFormat};
+use core_compat::parser::rmd::parse_rmd;
+use core_compat::parser::rmm::parse_rmm;
+use core_compat::parser::snd::parse_snd;
+
+use crate::doctor::list_version;
+use crate::error::Error;
+use crate::xref::{cross_reference, Problem, XrefRow};
+use super::{OUTPUT_PATH, RLE_ENTRIES, RMD_ENTRIES, RMM_ENTRY, SND_ENTRY};
+use super::{file_number, load_list_data, load_rle_folder};
+
+static USAGE: &str = "usage: apply-official-patch <patch folder> [--dry-run] [--force]";
+
+/// The folder all the paths of `RLE_ENTRIES` and the others are in
+const DATA_PATH: &str = "../data";
+
+/// What a file of the patch is, by where it goes
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Kind {
+    /// An RLE file, of the list with this short name
+    Rle(&'static str),
+    /// The list with this short name, and whether it's a version 1.2 one
+    List(&'static str, bool),
+    Rmd(RmdType),
+    Map,
+    Sound,
+    SoundList,
+}
+
+#[derive(Debug, PartialEq)]
+struct PatchFile {
+    source: PathBuf,
+    /// Where it goes, relative to the data folder
+    target: PathBuf,
+    kind: Kind,
+}
+
+pub fn apply_official_patch(args: &[String]) -> Result<(), Error> {
+    let mut patch = None;
+    let (mut dry_run, mut force) = (false, false);
+    for arg in args.iter() {
+        match arg.as_str() {
+            "--dry-run" => dry_run = true,
+            "--force" => force = true,
+            _ if patch.is_none() && !arg.starts_with("--") => patch = Some(Path::new(arg)),
+            _ => return Err(Error::Args(USAGE.into())),
+        }
+    }
+    let patch = patch.ok_or_else(|| Error::Args(USAGE.into()))?;
+    let data = Path::new(DATA_PATH);
+
+    let files = plan(patch, data)?;
+    println!("{:?}: {} files to apply", patch, files.len());
+    let missing = new_missing(data, &files)?;
+    if !missing.is_empty() {
+        if !force {
+            return Err(Error::Validation(missing));
+        }
+        for problem in missing.iter() {
+            println!("{} (--force)", problem);
+        }
+    }
+    if dry_run {
+        for file in files.iter() {
+            println!("{:?} -> {:?}", file.source, data.join(&file.target));
+        }
+        println!("the patch applies; nothing written (--dry-run)");
+        return Ok(());
+    }
+
+    let name = patch.file_name().map_or_else(|| "patch".into(), |name| name.to_string_lossy().into_owned());
+    let backup = Path::new(OUTPUT_PATH).join("patch-backup").join(name);
+    let replaced = apply(&files, data, &backup)?;
+    println!("applied {} files, {} of them replacing ones backed up in {:?}", files.len(), replaced, backup);
+    Ok(())
+}
+
+/// Finds where every file of the extracted `patch` goes in `data` and checks
+/// that it parses; fails with all the files that don't.
+fn plan(patch: &Path, data: &Path) -> Result<Vec<PatchFile>, Error> {
+    let mut sources = Vec::new();
+    files_in(patch, &mut sources)?;
+    sources.sort();
+
+    let mut files = Vec::new();
+    let mut problems = Vec::new();
+    for source in sources {
+        let relative = source.strip_prefix(patch).unwrap_or(&source);
+        let (target, kind) = match target(data, relative) {
+            Some(target) => target,
+            None => {
+                problems.push(format!("{:?}: not in a folder of the data", source));
+                continue;
+            }
+        };
+        let mut bytes = Vec::new();
+        File::open(&source)?.read_to_end(&mut bytes)?;
+        match check(&target, kind, &bytes) {
+            Ok(()) => files.push(PatchFile { source, target, kind }),
+            Err(problem) => problems.push(format!("{:?}: {}", source, problem)),
+        }
+    }
+    if problems.is_empty() {
+        Ok(files)
+    } else {
+        Err(Error::Validation(problems))
+    }
+}
+
+/// Every file in `folder` and the folders in it
+fn files_in(folder: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for entry in read_dir(folder)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files_in(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// A path of the data, relative to its folder
+fn relative(path: &'static str) -> &'static Path {
+    let path = Path::new(path);
+    path.strip_prefix(DATA_PATH).unwrap_or(path)
+}
+
+/// `path` lower case with `/` between its parts, to compare the paths of a
+/// patch made on Windows
+fn key(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy().to_lowercase()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Where the file at `path` in the patch goes in `data`, spelled as there,
+/// and what it is
+fn target(data: &Path, path: &Path) -> Option<(PathBuf, Kind)> {
+    let mut lists: Vec<(&Path, Kind)> = RLE_ENTRIES.iter()
+        .map(|&(_, short, _, list, use_v2)| (relative(list), Kind::List(short, use_v2)))
+        .collect();
+    lists.push((relative(SND_ENTRY.1), Kind::SoundList));
+    if let Some(&(list, kind)) = lists.iter().find(|&&(list, _)| key(list) == key(path)) {
+        return Some((list.to_path_buf(), kind));
+    }
+
+    let mut folders: Vec<(&Path, Kind)> = RLE_ENTRIES.iter()
+        .map(|&(_, short, folder, _, _)| (relative(folder), Kind::Rle(short)))
+        .collect();
+    folders.extend(RMD_ENTRIES.iter().map(|&(_, _, folder, kind)| (relative(folder), Kind::Rmd(kind))));
+    folders.push((relative(RMM_ENTRY.1), Kind::Map));
+    folders.push((relative(SND_ENTRY.0), Kind::Sound));
+    let parent = key(path.parent()?);
+    let &(folder, kind) = folders.iter().find(|&&(folder, _)| key(folder) == parent)?;
+
+    // the data's own spelling of a file the patch replaces
+    let name = path.file_name()?;
+    let name = read_dir(data.join(folder)).ok()
+        .and_then(|entries| entries.filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name())
+            .find(|existing| existing.to_string_lossy().to_lowercase() == name.to_string_lossy().to_lowercase()))
+        .unwrap_or_else(|| name.to_os_string());
+    Some((folder.join(name), kind))
+}
+
+/// Whether the file going to `target` parses as what it is
+fn check(target: &Path, kind: Kind, bytes: &[u8]) -> Result<(), String> {
+    match kind {
+        Kind::Rle(_) => {
+            let file = parse_rle_lenient(file_number(target), bytes, PixelFormat::Rgba8)
+                .map_err(|error| format!("{:?}", error))?;
+            for failure in file.failures.iter() {
+                println!("{:?}: resource {} doesn't decode: {:?}", target, failure.index, failure.reason);
+            }
+        }
+        Kind::List(_, use_v2) => {
+            let expected = if use_v2 { "1.2" } else { "1.0" };
+            match list_version(bytes) {
+                Some(ref version) if version == expected => (),
+                Some(version) => return Err(format!("list version {:?}, the data's is {}", version, expected)),
+                None => return Err("not a list file".into()),
+            }
+            parse_lst(bytes, use_v2).map_err(|error| format!("{:?}", error))?;
+        }
+        Kind::SoundList => { parse_lst(bytes, false).map_err(|error| format!("{:?}", error))?; },
+        Kind::Rmd(kind) => { parse_rmd(kind, bytes).map_err(|error| format!("{:?}", error))?; },
+        Kind::Map => { parse_rmm(bytes).map_err(|error| format!("{:?}", error))?; },
+        Kind::Sound => {
+            if parse_snd(bytes).is_empty() {
+                return Err("no sound in it".into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The list items the patch leaves without a resource that had one before,
+/// as `xref` reports them
+fn new_missing(data: &Path, files: &[PatchFile]) -> Result<Vec<String>, Error> {
+    let mut problems = Vec::new();
+    for &(_, short, folder, list_path, use_v2) in RLE_ENTRIES.iter() {
+        let patched: Vec<&PatchFile> = files.iter()
+            .filter(|file| match file.kind {
+                Kind::Rle(list) | Kind::List(list, _) => list == short,
+                _ => false,
+            })
+            .collect();
+        if patched.is_empty() {
+            continue;
+        }
+
+        let (list_path, folder) = (data.join(relative(list_path)), data.join(relative(folder)));
+        let list = if list_path.exists() { load_list_data(&list_path, use_v2)? } else { List::new() };
+        let mut resource_files = if folder.exists() {
+            load_rle_folder(&folder.to_string_lossy())?
+        } else {
+            HashMap::new()
+        };
+        let before = missing(cross_reference(short, &list, &resource_files));
+
+        let mut patched_list = list;
+        for file in patched.iter() {
+            match file.kind {
+                Kind::List(..) => patched_list = load_list_data(&file.source, use_v2)?,
+                _ => {
+                    let mut bytes = Vec::new();
+                    File::open(&file.source)?.read_to_end(&mut bytes)?;
+                    let number = file_number(&file.target);
+                    resource_files.insert(number, parse_rle_lenient(number, &bytes, PixelFormat::Rgba8)?);
+                }
+            }
+        }
+        for row in missing(cross_reference(short, &patched_list, &resource_files)) {
+            if !before.contains(&row) {
+                let (id, name) = row.item.clone().unwrap_or_default();
+                problems.push(format!("{}: item {} `{}` points at {}:{}, {}", short, id, name,
+                                      row.entry.file(), row.entry.index(), row.detail));
+            }
+        }
+    }
+    Ok(problems)
+}
+
+fn missing(rows: Vec<XrefRow>) -> Vec<XrefRow> {
+    rows.into_iter().filter(|row| row.problem == Problem::Missing).collect()
+}
+
+/// Copies the files of the patch into `data`, after the ones they replace
+/// into `backup`; returns how many were replaced
+fn apply(files: &[PatchFile], data: &Path, backup: &Path) -> Result<usize, Error> {
+    let mut replaced = 0;
+    for file in files.iter() {
+        let target = data.join(&file.target);
+        if target.exists() {
+            let kept = backup.join(&file.target);
+            if !kept.exists() {
+                std::fs::create_dir_all(kept.parent().unwrap())?;
+                std::fs::copy(&target, &kept)?;
+            }
+            replaced += 1;
+        }
+        std::fs::create_dir_all(target.parent().unwrap())?;
+        std::fs::copy(&file.source, &target)?;
+    }
+    Ok(replaced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{create_dir_all, read, remove_dir_all, write};
+
+    use core_compat::entity::entry::Entry;
+    use core_compat::fixture::{LstFixture, RleFixture, ResourceFixture};
+
+    fn rle(resources: usize) -> Vec<u8> {
+        (0..resources).fold(RleFixture::new(), |rle, _| rle.resource(ResourceFixture::new(1, 1).pixels(&[0xF800])))
+            .build()
+    }
+
+    fn obj_list(items: &[(u32, Entry)]) -> Vec<u8> {
+        items.iter().fold(LstFixture::new("1.2"), |list, &(id, entry)| list.item("obj", id, entry)).build()
+    }
+
+    /// A data folder with one object in `obj00001.rle`, and a patch folder
+    fn folders(name: &str) -> (PathBuf, PathBuf) {
+        let root = std::env::temp_dir().join(format!("novluno_test_patch_{}", name));
+        let _ = remove_dir_all(&root);
+        let (data, patch) = (root.join("data"), root.join("patch"));
+        create_dir_all(data.join("RLEs/Obj")).unwrap();
+        write(data.join("RLEs/obj.lst"), obj_list(&[(1, Entry::new(1, 0))])).unwrap();
+        write(data.join("RLEs/Obj/obj00001.rle"), rle(1)).unwrap();
+        create_dir_all(patch.join("rles/obj")).unwrap();
+        (data, patch)
+    }
+
+    #[test]
+    fn test_target() {
+        let (data, _) = folders("target");
+        let target = |path: &str| super::target(&data, Path::new(path));
+        assert_eq!(target("RLEs/obj.lst"), Some((PathBuf::from("RLEs/obj.lst"), Kind::List("obj", true))));
+        // spelled as in the data
+        assert_eq!(target("rles/OBJ/OBJ00001.RLE"), Some((PathBuf::from("RLEs/Obj/obj00001.rle"), Kind::Rle("obj"))));
+        assert_eq!(target("RLEs/Chr/C03/chr00002.rle"), Some((PathBuf::from("RLEs/Chr/C03/chr00002.rle"), Kind::Rle("ch3"))));
+        assert_eq!(target("DATAs/Chr/chr00002.rmd"), Some((PathBuf::from("DATAs/Chr/chr00002.rmd"), Kind::Rmd(RmdType::Character))));
+        assert_eq!(target("DATAs/Map/map00012.rmm"), Some((PathBuf::from("DATAs/Map/map00012.rmm"), Kind::Map)));
+        assert_eq!(target("RLEs/snd.lst"), Some((PathBuf::from("RLEs/snd.lst"), Kind::SoundList)));
+        assert_eq!(target("readme.txt"), None);
+        assert_eq!(target("RLEs/Obj/More/obj00001.rle"), None);
+    }
+
+    #[test]
+    fn test_apply_official_patch() {
+        let (data, patch) = folders("apply");
+        // a second object, in a new file
+        write(patch.join("rles/OBJ.LST"), obj_list(&[(1, Entry::new(1, 0)), (2, Entry::new(2, 0))])).unwrap();
+        write(patch.join("rles/obj/obj00002.rle"), rle(1)).unwrap();
+        let files = plan(&patch, &data).unwrap();
+        assert_eq!(files.iter().map(|file| (file.target.clone(), file.kind)).collect::<Vec<_>>(), vec![
+            (PathBuf::from("RLEs/obj.lst"), Kind::List("obj", true)),
+            (PathBuf::from("RLEs/Obj/obj00002.rle"), Kind::Rle("obj")),
+        ]);
+        assert!(new_missing(&data, &files).unwrap().is_empty());
+
+        let original = read(data.join("RLEs/obj.lst")).unwrap();
+        let backup = data.parent().unwrap().join("backup");
+        assert_eq!(apply(&files, &data, &backup).unwrap(), 1);
+        assert_eq!(read(data.join("RLEs/obj.lst")).unwrap(), read(patch.join("rles/OBJ.LST")).unwrap());
+        assert!(data.join("RLEs/Obj/obj00002.rle").exists());
+        // a second time keeps the original
+        apply(&files, &data, &backup).unwrap();
+        assert_eq!(read(backup.join("RLEs/obj.lst")).unwrap(), original);
+        let _ = remove_dir_all(data.parent().unwrap());
+    }
+
+    #[test]
+    fn test_apply_official_patch_problems() {
+        let (data, patch) = folders("problems");
+        write(patch.join("notes.txt"), b"hello").unwrap();
+        write(patch.join("rles/obj/obj00002.rle"), b"not an rle").unwrap();
+        write(patch.join("rles/obj.lst"), LstFixture::new("1.0").build()).unwrap();
+        match plan(&patch, &data) {
+            Err(Error::Validation(problems)) => {
+                assert_eq!(problems.len(), 3);
+                let found = |problem: &str| problems.iter().any(|p| p.contains(problem));
+                assert!(found("notes.txt\": not in a folder of the data"));
+                assert!(found("obj00002.rle\": MissingRleIdentifier"));
+                assert!(found("obj.lst\": list version \"1.0\", the data's is 1.2"));
+            }
+            other => panic!("{:?}", other),
+        }
+        let _ = remove_dir_all(data.parent().unwrap());
+
+        // the object's resource goes away
+        let (data, patch) = folders("missing");
+        write(patch.join("rles/obj/obj00001.rle"), RleFixture::new().null().build()).unwrap();
+        let files = plan(&patch, &data).unwrap();
+        assert_eq!(new_missing(&data, &files).unwrap(), vec!["obj: item 1 `obj` points at 1:0, empty slot".to_string()]);
+        let _ = remove_dir_all(data.parent().unwrap());
+    }
+}
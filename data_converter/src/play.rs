@@ -0,0 +1,115 @@
+//! `data_converter play --sound <id> [--loop] [--volume <v>]`
+//!
+//! Plays a sound from `snd.lst` straight from the client data, to check the
+//! sound extraction by ear without exporting files first.
+//!
+//! Playback links rodio and the system's audio libraries, so it is behind the
+//! `sound` feature: `cargo run --features sound -- play --sound 12`. Without
+//! it the sound is only looked up and described.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use core_compat::parser::snd::parse_snd;
+
+use crate::error::Error;
+use super::SND_ENTRY;
+use super::{find_rle_file, load_list_data};
+
+static USAGE: &str = "usage: play --sound <id> [--loop] [--volume <v>]";
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    id: u32,
+    looped: bool,
+    /// 1.0 is the original volume
+    volume: f32,
+}
+
+pub fn play(args: &[String]) -> Result<(), Error> {
+    let options = parse_options(args)?;
+    let (folder, list_path) = SND_ENTRY;
+
+    let list = load_list_data(Path::new(list_path), false)?;
+    let item = list.items.iter().find(|item| item.id == options.id)
+        .ok_or_else(|| Error::Validation(vec![format!("no sound with id {}", options.id)]))?;
+    let path = find_rle_file(folder, item.entry.file())?
+        .ok_or_else(|| Error::Validation(vec![format!("no sound file {} in {:?}", item.entry.file(), folder)]))?;
+    let mut data = Vec::new();
+    File::open(&path)?.read_to_end(&mut data)?;
+
+    let waves = parse_snd(&data);
+    let wave = waves.get(item.entry.index() as usize)
+        .ok_or_else(|| Error::Validation(vec![format!(
+            "{:?} holds {} sounds, there is no sound {}", path, waves.len(), item.entry.index())]))?;
+    println!("{} ({}): {} Hz, {} channels, {} bit, {} ms", item.name, item.id,
+             wave.sample_rate, wave.channels, wave.bits_per_sample, wave.duration_ms());
+
+    play_wave(wave.data, &options)
+}
+
+fn parse_options(args: &[String]) -> Result<Options, Error> {
+    let mut id = None;
+    let mut looped = false;
+    let mut volume = 1.0;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--sound" => id = iter.next().and_then(|id| id.parse().ok()),
+            "--loop" => looped = true,
+            "--volume" => volume = iter.next().and_then(|v| v.parse().ok())
+                .filter(|v: &f32| *v >= 0.0)
+                .ok_or_else(|| Error::Args(USAGE.into()))?,
+            _ => return Err(Error::Args(USAGE.into())),
+        }
+    }
+    let id = id.ok_or_else(|| Error::Args(USAGE.into()))?;
+    Ok(Options { id, looped, volume })
+}
+
+#[cfg(feature = "sound")]
+fn play_wave(data: &[u8], options: &Options) -> Result<(), Error> {
+    use std::io::Cursor;
+    use rodio::Source;
+
+    let audio_error = |e: &dyn std::fmt::Display| Error::Validation(vec![format!("can't play the sound: {}", e)]);
+    let output = rodio::DeviceSinkBuilder::open_default_sink().map_err(|e| audio_error(&e))?;
+    let player = rodio::Player::connect_new(output.mixer());
+    player.set_volume(options.volume);
+    let source = rodio::Decoder::new(Cursor::new(data.to_vec())).map_err(|e| audio_error(&e))?;
+    if options.looped {
+        println!("looping, stop with ctrl-c");
+        player.append(source.repeat_infinite());
+    } else {
+        player.append(source);
+    }
+    player.sleep_until_end();
+    Ok(())
+}
+
+#[cfg(not(feature = "sound"))]
+fn play_wave(_data: &[u8], _options: &Options) -> Result<(), Error> {
+    Err(Error::Validation(vec![
+        "built without playback, run with `cargo run --features sound -- play ..`".into()]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|&a| a.into()).collect()
+    }
+
+    #[test]
+    fn test_parse_options() {
+        assert_eq!(parse_options(&args(&["--sound", "12"])).unwrap(),
+                   Options { id: 12, looped: false, volume: 1.0 });
+        assert_eq!(parse_options(&args(&["--loop", "--sound", "3", "--volume", "0.5"])).unwrap(),
+                   Options { id: 3, looped: true, volume: 0.5 });
+        assert!(parse_options(&args(&["--loop"])).is_err());
+        assert!(parse_options(&args(&["--sound", "1", "--volume", "-1"])).is_err());
+        assert!(parse_options(&args(&["--sound", "x"])).is_err());
+    }
+}
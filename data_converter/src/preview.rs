@@ -0,0 +1,196 @@
+//! `data_converter preview <list> <rmd file> <animation> -o <out.png> [--variant <n>] [--delay <ms>] [--clean-edges]`
+//!
+//! Renders an animation of a character RMD file as an animated PNG, for
+//! character creators and web tools. Every frame is an entry of the file,
+//! composed the way `ora` composes one, and all the frames share a canvas so
+//! the character stays in place. `--variant` shows the n-th sprite of the
+//! images listing several (e.g. the weapons), the first one of those that
+//! list fewer. Frames last `--delay` milliseconds, 100 by default.
+//! `--clean-edges` cleans the sprites up the way `ora` does.
+//!
+//! An animated PNG is a PNG whose first frame is the plain image; the frame
+//! control (`fcTL`) and frame data (`fdAT`) chunks only APNG viewers read
+//! hold the others.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use byteorder::{BigEndian as BE, ByteOrder, WriteBytesExt};
+
+use core_compat::entity::resource_file::ResourceFile;
+use core_compat::entity::rmd_type::RmdType;
+
+use crate::error::Error;
+use crate::ora::{bounds, clean_edges, composite, crc32, entry_layers, load_entry_files};
+use super::{RLE_ENTRIES, RMD_ENTRIES};
+use super::{encode_png, load_list_data, load_rmd_data};
+
+static USAGE: &str = "usage: preview <list> <rmd file> <animation> -o <out.png> [--variant <n>] [--delay <ms>] [--clean-edges]";
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+pub fn preview(args: &[String]) -> Result<(), Error> {
+    let mut positional = Vec::new();
+    let (mut output, mut variant, mut delay, mut clean) = (None, 0usize, 100u16, false);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" => output = iter.next(),
+            "--variant" => variant = iter.next().and_then(|n| n.parse().ok()).ok_or_else(|| Error::Args(USAGE.into()))?,
+            "--delay" => delay = iter.next().and_then(|ms| ms.parse().ok()).ok_or_else(|| Error::Args(USAGE.into()))?,
+            "--clean-edges" => clean = true,
+            _ => positional.push(arg),
+        }
+    }
+    let (short, rmd_file, animation, output) = match (positional.as_slice(), output) {
+        (&[short, rmd_file, animation], Some(output)) => match (rmd_file.parse::<u32>(), animation.parse::<usize>()) {
+            (Ok(rmd_file), Ok(animation)) => (short, rmd_file, animation, output),
+            _ => return Err(Error::Args(USAGE.into())),
+        },
+        _ => return Err(Error::Args(USAGE.into())),
+    };
+
+    let &(_, _, folder, list_path, use_v2) = RLE_ENTRIES.iter()
+        .find(|e| e.1 == short.as_str())
+        .ok_or_else(|| Error::Args(format!("unknown list `{}`", short)))?;
+    let list = load_list_data(Path::new(list_path), use_v2)?;
+    let &(_, chr_short, chr_dir, _) = RMD_ENTRIES.iter().find(|e| e.3 == RmdType::Character).unwrap();
+    let rmd_path = Path::new(chr_dir).join(format!("{}{:05}.rmd", chr_short, rmd_file));
+    let rmd = load_rmd_data(&rmd_path, RmdType::Character)?;
+    let frames = rmd.animations().get(animation)
+        .ok_or_else(|| Error::Validation(vec![format!("no animation {} in {:?}", animation, rmd_path)]))?
+        .frames();
+
+    let mut problems = Vec::new();
+    let mut entries = Vec::new();
+    for &frame in frames {
+        match rmd.get_entry(frame as usize) {
+            Some(entry) => entries.push(entry),
+            None => problems.push(format!("no entry {} in {:?}", frame, rmd_path)),
+        }
+    }
+    let mut files: HashMap<u32, ResourceFile> = HashMap::new();
+    for entry in entries.iter() {
+        load_entry_files(entry, &list, short, folder, &mut files, &mut problems)?;
+    }
+    if clean {
+        clean_edges(&mut files)?;
+    }
+    let layers: Vec<_> = entries.iter()
+        .map(|entry| entry_layers(entry, &list, short, &files, variant, &mut problems))
+        .collect();
+    if !problems.is_empty() {
+        return Err(Error::Validation(problems));
+    }
+    if layers.is_empty() {
+        return Err(Error::Validation(vec![format!("animation {} of {:?} has no frames", animation, rmd_path)]));
+    }
+
+    let all: Vec<_> = layers.iter().flat_map(|frame| frame.iter().filter(|l| l.visible)).cloned().collect();
+    let (left, top, width, height) = bounds(&all);
+    let rgba: Vec<Vec<u8>> = layers.iter().map(|frame| composite(frame, left, top, width, height)).collect();
+    File::create(output)?.write_all(&write_apng(width, height, &rgba, delay)?)?;
+    println!("wrote {} frames of {}x{} to {}", rgba.len(), width, height, output);
+    Ok(())
+}
+
+/// An animated PNG of RGBA frames of the same size, looping forever
+pub fn write_apng(width: u32, height: u32, frames: &[Vec<u8>], delay_ms: u16) -> Result<Vec<u8>, Error> {
+    let mut out = PNG_SIGNATURE.to_vec();
+    let mut sequence = 0u32;
+    for (index, frame) in frames.iter().enumerate() {
+        let png = encode_png(width, height, frame)?;
+        let chunks = chunks(&png);
+        if index == 0 {
+            for &(kind, data) in chunks.iter().filter(|&&(kind, _)| kind == b"IHDR") {
+                write_chunk(&mut out, kind, data);
+            }
+            let mut animation = Vec::new();
+            animation.write_u32::<BE>(frames.len() as u32)?;
+            animation.write_u32::<BE>(0)?; // plays: forever
+            write_chunk(&mut out, b"acTL", &animation);
+        }
+
+        let mut control = Vec::new();
+        control.write_u32::<BE>(sequence)?;
+        control.write_u32::<BE>(width)?;
+        control.write_u32::<BE>(height)?;
+        control.write_u32::<BE>(0)?; // x
+        control.write_u32::<BE>(0)?; // y
+        control.write_u16::<BE>(delay_ms)?;
+        control.write_u16::<BE>(1000)?;
+        control.push(1); // cleared before the next frame
+        control.push(0); // replacing what's there
+        write_chunk(&mut out, b"fcTL", &control);
+        sequence += 1;
+
+        for &(_, data) in chunks.iter().filter(|&&(kind, _)| kind == b"IDAT") {
+            if index == 0 {
+                write_chunk(&mut out, b"IDAT", data);
+            } else {
+                let mut frame_data = Vec::with_capacity(4 + data.len());
+                frame_data.write_u32::<BE>(sequence)?;
+                frame_data.extend_from_slice(data);
+                write_chunk(&mut out, b"fdAT", &frame_data);
+                sequence += 1;
+            }
+        }
+    }
+    write_chunk(&mut out, b"IEND", &[]);
+    Ok(out)
+}
+
+/// The chunks of a PNG file, by type
+fn chunks(png: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut chunks = Vec::new();
+    let mut at = PNG_SIGNATURE.len();
+    while at + 12 <= png.len() {
+        let len = BE::read_u32(&png[at..at + 4]) as usize;
+        if at + 12 + len > png.len() {
+            break;
+        }
+        chunks.push((&png[at + 4..at + 8], &png[at + 8..at + 8 + len]));
+        at += 12 + len;
+    }
+    chunks
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+    out.write_u32::<BE>(data.len() as u32).unwrap();
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.write_u32::<BE>(crc).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_apng() {
+        let red = vec![0xFF, 0, 0, 0xFF];
+        let blue = vec![0, 0, 0xFF, 0xFF];
+        let apng = write_apng(1, 1, &[red, blue.clone(), blue], 80).unwrap();
+        assert!(apng.starts_with(PNG_SIGNATURE));
+
+        let chunks = chunks(&apng);
+        let kinds: Vec<&[u8]> = chunks.iter().map(|&(kind, _)| kind).collect();
+        assert_eq!(kinds, vec![&b"IHDR"[..], b"acTL", b"fcTL", b"IDAT", b"fcTL", b"fdAT", b"fcTL", b"fdAT", b"IEND"]);
+        assert_eq!(chunks[1].1, &[0, 0, 0, 3, 0, 0, 0, 0]);
+        // the sequence numbers count the fcTL and fdAT chunks, 80/1000 s a frame
+        assert_eq!(&chunks[6].1[..4], &[0, 0, 0, 3]);
+        assert_eq!(&chunks[7].1[..4], &[0, 0, 0, 4]);
+        assert_eq!(&chunks[6].1[20..24], &[0, 80, 3, 0xE8]);
+        // and every checksum holds
+        let mut at = PNG_SIGNATURE.len();
+        for &(kind, data) in chunks.iter() {
+            let crc = BE::read_u32(&apng[at + 8 + data.len()..]);
+            assert_eq!(crc, crc32(&apng[at + 4..at + 8 + data.len()]), "{:?}", kind);
+            at += 12 + data.len();
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! `data_converter provenance --db <rm.sqlite> <gid>..`
+//!
+//! Where the derived sprites of the `rle2sqlite` database come from. A tool
+//! adding a sprite made from another one records a step in the `provenance`
+//! table: the gid of the new sprite, the gid of its source, the operation
+//! and its parameters. Following the steps back from a gid ends at the
+//! original resource, and replaying them from there regenerates the sprite.
+//!
+//! The parameters are written so the operation can read them back; for
+//! `recolor` they are the fields of a `[[rule]]` without its `gids`.
+//!
+//! The command prints the chain of every gid, newest step first. Needs the
+//! `sqlite` feature.
+
+#[cfg(feature = "sqlite")]
+use std::collections::HashSet;
+use std::path::Path;
+
+#[cfg(feature = "sqlite")]
+use core_compat::utility::lock::ReadLock;
+
+use crate::error::Error;
+
+static USAGE: &str = "usage: provenance --db <rm.sqlite> <gid>..";
+
+/// Made by the tools writing to the database, in the same transaction as
+/// the sprites
+pub static SCHEMA: &str = "CREATE TABLE IF NOT EXISTS provenance (
+    gid        INTEGER PRIMARY KEY,
+    source_gid INTEGER NOT NULL,
+    operation  TEXT NOT NULL,
+    parameters TEXT NOT NULL
+)";
+
+/// The sprite `gid` was made from `source` by `operation`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub gid: i64,
+    pub source: i64,
+    pub operation: String,
+    pub parameters: String,
+}
+
+pub fn provenance(args: &[String]) -> Result<(), Error> {
+    let (mut db, mut gids) = (None, Vec::new());
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--db" => db = iter.next(),
+            _ => gids.push(arg.parse::<i64>().map_err(|_| Error::Args(USAGE.into()))?),
+        }
+    }
+    let db = match db {
+        Some(db) if !gids.is_empty() => db,
+        _ => return Err(Error::Args(USAGE.into())),
+    };
+
+    for (gid, steps) in gids.iter().zip(load_chains(Path::new(db), &gids)?) {
+        match steps.last() {
+            None => println!("gid {}: original", gid),
+            Some(first) => {
+                println!("gid {}: derived from the original gid {}", gid, first.source);
+                for step in steps.iter() {
+                    println!("  gid {} <- {} of gid {}", step.gid, step.operation, step.source);
+                    for line in step.parameters.lines() {
+                        println!("      {}", line);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The steps that made `gid`, newest first, as found by `lookup`; the last
+/// one's source is the original. A chain going round in circles is an error.
+pub fn chain<F>(gid: i64, mut lookup: F) -> Result<Vec<Step>, Error>
+    where F: FnMut(i64) -> Result<Option<Step>, Error>
+{
+    let mut steps: Vec<Step> = Vec::new();
+    let mut at = gid;
+    while let Some(step) = lookup(at)? {
+        if step.source == gid || steps.iter().any(|s| s.gid == step.source) {
+            return Err(Error::Validation(vec![format!("the provenance of gid {} goes round through gid {}", gid, step.source)]));
+        }
+        at = step.source;
+        steps.push(step);
+    }
+    Ok(steps)
+}
+
+/// Records the steps, creating the table if the database predates it
+#[cfg(feature = "sqlite")]
+pub fn record(tx: &sql::Transaction, steps: &[Step]) -> Result<(), Error> {
+    tx.execute_batch(SCHEMA)?;
+    let mut statement = tx.prepare(
+        "INSERT OR REPLACE INTO provenance (gid, source_gid, operation, parameters) VALUES (?1, ?2, ?3, ?4)")?;
+    for step in steps.iter() {
+        statement.execute(sql::params![step.gid, step.source, &step.operation, &step.parameters])?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+fn load_chains(path: &Path, gids: &[i64]) -> Result<Vec<Vec<Step>>, Error> {
+    let _read = ReadLock::acquire(path)?;
+    let connection = sql::Connection::open_with_flags(path, sql::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let tables: HashSet<String> = connection.prepare("SELECT name FROM sqlite_master WHERE type = 'table'")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    // nothing was derived yet
+    if !tables.contains("provenance") {
+        return Ok(gids.iter().map(|_| Vec::new()).collect());
+    }
+    let mut statement = connection.prepare(
+        "SELECT source_gid, operation, parameters FROM provenance WHERE gid = ?1")?;
+    gids.iter()
+        .map(|&gid| chain(gid, |at| {
+            let mut rows = statement.query([at])?;
+            match rows.next()? {
+                Some(row) => Ok(Some(Step { gid: at, source: row.get(0)?, operation: row.get(1)?, parameters: row.get(2)? })),
+                None => Ok(None),
+            }
+        }))
+        .collect()
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn load_chains(_path: &Path, _gids: &[i64]) -> Result<Vec<Vec<Step>>, Error> {
+    Err(Error::Args("provenance needs data_converter built with the `sqlite` feature".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(gid: i64, source: i64) -> Step {
+        Step { gid, source, operation: "recolor".into(), parameters: format!("hue = {}", gid) }
+    }
+
+    #[test]
+    fn test_chain() {
+        let steps = [step(30, 20), step(20, 10), step(41, 40), step(40, 41)];
+        let lookup = |gid| Ok(steps.iter().find(|s| s.gid == gid).cloned());
+        assert_eq!(chain(30, lookup).unwrap(), vec![step(30, 20), step(20, 10)]);
+        assert_eq!(chain(10, lookup).unwrap(), Vec::new());
+        match chain(41, lookup) {
+            Err(Error::Validation(problems)) => assert_eq!(problems, vec!["the provenance of gid 41 goes round through gid 41"]),
+            other => panic!("{:?}", other),
+        }
+    }
+}
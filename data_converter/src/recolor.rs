@@ -0,0 +1,473 @@
+//! `data_converter recolor <rules> --db <rm.sqlite> --file-number <n> [--insert] [-o <out.rle>]`
+//!
+//! Makes recolored copies of sprites of the `rle2sqlite` database in bulk, for
+//! mods: a slime of every color from the green one. Needs the `sqlite`
+//! feature.
+//!
+//! The rules are a TOML file with a `[[rule]]` table for every recoloring:
+//!
+//! ```toml
+//! [[rule]]
+//! name = "blue slime"   # shown in the report, optional
+//! gids = [120, 121, 122]
+//! hue = 180             # degrees to turn the hue by
+//! saturation = 1.2      # times the saturation, 1 by default
+//! lightness = 0.9       # times the lightness, 1 by default
+//! # exact colors to replace, before anything is turned
+//! palette = ["#30C030:#3030C0", "#208020:#202080"]
+//! ```
+//!
+//! Only that much of TOML is read; see the `toml` module.
+//!
+//! Every rule makes a copy of each of its sprites, in order, which together
+//! are a new RLE file numbered `--file-number`. `-o` writes that file,
+//! `--insert` adds the copies to the database under new gids. Transparent
+//! pixels are left alone. All the sprites are checked to be in the database
+//! before anything is written. The inserted copies have their source and
+//! rule recorded, see `provenance`.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+
+use core_compat::entity::resource::Resource;
+use core_compat::entity::resource_file::{ResourceFile, ResourceSlot};
+use core_compat::parser::rle::PixelFormat;
+#[cfg(feature = "sqlite")]
+use core_compat::utility::lock::{ReadLock, WriteLock};
+use core_compat::writer::rle::{encode_resource, write_rle};
+
+use crate::error::Error;
+#[cfg(feature = "sqlite")]
+use crate::provenance::{record, Step};
+use crate::toml::{parse_tables, Value};
+
+static USAGE: &str = "usage: recolor <rules> --db <rm.sqlite> --file-number <n> [--insert] [-o <out.rle>]";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub name: Option<String>,
+    pub gids: Vec<i64>,
+    /// Degrees to turn the hue by
+    pub hue: f32,
+    pub saturation: f32,
+    pub lightness: f32,
+    /// Colors replaced as they are, by the color they become
+    pub palette: Vec<([u8; 3], [u8; 3])>,
+}
+
+/// A decoded sprite of the database
+#[derive(Debug, Clone)]
+pub struct Source {
+    /// The short name of the list it's from
+    pub kind: String,
+    pub offset_x: i32,
+    pub offset_y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub rgba: Vec<u8>,
+}
+
+/// A recolored copy of the sprite with the gid `source`
+pub struct Recolored {
+    pub source: i64,
+    pub rule: usize,
+    pub kind: String,
+    pub resource: Resource,
+}
+
+pub fn recolor(args: &[String]) -> Result<(), Error> {
+    let (mut rules_path, mut db, mut file_number, mut insert, mut output) = (None, None, None, false, None);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--db" => db = iter.next(),
+            "--file-number" => file_number = iter.next().and_then(|n| n.parse::<u32>().ok()),
+            "--insert" => insert = true,
+            "-o" => output = iter.next(),
+            _ if rules_path.is_none() => rules_path = Some(arg),
+            _ => return Err(Error::Args(USAGE.into())),
+        }
+    }
+    let (rules_path, db, file_number) = match (rules_path, db, file_number) {
+        (Some(rules_path), Some(db), Some(file_number)) if insert || output.is_some() => (rules_path, db, file_number),
+        _ => return Err(Error::Args(USAGE.into())),
+    };
+
+    let mut text = String::new();
+    File::open(rules_path)?.read_to_string(&mut text)?;
+    let rules = parse_rules(&text)
+        .map_err(|problems| Error::Validation(problems.iter().map(|p| format!("{}:{}", rules_path, p)).collect()))?;
+    let gids: Vec<i64> = rules.iter().flat_map(|rule| rule.gids.iter().cloned()).collect();
+    let sources = load_sources(Path::new(db), &gids)?;
+    let missing: Vec<String> = gids.iter()
+        .filter(|gid| !sources.contains_key(gid))
+        .map(|gid| format!("no decoded sprite with gid {} in {}", gid, db))
+        .collect();
+    if !missing.is_empty() {
+        return Err(Error::Validation(missing));
+    }
+
+    let recolored = recolor_sources(&rules, &sources, file_number);
+    let file = resource_file(file_number, &recolored);
+    // encoded whether it's written or not, so nothing is inserted that can't be
+    let data = write_rle(&file, PixelFormat::Rgba8)?;
+    if let Some(output) = output {
+        File::create(output)?.write_all(&data)?;
+        println!("wrote {} sprites to {}", recolored.len(), output);
+    }
+    let new_gids = if insert { Some(insert_recolored(Path::new(db), &rules, &recolored)?) } else { None };
+    for (index, copy) in recolored.iter().enumerate() {
+        let rule = &rules[copy.rule];
+        let name = rule.name.clone().unwrap_or_else(|| format!("rule {}", copy.rule + 1));
+        match new_gids {
+            Some(ref gids) => println!("{}: gid {} -> {}:{}, gid {}", name, copy.source, file_number, index, gids[index]),
+            None => println!("{}: gid {} -> {}:{}", name, copy.source, file_number, index),
+        }
+    }
+    Ok(())
+}
+
+/// A copy of every sprite of every rule, recolored, as the resources of the
+/// file `file_number`
+pub fn recolor_sources(rules: &[Rule], sources: &BTreeMap<i64, Source>, file_number: u32) -> Vec<Recolored> {
+    let mut recolored = Vec::new();
+    for (number, rule) in rules.iter().enumerate() {
+        for gid in rule.gids.iter() {
+            let source = match sources.get(gid) {
+                Some(source) => source,
+                None => continue,
+            };
+            let mut resource = Resource::new();
+            resource.file_num = Some(file_number);
+            resource.set_index(recolored.len() as u32);
+            resource.offset_x = source.offset_x;
+            resource.offset_y = source.offset_y;
+            resource.width = source.width;
+            resource.height = source.height;
+            resource.image_raw = source.rgba.clone();
+            recolor_pixels(rule, &mut resource.image_raw);
+            if let Ok(encoded) = encode_resource(&resource, PixelFormat::Rgba8) {
+                resource.len = u32::from_le_bytes([encoded[0], encoded[1], encoded[2], encoded[3]]);
+            }
+            recolored.push(Recolored { source: *gid, rule: number, kind: source.kind.clone(), resource });
+        }
+    }
+    recolored
+}
+
+fn resource_file(file_number: u32, recolored: &[Recolored]) -> ResourceFile {
+    let mut file = ResourceFile::new();
+    file.file_number = file_number;
+    for (index, copy) in recolored.iter().enumerate() {
+        let mut resource = Resource::new();
+        resource.file_num = copy.resource.file_num;
+        resource.set_index(copy.resource.index());
+        resource.len = copy.resource.len;
+        resource.offset_x = copy.resource.offset_x;
+        resource.offset_y = copy.resource.offset_y;
+        resource.width = copy.resource.width;
+        resource.height = copy.resource.height;
+        resource.image_raw = copy.resource.image_raw.clone();
+        file.resources.push(resource);
+        file.slots.push(ResourceSlot::Resource(index));
+    }
+    file
+}
+
+/// The fields of the rule but its name and gids, as in a rule file; with a
+/// `gids` of the source they make the rule recoloring just it again
+pub fn rule_parameters(rule: &Rule) -> String {
+    let mut fields = format!("hue = {}\nsaturation = {}\nlightness = {}\n", rule.hue, rule.saturation, rule.lightness);
+    if !rule.palette.is_empty() {
+        let hex = |c: [u8; 3]| format!("#{:02X}{:02X}{:02X}", c[0], c[1], c[2]);
+        let colors: Vec<String> = rule.palette.iter().map(|&(from, to)| format!("\"{}:{}\"", hex(from), hex(to))).collect();
+        fields.push_str(&format!("palette = [{}]\n", colors.join(", ")));
+    }
+    fields
+}
+
+/// Applies `rule` to RGBA pixels
+pub fn recolor_pixels(rule: &Rule, rgba: &mut [u8]) {
+    let turned = rule.hue != 0.0 || rule.saturation != 1.0 || rule.lightness != 1.0;
+    for pixel in rgba.chunks_mut(4) {
+        if pixel[3] == 0 {
+            continue;
+        }
+        let color = [pixel[0], pixel[1], pixel[2]];
+        if let Some(&(_, to)) = rule.palette.iter().find(|&&(from, _)| from == color) {
+            pixel[..3].copy_from_slice(&to);
+        } else if turned {
+            let (hue, saturation, lightness) = rgb_to_hsl(color);
+            let hue = (hue + rule.hue).rem_euclid(360.0);
+            let saturation = (saturation * rule.saturation).clamp(0.0, 1.0);
+            let lightness = (lightness * rule.lightness).clamp(0.0, 1.0);
+            pixel[..3].copy_from_slice(&hsl_to_rgb(hue, saturation, lightness));
+        }
+    }
+}
+
+/// Hue in degrees, saturation and lightness from 0 to 1
+fn rgb_to_hsl(color: [u8; 3]) -> (f32, f32, f32) {
+    let [r, g, b] = color.map(|c| c as f32 / 255.0);
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    let lightness = (max + min) / 2.0;
+    let delta = max - min;
+    if delta == 0.0 {
+        return (0.0, 0.0, lightness);
+    }
+    let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+    let hue = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    (hue, saturation, lightness)
+}
+
+fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> [u8; 3] {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    [r, g, b].map(|c| ((c + m) * 255.0).round().clamp(0.0, 255.0) as u8)
+}
+
+/// The rules of a rule file, or what's wrong with it as `<line>: <problem>`
+pub fn parse_rules(text: &str) -> Result<Vec<Rule>, Vec<String>> {
+    let (tables, mut problems) = parse_tables(text, "rule");
+    let mut rules = Vec::new();
+    for table in tables {
+        match rule(&table.fields) {
+            Ok(rule) => rules.push(rule),
+            Err(problem) => problems.push(format!("{}: {}", table.line, problem)),
+        }
+    }
+    if problems.is_empty() {
+        Ok(rules)
+    } else {
+        Err(problems)
+    }
+}
+
+fn rule(fields: &BTreeMap<String, Value>) -> Result<Rule, String> {
+    let mut rule = Rule { name: None, gids: Vec::new(), hue: 0.0, saturation: 1.0, lightness: 1.0, palette: Vec::new() };
+    for (key, value) in fields.iter() {
+        match (key.as_str(), value) {
+            ("name", Value::Str(name)) => rule.name = Some(name.clone()),
+            ("gids", Value::Array(gids)) => {
+                for gid in gids.iter() {
+                    match *gid {
+                        Value::Number(gid) if gid.fract() == 0.0 && gid > 0.0 => rule.gids.push(gid as i64),
+                        _ => return Err("the gids have to be whole numbers".into()),
+                    }
+                }
+            }
+            ("hue", &Value::Number(hue)) => rule.hue = hue as f32,
+            ("saturation", &Value::Number(saturation)) if saturation >= 0.0 => rule.saturation = saturation as f32,
+            ("lightness", &Value::Number(lightness)) if lightness >= 0.0 => rule.lightness = lightness as f32,
+            ("palette", Value::Array(colors)) => {
+                for color in colors.iter() {
+                    let pair = match *color {
+                        Value::Str(ref pair) => pair.split_once(':').and_then(|(from, to)| Some((hex(from)?, hex(to)?))),
+                        _ => None,
+                    };
+                    match pair {
+                        Some(pair) => rule.palette.push(pair),
+                        None => return Err("the palette has to be `\"#RRGGBB:#RRGGBB\"` colors".into()),
+                    }
+                }
+            }
+            ("name", _) | ("gids", _) | ("hue", _) | ("saturation", _) | ("lightness", _) | ("palette", _) => {
+                return Err(format!("`{}` has the wrong kind of value", key));
+            }
+            _ => return Err(format!("unknown key `{}`", key)),
+        }
+    }
+    if rule.gids.is_empty() {
+        return Err("a rule needs `gids`".into());
+    }
+    Ok(rule)
+}
+
+/// `#RRGGBB`
+fn hex(color: &str) -> Option<[u8; 3]> {
+    let color = color.trim().strip_prefix('#')?;
+    if color.len() != 6 || !color.is_ascii() {
+        return None;
+    }
+    let channel = |at: usize| u8::from_str_radix(&color[at..at + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// The decoded sprites of the database with these gids
+#[cfg(feature = "sqlite")]
+fn load_sources(path: &Path, gids: &[i64]) -> Result<BTreeMap<i64, Source>, Error> {
+    let _read = ReadLock::acquire(path)?;
+    let connection = sql::Connection::open_with_flags(path, sql::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut statement = connection.prepare(
+        "SELECT type, offset_x, offset_y, width, height, image FROM rle WHERE gid = ?1")?;
+    let mut sources = BTreeMap::new();
+    for &gid in gids.iter() {
+        let mut rows = statement.query([gid])?;
+        if let Some(row) = rows.next()? {
+            let source = Source {
+                kind: row.get(0)?,
+                offset_x: row.get(1)?,
+                offset_y: row.get(2)?,
+                width: row.get(3)?,
+                height: row.get(4)?,
+                rgba: row.get::<_, Option<Vec<u8>>>(5)?.unwrap_or_default(),
+            };
+            // a sprite that was never decoded has no pixels
+            if source.width > 0 && source.height > 0
+                && source.rgba.len() == source.width as usize * source.height as usize * 4 {
+                sources.insert(gid, source);
+            }
+        }
+    }
+    Ok(sources)
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn load_sources(_path: &Path, _gids: &[i64]) -> Result<BTreeMap<i64, Source>, Error> {
+    Err(Error::Args("recolor needs data_converter built with the `sqlite` feature".into()))
+}
+
+/// Adds the copies to the database after its last gid, with where they come
+/// from; their new gids
+#[cfg(feature = "sqlite")]
+fn insert_recolored(path: &Path, rules: &[Rule], recolored: &[Recolored]) -> Result<Vec<i64>, Error> {
+    let _lock = WriteLock::acquire(path)?;
+    let mut connection = sql::Connection::open(path)?;
+    let tx = connection.transaction()?;
+    let last: Option<i64> = tx.query_row("SELECT MAX(gid) FROM rle", [], |row| row.get(0))?;
+    let mut gids = Vec::with_capacity(recolored.len());
+    for (number, copy) in recolored.iter().enumerate() {
+        let gid = last.unwrap_or(0) + 1 + number as i64;
+        let resource = &copy.resource;
+        let extras = resource.extras();
+        let shadow = extras.shadow_offset();
+        let hotspot = extras.hotspot(resource.width, resource.height);
+        tx.execute(
+            "INSERT INTO rle (
+                gid,
+                type,      file_num,  file_idx,
+                length,    offset_x,  offset_y,
+                width,     height,
+                unknown_1, unknown_2, unknown_3, unknown_4,
+                shadow_x,  shadow_y,  hotspot_x, hotspot_y,
+                image)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
+            sql::params![gid,
+             &copy.kind,       resource.file_num, resource.index(),
+             resource.len,     resource.offset_x, resource.offset_y,
+             resource.width,   resource.height,
+             extras.unknown_1, extras.unknown_2,  extras.unknown_3, extras.unknown_4,
+             shadow.map(|s| s.0), shadow.map(|s| s.1), hotspot.map(|h| h.0), hotspot.map(|h| h.1),
+             &resource.image_raw])?;
+        gids.push(gid);
+    }
+    let steps: Vec<Step> = recolored.iter().zip(gids.iter())
+        .map(|(copy, &gid)| Step {
+            gid,
+            source: copy.source,
+            operation: "recolor".into(),
+            parameters: rule_parameters(&rules[copy.rule]),
+        })
+        .collect();
+    record(&tx, &steps)?;
+    tx.commit()?;
+    Ok(gids)
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn insert_recolored(_path: &Path, _rules: &[Rule], _recolored: &[Recolored]) -> Result<Vec<i64>, Error> {
+    Err(Error::Args("recolor needs data_converter built with the `sqlite` feature".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core_compat::parser::rle::parse_rle;
+
+    #[test]
+    fn test_parse_rules() {
+        let rules = parse_rules("# slimes\n\
+                                 [[rule]]\n\
+                                 name = \"blue # slime\" # of the sea\n\
+                                 gids = [\n  120, # the small one\n  121,\n]\n\
+                                 hue = -120.5\n\
+                                 [[rule]]\n\
+                                 gids = [7]\n\
+                                 palette = [\"#FF0000:#0000ff\"]\n").unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].name.as_deref(), Some("blue # slime"));
+        assert_eq!(rules[0].gids, vec![120, 121]);
+        assert_eq!((rules[0].hue, rules[0].saturation), (-120.5, 1.0));
+        assert_eq!(rules[1].palette, vec![([0xFF, 0, 0], [0, 0, 0xFF])]);
+
+        let problems = parse_rules("hue = 3\n[[rule]]\ngids = [1.5]\n[[rule]]\ngids = [1]\nhue = \"red\"\n\
+                                    [[rule]]\ngids = [1]\ntint = 4\n[[rule]]\nhue = 1\n").unwrap_err();
+        assert_eq!(problems, vec![
+            "1: `hue` is outside of a `[[rule]]`",
+            "2: the gids have to be whole numbers",
+            "4: `hue` has the wrong kind of value",
+            "7: unknown key `tint`",
+            "10: a rule needs `gids`",
+        ]);
+        assert_eq!(parse_rules("[[rule]]\ngids = [1,\n").unwrap_err(),
+                   vec!["2: can't read the value of `gids`", "1: a rule needs `gids`"]);
+    }
+
+    #[test]
+    fn test_recolor_pixels() {
+        let rule = Rule { name: None, gids: vec![1], hue: 120.0, saturation: 1.0, lightness: 1.0,
+                          palette: vec![([0, 0, 0xFF], [0x10, 0x20, 0x30])] };
+        let mut rgba = vec![0xFF, 0, 0, 0xFF, 0, 0, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0x80, 0x80, 0x80, 0xFF];
+        recolor_pixels(&rule, &mut rgba);
+        // red turns green, blue is in the palette, the transparent pixel and
+        // the grey one stay as they are
+        assert_eq!(rgba, vec![0, 0xFF, 0, 0xFF, 0x10, 0x20, 0x30, 0xFF, 0xFF, 0, 0, 0, 0x80, 0x80, 0x80, 0xFF]);
+
+        // what the provenance records makes the same rule again
+        let parameters = rule_parameters(&rule);
+        assert_eq!(parameters, "hue = 120\nsaturation = 1\nlightness = 1\npalette = [\"#0000FF:#102030\"]\n");
+        assert_eq!(parse_rules(&format!("[[rule]]\ngids = [1]\n{}", parameters)).unwrap(), vec![rule.clone()]);
+
+        for &color in [[0x12, 0x34, 0x56], [0xFF, 0xFF, 0], [0, 0x80, 0x40], [0xC0, 0xC0, 0xC0]].iter() {
+            let (h, s, l) = rgb_to_hsl(color);
+            assert_eq!(hsl_to_rgb(h, s, l), color);
+        }
+    }
+
+    #[test]
+    fn test_recolor_sources() {
+        let red = Source { kind: "ch0".into(), offset_x: 3, offset_y: -4, width: 2, height: 1,
+                           rgba: vec![0xFF, 0, 0, 0xFF, 0, 0, 0, 0] };
+        let sources: BTreeMap<i64, Source> = vec![(5, red)].into_iter().collect();
+        let rule = |hue| Rule { name: None, gids: vec![5, 6], hue, saturation: 1.0, lightness: 1.0, palette: Vec::new() };
+        let recolored = recolor_sources(&[rule(120.0), rule(240.0)], &sources, 77);
+        assert_eq!(recolored.iter().map(|copy| (copy.source, copy.rule)).collect::<Vec<_>>(), vec![(5, 0), (5, 1)]);
+        assert_eq!(recolored[1].kind, "ch0");
+
+        // and they make a file of their own
+        let data = write_rle(&resource_file(77, &recolored), PixelFormat::Rgba8).unwrap();
+        let file = parse_rle(77, &data).unwrap();
+        let blue = file.get(1).unwrap();
+        assert_eq!((blue.offset_x, blue.offset_y, blue.len), (3, -4, recolored[1].resource.len));
+        assert_eq!(blue.image_raw, vec![0, 0, 0xFF, 0xFF, 0, 0, 0, 0]);
+    }
+}
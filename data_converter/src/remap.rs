@@ -0,0 +1,207 @@
+//! `data_converter remap <table> [--dry-run]`
+//!
+//! Rewrites the map and list files according to a remapping table, so tile
+//! sets can be reorganized or merged without fixing every reference by hand.
+//!
+//! Each line of the table is `<target> <file>:<index> <new file>:<new index>`
+//! where the target is `map-obj` / `map-tle` for the RMD entries referenced by
+//! the map tiles, or the short name of a list (`obj`, `tle`, `ch0`, ...) for the
+//! RLE entries referenced by that list file. `#` starts a comment.
+//!
+//! All new entries are checked to exist before anything is written; the
+//! rewritten files are saved to `<OUTPUT_PATH>/remap/`.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::fs::read_dir;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use core_compat::entity::entry::Entry;
+use core_compat::entity::rmd_type::RmdType;
+use core_compat::writer::lst::remap_lst_items;
+use core_compat::writer::rmm::remap_rmm_entries;
+
+use crate::error::Error;
+use super::{OUTPUT_PATH, RLE_ENTRIES, RMD_ENTRIES, RMM_ENTRY};
+use super::{find_rle_file, load_rle_data, load_rmd_data};
+
+struct Remap {
+    target: String,
+    old: Entry,
+    new: Entry,
+}
+
+pub fn remap(args: &[String]) -> Result<(), Error> {
+    let table_path = match args.first() {
+        Some(path) => path,
+        None => return Err(Error::Args("usage: remap <table> [--dry-run]".into())),
+    };
+    let dry_run = args.iter().any(|arg| arg == "--dry-run");
+
+    let mut table = String::new();
+    File::open(table_path)?.read_to_string(&mut table)?;
+    let remaps = parse_table(&table)?;
+    println!("loaded {} remap entries", remaps.len());
+
+    validate(&remaps)?;
+    if dry_run {
+        println!("all remap targets exist; nothing written (--dry-run)");
+        return Ok(());
+    }
+
+    let mut out_dir = PathBuf::new();
+    out_dir.push(OUTPUT_PATH);
+    out_dir.push("remap");
+    std::fs::create_dir_all(&out_dir)?;
+
+    remap_maps(&remaps, &out_dir)?;
+    remap_lists(&remaps, &out_dir)?;
+    Ok(())
+}
+
+fn parse_table(table: &str) -> Result<Vec<Remap>, Error> {
+    let mut remaps = Vec::new();
+    for (line_num, line) in table.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let bad_line = || Error::Args(format!("line {}: expected `<target> <file>:<index> <file>:<index>`", line_num + 1));
+        if fields.len() != 3 {
+            return Err(bad_line());
+        }
+        let old = parse_entry(fields[1]).ok_or_else(bad_line)?;
+        let new = parse_entry(fields[2]).ok_or_else(bad_line)?;
+        remaps.push(Remap { target: fields[0].into(), old, new });
+    }
+    Ok(remaps)
+}
+
+fn parse_entry(field: &str) -> Option<Entry> {
+    let mut parts = field.splitn(2, ':');
+    let file = parts.next()?.parse().ok()?;
+    let index = parts.next()?.parse().ok()?;
+    Some(Entry::new(file, index))
+}
+
+fn map_target(target: &str) -> Option<RmdType> {
+    match target {
+        "map-obj" => Some(RmdType::Object),
+        "map-tle" => Some(RmdType::Tile),
+        _ => None,
+    }
+}
+
+fn validate(remaps: &[Remap]) -> Result<(), Error> {
+    let mut problems = Vec::new();
+    for remap in remaps {
+        let new = remap.new;
+        if let Some(kind) = map_target(&remap.target) {
+            let &(_, short, dir, _) = RMD_ENTRIES.iter().find(|e| e.3 == kind).unwrap();
+            let path = Path::new(dir).join(format!("{}{:05}.rmd", short, new.file()));
+            match load_rmd_data(&path, kind) {
+                Ok(ref rmd) if rmd.get_entry(new.index() as usize).is_some() => (),
+                Ok(_) => problems.push(format!("{}: no entry {} in {:?}", remap.target, new.index(), path)),
+                Err(_) => problems.push(format!("{}: can't load {:?}", remap.target, path)),
+            }
+        } else if let Some(&(_, _, folder, _, _)) = RLE_ENTRIES.iter().find(|e| e.1 == remap.target) {
+            let found = match find_rle_file(folder, new.file())? {
+                Some(path) => load_rle_data(&path)?.get(new.index()).is_some(),
+                None => false,
+            };
+            if !found {
+                problems.push(format!("{}: no resource {}:{} in {}", remap.target, new.file(), new.index(), folder));
+            }
+        } else {
+            problems.push(format!("unknown remap target `{}`", remap.target));
+        }
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Validation(problems))
+    }
+}
+
+fn remap_maps(remaps: &[Remap], out_dir: &Path) -> Result<(), Error> {
+    let table: HashMap<(RmdType, Entry), Entry> = remaps.iter()
+        .filter_map(|r| map_target(&r.target).map(|kind| ((kind, r.old), r.new)))
+        .collect();
+    if table.is_empty() {
+        return Ok(());
+    }
+
+    let (_, path) = RMM_ENTRY;
+    for entry in read_dir(path)? {
+        let path = entry?.path();
+        let mut data = Vec::new();
+        File::open(&path)?.read_to_end(&mut data)?;
+
+        let mut changes = 0;
+        let out = remap_rmm_entries(&data, |kind, entry| {
+            match table.get(&(kind, entry)) {
+                Some(new) => { changes += 1; *new },
+                None => entry,
+            }
+        })?;
+
+        if changes > 0 {
+            println!("{:?}: {} references remapped", path, changes);
+            File::create(out_dir.join(path.file_name().unwrap()))?.write_all(&out)?;
+        }
+    }
+    Ok(())
+}
+
+fn remap_lists(remaps: &[Remap], out_dir: &Path) -> Result<(), Error> {
+    for &(_, short, _, list, use_v2) in RLE_ENTRIES.iter() {
+        let table: HashMap<Entry, Entry> = remaps.iter()
+            .filter(|r| r.target == short)
+            .map(|r| (r.old, r.new))
+            .collect();
+        if table.is_empty() {
+            continue;
+        }
+
+        let path = Path::new(list);
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+
+        let mut changes = 0;
+        let out = remap_lst_items(&data, use_v2, |id, entry| {
+            match table.get(&entry) {
+                Some(new) => { changes += 1; (id, *new) },
+                None => (id, entry),
+            }
+        })?;
+
+        println!("{:?}: {} references remapped", path, changes);
+        File::create(out_dir.join(path.file_name().unwrap()))?.write_all(&out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_table() {
+        let table = "# moving tiles\nmap-tle 12:3 40:0\n\nobj 5:1 5:9 # merged\n";
+        let remaps = parse_table(table).unwrap();
+        assert_eq!(remaps.len(), 2);
+        assert_eq!(remaps[0].target, "map-tle");
+        assert_eq!(remaps[0].old, Entry::new(12, 3));
+        assert_eq!(remaps[1].new, Entry::new(5, 9));
+    }
+
+    #[test]
+    fn test_parse_table_bad_line() {
+        assert!(parse_table("map-tle 12:3").is_err());
+        assert!(parse_table("map-tle 12-3 4:0").is_err());
+    }
+}
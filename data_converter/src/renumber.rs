@@ -0,0 +1,221 @@
+//! `data_converter renumber <list> (--compact | --offset <n>) [--dry-run]`
+//!
+//! Renumbers the item ids of a list file, either closing the gaps between the
+//! ids (`--compact`) or shifting all of them (`--offset`, to make room for the
+//! ids of another mod pack), and updates the RMD files referencing the list.
+//! Those are found from `RLE_ENTRIES` and `RMD_ENTRIES`: the RMD folder named
+//! like the list's RLE folder, or like the folder it's in. The character lists
+//! (`ch0` to `ch9` and `etc`) all share the Chr RMD files, which don't say
+//! which of them an id is from; every id of the list found in them is
+//! renumbered.
+//!
+//! The rewritten files are saved to `<OUTPUT_PATH>/renumber/`.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::fs::read_dir;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use core_compat::entity::rmd_type::RmdType;
+use core_compat::writer::lst::{remap_lst_items, set_next_free_id};
+use core_compat::writer::rmd::remap_rmd_image_ids;
+
+use crate::error::Error;
+use super::{OUTPUT_PATH, RLE_ENTRIES, RMD_ENTRIES};
+use super::load_list_data;
+
+static USAGE: &str = "usage: renumber <list> (--compact | --offset <n>) [--dry-run]";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Renumbering {
+    Compact,
+    Offset(i64),
+}
+
+pub fn renumber(args: &[String]) -> Result<(), Error> {
+    let short = match args.first() {
+        Some(short) => short,
+        None => return Err(Error::Args(USAGE.into())),
+    };
+    let mut renumbering = None;
+    let mut dry_run = false;
+    let mut iter = args[1..].iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--compact" => renumbering = Some(Renumbering::Compact),
+            "--offset" => match iter.next().and_then(|n| n.parse().ok()) {
+                Some(offset) => renumbering = Some(Renumbering::Offset(offset)),
+                None => return Err(Error::Args(USAGE.into())),
+            },
+            "--dry-run" => dry_run = true,
+            _ => return Err(Error::Args(USAGE.into())),
+        }
+    }
+    let renumbering = renumbering.ok_or_else(|| Error::Args(USAGE.into()))?;
+
+    let &(_, _, _, list_path, use_v2) = RLE_ENTRIES.iter()
+        .find(|e| e.1 == short.as_str())
+        .ok_or_else(|| Error::Args(format!("unknown list `{}`", short)))?;
+    let (_, rmd_dir) = references(short)
+        .ok_or_else(|| Error::Args(format!("no references are known for list `{}`", short)))?;
+
+    let list_path = Path::new(list_path);
+    let list = load_list_data(list_path, use_v2)?;
+    let ids: Vec<u32> = list.items.iter().map(|item| item.id).collect();
+    let table = renumber_table(&ids, renumbering)?;
+    let changed = table.iter().filter(|&(old, new)| old != new).count();
+    println!("{:?}: {} of {} ids change", list_path, changed, ids.len());
+    if dry_run || changed == 0 {
+        return Ok(());
+    }
+
+    let mut out_dir = PathBuf::new();
+    out_dir.push(OUTPUT_PATH);
+    out_dir.push("renumber");
+    std::fs::create_dir_all(&out_dir)?;
+
+    // the list itself
+    let mut data = Vec::new();
+    File::open(list_path)?.read_to_end(&mut data)?;
+    let mut out = remap_lst_items(&data, use_v2, |id, entry| (table[&id], entry))?;
+    if let Some(max) = table.values().max() {
+        set_next_free_id(&mut out, max + 1)?;
+    }
+    File::create(out_dir.join(list_path.file_name().unwrap()))?.write_all(&out)?;
+
+    // ... and every RMD file referencing it
+    for entry in read_dir(rmd_dir)? {
+        let path = entry?.path();
+        let mut data = Vec::new();
+        File::open(&path)?.read_to_end(&mut data)?;
+
+        let (out, changes, unknown) = renumber_rmd(&data, &table)?;
+        if unknown > 0 {
+            println!("{:?}: {} ids not in the list, left as they are", path, unknown);
+        }
+        if changes > 0 {
+            println!("{:?}: {} references renumbered", path, changes);
+            File::create(out_dir.join(path.file_name().unwrap()))?.write_all(&out)?;
+        }
+    }
+    Ok(())
+}
+
+/// The kind and folder of the RMD files referencing the item ids of the list
+/// `short`
+fn references(short: &str) -> Option<(RmdType, &'static str)> {
+    let &(_, _, folder, _, _) = RLE_ENTRIES.iter().find(|e| e.1 == short)?;
+    let folder = Path::new(folder);
+    let names = [folder.file_name(), folder.parent().and_then(|parent| parent.file_name())];
+    RMD_ENTRIES.iter()
+        .find(|e| names.contains(&Path::new(e.2).file_name()))
+        .map(|&(_, _, rmd_dir, kind)| (kind, rmd_dir))
+}
+
+/// A copy of the RMD file `data` with its ids renumbered by `table`, with
+/// how many of them changed and how many aren't in the table and were left
+fn renumber_rmd(data: &[u8], table: &HashMap<u32, u32>) -> Result<(Vec<u8>, usize, usize), Error> {
+    let mut changes = 0;
+    let mut unknown = 0;
+    let out = remap_rmd_image_ids(data, |id| {
+        match table.get(&(id as u32)) {
+            Some(&new) if new as i32 != id => { changes += 1; new as i32 },
+            Some(_) => id,
+            None => { unknown += 1; id },
+        }
+    })?;
+    Ok((out, changes, unknown))
+}
+
+/// Maps every old id to its new id. Compacting keeps the order of the ids and
+/// the lowest id.
+fn renumber_table(ids: &[u32], renumbering: Renumbering) -> Result<HashMap<u32, u32>, Error> {
+    let mut sorted = ids.to_vec();
+    sorted.sort();
+    sorted.dedup();
+    if sorted.len() != ids.len() {
+        return Err(Error::Validation(vec!["the list contains duplicate ids".into()]));
+    }
+
+    let mut table = HashMap::new();
+    match renumbering {
+        Renumbering::Compact => {
+            let first = sorted.first().cloned().unwrap_or(0);
+            for (rank, &id) in sorted.iter().enumerate() {
+                table.insert(id, first + rank as u32);
+            }
+        }
+        Renumbering::Offset(offset) => {
+            for &id in sorted.iter() {
+                let new = id as i64 + offset;
+                if new < 0 || new > i32::MAX as i64 {
+                    return Err(Error::Validation(vec![format!("id {} is out of range after the offset", id)]));
+                }
+                table.insert(id, new as u32);
+            }
+        }
+    }
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core_compat::entity::entry::Entry;
+    use core_compat::fixture::{LstFixture, RmdFixture};
+    use core_compat::parser::lst::parse_lst;
+    use core_compat::parser::rmd::parse_rmd;
+
+    #[test]
+    fn test_renumber_table_compact() {
+        let table = renumber_table(&[4, 9, 2, 5], Renumbering::Compact).unwrap();
+        assert_eq!(table[&2], 2);
+        assert_eq!(table[&4], 3);
+        assert_eq!(table[&5], 4);
+        assert_eq!(table[&9], 5);
+    }
+
+    #[test]
+    fn test_renumber_table_offset() {
+        let table = renumber_table(&[1, 3], Renumbering::Offset(100)).unwrap();
+        assert_eq!(table[&3], 103);
+        assert!(renumber_table(&[1, 3], Renumbering::Offset(-2)).is_err());
+        assert!(renumber_table(&[1, 1], Renumbering::Offset(0)).is_err());
+    }
+
+    #[test]
+    fn test_references() {
+        assert_eq!(references("bul"), Some((RmdType::Bullet, "../data/DATAs/Bul")));
+        assert_eq!(references("tle"), Some((RmdType::Tile, "../data/DATAs/Tle")));
+        for short in ["ch0", "ch9", "etc"].iter() {
+            assert_eq!(references(short), Some((RmdType::Character, "../data/DATAs/Chr")));
+        }
+        // nothing references the interface
+        assert_eq!(references("int"), None);
+        assert_eq!(references("snd"), None);
+    }
+
+    #[test]
+    fn test_renumber_character_list() {
+        let list = parse_lst(&LstFixture::new("1.0")
+            .item("idle", 4, Entry::new(0, 0))
+            .item("walk", 9, Entry::new(0, 1))
+            .build(), false).unwrap();
+        let ids: Vec<u32> = list.items.iter().map(|item| item.id).collect();
+        let table = renumber_table(&ids, Renumbering::Compact).unwrap();
+
+        let chr = RmdFixture::new().entry(&[&[4, 9]]).entry(&[&[9], &[12]]).build();
+        let (out, changes, unknown) = renumber_rmd(&chr, &table).unwrap();
+        assert_eq!((changes, unknown), (2, 1));
+        let rmd = parse_rmd(RmdType::Character, &out).unwrap();
+        let image_ids = |index: usize| -> Vec<Vec<i32>> {
+            rmd.get_entry(index).unwrap().images().iter().map(|image| image.image_id.clone()).collect()
+        };
+        assert_eq!(image_ids(0), vec![vec![4, 5]]);
+        assert_eq!(image_ids(1), vec![vec![5], vec![12]]);
+    }
+}
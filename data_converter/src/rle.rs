@@ -0,0 +1,235 @@
+//! `data_converter rle <command>`: tools working directly on RLE files.
+//!
+//! The resources are copied as undecoded bytes, so the written files keep the
+//! exact pixel runs of the originals.
+
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+
+use core_compat::parser::rle::raw_resources;
+use core_compat::writer::rle::write_raw_rle;
+
+use crate::error::Error;
+
+static USAGE: &str = "usage: data_converter rle <command>
+commands:
+    split <file> --indices <i,j,..> -o <out> [--preserve-indices]
+    merge <file> <file> [..] -o <out> [--on-collision error|renumber|prefer-first] [--report <csv>]";
+
+pub fn rle(args: &[String]) -> Result<(), Error> {
+    match args.first().map(|arg| arg.as_str()) {
+        Some("split") => split(&args[1..]),
+        Some("merge") => merge(&args[1..]),
+        _ => Err(Error::Args(USAGE.into())),
+    }
+}
+
+/// Writes a new resource file containing only the selected resources. They are
+/// renumbered from 0 in index order, unless `--preserve-indices` is given in
+/// which case the other indices are written as null offsets.
+fn split(args: &[String]) -> Result<(), Error> {
+    let mut input = None;
+    let mut output = None;
+    let mut indices = None;
+    let mut preserve_indices = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--indices" => indices = iter.next().map(|list| parse_indices(list)),
+            "-o" => output = iter.next(),
+            "--preserve-indices" => preserve_indices = true,
+            _ => input = Some(arg),
+        }
+    }
+    let (input, output, mut indices) = match (input, output, indices) {
+        (Some(input), Some(output), Some(Ok(indices))) => (input, output, indices),
+        (_, _, Some(Err(e))) => return Err(e),
+        _ => return Err(Error::Args(USAGE.into())),
+    };
+    indices.sort();
+    indices.dedup();
+
+    let mut data = Vec::new();
+    File::open(input)?.read_to_end(&mut data)?;
+    let resources = raw_resources(&data)?;
+
+    let mut selected = Vec::new();
+    for &index in indices.iter() {
+        match resources.get(index) {
+            Some(&Some(resource)) => selected.push((index, resource)),
+            _ => return Err(Error::Validation(vec![format!("no resource at index {} in {}", index, input)])),
+        }
+    }
+
+    let slots: Vec<Option<&[u8]>> = if preserve_indices {
+        let mut slots = vec![None; indices.last().map_or(0, |last| last + 1)];
+        for &(index, resource) in selected.iter() {
+            slots[index] = Some(resource);
+        }
+        slots
+    } else {
+        selected.iter().map(|&(_, resource)| Some(resource)).collect()
+    };
+
+    File::create(output)?.write_all(&write_raw_rle(&slots))?;
+    println!("wrote {} of {} resources to {}", selected.len(), resources.len(), output);
+    Ok(())
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum CollisionPolicy {
+    Error,
+    Renumber,
+    PreferFirst,
+}
+
+/// Where a resource of an input file ended up in the merged file
+#[derive(Debug)]
+struct MergeMapping {
+    input: usize,
+    index: usize,
+    merged_index: Option<usize>,
+}
+
+/// Merges several resource files into one. Resources keep their index unless
+/// an earlier file already uses it; what happens then depends on the policy.
+fn merge(args: &[String]) -> Result<(), Error> {
+    let mut inputs = Vec::new();
+    let mut output = None;
+    let mut report = None;
+    let mut policy = CollisionPolicy::Error;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" => output = iter.next(),
+            "--report" => report = iter.next(),
+            "--on-collision" => policy = match iter.next().map(|p| p.as_str()) {
+                Some("error") => CollisionPolicy::Error,
+                Some("renumber") => CollisionPolicy::Renumber,
+                Some("prefer-first") => CollisionPolicy::PreferFirst,
+                _ => return Err(Error::Args(USAGE.into())),
+            },
+            _ => inputs.push(arg),
+        }
+    }
+    let output = match output {
+        Some(output) if inputs.len() > 1 => output,
+        _ => return Err(Error::Args(USAGE.into())),
+    };
+
+    let mut data = Vec::new();
+    for input in inputs.iter() {
+        let mut bytes = Vec::new();
+        File::open(input)?.read_to_end(&mut bytes)?;
+        data.push(bytes);
+    }
+    let mut resources = Vec::new();
+    for bytes in data.iter() {
+        resources.push(raw_resources(bytes)?);
+    }
+
+    let (slots, mappings) = match merge_slots(&resources, policy) {
+        Ok(merged) => merged,
+        Err(collisions) => {
+            let problems = collisions.iter()
+                .map(|m| format!("index {} of {} is already used", m.index, inputs[m.input]))
+                .collect();
+            return Err(Error::Validation(problems));
+        }
+    };
+    File::create(output)?.write_all(&write_raw_rle(&slots))?;
+
+    let mut csv = String::from("source,index,merged_index\n");
+    for mapping in mappings.iter() {
+        let merged_index = mapping.merged_index.map_or(String::new(), |idx| idx.to_string());
+        csv.push_str(&format!("{},{},{}\n", inputs[mapping.input], mapping.index, merged_index));
+    }
+    match report {
+        Some(report) => File::create(report)?.write_all(csv.as_bytes())?,
+        None => print!("{}", csv),
+    }
+    println!("wrote {} resources to {}", slots.iter().filter(|s| s.is_some()).count(), output);
+    Ok(())
+}
+
+/// Places the resources of all inputs, in input order. With
+/// `CollisionPolicy::Error` the colliding resources are returned as error.
+#[allow(clippy::type_complexity)]
+fn merge_slots<'a>(
+    inputs: &[Vec<Option<&'a [u8]>>],
+    policy: CollisionPolicy
+) -> Result<(Vec<Option<&'a [u8]>>, Vec<MergeMapping>), Vec<MergeMapping>> {
+    let mut slots: Vec<Option<&'a [u8]>> = Vec::new();
+    let mut mappings = Vec::new();
+    let mut collisions = Vec::new();
+
+    for (input, resources) in inputs.iter().enumerate() {
+        for (index, resource) in resources.iter().enumerate() {
+            let resource = match *resource {
+                Some(resource) => resource,
+                None => continue,
+            };
+            if slots.len() <= index {
+                slots.resize(index + 1, None);
+            }
+            if slots[index].is_none() {
+                slots[index] = Some(resource);
+                mappings.push(MergeMapping { input, index, merged_index: Some(index) });
+            } else {
+                collisions.push((MergeMapping { input, index, merged_index: None }, resource));
+            }
+        }
+    }
+
+    match policy {
+        CollisionPolicy::Error if !collisions.is_empty() => {
+            return Err(collisions.into_iter().map(|(m, _)| m).collect());
+        }
+        CollisionPolicy::Renumber => {
+            // colliding resources go to the end of the merged file
+            for (mut mapping, resource) in collisions {
+                mapping.merged_index = Some(slots.len());
+                slots.push(Some(resource));
+                mappings.push(mapping);
+            }
+        }
+        _ => mappings.extend(collisions.into_iter().map(|(m, _)| m)),
+    }
+    Ok((slots, mappings))
+}
+
+pub fn parse_indices(list: &str) -> Result<Vec<usize>, Error> {
+    list.split(',')
+        .map(|index| index.trim().parse()
+            .map_err(|_| Error::Args(format!("invalid index `{}`", index))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_indices() {
+        assert_eq!(parse_indices("3,7, 9").unwrap(), vec![3, 7, 9]);
+        assert!(parse_indices("3,x").is_err());
+    }
+
+    #[test]
+    fn test_merge_slots() {
+        let (a, b, c): (&[u8], &[u8], &[u8]) = (&[1], &[2], &[3]);
+        let inputs = vec![vec![Some(a), None], vec![Some(b), Some(c)]];
+
+        assert_eq!(merge_slots(&inputs, CollisionPolicy::Error).err().unwrap().len(), 1);
+
+        let (slots, mappings) = merge_slots(&inputs, CollisionPolicy::PreferFirst).unwrap();
+        assert_eq!(slots, vec![Some(a), Some(c)]);
+        assert_eq!(mappings.iter().filter(|m| m.merged_index.is_none()).count(), 1);
+
+        let (slots, mappings) = merge_slots(&inputs, CollisionPolicy::Renumber).unwrap();
+        assert_eq!(slots, vec![Some(a), Some(c), Some(b)]);
+        let moved = mappings.iter().find(|m| m.input == 1 && m.index == 0).unwrap();
+        assert_eq!(moved.merged_index, Some(2));
+    }
+}
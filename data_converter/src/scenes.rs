@@ -0,0 +1,362 @@
+//! `data_converter scenes <scenes.toml> -o <out folder>`
+//!
+//! Renders the scenes a TOML file lists as PNG files, without a window, so
+//! the images of the wiki and the changelogs can be made again whenever the
+//! assets change. Every `[[scene]]` table is written to `<name>.png` and is
+//! one of:
+//!
+//! ```toml
+//! [[scene]]
+//! name = "harbor"
+//! kind = "map"          # a part of a map, tiles and objects as deepzoom draws them
+//! map = 2
+//! x = 960               # the top left pixel of the map, a tile is 48x24
+//! y = 480
+//! width = 640
+//! height = 360
+//!
+//! [[scene]]
+//! name = "knight"
+//! kind = "character"    # an entry of a character RMD file, composed as ora does
+//! list = "ch0"
+//! rmd = 1
+//! entry = 12
+//! variant = 1           # of the images listing several sprites, 0 by default
+//! background = [32, 35, 42]
+//!
+//! [[scene]]
+//! name = "inventory"
+//! kind = "interface"    # sprites of a list placed by hand, for UI mockups
+//! list = "int"          # the default
+//! width = 320
+//! height = 240
+//! sprites = [[100, 0, 0], [104, 12, 40]]   # list item, and where its top left goes
+//! ```
+//!
+//! Without a `background` color the scenes are transparent where nothing is
+//! drawn. All the scenes are written, then the sprites that couldn't be
+//! found are reported and the command fails, so a script regenerating the
+//! images notices.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::hash_map;
+use std::fs;
+use std::path::Path;
+
+use core_compat::entity::list::List;
+use core_compat::entity::resource_file::ResourceFile;
+use core_compat::entity::rmd_type::RmdType;
+
+use crate::deepzoom::{Scene, SpriteSource};
+use crate::error::Error;
+use crate::ora::{bounds, composite, entry_layers, load_entry_files, Layer};
+use crate::toml::{parse_tables, Value};
+use super::{RLE_ENTRIES, RMD_ENTRIES, RMM_ENTRY};
+use super::{encode_png, find_rle_file, load_list_data, load_rle_data, load_rmd_data, load_rmm_data};
+
+static USAGE: &str = "usage: scenes <scenes.toml> -o <out folder>";
+
+/// Scenes bigger than this on either side are refused
+pub const MAX_SIZE: u32 = 8192;
+
+/// A `[[scene]]` of the file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shot {
+    /// The PNG file is `<name>.png`
+    pub name: String,
+    pub subject: Subject,
+    pub background: Option<[u8; 3]>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Subject {
+    /// The pixels of `map` from `x`, `y` on
+    Map { map: u32, x: u32, y: u32, width: u32, height: u32 },
+    /// An entry of the character RMD file `rmd`, with the sprites of `list`
+    Character { list: String, rmd: u32, entry: usize, variant: usize },
+    /// Items of `list` with where their top left pixel goes
+    Interface { list: String, width: u32, height: u32, sprites: Vec<(u32, u32, u32)> },
+}
+
+pub fn scenes(args: &[String]) -> Result<(), Error> {
+    let (mut script, mut output) = (None, None);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" => output = iter.next(),
+            _ if script.is_none() => script = Some(arg),
+            _ => return Err(Error::Args(USAGE.into())),
+        }
+    }
+    let (script, output) = match (script, output) {
+        (Some(script), Some(output)) => (script, Path::new(output)),
+        _ => return Err(Error::Args(USAGE.into())),
+    };
+
+    let shots = parse_scenes(&fs::read_to_string(script)?)
+        .map_err(|problems| Error::Validation(problems.iter().map(|p| format!("{}:{}", script, p)).collect()))?;
+    fs::create_dir_all(output)?;
+    let mut sprites = Sprites::default();
+    let mut problems = Vec::new();
+    for shot in shots.iter() {
+        let (width, height, mut rgba) = render(shot, &mut sprites, &mut problems)?;
+        if let Some([r, g, b]) = shot.background {
+            for px in rgba.chunks_mut(4).filter(|px| px[3] == 0) {
+                px.copy_from_slice(&[r, g, b, 0xFF]);
+            }
+        }
+        let path = output.join(format!("{}.png", shot.name));
+        fs::write(&path, encode_png(width, height, &rgba)?)?;
+        println!("wrote {}x{} to {:?}", width, height, path);
+    }
+    if !problems.is_empty() {
+        return Err(Error::Validation(problems));
+    }
+    Ok(())
+}
+
+/// The `[[scene]]` tables of a scenes file, or what's wrong with it as
+/// `<line>: <problem>`
+pub fn parse_scenes(text: &str) -> Result<Vec<Shot>, Vec<String>> {
+    let (tables, mut problems) = parse_tables(text, "scene");
+    let mut shots: Vec<Shot> = Vec::new();
+    for table in tables {
+        match shot(&table.fields) {
+            Ok(shot) if shots.iter().any(|other| other.name == shot.name) => {
+                problems.push(format!("{}: there's another scene named `{}`", table.line, shot.name))
+            }
+            Ok(shot) => shots.push(shot),
+            Err(problem) => problems.push(format!("{}: {}", table.line, problem)),
+        }
+    }
+    if problems.is_empty() {
+        Ok(shots)
+    } else {
+        Err(problems)
+    }
+}
+
+fn shot(fields: &BTreeMap<String, Value>) -> Result<Shot, String> {
+    let whole = |key: &str, max: u32| -> Result<Option<u32>, String> {
+        match fields.get(key) {
+            Some(&Value::Number(n)) if n.fract() == 0.0 && n >= 0.0 && n <= max as f64 => Ok(Some(n as u32)),
+            Some(_) => Err(format!("`{}` has to be a whole number up to {}", key, max)),
+            None => Ok(None),
+        }
+    };
+    let needed = |key: &str, max: u32| whole(key, max)?.ok_or_else(|| format!("a scene of that kind needs `{}`", key));
+    let string = |key: &str| match fields.get(key) {
+        Some(Value::Str(string)) => Ok(Some(string.clone())),
+        Some(_) => Err(format!("`{}` has the wrong kind of value", key)),
+        None => Ok(None),
+    };
+    let size = |key: &str| match needed(key, MAX_SIZE)? {
+        0 => Err(format!("`{}` can't be 0", key)),
+        size => Ok(size),
+    };
+
+    let name = string("name")?.filter(|name| !name.is_empty()).ok_or("a scene needs a `name`")?;
+    if name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(format!("`{}` can't be the name of a file in the out folder", name));
+    }
+    let (subject, keys): (Subject, &[&str]) = match string("kind")?.as_deref() {
+        Some("map") => (Subject::Map {
+            map: needed("map", u32::MAX)?,
+            x: whole("x", u32::MAX)?.unwrap_or(0),
+            y: whole("y", u32::MAX)?.unwrap_or(0),
+            width: size("width")?,
+            height: size("height")?,
+        }, &["map", "x", "y", "width", "height"][..]),
+        Some("character") => (Subject::Character {
+            list: string("list")?.ok_or("a scene of that kind needs `list`")?,
+            rmd: needed("rmd", u32::MAX)?,
+            entry: needed("entry", u32::MAX)? as usize,
+            variant: whole("variant", u32::MAX)?.unwrap_or(0) as usize,
+        }, &["list", "rmd", "entry", "variant"][..]),
+        Some("interface") => {
+            let sprites = match fields.get("sprites") {
+                Some(Value::Array(sprites)) => sprites.iter().map(placement).collect::<Option<Vec<_>>>()
+                    .ok_or("the `sprites` are `[<list item>, <x>, <y>]`")?,
+                Some(_) => return Err("the `sprites` are `[<list item>, <x>, <y>]`".into()),
+                None => Vec::new(),
+            };
+            (Subject::Interface {
+                list: string("list")?.unwrap_or_else(|| "int".into()),
+                width: size("width")?,
+                height: size("height")?,
+                sprites,
+            }, &["list", "width", "height", "sprites"][..])
+        }
+        Some(kind) => return Err(format!("unknown kind `{}`", kind)),
+        None => return Err("a scene needs a `kind`".into()),
+    };
+    let known = |key: &&String| ["name", "kind", "background"].contains(&key.as_str()) || keys.contains(&key.as_str());
+    if let Some(key) = fields.keys().find(|key| !known(key)) {
+        return Err(format!("unknown key `{}`", key));
+    }
+    let background = match fields.get("background") {
+        Some(Value::Array(color)) => match color.iter().map(channel).collect::<Option<Vec<u8>>>() {
+            Some(ref color) if color.len() == 3 => Some([color[0], color[1], color[2]]),
+            _ => return Err("the `background` is `[<red>, <green>, <blue>]`, from 0 to 255".into()),
+        },
+        Some(_) => return Err("the `background` is `[<red>, <green>, <blue>]`, from 0 to 255".into()),
+        None => None,
+    };
+    Ok(Shot { name, subject, background })
+}
+
+/// A `[<list item>, <x>, <y>]` of an interface scene
+fn placement(value: &Value) -> Option<(u32, u32, u32)> {
+    let number = |value: &Value| match *value {
+        Value::Number(n) if n.fract() == 0.0 && n >= 0.0 && n <= MAX_SIZE as f64 => Some(n as u32),
+        _ => None,
+    };
+    match value {
+        Value::Array(values) if values.len() == 3 => Some((number(&values[0])?, number(&values[1])?, number(&values[2])?)),
+        _ => None,
+    }
+}
+
+fn channel(value: &Value) -> Option<u8> {
+    match *value {
+        Value::Number(n) if n.fract() == 0.0 && (0.0..=255.0).contains(&n) => Some(n as u8),
+        _ => None,
+    }
+}
+
+/// The lists and RLE files loaded so far, by the short name of the list, so
+/// the scenes sharing them load them once
+#[derive(Default)]
+struct Sprites {
+    lists: HashMap<String, (List, HashMap<u32, ResourceFile>)>,
+}
+
+impl Sprites {
+    fn list(&mut self, short: &str) -> Result<&mut (List, HashMap<u32, ResourceFile>), Error> {
+        match self.lists.entry(short.to_string()) {
+            hash_map::Entry::Occupied(list) => Ok(list.into_mut()),
+            hash_map::Entry::Vacant(slot) => {
+                let &(_, _, _, list_path, use_v2) = RLE_ENTRIES.iter()
+                    .find(|e| e.1 == short)
+                    .ok_or_else(|| Error::Args(format!("unknown list `{}`", short)))?;
+                Ok(slot.insert((load_list_data(Path::new(list_path), use_v2)?, HashMap::new())))
+            }
+        }
+    }
+}
+
+/// The width, height and pixels of the scene; the sprites that can't be
+/// found are added to `problems` and left out
+fn render(shot: &Shot, sprites: &mut Sprites, problems: &mut Vec<String>) -> Result<(u32, u32, Vec<u8>), Error> {
+    let mut missing = Vec::new();
+    let image = match shot.subject {
+        Subject::Map { map, x, y, width, height } => {
+            let map = load_rmm_data(&Path::new(RMM_ENTRY.1).join(format!("Map{:05}.rmm", map)))?;
+            let tiles = SpriteSource::load(RmdType::Tile, "tle", &map, &mut missing)?;
+            let objects = SpriteSource::load(RmdType::Object, "obj", &map, &mut missing)?;
+            let scene = Scene::new(&map, &tiles, &objects, &mut missing);
+            (width, height, scene.render_area(x, y, width, height))
+        }
+        Subject::Character { ref list, rmd, entry, variant } => {
+            let &(_, _, folder, _, _) = RLE_ENTRIES.iter()
+                .find(|e| e.1 == list.as_str())
+                .ok_or_else(|| Error::Args(format!("unknown list `{}`", list)))?;
+            let &(_, chr_short, chr_dir, _) = RMD_ENTRIES.iter().find(|e| e.3 == RmdType::Character).unwrap();
+            let rmd_path = Path::new(chr_dir).join(format!("{}{:05}.rmd", chr_short, rmd));
+            let rmd = load_rmd_data(&rmd_path, RmdType::Character)?;
+            let rmd_entry = rmd.get_entry(entry)
+                .ok_or_else(|| Error::Validation(vec![format!("scene {}: no entry {} in {:?}", shot.name, entry, rmd_path)]))?;
+            let (list_data, files) = sprites.list(list)?;
+            load_entry_files(rmd_entry, list_data, list, folder, files, &mut missing)?;
+            let layers = entry_layers(rmd_entry, list_data, list, files, variant, &mut missing);
+            let visible: Vec<_> = layers.into_iter().filter(|layer| layer.visible).collect();
+            let (left, top, width, height) = bounds(&visible);
+            (width, height, composite(&visible, left, top, width, height))
+        }
+        Subject::Interface { ref list, width, height, sprites: ref placed } => {
+            let &(_, _, folder, _, _) = RLE_ENTRIES.iter()
+                .find(|e| e.1 == list.as_str())
+                .ok_or_else(|| Error::Args(format!("unknown list `{}`", list)))?;
+            let (list_data, files) = sprites.list(list)?;
+            let mut found = Vec::new();
+            for &(id, x, y) in placed.iter() {
+                let item = match list_data.get_item(id as usize) {
+                    Some(item) => item,
+                    None => { missing.push(format!("{}: no list item {}", list, id)); continue },
+                };
+                let file = item.entry.file();
+                if let hash_map::Entry::Vacant(slot) = files.entry(file) {
+                    match find_rle_file(folder, file)? {
+                        Some(path) => { slot.insert(load_rle_data(&path)?); },
+                        None => { missing.push(format!("{}: no RLE file {}", list, file)); continue },
+                    }
+                }
+                found.push((file, item.entry.index(), x, y));
+            }
+            let mut layers = Vec::new();
+            for (file, index, x, y) in found {
+                match files[&file].get(index) {
+                    Some(res) => layers.push(Layer {
+                        name: String::new(),
+                        x: x as i32,
+                        y: y as i32,
+                        width: res.width.max(0) as u32,
+                        height: res.height.max(0) as u32,
+                        rgba: &res.image_raw,
+                        visible: true,
+                    }),
+                    None => missing.push(format!("{}: no resource {}:{}", list, file, index)),
+                }
+            }
+            (width, height, composite(&layers, 0, 0, width, height))
+        }
+    };
+    problems.extend(missing.into_iter().map(|problem| format!("scene {}: {}", shot.name, problem)));
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scenes() {
+        let shots = parse_scenes("[[scene]]\nname = \"harbor\"\nkind = \"map\"\nmap = 2\nx = 960\nwidth = 640\nheight = 360\n\
+                                  [[scene]]\nname = \"knight\"\nkind = \"character\"\nlist = \"ch0\"\nrmd = 1\nentry = 12\n\
+                                  background = [32, 35, 42]\n\
+                                  [[scene]]\nname = \"inventory\"\nkind = \"interface\"\nwidth = 320\nheight = 240\n\
+                                  sprites = [[100, 0, 0], [104, 12, 40]]\n").unwrap();
+        assert_eq!(shots, vec![
+            Shot { name: "harbor".into(), subject: Subject::Map { map: 2, x: 960, y: 0, width: 640, height: 360 }, background: None },
+            Shot {
+                name: "knight".into(),
+                subject: Subject::Character { list: "ch0".into(), rmd: 1, entry: 12, variant: 0 },
+                background: Some([32, 35, 42]),
+            },
+            Shot {
+                name: "inventory".into(),
+                subject: Subject::Interface { list: "int".into(), width: 320, height: 240, sprites: vec![(100, 0, 0), (104, 12, 40)] },
+                background: None,
+            },
+        ]);
+
+        let problems = parse_scenes("[[scene]]\nkind = \"map\"\n\
+                                     [[scene]]\nname = \"a\"\nkind = \"map\"\nmap = 1\nwidth = 0\nheight = 1\n\
+                                     [[scene]]\nname = \"b\"\nkind = \"interface\"\nwidth = 1\nheight = 1\nsprites = [[1, 2]]\n\
+                                     [[scene]]\nname = \"c\"\nkind = \"character\"\nlist = \"ch0\"\nrmd = 1\nentry = 0\nmap = 3\n\
+                                     [[scene]]\nname = \"../d\"\nkind = \"map\"\n\
+                                     [[scene]]\nname = \"e\"\nkind = \"map\"\nmap = 1\nwidth = 1\nheight = 1\nbackground = [0, 0, 256]\n\
+                                     [[scene]]\nname = \"f\"\nkind = \"interface\"\nwidth = 1\nheight = 1\n\
+                                     [[scene]]\nname = \"f\"\nkind = \"tiles\"\n").unwrap_err();
+        assert_eq!(problems, vec![
+            "1: a scene needs a `name`",
+            "3: `width` can't be 0",
+            "9: the `sprites` are `[<list item>, <x>, <y>]`",
+            "15: unknown key `map`",
+            "22: `../d` can't be the name of a file in the out folder",
+            "25: the `background` is `[<red>, <green>, <blue>]`, from 0 to 255",
+            "37: unknown kind `tiles`",
+        ]);
+    }
+}
@@ -0,0 +1,99 @@
+//! Finding items and monsters of the rule tables by name.
+//!
+//! Queries are matched case ignored, the way players type them in chat: an
+//! id or exact name first, then names starting with the query, names with a
+//! word starting with it, and names containing it anywhere.
+
+use core_rules::table::Tables;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    Item,
+    Monster,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hit {
+    pub kind: Kind,
+    pub id: u32,
+    pub name: String,
+}
+
+/// How well a name matches, best first
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Rank {
+    Exact,
+    Prefix,
+    WordPrefix,
+    Contains,
+}
+
+/// The names of the tables, lowercased once
+pub struct Index {
+    entries: Vec<(Hit, String)>,
+}
+
+impl Index {
+    pub fn new(tables: &Tables) -> Index {
+        let items = tables.items.values().map(|item| (Kind::Item, item.id, &item.name));
+        let monsters = tables.monsters.values().map(|monster| (Kind::Monster, monster.id, &monster.name));
+        let entries = items.chain(monsters)
+            .map(|(kind, id, name)| (Hit { kind, id, name: name.clone() }, name.to_lowercase()))
+            .collect();
+        Index { entries }
+    }
+
+    /// The entries matching `query`, best first; ties go by kind and id.
+    pub fn search(&self, query: &str) -> Vec<Hit> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let id = query.parse::<u32>().ok();
+        let mut hits: Vec<(Rank, &Hit)> = self.entries.iter()
+            .filter_map(|(hit, name)| {
+                let rank = if Some(hit.id) == id || *name == query {
+                    Rank::Exact
+                } else if name.starts_with(&query) {
+                    Rank::Prefix
+                } else if name.split_whitespace().any(|word| word.starts_with(&query)) {
+                    Rank::WordPrefix
+                } else if name.contains(&query) {
+                    Rank::Contains
+                } else {
+                    return None;
+                };
+                Some((rank, hit))
+            })
+            .collect();
+        hits.sort_by_key(|&(rank, hit)| (rank, hit.kind, hit.id));
+        hits.into_iter().map(|(_, hit)| hit.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core_rules::table::{parse_items, parse_monsters};
+
+    #[test]
+    fn test_search() {
+        let tables = Tables {
+            items: parse_items("id\tname\tattack\tdefense\taccuracy\tevasion\n\
+                                1\tRat Tail\t0\t0\t0\t0\n\
+                                2\tPirate Hat\t0\t1\t0\t0\n").unwrap(),
+            monsters: parse_monsters("id\tname\tlevel\thp\texp\tattack\tdefense\taccuracy\tevasion\n\
+                                      1\tRat\t1\t30\t5\t4\t0\t5\t0\n\
+                                      2\tGiant Rat\t4\t90\t20\t9\t2\t8\t3\n").unwrap(),
+            ..Tables::default()
+        };
+        let index = Index::new(&tables);
+        let names = |query: &str| index.search(query).into_iter().map(|hit| hit.name).collect::<Vec<_>>();
+        assert_eq!(names("RAT"), vec!["Rat", "Rat Tail", "Giant Rat", "Pirate Hat"]);
+        assert_eq!(names(" hat"), vec!["Pirate Hat"]);
+        assert_eq!(names("2"), vec!["Pirate Hat", "Giant Rat"]);
+        assert!(names("").is_empty() && names("dragon").is_empty());
+        assert_eq!(index.search("rat")[0], Hit { kind: Kind::Monster, id: 1, name: "Rat".into() });
+    }
+}
@@ -0,0 +1,121 @@
+//! Compact per-map binary consumed by the server, so it never has to parse
+//! sprite data or the full RMM files at runtime.
+//!
+//! [HEADER]
+//! String "Novluno ServerMap" (first byte indicates how long the string is)
+//! int version
+//! int map number
+//! int map size x
+//! int map size y
+//!
+//! [Passability]
+//! (size x * size y) bits, row major, LSB first; a set bit is a walkable tile
+//!
+//! [Warps]
+//! int number of warps
+//! int x, int y, int warp value
+//!
+//! [Spawn regions]
+//! int number of regions
+//! int id, int left, int top, int right, int bottom
+
+use byteorder::WriteBytesExt;
+use byteorder::LittleEndian as LE;
+
+use core_compat::entity::map::Map;
+
+pub const SERVER_MAP_IDENTIFIER: &str = "Novluno ServerMap";
+pub const SERVER_MAP_VERSION: u32 = 1;
+
+pub struct SpawnRegion {
+    pub id: u32,
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+pub fn encode_server_map(map: &Map, spawns: &[SpawnRegion]) -> Vec<u8> {
+    let mut out = Vec::<u8>::new();
+
+    // header
+    out.push(SERVER_MAP_IDENTIFIER.len() as u8);
+    out.extend_from_slice(SERVER_MAP_IDENTIFIER.as_bytes());
+    out.write_u32::<LE>(SERVER_MAP_VERSION).unwrap();
+    out.write_u32::<LE>(map.number()).unwrap();
+    out.write_u32::<LE>(map.size_x()).unwrap();
+    out.write_u32::<LE>(map.size_y()).unwrap();
+
+    // passability bitset
+    let mut bits = vec![0u8; map.tile_count().div_ceil(8)];
+    for (idx, tile) in map.tiles().iter().enumerate() {
+        if tile.is_passable() {
+            bits[idx / 8] |= 1 << (idx % 8);
+        }
+    }
+    out.extend_from_slice(&bits);
+
+    // warps
+    let size_x = map.size_x().max(1);
+    let warps: Vec<(u32, u32, u32)> = map.tiles().iter()
+        .enumerate()
+        .filter(|&(_, tile)| tile.warp != 0)
+        .map(|(idx, tile)| (idx as u32 % size_x, idx as u32 / size_x, tile.warp))
+        .collect();
+    out.write_u32::<LE>(warps.len() as u32).unwrap();
+    for (x, y, warp) in warps {
+        out.write_u32::<LE>(x).unwrap();
+        out.write_u32::<LE>(y).unwrap();
+        out.write_u32::<LE>(warp).unwrap();
+    }
+
+    // spawn regions
+    out.write_u32::<LE>(spawns.len() as u32).unwrap();
+    for region in spawns {
+        out.write_u32::<LE>(region.id).unwrap();
+        out.write_u32::<LE>(region.left).unwrap();
+        out.write_u32::<LE>(region.top).unwrap();
+        out.write_u32::<LE>(region.right).unwrap();
+        out.write_u32::<LE>(region.bottom).unwrap();
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core_compat::entity::entry::Entry;
+    use core_compat::entity::map_tile::MapTile;
+
+    fn tile(warp: u32, collision: u32) -> MapTile {
+        MapTile {
+            obj_rmd_entry: Entry::new(0, 0),
+            tle_rmd_entry: Entry::new(0, 0),
+            warp,
+            collision,
+        }
+    }
+
+    #[test]
+    fn test_encode_server_map() {
+        let mut map = Map::new();
+        map.set_map_number(7);
+        map.set_size_x(3);
+        map.set_size_y(1);
+        map.add_tile(tile(0, 0));
+        map.add_tile(tile(0, 1));
+        map.add_tile(tile(16, 0));
+
+        let data = encode_server_map(&map, &[]);
+        let hdr_len = 1 + SERVER_MAP_IDENTIFIER.len();
+        assert_eq!(&data[1..hdr_len], SERVER_MAP_IDENTIFIER.as_bytes());
+        // passability: tiles 0 and 2 are walkable
+        assert_eq!(data[hdr_len + 16], 0b101);
+        // one warp at (2, 0)
+        assert_eq!(&data[hdr_len + 17..hdr_len + 21], &[1, 0, 0, 0]);
+        assert_eq!(&data[hdr_len + 21..hdr_len + 25], &[2, 0, 0, 0]);
+        assert_eq!(data.len(), hdr_len + 16 + 1 + 4 + 12 + 4);
+    }
+}
@@ -0,0 +1,229 @@
+//! A small Mustache-like template engine for the generated pages.
+//!
+//! ```text
+//! {{name}}                   the value, HTML escaped
+//! {{{name}}}                 the value as is
+//! {{#name}}..{{/name}}       once per entry of a list, or once if the
+//!                            value is set and not empty
+//! {{^name}}..{{/name}}       once if the value is missing or empty
+//! ```
+//!
+//! Inside a list section the names are looked up in the entry first and then
+//! in the enclosing contexts.
+
+use std::collections::BTreeMap;
+
+use crate::error::Error;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    List(Vec<Context>),
+}
+
+pub type Context = BTreeMap<String, Value>;
+
+impl Value {
+    fn is_empty(&self) -> bool {
+        match *self {
+            Value::Text(ref text) => text.is_empty(),
+            Value::List(ref list) => list.is_empty(),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(text: &'a str) -> Value {
+        Value::Text(text.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(text: String) -> Value {
+        Value::Text(text)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(number: u32) -> Value {
+        Value::Text(number.to_string())
+    }
+}
+
+impl From<Vec<Context>> for Value {
+    fn from(list: Vec<Context>) -> Value {
+        Value::List(list)
+    }
+}
+
+/// A context from names and values
+pub fn context(entries: Vec<(&str, Value)>) -> Context {
+    entries.into_iter().map(|(name, value)| (name.to_string(), value)).collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Literal(String),
+    Escaped(String),
+    Raw(String),
+    Section { name: String, inverted: bool, children: Vec<Node> },
+}
+
+/// A section being parsed: its name and whether it's inverted, and its
+/// nodes so far
+type Frame = (Option<(String, bool)>, Vec<Node>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+impl Template {
+    pub fn parse(text: &str) -> Result<Template, Error> {
+        // the sections being parsed, innermost last
+        let mut stack: Vec<Frame> = vec![(None, Vec::new())];
+        let mut rest = text;
+        while let Some(start) = rest.find("{{") {
+            push(&mut stack, Node::Literal(rest[..start].into()));
+            let tag = &rest[start..];
+            let (inner, length) = if tag.starts_with("{{{") {
+                let end = tag.find("}}}").ok_or_else(|| unclosed(tag))?;
+                (&tag[2..end + 1], end + 3)
+            } else {
+                let end = tag.find("}}").ok_or_else(|| unclosed(tag))?;
+                (&tag[2..end], end + 2)
+            };
+            rest = &tag[length..];
+
+            let (sigil, name) = inner.split_at(inner.chars().next().map_or(0, char::len_utf8));
+            let name = name.trim().to_string();
+            match sigil {
+                "{" => push(&mut stack, Node::Raw(name.trim_end_matches('}').trim().into())),
+                "#" | "^" => stack.push((Some((name, sigil == "^")), Vec::new())),
+                "/" => match stack.pop() {
+                    Some((Some((open, inverted)), children)) if open == name => {
+                        push(&mut stack, Node::Section { name, inverted, children });
+                    }
+                    _ => return Err(Error::Template(format!("`{{{{/{}}}}}` closes no open section", name))),
+                },
+                _ => push(&mut stack, Node::Escaped(inner.trim().into())),
+            }
+        }
+        push(&mut stack, Node::Literal(rest.into()));
+        match stack.pop() {
+            Some((None, nodes)) if stack.is_empty() => Ok(Template { nodes }),
+            Some((Some((name, _)), _)) => Err(Error::Template(format!("section `{}` isn't closed", name))),
+            _ => Err(Error::Template("unbalanced sections".into())),
+        }
+    }
+
+    pub fn render(&self, context: &Context) -> String {
+        let mut out = String::new();
+        render(&self.nodes, &mut vec![context], &mut out);
+        out
+    }
+}
+
+fn push(stack: &mut [Frame], node: Node) {
+    if node == Node::Literal(String::new()) {
+        return;
+    }
+    if let Some(&mut (_, ref mut nodes)) = stack.last_mut() {
+        nodes.push(node);
+    }
+}
+
+fn unclosed(tag: &str) -> Error {
+    Error::Template(format!("unclosed tag `{}`", tag.chars().take(20).collect::<String>()))
+}
+
+fn lookup<'a>(contexts: &[&'a Context], name: &str) -> Option<&'a Value> {
+    contexts.iter().rev().filter_map(|context| context.get(name)).next()
+}
+
+fn render(nodes: &[Node], contexts: &mut Vec<&Context>, out: &mut String) {
+    for node in nodes.iter() {
+        match *node {
+            Node::Literal(ref text) => out.push_str(text),
+            Node::Escaped(ref name) => {
+                if let Some(Value::Text(text)) = lookup(contexts, name) {
+                    out.push_str(&escape(text));
+                }
+            }
+            Node::Raw(ref name) => {
+                if let Some(Value::Text(text)) = lookup(contexts, name) {
+                    out.push_str(text);
+                }
+            }
+            Node::Section { ref name, inverted, ref children } => {
+                let value = lookup(contexts, name);
+                let empty = value.is_none_or(Value::is_empty);
+                match value {
+                    _ if inverted => if empty {
+                        render(children, contexts, out);
+                    },
+                    Some(Value::List(list)) => {
+                        for entry in list.iter() {
+                            contexts.push(entry);
+                            render(children, contexts, out);
+                            contexts.pop();
+                        }
+                    }
+                    _ => if !empty {
+                        render(children, contexts, out);
+                    },
+                }
+            }
+        }
+    }
+}
+
+/// Escapes text for HTML content and attribute values
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for chr in text.chars() {
+        match chr {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            chr => out.push(chr),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let template = Template::parse("<h1>{{ name }}</h1>{{{html}}}\
+                                        <ul>{{#drops}}<li>{{item}} of {{name}}</li>{{/drops}}</ul>\
+                                        {{^spawns}}nowhere{{/spawns}}{{#note}}!{{/note}}").unwrap();
+        let drops = vec![context(vec![("item", "Gold".into())]), context(vec![("item", 7.into())])];
+        let page = context(vec![
+            ("name", "Rat & <Mouse>".into()),
+            ("html", "<b>big</b>".into()),
+            ("drops", drops.into()),
+            ("spawns", Vec::new().into()),
+            ("note", "".into()),
+        ]);
+        assert_eq!(template.render(&page),
+                   "<h1>Rat &amp; &lt;Mouse&gt;</h1><b>big</b>\
+                    <ul><li>Gold of Rat &amp; &lt;Mouse&gt;</li><li>7 of Rat &amp; &lt;Mouse&gt;</li></ul>\
+                    nowhere");
+    }
+
+    #[test]
+    fn test_invalid() {
+        for text in ["{{#a}}", "{{/a}}", "{{#a}}{{/b}}", "{{name", "{{{name}}"].iter() {
+            match Template::parse(text) {
+                Err(Error::Template(_)) => (),
+                other => panic!("{:?} gave {:?}", text, other),
+            }
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! The bit of TOML the rule and profile files are written in: `[[<table>]]`
+//! headers and `key = value` lines of strings, numbers, booleans and arrays
+//! of those, which may span lines. `#` starts a comment.
+
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Array(Vec<Value>),
+    Bool(bool),
+    Number(f64),
+    Str(String),
+}
+
+/// A `[[<table>]]` of the file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    /// Where its header is
+    pub line: usize,
+    pub fields: BTreeMap<String, Value>,
+}
+
+/// The `[[<name>]]` tables of the file, and what's wrong with it as
+/// `<line>: <problem>`
+pub fn parse_tables(text: &str, name: &str) -> (Vec<Table>, Vec<String>) {
+    let header = format!("[[{}]]", name);
+    let mut tables: Vec<Table> = Vec::new();
+    let mut problems = Vec::new();
+    let mut lines = text.lines().enumerate();
+    while let Some((number, line)) = lines.next() {
+        let number = number + 1;
+        let mut line = strip_comment(line).trim().to_string();
+        if line.is_empty() {
+            continue;
+        }
+        if line == header {
+            tables.push(Table { line: number, fields: BTreeMap::new() });
+            continue;
+        }
+        // arrays go on until their brackets are closed
+        while depth(&line) > 0 {
+            match lines.next() {
+                Some((_, next)) => {
+                    line.push(' ');
+                    line.push_str(strip_comment(next).trim());
+                }
+                None => break,
+            }
+        }
+        let (key, value) = match line.find('=') {
+            Some(at) => (line[..at].trim(), line[at + 1..].trim()),
+            None => {
+                problems.push(format!("{}: expected `{}` or `key = value`", number, header));
+                continue;
+            }
+        };
+        match (tables.last_mut(), parse_value(value)) {
+            (None, _) => problems.push(format!("{}: `{}` is outside of a `{}`", number, key, header)),
+            (_, None) => problems.push(format!("{}: can't read the value of `{}`", number, key)),
+            (Some(table), Some(value)) => {
+                if table.fields.insert(key.to_string(), value).is_some() {
+                    problems.push(format!("{}: `{}` is there twice", number, key));
+                }
+            }
+        }
+    }
+    (tables, problems)
+}
+
+/// The line without its comment, if any
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (at, chr) in line.char_indices() {
+        match chr {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..at],
+            _ => (),
+        }
+    }
+    line
+}
+
+/// How many brackets are left open
+fn depth(text: &str) -> i32 {
+    let mut quoted = false;
+    let mut depth = 0;
+    for chr in text.chars() {
+        match chr {
+            '"' => quoted = !quoted,
+            '[' if !quoted => depth += 1,
+            ']' if !quoted => depth -= 1,
+            _ => (),
+        }
+    }
+    depth
+}
+
+fn parse_value(text: &str) -> Option<Value> {
+    let mut chars = text.chars().peekable();
+    let value = parse_item(&mut chars)?;
+    if chars.any(|chr| !chr.is_whitespace()) {
+        return None;
+    }
+    Some(value)
+}
+
+fn parse_item(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<Value> {
+    while chars.peek()?.is_whitespace() {
+        chars.next();
+    }
+    match *chars.peek()? {
+        '"' => {
+            chars.next();
+            let mut string = String::new();
+            loop {
+                match chars.next()? {
+                    '"' => return Some(Value::Str(string)),
+                    '\\' => match chars.next()? {
+                        'n' => string.push('\n'),
+                        't' => string.push('\t'),
+                        chr => string.push(chr),
+                    },
+                    chr => string.push(chr),
+                }
+            }
+        }
+        '[' => {
+            chars.next();
+            let mut items = Vec::new();
+            loop {
+                while chars.peek()?.is_whitespace() {
+                    chars.next();
+                }
+                if *chars.peek()? == ']' {
+                    chars.next();
+                    return Some(Value::Array(items));
+                }
+                items.push(parse_item(chars)?);
+                while chars.peek()?.is_whitespace() {
+                    chars.next();
+                }
+                match chars.next()? {
+                    ',' => (),
+                    ']' => return Some(Value::Array(items)),
+                    _ => return None,
+                }
+            }
+        }
+        _ => {
+            let mut word = String::new();
+            while let Some(&chr) = chars.peek() {
+                if chr == ',' || chr == ']' || chr.is_whitespace() {
+                    break;
+                }
+                word.push(chr);
+                chars.next();
+            }
+            match word.as_str() {
+                "true" => Some(Value::Bool(true)),
+                "false" => Some(Value::Bool(false)),
+                _ => word.replace('_', "").parse().ok().filter(|n: &f64| n.is_finite()).map(Value::Number),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tables() {
+        let (tables, problems) = parse_tables("# profiles\n\
+                                              [[profile]]\n\
+                                              name = \"web # thumbs\" # small\n\
+                                              sizes = [\n  1, # the first\n  2_000,\n]\n\
+                                              trim = true\n\
+                                              [[profile]]\n\
+                                              scale = -0.5\n", "profile");
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert_eq!(tables.iter().map(|table| table.line).collect::<Vec<_>>(), vec![2, 9]);
+        let fields = &tables[0].fields;
+        assert_eq!(fields["name"], Value::Str("web # thumbs".into()));
+        assert_eq!(fields["sizes"], Value::Array(vec![Value::Number(1.0), Value::Number(2000.0)]));
+        assert_eq!(fields["trim"], Value::Bool(true));
+        assert_eq!(tables[1].fields["scale"], Value::Number(-0.5));
+
+        let (tables, problems) = parse_tables("a = 1\n[[rule]]\nb\nc = nope\nd = 1\nd = 2\ne = [1,\n", "rule");
+        assert_eq!(tables.len(), 1);
+        assert_eq!(problems, vec![
+            "1: `a` is outside of a `[[rule]]`",
+            "3: expected `[[rule]]` or `key = value`",
+            "4: can't read the value of `c`",
+            "6: `d` is there twice",
+            "7: can't read the value of `e`",
+        ]);
+    }
+}
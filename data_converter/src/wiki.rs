@@ -0,0 +1,374 @@
+//! `data_converter wiki <tables> -o <out> [--sprites <rm.sqlite>] [--templates <folder>]`
+//!
+//! Renders a static site for the community wiki from the rule tables in the
+//! folder `<tables>` (see `core_rules::table`): a page per item, monster and
+//! map with a spawn table, linked both ways through the drop and spawn
+//! tables, and an index.
+//!
+//! The pages are rendered with the templates of `template`. The built-in
+//! ones can be replaced by files of the same names in `--templates`:
+//! `page.html` wraps the others, which are `index.html`, `item.html`,
+//! `monster.html` and `map.html`.
+//!
+//! With `--sprites`, the items and monsters get the sprite the database of
+//! `rle2sqlite` lists under the same name, case ignored; that needs the
+//! `sqlite` feature.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+#[cfg(feature = "sqlite")]
+use core_compat::utility::lock::ReadLock;
+use core_rules::table::{Behavior, SpawnEntry, Stats, Tables, CHANCE_SCALE};
+
+use crate::error::Error;
+use crate::template::{context, Context, Template, Value};
+use super::encode_png;
+
+static USAGE: &str = "usage: wiki <tables> -o <out> [--sprites <rm.sqlite>] [--templates <folder>]";
+
+static TEMPLATES: [(&str, &str); 5] = [
+    ("page.html", include_str!("../templates/wiki/page.html")),
+    ("index.html", include_str!("../templates/wiki/index.html")),
+    ("item.html", include_str!("../templates/wiki/item.html")),
+    ("monster.html", include_str!("../templates/wiki/monster.html")),
+    ("map.html", include_str!("../templates/wiki/map.html")),
+];
+
+/// Width of the spawn maps on the page, in pixels
+const SPAWN_MAP_WIDTH: u32 = 400;
+
+/// An RGBA image
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sprite {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+/// Finds the sprite listed under a name
+pub type SpriteLookup<'a> = dyn FnMut(&str) -> Result<Option<Sprite>, Error> + 'a;
+
+pub fn wiki(args: &[String]) -> Result<(), Error> {
+    let mut tables_folder = None;
+    let mut output = None;
+    let mut sprites = None;
+    let mut template_folder = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" => output = Some(iter.next().ok_or_else(|| Error::Args(USAGE.into()))?),
+            "--sprites" => sprites = Some(iter.next().ok_or_else(|| Error::Args(USAGE.into()))?),
+            "--templates" => template_folder = Some(iter.next().ok_or_else(|| Error::Args(USAGE.into()))?),
+            _ if tables_folder.is_none() && !arg.starts_with('-') => tables_folder = Some(arg),
+            _ => return Err(Error::Args(USAGE.into())),
+        }
+    }
+    let (tables_folder, output) = match (tables_folder, output) {
+        (Some(tables), Some(output)) => (tables, Path::new(output)),
+        _ => return Err(Error::Args(USAGE.into())),
+    };
+
+    let tables = Tables::load(Path::new(tables_folder))?;
+    let templates = load_templates(template_folder.map(Path::new))?;
+    let files = match sprites {
+        Some(path) => site(&tables, &templates, &mut open_sprites(Path::new(path))?)?,
+        None => site(&tables, &templates, &mut |_: &str| Ok(None))?,
+    };
+    for (path, data) in files.iter() {
+        let path = output.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, data)?;
+    }
+    println!("wrote {} files to {:?}", files.len(), output);
+    Ok(())
+}
+
+/// The templates by file name, from `folder` where it has them
+fn load_templates(folder: Option<&Path>) -> Result<BTreeMap<&'static str, Template>, Error> {
+    let mut templates = BTreeMap::new();
+    for &(name, built_in) in TEMPLATES.iter() {
+        let text = match folder.map(|folder| folder.join(name)).filter(|path| path.exists()) {
+            Some(path) => fs::read_to_string(path)?,
+            None => built_in.to_string(),
+        };
+        let template = Template::parse(&text)
+            .map_err(|error| Error::Template(format!("{}: {:?}", name, error)))?;
+        templates.insert(name, template);
+    }
+    Ok(templates)
+}
+
+#[cfg(feature = "sqlite")]
+pub fn open_sprites(path: &Path) -> Result<impl FnMut(&str) -> Result<Option<Sprite>, Error>, Error> {
+    let read = ReadLock::acquire(path)?;
+    let connection = sql::Connection::open_with_flags(path, sql::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    Ok(move |name: &str| {
+        // held for as long as the sprites are looked up
+        let _read = &read;
+        let mut statement = connection.prepare_cached(
+            "SELECT rle.width, rle.height, rle.image FROM list
+             JOIN rle ON rle.type = list.type AND rle.file_num = list.file_num AND rle.file_idx = list.file_idx
+             WHERE list.name = ?1 COLLATE NOCASE
+             ORDER BY list.inferred, list.gid LIMIT 1")?;
+        let mut rows = statement.query([name])?;
+        match rows.next()? {
+            Some(row) => {
+                let (width, height, rgba): (u32, u32, Vec<u8>) = (row.get(0)?, row.get(1)?, row.get(2)?);
+                // a sprite that was never decoded has no pixels
+                if rgba.len() == (width * height * 4) as usize {
+                    Ok(Some(Sprite { width, height, rgba }))
+                } else {
+                    Ok(None)
+                }
+            }
+            None => Ok(None),
+        }
+    })
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn open_sprites(_path: &Path) -> Result<impl FnMut(&str) -> Result<Option<Sprite>, Error>, Error> {
+    Err::<fn(&str) -> Result<Option<Sprite>, Error>, _>(
+        Error::Args("--sprites needs data_converter built with the `sqlite` feature".into()))
+}
+
+/// Every file of the site, by path
+fn site(
+    tables: &Tables,
+    templates: &BTreeMap<&'static str, Template>,
+    sprites: &mut SpriteLookup,
+) -> Result<BTreeMap<String, Vec<u8>>, Error> {
+    let mut files = BTreeMap::new();
+    let page = |files: &mut BTreeMap<String, Vec<u8>>, path: String, title: &str, template: &str, mut body: Context| {
+        let root = if path.contains('/') { "../" } else { "" };
+        body.insert("root".into(), root.into());
+        let html = templates[template].render(&body);
+        let page = context(vec![("title", title.into()), ("root", root.into()), ("body", html.into())]);
+        files.insert(path, templates["page.html"].render(&page).into_bytes());
+    };
+    let mut sprite = |files: &mut BTreeMap<String, Vec<u8>>, kind: &str, id: u32, name: &str| -> Result<Value, Error> {
+        Ok(match sprites(name)? {
+            Some(sprite) => {
+                let path = format!("sprites/{}_{}.png", kind, id);
+                files.insert(path.clone(), encode_png(sprite.width, sprite.height, &sprite.rgba)?);
+                path.into()
+            }
+            None => "".into(),
+        })
+    };
+    let monster_name = |id: u32| tables.monsters.get(&id).map_or_else(|| format!("Monster {}", id), |m| m.name.clone());
+    let item_name = |id: u32| tables.items.get(&id).map_or_else(|| format!("Item {}", id), |i| i.name.clone());
+
+    for item in tables.items.values() {
+        let droppers = tables.drops.iter()
+            .filter(|drop| drop.item == item.id)
+            .map(|drop| context(vec![
+                ("monster", drop.monster.into()),
+                ("monster_name", monster_name(drop.monster).into()),
+                ("count", count(drop.min, drop.max).into()),
+                ("chance", chance(drop.chance).into()),
+            ]))
+            .collect::<Vec<_>>();
+        let body = context(vec![
+            ("id", item.id.into()),
+            ("name", item.name.as_str().into()),
+            ("sprite", sprite(&mut files, "item", item.id, &item.name)?),
+            ("stats", stat_rows(item.stats).into()),
+            ("droppers", droppers.into()),
+        ]);
+        page(&mut files, format!("items/{}.html", item.id), &item.name, "item.html", body);
+    }
+
+    for monster in tables.monsters.values() {
+        let drops = tables.drops.iter()
+            .filter(|drop| drop.monster == monster.id)
+            .map(|drop| context(vec![
+                ("item", drop.item.into()),
+                ("item_name", item_name(drop.item).into()),
+                ("count", count(drop.min, drop.max).into()),
+                ("chance", chance(drop.chance).into()),
+            ]))
+            .collect::<Vec<_>>();
+        let spawns = tables.spawns.iter()
+            .filter(|spawn| spawn.monster == monster.id)
+            .map(spawn_row)
+            .collect::<Vec<_>>();
+        let body = context(vec![
+            ("id", monster.id.into()),
+            ("name", monster.name.as_str().into()),
+            ("sprite", sprite(&mut files, "monster", monster.id, &monster.name)?),
+            ("level", monster.level.into()),
+            ("hp", monster.hp.into()),
+            ("exp", monster.exp.into()),
+            ("stats", stat_rows(monster.stats).into()),
+            ("behavior", behavior(&monster.behavior).into()),
+            ("drops", drops.into()),
+            ("spawns", spawns.into()),
+        ]);
+        page(&mut files, format!("monsters/{}.html", monster.id), &monster.name, "monster.html", body);
+    }
+
+    let maps: BTreeSet<u32> = tables.spawns.iter().map(|spawn| spawn.map).collect();
+    for &map in maps.iter() {
+        let entries: Vec<&SpawnEntry> = tables.spawns.iter().filter(|spawn| spawn.map == map).collect();
+        let spawns = entries.iter()
+            .map(|&spawn| {
+                let mut row = spawn_row(spawn);
+                row.insert("monster".into(), spawn.monster.into());
+                row.insert("monster_name".into(), monster_name(spawn.monster).into());
+                row
+            })
+            .collect::<Vec<_>>();
+        let body = context(vec![
+            ("map", map.into()),
+            ("spawn_map", spawn_map(&entries, &monster_name).into()),
+            ("spawns", spawns.into()),
+        ]);
+        page(&mut files, format!("maps/{}.html", map), &format!("Map {}", map), "map.html", body);
+    }
+
+    let items = tables.items.values()
+        .map(|item| context(vec![("id", item.id.into()), ("name", item.name.as_str().into())]))
+        .collect::<Vec<_>>();
+    let monsters = tables.monsters.values()
+        .map(|m| context(vec![("id", m.id.into()), ("name", m.name.as_str().into()), ("level", m.level.into())]))
+        .collect::<Vec<_>>();
+    let maps = maps.iter().map(|&map| context(vec![("map", map.into())])).collect::<Vec<_>>();
+    let body = context(vec![("items", items.into()), ("monsters", monsters.into()), ("maps", maps.into())]);
+    page(&mut files, "index.html".into(), "Index", "index.html", body);
+    Ok(files)
+}
+
+fn stat_rows(stats: Stats) -> Vec<Context> {
+    [("Attack", stats.attack), ("Defense", stats.defense), ("Accuracy", stats.accuracy), ("Evasion", stats.evasion)]
+        .iter()
+        .map(|&(stat, value)| context(vec![("stat", stat.into()), ("value", value.into())]))
+        .collect()
+}
+
+fn spawn_row(spawn: &SpawnEntry) -> Context {
+    let respawn = match spawn.jitter {
+        0 => format!("{} s", spawn.respawn),
+        jitter => format!("{} s ± {}%", spawn.respawn, jitter),
+    };
+    context(vec![
+        ("map", spawn.map.into()),
+        ("count", spawn.count.into()),
+        ("area", format!("({}, {})-({}, {})", spawn.left, spawn.top, spawn.right, spawn.bottom).into()),
+        ("respawn", respawn.into()),
+    ])
+}
+
+fn count(min: u32, max: u32) -> String {
+    if min == max { min.to_string() } else { format!("{}-{}", min, max) }
+}
+
+fn chance(chance: u32) -> String {
+    format!("{}%", chance as f64 * 100.0 / CHANCE_SCALE as f64)
+}
+
+fn behavior(behavior: &Behavior) -> String {
+    let mut text = if behavior.aggressive {
+        format!("attacks within {} tiles", behavior.aggro_radius)
+    } else {
+        "attacks when attacked".to_string()
+    };
+    text.push_str(&format!(", chases up to {} tiles from home", behavior.leash_radius));
+    if behavior.wander_radius > 0 {
+        text.push_str(&format!(", wanders {} tiles", behavior.wander_radius));
+    }
+    text
+}
+
+/// An SVG of the spawn regions on a map, a unit per tile
+fn spawn_map(spawns: &[&SpawnEntry], monster_name: &dyn Fn(u32) -> String) -> String {
+    let width = spawns.iter().map(|spawn| spawn.right as u32 + 1).max().unwrap_or(1);
+    let height = spawns.iter().map(|spawn| spawn.bottom as u32 + 1).max().unwrap_or(1);
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" viewBox=\"0 0 {} {}\">\n\
+         <rect width=\"{}\" height=\"{}\" fill=\"#eee\"/>\n",
+        SPAWN_MAP_WIDTH, width, height, width, height);
+    for spawn in spawns.iter() {
+        svg.push_str(&format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#c33\" fill-opacity=\"0.4\">\
+             <title>{} x {}</title></rect>\n",
+            spawn.left, spawn.top, spawn.right - spawn.left + 1, spawn.bottom - spawn.top + 1,
+            spawn.count, crate::template::escape(&monster_name(spawn.monster))));
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core_rules::table::{parse_drops, parse_items, parse_monsters, parse_spawns};
+
+    fn tables() -> Tables {
+        Tables {
+            items: parse_items("id\tname\tattack\tdefense\taccuracy\tevasion\n7\tRat <Tail>\t0\t0\t0\t0\n").unwrap(),
+            monsters: parse_monsters("id\tname\tlevel\thp\texp\tattack\tdefense\taccuracy\tevasion\n\
+                                      1\tRat\t1\t30\t5\t4\t0\t5\t0\n").unwrap(),
+            drops: parse_drops("monster\titem\tchance\tmin\tmax\n1\t7\t125\t1\t2\n").unwrap(),
+            spawns: parse_spawns("map\tleft\ttop\tright\tbottom\tmonster\tcount\trespawn\tjitter\n\
+                                  3\t10\t10\t20\t15\t1\t5\t30\t0\n").unwrap(),
+            ..Tables::default()
+        }
+    }
+
+    fn text(files: &BTreeMap<String, Vec<u8>>, path: &str) -> String {
+        String::from_utf8(files[path].clone()).unwrap()
+    }
+
+    #[test]
+    fn test_site() {
+        let templates = load_templates(None).unwrap();
+        let mut looked_up = Vec::new();
+        let files = site(&tables(), &templates, &mut |name: &str| {
+            looked_up.push(name.to_string());
+            Ok(if name == "Rat" { Some(Sprite { width: 1, height: 1, rgba: vec![255; 4] }) } else { None })
+        }).unwrap();
+        assert_eq!(looked_up, vec!["Rat <Tail>", "Rat"]);
+        assert_eq!(files.keys().cloned().collect::<Vec<_>>(),
+                   vec!["index.html", "items/7.html", "maps/3.html", "monsters/1.html", "sprites/monster_1.png"]);
+        assert!(files["sprites/monster_1.png"].starts_with(b"\x89PNG"));
+
+        let item = text(&files, "items/7.html");
+        assert!(item.contains("<title>Rat &lt;Tail&gt; - Novluno wiki</title>"));
+        assert!(item.contains("<a href=\"../monsters/1.html\">Rat</a>: 1-2, 12.5%"));
+        assert!(!item.contains("<img"));
+
+        let monster = text(&files, "monsters/1.html");
+        assert!(monster.contains("<img class=\"sprite\" src=\"../sprites/monster_1.png\" alt=\"Rat\">"));
+        assert!(monster.contains("<a href=\"../maps/3.html\">Map 3</a>: 5 in (10, 10)-(20, 15), back after 30 s"));
+        assert!(monster.contains("attacks within 5 tiles"));
+
+        let map = text(&files, "maps/3.html");
+        assert!(map.contains("viewBox=\"0 0 21 16\""));
+        assert!(map.contains("<rect x=\"10\" y=\"10\" width=\"11\" height=\"6\""));
+        assert!(text(&files, "index.html").contains("<a href=\"monsters/1.html\">Rat</a> (level 1)"));
+    }
+
+    #[test]
+    fn test_template_override() {
+        let folder = std::env::temp_dir().join(format!("novluno_wiki_{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(folder.join("item.html"), "<p>{{name}} is item {{id}}</p>").unwrap();
+        let templates = load_templates(Some(&folder)).unwrap();
+        let files = site(&tables(), &templates, &mut |_: &str| Ok(None)).unwrap();
+        assert!(text(&files, "items/7.html").contains("<p>Rat &lt;Tail&gt; is item 7</p>"));
+
+        fs::write(folder.join("map.html"), "{{#spawns}}").unwrap();
+        match load_templates(Some(&folder)) {
+            Err(Error::Template(ref message)) if message.starts_with("map.html") => (),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,285 @@
+//! `data_converter world <tables> -o <out.dot|out.graphml|out.html> [--maps <folder>]`
+//!
+//! Exports how the maps connect: a node per map and an edge from each map to
+//! every map its warps lead to, from the warp table of the rule tables in
+//! `<tables>` (see `core_rules::table`). The format goes by the extension of
+//! the output: Graphviz DOT, GraphML, or a page laying the graph out by
+//! itself, force-directed, with the maps drawn by `minimap`.
+//!
+//! The maps come from the RMM files in `--maps`; without it the page has no
+//! pictures and the other formats no map sizes.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use core_compat::entity::map::Map;
+use core_rules::table::{Tables, WarpEntry};
+
+use crate::error::Error;
+use crate::minimap::minimap;
+use crate::template::{context, Template};
+use super::{encode_png, json_string, load_rmm_data};
+
+static USAGE: &str = "usage: world <tables> -o <out.dot|out.graphml|out.html> [--maps <folder>]";
+
+static PAGE: &str = include_str!("../templates/world.html");
+
+/// Longer side of the map pictures on the page, in pixels
+const THUMBNAIL_SIZE: u32 = 96;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Node {
+    map: u32,
+    /// In tiles, when the map file was there
+    size: Option<(u32, u32)>,
+    /// PNG
+    thumbnail: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Graph {
+    nodes: BTreeMap<u32, Node>,
+    /// How many warps lead from a map to another
+    edges: BTreeMap<(u32, u32), u32>,
+}
+
+pub fn world(args: &[String]) -> Result<(), Error> {
+    let mut tables_folder = None;
+    let mut output = None;
+    let mut maps_folder = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" => output = Some(iter.next().ok_or_else(|| Error::Args(USAGE.into()))?),
+            "--maps" => maps_folder = Some(iter.next().ok_or_else(|| Error::Args(USAGE.into()))?),
+            _ if tables_folder.is_none() => tables_folder = Some(arg),
+            _ => return Err(Error::Args(USAGE.into())),
+        }
+    }
+    let (tables_folder, output) = match (tables_folder, output) {
+        (Some(tables), Some(output)) => (tables, output),
+        _ => return Err(Error::Args(USAGE.into())),
+    };
+
+    let tables = Tables::load(Path::new(tables_folder))?;
+    let maps = match maps_folder {
+        Some(folder) => load_maps(Path::new(folder))?,
+        None => Vec::new(),
+    };
+    let graph = graph(&tables.warps, &maps)?;
+    let text = if output.ends_with(".dot") || output.ends_with(".gv") {
+        to_dot(&graph)
+    } else if output.ends_with(".graphml") {
+        to_graphml(&graph)?
+    } else {
+        to_html(&graph)?
+    };
+    fs::write(output, text)?;
+    println!("wrote {} maps and {} links to {}", graph.nodes.len(), graph.edges.len(), output);
+    Ok(())
+}
+
+/// Every map file in `folder`; the ones that don't parse are left out with
+/// a message.
+fn load_maps(folder: &Path) -> Result<Vec<Map>, Error> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(folder)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+    let mut maps = Vec::new();
+    for path in paths {
+        match load_rmm_data(&path) {
+            Ok(map) => maps.push(map),
+            Err(e) => println!("skipping {:?}: {:?}", path, e),
+        }
+    }
+    Ok(maps)
+}
+
+/// The maps with a file or a warp, and the links between them
+fn graph(warps: &[WarpEntry], maps: &[Map]) -> Result<Graph, Error> {
+    let mut nodes = BTreeMap::new();
+    for map in maps.iter() {
+        let (width, height, rgba) = minimap(map, THUMBNAIL_SIZE);
+        let node = Node {
+            map: map.number(),
+            size: Some((map.size_x(), map.size_y())),
+            thumbnail: Some(encode_png(width, height, &rgba)?),
+        };
+        nodes.insert(map.number(), node);
+    }
+    let mut edges = BTreeMap::new();
+    for warp in warps.iter() {
+        for &map in [warp.map, warp.to_map].iter() {
+            nodes.entry(map).or_insert(Node { map, size: None, thumbnail: None });
+        }
+        *edges.entry((warp.map, warp.to_map)).or_insert(0) += 1;
+    }
+    Ok(Graph { nodes, edges })
+}
+
+fn label(node: &Node) -> String {
+    match node.size {
+        Some((x, y)) => format!("Map {} ({}x{})", node.map, x, y),
+        None => format!("Map {}", node.map),
+    }
+}
+
+fn to_dot(graph: &Graph) -> String {
+    let mut dot = String::from("digraph world {\n    node [shape=box];\n");
+    for node in graph.nodes.values() {
+        dot.push_str(&format!("    map_{} [label={}];\n", node.map, json_string(&label(node))));
+    }
+    for (&(from, to), &warps) in graph.edges.iter() {
+        dot.push_str(&format!("    map_{} -> map_{}", from, to));
+        if warps > 1 {
+            dot.push_str(&format!(" [label=\"{}\"]", warps));
+        }
+        dot.push_str(";\n");
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+fn to_graphml(graph: &Graph) -> Result<String, Error> {
+    let mut xml = xml_writer::XmlWriter::new(Vec::new());
+    xml.dtd("UTF-8")?;
+    xml.begin_elem("graphml")?;
+    xml.attr("xmlns", "http://graphml.graphdrawing.org/xmlns")?;
+    for &(id, target, name, kind) in [("label", "node", "label", "string"), ("warps", "edge", "warps", "int")].iter() {
+        xml.begin_elem("key")?;
+        xml.attr("id", id)?;
+        xml.attr("for", target)?;
+        xml.attr("attr.name", name)?;
+        xml.attr("attr.type", kind)?;
+        xml.end_elem()?;
+    }
+    xml.begin_elem("graph")?;
+    xml.attr("id", "world")?;
+    xml.attr("edgedefault", "directed")?;
+    for node in graph.nodes.values() {
+        xml.begin_elem("node")?;
+        xml.attr("id", &format!("map_{}", node.map))?;
+        xml.begin_elem("data")?;
+        xml.attr("key", "label")?;
+        xml.text(&label(node))?;
+        xml.end_elem()?;
+        xml.end_elem()?;
+    }
+    for (&(from, to), &warps) in graph.edges.iter() {
+        xml.begin_elem("edge")?;
+        xml.attr("source", &format!("map_{}", from))?;
+        xml.attr("target", &format!("map_{}", to))?;
+        xml.begin_elem("data")?;
+        xml.attr("key", "warps")?;
+        xml.text(&warps.to_string())?;
+        xml.end_elem()?;
+        xml.end_elem()?;
+    }
+    xml.end_elem()?;
+    xml.end_elem()?;
+    xml.close()?;
+    xml.flush()?;
+    Ok(String::from_utf8_lossy(&xml.into_inner()).into_owned())
+}
+
+fn to_html(graph: &Graph) -> Result<String, Error> {
+    let nodes: Vec<String> = graph.nodes.values()
+        .map(|node| {
+            let thumbnail = node.thumbnail.as_ref()
+                .map_or("null".into(), |png| format!("\"data:image/png;base64,{}\"", base64(png)));
+            format!("{{\"id\": {}, \"label\": {}, \"thumbnail\": {}}}", node.map, json_string(&label(node)), thumbnail)
+        })
+        .collect();
+    let edges: Vec<String> = graph.edges.iter()
+        .map(|(&(from, to), &warps)| format!("{{\"from\": {}, \"to\": {}, \"warps\": {}}}", from, to, warps))
+        .collect();
+    let json = format!("{{\"nodes\": [{}], \"edges\": [{}]}}", nodes.join(", "), edges.join(", "));
+    let page = context(vec![
+        ("maps", (graph.nodes.len() as u32).into()),
+        ("links", (graph.edges.len() as u32).into()),
+        ("graph", json.into()),
+    ]);
+    Ok(Template::parse(PAGE)?.render(&page))
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (idx, &byte)| bits | (byte as u32) << (16 - 8 * idx));
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                out.push(ALPHABET[(bits >> (18 - 6 * idx) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core_compat::entity::entry::Entry;
+    use core_compat::entity::map_tile::MapTile;
+
+    fn warp(map: u32, to_map: u32) -> WarpEntry {
+        WarpEntry { map, x: 0, y: 0, to_map, to_x: 0, to_y: 0 }
+    }
+
+    fn graph_with_map() -> Graph {
+        let mut map = Map::new();
+        map.set_map_number(1);
+        map.set_size_x(2);
+        map.set_size_y(1);
+        for _ in 0..2 {
+            map.add_tile(MapTile { obj_rmd_entry: Entry::new(0, 0), tle_rmd_entry: Entry::new(0, 0), warp: 0, collision: 0 });
+        }
+        graph(&[warp(1, 2), warp(1, 2), warp(2, 1), warp(2, 3)], &[map]).unwrap()
+    }
+
+    #[test]
+    fn test_graph() {
+        let graph = graph_with_map();
+        assert_eq!(graph.nodes.keys().cloned().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(graph.nodes[&1].size, Some((2, 1)));
+        assert!(graph.nodes[&1].thumbnail.as_ref().is_some_and(|png| png.starts_with(b"\x89PNG")));
+        assert_eq!(graph.nodes[&3], Node { map: 3, size: None, thumbnail: None });
+        assert_eq!(graph.edges.iter().map(|(&edge, &warps)| (edge, warps)).collect::<Vec<_>>(),
+                   vec![((1, 2), 2), ((2, 1), 1), ((2, 3), 1)]);
+    }
+
+    #[test]
+    fn test_formats() {
+        let graph = graph_with_map();
+        assert_eq!(to_dot(&graph),
+                   "digraph world {\n    node [shape=box];\n\
+                    \x20   map_1 [label=\"Map 1 (2x1)\"];\n    map_2 [label=\"Map 2\"];\n    map_3 [label=\"Map 3\"];\n\
+                    \x20   map_1 -> map_2 [label=\"2\"];\n    map_2 -> map_1;\n    map_2 -> map_3;\n}\n");
+
+        let graphml = to_graphml(&graph).unwrap();
+        assert!(graphml.contains("<node id=\"map_2\">\n      <data key=\"label\">Map 2</data></node>"));
+        assert!(graphml.contains("<edge source=\"map_1\" target=\"map_2\">\n      <data key=\"warps\">2</data></edge>"));
+
+        let html = to_html(&graph).unwrap();
+        assert!(html.contains("3 maps, 3 links"));
+        assert!(html.contains("{\"id\": 1, \"label\": \"Map 1 (2x1)\", \"thumbnail\": \"data:image/png;base64,iVBORw0KGgo"));
+        assert!(html.contains("{\"id\": 3, \"label\": \"Map 3\", \"thumbnail\": null}"));
+        assert!(html.contains("{\"from\": 1, \"to\": 2, \"warps\": 2}"));
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+}
@@ -0,0 +1,234 @@
+//! `data_converter xref [<list>..] [--json] [-o <out>]`
+//!
+//! Cross-references the list files with the RLE files they point into, to
+//! triage incomplete client dumps. Reported are
+//!
+//! - `missing`: list items without a decodable resource,
+//! - `unlisted`: decoded resources no list item points at,
+//! - `collision`: list items sharing the same resource.
+//!
+//! All lists are checked unless some are given by short name. The report is
+//! CSV, or JSON with `--json`, and goes to stdout unless written with `-o`.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use core_compat::entity::entry::Entry;
+use core_compat::entity::list::List;
+use core_compat::entity::resource_file::{ResourceFile, ResourceSlot};
+
+use crate::error::Error;
+use super::RLE_ENTRIES;
+use super::{json_string, load_list_data, load_rle_folder};
+
+static USAGE: &str = "usage: xref [<list>..] [--json] [-o <out>]";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Problem {
+    Missing,
+    Unlisted,
+    Collision,
+}
+
+impl Problem {
+    fn name(self) -> &'static str {
+        match self {
+            Problem::Missing => "missing",
+            Problem::Unlisted => "unlisted",
+            Problem::Collision => "collision",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct XrefRow {
+    pub problem: Problem,
+    pub list: String,
+    /// The list item, if there is one
+    pub item: Option<(u32, String)>,
+    pub entry: Entry,
+    pub detail: &'static str,
+}
+
+pub fn xref(args: &[String]) -> Result<(), Error> {
+    let mut lists = Vec::new();
+    let mut output = None;
+    let mut json = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" => output = Some(iter.next().ok_or_else(|| Error::Args(USAGE.into()))?),
+            "--json" => json = true,
+            _ => lists.push(arg.as_str()),
+        }
+    }
+    for short in lists.iter() {
+        if !RLE_ENTRIES.iter().any(|e| e.1 == *short) {
+            return Err(Error::Args(format!("unknown list `{}`\n{}", short, USAGE)));
+        }
+    }
+
+    let mut rows = Vec::new();
+    for &(_, short, folder, list_path, use_v2) in RLE_ENTRIES.iter() {
+        if !lists.is_empty() && !lists.contains(&short) {
+            continue;
+        }
+        let list = load_list_data(Path::new(list_path), use_v2)?;
+        let files = load_rle_folder(folder)?;
+        rows.extend(cross_reference(short, &list, &files));
+    }
+
+    let report = if json { to_json(&rows) } else { to_csv(&rows) };
+    match output {
+        Some(output) => {
+            File::create(output)?.write_all(report.as_bytes())?;
+            println!("wrote {} rows to {}", rows.len(), output);
+        }
+        None => print!("{}", report),
+    }
+    Ok(())
+}
+
+pub fn cross_reference(short: &str, list: &List, files: &HashMap<u32, ResourceFile>) -> Vec<XrefRow> {
+    let mut rows = Vec::new();
+    let row = |problem, item: Option<(u32, String)>, entry, detail| XrefRow {
+        problem, list: short.into(), item, entry, detail,
+    };
+
+    // list items by the resource they point at, in resource order
+    let mut referenced: BTreeMap<(u32, u32), Vec<(u32, String)>> = BTreeMap::new();
+    for item in list.items.iter() {
+        let key = (item.entry.file(), item.entry.index());
+        referenced.entry(key).or_default().push((item.id, item.name.clone()));
+    }
+
+    for (&(file, index), items) in referenced.iter() {
+        let entry = Entry::new(file, index);
+        let detail = match files.get(&file).map(|f| f.slots.get(index as usize)) {
+            None => Some("no rle file"),
+            Some(None) => Some("index out of range"),
+            Some(Some(&ResourceSlot::Empty)) => Some("empty slot"),
+            Some(Some(&ResourceSlot::Undecoded)) => Some("undecodable resource"),
+            Some(Some(&ResourceSlot::Resource(_))) => None,
+        };
+        if let Some(detail) = detail {
+            for item in items.iter() {
+                rows.push(row(Problem::Missing, Some(item.clone()), entry, detail));
+            }
+        }
+        if items.len() > 1 {
+            for item in items.iter() {
+                rows.push(row(Problem::Collision, Some(item.clone()), entry, "shared resource"));
+            }
+        }
+    }
+
+    let mut file_numbers: Vec<&u32> = files.keys().collect();
+    file_numbers.sort();
+    for &file in file_numbers {
+        for resource in files[&file].resources.iter() {
+            if !referenced.contains_key(&(file, resource.index())) {
+                rows.push(row(Problem::Unlisted, None, Entry::new(file, resource.index()), "no list item"));
+            }
+        }
+    }
+    rows
+}
+
+fn to_csv(rows: &[XrefRow]) -> String {
+    let mut csv = String::from("problem,list,id,name,file,index,detail\n");
+    for row in rows {
+        let (id, name) = match row.item {
+            Some((id, ref name)) => (id.to_string(), format!("\"{}\"", name.replace('"', "\"\""))),
+            None => (String::new(), String::new()),
+        };
+        csv.push_str(&format!("{},{},{},{},{},{},{}\n", row.problem.name(), row.list, id, name,
+                              row.entry.file(), row.entry.index(), row.detail));
+    }
+    csv
+}
+
+fn to_json(rows: &[XrefRow]) -> String {
+    let mut json = String::from("[\n");
+    for (idx, row) in rows.iter().enumerate() {
+        let (id, name) = match row.item {
+            Some((id, ref name)) => (id.to_string(), json_string(name)),
+            None => ("null".into(), "null".into()),
+        };
+        json.push_str(&format!(
+            "  {{\"problem\": \"{}\", \"list\": {}, \"id\": {}, \"name\": {}, \"file\": {}, \"index\": {}, \"detail\": \"{}\"}}",
+            row.problem.name(), json_string(&row.list), id, name, row.entry.file(), row.entry.index(), row.detail));
+        json.push_str(if idx + 1 < rows.len() { ",\n" } else { "\n" });
+    }
+    json.push_str("]\n");
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core_compat::fixture::{LstFixture, RleFixture, ResourceFixture};
+    use core_compat::parser::lst::parse_lst;
+    use core_compat::parser::rle::parse_rle;
+
+    fn files() -> HashMap<u32, ResourceFile> {
+        let resource = || ResourceFixture::new(1, 1).pixels(&[0xF800]);
+        let data = RleFixture::new()
+            .resource(resource())
+            .null()
+            .resource(resource())
+            .resource(ResourceFixture::new(0, 0))
+            .resource(resource())
+            .build();
+        let mut files = HashMap::new();
+        files.insert(1, parse_rle(1, &data).unwrap());
+        files
+    }
+
+    #[test]
+    fn test_cross_reference() {
+        let list = parse_lst(&LstFixture::new("1.0")
+            .item("ok", 0, Entry::new(1, 0))
+            .item("empty", 1, Entry::new(1, 1))
+            .item("twice", 2, Entry::new(1, 2))
+            .item("again", 3, Entry::new(1, 2))
+            .item("broken", 4, Entry::new(1, 3))
+            .item("past end", 5, Entry::new(1, 9))
+            .item("no file", 6, Entry::new(2, 0))
+            .build(), false).unwrap();
+        let rows = cross_reference("obj", &list, &files());
+
+        let summary: Vec<_> = rows.iter()
+            .map(|r| (r.problem, r.item.as_ref().map(|i| i.0), r.entry.file(), r.entry.index(), r.detail))
+            .collect();
+        assert_eq!(summary, vec![
+            (Problem::Missing, Some(1), 1, 1, "empty slot"),
+            (Problem::Collision, Some(2), 1, 2, "shared resource"),
+            (Problem::Collision, Some(3), 1, 2, "shared resource"),
+            (Problem::Missing, Some(4), 1, 3, "undecodable resource"),
+            (Problem::Missing, Some(5), 1, 9, "index out of range"),
+            (Problem::Missing, Some(6), 2, 0, "no rle file"),
+            (Problem::Unlisted, None, 1, 4, "no list item"),
+        ]);
+    }
+
+    #[test]
+    fn test_report_formats() {
+        let rows = vec![
+            XrefRow { problem: Problem::Missing, list: "obj".into(), item: Some((3, "a \"b\"".into())),
+                      entry: Entry::new(1, 2), detail: "empty slot" },
+            XrefRow { problem: Problem::Unlisted, list: "obj".into(), item: None,
+                      entry: Entry::new(1, 4), detail: "no list item" },
+        ];
+        assert_eq!(to_csv(&rows), "problem,list,id,name,file,index,detail\n\
+                                   missing,obj,3,\"a \"\"b\"\"\",1,2,empty slot\n\
+                                   unlisted,obj,,,1,4,no list item\n");
+        assert_eq!(to_json(&rows), "[\n  \
+            {\"problem\": \"missing\", \"list\": \"obj\", \"id\": 3, \"name\": \"a \\\"b\\\"\", \"file\": 1, \"index\": 2, \"detail\": \"empty slot\"},\n  \
+            {\"problem\": \"unlisted\", \"list\": \"obj\", \"id\": null, \"name\": null, \"file\": 1, \"index\": 4, \"detail\": \"no list item\"}\n]\n");
+    }
+}
@@ -11,56 +11,213 @@
 //!    between objects because they could change depending on the input data.
 //!  - The best way it seems to match the data from the `rle` and `list` tables
 //!    is to use the file number and file index
+//!  - The files are imported in sorted order and the gid's are assigned while
+//!    importing, so the same input data always ends up with the same gid's.
+//!    With `--reproducible` the import time is zeroed as well and the database
+//!    is rebuilt from scratch, so two imports are byte-identical.
+//!  - The rows are committed in chunks (`--chunk-size`, 5000 by default) with
+//!    the database in WAL mode. Every chunk records the last gid it inserted in
+//!    the `meta` table, so an interrupted import only loses the current chunk
+//!    and `--resume` carries on after the last committed one.
+//!  - Images above `BLOB_STREAM_THRESHOLD` bytes are inserted as a zeroblob
+//!    and then written through an incremental blob handle, so SQLite doesn't
+//!    need its own copy of the big Chr sheets while binding them.
+//!  - The import holds the lock of the database (see
+//!    `core_compat::utility::lock`); the tools reading it refuse to open it
+//!    meanwhile, and a second import stops right away.
+//!  - Resources no list item points at get an id inferred from their listed
+//!    neighbours (see `core_compat::utility::recover`). Those rows have
+//!    `inferred` set and a `confidence` between 0 and 1; the ones that can't be
+//!    placed are printed.
+//!  - The four header fields of unknown meaning go into `unknown_1..4` as
+//!    read, next to what they mean if the guesses of
+//!    `core_compat::entity::raw_header_extras` are right: `shadow_x`,
+//!    `shadow_y`, `hotspot_x` and `hotspot_y`, NULL where a field doesn't fit
+//!    its guess. `SELECT ... GROUP BY unknown_1` and friends are the way to
+//!    check the guesses across the data set.
+//!  - Every sprite gets statistics of its pixels (see
+//!    `core_compat::entity::image_stats`): `opaque_ratio`, the painted share
+//!    of the canvas, `avg_r`, `avg_g` and `avg_b`, the average color of the
+//!    painted pixels, and `bbox_x`, `bbox_y`, `bbox_w` and `bbox_h`, the
+//!    rectangle around them; NULL for a sprite without any. Mostly red
+//!    icons are `avg_r > 2 * (avg_g + avg_b)`, effectively empty sprites
+//!    `opaque_ratio < 0.01`, and loosely cropped ones
+//!    `bbox_w * bbox_h < width * height / 4`.
+//!  - `hash` is `Resource::content_hash` of the sprite, the same for the
+//!    sprites drawing the same pixels in any file; it is indexed, so
+//!    `SELECT hash, COUNT(*) FROM rle GROUP BY hash HAVING COUNT(*) > 1`
+//!    lists the duplicates quickly.
+//!  - `--only <type>` re-imports one sprite type and `--only-file <name>` a
+//!    single RLE file of any type, replacing just their rows in one
+//!    transaction and leaving the rest of the database alone. The sprites
+//!    keep their gid's (matched by file number and index), the new ones are
+//!    numbered after the highest gid and the ones gone from the file are
+//!    deleted. `--only` rewrites the list rows of the type too, with new
+//!    gid's; `--only-file` leaves them, so run `--only` once the list ids
+//!    could have changed.
 
 extern crate core_compat;
 extern crate rusqlite as sql;
 
+use std::collections::HashMap;
 use std::path::Path;
+use std::path::PathBuf;
 use std::fs::File;
 use std::fs::read_dir;
 use std::io::Read;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use core_compat::entity::entry::Entry;
 use core_compat::entity::resource_file::ResourceFile;
 use core_compat::entity::resource::Resource;
 use core_compat::entity::list::List;
+use core_compat::entity::list_item::ListItem;
 use core_compat::error::Error;
-use core_compat::parser::rle::parse_rle;
+use core_compat::parser::rle::{parse_rle_lenient, PixelFormat};
 use core_compat::parser::lst::parse_lst;
+use core_compat::utility::lock::WriteLock;
+use core_compat::utility::recover::recover_list_ids;
 
 use sql::Connection;
+use sql::DatabaseName;
+use sql::Transaction;
+use sql::types::ToSqlOutput;
 
-// This is the list of data folder's and list files for them
-static FOLDER_ENTRIES: [(&'static str, &'static str, &'static str); 1] = [
-    // ("Bullets", "../data/RLEs/Bul", "../data/RLEs/bul.lst"),
-    // ("Icons", "../data/RLEs/Ico", "../data/RLEs/ico.lst"),
-    // ("Objects", "../data/RLEs/Obj", "../data/RLEs/obj.lst"),
-    // ("Tiles", "../data/RLEs/Tle", "../data/RLEs/tle.lst"),
-    ("Interface", "../data/RLEs/Int", "../data/RLEs/int.lst"),
+// This is the list of data folder's and list files for them, and whether a
+// full import includes them; the others can still be imported with `--only`
+static FOLDER_ENTRIES: [(&'static str, &'static str, &'static str, bool); 5] = [
+    ("Bullets", "../data/RLEs/Bul", "../data/RLEs/bul.lst", false),
+    ("Icons", "../data/RLEs/Ico", "../data/RLEs/ico.lst", false),
+    ("Objects", "../data/RLEs/Obj", "../data/RLEs/obj.lst", false),
+    ("Tiles", "../data/RLEs/Tle", "../data/RLEs/tle.lst", false),
+    ("Interface", "../data/RLEs/Int", "../data/RLEs/int.lst", true),
     // The sounds one is the only one which is a little different...
     // ("Sounds", "../data/RLEs/Snd", "../data/RLEs/snd.lst"),
 ];
 
+static DATABASE_PATH: &str = "./rm.sqlite";
+
+/// Images larger than this are streamed into the database
+const BLOB_STREAM_THRESHOLD: usize = 256 * 1024;
+/// Bytes per incremental blob write
+const BLOB_CHUNK_SIZE: usize = 64 * 1024;
+
+static USAGE: &str = "usage: rle2sqlite [--reproducible] [--resume] [--chunk-size <rows>] | --only <type> | --only-file <name.rle> | --doctor";
+
+/// SQLite 3.7.0 added WAL mode
+const MIN_SQLITE_VERSION: i32 = 3_007_000;
+
 fn main() {
+    let mut reproducible = false;
+    let mut resume = false;
+    let mut chunk_size = 5000;
+    let mut only = None;
+    let mut only_file = None;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--doctor" => {
+                if !doctor() {
+                    std::process::exit(1);
+                }
+                return;
+            }
+            "--reproducible" => reproducible = true,
+            "--resume" => resume = true,
+            "--chunk-size" => match iter.next().and_then(|n| n.parse().ok()) {
+                Some(n) if n > 0 => chunk_size = n,
+                _ => { println!("{}", USAGE); return; }
+            },
+            "--only" => match iter.next() {
+                Some(name) => only = Some(name.clone()),
+                None => { println!("{}", USAGE); return; }
+            },
+            "--only-file" => match iter.next() {
+                Some(name) => only_file = Some(name.clone()),
+                None => { println!("{}", USAGE); return; }
+            },
+            _ => { println!("{}", USAGE); return; }
+        }
+    }
+    // a partial import works on the rows already there
+    if (only.is_some() || only_file.is_some()) && (reproducible || resume || (only.is_some() && only_file.is_some())) {
+        println!("{}", USAGE);
+        return;
+    }
+
+    // held until the import is done, so the tools reading the database don't
+    // open it halfway and a second import doesn't write into this one
+    let _lock = match WriteLock::acquire(Path::new(DATABASE_PATH)) {
+        Ok(lock) => lock,
+        Err(Error::Locked { ref holder, .. }) if holder.is_empty() => {
+            println!("{} is being read; try again once the readers are done", DATABASE_PATH);
+            std::process::exit(1);
+        }
+        Err(Error::Locked { holder, .. }) => {
+            println!("{} is being written by process {}", DATABASE_PATH, holder);
+            std::process::exit(1);
+        }
+        Err(e) => panic!("{:?}", e),
+    };
+
+    if only.is_some() || only_file.is_some() {
+        let mut connection = Connection::open(Path::new(DATABASE_PATH)).unwrap();
+        if let Err(e) = reimport(&mut connection, only.as_ref().map(|s| s.as_str()), only_file.as_ref().map(|s| s.as_str())) {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // a fresh file; dropping the tables would leave the old pages behind
+    if reproducible && !resume {
+        let _ = std::fs::remove_file(DATABASE_PATH);
+    }
 
     // create sqlite database
     // let connection = Connection::open_in_memory().unwrap();
-    let mut connection = Connection::open(Path::new("./rm.sqlite")).unwrap();
+    let mut connection = Connection::open(Path::new(DATABASE_PATH)).unwrap();
+    connection.execute_batch("PRAGMA journal_mode = WAL").unwrap();
+
+    if !resume {
+        let _ = connection.execute("DROP TABLE meta", &[]);
+        let _ = connection.execute("DROP TABLE list", &[]);
+        let _ = connection.execute("DROP TABLE rle", &[]);
+        let _ = connection.execute("DROP TABLE provenance", &[]);
+    }
+
+    // stored by key, so the rewritten checkpoints don't change the file layout
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS meta (
+            key      TEXT PRIMARY KEY,
+            value    TEXT NOT NULL
+        ) WITHOUT ROWID", &[]).unwrap();
 
-    let _ = connection.execute("DROP TABLE list", &[]);
-    let _ = connection.execute("DROP TABLE rle", &[]);
+    let imported_at = if reproducible {
+        0
+    } else {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+    };
+    connection.execute(
+        "INSERT OR REPLACE INTO meta (key, value) VALUES ('imported_at', ?1)",
+        &[&imported_at.to_string()]).unwrap();
 
     connection.execute(
-        "CREATE TABLE list (
+        "CREATE TABLE IF NOT EXISTS list (
             gid      INTEGER PRIMARY KEY,
             type     TEXT NOT NULL,
             file_num INTEGER,
             file_idx INTEGER,
             name     TEXT NOT NULL,
-            list_id  INTEGER
+            list_id  INTEGER,
+            inferred INTEGER NOT NULL DEFAULT 0,
+            confidence REAL
         )", &[]).unwrap();
 
     connection.execute(
-        "CREATE TABLE rle (
+        "CREATE TABLE IF NOT EXISTS rle (
             gid      INTEGER PRIMARY KEY,
             type     TEXT NOT NULL,
             file_num INTEGER,
@@ -70,11 +227,50 @@ fn main() {
             offset_y INTEGER,
             width    INTEGER,
             height   INTEGER,
+            unknown_1 INTEGER NOT NULL,
+            unknown_2 INTEGER NOT NULL,
+            unknown_3 INTEGER NOT NULL,
+            unknown_4 INTEGER NOT NULL,
+            shadow_x  INTEGER,
+            shadow_y  INTEGER,
+            hotspot_x INTEGER,
+            hotspot_y INTEGER,
+            opaque_ratio REAL NOT NULL,
+            avg_r    INTEGER,
+            avg_g    INTEGER,
+            avg_b    INTEGER,
+            bbox_x   INTEGER,
+            bbox_y   INTEGER,
+            bbox_w   INTEGER,
+            bbox_h   INTEGER,
+            hash     INTEGER NOT NULL,
             image    BLOB
         )", &[]).unwrap();
+    connection.execute("CREATE INDEX IF NOT EXISTS rle_hash ON rle (hash)", &[]).unwrap();
+
+    // filled by the tools deriving sprites from these, see
+    // `data_converter provenance`
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS provenance (
+            gid        INTEGER PRIMARY KEY,
+            source_gid INTEGER NOT NULL,
+            operation  TEXT NOT NULL,
+            parameters TEXT NOT NULL
+        )", &[]).unwrap();
+
+    // the gid's are handed out in import order
+    let mut list_gid: i64 = 0;
+    let mut rle_gid: i64 = 0;
+
+    // ... so everything up to the last committed gid's is already imported
+    let list_done = if resume { load_checkpoint(&connection, "list_gid") } else { 0 };
+    let rle_done = if resume { load_checkpoint(&connection, "rle_gid") } else { 0 };
+    if resume {
+        println!("resuming after list gid {} and rle gid {}", list_done, rle_done);
+    }
 
     // parse the list file and insert them into the database
-    for &(_type, folder, list) in FOLDER_ENTRIES.iter() {
+    for &(_type, folder, list, _) in FOLDER_ENTRIES.iter().filter(|entry| entry.3) {
 
         println!("file: {:?}", _type);
 
@@ -83,82 +279,317 @@ fn main() {
         let list = load_list_data(&list_path).unwrap();
         println!("list.items.len() == {:?}", list.items.len());
 
-        // Commit all of the list objects in one transaction
-        {
-            let tx = connection.transaction().unwrap();
-            for item in list.items {
-                // insert the data into the database
-                tx.execute(
-                    "INSERT INTO list (
-                        type, name, list_id, file_num, file_idx)
-                    VALUES (?1, ?2, ?3, ?4, ?5)",
-                    &[&_type, &item.name, &item.id,
-                      &item.entry.file(), &item.entry.index()]
-                ).unwrap();
-            }
-            tx.commit().unwrap();
+        let resources = load_folder(folder);
+        let items = list_items(&list, &resources);
+
+        // Commit the list objects in chunks
+        insert_chunked(&mut connection, &items, list_gid, list_done, chunk_size, "list_gid",
+                       |tx, gid, &(ref item, confidence)| insert_list_item(tx, gid, _type, item, confidence)).unwrap();
+        list_gid += items.len() as i64;
+
+        // Commit the sprite objects in chunks
+        insert_chunked(&mut connection, &resources, rle_gid, rle_done, chunk_size, "rle_gid",
+                       |tx, gid, rle| insert_resource(tx, gid, _type, rle)).unwrap();
+        rle_gid += resources.len() as i64;
+        println!("resources.len() == {:?}", &resources.len());
+    }
+
+    // check the # of entries in the database
+    let mut stmt = connection.prepare("SELECT list_id, name FROM list").unwrap();
+    let lst_itr = stmt.query_map(&[], |row| {
+        let id: u32 = row.get(0);
+        let name: String = row.get(1);
+        (id, name)
+    }).unwrap();
+    let lst_vec = lst_itr.filter_map(|x| x.ok()).collect::<Vec<_>>();
+    println!("lst_vec.len(): {:?}", lst_vec.len());
+
+    // compact the file so it only depends on the imported data
+    if reproducible {
+        connection.execute_batch("VACUUM").unwrap();
+    }
+}
+
+/// Checks the SQLite build for what the import and the tools built on the
+/// database need; prints a fix for every missing piece. The data layout is
+/// checked by `data_converter doctor`.
+fn doctor() -> bool {
+    let mut ok = true;
+    let mut report = |passed: bool, what: String, fix: &str| {
+        println!("{} {}", if passed { "ok  " } else { "FAIL" }, what);
+        if !passed {
+            println!("       fix: {}", fix);
+            ok = false;
         }
+    };
 
-        // load the actual sprites into the database
-        let rle_paths = read_dir(folder).unwrap();
-        let mut resources = Vec::<Resource>::new();
+    report(sql::version_number() >= MIN_SQLITE_VERSION,
+           format!("SQLite {}", sql::version()),
+           "SQLite 3.7 or newer is needed; build with the `bundled` rusqlite feature");
 
-        for entry in rle_paths {
+    // FTS5 is only there if it was compiled in
+    let fts5 = Connection::open_in_memory()
+        .and_then(|c| c.execute_batch("CREATE VIRTUAL TABLE temp.doctor USING fts5(name)"));
+    report(fts5.is_ok(), "FTS5 full text search".into(),
+           "use a SQLite built with SQLITE_ENABLE_FTS5, e.g. the `bundled` rusqlite feature");
 
-            let entry = entry.unwrap();
-            let path = entry.path();
+    // WAL needs a file and a file system with shared memory support
+    let path = std::env::temp_dir().join("rle2sqlite_doctor.sqlite");
+    let mode = Connection::open(&path)
+        .and_then(|c| c.query_row("PRAGMA journal_mode = WAL", &[], |row| row.get::<_, String>(0)));
+    for suffix in ["", "-wal", "-shm"].iter() {
+        let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+    }
+    report(mode.as_ref().map(|m| m == "wal").unwrap_or(false),
+           format!("WAL journal mode ({:?})", mode.unwrap_or_default()),
+           "put the database on a local disk; network file systems usually can't do WAL");
 
-            let res_file: ResourceFile = load_rle_data(&path).unwrap();
+    if ok {
+        println!("no problems found");
+    }
+    ok
+}
+
+/// Replaces the rows of the sprite type `only`, or of the single RLE file
+/// `only_file`, in one transaction. The sprites keep their gid's; see the
+/// notes at the top.
+fn reimport(connection: &mut Connection, only: Option<&str>, only_file: Option<&str>)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let tables: i64 = connection.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name IN ('list', 'rle')",
+        &[], |row| row.get(0))?;
+    if tables != 2 {
+        return Err(format!("{} has no imported data yet; run a full import first", DATABASE_PATH).into());
+    }
+
+    // the type and what to read: a whole folder, or the one file in it
+    let (_type, folder, list, file) = match (only, only_file) {
+        (Some(only), _) => match FOLDER_ENTRIES.iter().find(|entry| entry.0.eq_ignore_ascii_case(only)) {
+            Some(&(_type, folder, list, _)) => (_type, folder, list, None),
+            None => return Err(format!("unknown type {:?}", only).into()),
+        },
+        (None, Some(name)) => match FOLDER_ENTRIES.iter().find(|entry| Path::new(entry.1).join(name).is_file()) {
+            Some(&(_type, folder, list, _)) => (_type, folder, list, Some(Path::new(folder).join(name))),
+            None => return Err(format!("{:?} is in none of the data folders", name).into()),
+        },
+        (None, None) => unreachable!(),
+    };
+    println!("file: {:?}", _type);
+
+    let resources = match file {
+        Some(ref path) => load_rle_data(path).map_err(|e| format!("{:?}: {:?}", path, e))?.resources,
+        None => load_folder(folder),
+    };
 
-            for resource in res_file.resources {
-                resources.push(resource);
+    let tx = connection.transaction()?;
+
+    // the list rows depend on every resource of the type
+    if file.is_none() {
+        let list = load_list_data(Path::new(list)).map_err(|e| format!("{}: {:?}", list, e))?;
+        let items = list_items(&list, &resources);
+        let last_gid: i64 = tx.query_row("SELECT COALESCE(MAX(gid), 0) FROM list", &[], |row| row.get(0))?;
+        tx.execute("DELETE FROM list WHERE type = ?1", &[&_type])?;
+        for (gid, &(ref item, confidence)) in (last_gid + 1..).zip(items.iter()) {
+            insert_list_item(&tx, gid, _type, item, confidence)?;
+        }
+        println!("list.items.len() == {:?}", items.len());
+    }
+
+    // the gid's of the sprites being replaced, by file number and index;
+    // a file without a number in its name can't be told apart from another
+    let file_num = resources.first().and_then(|rle| rle.file_num);
+    let mut gids = HashMap::<(Option<u32>, u32), i64>::new();
+    {
+        let mut statement = tx.prepare("SELECT file_num, file_idx, gid FROM rle WHERE type = ?1")?;
+        let rows = statement.query_map(&[&_type], |row| ((row.get(0), row.get(1)), row.get(2)))?;
+        for row in rows {
+            let (key, gid): ((Option<u32>, u32), i64) = row?;
+            if file.is_none() || key.0 == file_num {
+                gids.insert(key, gid);
             }
+        }
+    }
+    let mut next_gid: i64 = tx.query_row("SELECT COALESCE(MAX(gid), 0) FROM rle", &[], |row| row.get(0))?;
+    match file {
+        Some(_) => tx.execute("DELETE FROM rle WHERE type = ?1 AND file_num IS ?2", &[&_type, &file_num])?,
+        None => tx.execute("DELETE FROM rle WHERE type = ?1", &[&_type])?,
+    };
 
+    let (mut kept, mut added) = (0, 0);
+    for rle in resources.iter() {
+        let gid = match gids.remove(&(rle.file_num, rle.index())) {
+            Some(gid) => { kept += 1; gid }
+            None => { added += 1; next_gid += 1; next_gid }
+        };
+        insert_resource(&tx, gid, _type, rle)?;
+    }
+    println!("resources: {} replaced, {} added, {} removed", kept, added, gids.len());
+    tx.commit()?;
+    Ok(())
+}
+
+/// Inserts the rows not imported yet (gid above `done`) in transactions of
+/// `chunk_size` rows. Each transaction stores its last gid under `checkpoint`
+/// in the meta table, so the database never holds a partial chunk.
+fn insert_chunked<T, F>(
+    connection: &mut Connection,
+    rows: &[T],
+    gid_base: i64,
+    done: i64,
+    chunk_size: usize,
+    checkpoint: &str,
+    mut insert: F)
+    -> Result<(), Box<dyn std::error::Error>>
+    where F: FnMut(&Transaction, i64, &T) -> Result<(), Box<dyn std::error::Error>>
+{
+    let rows: Vec<(i64, &T)> = rows.iter()
+        .enumerate()
+        .map(|(idx, row)| (gid_base + idx as i64 + 1, row))
+        .filter(|&(gid, _)| gid > done)
+        .collect();
+    for chunk in rows.chunks(chunk_size) {
+        let tx = connection.transaction()?;
+        for &(gid, row) in chunk {
+            insert(&tx, gid, row)?;
         }
+        let last_gid = chunk[chunk.len() - 1].0.to_string();
+        tx.execute("INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)",
+                   &[&checkpoint, &last_gid])?;
+        tx.commit()?;
+    }
+    Ok(())
+}
 
-        // Commit all of the sprite objects in one transaction
-        {
-            let tx = connection.transaction().unwrap();
-            for ref rle in &resources {
-
-                // TODO: hack the Vec<Pixel> into a Vec<U8>
-                let mut img = Vec::<u8>::new();
-                for ref pixel in &rle.image {
-                    img.push(pixel.r);
-                    img.push(pixel.g);
-                    img.push(pixel.b);
-                    img.push(pixel.a);
-                }
+/// The image itself, or a zeroblob of its size to be filled by `write_blob`
+fn image_param<'a>(image: &'a [u8]) -> ToSqlOutput<'a> {
+    if image.len() > BLOB_STREAM_THRESHOLD {
+        ToSqlOutput::ZeroBlob(image.len() as i32)
+    } else {
+        ToSqlOutput::from(image)
+    }
+}
 
-                // insert the data into the database
-                tx.execute(
-                    "INSERT INTO rle (
-                        type,   file_num, file_idx,
-                        length, offset_x, offset_y,
-                        width,  height,   image)
-                    VALUES (?1, ?2, ?3,
-                            ?4, ?5, ?6,
-                            ?7, ?8, ?9)",
-                    &[&_type,   &rle.file_num, &rle.index(),
-                    &rle.len,   &rle.offset_x, &rle.offset_y,
-                    &rle.width, &rle.height,   &img]
-                ).unwrap();
-            }
-            tx.commit().unwrap();
+/// Fills the zeroblob in `column` of row `gid` a chunk at a time
+fn write_blob(connection: &Connection, table: &str, column: &str, gid: i64, data: &[u8])
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let mut blob = connection.blob_open(DatabaseName::Main, table, column, gid, false)?;
+    for chunk in data.chunks(BLOB_CHUNK_SIZE) {
+        blob.write_all(chunk)?;
+    }
+    blob.close()?;
+    Ok(())
+}
 
+fn load_checkpoint(connection: &Connection, checkpoint: &str) -> i64 {
+    connection.query_row("SELECT value FROM meta WHERE key = ?1", &[&checkpoint],
+                         |row| row.get::<_, String>(0))
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0)
+}
+
+fn insert_list_item(tx: &Transaction, gid: i64, _type: &str, item: &ListItem, confidence: Option<f32>)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    tx.execute(
+        "INSERT INTO list (
+            gid, type, name, list_id, file_num, file_idx, inferred, confidence)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        &[&gid, &_type, &item.name, &item.id,
+          &item.entry.file(), &item.entry.index(),
+          &confidence.is_some(), &confidence.map(|c| c as f64)]
+    )?;
+    Ok(())
+}
+
+fn insert_resource(tx: &Transaction, gid: i64, _type: &str, rle: &Resource)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let extras = rle.extras();
+    let shadow = extras.shadow_offset();
+    let hotspot = extras.hotspot(rle.width, rle.height);
+    let stats = rle.stats(PixelFormat::Rgba8).map_err(|e| format!("resource {}: {:?}", rle.index(), e))?;
+    let color = stats.average_color;
+    let bounds = stats.bounds;
+    tx.execute(
+        "INSERT INTO rle (
+            gid,
+            type,      file_num,  file_idx,
+            length,    offset_x,  offset_y,
+            width,     height,
+            unknown_1, unknown_2, unknown_3, unknown_4,
+            shadow_x,  shadow_y,  hotspot_x, hotspot_y,
+            opaque_ratio,
+            avg_r,     avg_g,     avg_b,
+            bbox_x,    bbox_y,    bbox_w,    bbox_h,
+            hash,
+            image)
+        VALUES (?1,
+                ?2, ?3, ?4,
+                ?5, ?6, ?7,
+                ?8, ?9,
+                ?10, ?11, ?12, ?13,
+                ?14, ?15, ?16, ?17,
+                ?18,
+                ?19, ?20, ?21,
+                ?22, ?23, ?24, ?25,
+                ?26,
+                ?27)",
+        &[&gid,
+        &_type,            &rle.file_num,     &rle.index(),
+        &rle.len,          &rle.offset_x,     &rle.offset_y,
+        &rle.width,        &rle.height,
+        &extras.unknown_1, &extras.unknown_2, &extras.unknown_3, &extras.unknown_4,
+        &shadow.map(|s| s.0), &shadow.map(|s| s.1), &hotspot.map(|h| h.0), &hotspot.map(|h| h.1),
+        &(stats.opaque_ratio as f64),
+        &color.map(|c| c.0 as i32), &color.map(|c| c.1 as i32), &color.map(|c| c.2 as i32),
+        &bounds.map(|b| b.0), &bounds.map(|b| b.1), &bounds.map(|b| b.2), &bounds.map(|b| b.3),
+        // SQLite's integers are signed; the bits are kept
+        &(rle.content_hash() as i64),
+        &image_param(&rle.image_raw)]
+    )?;
+    if rle.image_raw.len() > BLOB_STREAM_THRESHOLD {
+        write_blob(tx, "rle", "image", gid, &rle.image_raw)?;
+    }
+    Ok(())
+}
+
+/// The sprites of every file in `folder`; `read_dir` has no defined order so
+/// the paths are sorted first
+fn load_folder(folder: &str) -> Vec<Resource> {
+    let mut rle_paths: Vec<PathBuf> = read_dir(folder).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    rle_paths.sort();
+    let mut resources = Vec::<Resource>::new();
+
+    for path in rle_paths {
+
+        let res_file: ResourceFile = load_rle_data(&path).unwrap();
+
+        for resource in res_file.resources {
+            resources.push(resource);
         }
-        println!("resources.len() == {:?}", &resources.len());
+
     }
+    resources
+}
 
-    // check the # of entries in the database
-    let mut stmt = connection.prepare("SELECT list_id, name FROM list").unwrap();
-    let lst_itr = stmt.query_map(&[], |row| {
-        let id: u32 = row.get(0);
-        let name: String = row.get(1);
-        (id, name)
-    }).unwrap();
-    let lst_vec = lst_itr.filter_map(|x| x.ok()).collect::<Vec<_>>();
-    println!("lst_vec.len(): {:?}", lst_vec.len());
+/// The listed items followed by the ones inferred for unlisted resources
+fn list_items(list: &List, resources: &[Resource]) -> Vec<(ListItem, Option<f32>)> {
+    let entries: Vec<Entry> = resources.iter()
+        .map(|rle| Entry::new(rle.file_num.unwrap_or(0xFFFF), rle.index()))
+        .collect();
+    let recovery = recover_list_ids(list, &entries);
+    for entry in recovery.unresolved.iter() {
+        println!("no list id for resource {}:{}", entry.file(), entry.index());
+    }
+    println!("inferred {} list ids, {} unresolved", recovery.inferred.len(), recovery.unresolved.len());
+    let mut items: Vec<(ListItem, Option<f32>)> = list.items.iter().map(|item| (item.clone(), None)).collect();
+    items.extend(recovery.inferred.into_iter().map(|i| (i.item, Some(i.confidence))));
+    items
 }
 
 fn load_list_data(path: &Path) -> Result<List, Error> {
@@ -184,6 +615,10 @@ fn load_rle_data(path: &Path) -> Result<ResourceFile, Error> {
         }
     }
 
-    // parse && append results
-    parse_rle(file_num, &mut bytes)
+    // parse && append results, going past the resources that are broken
+    let resource_file = parse_rle_lenient(file_num, &bytes, PixelFormat::Rgba8)?;
+    for failure in resource_file.failures.iter() {
+        println!("{:?}: skipped resource {} at {}: {:?}", path, failure.index, failure.offset, failure.reason);
+    }
+    Ok(resource_file)
 }
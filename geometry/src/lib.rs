@@ -1,3 +1,5 @@
+#![no_std]
+
 pub mod point;
 pub mod rectangle;
 pub mod size;
@@ -0,0 +1,169 @@
+//! Commands of the admin console, which `net::serve` reads from stdin. Every
+//! command run goes to the audit log (see `audit`).
+//!
+//! ```text
+//! anomalies what the anomaly checks flagged, and who most
+//! help      lists the commands
+//! limits    packets dropped by the rate limits, and queue overflows
+//! players   who's online
+//! reload    loads the rule tables again and lists what changed
+//! shutdown  saves everything and stops the server; `shutdown <message>`
+//!           tells the players why
+//! spawns    live monsters of each spawn region
+//! status    tick, overruns, entities
+//! weather   the time of day and the weather; `weather <name>` changes it
+//! ```
+
+use core_rules::ambiance::Weather;
+use core_rules::table::Tables;
+
+use crate::audit::AuditEvent;
+use crate::game::GameServer;
+
+static HELP: &str = "commands: anomalies, help, limits, players, reload, shutdown, spawns, status, weather";
+
+/// Runs a command line; returns what to print.
+pub fn execute(game: &mut GameServer, line: &str) -> String {
+    if !line.trim().is_empty() {
+        game.record(AuditEvent::Command { line: line.trim().into() });
+    }
+    match line.trim() {
+        "" => String::new(),
+        "anomalies" => {
+            let counts: Vec<String> = game.anomalies.counts().iter()
+                .map(|&(anomaly, count)| format!("{} {}", anomaly.name(), count))
+                .collect();
+            let sessions = game.anomalies.sessions().into_iter()
+                .map(|(session, count)| {
+                    let name = game.player(session).and_then(|id| game.world.entity(id)).map_or("", |e| &e.name);
+                    format!("\nsession {} {}: {}", session, name, count)
+                });
+            format!("anomalies: {}", counts.join(", ")) + &sessions.collect::<String>()
+        }
+        "help" => HELP.into(),
+        "limits" => {
+            let dropped: Vec<String> = game.limits.dropped().iter()
+                .map(|&(category, count)| format!("{} {}", category.name(), count))
+                .collect();
+            format!("dropped: {}; {} overflows", dropped.join(", "), game.limits.overflows())
+        }
+        "players" => {
+            let names: Vec<String> = game.players()
+                .filter_map(|id| game.world.entity(id).map(|e| format!("{} ({})", e.name, id)))
+                .collect();
+            format!("{} online: {}", names.len(), names.join(", "))
+        }
+        "reload" => {
+            let folder = match game.tables_folder.clone() {
+                Some(folder) => folder,
+                None => return "no rule tables to reload".into(),
+            };
+            match Tables::load(&folder) {
+                Ok(tables) => {
+                    let changes = game.reload_tables(tables);
+                    let lines = changes.iter().map(|change| format!("\n{}", change));
+                    format!("reloaded the rule tables from {:?}: {} changes", folder, changes.len()) + &lines.collect::<String>()
+                }
+                // the tables in use stay
+                Err(error) => format!("the rule tables could not be loaded from {:?}: {:?}", folder, error),
+            }
+        }
+        "shutdown" => shut_down(game, "the server is shutting down"),
+        other if other.starts_with("shutdown ") => shut_down(game, other["shutdown ".len()..].trim()),
+        "spawns" => {
+            let counts = game.spawner.counts();
+            if counts.is_empty() {
+                return "no spawn regions".into();
+            }
+            counts.iter().enumerate()
+                .map(|(region, count)| format!("region {} map {} monster {}: {}/{} alive, {} waiting",
+                                               region, count.map, count.monster, count.alive, count.cap, count.waiting))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        "status" => format!("tick {}, {} overruns, {} entities, {} players",
+                            game.tick, game.tick_overruns, game.world.entities().count(), game.players().count()),
+        "weather" => {
+            let minute = game.ambiance.minute();
+            format!("{:02}:{:02}, {}", minute / 60, minute % 60, game.ambiance.weather().name())
+        }
+        other if other.starts_with("weather ") => match Weather::from_name(other["weather ".len()..].trim()) {
+            Some(weather) => {
+                game.set_weather(weather);
+                format!("the weather is now {}", weather.name())
+            }
+            None => "weather: clear, rain, snow or fog".into(),
+        },
+        other => format!("unknown command `{}`; {}", other, HELP),
+    }
+}
+
+fn shut_down(game: &mut GameServer, message: &str) -> String {
+    game.shut_down(message);
+    format!("shutting down, {} players told", game.players().count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use core_rules::table::SpawnEntry;
+
+    use crate::spawn::Spawner;
+    use crate::world::{World, WorldConfig};
+
+    #[test]
+    fn test_commands() {
+        let mut game = GameServer::new(World::new(WorldConfig { start_map: 1, start_x: 5, start_y: 5 }));
+        assert_eq!(execute(&mut game, "spawns"), "no spawn regions");
+        let entry = SpawnEntry { map: 1, left: 0, top: 0, right: 4, bottom: 4, monster: 2, count: 3, respawn: 10, jitter: 0 };
+        game.spawner = Spawner::new(&[entry], Duration::from_millis(100), 1);
+        assert_eq!(execute(&mut game, " spawns\n"), "region 0 map 1 monster 2: 0/3 alive, 3 waiting");
+
+        let session = game.connect();
+        game.receive(session, core_net::packet::Packet::Login { name: "Philar".into() });
+        game.tick();
+        assert_eq!(execute(&mut game, "players"), "1 online: Philar (1)");
+        assert_eq!(execute(&mut game, "status"), "tick 1, 0 overruns, 1 entities, 1 players");
+        assert!(execute(&mut game, "spawn").starts_with("unknown command `spawn`"));
+
+        assert_eq!(execute(&mut game, "weather"), "08:00, clear");
+        assert_eq!(execute(&mut game, "weather fog"), "the weather is now fog");
+        assert_eq!(execute(&mut game, "weather hail"), "weather: clear, rain, snow or fog");
+        assert_eq!(execute(&mut game, "weather"), "08:00, fog");
+
+        assert_eq!(execute(&mut game, "anomalies"), "anomalies: flood 0, range 0, speed 0");
+        game.receive(session, core_net::packet::Packet::Walk { x: 9, y: 5 });
+        game.tick();
+        assert_eq!(execute(&mut game, "anomalies"), "anomalies: flood 0, range 0, speed 1\nsession 0 Philar: 1");
+
+        assert_eq!(execute(&mut game, "limits"), "dropped: movement 0, combat 0, chat 0, social 0, other 0; 0 overflows");
+        // the first session is throttled for its jump
+        let session = game.connect();
+        for _ in 0..6 {
+            game.receive(session, core_net::packet::Packet::Say { text: "hi".into() });
+        }
+        game.tick();
+        assert_eq!(execute(&mut game, "limits"), "dropped: movement 0, combat 0, chat 1, social 0, other 0; 0 overflows");
+
+        assert_eq!(execute(&mut game, "reload"), "no rule tables to reload");
+        let folder = std::env::temp_dir().join(format!("novluno_admin_{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::write(folder.join("items.tsv"), "id\tname\tattack\tdefense\taccuracy\tevasion\n7\tHerb\t0\t0\t0\t0\n").unwrap();
+        std::fs::write(folder.join("monsters.tsv"), "id\tname\tlevel\thp\texp\tattack\tdefense\taccuracy\tevasion\n").unwrap();
+        std::fs::write(folder.join("levels.tsv"), "level\texp\thp\tattack\tdefense\taccuracy\tevasion\n1\t0\t100\t10\t0\t100\t5\n").unwrap();
+        game.tables_folder = Some(folder.clone());
+        assert_eq!(execute(&mut game, "reload"), format!("reloaded the rule tables from {:?}: 2 changes\n\
+                                                          items 7 (Herb): added\nlevels 1: added", folder));
+        assert_eq!(execute(&mut game, "reload"), format!("reloaded the rule tables from {:?}: 0 changes", folder));
+        std::fs::remove_file(folder.join("levels.tsv")).unwrap();
+        assert!(execute(&mut game, "reload").starts_with("the rule tables could not be loaded"));
+        let _ = std::fs::remove_dir_all(&folder);
+
+        assert_eq!(execute(&mut game, "shutdown back in 5"), "shutting down, 1 players told");
+        assert!(game.is_shutting_down());
+        assert_eq!(game.characters.len(), 1);
+    }
+}
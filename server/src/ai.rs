@@ -0,0 +1,274 @@
+//! What the monsters do.
+//!
+//! Every monster is a small state machine:
+//!
+//! * `Idle`: strolls now and then to a tile near home. An aggressive monster
+//!   goes after the nearest player that comes within its aggro radius.
+//! * `Chase`: walks at its target (see `path`) and hits it once next to it.
+//!   Getting hit makes any monster chase the attacker.
+//! * `Return`: once the target is gone, dead, or the chase got too far from
+//!   home, it walks back and ignores everyone until it's home.
+//!
+//! How far each of those reaches comes from the monster table (see
+//! `core_rules::table::Behavior`). Monsters act every `AiConfig::interval`
+//! ticks, so they don't outrun the players.
+
+use std::collections::BTreeMap;
+
+use core_rules::random::Rng;
+use core_rules::table::Behavior;
+
+use crate::path::{self, Tile};
+use crate::world::{EntityId, World};
+
+#[derive(Debug, Copy, Clone)]
+pub struct AiConfig {
+    /// Ticks between two actions of a monster
+    pub interval: u64,
+    /// One in this many idle turns is spent strolling
+    pub wander_odds: u32,
+    /// Most tiles a path search looks at
+    pub search_limit: usize,
+}
+
+impl Default for AiConfig {
+    fn default() -> AiConfig {
+        AiConfig { interval: 5, wander_odds: 4, search_limit: 256 }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum State {
+    Idle,
+    Chase(EntityId),
+    Return,
+}
+
+/// What the monsters did, for the game to tell the players
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Action {
+    Moved { id: EntityId, x: u16, y: u16 },
+    Attacked { attacker: EntityId, target: EntityId, amount: u16, hp: u16 },
+}
+
+struct Brain {
+    home: Tile,
+    behavior: Behavior,
+    state: State,
+    next_turn: u64,
+}
+
+pub struct Ai {
+    pub config: AiConfig,
+    brains: BTreeMap<EntityId, Brain>,
+    rng: Rng,
+}
+
+impl Ai {
+    pub fn new(config: AiConfig, seed: u64) -> Ai {
+        Ai { config, brains: BTreeMap::new(), rng: Rng::new(seed) }
+    }
+
+    /// Starts thinking for the monster `id`, at home where it is now
+    pub fn add(&mut self, world: &World, id: EntityId, behavior: Behavior) {
+        if let Some(entity) = world.entity(id) {
+            let brain = Brain { home: (entity.x, entity.y), behavior, state: State::Idle, next_turn: 0 };
+            self.brains.insert(id, brain);
+        }
+    }
+
+    pub fn remove(&mut self, id: EntityId) {
+        self.brains.remove(&id);
+    }
+
+    pub fn state(&self, id: EntityId) -> Option<State> {
+        self.brains.get(&id).map(|brain| brain.state)
+    }
+
+    /// `attacker` hit `id`; the monster turns on it unless it's on its way
+    /// home.
+    pub fn on_damaged(&mut self, id: EntityId, attacker: EntityId) {
+        if let Some(brain) = self.brains.get_mut(&id) {
+            if brain.state != State::Return {
+                brain.state = State::Chase(attacker);
+            }
+        }
+    }
+
+    /// Lets the monsters whose turn it is act.
+    pub fn tick(&mut self, world: &mut World, tick: u64) -> Vec<Action> {
+        let mut actions = Vec::new();
+        let ids: Vec<EntityId> = self.brains.keys().cloned().collect();
+        for id in ids {
+            let brain = &self.brains[&id];
+            if brain.next_turn > tick || !world.entity(id).is_some_and(|e| e.is_alive()) {
+                continue;
+            }
+            let state = self.think(world, id);
+            let brain = self.brains.get_mut(&id).unwrap();
+            brain.state = state;
+            brain.next_turn = tick + self.config.interval;
+            actions.extend(self.act(world, id));
+        }
+        actions
+    }
+
+    /// The state the monster should be in now
+    fn think(&self, world: &World, id: EntityId) -> State {
+        let brain = &self.brains[&id];
+        let monster = world.entity(id).unwrap();
+        let from_home = |x: u16, y: u16| brain.home.0.abs_diff(x).max(brain.home.1.abs_diff(y));
+        match brain.state {
+            State::Idle if brain.behavior.aggressive => {
+                let nearest = world.near(monster.map, monster.x, monster.y, brain.behavior.aggro_radius)
+                    .into_iter()
+                    .filter_map(|other| world.entity(other))
+                    .filter(|other| other.is_player() && other.is_alive())
+                    .min_by_key(|other| (monster.distance(other), other.id));
+                match nearest {
+                    Some(target) => State::Chase(target.id),
+                    None => State::Idle,
+                }
+            }
+            State::Chase(target) => {
+                let chasing = world.entity(target)
+                    .is_some_and(|t| t.is_alive() && monster.distance(t).is_some());
+                if chasing && from_home(monster.x, monster.y) <= brain.behavior.leash_radius {
+                    State::Chase(target)
+                } else {
+                    State::Return
+                }
+            }
+            State::Return if (monster.x, monster.y) == brain.home => State::Idle,
+            state => state,
+        }
+    }
+
+    fn act(&mut self, world: &mut World, id: EntityId) -> Option<Action> {
+        let (state, home, wander) = {
+            let brain = &self.brains[&id];
+            (brain.state, brain.home, brain.behavior.wander_radius)
+        };
+        let monster = world.entity(id).unwrap();
+        let (map, position) = (monster.map, (monster.x, monster.y));
+        let goal = match state {
+            State::Chase(target) => {
+                let target_entity = world.entity(target)?;
+                if monster.is_next_to(target_entity) {
+                    let (amount, hp) = world.attack(id, target)?;
+                    return Some(Action::Attacked { attacker: id, target, amount, hp });
+                }
+                ((target_entity.x, target_entity.y), 1)
+            }
+            State::Return => (home, 0),
+            State::Idle => {
+                if self.rng.below(self.config.wander_odds) != 0 {
+                    return None;
+                }
+                let offset = |rng: &mut Rng, center: u16| {
+                    let delta = rng.below(wander as u32 * 2 + 1) as i32 - wander as i32;
+                    (center as i32 + delta).clamp(0, u16::MAX as i32) as u16
+                };
+                ((offset(&mut self.rng, home.0), offset(&mut self.rng, home.1)), 0)
+            }
+        };
+        let (x, y) = path::next_step(world, map, position, goal.0, goal.1, self.config.search_limit)?;
+        if world.walk(id, x, y) {
+            Some(Action::Moved { id, x, y })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core_rules::table::{parse_levels, parse_monsters, Tables};
+
+    use crate::world::WorldConfig;
+
+    fn setup(behavior: &str) -> (World, Ai, EntityId) {
+        let levels = "level\texp\thp\tattack\tdefense\taccuracy\tevasion\n1\t0\t100\t10\t0\t10\t5\n";
+        let monsters = format!("id\tname\tlevel\thp\texp\tattack\tdefense\taccuracy\tevasion\taggressive\taggro\tleash\twander\n\
+                                1\tRat\t1\t30\t5\t4\t0\t100\t0\t{}\n", behavior);
+        let tables = Tables {
+            levels: parse_levels(levels).unwrap(),
+            monsters: parse_monsters(&monsters).unwrap(),
+            ..Tables::default()
+        };
+        let mut world = World::new(WorldConfig { start_map: 1, start_x: 10, start_y: 10 });
+        world.set_tables(tables, 1);
+        let rat = world.spawn_monster(1, 1, 20, 10).unwrap();
+        let mut ai = Ai::new(AiConfig { interval: 1, ..AiConfig::default() }, 1);
+        let behavior = world.tables().unwrap().monsters[&1].behavior;
+        ai.add(&world, rat, behavior);
+        (world, ai, rat)
+    }
+
+    fn position(world: &World, id: EntityId) -> Tile {
+        let entity = world.entity(id).unwrap();
+        (entity.x, entity.y)
+    }
+
+    #[test]
+    fn test_wander_near_home() {
+        let (mut world, mut ai, rat) = setup("1\t5\t15\t2");
+        for tick in 0..200 {
+            ai.tick(&mut world, tick);
+            let (x, y) = position(&world, rat);
+            assert!(x.abs_diff(20) <= 2 && y.abs_diff(10) <= 2);
+        }
+        assert_ne!(position(&world, rat), (20, 10));
+    }
+
+    #[test]
+    fn test_aggro_chase_and_attack() {
+        let (mut world, mut ai, rat) = setup("1\t5\t15\t0");
+        // the player starts 10 tiles away, too far to be noticed
+        let player = world.spawn_player("Philar");
+        ai.tick(&mut world, 0);
+        assert_eq!(ai.state(rat), Some(State::Idle));
+        for x in 11..=15 {
+            world.walk(player, x, 10);
+        }
+        ai.tick(&mut world, 1);
+        assert_eq!(ai.state(rat), Some(State::Chase(player)));
+
+        let mut hit = false;
+        for tick in 2..10 {
+            for action in ai.tick(&mut world, tick) {
+                if let Action::Attacked { target, .. } = action {
+                    assert_eq!(target, player);
+                    hit = true;
+                }
+            }
+        }
+        assert!(hit);
+        assert!(world.entity(rat).unwrap().is_next_to(world.entity(player).unwrap()));
+    }
+
+    #[test]
+    fn test_passive_until_hit_then_leash() {
+        let (mut world, mut ai, rat) = setup("0\t5\t3\t0");
+        let player = world.spawn_player("Philar");
+        for x in 11..=19 {
+            world.walk(player, x, 10);
+        }
+        ai.tick(&mut world, 0);
+        assert_eq!(ai.state(rat), Some(State::Idle));
+        world.attack(player, rat);
+        ai.on_damaged(rat, player);
+
+        // the player runs off to the west; the rat gives up 3 tiles from home
+        for x in (0..19).rev() {
+            world.walk(player, x, 10);
+        }
+        for tick in 1..20 {
+            ai.tick(&mut world, tick);
+        }
+        assert_eq!(ai.state(rat), Some(State::Idle));
+        assert_eq!(position(&world, rat), (20, 10));
+    }
+}
@@ -0,0 +1,163 @@
+//! The world's clock and weather (see `core_rules::ambiance`).
+//!
+//! The clock starts at `AmbianceConfig::start` and goes round once every
+//! `day_length`. The weather is rolled again every `weather_period`, by the
+//! weights of `AmbianceConfig::weather`, and may well stay as it was. The
+//! game server tells every player at login, at every full hour and whenever
+//! the weather changes; the clients keep the clock going in between.
+
+use std::time::Duration;
+
+use core_net::packet::Packet;
+use core_rules::ambiance::{Weather, MINUTES_PER_DAY};
+use core_rules::random::Rng;
+
+#[derive(Debug, Copy, Clone)]
+pub struct AmbianceConfig {
+    /// How long a day lasts
+    pub day_length: Duration,
+    /// The time of day at the first tick, in minutes since midnight
+    pub start: u16,
+    pub weather_period: Duration,
+    /// The weights of `Weather::ALL`
+    pub weather: [u32; 4],
+}
+
+impl Default for AmbianceConfig {
+    /// Two hour days starting in the morning, the weather rolled every ten
+    /// minutes and clear more often than not
+    fn default() -> AmbianceConfig {
+        AmbianceConfig {
+            day_length: Duration::from_secs(2 * 60 * 60),
+            start: 8 * 60,
+            weather_period: Duration::from_secs(10 * 60),
+            weather: [12, 4, 1, 3],
+        }
+    }
+}
+
+pub struct Ambiance {
+    pub config: AmbianceConfig,
+    /// Ticks
+    day_length: u64,
+    weather_period: u64,
+    minute: u16,
+    weather: Weather,
+    rng: Rng,
+}
+
+impl Ambiance {
+    /// The clock and weather of a server ticking every `tick`; clear to
+    /// begin with
+    pub fn new(config: AmbianceConfig, tick: Duration, seed: u64) -> Ambiance {
+        let tick_ms = tick.as_millis().max(1) as u64;
+        let ticks = |duration: Duration| (duration.as_millis() as u64).div_ceil(tick_ms).max(1);
+        Ambiance {
+            config,
+            day_length: ticks(config.day_length),
+            weather_period: ticks(config.weather_period),
+            minute: config.start % MINUTES_PER_DAY,
+            weather: Weather::Clear,
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// The time of day at `tick`
+    pub fn minute_at(&self, tick: u64) -> u16 {
+        let passed = (tick % self.day_length) * MINUTES_PER_DAY as u64 / self.day_length;
+        ((self.config.start as u64 + passed) % MINUTES_PER_DAY as u64) as u16
+    }
+
+    /// The time of day as of the last `update`
+    pub fn minute(&self) -> u16 {
+        self.minute
+    }
+
+    pub fn weather(&self) -> Weather {
+        self.weather
+    }
+
+    /// Changes the weather until it's next rolled; returns whether it changed.
+    pub fn set_weather(&mut self, weather: Weather) -> bool {
+        let changed = weather != self.weather;
+        self.weather = weather;
+        changed
+    }
+
+    /// Moves on to `tick`, rolling the weather when it's due; returns whether
+    /// the players need telling.
+    pub fn update(&mut self, tick: u64) -> bool {
+        let minute = self.minute_at(tick);
+        let new_hour = minute / 60 != self.minute / 60;
+        self.minute = minute;
+        let mut changed = false;
+        if tick > 0 && tick.is_multiple_of(self.weather_period) {
+            let weather = Weather::roll(&self.config.weather, &mut self.rng);
+            changed = self.set_weather(weather);
+        }
+        new_hour || changed
+    }
+
+    pub fn packet(&self) -> Packet {
+        Packet::Ambiance {
+            minute: self.minute,
+            day_length: self.config.day_length.as_secs().min(u32::MAX as u64) as u32,
+            weather: self.weather.code(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 24 second days of 1 second ticks: an hour a tick
+    fn config() -> AmbianceConfig {
+        AmbianceConfig {
+            day_length: Duration::from_secs(24),
+            start: 6 * 60 + 30,
+            weather_period: Duration::from_secs(5),
+            weather: [0, 1, 0, 1],
+        }
+    }
+
+    #[test]
+    fn test_clock() {
+        let mut ambiance = Ambiance::new(config(), Duration::from_secs(1), 1);
+        assert_eq!(ambiance.minute(), 6 * 60 + 30);
+        assert_eq!(ambiance.minute_at(1), 7 * 60 + 30);
+        assert_eq!(ambiance.minute_at(18), 30);
+        assert_eq!(ambiance.minute_at(24), 6 * 60 + 30);
+        assert!(!ambiance.update(0));
+        assert!(ambiance.update(1));
+        assert_eq!(ambiance.packet(), Packet::Ambiance { minute: 7 * 60 + 30, day_length: 24, weather: 0 });
+
+        // 50 ticks a minute at the default tick rate
+        let ambiance = Ambiance::new(AmbianceConfig::default(), Duration::from_millis(100), 1);
+        assert_eq!((ambiance.minute_at(9), ambiance.minute_at(50)), (8 * 60, 8 * 60 + 1));
+        assert_eq!(ambiance.minute_at(72000), 8 * 60);
+    }
+
+    #[test]
+    fn test_weather() {
+        let config = AmbianceConfig { day_length: Duration::from_secs(24 * 60 * 60), ..config() };
+        let mut ambiance = Ambiance::new(config, Duration::from_secs(1), 1);
+        assert_eq!(ambiance.weather(), Weather::Clear);
+        // nothing to tell until the weather turns to rain or fog
+        assert!(!(1..5).any(|tick| ambiance.update(tick)));
+        assert!(ambiance.update(5));
+        assert!(ambiance.weather() == Weather::Rain || ambiance.weather() == Weather::Fog);
+        let mut seen = Vec::new();
+        for tick in 6..200 {
+            if ambiance.update(tick) {
+                assert_eq!(tick % 5, 0);
+                seen.push(ambiance.weather());
+            }
+        }
+        assert!(seen.contains(&Weather::Rain) && seen.contains(&Weather::Fog));
+
+        assert!(ambiance.set_weather(Weather::Snow));
+        assert!(!ambiance.set_weather(Weather::Snow));
+        assert!(matches!(ambiance.packet(), Packet::Ambiance { weather: 2, .. }));
+    }
+}
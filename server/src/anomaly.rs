@@ -0,0 +1,290 @@
+//! Flags what an honest client never does, so cheats show up.
+//!
+//! Three kinds of anomalies are looked for:
+//!
+//! - `Flood`: more packets in a tick than `AnomalyConfig::max_packets`
+//! - `Range`: attacks, skills, pick ups and trade requests at something
+//!   further than they reach, by more than `range_slack` tiles, as a target
+//!   may step away before the packet arrives
+//! - `Speed`: a step of more than a tile, or more than `max_steps` steps
+//!   within `speed_window` ticks
+//!
+//! Each kind is answered as configured: logged, throttled (the session gets
+//! a single packet a tick handled for `throttle_ticks`, the others dropped),
+//! or disconnected. Every anomaly is counted, by kind and by session, for the
+//! admin console, and kept for the driver to log until `take_flagged`.
+
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+
+use crate::game::SessionId;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Anomaly {
+    Flood,
+    Range,
+    Speed,
+}
+
+impl Anomaly {
+    pub fn name(self) -> &'static str {
+        match self {
+            Anomaly::Flood => "flood",
+            Anomaly::Range => "range",
+            Anomaly::Speed => "speed",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Anomaly> {
+        [Anomaly::Flood, Anomaly::Range, Anomaly::Speed].iter().cloned().find(|anomaly| anomaly.name() == name)
+    }
+}
+
+/// What to do about an anomaly
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Response {
+    Log,
+    Throttle,
+    Disconnect,
+}
+
+impl Response {
+    pub fn name(self) -> &'static str {
+        match self {
+            Response::Log => "log",
+            Response::Throttle => "throttle",
+            Response::Disconnect => "disconnect",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Response> {
+        [Response::Log, Response::Throttle, Response::Disconnect].iter().cloned().find(|response| response.name() == name)
+    }
+}
+
+/// An anomaly as it was flagged
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Flagged {
+    pub session: SessionId,
+    pub anomaly: Anomaly,
+    pub tick: u64,
+    pub response: Response,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct AnomalyConfig {
+    /// Packets a session may send in a tick
+    pub max_packets: usize,
+    /// Steps a player may take within `speed_window` ticks
+    pub max_steps: usize,
+    pub speed_window: u64,
+    /// Tiles past its reach an interaction is let through
+    pub range_slack: u16,
+    /// Ticks a throttled session stays throttled
+    pub throttle_ticks: u64,
+    pub flood: Response,
+    pub range: Response,
+    pub speed: Response,
+}
+
+impl Default for AnomalyConfig {
+    /// At the default tick rate: 20 packets and 6 steps a second, throttled
+    /// for 5 seconds; being out of range is only logged
+    fn default() -> AnomalyConfig {
+        AnomalyConfig {
+            max_packets: 20,
+            max_steps: 6,
+            speed_window: 10,
+            range_slack: 2,
+            throttle_ticks: 50,
+            flood: Response::Throttle,
+            range: Response::Log,
+            speed: Response::Throttle,
+        }
+    }
+}
+
+impl AnomalyConfig {
+    pub fn response(&self, anomaly: Anomaly) -> Response {
+        match anomaly {
+            Anomaly::Flood => self.flood,
+            Anomaly::Range => self.range,
+            Anomaly::Speed => self.speed,
+        }
+    }
+
+    pub fn set_response(&mut self, anomaly: Anomaly, response: Response) {
+        match anomaly {
+            Anomaly::Flood => self.flood = response,
+            Anomaly::Range => self.range = response,
+            Anomaly::Speed => self.speed = response,
+        }
+    }
+}
+
+pub struct Detector {
+    pub config: AnomalyConfig,
+    /// Anomalies so far, by kind
+    counts: BTreeMap<Anomaly, u64>,
+    /// And by session, for the sessions still there
+    by_session: BTreeMap<SessionId, BTreeMap<Anomaly, u64>>,
+    /// The ticks of the recent steps of each session
+    steps: BTreeMap<SessionId, VecDeque<u64>>,
+    /// The tick each throttled session is let go
+    throttled: BTreeMap<SessionId, u64>,
+    flagged: Vec<Flagged>,
+}
+
+impl Detector {
+    pub fn new(config: AnomalyConfig) -> Detector {
+        Detector {
+            config,
+            counts: BTreeMap::new(),
+            by_session: BTreeMap::new(),
+            steps: BTreeMap::new(),
+            throttled: BTreeMap::new(),
+            flagged: Vec::new(),
+        }
+    }
+
+    /// `session` sent `count` packets for `tick`.
+    pub fn received(&mut self, session: SessionId, count: usize, tick: u64) -> Option<Response> {
+        if count > self.config.max_packets {
+            Some(self.flag(session, Anomaly::Flood, tick))
+        } else {
+            None
+        }
+    }
+
+    /// `session` asked to walk `tiles` tiles at `tick`; only single steps
+    /// count towards the speed.
+    pub fn walked(&mut self, session: SessionId, tiles: u16, tick: u64) -> Option<Response> {
+        if tiles > 1 {
+            return Some(self.flag(session, Anomaly::Speed, tick));
+        }
+        let window = self.config.speed_window;
+        let steps = self.steps.entry(session).or_default();
+        while steps.front().is_some_and(|&step| step + window <= tick) {
+            steps.pop_front();
+        }
+        steps.push_back(tick);
+        if steps.len() > self.config.max_steps {
+            steps.clear();
+            Some(self.flag(session, Anomaly::Speed, tick))
+        } else {
+            None
+        }
+    }
+
+    /// `session` used something reaching `reach` tiles on a target `distance`
+    /// tiles away, `None` for another map.
+    pub fn reached(&mut self, session: SessionId, distance: Option<u16>, reach: u16, tick: u64) -> Option<Response> {
+        if distance.is_some_and(|distance| distance <= reach.saturating_add(self.config.range_slack)) {
+            None
+        } else {
+            Some(self.flag(session, Anomaly::Range, tick))
+        }
+    }
+
+    pub fn is_throttled(&self, session: SessionId, tick: u64) -> bool {
+        self.throttled.get(&session).is_some_and(|&until| tick < until)
+    }
+
+    /// Forgets a session that's gone; its anomalies stay counted by kind.
+    pub fn remove(&mut self, session: SessionId) {
+        self.by_session.remove(&session);
+        self.steps.remove(&session);
+        self.throttled.remove(&session);
+    }
+
+    /// The anomalies flagged since the last call, to log them
+    pub fn take_flagged(&mut self) -> Vec<Flagged> {
+        std::mem::take(&mut self.flagged)
+    }
+
+    /// How many anomalies of each kind there were
+    pub fn counts(&self) -> Vec<(Anomaly, u64)> {
+        [Anomaly::Flood, Anomaly::Range, Anomaly::Speed].iter()
+            .map(|&anomaly| (anomaly, self.counts.get(&anomaly).cloned().unwrap_or(0)))
+            .collect()
+    }
+
+    /// The sessions with anomalies, the most first
+    pub fn sessions(&self) -> Vec<(SessionId, u64)> {
+        let mut sessions: Vec<(SessionId, u64)> = self.by_session.iter()
+            .map(|(&session, counts)| (session, counts.values().sum()))
+            .collect();
+        sessions.sort_by_key(|&(session, count)| (std::cmp::Reverse(count), session));
+        sessions
+    }
+
+    fn flag(&mut self, session: SessionId, anomaly: Anomaly, tick: u64) -> Response {
+        *self.counts.entry(anomaly).or_insert(0) += 1;
+        *self.by_session.entry(session).or_default().entry(anomaly).or_insert(0) += 1;
+        let response = self.config.response(anomaly);
+        if response == Response::Throttle {
+            self.throttled.insert(session, tick + self.config.throttle_ticks);
+        }
+        self.flagged.push(Flagged { session, anomaly, tick, response });
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detector() -> Detector {
+        Detector::new(AnomalyConfig {
+            max_packets: 3,
+            max_steps: 2,
+            speed_window: 4,
+            range_slack: 1,
+            throttle_ticks: 10,
+            flood: Response::Disconnect,
+            range: Response::Log,
+            speed: Response::Throttle,
+        })
+    }
+
+    #[test]
+    fn test_flood() {
+        let mut detector = detector();
+        assert_eq!(detector.received(0, 3, 0), None);
+        assert_eq!(detector.received(0, 4, 1), Some(Response::Disconnect));
+        assert!(!detector.is_throttled(0, 1));
+        assert_eq!(detector.counts()[0], (Anomaly::Flood, 1));
+        let flagged = Flagged { session: 0, anomaly: Anomaly::Flood, tick: 1, response: Response::Disconnect };
+        assert_eq!(detector.take_flagged(), vec![flagged]);
+        assert!(detector.take_flagged().is_empty());
+    }
+
+    #[test]
+    fn test_speed() {
+        let mut detector = detector();
+        // two steps in any 4 ticks
+        for &tick in [0, 2, 4, 6, 8].iter() {
+            assert_eq!(detector.walked(0, 1, tick), None);
+        }
+        assert_eq!(detector.walked(0, 1, 9), Some(Response::Throttle));
+        assert!(detector.is_throttled(0, 18) && !detector.is_throttled(0, 19));
+        // a jump is too fast whatever came before
+        assert_eq!(detector.walked(1, 2, 0), Some(Response::Throttle));
+        assert_eq!(detector.walked(1, 0, 1), None);
+    }
+
+    #[test]
+    fn test_range_and_counts() {
+        let mut detector = detector();
+        assert_eq!(detector.reached(0, Some(2), 1, 0), None);
+        assert_eq!(detector.reached(0, Some(3), 1, 0), Some(Response::Log));
+        assert_eq!(detector.reached(0, None, 1, 0), Some(Response::Log));
+        assert_eq!(detector.reached(1, Some(9), 3, 0), Some(Response::Log));
+        assert_eq!(detector.counts(), vec![(Anomaly::Flood, 0), (Anomaly::Range, 3), (Anomaly::Speed, 0)]);
+        assert_eq!(detector.sessions(), vec![(0, 2), (1, 1)]);
+
+        detector.remove(0);
+        assert_eq!(detector.sessions(), vec![(1, 1)]);
+        assert_eq!(detector.counts()[1], (Anomaly::Range, 3));
+    }
+}
@@ -0,0 +1,311 @@
+//! An append only log of what changes the game for good, to settle disputes
+//! with: items picked up, trades, deaths and the admin commands run.
+//!
+//! The game server keeps the records of a tick until the driver takes them
+//! (see `GameServer::take_audit`), which appends them to the audit log as
+//! JSON lines, stamped with the time in seconds since the epoch:
+//!
+//! ```json
+//! {"time":1700000000,"tick":42,"event":"pickup","player":"Lavita","item":7,"count":3}
+//! {"time":1700000000,"tick":43,"event":"trade","from":"Lavita","to":"Philar","items":"7:3,9:1"}
+//! {"time":1700000000,"tick":44,"event":"death","victim":"Rat","killer":"Lavita"}
+//! {"time":1700000000,"tick":45,"event":"command","line":"weather rain"}
+//! ```
+//!
+//! A log growing past `Rotation::max_bytes` is moved to `<log>.1`, the one
+//! before that to `<log>.2` and so on, keeping `Rotation::keep` of them.
+//! `novluno-audit` searches the log and the ones rotated out.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::item::ItemId;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditEvent {
+    /// A command of the admin console, as typed
+    Command { line: String },
+    /// Of a player or a monster, by name
+    Death { victim: String, killer: String },
+    Pickup { player: String, item: ItemId, count: u32 },
+    /// What `from` gave `to` in a completed trade
+    Trade { from: String, to: String, items: Vec<(ItemId, u32)> },
+}
+
+impl AuditEvent {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            AuditEvent::Command { .. } => "command",
+            AuditEvent::Death { .. } => "death",
+            AuditEvent::Pickup { .. } => "pickup",
+            AuditEvent::Trade { .. } => "trade",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub tick: u64,
+    pub event: AuditEvent,
+}
+
+impl Record {
+    /// The record as a line of the log, without the line break
+    pub fn to_json(&self, time: u64) -> String {
+        let mut json = format!("{{\"time\":{},\"tick\":{},\"event\":\"{}\"", time, self.tick, self.event.name());
+        match self.event {
+            AuditEvent::Command { ref line } => json += &format!(",\"line\":{}", json_string(line)),
+            AuditEvent::Death { ref victim, ref killer } => {
+                json += &format!(",\"victim\":{},\"killer\":{}", json_string(victim), json_string(killer));
+            }
+            AuditEvent::Pickup { ref player, item, count } => {
+                json += &format!(",\"player\":{},\"item\":{},\"count\":{}", json_string(player), item, count);
+            }
+            AuditEvent::Trade { ref from, ref to, ref items } => {
+                let items: Vec<String> = items.iter().map(|&(item, count)| format!("{}:{}", item, count)).collect();
+                json += &format!(",\"from\":{},\"to\":{},\"items\":\"{}\"", json_string(from), json_string(to), items.join(","));
+            }
+        }
+        json + "}"
+    }
+}
+
+/// The fields of a log line, the strings unquoted and the numbers as they
+/// were written; `None` for anything but a flat JSON object.
+pub fn parse_fields(line: &str) -> Option<BTreeMap<String, String>> {
+    let mut chars = line.trim().chars().peekable();
+    let mut fields = BTreeMap::new();
+    if chars.next()? != '{' {
+        return None;
+    }
+    loop {
+        match chars.next()? {
+            '}' if fields.is_empty() => break,
+            '"' => (),
+            _ => return None,
+        }
+        let name = parse_string(&mut chars)?;
+        if chars.next()? != ':' {
+            return None;
+        }
+        let value = if chars.peek() == Some(&'"') {
+            chars.next();
+            parse_string(&mut chars)?
+        } else {
+            let mut value = String::new();
+            while let Some(&chr) = chars.peek() {
+                if chr == ',' || chr == '}' {
+                    break;
+                }
+                value.push(chr);
+                chars.next();
+            }
+            value
+        };
+        fields.insert(name, value);
+        match chars.next()? {
+            ',' => continue,
+            '}' => break,
+            _ => return None,
+        }
+    }
+    if chars.next().is_some() {
+        return None;
+    }
+    Some(fields)
+}
+
+/// The rest of a string whose opening quote was taken
+fn parse_string(chars: &mut impl Iterator<Item = char>) -> Option<String> {
+    let mut string = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(string),
+            '\\' => match chars.next()? {
+                'n' => string.push('\n'),
+                'r' => string.push('\r'),
+                't' => string.push('\t'),
+                'u' => {
+                    let code: String = chars.take(4).collect();
+                    string.push(char::from_u32(u32::from_str_radix(&code, 16).ok()?)?);
+                }
+                chr => string.push(chr),
+            },
+            chr => string.push(chr),
+        }
+    }
+}
+
+/// Quotes a string for the log
+fn json_string(string: &str) -> String {
+    let mut out = String::from("\"");
+    for chr in string.chars() {
+        match chr {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            chr if (chr as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", chr as u32)),
+            chr => out.push(chr),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// What `novluno-audit` looks for; every field left out matches anything
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    /// A name in any role: the player picking up, a trader, the victim or
+    /// the killer
+    pub player: Option<String>,
+    pub event: Option<String>,
+    /// Seconds since the epoch, both included
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+}
+
+impl Query {
+    pub fn matches(&self, fields: &BTreeMap<String, String>) -> bool {
+        let time = fields.get("time").and_then(|time| time.parse::<u64>().ok());
+        self.player.as_ref().is_none_or(|player| {
+            ["player", "from", "to", "victim", "killer"].iter().any(|&role| fields.get(role) == Some(player))
+        })
+            && self.event.as_ref().is_none_or(|event| fields.get("event") == Some(event))
+            && self.since.is_none_or(|since| time.is_some_and(|time| time >= since))
+            && self.until.is_none_or(|until| time.is_some_and(|time| time <= until))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Rotation {
+    /// Bytes a log may grow to before it's rotated
+    pub max_bytes: u64,
+    /// Logs kept besides the one being written
+    pub keep: usize,
+}
+
+impl Default for Rotation {
+    /// 10 MiB logs, five of them kept
+    fn default() -> Rotation {
+        Rotation { max_bytes: 10 << 20, keep: 5 }
+    }
+}
+
+/// The logs rotated out of `path` and then `path` itself, the oldest first
+pub fn log_paths(path: &Path, keep: usize) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = (1..=keep).rev().map(|number| rotated(path, number)).collect();
+    paths.push(path.to_path_buf());
+    paths
+}
+
+fn rotated(path: &Path, number: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{}", number));
+    PathBuf::from(name)
+}
+
+pub struct AuditLog {
+    path: PathBuf,
+    rotation: Rotation,
+    file: File,
+    /// Bytes in the file
+    len: u64,
+}
+
+impl AuditLog {
+    /// Opens the log at `path` to append to it
+    pub fn open(path: &Path, rotation: Rotation) -> io::Result<AuditLog> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let len = file.metadata()?.len();
+        Ok(AuditLog { path: path.to_path_buf(), rotation, file, len })
+    }
+
+    /// Appends the records, rotating the log first if they'd take it past
+    /// its size
+    pub fn write(&mut self, records: &[Record], time: u64) -> io::Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let text: String = records.iter().map(|record| record.to_json(time) + "\n").collect();
+        if self.len > 0 && self.len + text.len() as u64 > self.rotation.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(text.as_bytes())?;
+        self.len += text.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.rotation.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for number in (1..self.rotation.keep).rev() {
+                let from = rotated(&self.path, number);
+                if from.exists() {
+                    fs::rename(&from, rotated(&self.path, number + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated(&self.path, 1))?;
+        }
+        *self = AuditLog::open(&self.path, self.rotation)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json() {
+        let trade = Record {
+            tick: 43,
+            event: AuditEvent::Trade { from: "Lavita".into(), to: "\"달빛\"".into(), items: vec![(7, 3), (9, 1)] },
+        };
+        let line = trade.to_json(1700000000);
+        assert_eq!(line, "{\"time\":1700000000,\"tick\":43,\"event\":\"trade\",\"from\":\"Lavita\",\
+                          \"to\":\"\\\"달빛\\\"\",\"items\":\"7:3,9:1\"}");
+        let fields = parse_fields(&line).unwrap();
+        assert_eq!(fields["tick"], "43");
+        assert_eq!(fields["to"], "\"달빛\"");
+        assert_eq!(fields["items"], "7:3,9:1");
+
+        let command = Record { tick: 1, event: AuditEvent::Command { line: "shutdown a, b}\n".into() } };
+        assert_eq!(parse_fields(&command.to_json(0)).unwrap()["line"], "shutdown a, b}\n");
+        assert!(parse_fields("{\"tick\":1").is_none());
+        assert!(parse_fields("[1]").is_none());
+        assert_eq!(parse_fields("{}").map(|fields| fields.len()), Some(0));
+
+        let query = Query { player: Some("\"달빛\"".into()), since: Some(1700000000), ..Query::default() };
+        assert!(query.matches(&fields));
+        assert!(!Query { until: Some(1699999999), ..query.clone() }.matches(&fields));
+        assert!(!Query { event: Some("death".into()), ..query }.matches(&fields));
+    }
+
+    #[test]
+    fn test_rotation() {
+        let folder = std::env::temp_dir().join(format!("novluno_audit_{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("audit.log");
+        let record = Record { tick: 1, event: AuditEvent::Pickup { player: "Lavita".into(), item: 7, count: 3 } };
+        let line_len = record.to_json(0).len() as u64 + 1;
+
+        // two records a log, two logs kept besides the one written
+        let mut log = AuditLog::open(&path, Rotation { max_bytes: line_len * 2, keep: 2 }).unwrap();
+        for tick in 0..7 {
+            log.write(&[Record { tick, ..record.clone() }], 0).unwrap();
+        }
+        let ticks: Vec<Vec<String>> = log_paths(&path, 2).iter()
+            .map(|path| fs::read_to_string(path).unwrap().lines().map(|line| parse_fields(line).unwrap()["tick"].clone()).collect())
+            .collect();
+        assert_eq!(ticks, vec![vec!["2", "3"], vec!["4", "5"], vec!["6"]]);
+        assert!(!rotated(&path, 3).exists());
+
+        // appends to what's there
+        let mut log = AuditLog::open(&path, Rotation { max_bytes: line_len * 2, keep: 2 }).unwrap();
+        log.write(std::slice::from_ref(&record), 0).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+        let _ = fs::remove_dir_all(&folder);
+    }
+}
@@ -0,0 +1,90 @@
+//! `novluno-audit <log> [--player <name>] [--event <kind>] [--since <time>] [--until <time>] [--keep <n>]`
+//!
+//! Searches the audit log of `novluno-server --audit`, and the `--keep` logs
+//! rotated out of it (5 by default) before that, printing the matching
+//! records as they are, the oldest first. `--player` matches a player in any
+//! role, `--event` is `pickup`, `trade`, `death` or `command`, and the times
+//! are in seconds since the epoch. See `server::audit`.
+
+extern crate server;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use server::audit::{log_paths, parse_fields, Query, Rotation};
+use server::error::Error;
+
+static USAGE: &str = "usage: novluno-audit <log> [--player <name>] [--event <kind>] [--since <time>] [--until <time>] \
+                      [--keep <n>]";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(true) => (),
+        Ok(false) => {
+            println!("{}", USAGE);
+            std::process::exit(2);
+        }
+        Err(error) => {
+            println!("{:?}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<bool, Error> {
+    let mut log = None;
+    let mut query = Query::default();
+    let mut keep = Rotation::default().keep;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if !arg.starts_with("--") {
+            if log.is_some() {
+                return Ok(false);
+            }
+            log = Some(arg);
+            continue;
+        }
+        match (arg.as_str(), iter.next()) {
+            ("--player", Some(name)) => query.player = Some(name.clone()),
+            ("--event", Some(event)) => query.event = Some(event.clone()),
+            ("--since", Some(time)) => match time.parse() {
+                Ok(time) => query.since = Some(time),
+                Err(_) => return Ok(false),
+            },
+            ("--until", Some(time)) => match time.parse() {
+                Ok(time) => query.until = Some(time),
+                Err(_) => return Ok(false),
+            },
+            ("--keep", Some(count)) => match count.parse() {
+                Ok(count) => keep = count,
+                Err(_) => return Ok(false),
+            },
+            _ => return Ok(false),
+        }
+    }
+    let log = match log {
+        Some(log) => log,
+        None => return Ok(false),
+    };
+
+    for path in log_paths(Path::new(log), keep) {
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(error.into()),
+        };
+        for line in text.lines() {
+            match parse_fields(line) {
+                Some(fields) => {
+                    if query.matches(&fields) {
+                        println!("{}", line);
+                    }
+                }
+                None => eprintln!("{:?}: not a record: {}", path, line),
+            }
+        }
+    }
+    Ok(true)
+}
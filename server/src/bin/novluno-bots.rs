@@ -0,0 +1,223 @@
+//! `novluno-bots --server <addr> [--bots <n>] [--seconds <s>] [--seed <n>]`
+//!
+//! Load test for the server emulator: connects a swarm of bots that walk
+//! around and chat at random while pinging the server, then reports the ping
+//! latency percentiles and how many server ticks overran.
+
+extern crate core_net;
+extern crate server;
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use core_net::packet::Packet;
+use core_net::stream::PacketReader;
+
+static USAGE: &str = "usage: novluno-bots --server <addr> [--bots <n>] [--seconds <s>] [--seed <n>]";
+
+/// How long a bot waits for its login
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(5);
+/// Bounds of the pause between two actions of a bot, in milliseconds
+const THINK_MS: (u64, u64) = (50, 250);
+
+struct Options {
+    server: String,
+    bots: usize,
+    duration: Duration,
+    seed: u64,
+}
+
+#[derive(Default)]
+struct BotReport {
+    latencies: Vec<Duration>,
+    actions: usize,
+    /// The server's count at the bot's last pong
+    tick: u64,
+    tick_overruns: u32,
+}
+
+/// xorshift64*, good enough to make the bots wander
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) % n
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_options(&args) {
+        Some(options) => options,
+        None => {
+            println!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let threads: Vec<_> = (0..options.bots).map(|n| {
+        let server = options.server.clone();
+        let (duration, seed) = (options.duration, options.seed + n as u64);
+        thread::spawn(move || run_bot(n, &server, duration, seed))
+    }).collect();
+
+    let mut reports = Vec::new();
+    for (n, thread) in threads.into_iter().enumerate() {
+        match thread.join() {
+            Ok(Ok(report)) => reports.push(report),
+            Ok(Err(error)) => println!("bot{} failed: {}", n, error),
+            Err(_) => println!("bot{} panicked", n),
+        }
+    }
+    print!("{}", summary(options.bots, options.duration, &reports));
+}
+
+fn parse_options(args: &[String]) -> Option<Options> {
+    let mut options = Options { server: String::new(), bots: 10, duration: Duration::from_secs(10), seed: 1 };
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let value = iter.next()?;
+        match arg.as_str() {
+            "--server" => options.server = value.clone(),
+            "--bots" => options.bots = value.parse().ok()?,
+            "--seconds" => options.duration = Duration::from_secs(value.parse().ok()?),
+            "--seed" => options.seed = value.parse().ok()?,
+            _ => return None,
+        }
+    }
+    if options.server.is_empty() { None } else { Some(options) }
+}
+
+fn run_bot(n: usize, server: &str, duration: Duration, seed: u64) -> Result<BotReport, String> {
+    let mut stream = TcpStream::connect(server).map_err(|e| e.to_string())?;
+    let _ = stream.set_nodelay(true);
+    let packets = spawn_reader(stream.try_clone().map_err(|e| e.to_string())?);
+    let mut send = |packet: Packet| stream.write_all(&packet.encode()).map_err(|e| e.to_string());
+
+    send(Packet::Login { name: format!("bot{}", n) })?;
+    let (id, mut x, mut y) = loop {
+        match packets.recv_timeout(LOGIN_TIMEOUT) {
+            Ok(Packet::LoginOk { id, x, y, .. }) => break (id, x, y),
+            Ok(_) => (),
+            Err(_) => return Err("no login".into()),
+        }
+    };
+
+    let mut rng = Rng::new(seed);
+    let mut report = BotReport::default();
+    let mut pings: HashMap<u32, Instant> = HashMap::new();
+    let deadline = Instant::now() + duration;
+    let mut seq = 0;
+    while Instant::now() < deadline {
+        match rng.below(10) {
+            0..=5 => {
+                let step = |v: u16, r: u64| (v as i32 + r as i32 - 1).max(0) as u16;
+                send(Packet::Walk { x: step(x, rng.below(3)), y: step(y, rng.below(3)) })?;
+            }
+            6 | 7 => send(Packet::Say { text: format!("bot{} says {}", n, rng.below(1000)) })?,
+            _ => (),
+        }
+        report.actions += 1;
+        seq += 1;
+        pings.insert(seq, Instant::now());
+        send(Packet::Ping { seq })?;
+
+        let think = Duration::from_millis(THINK_MS.0 + rng.below(THINK_MS.1 - THINK_MS.0));
+        let next_action = Instant::now() + think;
+        loop {
+            let now = Instant::now();
+            if now >= next_action {
+                break;
+            }
+            match packets.recv_timeout(next_action - now) {
+                Ok(Packet::Pong { seq, tick, tick_overruns }) => {
+                    if let Some(sent) = pings.remove(&seq) {
+                        report.latencies.push(sent.elapsed());
+                    }
+                    report.tick = tick;
+                    report.tick_overruns = tick_overruns;
+                }
+                Ok(Packet::Moved { id: moved, x: new_x, y: new_y }) if moved == id => {
+                    x = new_x;
+                    y = new_y;
+                }
+                Ok(_) | Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => return Err("server closed the connection".into()),
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Forwards the packets from the server until the connection closes
+fn spawn_reader(stream: TcpStream) -> Receiver<Packet> {
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        let mut reader = PacketReader::new(stream);
+        while let Ok(Some(packet)) = reader.read_packet() {
+            if sender.send(packet).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::from_secs(0);
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn summary(bots: usize, duration: Duration, reports: &[BotReport]) -> String {
+    let mut latencies: Vec<Duration> = reports.iter().flat_map(|r| r.latencies.iter().cloned()).collect();
+    latencies.sort();
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    let actions: usize = reports.iter().map(|r| r.actions).sum();
+    // the latest report has the highest counts
+    let server = reports.iter().max_by_key(|r| r.tick);
+    let mut summary = format!("{} of {} bots ran for {:?}, {} actions, {} pongs\n",
+                              reports.len(), bots, duration, actions, latencies.len());
+    summary.push_str(&format!("latency ms: p50 {:.1}, p90 {:.1}, p99 {:.1}, max {:.1}\n",
+                              ms(percentile(&latencies, 50.0)), ms(percentile(&latencies, 90.0)),
+                              ms(percentile(&latencies, 99.0)), ms(percentile(&latencies, 100.0))));
+    if let Some(server) = server {
+        summary.push_str(&format!("server: {} ticks, {} overruns\n", server.tick, server.tick_overruns));
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let values: Vec<Duration> = (1..=10).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&values, 50.0), Duration::from_millis(5));
+        assert_eq!(percentile(&values, 90.0), Duration::from_millis(9));
+        assert_eq!(percentile(&values, 99.0), Duration::from_millis(10));
+        assert_eq!(percentile(&values, 0.0), Duration::from_millis(1));
+        assert_eq!(percentile(&[], 50.0), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn test_rng_stays_below() {
+        let mut rng = Rng::new(3);
+        assert!((0..1000).all(|_| rng.below(3) < 3));
+    }
+}
@@ -0,0 +1,91 @@
+//! `novluno-decode-packet <hex|file> [--defs <file>] [--encrypted]`
+//!
+//! Decodes a single packet, given as hex (the `data` of a proxy log record)
+//! or as a file of raw bytes: names the packet, prints the fields known from
+//! the definitions and hex-dumps the rest. `--encrypted` decrypts the packet
+//! first, for the `raw` bytes of a record. See `server::packet` for the
+//! definition format.
+
+extern crate server;
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use server::crypto;
+use server::error::Error;
+use server::packet::{decode, from_hex, hex_dump, parse_definitions};
+
+static USAGE: &str = "usage: novluno-decode-packet <hex|file> [--defs <file>] [--encrypted]";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(true) => (),
+        Ok(false) => {
+            println!("{}", USAGE);
+            std::process::exit(2);
+        }
+        Err(error) => {
+            println!("{:?}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<bool, Error> {
+    let mut input = None;
+    let mut defs_path = None;
+    let mut encrypted = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--defs" => match iter.next() {
+                Some(path) => defs_path = Some(path),
+                None => return Ok(false),
+            },
+            "--encrypted" => encrypted = true,
+            _ if input.is_none() => input = Some(arg),
+            _ => return Ok(false),
+        }
+    }
+    let input = match input {
+        Some(input) => input,
+        None => return Ok(false),
+    };
+
+    let mut data = Vec::new();
+    if Path::new(input).is_file() {
+        File::open(input)?.read_to_end(&mut data)?;
+    } else {
+        let hex: String = input.split_whitespace().collect();
+        data = match from_hex(&hex) {
+            Some(data) => data,
+            None => return Ok(false),
+        };
+    }
+    if encrypted {
+        data = crypto::decrypt(&data);
+    }
+    let defs = match defs_path {
+        Some(path) => {
+            let mut table = String::new();
+            File::open(path)?.read_to_string(&mut table)?;
+            parse_definitions(&table)?
+        }
+        None => Vec::new(),
+    };
+
+    let decoded = decode(&defs, &data);
+    match decoded.def {
+        Some(def) => println!("{} ({} bytes)", def.name, data.len()),
+        None => println!("unknown packet ({} bytes)", data.len()),
+    }
+    for field in decoded.fields.iter() {
+        println!("{:04x}  {} = {}", field.offset, field.name, field.value);
+    }
+    if decoded.rest < data.len() {
+        print!("{}", hex_dump(&data[decoded.rest..], decoded.rest));
+    }
+    Ok(true)
+}
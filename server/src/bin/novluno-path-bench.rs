@@ -0,0 +1,113 @@
+//! `novluno-path-bench <server map folder> [--maps <n>] [--queries <n>] [--limit <n>]`
+//!
+//! Times the path search of `core_rules::pathfind` on the largest of the
+//! server maps in the folder (as written by the `data_converter`): from and
+//! to random walkable tiles, `--queries` times on each of the `--maps`
+//! largest maps. Without `--limit` a search may look at the whole map, the
+//! worst case of a click-to-move across it.
+
+extern crate core_rules;
+extern crate server;
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use core_rules::pathfind::{self, Tile};
+use core_rules::random::Rng;
+use server::error::Error;
+use server::map::{load_server_map, ServerMap};
+
+static USAGE: &str = "usage: novluno-path-bench <server map folder> [--maps <n>] [--queries <n>] [--limit <n>]";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(true) => (),
+        Ok(false) => {
+            println!("{}", USAGE);
+            std::process::exit(2);
+        }
+        Err(error) => {
+            println!("{:?}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<bool, Error> {
+    let mut folder = None;
+    let (mut map_count, mut queries, mut limit) = (3, 200, None);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let value = match arg.as_str() {
+            "--maps" | "--queries" | "--limit" => match iter.next().and_then(|value| value.parse().ok()) {
+                Some(value) => value,
+                None => return Ok(false),
+            },
+            _ if folder.is_none() && !arg.starts_with("--") => {
+                folder = Some(arg);
+                continue;
+            }
+            _ => return Ok(false),
+        };
+        match arg.as_str() {
+            "--maps" => map_count = value,
+            "--queries" => queries = value,
+            _ => limit = Some(value),
+        }
+    }
+    let folder = match folder {
+        Some(folder) => folder,
+        None => return Ok(false),
+    };
+
+    let mut maps = Vec::new();
+    for entry in fs::read_dir(Path::new(folder))? {
+        let path = entry?.path();
+        match load_server_map(&fs::read(&path)?) {
+            Ok(map) => maps.push(map),
+            Err(error) => println!("skipping {:?}: {:?}", path, error),
+        }
+    }
+    maps.sort_by_key(|map| (std::cmp::Reverse(map.size_x * map.size_y), map.number));
+    println!("map    size       walkable  queries  mean        max         mean steps  cut short");
+    for map in maps.iter().take(map_count) {
+        bench(map, queries, limit);
+    }
+    Ok(true)
+}
+
+fn bench(map: &ServerMap, queries: usize, limit: Option<usize>) {
+    let walkable: Vec<Tile> = (0..map.size_y)
+        .flat_map(|y| (0..map.size_x).map(move |x| (x, y)))
+        .filter(|&(x, y)| map.is_passable(x, y))
+        .map(|(x, y)| (x as u16, y as u16))
+        .collect();
+    if walkable.is_empty() {
+        println!("{:<6} {:<10} nothing walkable", map.number, format!("{}x{}", map.size_x, map.size_y));
+        return;
+    }
+    let limit = limit.unwrap_or(walkable.len());
+    let passable = |(x, y): Tile| map.is_passable(x as u32, y as u32);
+    let mut rng = Rng::new(map.number as u64);
+    let (mut total, mut max, mut steps, mut cut_short) = (Duration::default(), Duration::default(), 0, 0);
+    for _ in 0..queries {
+        let from = walkable[rng.below(walkable.len() as u32) as usize];
+        let goal = walkable[rng.below(walkable.len() as u32) as usize];
+        let start = Instant::now();
+        let path = pathfind::find_path(passable, from, goal, 0, limit);
+        let elapsed = start.elapsed();
+        total += elapsed;
+        max = max.max(elapsed);
+        steps += path.len();
+        if path.last().map_or(from, |&tile| tile) != goal {
+            cut_short += 1;
+        }
+    }
+    let queries = queries.max(1);
+    println!("{:<6} {:<10} {:<9} {:<8} {:<11} {:<11} {:<11} {}",
+             map.number, format!("{}x{}", map.size_x, map.size_y), walkable.len(), queries,
+             format!("{:.1?}", total / queries as u32), format!("{:.1?}", max),
+             steps / queries, cut_short);
+}
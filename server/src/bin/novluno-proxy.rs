@@ -0,0 +1,67 @@
+//! `novluno-proxy --server <addr> [--listen <addr>] [--log <file>] [--rules <file>]`
+//!
+//! Sits between a client and a server and logs the decrypted traffic; see
+//! `server::proxy` for the log and rule formats.
+
+extern crate server;
+
+use std::fs::File;
+use std::io::Read;
+
+use server::error::Error;
+use server::proxy;
+use server::proxy::ProxyConfig;
+
+const DEFAULT_LISTEN_ADDR: &str = "127.0.0.1:10101";
+
+static USAGE: &str = "usage: novluno-proxy --server <addr> [--listen <addr>] [--log <file>] [--rules <file>]";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = match parse_args(&args) {
+        Ok(Some(config)) => config,
+        Ok(None) => {
+            println!("{}", USAGE);
+            std::process::exit(2);
+        }
+        Err(error) => {
+            println!("{:?}", error);
+            std::process::exit(1);
+        }
+    };
+    if let Err(error) = proxy::run(&config) {
+        println!("{:?}", error);
+        std::process::exit(1);
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Option<ProxyConfig>, Error> {
+    let mut server = None;
+    let mut listen = DEFAULT_LISTEN_ADDR.to_string();
+    let mut log = None;
+    let mut rules = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let value = match iter.next() {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        match arg.as_str() {
+            "--server" => server = Some(value.clone()),
+            "--listen" => listen = value.clone(),
+            "--log" => log = Some(value.into()),
+            "--rules" => {
+                let mut table = String::new();
+                File::open(value)?.read_to_string(&mut table)?;
+                rules = proxy::parse_rules(&table)?;
+            }
+            _ => return Ok(None),
+        }
+    }
+    Ok(server.map(|server| {
+        let mut config = ProxyConfig::new(&listen, &server);
+        config.log = log;
+        config.rules = rules;
+        config
+    }))
+}
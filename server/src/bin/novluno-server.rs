@@ -0,0 +1,187 @@
+//! `novluno-server [--listen <addr>] [--tick-ms <ms>] [--guilds <file>] [--characters <file>]
+//! [--snapshot <file>] [--tables <folder>] [--console] [--on-anomaly <kind>=<response>]...
+//! [--on-overflow <policy>] [--metrics <addr>] [--audit <file>]`
+//!
+//! Runs the game server emulator; see `server::game` and `server::net`. The
+//! guilds are loaded from and saved to the guild file, if one is given, and
+//! the characters to the character file. The world is restored from the
+//! snapshot file, if there's one, and written back to it at shutdown. With
+//! the rule tables of `core_rules::table`, fights follow the combat formulas
+//! and the spawn table keeps the maps populated. `--console` reads admin
+//! commands from stdin, `reload` of the tables and `shutdown` among them;
+//! see `server::admin`. The day
+//! starts in the morning and lasts two hours; see `server::ambiance`.
+//! `--on-anomaly` changes what is done about an anomaly (`flood`, `range` or
+//! `speed`): `log`, `throttle` or `disconnect` the player; see
+//! `server::anomaly`. `--on-overflow` is what happens to a player whose
+//! outgoing queue is full: `disconnect`, or `drop-newest` or `drop-oldest`
+//! packets; see `server::limit`. `--metrics` serves the metrics of
+//! `server::metrics` over HTTP on the address given, for Prometheus to
+//! scrape. `--audit` appends the pick ups, trades, deaths and admin commands
+//! to the audit log, rotated every 10 MiB; see `server::audit` and
+//! `novluno-audit`.
+
+extern crate core_rules;
+extern crate server;
+
+use std::fs;
+use std::io;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use core_rules::table::Tables;
+use std::time::Duration;
+
+use server::ambiance::{Ambiance, AmbianceConfig};
+use server::anomaly::{Anomaly, AnomalyConfig, Detector, Response};
+use server::error::Error;
+use server::game::GameServer;
+use server::group::Guilds;
+use server::limit::{LimitConfig, Limiter, Overflow};
+use server::net::{self, ServeConfig};
+use server::save::{Characters, Snapshot};
+use server::spawn::Spawner;
+use server::world::{World, WorldConfig};
+
+const DEFAULT_LISTEN_ADDR: &str = "127.0.0.1:10101";
+
+static USAGE: &str = "usage: novluno-server [--listen <addr>] [--tick-ms <ms>] [--guilds <file>] [--characters <file>] \
+                      [--snapshot <file>] [--tables <folder>] [--console] \
+                      [--on-anomaly <flood|range|speed>=<log|throttle|disconnect>]... \
+                      [--on-overflow <disconnect|drop-newest|drop-oldest>] [--metrics <addr>] [--audit <file>]";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut listen = DEFAULT_LISTEN_ADDR.to_string();
+    let mut tick = net::DEFAULT_TICK;
+    let mut guild_file = None;
+    let mut character_file = None;
+    let mut snapshot_file = None;
+    let mut tables = None;
+    let mut console = false;
+    let mut anomalies = AnomalyConfig::default();
+    let mut limits = LimitConfig::default();
+    let mut metrics = None;
+    let mut audit_file = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--console" {
+            console = true;
+            continue;
+        }
+        match (arg.as_str(), iter.next()) {
+            ("--listen", Some(addr)) => listen = addr.clone(),
+            ("--tick-ms", Some(ms)) => match ms.parse() {
+                Ok(ms) => tick = Duration::from_millis(ms),
+                Err(_) => return println!("{}", USAGE),
+            },
+            ("--guilds", Some(path)) => guild_file = Some(PathBuf::from(path)),
+            ("--characters", Some(path)) => character_file = Some(PathBuf::from(path)),
+            ("--snapshot", Some(path)) => snapshot_file = Some(PathBuf::from(path)),
+            ("--audit", Some(path)) => audit_file = Some(PathBuf::from(path)),
+            ("--tables", Some(path)) => tables = Some(path.clone()),
+            ("--on-anomaly", Some(setting)) => {
+                let mut parts = setting.splitn(2, '=');
+                match (parts.next().and_then(Anomaly::from_name), parts.next().and_then(Response::from_name)) {
+                    (Some(anomaly), Some(response)) => anomalies.set_response(anomaly, response),
+                    _ => return println!("{}", USAGE),
+                }
+            }
+            ("--on-overflow", Some(policy)) => match Overflow::from_name(policy) {
+                Some(overflow) => limits.overflow = overflow,
+                None => return println!("{}", USAGE),
+            },
+            ("--metrics", Some(addr)) => match addr.parse() {
+                Ok(addr) => metrics = Some(addr),
+                Err(_) => return println!("{}", USAGE),
+            },
+            _ => return println!("{}", USAGE),
+        }
+    }
+
+    let mut world = World::new(WorldConfig { start_map: 3, start_x: 20, start_y: 20 });
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let mut spawner = None;
+    if let Some(ref folder) = tables {
+        match Tables::load(Path::new(folder)) {
+            Ok(tables) => {
+                spawner = Some(Spawner::new(&tables.spawns, tick, seed));
+                world.set_tables(tables, seed);
+            }
+            Err(error) => {
+                println!("the rule tables could not be loaded from `{}`: {:?}", folder, error);
+                std::process::exit(1);
+            }
+        }
+    }
+    let mut game = GameServer::new(world);
+    game.tables_folder = tables.map(PathBuf::from);
+    game.ambiance = Ambiance::new(AmbianceConfig::default(), tick, seed);
+    game.anomalies = Detector::new(anomalies);
+    game.limits = Limiter::new(limits);
+    if let Some(spawner) = spawner {
+        game.spawner = spawner;
+    }
+    if let Some(ref path) = guild_file {
+        // a missing file is no guilds yet
+        match load(path, Guilds::parse) {
+            Ok(guilds) => game.guilds = guilds.unwrap_or_else(Guilds::new),
+            Err(error) => {
+                println!("the guilds could not be loaded from {:?}: {:?}", path, error);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(ref path) = character_file {
+        match load(path, Characters::parse) {
+            Ok(characters) => game.characters = characters.unwrap_or_else(Characters::new),
+            Err(error) => {
+                println!("the characters could not be loaded from {:?}: {:?}", path, error);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(ref path) = snapshot_file {
+        match load(path, Snapshot::parse) {
+            Ok(Some(snapshot)) => game.restore(&snapshot),
+            Ok(None) => (),
+            Err(error) => {
+                println!("the snapshot could not be loaded from {:?}: {:?}", path, error);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let listener = match TcpListener::bind(&listen) {
+        Ok(listener) => listener,
+        Err(error) => {
+            println!("address `{}` could not be bound: {}", listen, error);
+            std::process::exit(1);
+        }
+    };
+    println!("serving on `{}`, ticking every {:?}", listen, tick);
+    let config = ServeConfig {
+        tick,
+        guild_file,
+        character_file,
+        snapshot_file,
+        audit_file,
+        console,
+        metrics,
+        ..ServeConfig::default()
+    };
+    if let Err(error) = net::serve(listener, game, config) {
+        println!("{:?}", error);
+        std::process::exit(1);
+    }
+}
+
+/// Parses a file; `None` if it isn't there
+fn load<T>(path: &Path, parse: fn(&str) -> Result<T, Error>) -> Result<Option<T>, Error> {
+    match fs::read_to_string(path) {
+        Ok(text) => parse(&text).map(Some),
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
@@ -24,9 +24,23 @@ pub fn encrypt (input: &[u8]) -> Vec<u8> {
         if *next <= 7 {
             output.push(7);
             output.push(*next ^ 15);
+        } else {
+            output.push(*next);
         }
-        output.push(*next);
     }
 
     output
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_round_trip() {
+        let data = [0, 7, 8, 15, 0x41, 0xFF];
+        let encrypted = encrypt(&data);
+        assert_eq!(encrypted, vec![7, 15, 7, 8, 8, 15, 0x41, 0xFF]);
+        assert_eq!(decrypt(&encrypted), data.to_vec());
+    }
+}
@@ -0,0 +1,19 @@
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidCharacterLine(usize),
+    InvalidGuildLine(usize),
+    InvalidPacketDefinition(usize),
+    InvalidProxyRule(usize),
+    InvalidSnapshotLine(usize),
+    Io(io::Error),
+    MissingServerMapIdentifier,
+    UnsupportedServerMapVersion(u32),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
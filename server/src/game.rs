@@ -0,0 +1,776 @@
+//! Sessions on top of the world: turns the packets of the clients into world
+//! actions and tells every client what it needs to know about the results.
+//!
+//! Parties and guilds (see `group`) are sent whole to their online members
+//! whenever they change, and the guild once more at login. Trades (see
+//! `trade`) are sent to both traders on every change, together with the
+//! inventories they change.
+//!
+//! Players only hear about what happens within their view (see `interest`):
+//! entities coming into view are spawned on the client, the ones going out
+//! of it despawned, and moves, hits and chat go to those that see them.
+//!
+//! Monsters act at the end of every tick, after the players (see `ai`), and
+//! the spawner fills up the maps before them (see `spawn`). Dead monsters
+//! leave the world right away, dropping what the drop table rolls on their
+//! tile for the killer and their party (see `ground`).
+//!
+//! The packets are checked for what an honest client never sends (see
+//! `anomaly`) before they're handled: a flooding session is looked at before
+//! any of its packets, and a session to disconnect is, once the packets of
+//! the tick are all handled.
+//!
+//! Every session is rate limited by packet category, with bounded queues
+//! both ways (see `limit`): the packets over the limits are dropped before
+//! they're handled, and a session to disconnect for a full queue is at the
+//! end of the tick.
+//!
+//! The time of day and the weather (see `ambiance`) go to every player at
+//! login and to all of them at once whenever they need telling again.
+//!
+//! The characters of the players are kept by name (see `save`) when they
+//! leave, and given back when they log in again. `shut_down` tells every
+//! player the server is going down and keeps their characters; from then on
+//! nobody logs in, and the driver closes the connections. A snapshot of what
+//! else lives in the world can be taken, and restored before anyone logs in.
+//!
+//! The rule tables can be swapped while the game runs (see
+//! `reload_tables`), to try out new numbers without a restart.
+//!
+//! Pick ups, completed trades and deaths are recorded for the audit log (see
+//! `audit`) until the driver takes them.
+//!
+//! Of two players picking up the same drop in a tick, the one whose session
+//! comes first gets it; a drop picked up in the tick it expires is still
+//! picked up, as the packets are handled before the drops expire.
+//!
+//! The game server doesn't do any IO or look at the clock. Packets are queued
+//! with `receive`, handled in `tick` in session order and picked up with
+//! `take_outgoing`, so the same inputs always give the same outputs; whoever
+//! drives it decides where the packets come from and how often it ticks.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use core_net::packet::Packet;
+use core_rules::ambiance::Weather;
+use core_rules::diff::{self, Change};
+use core_rules::table::Tables;
+
+use crate::ai::{Action, Ai, AiConfig};
+use crate::ambiance::{Ambiance, AmbianceConfig};
+use crate::anomaly::{AnomalyConfig, Detector, Response};
+use crate::audit::{AuditEvent, Record};
+use crate::ground::{DropConfig, GroundItems};
+use crate::group::{Guild, Guilds, Parties};
+use crate::interest::{Interest, InterestConfig, Visibility};
+use crate::limit::{Category, LimitConfig, Limiter, Overflow};
+use crate::net::DEFAULT_TICK;
+use crate::save::{Character, Characters, Snapshot};
+use crate::spawn::Spawner;
+use crate::trade::{Outcome, TradeId, Trades, TRADE_RANGE};
+use crate::world::{Entity, EntityId, EntityKind, World};
+
+pub type SessionId = usize;
+
+#[derive(Default)]
+struct Session {
+    player: Option<EntityId>,
+    incoming: VecDeque<Packet>,
+    outgoing: Vec<Packet>,
+}
+
+pub struct GameServer {
+    pub world: World,
+    /// Ticks done so far
+    pub tick: u64,
+    /// Ticks that took longer than the tick rate, as counted by the driver
+    pub tick_overruns: u32,
+    pub interest: Interest,
+    pub parties: Parties,
+    /// Loaded and saved by the driver; see `Guilds::take_changed`
+    pub guilds: Guilds,
+    pub trades: Trades,
+    pub ai: Ai,
+    /// Empty until given the spawn table
+    pub spawner: Spawner,
+    pub ground: GroundItems,
+    pub ambiance: Ambiance,
+    pub anomalies: Detector,
+    pub limits: Limiter,
+    /// Loaded and saved by the driver; see `Characters::take_changed`
+    pub characters: Characters,
+    /// Where the rule tables were loaded from, for the admin console to
+    /// load them again
+    pub tables_folder: Option<PathBuf>,
+    /// Whether `shut_down` was called
+    shutting_down: bool,
+    sessions: BTreeMap<SessionId, Session>,
+    /// The session of each player
+    players: BTreeMap<EntityId, SessionId>,
+    next_session: SessionId,
+    /// Sessions to disconnect at the end of the packets, or of the tick
+    kicked: BTreeSet<SessionId>,
+    /// For the audit log, until `take_audit`
+    audit: Vec<Record>,
+}
+
+impl GameServer {
+    pub fn new(world: World) -> GameServer {
+        GameServer {
+            world,
+            tick: 0,
+            tick_overruns: 0,
+            interest: Interest::new(InterestConfig::default()),
+            parties: Parties::new(),
+            guilds: Guilds::new(),
+            trades: Trades::new(),
+            ai: Ai::new(AiConfig::default(), 0),
+            spawner: Spawner::new(&[], Duration::from_secs(1), 0),
+            ground: GroundItems::new(DropConfig::default(), 0),
+            ambiance: Ambiance::new(AmbianceConfig::default(), DEFAULT_TICK, 0),
+            anomalies: Detector::new(AnomalyConfig::default()),
+            limits: Limiter::new(LimitConfig::default()),
+            characters: Characters::new(),
+            tables_folder: None,
+            shutting_down: false,
+            sessions: BTreeMap::new(),
+            players: BTreeMap::new(),
+            next_session: 0,
+            kicked: BTreeSet::new(),
+            audit: Vec::new(),
+        }
+    }
+
+    pub fn connect(&mut self) -> SessionId {
+        let session = self.next_session;
+        self.next_session += 1;
+        self.sessions.insert(session, Session::default());
+        session
+    }
+
+    /// Ends a session; its player leaves the world.
+    pub fn disconnect(&mut self, session: SessionId) {
+        let player = self.sessions.remove(&session).and_then(|s| s.player);
+        self.anomalies.remove(session);
+        self.limits.remove(session);
+        if let Some(id) = player {
+            self.leave_party(id);
+            self.cancel_trade(id);
+            self.players.remove(&id);
+            if let Some(entity) = self.world.remove(id) {
+                self.characters.store(&entity.name, Character::of(&entity));
+            }
+            let events = self.interest.remove(id);
+            self.send_visibility(events);
+        }
+    }
+
+    /// Whether the session is still there; the server disconnects the ones
+    /// the anomaly checks say so
+    pub fn is_connected(&self, session: SessionId) -> bool {
+        self.sessions.contains_key(&session)
+    }
+
+    pub fn player(&self, session: SessionId) -> Option<EntityId> {
+        self.sessions.get(&session).and_then(|s| s.player)
+    }
+
+    /// Queues a packet for the next tick; dropped if the queue is full.
+    pub fn receive(&mut self, session: SessionId, packet: Packet) {
+        if let Some(session) = self.sessions.get_mut(&session) {
+            if session.incoming.len() < self.limits.config.max_incoming {
+                session.incoming.push_back(packet);
+            } else {
+                self.limits.overflowed();
+            }
+        }
+    }
+
+    pub fn take_outgoing(&mut self, session: SessionId) -> Vec<Packet> {
+        match self.sessions.get_mut(&session) {
+            Some(session) => std::mem::take(&mut session.outgoing),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn tick(&mut self) {
+        let ids: Vec<SessionId> = self.sessions.keys().cloned().collect();
+        for id in ids {
+            let count = self.sessions.get(&id).map_or(0, |s| s.incoming.len());
+            if let Some(response) = self.anomalies.received(id, count, self.tick) {
+                self.respond(id, response);
+            }
+            if self.anomalies.is_throttled(id, self.tick) {
+                if let Some(session) = self.sessions.get_mut(&id) {
+                    session.incoming.truncate(1);
+                }
+            }
+            while let Some(packet) = self.sessions.get_mut(&id).and_then(|s| s.incoming.pop_front()) {
+                if self.limits.allow(id, Category::of(&packet), self.tick) {
+                    self.handle(id, packet);
+                }
+            }
+        }
+        self.disconnect_kicked();
+        for id in self.ground.expired(self.tick) {
+            self.world.remove(id);
+            let events = self.interest.remove(id);
+            self.send_visibility(events);
+        }
+        for spawn in self.spawner.due(&self.world, self.tick) {
+            if let Some(id) = self.spawn_monster(spawn.monster, spawn.map, spawn.x, spawn.y) {
+                self.spawner.spawned(&spawn, id, self.tick);
+            }
+        }
+        for action in self.ai.tick(&mut self.world, self.tick) {
+            match action {
+                Action::Moved { id, x, y } => self.moved(id, x, y),
+                Action::Attacked { attacker, target, amount, hp } => {
+                    self.send_to(&[attacker, target], Packet::Damage { attacker, target, amount, hp });
+                    if hp == 0 {
+                        self.record_death(target, attacker);
+                    }
+                }
+            }
+        }
+        if self.ambiance.update(self.tick) {
+            self.send_ambiance();
+        }
+        self.disconnect_kicked();
+        self.tick += 1;
+    }
+
+    fn handle(&mut self, session: SessionId, packet: Packet) {
+        let player = self.player(session);
+        match (packet, player) {
+            (Packet::Login { name }, None) => self.login(session, &name),
+            (Packet::Ping { seq }, _) => {
+                let packet = Packet::Pong { seq, tick: self.tick, tick_overruns: self.tick_overruns };
+                self.send(session, packet);
+            }
+            (Packet::Walk { x, y }, Some(id)) => {
+                let tiles = self.world.entity(id).map_or(0, |e| e.x.abs_diff(x).max(e.y.abs_diff(y)));
+                if let Some(response) = self.anomalies.walked(session, tiles, self.tick) {
+                    self.respond(session, response);
+                }
+                if self.world.walk(id, x, y) {
+                    self.moved(id, x, y);
+                    if let Some(traders) = self.trades.check_range(&mut self.world, id) {
+                        self.close_trade(traders, false);
+                    }
+                } else if let Some(entity) = self.world.entity(id) {
+                    // puts the client back where the server has it
+                    let packet = Packet::Moved { id, x: entity.x, y: entity.y };
+                    self.send(session, packet);
+                }
+            }
+            (Packet::Attack { target }, Some(id)) => {
+                self.check_reach(session, id, target, 1);
+                if let Some((amount, hp)) = self.world.attack(id, target) {
+                    self.damaged(id, target, amount, hp);
+                }
+            }
+            (Packet::UseSkill { skill, target }, Some(id)) => {
+                let range = self.world.tables().and_then(|tables| tables.skills.get(&skill)).map(|skill| skill.range);
+                if let Some(range) = range {
+                    self.check_reach(session, id, target, range);
+                }
+                if let Some((amount, hp)) = self.world.use_skill(id, skill, target) {
+                    self.damaged(id, target, amount, hp);
+                }
+            }
+            (Packet::Say { text }, Some(id)) => self.send_to(&[id], Packet::Chat { from: id, text }),
+            (Packet::PartyInvite { target }, Some(id)) => self.invite_to_party(id, target),
+            (Packet::PartyAccept { leader }, Some(id)) => {
+                if let Some(party) = self.parties.accept(id, leader) {
+                    self.send_party(self.parties.members(party).to_vec());
+                }
+            }
+            (Packet::PartyLeave, Some(id)) => self.leave_party(id),
+            (Packet::GuildCreate { name }, Some(id)) => {
+                if let Some(guild) = self.guilds.create(&self.name(id), &name) {
+                    self.send_guild(guild);
+                }
+            }
+            (Packet::GuildInvite { target }, Some(id)) => self.invite_to_guild(id, target),
+            (Packet::GuildAccept { guild }, Some(id)) => self.join_guild(id, guild),
+            (Packet::GuildLeave, Some(id)) => {
+                if let Some(guild) = self.guilds.leave(&self.name(id)) {
+                    self.send_to_player(id, no_guild());
+                    self.send_guild(guild);
+                }
+            }
+            (Packet::TradeRequest { target }, Some(id)) => {
+                self.check_reach(session, id, target, TRADE_RANGE);
+                self.request_trade(id, target);
+            }
+            (Packet::TradeAccept { from }, Some(id)) => {
+                if let Some(trade) = self.trades.accept(&self.world, id, from) {
+                    self.send_trade(trade);
+                }
+            }
+            (Packet::TradeOffer { item, count }, Some(id)) => {
+                if let Some(trade) = self.trades.offer(&mut self.world, id, item, count) {
+                    self.send_inventory(id);
+                    self.send_trade(trade);
+                }
+            }
+            (Packet::TradeConfirm, Some(id)) => {
+                let offers = self.trades.trade_of(id).and_then(|trade| self.trades.trade(trade)).map(|t| t.offers.clone());
+                match self.trades.confirm(&mut self.world, id) {
+                    Some(Outcome::Open(trade)) => self.send_trade(trade),
+                    Some(Outcome::Completed(traders)) => {
+                        for (side, offer) in offers.into_iter().flatten().enumerate() {
+                            if !offer.is_empty() {
+                                let (from, to) = (self.name(traders[side]), self.name(traders[1 - side]));
+                                self.record(AuditEvent::Trade { from, to, items: offer.into_iter().collect() });
+                            }
+                        }
+                        self.close_trade(traders, true);
+                    }
+                    None => (),
+                }
+            }
+            (Packet::TradeCancel, Some(id)) => self.cancel_trade(id),
+            (Packet::PickUp { id: ground }, Some(id)) => {
+                self.check_reach(session, id, ground, 1);
+                self.pick_up(id, ground);
+            }
+            // everything else needs a login first or is server-only
+            _ => (),
+        }
+    }
+
+    /// Adds a monster of the monster table, acting as the table says
+    pub fn spawn_monster(&mut self, kind: u32, map: u32, x: u16, y: u16) -> Option<EntityId> {
+        let id = self.world.spawn_monster(kind, map, x, y)?;
+        let behavior = self.world.tables()?.monsters[&kind].behavior;
+        self.ai.add(&self.world, id, behavior);
+        let events = self.interest.update(&self.world, id);
+        self.send_visibility(events);
+        Some(id)
+    }
+
+    /// Changes the weather for everyone, until it's next rolled
+    pub fn set_weather(&mut self, weather: Weather) {
+        if self.ambiance.set_weather(weather) {
+            self.send_ambiance();
+        }
+    }
+
+    /// Swaps in new rule tables; returns what changed. The monsters alive
+    /// keep their hit points and how they act, their new stats counting from
+    /// the next strike, and the spawn regions start over from the new spawn
+    /// table (see `Spawner::reload`).
+    pub fn reload_tables(&mut self, tables: Tables) -> Vec<Change> {
+        let changes = diff::diff(self.world.tables().unwrap_or(&Tables::default()), &tables);
+        let spawns = tables.spawns.clone();
+        self.world.replace_tables(tables);
+        self.spawner.reload(&spawns, &self.world);
+        changes
+    }
+
+    /// Keeps the characters of every player there is, as they are now
+    pub fn save_characters(&mut self) {
+        for &id in self.players.keys() {
+            if let Some(entity) = self.world.entity(id) {
+                self.characters.store(&entity.name, Character::of(entity));
+            }
+        }
+    }
+
+    /// What lives in the world besides the players
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::of(self.tick, self.ambiance.weather(), self.world.entities())
+    }
+
+    /// Brings back what a snapshot holds, before anyone logs in: the clock
+    /// goes on from its tick, its monsters count towards their spawn regions
+    /// and its drops are anyone's.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.tick = snapshot.tick;
+        self.ambiance.update(self.tick);
+        self.ambiance.set_weather(snapshot.weather);
+        for monster in snapshot.monsters.iter() {
+            if let Some(id) = self.spawn_monster(monster.kind, monster.map, monster.x, monster.y) {
+                self.world.set_hp(id, monster.hp);
+                self.spawner.adopt(id, monster.kind, monster.map, monster.x, monster.y);
+            }
+        }
+        for ground in snapshot.ground.iter() {
+            let id = self.world.drop_item(ground.item, ground.count, ground.map, ground.x, ground.y);
+            self.ground.add(id, Vec::new(), self.tick);
+            let events = self.interest.update(&self.world, id);
+            self.send_visibility(events);
+        }
+    }
+
+    /// Tells every player the server is going down with `message` and keeps
+    /// their characters; nobody logs in anymore.
+    pub fn shut_down(&mut self, message: &str) {
+        self.shutting_down = true;
+        self.save_characters();
+        let sessions: Vec<SessionId> = self.sessions.keys().cloned().collect();
+        for session in sessions {
+            self.send(session, Packet::Shutdown { message: message.into() });
+        }
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down
+    }
+
+    /// Records an event for the audit log at the current tick
+    pub fn record(&mut self, event: AuditEvent) {
+        self.audit.push(Record { tick: self.tick, event });
+    }
+
+    /// The records since the last call, to append them to the audit log
+    pub fn take_audit(&mut self) -> Vec<Record> {
+        std::mem::take(&mut self.audit)
+    }
+
+    /// How many sessions are connected, logged in or not
+    pub fn session_count(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Every player there is, by id
+    pub fn players(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.players.keys().cloned()
+    }
+
+    /// Disconnects the kicked sessions, each kept kicked until it's gone so
+    /// it isn't kicked again for what the others' leaving sends it
+    fn disconnect_kicked(&mut self) {
+        while let Some(&session) = self.kicked.iter().next() {
+            self.disconnect(session);
+            self.kicked.remove(&session);
+        }
+    }
+
+    /// Flags player `id` using something reaching `reach` tiles on `target`
+    /// further away, if both are there
+    fn check_reach(&mut self, session: SessionId, id: EntityId, target: EntityId, reach: u16) {
+        let distance = match (self.world.entity(id), self.world.entity(target)) {
+            (Some(player), Some(target)) => player.distance(target),
+            _ => return,
+        };
+        if let Some(response) = self.anomalies.reached(session, distance, reach, self.tick) {
+            self.respond(session, response);
+        }
+    }
+
+    /// Answers an anomaly of `session`: throttling is up to the detector, a
+    /// disconnect drops the session's packets and waits for the end of them
+    fn respond(&mut self, session: SessionId, response: Response) {
+        if response == Response::Disconnect {
+            if let Some(session) = self.sessions.get_mut(&session) {
+                session.incoming.clear();
+            }
+            self.kicked.insert(session);
+        }
+    }
+
+    /// Tells both sides of a hit, and what follows from it
+    fn damaged(&mut self, attacker: EntityId, target: EntityId, amount: u16, hp: u16) {
+        self.ai.on_damaged(target, attacker);
+        self.send_to(&[attacker, target], Packet::Damage { attacker, target, amount, hp });
+        if hp == 0 {
+            self.record_death(target, attacker);
+            if self.world.entity(target).is_some_and(|e| !e.is_player()) {
+                self.monster_died(target, attacker);
+            }
+        }
+    }
+
+    fn record_death(&mut self, victim: EntityId, killer: EntityId) {
+        let (victim, killer) = (self.name(victim), self.name(killer));
+        self.record(AuditEvent::Death { victim, killer });
+    }
+
+    fn monster_died(&mut self, id: EntityId, killer: EntityId) {
+        let monster = match self.world.remove(id) {
+            Some(monster) => monster,
+            None => return,
+        };
+        self.ai.remove(id);
+        self.spawner.died(id, self.tick);
+        let events = self.interest.update(&self.world, id);
+        self.send_visibility(events);
+
+        let drops = match monster.kind {
+            EntityKind::Monster(kind) => self.ground.roll(&self.world, kind),
+            _ => Vec::new(),
+        };
+        let owners = match self.parties.party_of(killer) {
+            Some(party) => self.parties.members(party).to_vec(),
+            None => vec![killer],
+        };
+        for (item, count) in drops {
+            let ground = self.world.drop_item(item, count, monster.map, monster.x, monster.y);
+            self.ground.add(ground, owners.clone(), self.tick);
+            let events = self.interest.update(&self.world, ground);
+            self.send_visibility(events);
+        }
+    }
+
+    fn pick_up(&mut self, id: EntityId, ground: EntityId) {
+        if !self.ground.may_pick_up(ground, id, self.tick) {
+            return;
+        }
+        let (item, count) = match self.world.pick_up(id, ground) {
+            Some(picked) => picked,
+            None => return,
+        };
+        self.record(AuditEvent::Pickup { player: self.name(id), item, count });
+        self.ground.remove(ground);
+        let events = self.interest.remove(ground);
+        self.send_visibility(events);
+        self.send_inventory(id);
+    }
+
+    /// Tells everyone concerned that `id` moved to `x`, `y`
+    fn moved(&mut self, id: EntityId, x: u16, y: u16) {
+        let events = self.interest.update(&self.world, id);
+        // the ones that just saw it appear have the new position
+        let spawned: Vec<EntityId> = events.iter().filter_map(|event| match *event {
+            Visibility::Enter { observer, target } if target == id => Some(observer),
+            _ => None,
+        }).collect();
+        self.send_visibility(events);
+        for session in self.audience(&[id]) {
+            if self.player(session).is_none_or(|observer| !spawned.contains(&observer)) {
+                self.send(session, Packet::Moved { id, x, y });
+            }
+        }
+    }
+
+    fn login(&mut self, session: SessionId, name: &str) {
+        if self.shutting_down {
+            return;
+        }
+        let id = match self.characters.get(name) {
+            Some(character) => self.world.restore_player(name, character),
+            None => self.world.spawn_player(name),
+        };
+        self.sessions.get_mut(&session).unwrap().player = Some(id);
+        self.players.insert(id, session);
+        let entity = self.world.entity(id).unwrap();
+
+        let packet = Packet::LoginOk { id, map: entity.map, x: entity.x, y: entity.y };
+        self.send(session, packet);
+        self.interest.add_observer(id);
+        let events = self.interest.update(&self.world, id);
+        self.send_visibility(events);
+        if let Some(guild) = self.guilds.guild_of(name) {
+            let packet = guild_packet(guild, self.guilds.guild(guild).unwrap());
+            self.send(session, packet);
+        }
+        let packet = self.ambiance.packet();
+        self.send(session, packet);
+        if self.world.entity(id).is_some_and(|entity| !entity.inventory.items().is_empty()) {
+            self.send_inventory(id);
+        }
+    }
+
+    fn name(&self, id: EntityId) -> String {
+        self.world.entity(id).map(|entity| entity.name.clone()).unwrap_or_default()
+    }
+
+    fn invite_to_party(&mut self, id: EntityId, target: EntityId) {
+        if self.players.contains_key(&target) && self.parties.invite(id, target) {
+            let name = self.name(id);
+            self.send_to_player(target, Packet::PartyInvited { leader: id, name });
+        }
+    }
+
+    fn leave_party(&mut self, id: EntityId) {
+        if let Some((party, left)) = self.parties.leave(id) {
+            for member in left {
+                self.send_to_player(member, Packet::Party { members: Vec::new() });
+            }
+            self.send_party(self.parties.members(party).to_vec());
+        }
+    }
+
+    fn invite_to_guild(&mut self, id: EntityId, target: EntityId) {
+        if !self.players.contains_key(&target) {
+            return;
+        }
+        if let Some(guild) = self.guilds.invite(&self.name(id), &self.name(target)) {
+            let name = self.guilds.guild(guild).unwrap().name.clone();
+            self.send_to_player(target, Packet::GuildInvited { guild, name });
+        }
+    }
+
+    fn join_guild(&mut self, id: EntityId, guild: u32) {
+        if self.guilds.accept(&self.name(id), guild) {
+            self.send_guild(guild);
+        }
+    }
+
+    fn request_trade(&mut self, id: EntityId, target: EntityId) {
+        if self.players.contains_key(&target) && self.trades.request(&self.world, id, target) {
+            let name = self.name(id);
+            self.send_to_player(target, Packet::TradeRequested { from: id, name });
+        }
+    }
+
+    fn cancel_trade(&mut self, id: EntityId) {
+        if let Some(traders) = self.trades.cancel(&mut self.world, id) {
+            self.close_trade(traders, false);
+        }
+    }
+
+    /// Sends the trade to both traders, each from their side
+    fn send_trade(&mut self, trade: TradeId) {
+        let trade = match self.trades.trade(trade) {
+            Some(trade) => trade.clone(),
+            None => return,
+        };
+        let items = |side: usize| -> Vec<(u32, u32)> {
+            trade.offers[side].iter().map(|(&item, &count)| (item, count)).collect()
+        };
+        for side in 0..2 {
+            let packet = Packet::Trade {
+                with: trade.traders[1 - side],
+                mine: items(side),
+                theirs: items(1 - side),
+                confirmed: trade.confirmed[side],
+                they_confirmed: trade.confirmed[1 - side],
+            };
+            self.send_to_player(trade.traders[side], packet);
+        }
+    }
+
+    /// Tells the traders the trade is over, and what they have now
+    fn close_trade(&mut self, traders: [EntityId; 2], completed: bool) {
+        for &trader in traders.iter() {
+            self.send_to_player(trader, Packet::TradeClosed { completed });
+            self.send_inventory(trader);
+        }
+    }
+
+    fn send_inventory(&mut self, id: EntityId) {
+        if let Some(entity) = self.world.entity(id) {
+            let packet = Packet::Inventory { items: entity.inventory.items() };
+            self.send_to_player(id, packet);
+        }
+    }
+
+    /// Sends the party to its members
+    fn send_party(&mut self, members: Vec<EntityId>) {
+        for &member in members.iter() {
+            self.send_to_player(member, Packet::Party { members: members.clone() });
+        }
+    }
+
+    /// Sends the guild to its members that are online; a closed guild has
+    /// none left.
+    fn send_guild(&mut self, guild: u32) {
+        let packet = match self.guilds.guild(guild) {
+            Some(entry) => guild_packet(guild, entry),
+            None => return,
+        };
+        let online: Vec<EntityId> = self.players.keys()
+            .filter(|&&id| self.world.entity(id).is_some_and(|e| self.guilds.guild_of(&e.name) == Some(guild)))
+            .cloned()
+            .collect();
+        for id in online {
+            self.send_to_player(id, packet.clone());
+        }
+    }
+
+    /// Spawns and despawns the targets on the observers' clients
+    fn send_visibility(&mut self, events: Vec<Visibility>) {
+        for event in events {
+            let (observer, packet) = match event {
+                Visibility::Enter { observer, target } => match self.world.entity(target) {
+                    Some(entity) => (observer, spawn_packet(entity)),
+                    None => continue,
+                },
+                Visibility::Leave { observer, target } => (observer, Packet::Despawn { id: target }),
+            };
+            if let Some(&session) = self.players.get(&observer) {
+                self.send(session, packet);
+            }
+        }
+    }
+
+    fn send_ambiance(&mut self) {
+        let packet = self.ambiance.packet();
+        let sessions: Vec<SessionId> = self.players.values().cloned().collect();
+        for session in sessions {
+            self.send(session, packet.clone());
+        }
+    }
+
+    fn send(&mut self, id: SessionId, packet: Packet) {
+        let session = match self.sessions.get_mut(&id) {
+            Some(session) => session,
+            None => return,
+        };
+        if session.outgoing.len() < self.limits.config.max_outgoing {
+            session.outgoing.push(packet);
+            return;
+        }
+        match self.limits.config.overflow {
+            Overflow::Disconnect => {
+                if self.kicked.insert(id) {
+                    self.limits.overflowed();
+                }
+            }
+            Overflow::DropNewest => self.limits.overflowed(),
+            Overflow::DropOldest => {
+                session.outgoing.remove(0);
+                session.outgoing.push(packet);
+                self.limits.overflowed();
+            }
+        }
+    }
+
+    fn send_to_player(&mut self, id: EntityId, packet: Packet) {
+        if let Some(&session) = self.players.get(&id) {
+            self.send(session, packet);
+        }
+    }
+
+    /// Sends to the players among `ids` and everyone who sees one of them
+    fn send_to(&mut self, ids: &[EntityId], packet: Packet) {
+        for session in self.audience(ids) {
+            self.send(session, packet.clone());
+        }
+    }
+
+    fn audience(&self, ids: &[EntityId]) -> BTreeSet<SessionId> {
+        ids.iter()
+            .flat_map(|&id| self.interest.watchers(id).into_iter().chain(Some(id)))
+            .filter_map(|id| self.players.get(&id).cloned())
+            .collect()
+    }
+}
+
+fn spawn_packet(entity: &Entity) -> Packet {
+    match entity.kind {
+        EntityKind::Item { item, count } => Packet::GroundItem { id: entity.id, item, count, x: entity.x, y: entity.y },
+        _ => Packet::Spawn { id: entity.id, name: entity.name.clone(), x: entity.x, y: entity.y, hp: entity.hp },
+    }
+}
+
+fn guild_packet(id: u32, guild: &Guild) -> Packet {
+    Packet::Guild {
+        guild: id,
+        name: guild.name.clone(),
+        leader: guild.leader.clone(),
+        members: guild.members.iter().cloned().collect(),
+    }
+}
+
+fn no_guild() -> Packet {
+    Packet::Guild { guild: 0, name: String::new(), leader: String::new(), members: Vec::new() }
+}
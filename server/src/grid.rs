@@ -0,0 +1,90 @@
+//! Buckets the entities by map and square cells of tiles, so the entities
+//! near a position are found without looking at all of them.
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+use crate::world::EntityId;
+
+/// Width and height of a cell, in tiles
+pub const CELL_SIZE: u16 = 16;
+
+type Cell = (u32, u16, u16);
+
+#[derive(Default)]
+pub struct SpatialGrid {
+    cells: HashMap<Cell, BTreeSet<EntityId>>,
+}
+
+impl SpatialGrid {
+    pub fn new() -> SpatialGrid {
+        SpatialGrid::default()
+    }
+
+    pub fn insert(&mut self, id: EntityId, map: u32, x: u16, y: u16) {
+        self.cells.entry(cell(map, x, y)).or_default().insert(id);
+    }
+
+    pub fn remove(&mut self, id: EntityId, map: u32, x: u16, y: u16) {
+        let key = cell(map, x, y);
+        if let Some(ids) = self.cells.get_mut(&key) {
+            ids.remove(&id);
+            if ids.is_empty() {
+                self.cells.remove(&key);
+            }
+        }
+    }
+
+    /// Moves an entity within a map
+    pub fn relocate(&mut self, id: EntityId, map: u32, from: (u16, u16), to: (u16, u16)) {
+        if cell(map, from.0, from.1) != cell(map, to.0, to.1) {
+            self.remove(id, map, from.0, from.1);
+            self.insert(id, map, to.0, to.1);
+        }
+    }
+
+    /// The entities in the cells touching the square of `radius` around the
+    /// position, sorted; some of them may be further away than `radius`.
+    pub fn candidates(&self, map: u32, x: u16, y: u16, radius: u16) -> Vec<EntityId> {
+        let (left, top) = (x.saturating_sub(radius) / CELL_SIZE, y.saturating_sub(radius) / CELL_SIZE);
+        let (right, bottom) = (x.saturating_add(radius) / CELL_SIZE, y.saturating_add(radius) / CELL_SIZE);
+        let mut ids = Vec::new();
+        for cell_y in top..=bottom {
+            for cell_x in left..=right {
+                if let Some(cell) = self.cells.get(&(map, cell_x, cell_y)) {
+                    ids.extend(cell.iter().cloned());
+                }
+            }
+        }
+        ids.sort_unstable();
+        ids
+    }
+}
+
+fn cell(map: u32, x: u16, y: u16) -> Cell {
+    (map, x / CELL_SIZE, y / CELL_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates() {
+        let mut grid = SpatialGrid::new();
+        grid.insert(1, 3, 5, 5);
+        grid.insert(2, 3, 20, 5);
+        grid.insert(3, 3, 100, 100);
+        grid.insert(4, 4, 5, 5);
+
+        assert_eq!(grid.candidates(3, 5, 5, 2), vec![1]);
+        assert_eq!(grid.candidates(3, 14, 5, 2), vec![1, 2]);
+        assert_eq!(grid.candidates(3, 0, 0, 0xFFFF), vec![1, 2, 3]);
+
+        grid.relocate(2, 3, (20, 5), (99, 99));
+        assert_eq!(grid.candidates(3, 100, 100, 1), vec![2, 3]);
+        grid.remove(3, 3, 100, 100);
+        assert_eq!(grid.candidates(3, 100, 100, 1), vec![2]);
+        assert_eq!(grid.cells.len(), 3);
+    }
+}
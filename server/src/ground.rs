@@ -0,0 +1,133 @@
+//! Who may pick up the items on the ground, and for how long they lie there.
+//!
+//! What a monster drops belongs to the player that killed it, and their
+//! party, for `DropConfig::ownership` ticks; after that anyone may take it.
+//! Items nobody took are gone after `DropConfig::lifetime` ticks.
+//!
+//! The items themselves are entities of the world (see `World::drop_item`);
+//! this only keeps the claims on them.
+
+use std::collections::BTreeMap;
+
+use core_rules::loot;
+use core_rules::random::Rng;
+
+use crate::item::ItemId;
+use crate::world::{EntityId, World};
+
+#[derive(Debug, Copy, Clone)]
+pub struct DropConfig {
+    /// Ticks only the owners may pick a drop up
+    pub ownership: u64,
+    /// Ticks until a drop disappears
+    pub lifetime: u64,
+}
+
+impl Default for DropConfig {
+    /// Half a minute and three minutes at the default tick rate
+    fn default() -> DropConfig {
+        DropConfig { ownership: 300, lifetime: 1800 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Claim {
+    /// Nobody owns what was dropped without owners
+    owners: Vec<EntityId>,
+    public_at: u64,
+    expires_at: u64,
+}
+
+pub struct GroundItems {
+    pub config: DropConfig,
+    claims: BTreeMap<EntityId, Claim>,
+    /// Rolls of the drops
+    rng: Rng,
+}
+
+impl GroundItems {
+    pub fn new(config: DropConfig, seed: u64) -> GroundItems {
+        GroundItems { config, claims: BTreeMap::new(), rng: Rng::new(seed) }
+    }
+
+    /// What a monster of `kind` drops by the drop table; nothing without
+    /// rule tables
+    pub fn roll(&mut self, world: &World, kind: u32) -> Vec<(ItemId, u32)> {
+        match world.tables() {
+            Some(tables) => loot::roll(&tables.drops, kind, &mut self.rng),
+            None => Vec::new(),
+        }
+    }
+
+    /// The items on the ground `id` were dropped at `tick` for `owners`.
+    pub fn add(&mut self, id: EntityId, owners: Vec<EntityId>, tick: u64) {
+        let claim = Claim {
+            owners,
+            public_at: tick + self.config.ownership,
+            expires_at: tick + self.config.lifetime,
+        };
+        self.claims.insert(id, claim);
+    }
+
+    /// Whether `player` may pick up `id` at `tick`; drops are there until
+    /// taken out by `expired`.
+    pub fn may_pick_up(&self, id: EntityId, player: EntityId, tick: u64) -> bool {
+        self.claims.get(&id).is_some_and(|claim| {
+            tick >= claim.public_at || claim.owners.is_empty() || claim.owners.contains(&player)
+        })
+    }
+
+    /// Forgets the claim on `id`, once it's picked up
+    pub fn remove(&mut self, id: EntityId) {
+        self.claims.remove(&id);
+    }
+
+    /// Takes out the items whose time is up at `tick`; remove them from the
+    /// world.
+    pub fn expired(&mut self, tick: u64) -> Vec<EntityId> {
+        let expired: Vec<EntityId> = self.claims.iter()
+            .filter(|&(_, claim)| claim.expires_at <= tick)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in expired.iter() {
+            self.claims.remove(id);
+        }
+        expired
+    }
+
+    /// How many drops are lying around
+    pub fn len(&self) -> usize {
+        self.claims.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.claims.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ownership_and_expiry() {
+        let mut ground = GroundItems::new(DropConfig { ownership: 10, lifetime: 30 }, 1);
+        ground.add(5, vec![1, 2], 100);
+        ground.add(6, Vec::new(), 100);
+        assert!(ground.may_pick_up(5, 1, 100) && ground.may_pick_up(5, 2, 109));
+        assert!(!ground.may_pick_up(5, 3, 109));
+        assert!(ground.may_pick_up(5, 3, 110));
+        assert!(ground.may_pick_up(6, 3, 100));
+        assert!(!ground.may_pick_up(7, 1, 100));
+
+        assert!(ground.expired(129).is_empty());
+        assert_eq!(ground.expired(130), vec![5, 6]);
+        assert!(ground.is_empty());
+    }
+
+    #[test]
+    fn test_roll_without_tables() {
+        let world = World::new(crate::world::WorldConfig { start_map: 1, start_x: 0, start_y: 0 });
+        assert!(GroundItems::new(DropConfig::default(), 1).roll(&world, 1).is_empty());
+    }
+}
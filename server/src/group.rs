@@ -0,0 +1,327 @@
+//! Parties and guilds.
+//!
+//! A party only lasts while its members are online, so it's kept by entity.
+//! A guild outlasts the sessions and is kept by player name; it's saved as a
+//! text file with a line per guild:
+//!
+//! ```text
+//! <id>\t<name>\t<leader>\t<member>,<member>,..
+//! ```
+//!
+//! The leader is one of the members. Invites are remembered until they're
+//! accepted or the inviter invites someone else.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use crate::error::Error;
+use crate::world::EntityId;
+
+/// Most members of a party
+pub const MAX_PARTY_SIZE: usize = 8;
+/// Longest guild name, in characters
+pub const MAX_GUILD_NAME: usize = 16;
+
+pub type PartyId = u32;
+pub type GuildId = u32;
+
+#[derive(Default)]
+pub struct Parties {
+    /// Members by party, leader first
+    parties: BTreeMap<PartyId, Vec<EntityId>>,
+    party_of: BTreeMap<EntityId, PartyId>,
+    /// The open invite of each inviter
+    invites: BTreeMap<EntityId, EntityId>,
+    next_id: PartyId,
+}
+
+impl Parties {
+    pub fn new() -> Parties {
+        Parties::default()
+    }
+
+    pub fn party_of(&self, id: EntityId) -> Option<PartyId> {
+        self.party_of.get(&id).cloned()
+    }
+
+    /// Members of a party, leader first
+    pub fn members(&self, party: PartyId) -> &[EntityId] {
+        self.parties.get(&party).map(|m| m.as_slice()).unwrap_or(&[])
+    }
+
+    /// Whether `leader` may invite `target`: the target is in no party and
+    /// the leader leads a party with room, or is in none.
+    pub fn invite(&mut self, leader: EntityId, target: EntityId) -> bool {
+        let allowed = leader != target && self.party_of(target).is_none() && match self.party_of(leader) {
+            Some(party) => {
+                let members = self.members(party);
+                members[0] == leader && members.len() < MAX_PARTY_SIZE
+            }
+            None => true,
+        };
+        if allowed {
+            self.invites.insert(leader, target);
+        }
+        allowed
+    }
+
+    /// Joins the party of `leader`, which is founded if need be.
+    pub fn accept(&mut self, target: EntityId, leader: EntityId) -> Option<PartyId> {
+        if self.invites.get(&leader) != Some(&target) || self.party_of(target).is_some() {
+            return None;
+        }
+        self.invites.remove(&leader);
+        let party = match self.party_of(leader) {
+            Some(party) if self.members(party).len() < MAX_PARTY_SIZE => party,
+            Some(_) => return None,
+            None => {
+                let party = self.next_id;
+                self.next_id += 1;
+                self.parties.insert(party, vec![leader]);
+                self.party_of.insert(leader, party);
+                party
+            }
+        };
+        self.parties.get_mut(&party).unwrap().push(target);
+        self.party_of.insert(target, party);
+        Some(party)
+    }
+
+    /// Leaves the party; the next member leads if it was the leader, and a
+    /// party of one is no party. Returns the party and who left it, which
+    /// is everyone when it broke up.
+    pub fn leave(&mut self, id: EntityId) -> Option<(PartyId, Vec<EntityId>)> {
+        self.invites.remove(&id);
+        let party = self.party_of.remove(&id)?;
+        let members = self.parties.get_mut(&party).unwrap();
+        members.retain(|&member| member != id);
+        if members.len() > 1 {
+            return Some((party, vec![id]));
+        }
+        let mut left = vec![id];
+        for member in self.parties.remove(&party).unwrap() {
+            self.party_of.remove(&member);
+            left.push(member);
+        }
+        Some((party, left))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Guild {
+    pub name: String,
+    pub leader: String,
+    pub members: BTreeSet<String>,
+}
+
+#[derive(Default)]
+pub struct Guilds {
+    guilds: BTreeMap<GuildId, Guild>,
+    guild_of: BTreeMap<String, GuildId>,
+    /// The open invite of each inviter
+    invites: BTreeMap<String, (GuildId, String)>,
+    next_id: GuildId,
+    /// Whether the guilds changed since the last `take_changed`
+    changed: bool,
+}
+
+impl Guilds {
+    pub fn new() -> Guilds {
+        Guilds { next_id: 1, ..Guilds::default() }
+    }
+
+    pub fn parse(text: &str) -> Result<Guilds, Error> {
+        let mut guilds = Guilds::new();
+        for (number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let guild = match fields.as_slice() {
+                &[id, name, leader, members] => id.parse::<GuildId>().ok().filter(|&id| id > 0).map(|id| {
+                    let members: BTreeSet<String> = members.split(',').map(String::from).collect();
+                    (id, Guild { name: name.into(), leader: leader.into(), members })
+                }),
+                _ => None,
+            };
+            match guild {
+                Some((id, guild)) if guild.members.contains(&guild.leader) && !guilds.guilds.contains_key(&id) => {
+                    for member in guild.members.iter() {
+                        guilds.guild_of.insert(member.clone(), id);
+                    }
+                    guilds.next_id = guilds.next_id.max(id + 1);
+                    guilds.guilds.insert(id, guild);
+                }
+                _ => return Err(Error::InvalidGuildLine(number + 1)),
+            }
+        }
+        Ok(guilds)
+    }
+
+    pub fn to_text(&self) -> String {
+        self.guilds.iter()
+            .map(|(id, guild)| {
+                let members: Vec<&str> = guild.members.iter().map(|m| m.as_str()).collect();
+                format!("{}\t{}\t{}\t{}\n", id, guild.name, guild.leader, members.join(","))
+            })
+            .collect()
+    }
+
+    pub fn guild(&self, id: GuildId) -> Option<&Guild> {
+        self.guilds.get(&id)
+    }
+
+    pub fn guild_of(&self, name: &str) -> Option<GuildId> {
+        self.guild_of.get(name).cloned()
+    }
+
+    /// Whether anything changed since the last call
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::replace(&mut self.changed, false)
+    }
+
+    /// Founds a guild led by `founder`. Names are unique, case ignored, and
+    /// can't hold the separators of the guild file.
+    pub fn create(&mut self, founder: &str, name: &str) -> Option<GuildId> {
+        let valid = !name.is_empty() && name.chars().count() <= MAX_GUILD_NAME
+            && !name.contains(['\t', '\n', '\r', ','])
+            && !self.guilds.values().any(|guild| guild.name.to_lowercase() == name.to_lowercase());
+        if !valid || self.guild_of(founder).is_some() {
+            return None;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        let members = Some(founder.to_string()).into_iter().collect();
+        self.guilds.insert(id, Guild { name: name.into(), leader: founder.into(), members });
+        self.guild_of.insert(founder.into(), id);
+        self.changed = true;
+        Some(id)
+    }
+
+    /// Any member may invite players without a guild.
+    pub fn invite(&mut self, from: &str, target: &str) -> Option<GuildId> {
+        let guild = self.guild_of(from)?;
+        if self.guild_of(target).is_some() {
+            return None;
+        }
+        self.invites.insert(from.into(), (guild, target.into()));
+        Some(guild)
+    }
+
+    pub fn accept(&mut self, target: &str, guild: GuildId) -> bool {
+        let inviter = self.invites.iter()
+            .find(|&(_, &(g, ref t))| g == guild && t == target)
+            .map(|(inviter, _)| inviter.clone());
+        let inviter = match inviter {
+            Some(inviter) if self.guild_of(target).is_none() && self.guilds.contains_key(&guild) => inviter,
+            _ => return false,
+        };
+        self.invites.remove(&inviter);
+        self.guilds.get_mut(&guild).unwrap().members.insert(target.into());
+        self.guild_of.insert(target.into(), guild);
+        self.changed = true;
+        true
+    }
+
+    /// Leaves the guild; a leader hands it to the first of the others by
+    /// name, and the last one out closes it. Returns the guild left.
+    pub fn leave(&mut self, name: &str) -> Option<GuildId> {
+        let id = self.guild_of.remove(name)?;
+        self.invites.remove(name);
+        self.changed = true;
+        let guild = self.guilds.get_mut(&id).unwrap();
+        guild.members.remove(name);
+        match guild.members.iter().next().cloned() {
+            Some(first) => {
+                if guild.leader == name {
+                    guild.leader = first;
+                }
+            }
+            None => {
+                self.guilds.remove(&id);
+                self.invites.retain(|_, &mut (guild, _)| guild != id);
+            }
+        }
+        Some(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_party() {
+        let mut parties = Parties::new();
+        assert!(!parties.invite(1, 1));
+        assert!(parties.invite(1, 2));
+        assert_eq!(parties.accept(3, 1), None);
+        let party = parties.accept(2, 1).unwrap();
+        assert_eq!(parties.members(party), &[1, 2]);
+
+        // only the leader invites
+        assert!(!parties.invite(2, 3));
+        assert!(parties.invite(1, 3));
+        assert_eq!(parties.accept(3, 1), Some(party));
+        assert!(!parties.invite(1, 2));
+
+        // the leader leaving hands over the party
+        assert_eq!(parties.leave(1), Some((party, vec![1])));
+        assert_eq!(parties.members(party), &[2, 3]);
+        assert_eq!(parties.leave(3), Some((party, vec![3, 2])));
+        assert_eq!(parties.party_of(2), None);
+        assert!(parties.members(party).is_empty());
+        assert_eq!(parties.leave(2), None);
+    }
+
+    #[test]
+    fn test_party_size() {
+        let mut parties = Parties::new();
+        for member in 2..=MAX_PARTY_SIZE as EntityId {
+            assert!(parties.invite(1, member));
+            assert!(parties.accept(member, 1).is_some());
+        }
+        assert!(!parties.invite(1, 100));
+    }
+
+    #[test]
+    fn test_guild() {
+        let mut guilds = Guilds::new();
+        let id = guilds.create("Philar", "Moonlight").unwrap();
+        assert!(guilds.take_changed());
+        assert_eq!(guilds.create("Azlar", "moonLIGHT"), None);
+        assert_eq!(guilds.create("Azlar", "Bad,Name"), None);
+        assert_eq!(guilds.create("Philar", "Other"), None);
+
+        assert!(!guilds.accept("Azlar", id));
+        assert_eq!(guilds.invite("Philar", "Azlar"), Some(id));
+        assert!(guilds.accept("Azlar", id));
+        assert_eq!(guilds.guild_of("Azlar"), Some(id));
+
+        assert_eq!(guilds.leave("Philar"), Some(id));
+        assert_eq!(guilds.guild(id).unwrap().leader, "Azlar");
+        assert_eq!(guilds.leave("Azlar"), Some(id));
+        assert!(guilds.guild(id).is_none());
+    }
+
+    #[test]
+    fn test_guild_file() {
+        let mut guilds = Guilds::new();
+        let id = guilds.create("Philar", "Moonlight").unwrap();
+        guilds.invite("Philar", "Azlar");
+        guilds.accept("Azlar", id);
+        guilds.create("Kitara", "달빛").unwrap();
+
+        let text = guilds.to_text();
+        assert_eq!(text, "1\tMoonlight\tPhilar\tAzlar,Philar\n2\t달빛\tKitara\tKitara\n");
+        let loaded = Guilds::parse(&text).unwrap();
+        assert_eq!(loaded.guild(1), guilds.guild(1));
+        assert_eq!(loaded.guild_of("Kitara"), Some(2));
+        assert_eq!(loaded.to_text(), text);
+
+        match Guilds::parse("1\tMoonlight\tNobody\tPhilar\n") {
+            Err(Error::InvalidGuildLine(1)) => (),
+            other => panic!("unexpected result: {:?}", other.map(|g| g.to_text())),
+        }
+    }
+}
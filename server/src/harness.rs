@@ -0,0 +1,115 @@
+//! Runs the game server in-process with scripted clients, for deterministic
+//! tests of whole scenarios (log in, walk, attack, chat) that check the world
+//! state and the packets every client saw.
+//!
+//! Every packet passes through its wire encoding on the way, so the tests
+//! cover `core_net` as well. Nothing happens between `step`s: a step is one
+//! server tick, after which the clients receive what the tick sent them.
+
+use core_net::packet::{decode, Packet};
+
+use crate::game::{GameServer, SessionId};
+use crate::world::{EntityId, World};
+
+pub type ClientId = usize;
+
+pub struct ScriptedClient {
+    pub name: String,
+    pub session: SessionId,
+    /// The client's player, once logged in
+    pub id: Option<EntityId>,
+    /// Everything the client received, in order
+    pub received: Vec<Packet>,
+    /// How far `take_new` has read
+    seen: usize,
+    /// Until disconnected by the test or the server
+    pub connected: bool,
+}
+
+impl ScriptedClient {
+    /// The packets received since the last call
+    pub fn take_new(&mut self) -> Vec<Packet> {
+        let new = self.received[self.seen..].to_vec();
+        self.seen = self.received.len();
+        new
+    }
+}
+
+pub struct Harness {
+    pub server: GameServer,
+    clients: Vec<ScriptedClient>,
+}
+
+impl Harness {
+    pub fn new(world: World) -> Harness {
+        Harness { server: GameServer::new(world), clients: Vec::new() }
+    }
+
+    /// Connects a client; it logs in as `name` on the next step.
+    pub fn connect(&mut self, name: &str) -> ClientId {
+        let session = self.server.connect();
+        self.clients.push(ScriptedClient {
+            name: name.into(),
+            session,
+            id: None,
+            received: Vec::new(),
+            seen: 0,
+            connected: true,
+        });
+        let client = self.clients.len() - 1;
+        self.send(client, Packet::Login { name: name.into() });
+        client
+    }
+
+    pub fn disconnect(&mut self, client: ClientId) {
+        self.clients[client].connected = false;
+        self.server.disconnect(self.clients[client].session);
+    }
+
+    pub fn send(&mut self, client: ClientId, packet: Packet) {
+        let packet = over_the_wire(&packet);
+        self.server.receive(self.clients[client].session, packet);
+    }
+
+    pub fn client(&self, client: ClientId) -> &ScriptedClient {
+        &self.clients[client]
+    }
+
+    pub fn client_mut(&mut self, client: ClientId) -> &mut ScriptedClient {
+        &mut self.clients[client]
+    }
+
+    /// The client's player id; panics if it isn't logged in
+    pub fn id(&self, client: ClientId) -> EntityId {
+        self.clients[client].id.expect("client isn't logged in")
+    }
+
+    pub fn step(&mut self) {
+        self.server.tick();
+        for client in self.clients.iter_mut().filter(|c| c.connected) {
+            for packet in self.server.take_outgoing(client.session) {
+                let packet = over_the_wire(&packet);
+                if let Packet::LoginOk { id, .. } = packet {
+                    client.id = Some(id);
+                }
+                client.received.push(packet);
+            }
+            client.connected = self.server.is_connected(client.session);
+        }
+    }
+
+    pub fn run(&mut self, steps: usize) {
+        for _ in 0..steps {
+            self.step();
+        }
+    }
+}
+
+/// The packet as the other side decodes it
+fn over_the_wire(packet: &Packet) -> Packet {
+    let frame = packet.encode();
+    match decode(&frame) {
+        Ok(Some((decoded, length))) if length == frame.len() => decoded,
+        result => panic!("{:?} didn't survive the wire: {:?}", packet, result),
+    }
+}
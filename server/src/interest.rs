@@ -0,0 +1,227 @@
+//! Which entities each player can see.
+//!
+//! An entity comes into view once it's within `view_radius` tiles of the
+//! observer, diagonals counting like straight lines, and only goes out of view
+//! again beyond `view_radius + hysteresis`. Walking back and forth along the
+//! edge of the view therefore doesn't spawn and despawn everyone over there on
+//! every step.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use crate::world::{EntityId, World};
+
+#[derive(Debug, Copy, Clone)]
+pub struct InterestConfig {
+    pub view_radius: u16,
+    pub hysteresis: u16,
+}
+
+impl Default for InterestConfig {
+    /// About a screen around the player
+    fn default() -> InterestConfig {
+        InterestConfig { view_radius: 20, hysteresis: 4 }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Visibility {
+    /// `target` came into view of `observer`
+    Enter { observer: EntityId, target: EntityId },
+    /// `target` went out of view, or out of the world
+    Leave { observer: EntityId, target: EntityId },
+}
+
+pub struct Interest {
+    pub config: InterestConfig,
+    /// What each observer sees
+    visible: BTreeMap<EntityId, BTreeSet<EntityId>>,
+    /// Who sees each entity
+    watchers: BTreeMap<EntityId, BTreeSet<EntityId>>,
+}
+
+impl Interest {
+    pub fn new(config: InterestConfig) -> Interest {
+        Interest { config, visible: BTreeMap::new(), watchers: BTreeMap::new() }
+    }
+
+    /// Starts tracking what `id` sees; call `update` to fill it in.
+    pub fn add_observer(&mut self, id: EntityId) {
+        self.visible.entry(id).or_default();
+    }
+
+    pub fn sees(&self, observer: EntityId, target: EntityId) -> bool {
+        self.visible.get(&observer).is_some_and(|v| v.contains(&target))
+    }
+
+    /// The observers that see `id`
+    pub fn watchers(&self, id: EntityId) -> Vec<EntityId> {
+        self.watchers.get(&id).map(|w| w.iter().cloned().collect()).unwrap_or_default()
+    }
+
+    /// Brings the views up to date after `id` appeared or moved.
+    pub fn update(&mut self, world: &World, id: EntityId) -> Vec<Visibility> {
+        let entity = match world.entity(id) {
+            Some(entity) => entity,
+            None => return self.remove(id),
+        };
+        let (radius, leave_radius) = (self.config.view_radius, self.config.view_radius + self.config.hysteresis);
+        let near = world.near(entity.map, entity.x, entity.y, leave_radius);
+        let in_view = |other: EntityId| world.entity(other)
+            .and_then(|other| entity.distance(other))
+            .is_some_and(|distance| distance <= radius);
+        let out_of_view = |other: EntityId| world.entity(other)
+            .and_then(|other| entity.distance(other))
+            .is_none_or(|distance| distance > leave_radius);
+
+        let mut events = Vec::new();
+        // what `id` sees
+        if let Some(visible) = self.visible.get(&id) {
+            for &other in visible.iter().filter(|&&other| out_of_view(other)) {
+                events.push(Visibility::Leave { observer: id, target: other });
+            }
+            for &other in near.iter().filter(|&&other| other != id && !visible.contains(&other) && in_view(other)) {
+                events.push(Visibility::Enter { observer: id, target: other });
+            }
+        }
+        // who sees `id`
+        for &observer in self.watchers.get(&id).into_iter().flatten().filter(|&&o| out_of_view(o)) {
+            events.push(Visibility::Leave { observer, target: id });
+        }
+        for &observer in near.iter() {
+            if observer != id && self.visible.contains_key(&observer) && !self.sees(observer, id) && in_view(observer) {
+                events.push(Visibility::Enter { observer, target: id });
+            }
+        }
+
+        for event in events.iter() {
+            self.apply(*event);
+        }
+        events
+    }
+
+    /// Forgets `id`, as observer and as target
+    pub fn remove(&mut self, id: EntityId) -> Vec<Visibility> {
+        let events: Vec<Visibility> = self.watchers(id).into_iter()
+            .map(|observer| Visibility::Leave { observer, target: id })
+            .collect();
+        for event in events.iter() {
+            self.apply(*event);
+        }
+        for target in self.visible.remove(&id).into_iter().flatten() {
+            if let Some(watchers) = self.watchers.get_mut(&target) {
+                watchers.remove(&id);
+            }
+        }
+        self.watchers.remove(&id);
+        events
+    }
+
+    fn apply(&mut self, event: Visibility) {
+        match event {
+            Visibility::Enter { observer, target } => {
+                self.visible.entry(observer).or_default().insert(target);
+                self.watchers.entry(target).or_default().insert(observer);
+            }
+            Visibility::Leave { observer, target } => {
+                if let Some(visible) = self.visible.get_mut(&observer) {
+                    visible.remove(&target);
+                }
+                if let Some(watchers) = self.watchers.get_mut(&target) {
+                    watchers.remove(&observer);
+                    if watchers.is_empty() {
+                        self.watchers.remove(&target);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::world::WorldConfig;
+
+    fn setup() -> (World, Interest) {
+        let world = World::new(WorldConfig { start_map: 1, start_x: 10, start_y: 10 });
+        (world, Interest::new(InterestConfig { view_radius: 3, hysteresis: 2 }))
+    }
+
+    /// Walks `id` in a straight line along x to `x`
+    fn walk_to(world: &mut World, interest: &mut Interest, id: EntityId, x: u16) -> Vec<Visibility> {
+        let mut events = Vec::new();
+        while world.entity(id).unwrap().x != x {
+            let entity = world.entity(id).unwrap();
+            let next = if entity.x < x { entity.x + 1 } else { entity.x - 1 };
+            let y = entity.y;
+            assert!(world.walk(id, next, y));
+            events.extend(interest.update(world, id));
+        }
+        events
+    }
+
+    #[test]
+    fn test_enter_and_leave() {
+        let (mut world, mut interest) = setup();
+        let a = world.spawn_player("a");
+        interest.add_observer(a);
+        assert!(interest.update(&world, a).is_empty());
+
+        let b = world.spawn_player("b");
+        interest.add_observer(b);
+        assert_eq!(interest.update(&world, b), vec![
+            Visibility::Enter { observer: b, target: a },
+            Visibility::Enter { observer: a, target: b },
+        ]);
+        assert_eq!(interest.watchers(a), vec![b]);
+
+        // out to the edge of the hysteresis band nothing changes
+        assert!(walk_to(&mut world, &mut interest, b, 15).is_empty());
+        assert_eq!(walk_to(&mut world, &mut interest, b, 16), vec![
+            Visibility::Leave { observer: b, target: a },
+            Visibility::Leave { observer: a, target: b },
+        ]);
+        assert!(!interest.sees(a, b) && !interest.sees(b, a));
+
+        // and coming back they only meet at the view radius
+        assert!(walk_to(&mut world, &mut interest, b, 14).is_empty());
+        assert_eq!(walk_to(&mut world, &mut interest, b, 13).len(), 2);
+        assert!(interest.sees(a, b) && interest.sees(b, a));
+    }
+
+    #[test]
+    fn test_no_thrashing_at_the_edge() {
+        let (mut world, mut interest) = setup();
+        let a = world.spawn_player("a");
+        let b = world.spawn_player("b");
+        for &id in [a, b].iter() {
+            interest.add_observer(id);
+            interest.update(&world, id);
+        }
+        walk_to(&mut world, &mut interest, b, 13);
+        for _ in 0..5 {
+            assert!(walk_to(&mut world, &mut interest, b, 14).is_empty());
+            assert!(walk_to(&mut world, &mut interest, b, 13).is_empty());
+        }
+        assert!(interest.sees(a, b));
+    }
+
+    #[test]
+    fn test_targets_and_removal() {
+        let (mut world, mut interest) = setup();
+        let a = world.spawn_player("a");
+        interest.add_observer(a);
+        // b is seen but doesn't look itself, like a monster would
+        let b = world.spawn_player("b");
+        assert_eq!(interest.update(&world, b), vec![Visibility::Enter { observer: a, target: b }]);
+        assert!(!interest.sees(b, a));
+
+        world.remove(b);
+        assert_eq!(interest.update(&world, b), vec![Visibility::Leave { observer: a, target: b }]);
+        assert!(interest.watchers(b).is_empty());
+        assert!(interest.remove(a).is_empty());
+        assert!(interest.visible.is_empty() && interest.watchers.is_empty());
+    }
+}
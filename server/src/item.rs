@@ -0,0 +1,69 @@
+//! What the players carry.
+//!
+//! Items are only counted by kind for now; there's nothing that tells two
+//! swords of the same kind apart.
+
+use std::collections::BTreeMap;
+
+pub type ItemId = u32;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Inventory {
+    /// Count by item, never zero
+    items: BTreeMap<ItemId, u32>,
+}
+
+impl Inventory {
+    pub fn new() -> Inventory {
+        Inventory::default()
+    }
+
+    pub fn count(&self, item: ItemId) -> u32 {
+        self.items.get(&item).cloned().unwrap_or(0)
+    }
+
+    /// Items and their counts, by item
+    pub fn items(&self) -> Vec<(ItemId, u32)> {
+        self.items.iter().map(|(&item, &count)| (item, count)).collect()
+    }
+
+    pub fn add(&mut self, item: ItemId, count: u32) {
+        if count > 0 {
+            let held = self.items.entry(item).or_insert(0);
+            *held = held.saturating_add(count);
+        }
+    }
+
+    /// Takes `count` of an item; returns false and takes nothing if there
+    /// aren't that many.
+    pub fn take(&mut self, item: ItemId, count: u32) -> bool {
+        let held = self.count(item);
+        if held < count {
+            return false;
+        }
+        if held == count {
+            self.items.remove(&item);
+        } else {
+            self.items.insert(item, held - count);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_and_take() {
+        let mut inventory = Inventory::new();
+        inventory.add(7, 3);
+        inventory.add(2, 0);
+        assert_eq!(inventory.items(), vec![(7, 3)]);
+        assert!(!inventory.take(7, 4));
+        assert!(inventory.take(7, 2));
+        assert_eq!(inventory.count(7), 1);
+        assert!(inventory.take(7, 1));
+        assert!(inventory.items().is_empty());
+    }
+}
@@ -0,0 +1,31 @@
+#![allow(dead_code, unused_variables)]
+
+extern crate byteorder;
+extern crate core_net;
+extern crate core_rules;
+
+pub mod admin;
+pub mod ai;
+pub mod ambiance;
+pub mod anomaly;
+pub mod audit;
+pub mod crypto;
+pub mod error;
+pub mod game;
+pub mod grid;
+pub mod ground;
+pub mod group;
+pub mod harness;
+pub mod interest;
+pub mod item;
+pub mod limit;
+pub mod map;
+pub mod metrics;
+pub mod net;
+pub mod packet;
+pub mod path;
+pub mod proxy;
+pub mod save;
+pub mod spawn;
+pub mod trade;
+pub mod world;
@@ -0,0 +1,230 @@
+//! Per-session limits, so a single connection can take neither all the
+//! memory nor all of a tick.
+//!
+//! Packets fall in categories, each with a token bucket per session: a
+//! session starts with `Rate::burst` tokens, gets one back every
+//! `Rate::refill` ticks up to the burst, and every packet takes one; the
+//! packets finding no token are dropped. The queues of a session are bounded
+//! too: packets arriving beyond `max_incoming` are dropped, and the ones sent
+//! beyond `max_outgoing` in a tick go as `overflow` says.
+
+use std::collections::BTreeMap;
+
+use core_net::packet::Packet;
+
+use crate::game::SessionId;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Category {
+    Movement,
+    Combat,
+    Chat,
+    /// Parties, guilds and trades
+    Social,
+    /// Logging in, pings and picking up
+    Other,
+}
+
+impl Category {
+    pub const ALL: [Category; 5] = [Category::Movement, Category::Combat, Category::Chat, Category::Social, Category::Other];
+
+    pub fn of(packet: &Packet) -> Category {
+        match *packet {
+            Packet::Walk { .. } => Category::Movement,
+            Packet::Attack { .. } | Packet::UseSkill { .. } => Category::Combat,
+            Packet::Say { .. } => Category::Chat,
+            Packet::PartyInvite { .. } | Packet::PartyAccept { .. } | Packet::PartyLeave
+            | Packet::GuildCreate { .. } | Packet::GuildInvite { .. } | Packet::GuildAccept { .. } | Packet::GuildLeave
+            | Packet::TradeRequest { .. } | Packet::TradeAccept { .. } | Packet::TradeOffer { .. }
+            | Packet::TradeConfirm | Packet::TradeCancel => Category::Social,
+            _ => Category::Other,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Movement => "movement",
+            Category::Combat => "combat",
+            Category::Chat => "chat",
+            Category::Social => "social",
+            Category::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Rate {
+    /// Packets in a row after a pause
+    pub burst: u32,
+    /// Ticks a token takes to come back; 0 for no limit
+    pub refill: u64,
+}
+
+/// What happens to the packets sent to a session beyond its queue
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Overflow {
+    /// The session is disconnected at the end of the tick
+    Disconnect,
+    DropNewest,
+    DropOldest,
+}
+
+impl Overflow {
+    pub fn name(self) -> &'static str {
+        match self {
+            Overflow::Disconnect => "disconnect",
+            Overflow::DropNewest => "drop-newest",
+            Overflow::DropOldest => "drop-oldest",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Overflow> {
+        [Overflow::Disconnect, Overflow::DropNewest, Overflow::DropOldest].iter().cloned()
+            .find(|overflow| overflow.name() == name)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct LimitConfig {
+    pub movement: Rate,
+    pub combat: Rate,
+    pub chat: Rate,
+    pub social: Rate,
+    pub other: Rate,
+    /// Packets waiting for the next tick
+    pub max_incoming: usize,
+    /// Packets sent in a tick
+    pub max_outgoing: usize,
+    pub overflow: Overflow,
+}
+
+impl Default for LimitConfig {
+    /// At the default tick rate: a step a tick, five hits and a line of chat
+    /// a second, with room for bursts; a client that can't take a thousand
+    /// packets a tick is let go
+    fn default() -> LimitConfig {
+        LimitConfig {
+            movement: Rate { burst: 10, refill: 1 },
+            combat: Rate { burst: 5, refill: 2 },
+            chat: Rate { burst: 5, refill: 10 },
+            social: Rate { burst: 10, refill: 5 },
+            other: Rate { burst: 20, refill: 1 },
+            max_incoming: 256,
+            max_outgoing: 1000,
+            overflow: Overflow::Disconnect,
+        }
+    }
+}
+
+impl LimitConfig {
+    pub fn rate(&self, category: Category) -> Rate {
+        match category {
+            Category::Movement => self.movement,
+            Category::Combat => self.combat,
+            Category::Chat => self.chat,
+            Category::Social => self.social,
+            Category::Other => self.other,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Bucket {
+    tokens: u32,
+    /// The tick the tokens were last counted at
+    updated: u64,
+}
+
+pub struct Limiter {
+    pub config: LimitConfig,
+    buckets: BTreeMap<(SessionId, Category), Bucket>,
+    /// Packets dropped so far, by category
+    dropped: BTreeMap<Category, u64>,
+    /// Packets dropped or sessions disconnected for full queues
+    overflows: u64,
+}
+
+impl Limiter {
+    pub fn new(config: LimitConfig) -> Limiter {
+        Limiter { config, buckets: BTreeMap::new(), dropped: BTreeMap::new(), overflows: 0 }
+    }
+
+    /// Takes a token for a packet of `session` at `tick`; returns whether
+    /// the packet goes through.
+    pub fn allow(&mut self, session: SessionId, category: Category, tick: u64) -> bool {
+        let rate = self.config.rate(category);
+        if rate.refill == 0 {
+            return true;
+        }
+        let bucket = self.buckets.entry((session, category)).or_insert(Bucket { tokens: rate.burst, updated: tick });
+        let refilled = tick.saturating_sub(bucket.updated) / rate.refill;
+        bucket.tokens = (bucket.tokens as u64 + refilled).min(rate.burst as u64) as u32;
+        bucket.updated = if bucket.tokens == rate.burst { tick } else { bucket.updated + refilled * rate.refill };
+        if bucket.tokens == 0 {
+            *self.dropped.entry(category).or_insert(0) += 1;
+            return false;
+        }
+        bucket.tokens -= 1;
+        true
+    }
+
+    /// Counts a packet dropped or a session disconnected for a full queue
+    pub fn overflowed(&mut self) {
+        self.overflows += 1;
+    }
+
+    pub fn remove(&mut self, session: SessionId) {
+        for &category in Category::ALL.iter() {
+            self.buckets.remove(&(session, category));
+        }
+    }
+
+    /// How many packets of each category were dropped
+    pub fn dropped(&self) -> Vec<(Category, u64)> {
+        Category::ALL.iter().map(|&category| (category, self.dropped.get(&category).cloned().unwrap_or(0))).collect()
+    }
+
+    pub fn overflows(&self) -> u64 {
+        self.overflows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_category() {
+        assert_eq!(Category::of(&Packet::Walk { x: 1, y: 2 }), Category::Movement);
+        assert_eq!(Category::of(&Packet::UseSkill { skill: 1, target: 2 }), Category::Combat);
+        assert_eq!(Category::of(&Packet::TradeCancel), Category::Social);
+        assert_eq!(Category::of(&Packet::Ping { seq: 1 }), Category::Other);
+        assert_eq!(Overflow::from_name("drop-oldest"), Some(Overflow::DropOldest));
+        assert_eq!(Overflow::from_name("drop"), None);
+    }
+
+    #[test]
+    fn test_bucket() {
+        let config = LimitConfig { chat: Rate { burst: 3, refill: 4 }, ..LimitConfig::default() };
+        let mut limiter = Limiter::new(config);
+        // the burst, then a token every 4 ticks
+        assert!((0..3).all(|_| limiter.allow(0, Category::Chat, 0)));
+        assert!(!limiter.allow(0, Category::Chat, 3));
+        assert!(limiter.allow(0, Category::Chat, 4));
+        assert!(!limiter.allow(0, Category::Chat, 7));
+        assert!(limiter.allow(0, Category::Chat, 8));
+        // back to the burst after a pause, but no further
+        assert!((0..3).all(|_| limiter.allow(0, Category::Chat, 100)));
+        assert!(!limiter.allow(0, Category::Chat, 100));
+        // each session and category on its own
+        assert!(limiter.allow(1, Category::Chat, 100));
+        assert!(limiter.allow(0, Category::Movement, 100));
+        assert_eq!(limiter.dropped()[2], (Category::Chat, 3));
+
+        limiter.remove(0);
+        assert!((0..3).all(|_| limiter.allow(0, Category::Chat, 101)));
+        let unlimited = LimitConfig { chat: Rate { burst: 0, refill: 0 }, ..config };
+        let mut limiter = Limiter::new(unlimited);
+        assert!((0..1000).all(|_| limiter.allow(0, Category::Chat, 0)));
+    }
+}
@@ -0,0 +1,144 @@
+//! Loader for the compact per-map binaries written by the `data_converter`;
+//! see `data_converter/src/server_map.rs` for the layout.
+
+use std::io::Cursor;
+use std::io::Read;
+
+use byteorder::ReadBytesExt;
+use byteorder::LittleEndian as LE;
+
+use crate::error::Error;
+
+const SERVER_MAP_IDENTIFIER: &str = "Novluno ServerMap";
+const SERVER_MAP_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub struct Warp {
+    pub x: u32,
+    pub y: u32,
+    pub warp: u32,
+}
+
+#[derive(Debug)]
+pub struct SpawnRegion {
+    pub id: u32,
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+#[derive(Debug)]
+pub struct ServerMap {
+    pub number: u32,
+    pub size_x: u32,
+    pub size_y: u32,
+    pub passable: Vec<u8>,
+    pub warps: Vec<Warp>,
+    pub spawns: Vec<SpawnRegion>,
+}
+
+impl ServerMap {
+    pub fn is_passable(&self, x: u32, y: u32) -> bool {
+        if x >= self.size_x || y >= self.size_y {
+            return false;
+        }
+        let idx = (y * self.size_x + x) as usize;
+        self.passable[idx / 8] & (1 << (idx % 8)) != 0
+    }
+}
+
+pub fn load_server_map(data: &[u8]) -> Result<ServerMap, Error> {
+    let mut cursor = Cursor::new(data);
+
+    let string_length = cursor.read_u8()? as usize;
+    let mut string = vec![0u8; string_length];
+    cursor.read_exact(&mut string)?;
+    if string != SERVER_MAP_IDENTIFIER.as_bytes() {
+        return Err(Error::MissingServerMapIdentifier);
+    }
+
+    let version = cursor.read_u32::<LE>()?;
+    if version != SERVER_MAP_VERSION {
+        return Err(Error::UnsupportedServerMapVersion(version));
+    }
+
+    let number = cursor.read_u32::<LE>()?;
+    let size_x = cursor.read_u32::<LE>()?;
+    let size_y = cursor.read_u32::<LE>()?;
+
+    let mut passable = vec![0u8; (size_x as usize * size_y as usize).div_ceil(8)];
+    cursor.read_exact(&mut passable)?;
+
+    let warp_count = cursor.read_u32::<LE>()?;
+    let mut warps = Vec::new();
+    for _ in 0..warp_count {
+        warps.push(Warp {
+            x: cursor.read_u32::<LE>()?,
+            y: cursor.read_u32::<LE>()?,
+            warp: cursor.read_u32::<LE>()?,
+        });
+    }
+
+    let spawn_count = cursor.read_u32::<LE>()?;
+    let mut spawns = Vec::new();
+    for _ in 0..spawn_count {
+        spawns.push(SpawnRegion {
+            id: cursor.read_u32::<LE>()?,
+            left: cursor.read_u32::<LE>()?,
+            top: cursor.read_u32::<LE>()?,
+            right: cursor.read_u32::<LE>()?,
+            bottom: cursor.read_u32::<LE>()?,
+        });
+    }
+
+    Ok(ServerMap { number, size_x, size_y, passable, warps, spawns })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_u32(data: &mut Vec<u8>, val: u32) {
+        data.extend_from_slice(&[val as u8, (val >> 8) as u8, (val >> 16) as u8, (val >> 24) as u8]);
+    }
+
+    #[test]
+    fn test_load_server_map() {
+        let mut data = Vec::new();
+        data.push(SERVER_MAP_IDENTIFIER.len() as u8);
+        data.extend_from_slice(SERVER_MAP_IDENTIFIER.as_bytes());
+        push_u32(&mut data, SERVER_MAP_VERSION);
+        push_u32(&mut data, 3); // number
+        push_u32(&mut data, 3); // size_x
+        push_u32(&mut data, 1); // size_y
+        data.push(0b101);
+        push_u32(&mut data, 1);
+        push_u32(&mut data, 2);
+        push_u32(&mut data, 0);
+        push_u32(&mut data, 16);
+        push_u32(&mut data, 0);
+
+        let map = load_server_map(&data).unwrap();
+        assert_eq!(map.number, 3);
+        assert!(map.is_passable(0, 0));
+        assert!(!map.is_passable(1, 0));
+        assert!(map.is_passable(2, 0));
+        assert!(!map.is_passable(3, 0));
+        assert_eq!(map.warps.len(), 1);
+        assert_eq!(map.warps[0].warp, 16);
+        assert!(map.spawns.is_empty());
+    }
+
+    #[test]
+    fn test_load_server_map_wrong_version() {
+        let mut data = Vec::new();
+        data.push(SERVER_MAP_IDENTIFIER.len() as u8);
+        data.extend_from_slice(SERVER_MAP_IDENTIFIER.as_bytes());
+        push_u32(&mut data, 99);
+        match load_server_map(&data) {
+            Err(Error::UnsupportedServerMapVersion(99)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
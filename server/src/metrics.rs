@@ -0,0 +1,146 @@
+//! Metrics to watch a long running server with, in the Prometheus text
+//! format.
+//!
+//! The driver counts what the game server doesn't see: how long the ticks
+//! take, and the packets in and out by opcode. `render` adds what the game
+//! server keeps: sessions, players, entities, packets dropped by the rate
+//! limits and the anomalies flagged. All the counts only go up; `rate()` in
+//! Prometheus makes packets per second of them.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+use core_net::packet::Packet;
+
+use crate::game::GameServer;
+
+/// Upper bounds of the tick duration histogram, in seconds
+pub const TICK_BUCKETS: [f64; 8] = [0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25];
+
+#[derive(Default)]
+pub struct Metrics {
+    /// Ticks by the first bucket they fit in; the last for none
+    ticks: [u64; TICK_BUCKETS.len() + 1],
+    tick_time: Duration,
+    received: BTreeMap<u8, u64>,
+    sent: BTreeMap<u8, u64>,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// Counts a tick that took `duration`
+    pub fn ticked(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let bucket = TICK_BUCKETS.iter().position(|&bound| seconds <= bound).unwrap_or(TICK_BUCKETS.len());
+        self.ticks[bucket] += 1;
+        self.tick_time += duration;
+    }
+
+    pub fn received(&mut self, packet: &Packet) {
+        *self.received.entry(packet.opcode()).or_insert(0) += 1;
+    }
+
+    pub fn sent(&mut self, packet: &Packet) {
+        *self.sent.entry(packet.opcode()).or_insert(0) += 1;
+    }
+
+    /// Everything there is to know, as a scrape answer
+    pub fn render(&self, game: &GameServer) -> String {
+        let mut text = String::new();
+        header(&mut text, "novluno_tick_seconds", "histogram", "How long the ticks took");
+        let mut count = 0;
+        for (bound, &ticks) in TICK_BUCKETS.iter().zip(self.ticks.iter()) {
+            count += ticks;
+            let _ = writeln!(text, "novluno_tick_seconds_bucket{{le=\"{}\"}} {}", bound, count);
+        }
+        count += self.ticks[TICK_BUCKETS.len()];
+        let _ = writeln!(text, "novluno_tick_seconds_bucket{{le=\"+Inf\"}} {}", count);
+        let _ = writeln!(text, "novluno_tick_seconds_sum {}", self.tick_time.as_secs_f64());
+        let _ = writeln!(text, "novluno_tick_seconds_count {}", count);
+
+        let values = [
+            ("novluno_ticks_total", "counter", "Ticks done", game.tick),
+            ("novluno_tick_overruns_total", "counter", "Ticks longer than the tick rate", game.tick_overruns as u64),
+            ("novluno_sessions", "gauge", "Connected sessions", game.session_count() as u64),
+            ("novluno_players", "gauge", "Players logged in", game.players().count() as u64),
+            ("novluno_entities", "gauge", "Entities in the world", game.world.entities().count() as u64),
+            ("novluno_queue_overflows_total", "counter", "Packets dropped or sessions disconnected for full queues",
+             game.limits.overflows()),
+        ];
+        for &(name, kind, help, value) in values.iter() {
+            header(&mut text, name, kind, help);
+            let _ = writeln!(text, "{} {}", name, value);
+        }
+
+        header(&mut text, "novluno_packets_received_total", "counter", "Packets read from the clients, by opcode");
+        for (&opcode, &count) in self.received.iter() {
+            let _ = writeln!(text, "novluno_packets_received_total{{opcode=\"0x{:02X}\"}} {}", opcode, count);
+        }
+        header(&mut text, "novluno_packets_sent_total", "counter", "Packets written to the clients, by opcode");
+        for (&opcode, &count) in self.sent.iter() {
+            let _ = writeln!(text, "novluno_packets_sent_total{{opcode=\"0x{:02X}\"}} {}", opcode, count);
+        }
+        header(&mut text, "novluno_packets_dropped_total", "counter", "Packets dropped by the rate limits, by category");
+        for (category, count) in game.limits.dropped() {
+            let _ = writeln!(text, "novluno_packets_dropped_total{{category=\"{}\"}} {}", category.name(), count);
+        }
+        header(&mut text, "novluno_anomalies_total", "counter", "Anomalies flagged, by kind");
+        for (anomaly, count) in game.anomalies.counts() {
+            let _ = writeln!(text, "novluno_anomalies_total{{kind=\"{}\"}} {}", anomaly.name(), count);
+        }
+        text
+    }
+}
+
+fn header(text: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} {}", name, kind);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::world::{World, WorldConfig};
+
+    #[test]
+    fn test_render() {
+        let mut game = GameServer::new(World::new(WorldConfig { start_map: 1, start_x: 5, start_y: 5 }));
+        let session = game.connect();
+        game.receive(session, Packet::Login { name: "Lavita".into() });
+        game.tick();
+
+        let mut metrics = Metrics::new();
+        metrics.ticked(Duration::from_millis(3));
+        metrics.ticked(Duration::from_millis(4));
+        metrics.ticked(Duration::from_secs(1));
+        metrics.received(&Packet::Login { name: "Lavita".into() });
+        metrics.sent(&Packet::Ping { seq: 1 });
+        metrics.sent(&Packet::Ping { seq: 2 });
+
+        let text = metrics.render(&game);
+        let lines: Vec<&str> = text.lines().collect();
+        for line in [
+            "novluno_tick_seconds_bucket{le=\"0.0025\"} 0",
+            "novluno_tick_seconds_bucket{le=\"0.005\"} 2",
+            "novluno_tick_seconds_bucket{le=\"0.25\"} 2",
+            "novluno_tick_seconds_bucket{le=\"+Inf\"} 3",
+            "novluno_tick_seconds_sum 1.007",
+            "novluno_tick_seconds_count 3",
+            "# TYPE novluno_sessions gauge",
+            "novluno_sessions 1",
+            "novluno_players 1",
+            "novluno_ticks_total 1",
+            "novluno_packets_received_total{opcode=\"0x01\"} 1",
+            "novluno_packets_sent_total{opcode=\"0x05\"} 2",
+            "novluno_packets_dropped_total{category=\"chat\"} 0",
+            "novluno_anomalies_total{kind=\"speed\"} 0",
+        ].iter() {
+            assert!(lines.contains(line), "{} missing from:\n{}", line, text);
+        }
+    }
+}
@@ -0,0 +1,494 @@
+//! TCP front end of the game server.
+//!
+//! One thread per connection reads and decodes the packets and hands them to
+//! the tick loop, which owns the game server: every tick it takes in what
+//! arrived, ticks the game and writes out what the game sent. A tick that
+//! takes longer than the tick rate counts as an overrun.
+//!
+//! The guilds are written to the guild file after the ticks that changed
+//! them, through a temporary file so a crash never leaves half of one. So
+//! are the characters to the character file, which the game server keeps
+//! as players leave and every `ServeConfig::autosave` ticks for the others.
+//!
+//! Once the game server is shut down (see `admin`), no more connections are
+//! taken, what was sent still goes out, within `SHUTDOWN_TIMEOUT` a client,
+//! and the connections are closed; then the guilds and characters are
+//! saved, a snapshot of the world is written to the snapshot file, and
+//! `serve` returns.
+//!
+//! With the console on, the lines typed on stdin run as admin commands (see
+//! `admin`) between two ticks.
+//!
+//! A connection's reader stops reading once `ServeConfig::max_queued` of its
+//! packets wait for the tick loop, which leaves the rest in the socket and
+//! so slows the client down. What the tick loop writes waits in a buffer for
+//! the client to take it, the tick loop never blocking more than
+//! `WRITE_TIMEOUT` on a write; a client letting more than `max_pending` bytes
+//! pile up is disconnected.
+//!
+//! The anomalies the game server flags are logged (see `anomaly`), and the
+//! connections of the sessions it disconnected for them closed.
+//!
+//! What the game server records for the audit log is appended to the audit
+//! file after every tick; see `audit`.
+//!
+//! With `ServeConfig::metrics` set, any HTTP request on that address is
+//! answered with the metrics of `metrics`, rendered by the tick loop between
+//! two ticks.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use core_net::packet::Packet;
+use core_net::stream::PacketReader;
+
+use crate::admin;
+use crate::audit::{AuditLog, Rotation};
+use crate::error::Error;
+use crate::game::{GameServer, SessionId};
+use crate::metrics::Metrics;
+
+/// Tick rate of the server
+pub const DEFAULT_TICK: Duration = Duration::from_millis(100);
+
+/// The longest a write to a client holds up the tick loop
+const WRITE_TIMEOUT: Duration = Duration::from_millis(1);
+/// The longest a client is waited for to take its last packets
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+/// The longest a metrics scrape waits on its request, and on the tick loop
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(1);
+
+type ConnectionId = usize;
+
+/// Packets read from a connection that the tick loop hasn't taken in yet
+type Queued = Arc<AtomicUsize>;
+
+enum Event {
+    Connected(ConnectionId, TcpStream, Queued),
+    Packet(ConnectionId, Packet),
+    Closed(ConnectionId),
+    Admin(String),
+    /// A scrape waiting for the metrics
+    Metrics(Sender<String>),
+}
+
+#[derive(Debug, Clone)]
+pub struct ServeConfig {
+    pub tick: Duration,
+    /// Where to save the guilds
+    pub guild_file: Option<PathBuf>,
+    /// Where to save the characters
+    pub character_file: Option<PathBuf>,
+    /// Where to write the snapshot of the world at shutdown
+    pub snapshot_file: Option<PathBuf>,
+    /// Ticks between two savings of the characters online; 0 for never
+    pub autosave: u64,
+    /// Where to append the audit log
+    pub audit_file: Option<PathBuf>,
+    pub audit_rotation: Rotation,
+    /// Whether to read admin commands from stdin
+    pub console: bool,
+    /// Packets read ahead of the tick loop, per connection
+    pub max_queued: usize,
+    /// Bytes waiting for a client before it's disconnected
+    pub max_pending: usize,
+    /// Where to serve the metrics over HTTP
+    pub metrics: Option<SocketAddr>,
+}
+
+impl Default for ServeConfig {
+    fn default() -> ServeConfig {
+        ServeConfig {
+            tick: DEFAULT_TICK,
+            guild_file: None,
+            character_file: None,
+            snapshot_file: None,
+            // a minute at the default tick rate
+            autosave: 600,
+            audit_file: None,
+            audit_rotation: Rotation::default(),
+            console: false,
+            max_queued: 256,
+            max_pending: 1 << 20,
+            metrics: None,
+        }
+    }
+}
+
+struct Connection {
+    session: SessionId,
+    stream: TcpStream,
+    queued: Queued,
+    /// Written by the game, not yet taken by the client
+    pending: Vec<u8>,
+}
+
+/// Serves the game on `listener` until it's shut down
+pub fn serve(listener: TcpListener, game: GameServer, config: ServeConfig) -> Result<(), Error> {
+    let addr = listener.local_addr()?;
+    let (events, receiver) = channel();
+    if config.console {
+        let events = events.clone();
+        thread::spawn(move || console(events));
+    }
+    let audit = match config.audit_file {
+        Some(ref path) => Some(AuditLog::open(path, config.audit_rotation)?),
+        None => None,
+    };
+    let stopped = Arc::new(AtomicBool::new(false));
+    let metrics = match config.metrics {
+        Some(metrics) => {
+            let metrics = TcpListener::bind(metrics)?;
+            let (events, scraping) = (events.clone(), stopped.clone());
+            let addr = metrics.local_addr()?;
+            thread::spawn(move || scrape(metrics, events, scraping));
+            Some(addr)
+        }
+        None => None,
+    };
+    let max_queued = config.max_queued;
+    let accepting = stopped.clone();
+    thread::spawn(move || accept(listener, events, max_queued, accepting));
+    tick_loop(game, &config, audit, receiver);
+    stopped.store(true, Ordering::SeqCst);
+    // wakes up the accepting threads so they see they're stopped
+    let _ = TcpStream::connect(addr);
+    if let Some(metrics) = metrics {
+        let _ = TcpStream::connect(metrics);
+    }
+    Ok(())
+}
+
+fn console(events: Sender<Event>) {
+    let stdin = io::stdin();
+    for line in stdin.lock().lines().map_while(Result::ok) {
+        if events.send(Event::Admin(line)).is_err() {
+            return;
+        }
+    }
+}
+
+fn write_file(path: &Path, text: &str) -> Result<(), Error> {
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, text)?;
+    fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+/// Appends what the game recorded to the audit log
+fn write_audit(game: &mut GameServer, audit: &mut Option<AuditLog>) {
+    let records = game.take_audit();
+    if let Some(ref mut audit) = *audit {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        if let Err(error) = audit.write(&records, time) {
+            println!("writing the audit log failed with: {:?}", error);
+        }
+    }
+}
+
+/// Writes the guilds and the characters that changed
+fn save(game: &mut GameServer, config: &ServeConfig) {
+    if let Some(ref path) = config.guild_file {
+        if game.guilds.take_changed() {
+            if let Err(error) = write_file(path, &game.guilds.to_text()) {
+                println!("saving the guilds to {:?} failed with: {:?}", path, error);
+            }
+        }
+    }
+    if let Some(ref path) = config.character_file {
+        if game.characters.take_changed() {
+            if let Err(error) = write_file(path, &game.characters.to_text()) {
+                println!("saving the characters to {:?} failed with: {:?}", path, error);
+            }
+        }
+    }
+}
+
+fn accept(listener: TcpListener, events: Sender<Event>, max_queued: usize, stopped: Arc<AtomicBool>) {
+    for (connection, maybe_stream) in listener.incoming().enumerate() {
+        if stopped.load(Ordering::SeqCst) {
+            return;
+        }
+        let stream = match maybe_stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                println!("accepting a connection failed with: `{}`", error);
+                continue;
+            }
+        };
+        let _ = stream.set_nodelay(true);
+        let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+        let reader = match stream.try_clone() {
+            Ok(reader) => reader,
+            Err(_) => continue,
+        };
+        let queued = Queued::default();
+        if events.send(Event::Connected(connection, stream, queued.clone())).is_err() {
+            return;
+        }
+        let events = events.clone();
+        thread::spawn(move || {
+            let mut reader = PacketReader::new(reader);
+            while let Ok(Some(packet)) = reader.read_packet() {
+                if events.send(Event::Packet(connection, packet)).is_err() {
+                    return;
+                }
+                while queued.fetch_add(1, Ordering::SeqCst) + 1 > max_queued {
+                    queued.fetch_sub(1, Ordering::SeqCst);
+                    thread::sleep(WRITE_TIMEOUT);
+                }
+            }
+            let _ = events.send(Event::Closed(connection));
+        });
+    }
+}
+
+/// Answers every HTTP request with the metrics, whatever it asks for
+fn scrape(listener: TcpListener, events: Sender<Event>, stopped: Arc<AtomicBool>) {
+    for maybe_stream in listener.incoming() {
+        if stopped.load(Ordering::SeqCst) {
+            return;
+        }
+        let mut stream = match maybe_stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        let _ = stream.set_read_timeout(Some(SCRAPE_TIMEOUT));
+        let _ = stream.set_write_timeout(Some(SCRAPE_TIMEOUT));
+        // the request head, up to the blank line
+        let mut request = Vec::new();
+        let mut buffer = [0u8; 1024];
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < 8192 {
+            match stream.read(&mut buffer) {
+                Ok(count) if count > 0 => request.extend_from_slice(&buffer[..count]),
+                _ => break,
+            }
+        }
+        let (reply, metrics) = channel();
+        if events.send(Event::Metrics(reply)).is_err() {
+            return;
+        }
+        let response = match metrics.recv_timeout(SCRAPE_TIMEOUT) {
+            Ok(body) => format!("HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+                                 Content-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body),
+            Err(_) => "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".into(),
+        };
+        let _ = stream.write_all(response.as_bytes());
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+}
+
+fn tick_loop(mut game: GameServer, config: &ServeConfig, mut audit: Option<AuditLog>, events: Receiver<Event>) {
+    let tick = config.tick;
+    let mut connections: BTreeMap<ConnectionId, Connection> = BTreeMap::new();
+    let mut metrics = Metrics::new();
+    loop {
+        let start = Instant::now();
+        while let Ok(event) = events.try_recv() {
+            match event {
+                Event::Connected(connection, stream, queued) => {
+                    let session = game.connect();
+                    connections.insert(connection, Connection { session, stream, queued, pending: Vec::new() });
+                }
+                Event::Packet(connection, packet) => {
+                    if let Some(connection) = connections.get(&connection) {
+                        connection.queued.fetch_sub(1, Ordering::SeqCst);
+                        metrics.received(&packet);
+                        game.receive(connection.session, packet);
+                    }
+                }
+                Event::Closed(connection) => {
+                    if let Some(connection) = connections.remove(&connection) {
+                        game.disconnect(connection.session);
+                    }
+                }
+                Event::Admin(line) => {
+                    let output = admin::execute(&mut game, &line);
+                    if !output.is_empty() {
+                        println!("{}", output);
+                    }
+                }
+                Event::Metrics(reply) => {
+                    let _ = reply.send(metrics.render(&game));
+                }
+            }
+        }
+        if game.is_shutting_down() {
+            write_audit(&mut game, &mut audit);
+            return shut_down(game, config, connections);
+        }
+
+        game.tick();
+        for flagged in game.anomalies.take_flagged() {
+            println!("anomaly `{}` from session {} at tick {}: {}",
+                     flagged.anomaly.name(), flagged.session, flagged.tick, flagged.response.name());
+        }
+        if config.autosave > 0 && game.tick.is_multiple_of(config.autosave) {
+            game.save_characters();
+        }
+        save(&mut game, config);
+        write_audit(&mut game, &mut audit);
+
+        let mut failed = Vec::new();
+        for (&id, connection) in connections.iter_mut() {
+            for packet in game.take_outgoing(connection.session) {
+                metrics.sent(&packet);
+                connection.pending.extend_from_slice(&packet.encode());
+            }
+            let flushed = flush(&mut connection.stream, &mut connection.pending);
+            if !game.is_connected(connection.session) || flushed.is_err() || connection.pending.len() > config.max_pending {
+                failed.push(id);
+            }
+        }
+        for id in failed {
+            if let Some(connection) = connections.remove(&id) {
+                let _ = connection.stream.shutdown(Shutdown::Both);
+                game.disconnect(connection.session);
+            }
+        }
+
+        let elapsed = start.elapsed();
+        metrics.ticked(elapsed);
+        if elapsed > tick {
+            game.tick_overruns += 1;
+        } else {
+            thread::sleep(tick - elapsed);
+        }
+    }
+}
+
+/// Sends the clients what's left for them and closes the connections, then
+/// saves everything
+fn shut_down(mut game: GameServer, config: &ServeConfig, connections: BTreeMap<ConnectionId, Connection>) {
+    for (_, mut connection) in connections {
+        for packet in game.take_outgoing(connection.session) {
+            connection.pending.extend_from_slice(&packet.encode());
+        }
+        let _ = connection.stream.set_write_timeout(Some(SHUTDOWN_TIMEOUT));
+        let _ = connection.stream.write_all(&connection.pending);
+        let _ = connection.stream.shutdown(Shutdown::Both);
+        game.disconnect(connection.session);
+    }
+    save(&mut game, config);
+    if let Some(ref path) = config.snapshot_file {
+        if let Err(error) = write_file(path, &game.snapshot().to_text()) {
+            println!("writing the snapshot to {:?} failed with: {:?}", path, error);
+        }
+    }
+    println!("shut down at tick {}", game.tick);
+}
+
+/// Writes what the client takes of `pending` without waiting, and keeps the
+/// rest
+fn flush(stream: &mut TcpStream, pending: &mut Vec<u8>) -> io::Result<()> {
+    let mut written = 0;
+    let result = loop {
+        if written == pending.len() {
+            break Ok(());
+        }
+        match stream.write(&pending[written..]) {
+            Ok(0) => break Err(io::ErrorKind::WriteZero.into()),
+            Ok(count) => written += count,
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted => (),
+            Err(ref error) if error.kind() == io::ErrorKind::WouldBlock || error.kind() == io::ErrorKind::TimedOut => {
+                break Ok(());
+            }
+            Err(error) => break Err(error),
+        }
+    };
+    pending.drain(..written);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::world::{World, WorldConfig};
+
+    #[test]
+    fn test_serve() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let game = GameServer::new(World::new(WorldConfig { start_map: 1, start_x: 5, start_y: 5 }));
+        thread::spawn(move || serve(listener, game, ServeConfig { tick: Duration::from_millis(5), ..ServeConfig::default() }));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut reader = PacketReader::new(stream.try_clone().unwrap());
+        stream.write_all(&Packet::Login { name: "Lavita".into() }.encode()).unwrap();
+        stream.write_all(&Packet::Ping { seq: 7 }.encode()).unwrap();
+        match reader.read_packet().unwrap() {
+            Some(Packet::LoginOk { map: 1, x: 5, y: 5, .. }) => (),
+            other => panic!("unexpected packet: {:?}", other),
+        }
+        match reader.read_packet().unwrap() {
+            Some(Packet::Ambiance { .. }) => (),
+            other => panic!("unexpected packet: {:?}", other),
+        }
+        match reader.read_packet().unwrap() {
+            Some(Packet::Pong { seq: 7, .. }) => (),
+            other => panic!("unexpected packet: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_metrics() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // a free port for the metrics
+        let metrics = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let game = GameServer::new(World::new(WorldConfig { start_map: 1, start_x: 5, start_y: 5 }));
+        let config = ServeConfig { tick: Duration::from_millis(5), metrics: Some(metrics), ..ServeConfig::default() };
+        thread::spawn(move || serve(listener, game, config));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut reader = PacketReader::new(stream.try_clone().unwrap());
+        stream.write_all(&Packet::Ping { seq: 7 }.encode()).unwrap();
+        while !matches!(reader.read_packet().unwrap(), Some(Packet::Pong { .. })) {}
+
+        // bound before the first tick
+        let mut scrape = TcpStream::connect(metrics).unwrap();
+        scrape.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        scrape.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\nnovluno_sessions 1\n"));
+        assert!(response.contains("\nnovluno_packets_received_total{opcode=\"0x05\"} 1\n"));
+    }
+
+    #[test]
+    fn test_shut_down() {
+        let folder = std::env::temp_dir().join(format!("novluno_serve_{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut game = GameServer::new(World::new(WorldConfig { start_map: 1, start_x: 5, start_y: 5 }));
+        let session = game.connect();
+        game.receive(session, Packet::Login { name: "Lavita".into() });
+        game.tick();
+        admin::execute(&mut game, "shutdown bye");
+        let config = ServeConfig {
+            character_file: Some(folder.join("characters.txt")),
+            snapshot_file: Some(folder.join("snapshot.txt")),
+            audit_file: Some(folder.join("audit.log")),
+            ..ServeConfig::default()
+        };
+
+        // returns right away, with everything saved
+        serve(listener, game, config).unwrap();
+        let characters = fs::read_to_string(folder.join("characters.txt")).unwrap();
+        assert_eq!(characters, "Lavita\t1\t5\t5\t100\t1\t\n");
+        let snapshot = fs::read_to_string(folder.join("snapshot.txt")).unwrap();
+        assert_eq!(snapshot, "tick 1\nweather clear\n");
+        let audit = fs::read_to_string(folder.join("audit.log")).unwrap();
+        assert!(audit.ends_with(",\"tick\":1,\"event\":\"command\",\"line\":\"shutdown bye\"}\n"));
+        let _ = fs::remove_dir_all(&folder);
+    }
+}
@@ -0,0 +1,185 @@
+//! Decoding single packets against a table of packet definitions.
+//!
+//! Little is known about the protocol yet, so the definitions come from a
+//! text file that grows as packets are worked out. Each line is
+//!
+//! ```text
+//! <hex prefix> <name> [<field>:<type>..]
+//! ```
+//!
+//! where a packet matches the definition with the longest prefix its data
+//! starts with, and the fields follow the prefix. The types are `u8`, `u16`,
+//! `u32`, `i16` and `i32`, little endian. `#` starts a comment.
+
+use byteorder::ByteOrder;
+use byteorder::LittleEndian as LE;
+
+use crate::error::Error;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FieldType {
+    U8,
+    U16,
+    U32,
+    I16,
+    I32,
+}
+
+impl FieldType {
+    fn parse(name: &str) -> Option<FieldType> {
+        match name {
+            "u8" => Some(FieldType::U8),
+            "u16" => Some(FieldType::U16),
+            "u32" => Some(FieldType::U32),
+            "i16" => Some(FieldType::I16),
+            "i32" => Some(FieldType::I32),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            FieldType::U8 => 1,
+            FieldType::U16 | FieldType::I16 => 2,
+            FieldType::U32 | FieldType::I32 => 4,
+        }
+    }
+
+    fn read(self, data: &[u8]) -> i64 {
+        match self {
+            FieldType::U8 => data[0] as i64,
+            FieldType::U16 => LE::read_u16(data) as i64,
+            FieldType::U32 => LE::read_u32(data) as i64,
+            FieldType::I16 => LE::read_i16(data) as i64,
+            FieldType::I32 => LE::read_i32(data) as i64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PacketDef {
+    pub prefix: Vec<u8>,
+    pub name: String,
+    pub fields: Vec<(String, FieldType)>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Field {
+    pub offset: usize,
+    pub name: String,
+    pub value: i64,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Decoded<'a> {
+    pub def: Option<&'a PacketDef>,
+    pub fields: Vec<Field>,
+    /// Where the undecoded rest of the packet starts
+    pub rest: usize,
+}
+
+pub fn parse_definitions(table: &str) -> Result<Vec<PacketDef>, Error> {
+    let mut defs = Vec::new();
+    for (line_num, line) in table.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let bad_line = || Error::InvalidPacketDefinition(line_num + 1);
+        let mut words = line.split_whitespace();
+        let prefix = words.next().and_then(from_hex).ok_or_else(bad_line)?;
+        let name = words.next().ok_or_else(bad_line)?.to_string();
+        let mut fields = Vec::new();
+        for word in words {
+            let mut parts = word.splitn(2, ':');
+            let field = parts.next().unwrap_or("").to_string();
+            let kind = parts.next().and_then(FieldType::parse).ok_or_else(bad_line)?;
+            fields.push((field, kind));
+        }
+        defs.push(PacketDef { prefix, name, fields });
+    }
+    Ok(defs)
+}
+
+/// Decodes the fields of the definition matching `data`, as far as the data
+/// goes.
+pub fn decode<'a>(defs: &'a [PacketDef], data: &[u8]) -> Decoded<'a> {
+    let def = defs.iter()
+        .filter(|def| data.starts_with(&def.prefix))
+        .max_by_key(|def| def.prefix.len());
+    let mut fields = Vec::new();
+    let mut offset = 0;
+    if let Some(def) = def {
+        offset = def.prefix.len();
+        for &(ref name, kind) in def.fields.iter() {
+            if offset + kind.size() > data.len() {
+                break;
+            }
+            fields.push(Field { offset, name: name.clone(), value: kind.read(&data[offset..]) });
+            offset += kind.size();
+        }
+    }
+    Decoded { def, fields, rest: offset }
+}
+
+/// Classic hex dump, 16 bytes a line, with offsets counted from `base`
+pub fn hex_dump(data: &[u8], base: usize) -> String {
+    let mut dump = String::new();
+    for (line, chunk) in data.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let text: String = chunk.iter()
+            .map(|&byte| if (0x20..0x7F).contains(&byte) { byte as char } else { '.' })
+            .collect();
+        dump.push_str(&format!("{:04x}  {:<47}  |{}|\n", base + line * 16, hex.join(" "), text));
+    }
+    dump
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|idx| u8::from_str_radix(&hex[idx..idx + 2], 16).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_definitions() {
+        let defs = parse_definitions("# opcode name fields\n0a01 move x:u16 y:u16 # walk\n0a move_any\n").unwrap();
+        assert_eq!(defs.len(), 2);
+        assert_eq!(defs[0].prefix, vec![0x0a, 0x01]);
+        assert_eq!(defs[0].fields, vec![("x".to_string(), FieldType::U16), ("y".to_string(), FieldType::U16)]);
+        match parse_definitions("0a ok\n0b bad x:f32") {
+            Err(Error::InvalidPacketDefinition(2)) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        let defs = parse_definitions("0a move_any\n0a01 move x:u16 y:i16 z:u32").unwrap();
+        let decoded = decode(&defs, &[0x0a, 0x01, 0x10, 0x00, 0xFF, 0xFF, 0x01]);
+        assert_eq!(decoded.def.unwrap().name, "move");
+        assert_eq!(decoded.fields, vec![
+            Field { offset: 2, name: "x".into(), value: 16 },
+            Field { offset: 4, name: "y".into(), value: -1 },
+        ]);
+        // `z` doesn't fit
+        assert_eq!(decoded.rest, 6);
+
+        assert_eq!(decode(&defs, &[0x0a, 0x02]).def.unwrap().name, "move_any");
+        let unknown = decode(&defs, &[0x0b]);
+        assert_eq!((unknown.def, unknown.rest), (None, 0));
+    }
+
+    #[test]
+    fn test_hex_dump() {
+        let data: Vec<u8> = (0x30..0x42).collect();
+        assert_eq!(hex_dump(&data, 4),
+                   "0004  30 31 32 33 34 35 36 37 38 39 3a 3b 3c 3d 3e 3f  |0123456789:;<=>?|\n\
+                    0014  40 41                                            |@A|\n");
+    }
+}
@@ -0,0 +1,66 @@
+//! Finding the way over the walkable tiles of the world's maps, with the
+//! search of `core_rules::pathfind`.
+
+use core_rules::pathfind;
+
+use crate::world::World;
+
+pub use core_rules::pathfind::Tile;
+
+/// The first step from `from` towards a tile at most `reach` tiles from
+/// `goal`, looking at no more than `limit` tiles; `None` if already there or
+/// there's no way to get closer.
+pub fn next_step(world: &World, map: u32, from: Tile, goal: Tile, reach: u16, limit: usize) -> Option<Tile> {
+    pathfind::next_step(|(x, y)| world.is_passable(map, x, y), from, goal, reach, limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::map::ServerMap;
+    use crate::world::WorldConfig;
+
+    /// An 8x8 map with a wall down x = 3, open only at y = 7
+    fn walled() -> World {
+        let mut world = World::new(WorldConfig { start_map: 1, start_x: 0, start_y: 0 });
+        let mut passable = vec![0xFF; 8 * 8 / 8];
+        for row in passable.iter_mut().take(7) {
+            *row &= !(1 << 3);
+        }
+        world.add_map(ServerMap { number: 1, size_x: 8, size_y: 8, passable, warps: Vec::new(), spawns: Vec::new() });
+        world
+    }
+
+    #[test]
+    fn test_open_field() {
+        let world = World::new(WorldConfig { start_map: 1, start_x: 0, start_y: 0 });
+        assert_eq!(next_step(&world, 1, (5, 5), (9, 9), 0, 100), Some((6, 6)));
+        assert_eq!(next_step(&world, 1, (5, 5), (6, 6), 1, 100), None);
+        assert_eq!(next_step(&world, 1, (0, 0), (0, 5), 1, 100), Some((0, 1)));
+    }
+
+    #[test]
+    fn test_around_the_wall() {
+        let world = walled();
+        assert!(!world.is_passable(1, 3, 0) && world.is_passable(1, 3, 7));
+        // straight at the wall would be (3, 1); the way round goes down, and
+        // squarely past the end of the wall
+        let mut tile = (2, 1);
+        let mut steps = 0;
+        while let Some(next) = next_step(&world, 1, tile, (5, 1), 0, 64) {
+            assert!(world.is_passable(1, next.0, next.1));
+            tile = next;
+            steps += 1;
+        }
+        assert_eq!((tile, steps), ((5, 1), 14));
+    }
+
+    #[test]
+    fn test_limit() {
+        let world = walled();
+        // no tile closer to the goal among the few looked at
+        assert_eq!(next_step(&world, 1, (2, 1), (5, 1), 0, 4), None);
+        assert!(next_step(&world, 1, (2, 1), (5, 1), 0, 64).is_some());
+    }
+}
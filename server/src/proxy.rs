@@ -0,0 +1,343 @@
+//! Man-in-the-middle proxy between a client and a server, for protocol
+//! research. The traffic is decrypted on the way through, optionally
+//! rewritten by a rule table, and logged as JSON lines:
+//!
+//! ```json
+//! {"ms": 1520, "conn": 0, "dir": "c2s", "raw": "0a07080b", "data": "0a070b", "rewritten": false}
+//! ```
+//!
+//! The packet framing isn't known yet, so a record is whatever one read
+//! returned. `raw` is the bytes as they came in, `data` the decrypted bytes
+//! as they were passed on; rewritten data is encrypted again, everything else
+//! is passed on untouched.
+//!
+//! Each line of a rule table is `<c2s|s2c|any> <hex> <hex>`: every occurrence
+//! of the first byte string in the decrypted data going that way is replaced
+//! by the second, or removed if the second is `-`. `#` starts a comment.
+
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use crate::crypto;
+use crate::packet::from_hex;
+use crate::error::Error;
+
+const MAX_MSG_SIZE: usize = 2048;
+/// Escapes the next byte, see `crypto`
+const ESCAPE: u8 = 7;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    ClientToServer,
+    ServerToClient,
+}
+
+impl Direction {
+    fn name(self) -> &'static str {
+        match self {
+            Direction::ClientToServer => "c2s",
+            Direction::ServerToClient => "s2c",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    /// `None` for both directions
+    pub direction: Option<Direction>,
+    pub find: Vec<u8>,
+    pub replace: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProxyConfig {
+    pub listen: String,
+    pub server: String,
+    pub log: Option<PathBuf>,
+    pub rules: Vec<Rule>,
+}
+
+impl ProxyConfig {
+    pub fn new(listen: &str, server: &str) -> ProxyConfig {
+        ProxyConfig {
+            listen: listen.into(),
+            server: server.into(),
+            log: None,
+            rules: Vec::new(),
+        }
+    }
+}
+
+type Log = Arc<Mutex<Box<dyn Write + Send>>>;
+
+/// What the threads of one connection share
+struct Session {
+    conn: usize,
+    rules: Arc<Vec<Rule>>,
+    log: Option<Log>,
+    start: Instant,
+}
+
+pub fn run(config: &ProxyConfig) -> Result<(), Error> {
+    let listener = TcpListener::bind(&config.listen)?;
+    let log: Option<Log> = match config.log {
+        Some(ref path) => Some(Arc::new(Mutex::new(Box::new(File::create(path)?)))),
+        None => None,
+    };
+    let rules = Arc::new(config.rules.clone());
+    let start = Instant::now();
+
+    // NOTE: This iterator will not yield a `None` value so is equivalent to a loop
+    println!("listening for connections on `{}`", config.listen);
+    for (conn, maybe_stream) in listener.incoming().enumerate() {
+        match maybe_stream {
+            Ok(client_stream) => {
+                println!("got connection {} from: `{:?}`", conn, client_stream.peer_addr());
+                let session = Session { conn, rules: rules.clone(), log: log.clone(), start };
+                let server = config.server.clone();
+                thread::spawn(move || {
+                    if let Err(error) = handle_client(client_stream, &server, session) {
+                        println!("connection {} failed with: `{:?}`", conn, error);
+                    }
+                });
+            }
+            Err(error) => println!("Client Connection Listener failed with: `{}`", error),
+        }
+    }
+    Ok(())
+}
+
+fn handle_client(client_stream: TcpStream, server: &str, session: Session) -> Result<(), Error> {
+    println!("trying to connect to server: {:?}", server);
+    let server_stream = TcpStream::connect(server)?;
+    let session = Arc::new(session);
+
+    let to_server = {
+        let (client, server) = (client_stream.try_clone()?, server_stream.try_clone()?);
+        let session = session.clone();
+        thread::spawn(move || pump(client, server, Direction::ClientToServer, &session))
+    };
+    let to_client = pump(server_stream, client_stream, Direction::ServerToClient, &session);
+    let to_server = to_server.join().unwrap_or(Ok(()));
+    println!("Ending client connection {}", session.conn);
+    to_client.and(to_server)
+}
+
+/// Passes the data from one stream to the other until either is closed
+fn pump(mut from: TcpStream, mut to: TcpStream, direction: Direction, session: &Session) -> Result<(), Error> {
+    let result = pass_on(&mut from, &mut to, direction, session);
+    // whichever side hangs up ends the connection
+    let _ = from.shutdown(Shutdown::Both);
+    let _ = to.shutdown(Shutdown::Both);
+    result
+}
+
+fn pass_on(from: &mut TcpStream, to: &mut TcpStream, direction: Direction, session: &Session) -> Result<(), Error> {
+    let mut buffer = [0u8; MAX_MSG_SIZE];
+    let mut pending = Vec::new();
+    loop {
+        let bytes = match from.read(&mut buffer) {
+            Ok(0) | Err(_) => return Ok(()),
+            Ok(bytes) => bytes,
+        };
+        pending.extend_from_slice(&buffer[..bytes]);
+        // an escape split from the byte it escapes waits for the next read
+        let complete = complete_len(&pending);
+        let raw: Vec<u8> = pending.drain(..complete).collect();
+        if raw.is_empty() {
+            continue;
+        }
+
+        let data = crypto::decrypt(&raw);
+        let rewritten = apply_rules(&session.rules, direction, &data);
+        // logged before it's passed on, so an answer is never logged ahead
+        // of what it answers
+        if let Some(ref log) = session.log {
+            let record = log_record(session.start.elapsed().as_millis(), session.conn, direction,
+                                    &raw, rewritten.as_ref().unwrap_or(&data), rewritten.is_some());
+            log.lock().unwrap().write_all(record.as_bytes())?;
+        }
+        match rewritten {
+            Some(ref data) => to.write_all(&crypto::encrypt(data))?,
+            None => to.write_all(&raw)?,
+        }
+    }
+}
+
+/// The length of the prefix of `raw` that doesn't end in an escape
+fn complete_len(raw: &[u8]) -> usize {
+    let mut idx = 0;
+    while idx < raw.len() {
+        if raw[idx] == ESCAPE {
+            if idx + 1 == raw.len() {
+                return idx;
+            }
+            idx += 2;
+        } else {
+            idx += 1;
+        }
+    }
+    raw.len()
+}
+
+/// The rewritten data, if any rule matched
+fn apply_rules(rules: &[Rule], direction: Direction, data: &[u8]) -> Option<Vec<u8>> {
+    let mut result = data.to_vec();
+    for rule in rules.iter().filter(|rule| rule.direction.is_none_or(|d| d == direction)) {
+        result = replace_all(&result, &rule.find, &rule.replace);
+    }
+    if result != data { Some(result) } else { None }
+}
+
+fn replace_all(data: &[u8], find: &[u8], replace: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    let mut idx = 0;
+    while idx < data.len() {
+        if !find.is_empty() && data[idx..].starts_with(find) {
+            result.extend_from_slice(replace);
+            idx += find.len();
+        } else {
+            result.push(data[idx]);
+            idx += 1;
+        }
+    }
+    result
+}
+
+pub fn parse_rules(table: &str) -> Result<Vec<Rule>, Error> {
+    let mut rules = Vec::new();
+    for (line_num, line) in table.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let bad_line = || Error::InvalidProxyRule(line_num + 1);
+        if fields.len() != 3 {
+            return Err(bad_line());
+        }
+        let direction = match fields[0] {
+            "c2s" => Some(Direction::ClientToServer),
+            "s2c" => Some(Direction::ServerToClient),
+            "any" => None,
+            _ => return Err(bad_line()),
+        };
+        let find = from_hex(fields[1]).filter(|find| !find.is_empty()).ok_or_else(bad_line)?;
+        let replace = match fields[2] {
+            "-" => Vec::new(),
+            hex => from_hex(hex).ok_or_else(bad_line)?,
+        };
+        rules.push(Rule { direction, find, replace });
+    }
+    Ok(rules)
+}
+
+fn log_record(ms: u128, conn: usize, direction: Direction, raw: &[u8], data: &[u8], rewritten: bool) -> String {
+    format!("{{\"ms\": {}, \"conn\": {}, \"dir\": \"{}\", \"raw\": \"{}\", \"data\": \"{}\", \"rewritten\": {}}}\n",
+            ms, conn, direction.name(), to_hex(raw), to_hex(data), rewritten)
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A log that can still be read after the session has it
+    #[derive(Clone)]
+    struct SharedLog(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedLog {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let rules = parse_rules("c2s 0a0b 0c # shorter\ns2c FF 0000\n# comment\nany 07 -\n").unwrap();
+        assert_eq!(rules, vec![
+            Rule { direction: Some(Direction::ClientToServer), find: vec![0x0a, 0x0b], replace: vec![0x0c] },
+            Rule { direction: Some(Direction::ServerToClient), find: vec![0xff], replace: vec![0, 0] },
+            Rule { direction: None, find: vec![0x07], replace: vec![] },
+        ]);
+        match parse_rules("c2s 0a 0b\nboth 0a 0b") {
+            Err(Error::InvalidProxyRule(2)) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(parse_rules("any 0 0b").is_err());
+        assert!(parse_rules("any - 0b").is_err());
+    }
+
+    #[test]
+    fn test_apply_rules() {
+        let rules = parse_rules("c2s 0a0b 0c\nany ff ee").unwrap();
+        assert_eq!(apply_rules(&rules, Direction::ClientToServer, &[0x0a, 0x0b, 0x0a, 0xff]),
+                   Some(vec![0x0c, 0x0a, 0xee]));
+        assert_eq!(apply_rules(&rules, Direction::ServerToClient, &[0x0a, 0x0b, 0xff]),
+                   Some(vec![0x0a, 0x0b, 0xee]));
+        assert_eq!(apply_rules(&rules, Direction::ServerToClient, &[0x0a, 0x0b]), None);
+    }
+
+    #[test]
+    fn test_complete_len() {
+        assert_eq!(complete_len(&[1, 2, 3]), 3);
+        assert_eq!(complete_len(&[1, ESCAPE]), 1);
+        assert_eq!(complete_len(&[1, ESCAPE, ESCAPE]), 3);
+        assert_eq!(complete_len(&[ESCAPE, 8, ESCAPE]), 2);
+    }
+
+    #[test]
+    fn test_proxy_connection() {
+        // a server answering every message with `ok`
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (mut stream, _) = server.accept().unwrap();
+            let mut buffer = [0u8; 64];
+            while let Ok(bytes) = stream.read(&mut buffer) {
+                if bytes == 0 || stream.write_all(b"ok").is_err() {
+                    break;
+                }
+            }
+        });
+
+        let proxy = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(proxy.local_addr().unwrap()).unwrap();
+        let (client_stream, _) = proxy.accept().unwrap();
+        let log = SharedLog(Arc::new(Mutex::new(Vec::new())));
+        let session = Session {
+            conn: 3,
+            rules: Arc::new(parse_rules("s2c 6b 4b").unwrap()),
+            log: Some(Arc::new(Mutex::new(Box::new(log.clone())))),
+            start: Instant::now(),
+        };
+        let proxy_thread = thread::spawn(move || handle_client(client_stream, &server_addr, session));
+
+        client.write_all(b"hi").unwrap();
+        let mut answer = [0u8; 2];
+        client.read_exact(&mut answer).unwrap();
+        // rewritten on the way back
+        assert_eq!(&answer, b"oK");
+        drop(client);
+        proxy_thread.join().unwrap().unwrap();
+
+        let log = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+        let records: Vec<&str> = log.lines().collect();
+        assert_eq!(records.len(), 2);
+        assert!(records[0].contains("\"conn\": 3, \"dir\": \"c2s\", \"raw\": \"6869\", \"data\": \"6869\", \"rewritten\": false"));
+        assert!(records[1].contains("\"dir\": \"s2c\", \"raw\": \"6f6b\", \"data\": \"6f4b\", \"rewritten\": true"));
+    }
+}
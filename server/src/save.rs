@@ -0,0 +1,287 @@
+//! What outlasts a restart: the characters of the players, and snapshots of
+//! the world.
+//!
+//! The characters are kept by player name, as the entity ids change with
+//! every login, and saved as a text file with a line per character:
+//!
+//! ```text
+//! <name>\t<map>\t<x>\t<y>\t<hp>\t<level>\t<item>:<count>,<item>:<count>,..
+//! ```
+//!
+//! A snapshot is what lives in the world besides the players: the tick,
+//! which sets the time of day, the weather, the monsters and the items on
+//! the ground, a line each:
+//!
+//! ```text
+//! tick <tick>
+//! weather <name>
+//! monster <kind> <map> <x> <y> <hp>
+//! ground <item> <count> <map> <x> <y>
+//! ```
+
+use std::collections::BTreeMap;
+
+use core_rules::ambiance::Weather;
+
+use crate::error::Error;
+use crate::item::ItemId;
+use crate::world::{Entity, EntityKind};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Character {
+    pub map: u32,
+    pub x: u16,
+    pub y: u16,
+    pub hp: u16,
+    pub level: u32,
+    /// Items and their counts, by item
+    pub items: Vec<(ItemId, u32)>,
+}
+
+impl Character {
+    pub fn of(entity: &Entity) -> Character {
+        Character {
+            map: entity.map,
+            x: entity.x,
+            y: entity.y,
+            hp: entity.hp,
+            level: entity.level,
+            items: entity.inventory.items(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Characters {
+    characters: BTreeMap<String, Character>,
+    /// Whether the characters changed since the last `take_changed`
+    changed: bool,
+}
+
+impl Characters {
+    pub fn new() -> Characters {
+        Characters::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Characters, Error> {
+        let mut characters = Characters::new();
+        for (number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let character = match fields.as_slice() {
+                &[name, map, x, y, hp, level, items] => parse_character(map, x, y, hp, level, items)
+                    .filter(|_| !name.is_empty() && !characters.characters.contains_key(name))
+                    .map(|character| (name, character)),
+                _ => None,
+            };
+            match character {
+                Some((name, character)) => {
+                    characters.characters.insert(name.into(), character);
+                }
+                None => return Err(Error::InvalidCharacterLine(number + 1)),
+            }
+        }
+        Ok(characters)
+    }
+
+    pub fn to_text(&self) -> String {
+        self.characters.iter()
+            .map(|(name, character)| {
+                let items: Vec<String> = character.items.iter().map(|&(item, count)| format!("{}:{}", item, count)).collect();
+                format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\n", name, character.map, character.x, character.y,
+                        character.hp, character.level, items.join(","))
+            })
+            .collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Character> {
+        self.characters.get(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.characters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.characters.is_empty()
+    }
+
+    /// Keeps the character of `name`; names holding the separators of the
+    /// character file aren't kept.
+    pub fn store(&mut self, name: &str, character: Character) {
+        if name.is_empty() || name.contains(['\t', '\n', '\r']) || self.get(name) == Some(&character) {
+            return;
+        }
+        self.characters.insert(name.into(), character);
+        self.changed = true;
+    }
+
+    /// Whether anything changed since the last call
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::replace(&mut self.changed, false)
+    }
+}
+
+fn parse_character(map: &str, x: &str, y: &str, hp: &str, level: &str, items: &str) -> Option<Character> {
+    let items = items.split(',')
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let mut parts = item.splitn(2, ':');
+            let item = parts.next()?.parse().ok()?;
+            let count = parts.next()?.parse().ok().filter(|&count| count > 0)?;
+            Some((item, count))
+        })
+        .collect::<Option<Vec<(ItemId, u32)>>>()?;
+    Some(Character {
+        map: map.parse().ok()?,
+        x: x.parse().ok()?,
+        y: y.parse().ok()?,
+        hp: hp.parse().ok()?,
+        level: level.parse().ok()?,
+        items,
+    })
+}
+
+/// A monster as it was in a snapshot
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SavedMonster {
+    pub kind: u32,
+    pub map: u32,
+    pub x: u16,
+    pub y: u16,
+    pub hp: u16,
+}
+
+/// Items on the ground as they were in a snapshot
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SavedGround {
+    pub item: ItemId,
+    pub count: u32,
+    pub map: u32,
+    pub x: u16,
+    pub y: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub tick: u64,
+    pub weather: Weather,
+    pub monsters: Vec<SavedMonster>,
+    pub ground: Vec<SavedGround>,
+}
+
+impl Snapshot {
+    /// What lives in the world besides the players
+    pub fn of<'a>(tick: u64, weather: Weather, entities: impl Iterator<Item = &'a Entity>) -> Snapshot {
+        let mut snapshot = Snapshot { tick, weather, monsters: Vec::new(), ground: Vec::new() };
+        for entity in entities {
+            let (map, x, y) = (entity.map, entity.x, entity.y);
+            match entity.kind {
+                EntityKind::Player => (),
+                EntityKind::Monster(kind) => {
+                    if entity.is_alive() {
+                        snapshot.monsters.push(SavedMonster { kind, map, x, y, hp: entity.hp });
+                    }
+                }
+                EntityKind::Item { item, count } => snapshot.ground.push(SavedGround { item, count, map, x, y }),
+            }
+        }
+        snapshot
+    }
+
+    pub fn parse(text: &str) -> Result<Snapshot, Error> {
+        let mut snapshot = Snapshot { tick: 0, weather: Weather::Clear, monsters: Vec::new(), ground: Vec::new() };
+        for (number, line) in text.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let parsed = match fields.as_slice() {
+                [] => Some(()),
+                ["tick", tick] => tick.parse().ok().map(|tick| snapshot.tick = tick),
+                ["weather", name] => Weather::from_name(name).map(|weather| snapshot.weather = weather),
+                ["monster", kind, map, x, y, hp] => (|| {
+                    let monster = SavedMonster {
+                        kind: kind.parse().ok()?,
+                        map: map.parse().ok()?,
+                        x: x.parse().ok()?,
+                        y: y.parse().ok()?,
+                        hp: hp.parse().ok()?,
+                    };
+                    snapshot.monsters.push(monster);
+                    Some(())
+                })(),
+                ["ground", item, count, map, x, y] => (|| {
+                    let ground = SavedGround {
+                        item: item.parse().ok()?,
+                        count: count.parse().ok()?,
+                        map: map.parse().ok()?,
+                        x: x.parse().ok()?,
+                        y: y.parse().ok()?,
+                    };
+                    snapshot.ground.push(ground);
+                    Some(())
+                })(),
+                _ => None,
+            };
+            if parsed.is_none() {
+                return Err(Error::InvalidSnapshotLine(number + 1));
+            }
+        }
+        Ok(snapshot)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("tick {}\nweather {}\n", self.tick, self.weather.name());
+        for monster in self.monsters.iter() {
+            text += &format!("monster {} {} {} {} {}\n", monster.kind, monster.map, monster.x, monster.y, monster.hp);
+        }
+        for ground in self.ground.iter() {
+            text += &format!("ground {} {} {} {} {}\n", ground.item, ground.count, ground.map, ground.x, ground.y);
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_character_file() {
+        let mut characters = Characters::new();
+        let philar = Character { map: 3, x: 21, y: 20, hp: 80, level: 2, items: vec![(7, 3), (9, 1)] };
+        characters.store("Philar", philar.clone());
+        characters.store("달빛", Character { items: Vec::new(), ..philar.clone() });
+        characters.store("Bad\tName", philar.clone());
+        assert!(characters.take_changed());
+        // nothing changes for the same character
+        characters.store("Philar", philar.clone());
+        assert!(!characters.take_changed());
+
+        let text = characters.to_text();
+        assert_eq!(text, "Philar\t3\t21\t20\t80\t2\t7:3,9:1\n달빛\t3\t21\t20\t80\t2\t\n");
+        let loaded = Characters::parse(&text).unwrap();
+        assert_eq!(loaded.get("Philar"), Some(&philar));
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded.to_text(), text);
+
+        assert!(matches!(Characters::parse("Philar\t3\t21\t20\t80\t2\t7:0\n"), Err(Error::InvalidCharacterLine(1))));
+        assert!(matches!(Characters::parse("\nPhilar\t3\t21\n"), Err(Error::InvalidCharacterLine(2))));
+    }
+
+    #[test]
+    fn test_snapshot_file() {
+        let snapshot = Snapshot {
+            tick: 1200,
+            weather: Weather::Rain,
+            monsters: vec![SavedMonster { kind: 2, map: 3, x: 10, y: 11, hp: 40 }],
+            ground: vec![SavedGround { item: 7, count: 5, map: 3, x: 12, y: 13 }],
+        };
+        let text = snapshot.to_text();
+        assert_eq!(text, "tick 1200\nweather rain\nmonster 2 3 10 11 40\nground 7 5 3 12 13\n");
+        assert_eq!(Snapshot::parse(&text).unwrap(), snapshot);
+
+        assert!(matches!(Snapshot::parse("tick 1\nweather hail\n"), Err(Error::InvalidSnapshotLine(2))));
+        assert!(matches!(Snapshot::parse("monster 2 3 10\n"), Err(Error::InvalidSnapshotLine(1))));
+    }
+}
@@ -0,0 +1,265 @@
+//! Keeping the maps populated from the spawn table.
+//!
+//! Each line of the spawn table is a region with a cap on how many of its
+//! monster live at once. All of them spawn on the first tick; a monster that
+//! dies comes back after the respawn delay, stretched or shortened at random
+//! by up to the jitter so a cleared region doesn't refill all in the same
+//! tick. A region never holds more than its cap, however the timers fall.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use core_rules::random::Rng;
+use core_rules::table::SpawnEntry;
+
+use crate::world::{EntityId, EntityKind, World};
+
+/// Random tiles tried per spawn before waiting for the next tick
+const PLACEMENT_TRIES: u32 = 16;
+
+pub type RegionId = usize;
+
+struct Region {
+    entry: SpawnEntry,
+    /// Ticks
+    delay: u64,
+    alive: BTreeSet<EntityId>,
+    /// The ticks the waiting spawns are due
+    timers: Vec<u64>,
+}
+
+/// How a region is doing, for the admin console
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionCount {
+    pub map: u32,
+    pub monster: u32,
+    pub alive: usize,
+    pub cap: u32,
+    /// Spawns waiting for their timer
+    pub waiting: usize,
+}
+
+/// A monster the spawner wants placed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Spawn {
+    pub region: RegionId,
+    pub monster: u32,
+    pub map: u32,
+    pub x: u16,
+    pub y: u16,
+}
+
+pub struct Spawner {
+    /// Milliseconds a tick
+    tick_ms: u64,
+    regions: Vec<Region>,
+    /// The region of each monster spawned
+    region_of: BTreeMap<EntityId, RegionId>,
+    rng: Rng,
+}
+
+impl Spawner {
+    /// A spawner for the spawn table, on a server ticking every `tick`
+    pub fn new(entries: &[SpawnEntry], tick: Duration, seed: u64) -> Spawner {
+        let tick_ms = tick.as_millis().max(1) as u64;
+        Spawner { tick_ms, regions: regions(entries, tick_ms), region_of: BTreeMap::new(), rng: Rng::new(seed) }
+    }
+
+    /// Starts over from a new spawn table: the monsters alive count towards
+    /// the regions that hold them, as with `adopt`, and the regions fill up
+    /// from the next tick.
+    pub fn reload(&mut self, entries: &[SpawnEntry], world: &World) {
+        self.regions = regions(entries, self.tick_ms);
+        for id in std::mem::take(&mut self.region_of).into_keys() {
+            if let Some(entity) = world.entity(id) {
+                if let EntityKind::Monster(kind) = entity.kind {
+                    self.adopt(id, kind, entity.map, entity.x, entity.y);
+                }
+            }
+        }
+    }
+
+    /// The spawns due at `tick`, on random passable tiles of their regions;
+    /// report each placed one with `spawned`.
+    pub fn due(&mut self, world: &World, tick: u64) -> Vec<Spawn> {
+        let mut spawns = Vec::new();
+        let rng = &mut self.rng;
+        for (id, region) in self.regions.iter_mut().enumerate() {
+            let entry = &region.entry;
+            // timers past the cap can never spawn
+            region.timers.truncate((entry.count as usize).saturating_sub(region.alive.len()));
+            let due = region.timers.iter().filter(|&&at| at <= tick).count();
+            for _ in 0..due {
+                let tile = (0..PLACEMENT_TRIES)
+                    .map(|_| (random_in(rng, entry.left, entry.right), random_in(rng, entry.top, entry.bottom)))
+                    .find(|&(x, y)| world.is_passable(entry.map, x, y));
+                if let Some((x, y)) = tile {
+                    spawns.push(Spawn { region: id, monster: entry.monster, map: entry.map, x, y });
+                }
+            }
+        }
+        spawns
+    }
+
+    /// `id` was placed for `spawn`; its timer is done.
+    pub fn spawned(&mut self, spawn: &Spawn, id: EntityId, tick: u64) {
+        let region = &mut self.regions[spawn.region];
+        if let Some(index) = region.timers.iter().position(|&at| at <= tick) {
+            region.timers.swap_remove(index);
+        }
+        region.alive.insert(id);
+        self.region_of.insert(id, spawn.region);
+    }
+
+    /// Counts `id`, a monster of `kind` at `map`, `x`, `y` that was placed
+    /// otherwise, as when restoring a snapshot, in the first region of its
+    /// kind that holds it and has room; returns whether one had.
+    pub fn adopt(&mut self, id: EntityId, kind: u32, map: u32, x: u16, y: u16) -> bool {
+        let found = self.regions.iter().position(|region| {
+            let entry = &region.entry;
+            entry.monster == kind && entry.map == map
+                && (entry.left..=entry.right).contains(&x) && (entry.top..=entry.bottom).contains(&y)
+                && region.alive.len() < entry.count as usize
+        });
+        match found {
+            Some(index) => {
+                self.regions[index].alive.insert(id);
+                self.region_of.insert(id, index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// A spawned monster died at `tick`; its respawn is scheduled.
+    pub fn died(&mut self, id: EntityId, tick: u64) {
+        let region = match self.region_of.remove(&id) {
+            Some(region) => &mut self.regions[region],
+            None => return,
+        };
+        region.alive.remove(&id);
+        let spread = region.delay * region.entry.jitter as u64 / 100;
+        let delay = region.delay - spread + self.rng.below((spread * 2 + 1).min(u32::MAX as u64) as u32) as u64;
+        region.timers.push(tick + delay);
+    }
+
+    pub fn counts(&self) -> Vec<RegionCount> {
+        self.regions.iter()
+            .map(|region| RegionCount {
+                map: region.entry.map,
+                monster: region.entry.monster,
+                alive: region.alive.len(),
+                cap: region.entry.count,
+                waiting: region.timers.len(),
+            })
+            .collect()
+    }
+}
+
+fn regions(entries: &[SpawnEntry], tick_ms: u64) -> Vec<Region> {
+    entries.iter()
+        .map(|entry| Region {
+            entry: entry.clone(),
+            delay: (entry.respawn as u64 * 1000).div_ceil(tick_ms),
+            alive: BTreeSet::new(),
+            timers: vec![0; entry.count as usize],
+        })
+        .collect()
+}
+
+fn random_in(rng: &mut Rng, low: u16, high: u16) -> u16 {
+    low + rng.below((high - low) as u32 + 1) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::world::WorldConfig;
+
+    fn entry(count: u32, respawn: u32, jitter: u32) -> SpawnEntry {
+        SpawnEntry { map: 1, left: 5, top: 5, right: 9, bottom: 7, monster: 3, count, respawn, jitter }
+    }
+
+    fn world() -> World {
+        World::new(WorldConfig { start_map: 1, start_x: 0, start_y: 0 })
+    }
+
+    /// Places what's due with made up ids from `next_id`
+    fn place(spawner: &mut Spawner, world: &World, tick: u64, next_id: &mut EntityId) -> Vec<EntityId> {
+        let mut ids = Vec::new();
+        for spawn in spawner.due(world, tick) {
+            assert!((5..=9).contains(&spawn.x) && (5..=7).contains(&spawn.y));
+            spawner.spawned(&spawn, *next_id, tick);
+            ids.push(*next_id);
+            *next_id += 1;
+        }
+        ids
+    }
+
+    #[test]
+    fn test_initial_spawn_and_respawn() {
+        let world = world();
+        // 2 seconds at 100 ms ticks
+        let mut spawner = Spawner::new(&[entry(3, 2, 0)], Duration::from_millis(100), 1);
+        let mut next_id = 1;
+        let ids = place(&mut spawner, &world, 0, &mut next_id);
+        assert_eq!(ids.len(), 3);
+        assert!(place(&mut spawner, &world, 1, &mut next_id).is_empty());
+
+        spawner.died(ids[0], 10);
+        assert_eq!(spawner.counts()[0], RegionCount { map: 1, monster: 3, alive: 2, cap: 3, waiting: 1 });
+        assert!(place(&mut spawner, &world, 29, &mut next_id).is_empty());
+        assert_eq!(place(&mut spawner, &world, 30, &mut next_id).len(), 1);
+        assert_eq!(spawner.counts()[0].alive, 3);
+    }
+
+    #[test]
+    fn test_jitter() {
+        let world = world();
+        let mut spawner = Spawner::new(&[entry(50, 10, 20)], Duration::from_secs(1), 7);
+        let mut next_id = 1;
+        let ids = place(&mut spawner, &world, 0, &mut next_id);
+        for &id in ids.iter() {
+            spawner.died(id, 0);
+        }
+        // 10 ticks give or take 2
+        assert!(place(&mut spawner, &world, 7, &mut next_id).is_empty());
+        let early = place(&mut spawner, &world, 9, &mut next_id).len();
+        assert!(early > 0 && early < 50);
+        assert_eq!(early + place(&mut spawner, &world, 12, &mut next_id).len(), 50);
+    }
+
+    #[test]
+    fn test_cap() {
+        let world = world();
+        let mut spawner = Spawner::new(&[entry(2, 1, 0)], Duration::from_secs(1), 1);
+        let mut next_id = 1;
+        let ids = place(&mut spawner, &world, 0, &mut next_id);
+        // dying twice, or the same id reported dead again, queues no extra
+        spawner.died(ids[0], 0);
+        spawner.died(ids[0], 0);
+        spawner.died(99, 0);
+        assert_eq!(place(&mut spawner, &world, 5, &mut next_id).len(), 1);
+        assert_eq!(spawner.counts()[0].alive, 2);
+        assert_eq!(spawner.counts()[0].waiting, 0);
+    }
+
+    #[test]
+    fn test_adopt() {
+        let world = world();
+        let mut spawner = Spawner::new(&[entry(2, 1, 0)], Duration::from_secs(1), 1);
+        // only monsters of the kind, inside the region, up to the cap
+        assert!(!spawner.adopt(1, 4, 1, 6, 6));
+        assert!(!spawner.adopt(1, 3, 1, 6, 8));
+        assert!(spawner.adopt(1, 3, 1, 6, 6));
+        assert!(spawner.adopt(2, 3, 1, 9, 7));
+        assert!(!spawner.adopt(3, 3, 1, 5, 5));
+        let mut next_id = 10;
+        assert!(place(&mut spawner, &world, 0, &mut next_id).is_empty());
+        // and they respawn like the others
+        spawner.died(1, 0);
+        assert_eq!(place(&mut spawner, &world, 10, &mut next_id), vec![10]);
+    }
+}
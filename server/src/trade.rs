@@ -0,0 +1,263 @@
+//! Trades between two players.
+//!
+//! A trade starts with a request that the other player accepts. Both then put
+//! items on offer and confirm; once both confirmed, the offers swap owners.
+//! Changing an offer takes back both confirmations, so nobody confirms one
+//! offer and gets another.
+//!
+//! Items on offer are held in escrow: they leave the inventory when offered
+//! and go back when the offer is lowered or the trade is cancelled. An item
+//! is always in exactly one place, so offering the same items twice, or
+//! giving them away mid-trade, can't duplicate them.
+
+use std::collections::BTreeMap;
+
+use crate::item::ItemId;
+use crate::world::{EntityId, World};
+
+/// How far apart traders may be, in tiles
+pub const TRADE_RANGE: u16 = 3;
+
+pub type TradeId = u32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trade {
+    pub traders: [EntityId; 2],
+    /// What each trader offers, held in escrow
+    pub offers: [BTreeMap<ItemId, u32>; 2],
+    pub confirmed: [bool; 2],
+}
+
+impl Trade {
+    /// The index of `id` among the traders
+    pub fn side(&self, id: EntityId) -> usize {
+        if self.traders[0] == id { 0 } else { 1 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// Still going
+    Open(TradeId),
+    /// The items changed hands
+    Completed([EntityId; 2]),
+}
+
+#[derive(Default)]
+pub struct Trades {
+    /// The open request of each requester
+    requests: BTreeMap<EntityId, EntityId>,
+    trades: BTreeMap<TradeId, Trade>,
+    trade_of: BTreeMap<EntityId, TradeId>,
+    next_id: TradeId,
+}
+
+impl Trades {
+    pub fn new() -> Trades {
+        Trades::default()
+    }
+
+    pub fn trade(&self, id: TradeId) -> Option<&Trade> {
+        self.trades.get(&id)
+    }
+
+    pub fn trade_of(&self, id: EntityId) -> Option<TradeId> {
+        self.trade_of.get(&id).cloned()
+    }
+
+    /// Asks `target` to trade; both must be free and in range.
+    pub fn request(&mut self, world: &World, from: EntityId, target: EntityId) -> bool {
+        let allowed = from != target && self.trade_of(from).is_none() && self.trade_of(target).is_none()
+            && in_range(world, from, target);
+        if allowed {
+            self.requests.insert(from, target);
+        }
+        allowed
+    }
+
+    /// Opens the trade `from` asked `target` for.
+    pub fn accept(&mut self, world: &World, target: EntityId, from: EntityId) -> Option<TradeId> {
+        if self.requests.get(&from) != Some(&target) || self.trade_of(target).is_some()
+            || self.trade_of(from).is_some() || !in_range(world, from, target) {
+            return None;
+        }
+        self.requests.remove(&from);
+        self.requests.remove(&target);
+        let id = self.next_id;
+        self.next_id += 1;
+        self.trades.insert(id, Trade {
+            traders: [from, target],
+            offers: [BTreeMap::new(), BTreeMap::new()],
+            confirmed: [false, false],
+        });
+        self.trade_of.insert(from, id);
+        self.trade_of.insert(target, id);
+        Some(id)
+    }
+
+    /// Sets how many of an item `id` offers, moving the difference between
+    /// the inventory and escrow; fails if the inventory is short.
+    pub fn offer(&mut self, world: &mut World, id: EntityId, item: ItemId, count: u32) -> Option<TradeId> {
+        let trade_id = self.trade_of(id)?;
+        let trade = self.trades.get_mut(&trade_id).unwrap();
+        let side = trade.side(id);
+        let offered = trade.offers[side].get(&item).cloned().unwrap_or(0);
+        let inventory = world.inventory_mut(id)?;
+        if count > offered {
+            if !inventory.take(item, count - offered) {
+                return None;
+            }
+        } else {
+            inventory.add(item, offered - count);
+        }
+        if count == 0 {
+            trade.offers[side].remove(&item);
+        } else {
+            trade.offers[side].insert(item, count);
+        }
+        trade.confirmed = [false, false];
+        Some(trade_id)
+    }
+
+    /// Confirms the offers as they are; the second confirmation completes
+    /// the trade.
+    pub fn confirm(&mut self, world: &mut World, id: EntityId) -> Option<Outcome> {
+        let trade_id = self.trade_of(id)?;
+        let trade = self.trades.get_mut(&trade_id).unwrap();
+        let side = trade.side(id);
+        trade.confirmed[side] = true;
+        if trade.confirmed != [true, true] {
+            return Some(Outcome::Open(trade_id));
+        }
+        let trade = self.close(trade_id);
+        for side in 0..2 {
+            let receiver = trade.traders[1 - side];
+            release(world, receiver, &trade.offers[side]);
+        }
+        Some(Outcome::Completed(trade.traders))
+    }
+
+    /// Calls off the trade of `id`, or its request; the offers go back.
+    /// Returns the traders of a cancelled trade.
+    pub fn cancel(&mut self, world: &mut World, id: EntityId) -> Option<[EntityId; 2]> {
+        self.requests.remove(&id);
+        let trade = self.close(self.trade_of(id)?);
+        for side in 0..2 {
+            release(world, trade.traders[side], &trade.offers[side]);
+        }
+        Some(trade.traders)
+    }
+
+    /// Cancels the trade of `id` if the traders are out of range.
+    pub fn check_range(&mut self, world: &mut World, id: EntityId) -> Option<[EntityId; 2]> {
+        let traders = self.trades[&self.trade_of(id)?].traders;
+        if in_range(world, traders[0], traders[1]) {
+            return None;
+        }
+        self.cancel(world, id)
+    }
+
+    fn close(&mut self, trade_id: TradeId) -> Trade {
+        let trade = self.trades.remove(&trade_id).unwrap();
+        for trader in trade.traders.iter() {
+            self.trade_of.remove(trader);
+        }
+        trade
+    }
+}
+
+fn in_range(world: &World, a: EntityId, b: EntityId) -> bool {
+    match (world.entity(a), world.entity(b)) {
+        (Some(a), Some(b)) => a.distance(b).is_some_and(|distance| distance <= TRADE_RANGE),
+        _ => false,
+    }
+}
+
+/// Puts items out of escrow into an inventory
+fn release(world: &mut World, id: EntityId, items: &BTreeMap<ItemId, u32>) {
+    if let Some(inventory) = world.inventory_mut(id) {
+        for (&item, &count) in items.iter() {
+            inventory.add(item, count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::world::WorldConfig;
+
+    const GOLD: ItemId = 1;
+    const SWORD: ItemId = 2;
+
+    fn setup() -> (World, Trades, EntityId, EntityId) {
+        let mut world = World::new(WorldConfig { start_map: 1, start_x: 10, start_y: 10 });
+        let a = world.spawn_player("a");
+        let b = world.spawn_player("b");
+        world.inventory_mut(a).unwrap().add(GOLD, 100);
+        world.inventory_mut(b).unwrap().add(SWORD, 1);
+        (world, Trades::new(), a, b)
+    }
+
+    fn count(world: &World, id: EntityId, item: ItemId) -> u32 {
+        world.entity(id).unwrap().inventory.count(item)
+    }
+
+    #[test]
+    fn test_trade() {
+        let (mut world, mut trades, a, b) = setup();
+        assert_eq!(trades.accept(&world, b, a), None);
+        assert!(trades.request(&world, a, b));
+        let id = trades.accept(&world, b, a).unwrap();
+        assert!(!trades.request(&world, a, b));
+
+        assert_eq!(trades.offer(&mut world, a, GOLD, 101), None);
+        assert_eq!(trades.offer(&mut world, a, GOLD, 60), Some(id));
+        assert_eq!(count(&world, a, GOLD), 40);
+        assert_eq!(trades.offer(&mut world, b, SWORD, 1), Some(id));
+        assert_eq!(trades.confirm(&mut world, a), Some(Outcome::Open(id)));
+        // lowering the offer gives back the difference and the confirmation
+        assert_eq!(trades.offer(&mut world, a, GOLD, 50), Some(id));
+        assert_eq!(count(&world, a, GOLD), 50);
+        assert_eq!(trades.trade(id).unwrap().confirmed, [false, false]);
+
+        assert_eq!(trades.confirm(&mut world, b), Some(Outcome::Open(id)));
+        assert_eq!(trades.confirm(&mut world, a), Some(Outcome::Completed([a, b])));
+        assert_eq!((count(&world, a, GOLD), count(&world, a, SWORD)), (50, 1));
+        assert_eq!((count(&world, b, GOLD), count(&world, b, SWORD)), (50, 0));
+        assert_eq!(trades.trade_of(a), None);
+    }
+
+    #[test]
+    fn test_cancel_returns_escrow() {
+        let (mut world, mut trades, a, b) = setup();
+        trades.request(&world, a, b);
+        trades.accept(&world, b, a).unwrap();
+        trades.offer(&mut world, a, GOLD, 100).unwrap();
+        // escrowed items can't be offered again
+        assert_eq!(trades.offer(&mut world, a, GOLD, 101), None);
+        assert_eq!(trades.cancel(&mut world, b), Some([a, b]));
+        assert_eq!(count(&world, a, GOLD), 100);
+        assert_eq!(count(&world, b, SWORD), 1);
+        assert_eq!(trades.confirm(&mut world, a), None);
+    }
+
+    #[test]
+    fn test_range() {
+        let (mut world, mut trades, a, b) = setup();
+        for x in 11..=14 {
+            assert!(world.walk(b, x, 10));
+        }
+        assert!(!trades.request(&world, a, b));
+        assert!(world.walk(b, 13, 10));
+        assert!(trades.request(&world, a, b));
+        trades.accept(&world, b, a).unwrap();
+        trades.offer(&mut world, b, SWORD, 1).unwrap();
+        assert_eq!(trades.check_range(&mut world, a), None);
+
+        assert!(world.walk(b, 14, 10));
+        assert_eq!(trades.check_range(&mut world, b), Some([a, b]));
+        assert_eq!(count(&world, b, SWORD), 1);
+    }
+}
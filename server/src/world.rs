@@ -0,0 +1,509 @@
+//! The state of the game world and the rules that change it.
+//!
+//! Nothing in here knows about sessions or packets; the world only answers
+//! whether an action is allowed and applies it, so the rules can be tested on
+//! their own.
+//!
+//! Fights follow `core_rules::combat` once the rule tables are loaded with
+//! `set_tables`; until then every hit lands for `ATTACK_DAMAGE`. The skills
+//! with a range reach targets in sight by `core_rules::sight`, and need the
+//! tables.
+//!
+//! Items on the ground are entities too, so they come into view like
+//! everything else; they have no hit points and can't be hit or moved.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+use core_rules::combat::{self, CombatParams, Combatant, Strike};
+use core_rules::random::Rng;
+use core_rules::sight;
+use core_rules::table::{Stats, Tables};
+
+use crate::grid::SpatialGrid;
+use crate::item::{Inventory, ItemId};
+use crate::map::ServerMap;
+use crate::save::Character;
+
+pub type EntityId = u32;
+
+/// Hit points of a new player, without rule tables
+pub const PLAYER_HP: u16 = 100;
+/// Damage of a single hit, without rule tables
+pub const ATTACK_DAMAGE: u16 = 10;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EntityKind {
+    Player,
+    /// A monster of the monster table
+    Monster(u32),
+    /// Items lying on the ground
+    Item { item: ItemId, count: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entity {
+    pub id: EntityId,
+    pub kind: EntityKind,
+    pub name: String,
+    pub map: u32,
+    pub x: u16,
+    pub y: u16,
+    pub hp: u16,
+    pub level: u32,
+    pub inventory: Inventory,
+}
+
+impl Entity {
+    pub fn is_player(&self) -> bool {
+        self.kind == EntityKind::Player
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.hp > 0
+    }
+
+    /// Whether `other` is on the same map and at most a tile away, diagonals
+    /// included
+    pub fn is_next_to(&self, other: &Entity) -> bool {
+        self.distance(other).is_some_and(|distance| distance <= 1)
+    }
+
+    /// Tiles to `other` with diagonal steps, `None` on another map
+    pub fn distance(&self, other: &Entity) -> Option<u16> {
+        if self.map == other.map {
+            Some(self.x.abs_diff(other.x).max(self.y.abs_diff(other.y)))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct WorldConfig {
+    /// Where new players appear
+    pub start_map: u32,
+    pub start_x: u16,
+    pub start_y: u16,
+}
+
+pub struct World {
+    pub config: WorldConfig,
+    /// Maps without a loaded server map are open fields
+    maps: HashMap<u32, ServerMap>,
+    entities: BTreeMap<EntityId, Entity>,
+    grid: SpatialGrid,
+    next_id: EntityId,
+    tables: Option<Tables>,
+    pub combat: CombatParams,
+    /// Rolls of the fights
+    rng: Rng,
+}
+
+impl World {
+    pub fn new(config: WorldConfig) -> World {
+        World {
+            config,
+            maps: HashMap::new(),
+            entities: BTreeMap::new(),
+            grid: SpatialGrid::new(),
+            next_id: 1,
+            tables: None,
+            combat: CombatParams::default(),
+            rng: Rng::new(0),
+        }
+    }
+
+    /// Fights by the tables from now on, with rolls from `seed`
+    pub fn set_tables(&mut self, tables: Tables, seed: u64) {
+        self.tables = Some(tables);
+        self.rng = Rng::new(seed);
+    }
+
+    /// Swaps in new tables, keeping the rolls going; the entities stay as
+    /// they are. Returns the old tables.
+    pub fn replace_tables(&mut self, tables: Tables) -> Option<Tables> {
+        self.tables.replace(tables)
+    }
+
+    pub fn tables(&self) -> Option<&Tables> {
+        self.tables.as_ref()
+    }
+
+    pub fn add_map(&mut self, map: ServerMap) {
+        self.maps.insert(map.number, map);
+    }
+
+    pub fn entity(&self, id: EntityId) -> Option<&Entity> {
+        self.entities.get(&id)
+    }
+
+    pub fn inventory_mut(&mut self, id: EntityId) -> Option<&mut Inventory> {
+        self.entities.get_mut(&id).map(|entity| &mut entity.inventory)
+    }
+
+    /// All entities, by id
+    pub fn entities(&self) -> impl Iterator<Item = &Entity> {
+        self.entities.values()
+    }
+
+    /// The entities at most `radius` tiles away from the position, by id
+    pub fn near(&self, map: u32, x: u16, y: u16, radius: u16) -> Vec<EntityId> {
+        self.grid.candidates(map, x, y, radius).into_iter()
+            .filter(|id| {
+                let entity = &self.entities[id];
+                entity.x.abs_diff(x) <= radius && entity.y.abs_diff(y) <= radius
+            })
+            .collect()
+    }
+
+    /// Adds a player at the start position
+    pub fn spawn_player(&mut self, name: &str) -> EntityId {
+        let id = self.next_id;
+        self.next_id += 1;
+        let config = self.config;
+        let hp = match self.tables.as_ref().and_then(|tables| tables.level(1)) {
+            Some(level) => level.hp.min(u16::MAX as u32) as u16,
+            None => PLAYER_HP,
+        };
+        self.entities.insert(id, Entity {
+            id,
+            kind: EntityKind::Player,
+            name: name.into(),
+            map: config.start_map,
+            x: config.start_x,
+            y: config.start_y,
+            hp,
+            level: 1,
+            inventory: Inventory::new(),
+        });
+        self.grid.insert(id, config.start_map, config.start_x, config.start_y);
+        id
+    }
+
+    /// Adds a player as it was saved; one saved dead comes back with the hit
+    /// points of a new player.
+    pub fn restore_player(&mut self, name: &str, character: &Character) -> EntityId {
+        let id = self.spawn_player(name);
+        let entity = self.entities.get_mut(&id).unwrap();
+        self.grid.remove(id, entity.map, entity.x, entity.y);
+        entity.map = character.map;
+        entity.x = character.x;
+        entity.y = character.y;
+        if character.hp > 0 {
+            entity.hp = character.hp;
+        }
+        entity.level = character.level;
+        for &(item, count) in character.items.iter() {
+            entity.inventory.add(item, count);
+        }
+        self.grid.insert(id, character.map, character.x, character.y);
+        id
+    }
+
+    /// Sets the hit points of a live entity, as when restoring it
+    pub fn set_hp(&mut self, id: EntityId, hp: u16) -> bool {
+        match self.entities.get_mut(&id) {
+            Some(entity) if entity.is_alive() => {
+                entity.hp = hp;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Adds a monster of the monster table; `None` without rule tables or
+    /// for kinds that aren't in them
+    pub fn spawn_monster(&mut self, kind: u32, map: u32, x: u16, y: u16) -> Option<EntityId> {
+        let monster = self.tables.as_ref()?.monsters.get(&kind)?;
+        let entity = Entity {
+            id: self.next_id,
+            kind: EntityKind::Monster(kind),
+            name: monster.name.clone(),
+            map,
+            x,
+            y,
+            hp: monster.hp.min(u16::MAX as u32) as u16,
+            level: monster.level,
+            inventory: Inventory::new(),
+        };
+        let id = entity.id;
+        self.next_id += 1;
+        self.entities.insert(id, entity);
+        self.grid.insert(id, map, x, y);
+        Some(id)
+    }
+
+    /// Puts items on the ground
+    pub fn drop_item(&mut self, item: ItemId, count: u32, map: u32, x: u16, y: u16) -> EntityId {
+        let name = match self.tables.as_ref().and_then(|tables| tables.items.get(&item)) {
+            Some(stats) => stats.name.clone(),
+            None => format!("Item {}", item),
+        };
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entities.insert(id, Entity {
+            id,
+            kind: EntityKind::Item { item, count },
+            name,
+            map,
+            x,
+            y,
+            hp: 0,
+            level: 0,
+            inventory: Inventory::new(),
+        });
+        self.grid.insert(id, map, x, y);
+        id
+    }
+
+    /// Moves the items on the ground `ground` into the inventory of a player
+    /// next to them; returns what was picked up.
+    pub fn pick_up(&mut self, player: EntityId, ground: EntityId) -> Option<(ItemId, u32)> {
+        let (p, g) = (self.entities.get(&player)?, self.entities.get(&ground)?);
+        let (item, count) = match g.kind {
+            EntityKind::Item { item, count } => (item, count),
+            _ => return None,
+        };
+        if !p.is_player() || !p.is_alive() || !p.is_next_to(g) {
+            return None;
+        }
+        self.remove(ground);
+        self.entities.get_mut(&player)?.inventory.add(item, count);
+        Some((item, count))
+    }
+
+    pub fn remove(&mut self, id: EntityId) -> Option<Entity> {
+        let entity = self.entities.remove(&id)?;
+        self.grid.remove(id, entity.map, entity.x, entity.y);
+        Some(entity)
+    }
+
+    pub fn is_passable(&self, map: u32, x: u16, y: u16) -> bool {
+        match self.maps.get(&map) {
+            Some(server_map) => server_map.is_passable(x as u32, y as u32),
+            None => true,
+        }
+    }
+
+    /// Moves an entity a single step; returns whether it moved.
+    pub fn walk(&mut self, id: EntityId, x: u16, y: u16) -> bool {
+        let allowed = match self.entities.get(&id) {
+            Some(entity) => {
+                entity.is_alive()
+                    && (entity.x, entity.y) != (x, y)
+                    && entity.x.abs_diff(x) <= 1 && entity.y.abs_diff(y) <= 1
+                    && self.is_passable(entity.map, x, y)
+            }
+            None => false,
+        };
+        if allowed {
+            let entity = self.entities.get_mut(&id).unwrap();
+            self.grid.relocate(id, entity.map, (entity.x, entity.y), (x, y));
+            entity.x = x;
+            entity.y = y;
+        }
+        allowed
+    }
+
+    /// Strikes a neighbouring entity; returns the damage dealt, 0 for a miss,
+    /// and the target's hit points left, if the attack was possible.
+    pub fn attack(&mut self, attacker: EntityId, target: EntityId) -> Option<(u16, u16)> {
+        if attacker == target {
+            return None;
+        }
+        let (a, t) = (self.entities.get(&attacker)?, self.entities.get(&target)?);
+        if !a.is_alive() || !t.is_alive() || !a.is_next_to(t) {
+            return None;
+        }
+        self.strike(attacker, target, Stats::default())
+    }
+
+    /// Uses a skill with a range on an entity in sight; returns what `attack`
+    /// does, if the player has the level for the skill and the target is in
+    /// its reach.
+    pub fn use_skill(&mut self, attacker: EntityId, skill: u32, target: EntityId) -> Option<(u16, u16)> {
+        if attacker == target {
+            return None;
+        }
+        let skill = self.tables.as_ref()?.skills.get(&skill).filter(|skill| skill.range > 0)?;
+        let (a, t) = (self.entities.get(&attacker)?, self.entities.get(&target)?);
+        if !a.is_player() || a.level < skill.level || !a.is_alive() || !t.is_alive() || a.map != t.map {
+            return None;
+        }
+        let clear = |(x, y): (u16, u16)| self.is_passable(a.map, x, y);
+        if !sight::in_reach(clear, (a.x, a.y), (t.x, t.y), skill.range) {
+            return None;
+        }
+        let bonus = skill.stats;
+        self.strike(attacker, target, bonus)
+    }
+
+    /// Hits `target` for what the tables give, `attacker` having `bonus` on
+    /// top of their stats
+    fn strike(&mut self, attacker: EntityId, target: EntityId, bonus: Stats) -> Option<(u16, u16)> {
+        let (a, t) = (self.entities.get(&attacker)?, self.entities.get(&target)?);
+        let damage = match self.tables {
+            Some(ref tables) => {
+                let (mut a, t) = (combatant(tables, a), combatant(tables, t));
+                a.stats = a.stats + bonus;
+                match combat::strike(&a, &t, &self.combat, &mut self.rng) {
+                    Strike::Hit(damage) => damage.min(u16::MAX as u32) as u16,
+                    Strike::Miss => 0,
+                }
+            }
+            None => ATTACK_DAMAGE,
+        };
+        let target = self.entities.get_mut(&target)?;
+        let amount = damage.min(target.hp);
+        target.hp -= amount;
+        Some((amount, target.hp))
+    }
+}
+
+/// The combat side of an entity; players are at their level or the highest
+/// in the table
+fn combatant(tables: &Tables, entity: &Entity) -> Combatant {
+    let found = match entity.kind {
+        EntityKind::Player => tables.level(entity.level).or_else(|| tables.levels.last())
+            .map(|level| Combatant::player(level, &[])),
+        EntityKind::Monster(kind) => tables.monsters.get(&kind).map(Combatant::monster),
+        EntityKind::Item { .. } => None,
+    };
+    found.unwrap_or(Combatant { level: entity.level, stats: Default::default() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> World {
+        World::new(WorldConfig { start_map: 3, start_x: 10, start_y: 10 })
+    }
+
+    #[test]
+    fn test_walk() {
+        let mut world = world();
+        let id = world.spawn_player("a");
+        assert!(world.walk(id, 11, 11));
+        assert!(!world.walk(id, 13, 11));
+        assert!(!world.walk(id, 11, 11));
+        assert!(!world.walk(99, 11, 12));
+
+        // the server map blocks everything but its first row
+        let mut passable = vec![0; 16 * 16 / 8];
+        passable[0] = 0xFF;
+        passable[1] = 0xFF;
+        world.add_map(ServerMap { number: 3, size_x: 16, size_y: 16, passable, warps: Vec::new(), spawns: Vec::new() });
+        assert!(!world.walk(id, 11, 12));
+        assert_eq!((world.entity(id).unwrap().x, world.entity(id).unwrap().y), (11, 11));
+    }
+
+    #[test]
+    fn test_attack() {
+        let mut world = world();
+        let a = world.spawn_player("a");
+        let b = world.spawn_player("b");
+        assert_eq!(world.attack(a, b), Some((ATTACK_DAMAGE, PLAYER_HP - ATTACK_DAMAGE)));
+        assert_eq!(world.attack(a, a), None);
+
+        world.walk(b, 11, 11);
+        world.walk(b, 12, 12);
+        assert_eq!(world.attack(a, b), None);
+
+        world.walk(b, 11, 11);
+        for _ in 1..PLAYER_HP / ATTACK_DAMAGE {
+            world.attack(a, b).unwrap();
+        }
+        assert!(!world.entity(b).unwrap().is_alive());
+        assert_eq!(world.attack(a, b), None);
+        assert!(!world.walk(b, 10, 11));
+    }
+
+    #[test]
+    fn test_pick_up() {
+        let mut world = world();
+        let a = world.spawn_player("a");
+        let coins = world.drop_item(7, 30, 3, 12, 10);
+        assert_eq!(world.entity(coins).unwrap().name, "Item 7");
+        assert_eq!(world.near(3, 12, 10, 0), vec![coins]);
+        // out of reach, and not an item
+        assert_eq!(world.pick_up(a, coins), None);
+        assert!(world.walk(a, 11, 10));
+        assert_eq!(world.attack(a, coins), None);
+        assert_eq!(world.pick_up(coins, a), None);
+
+        assert_eq!(world.pick_up(a, coins), Some((7, 30)));
+        assert!(world.entity(coins).is_none());
+        assert!(world.near(3, 12, 10, 0).is_empty());
+        assert_eq!(world.entity(a).unwrap().inventory.count(7), 30);
+        assert_eq!(world.pick_up(a, coins), None);
+    }
+
+    #[test]
+    fn test_attack_by_tables() {
+        let levels = "level\texp\thp\tattack\tdefense\taccuracy\tevasion\n1\t0\t50\t20\t20\t10\t10\n";
+        let tables = Tables { levels: core_rules::table::parse_levels(levels).unwrap(), ..Tables::default() };
+        let mut world = world();
+        world.set_tables(tables, 1);
+        let a = world.spawn_player("a");
+        let b = world.spawn_player("b");
+        assert_eq!(world.entity(b).unwrap().hp, 50);
+
+        // 20² / (20 + 20) = 10 give or take 1, when it hits
+        let mut hp = 50;
+        for _ in 0..4 {
+            let (amount, left) = world.attack(a, b).unwrap();
+            assert!(amount == 0 || (9..=11).contains(&amount));
+            hp -= amount;
+            assert_eq!(left, hp);
+        }
+    }
+
+    #[test]
+    fn test_use_skill() {
+        let levels = "level\texp\thp\tattack\tdefense\taccuracy\tevasion\n1\t0\t50\t20\t20\t1000\t0\n";
+        let skills = "id\tname\tlevel\tattack\tdefense\taccuracy\tevasion\trange\n\
+                      1\tFocus\t1\t0\t0\t5\t0\t0\n2\tShot\t1\t20\t0\t0\t0\t3\n3\tVolley\t2\t0\t0\t0\t0\t5\n";
+        let tables = Tables {
+            levels: core_rules::table::parse_levels(levels).unwrap(),
+            skills: core_rules::table::parse_skills(skills).unwrap(),
+            ..Tables::default()
+        };
+        let mut world = world();
+        let a = world.spawn_player("a");
+        let b = world.spawn_player("b");
+        // no skills without the tables
+        assert_eq!(world.use_skill(a, 2, b), None);
+        world.set_tables(tables, 1);
+
+        // a wall at x = 12 down to y = 11, in a map otherwise open
+        let mut passable = vec![0xFF; 16 * 16 / 8];
+        for y in 0..12 {
+            let idx = y * 16 + 12;
+            passable[idx / 8] &= !(1 << (idx % 8));
+        }
+        world.add_map(ServerMap { number: 3, size_x: 16, size_y: 16, passable, warps: Vec::new(), spawns: Vec::new() });
+        for &(x, y) in [(11, 11), (11, 12), (12, 12)].iter() {
+            assert!(world.walk(b, x, y));
+        }
+
+        // 40² / (40 + 20) = 26 give or take 2, when it hits
+        let (amount, hp) = world.use_skill(a, 2, b).unwrap();
+        assert!((amount == 0 || (24..=28).contains(&amount)) && hp == PLAYER_HP - amount);
+        // passive, too high a level, unknown, or on themselves
+        assert_eq!(world.use_skill(a, 1, b), None);
+        assert_eq!(world.use_skill(a, 3, b), None);
+        assert_eq!(world.use_skill(a, 9, b), None);
+        assert_eq!(world.use_skill(a, 2, a), None);
+
+        // behind the wall, then out of range
+        assert!(world.walk(b, 13, 12) && world.walk(b, 13, 11) && world.walk(b, 13, 10));
+        assert_eq!(world.use_skill(a, 2, b), None);
+        assert!(world.walk(b, 13, 11) && world.walk(b, 13, 12) && world.walk(b, 14, 13) && world.walk(b, 14, 14));
+        assert_eq!(world.use_skill(a, 2, b), None);
+        assert!(world.walk(b, 13, 13));
+        assert!(world.use_skill(a, 2, b).is_some());
+        assert!(world.use_skill(b, 2, a).is_some());
+    }
+}
@@ -0,0 +1,767 @@
+//! Whole-game scenarios against the in-process server; see `server::harness`.
+
+extern crate core_net;
+extern crate core_rules;
+extern crate server;
+
+use std::time::Duration;
+
+use core_net::packet::Packet;
+use core_rules::ambiance::Weather;
+use core_rules::table::{parse_drops, parse_levels, parse_monsters, parse_skills, parse_spawns, Tables};
+
+use server::admin;
+use server::ambiance::{Ambiance, AmbianceConfig};
+use server::anomaly::{Anomaly, AnomalyConfig, Detector, Response};
+use server::audit::AuditEvent;
+use server::ground::{DropConfig, GroundItems};
+use server::harness::{ClientId, Harness};
+use server::limit::{Category, LimitConfig, Limiter, Overflow, Rate};
+use server::map::ServerMap;
+use server::save::{Characters, Snapshot};
+use server::spawn::Spawner;
+use server::world::{World, WorldConfig, ATTACK_DAMAGE, PLAYER_HP};
+
+fn harness() -> Harness {
+    Harness::new(World::new(WorldConfig { start_map: 3, start_x: 20, start_y: 20 }))
+}
+
+#[test]
+fn test_login() {
+    let mut h = harness();
+    let a = h.connect("Philar");
+    h.step();
+    let b = h.connect("Azlar");
+    h.step();
+
+    let (id_a, id_b) = (h.id(a), h.id(b));
+    let ambiance = Packet::Ambiance { minute: 8 * 60, day_length: 2 * 60 * 60, weather: 0 };
+    assert_eq!(h.client_mut(a).take_new(), vec![
+        Packet::LoginOk { id: id_a, map: 3, x: 20, y: 20 },
+        ambiance.clone(),
+        Packet::Spawn { id: id_b, name: "Azlar".into(), x: 20, y: 20, hp: PLAYER_HP },
+    ]);
+    // the second player is told about the first
+    assert_eq!(h.client_mut(b).take_new(), vec![
+        Packet::LoginOk { id: id_b, map: 3, x: 20, y: 20 },
+        Packet::Spawn { id: id_a, name: "Philar".into(), x: 20, y: 20, hp: PLAYER_HP },
+        ambiance,
+    ]);
+    assert_eq!(h.server.world.entities().count(), 2);
+}
+
+#[test]
+fn test_ambiance() {
+    let mut h = harness();
+    // an hour every 10 ticks, the weather rolled every 25 and never clear
+    let config = AmbianceConfig {
+        day_length: Duration::from_secs(24),
+        start: 0,
+        weather_period: Duration::from_millis(2500),
+        weather: [0, 1, 1, 1],
+    };
+    h.server.ambiance = Ambiance::new(config, Duration::from_millis(100), 1);
+    let a = h.connect("Philar");
+    let b = h.connect("Azlar");
+    h.step();
+    for &client in [a, b].iter() {
+        assert!(h.client_mut(client).take_new().contains(&Packet::Ambiance { minute: 0, day_length: 24, weather: 0 }));
+    }
+
+    // both told the same, on the hour and when the weather turns
+    h.run(29);
+    let (told_a, told_b) = (h.client_mut(a).take_new(), h.client_mut(b).take_new());
+    assert_eq!(told_a, told_b);
+    let minutes: Vec<(u16, u8)> = told_a.iter()
+        .filter_map(|packet| match *packet {
+            Packet::Ambiance { minute, weather, .. } => Some((minute, weather)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(minutes.iter().map(|&(minute, _)| minute).collect::<Vec<_>>(), vec![60, 120, 150]);
+    assert!(minutes[2].1 != 0);
+
+    h.server.set_weather(Weather::Clear);
+    h.step();
+    assert!(h.client_mut(a).take_new().iter().any(|p| matches!(*p, Packet::Ambiance { weather: 0, .. })));
+}
+
+#[test]
+fn test_anomalies() {
+    let mut h = harness();
+    let config = AnomalyConfig { flood: Response::Disconnect, ..AnomalyConfig::default() };
+    h.server.anomalies = Detector::new(config);
+    let a = h.connect("Philar");
+    let b = h.connect("Azlar");
+    h.step();
+    let (id_a, id_b) = (h.id(a), h.id(b));
+
+    // jumping is refused and throttles: a packet a tick for a while
+    h.send(a, Packet::Walk { x: 25, y: 20 });
+    h.step();
+    for x in 21..24 {
+        h.send(a, Packet::Walk { x, y: 20 });
+    }
+    h.step();
+    assert_eq!(h.server.world.entity(id_a).map(|e| e.x), Some(21));
+
+    // hitting from afar is only logged
+    for x in (17..20).rev() {
+        h.send(b, Packet::Walk { x, y: 20 });
+        h.step();
+    }
+    h.send(b, Packet::Attack { target: id_a });
+    h.step();
+    assert!(h.client_mut(b).take_new().iter().all(|p| !matches!(*p, Packet::Damage { .. })));
+
+    // flooding disconnects, the flood unhandled
+    for _ in 0..21 {
+        h.send(b, Packet::Say { text: "spam".into() });
+    }
+    h.step();
+    assert!(!h.client(b).connected);
+    assert!(h.server.world.entity(id_b).is_none());
+    assert!(h.client_mut(a).take_new().iter().all(|p| !matches!(*p, Packet::Chat { .. })));
+
+    let flagged: Vec<(usize, Anomaly, Response)> = h.server.anomalies.take_flagged().iter()
+        .map(|flagged| (flagged.session, flagged.anomaly, flagged.response))
+        .collect();
+    assert_eq!(flagged, vec![
+        (0, Anomaly::Speed, Response::Throttle),
+        (1, Anomaly::Range, Response::Log),
+        (1, Anomaly::Flood, Response::Disconnect),
+    ]);
+    assert_eq!(h.server.anomalies.counts(), vec![(Anomaly::Flood, 1), (Anomaly::Range, 1), (Anomaly::Speed, 1)]);
+}
+
+#[test]
+fn test_rate_limits() {
+    let mut h = harness();
+    let config = LimitConfig {
+        chat: Rate { burst: 5, refill: 1 },
+        max_outgoing: 3,
+        overflow: Overflow::DropOldest,
+        ..LimitConfig::default()
+    };
+    h.server.limits = Limiter::new(config);
+    let a = h.connect("Philar");
+    let b = h.connect("Azlar");
+    h.step();
+    h.client_mut(a).take_new();
+    h.client_mut(b).take_new();
+
+    // a burst of chat is cut to 5 lines, of which both only keep the last 3
+    for idx in 0..8 {
+        h.send(a, Packet::Say { text: idx.to_string() });
+    }
+    h.step();
+    for &client in [a, b].iter() {
+        let lines: Vec<Packet> = h.client_mut(client).take_new();
+        let texts: Vec<String> = lines.into_iter()
+            .filter_map(|p| match p {
+                Packet::Chat { text, .. } => Some(text),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(texts, vec!["2", "3", "4"]);
+    }
+    assert_eq!(h.server.limits.dropped()[2], (Category::Chat, 3));
+    assert_eq!(h.server.limits.overflows(), 4);
+
+    // once the burst is back, both are let go for a full queue
+    h.server.limits.config.overflow = Overflow::Disconnect;
+    h.run(5);
+    for _ in 0..4 {
+        h.send(a, Packet::Say { text: "spam".into() });
+    }
+    h.step();
+    assert!(!h.client(a).connected && !h.client(b).connected);
+    assert_eq!(h.server.players().count(), 0);
+    assert_eq!(h.server.limits.overflows(), 6);
+}
+
+#[test]
+fn test_walk_attack_chat() {
+    let mut h = harness();
+    let a = h.connect("Philar");
+    let b = h.connect("Azlar");
+    h.step();
+    let (id_a, id_b) = (h.id(a), h.id(b));
+    h.client_mut(a).take_new();
+    h.client_mut(b).take_new();
+
+    // a walks two tiles away, where b can't reach it
+    h.send(a, Packet::Walk { x: 21, y: 20 });
+    h.send(a, Packet::Walk { x: 22, y: 20 });
+    h.step();
+    h.send(b, Packet::Attack { target: id_a });
+    h.step();
+    // back next to b, and a hits
+    h.send(a, Packet::Walk { x: 21, y: 21 });
+    h.send(a, Packet::Attack { target: id_b });
+    h.send(a, Packet::Say { text: "sorry".into() });
+    h.step();
+
+    let expected = vec![
+        Packet::Moved { id: id_a, x: 21, y: 20 },
+        Packet::Moved { id: id_a, x: 22, y: 20 },
+        Packet::Moved { id: id_a, x: 21, y: 21 },
+        Packet::Damage { attacker: id_a, target: id_b, amount: ATTACK_DAMAGE, hp: PLAYER_HP - ATTACK_DAMAGE },
+        Packet::Chat { from: id_a, text: "sorry".into() },
+    ];
+    assert_eq!(h.client_mut(a).take_new(), expected);
+    assert_eq!(h.client_mut(b).take_new(), expected);
+
+    let world = &h.server.world;
+    let entity_a = world.entity(id_a).unwrap();
+    assert_eq!((entity_a.x, entity_a.y, entity_a.hp), (21, 21, PLAYER_HP));
+    assert_eq!(world.entity(id_b).unwrap().hp, PLAYER_HP - ATTACK_DAMAGE);
+    assert_eq!(h.server.tick, 4);
+}
+
+#[test]
+fn test_invalid_walk_is_corrected() {
+    let mut h = harness();
+    let a = h.connect("Philar");
+    let b = h.connect("Azlar");
+    h.step();
+    let id_a = h.id(a);
+    h.client_mut(a).take_new();
+    h.client_mut(b).take_new();
+
+    h.send(a, Packet::Walk { x: 25, y: 20 });
+    h.step();
+    // only the walker hears about it
+    assert_eq!(h.client_mut(a).take_new(), vec![Packet::Moved { id: id_a, x: 20, y: 20 }]);
+    assert_eq!(h.client_mut(b).take_new(), vec![]);
+}
+
+#[test]
+fn test_disconnect() {
+    let mut h = harness();
+    let a = h.connect("Philar");
+    let b = h.connect("Azlar");
+    h.step();
+    let id_a = h.id(a);
+    h.client_mut(b).take_new();
+
+    h.disconnect(a);
+    h.step();
+    assert_eq!(h.client_mut(b).take_new(), vec![Packet::Despawn { id: id_a }]);
+    assert!(h.server.world.entity(id_a).is_none());
+}
+
+#[test]
+fn test_view_radius() {
+    let mut h = harness();
+    let a = h.connect("Philar");
+    let b = h.connect("Azlar");
+    h.step();
+    let (id_a, id_b) = (h.id(a), h.id(b));
+    let config = h.server.interest.config;
+    let leave = 20 + config.view_radius + config.hysteresis + 1;
+
+    for x in 21..=leave {
+        h.send(b, Packet::Walk { x, y: 20 });
+        h.step();
+    }
+    h.client_mut(a).take_new();
+    h.client_mut(b).take_new();
+    // a is out of sight, so is what it does
+    h.send(a, Packet::Say { text: "anyone?".into() });
+    h.send(a, Packet::Walk { x: 21, y: 20 });
+    h.step();
+    assert_eq!(h.client_mut(b).take_new(), vec![]);
+
+    // b's last step took it out of a's view and a's out of b's
+    let received = &h.client(a).received;
+    assert!(received.contains(&Packet::Despawn { id: id_b }));
+    assert!(h.client(b).received.contains(&Packet::Despawn { id: id_a }));
+
+    // a comes into view again where it is now
+    let back = 21 + config.view_radius;
+    for x in (back..leave).rev() {
+        h.send(b, Packet::Walk { x, y: 20 });
+        h.step();
+    }
+    let new = h.client_mut(b).take_new();
+    assert!(new.contains(&Packet::Spawn { id: id_a, name: "Philar".into(), x: 21, y: 20, hp: PLAYER_HP }));
+    assert_eq!(new.last(), Some(&Packet::Moved { id: id_b, x: back, y: 20 }));
+}
+
+#[test]
+fn test_deterministic() {
+    let run = || {
+        let mut h = harness();
+        let clients: Vec<_> = (0..4).map(|n| h.connect(&format!("bot{}", n))).collect();
+        h.step();
+        for round in 0..20u16 {
+            for &c in clients.iter() {
+                h.send(c, Packet::Walk { x: 20 + round % 2, y: 20 + (c as u16 + round) % 2 });
+                h.send(c, Packet::Say { text: format!("{}", round) });
+            }
+            h.step();
+        }
+        clients.iter().map(|&c| h.client(c).received.clone()).collect::<Vec<_>>()
+    };
+    assert_eq!(run(), run());
+}
+
+#[test]
+fn test_party_and_guild() {
+    let mut h = harness();
+    let a = h.connect("Philar");
+    let b = h.connect("Azlar");
+    h.step();
+    let (id_a, id_b) = (h.id(a), h.id(b));
+    h.client_mut(a).take_new();
+    h.client_mut(b).take_new();
+
+    h.send(a, Packet::PartyInvite { target: id_b });
+    h.send(a, Packet::GuildCreate { name: "Moonlight".into() });
+    h.step();
+    assert_eq!(h.client_mut(b).take_new(), vec![Packet::PartyInvited { leader: id_a, name: "Philar".into() }]);
+    h.send(a, Packet::GuildInvite { target: id_b });
+    h.send(b, Packet::PartyAccept { leader: id_a });
+    h.step();
+    let guild = match h.client_mut(b).take_new().as_slice() {
+        &[Packet::GuildInvited { guild, ref name }, Packet::Party { ref members }] => {
+            assert_eq!((members.as_slice(), name.as_str()), (&[id_a, id_b][..], "Moonlight"));
+            guild
+        }
+        other => panic!("unexpected packets: {:?}", other),
+    };
+    h.send(b, Packet::GuildAccept { guild });
+    h.step();
+    let joined = Packet::Guild {
+        guild,
+        name: "Moonlight".into(),
+        leader: "Philar".into(),
+        members: vec!["Azlar".into(), "Philar".into()],
+    };
+    assert_eq!(h.client_mut(b).take_new(), vec![joined.clone()]);
+
+    // the party breaks up with the leader gone, the guild stays
+    h.disconnect(a);
+    h.step();
+    assert_eq!(h.client_mut(b).take_new(), vec![
+        Packet::Party { members: Vec::new() },
+        Packet::Despawn { id: id_a },
+    ]);
+    assert!(h.server.guilds.take_changed());
+    let c = h.connect("Philar");
+    h.step();
+    assert!(h.client_mut(c).take_new().contains(&joined));
+}
+
+#[test]
+fn test_trade() {
+    let mut h = harness();
+    let a = h.connect("Philar");
+    let b = h.connect("Azlar");
+    h.step();
+    let (id_a, id_b) = (h.id(a), h.id(b));
+    h.server.world.inventory_mut(id_a).unwrap().add(1, 100);
+    h.server.world.inventory_mut(id_b).unwrap().add(2, 1);
+    h.client_mut(a).take_new();
+    h.client_mut(b).take_new();
+
+    h.send(a, Packet::TradeRequest { target: id_b });
+    h.step();
+    assert_eq!(h.client_mut(b).take_new(), vec![Packet::TradeRequested { from: id_a, name: "Philar".into() }]);
+    h.send(b, Packet::TradeAccept { from: id_a });
+    h.step();
+    h.send(a, Packet::TradeOffer { item: 1, count: 40 });
+    h.send(a, Packet::TradeConfirm);
+    // more than b has isn't offered
+    h.send(b, Packet::TradeOffer { item: 2, count: 2 });
+    h.send(b, Packet::TradeOffer { item: 2, count: 1 });
+    h.step();
+    h.client_mut(a).take_new();
+    assert_eq!(h.client_mut(b).take_new().last(), Some(&Packet::Trade {
+        with: id_a,
+        mine: vec![(2, 1)],
+        theirs: vec![(1, 40)],
+        confirmed: false,
+        they_confirmed: false,
+    }));
+
+    h.send(a, Packet::TradeConfirm);
+    h.send(b, Packet::TradeConfirm);
+    h.step();
+    assert_eq!(h.client_mut(a).take_new(), vec![
+        Packet::Trade { with: id_b, mine: vec![(1, 40)], theirs: vec![(2, 1)], confirmed: true, they_confirmed: false },
+        Packet::TradeClosed { completed: true },
+        Packet::Inventory { items: vec![(1, 60), (2, 1)] },
+    ]);
+    assert_eq!(h.client_mut(b).take_new().last(), Some(&Packet::Inventory { items: vec![(1, 40)] }));
+
+    // a cancelled trade, or a disconnect, hands the offers back
+    h.send(a, Packet::TradeRequest { target: id_b });
+    h.send(b, Packet::TradeAccept { from: id_a });
+    h.step();
+    h.send(b, Packet::TradeOffer { item: 1, count: 40 });
+    h.step();
+    h.disconnect(b);
+    h.step();
+    assert!(h.server.trades.trade_of(id_a).is_none());
+    assert!(h.client_mut(a).take_new().contains(&Packet::TradeClosed { completed: false }));
+}
+
+#[test]
+fn test_monster_attacks() {
+    let levels = "level\texp\thp\tattack\tdefense\taccuracy\tevasion\n1\t0\t100\t10\t0\t10\t5\n";
+    let monsters = "id\tname\tlevel\thp\texp\tattack\tdefense\taccuracy\tevasion\n1\tRat\t1\t30\t5\t4\t0\t100\t0\n";
+    let mut world = World::new(WorldConfig { start_map: 3, start_x: 20, start_y: 20 });
+    world.set_tables(Tables {
+        levels: parse_levels(levels).unwrap(),
+        monsters: parse_monsters(monsters).unwrap(),
+        ..Tables::default()
+    }, 1);
+    let mut h = Harness::new(world);
+    let a = h.connect("Philar");
+    h.step();
+    let id_a = h.id(a);
+    let rat = h.server.spawn_monster(1, 3, 24, 20).unwrap();
+    h.run(30);
+
+    let packets = h.client_mut(a).take_new();
+    assert!(packets.contains(&Packet::Spawn { id: rat, name: "Rat".into(), x: 24, y: 20, hp: 30 }));
+    assert!(packets.iter().any(|p| matches!(*p, Packet::Moved { id, .. } if id == rat)));
+    assert!(packets.iter().any(|p| matches!(*p, Packet::Damage { attacker, target, .. } if attacker == rat && target == id_a)));
+}
+
+#[test]
+fn test_ranged_skill() {
+    let levels = "level\texp\thp\tattack\tdefense\taccuracy\tevasion\n1\t0\t100\t10\t0\t100\t5\n";
+    let monsters = "id\tname\tlevel\thp\texp\tattack\tdefense\taccuracy\tevasion\taggressive\twander\n\
+                    1\tRat\t1\t30\t5\t4\t0\t0\t0\t0\t0\n";
+    let skills = "id\tname\tlevel\tattack\tdefense\taccuracy\tevasion\trange\n1\tShot\t1\t5\t0\t0\t0\t4\n";
+    let mut world = World::new(WorldConfig { start_map: 3, start_x: 20, start_y: 20 });
+    world.set_tables(Tables {
+        levels: parse_levels(levels).unwrap(),
+        monsters: parse_monsters(monsters).unwrap(),
+        skills: parse_skills(skills).unwrap(),
+        ..Tables::default()
+    }, 1);
+    // a wall at x = 22 from the top down to the row of the start
+    let mut passable = vec![0xFF; 32 * 32 / 8];
+    for y in 0..=20 {
+        let idx = y * 32 + 22;
+        passable[idx / 8] &= !(1 << (idx % 8));
+    }
+    world.add_map(ServerMap { number: 3, size_x: 32, size_y: 32, passable, warps: Vec::new(), spawns: Vec::new() });
+    let mut h = Harness::new(world);
+    let a = h.connect("Philar");
+    h.step();
+    let id_a = h.id(a);
+    let hidden = h.server.spawn_monster(1, 3, 24, 20).unwrap();
+    let seen = h.server.spawn_monster(1, 3, 23, 23).unwrap();
+    h.step();
+    h.client_mut(a).take_new();
+
+    // the one behind the wall is out of sight, the other is hit from afar
+    h.send(a, Packet::UseSkill { skill: 1, target: hidden });
+    h.send(a, Packet::UseSkill { skill: 1, target: seen });
+    h.step();
+    let damaged: Vec<u32> = h.client_mut(a).take_new().iter()
+        .filter_map(|packet| match *packet {
+            Packet::Damage { attacker, target, .. } if attacker == id_a => Some(target),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(damaged, vec![seen]);
+}
+
+#[test]
+fn test_monster_respawns() {
+    let levels = "level\texp\thp\tattack\tdefense\taccuracy\tevasion\n1\t0\t100\t10\t0\t100\t5\n";
+    let monsters = "id\tname\tlevel\thp\texp\tattack\tdefense\taccuracy\tevasion\taggressive\n1\tRat\t1\t1\t5\t4\t0\t0\t0\t0\n";
+    // one rat on one tile, back 3 ticks after it dies
+    let spawns = "map\tleft\ttop\tright\tbottom\tmonster\tcount\trespawn\tjitter\n3\t22\t20\t22\t20\t1\t1\t3\t0\n";
+    let mut world = World::new(WorldConfig { start_map: 3, start_x: 20, start_y: 20 });
+    let spawns = parse_spawns(spawns).unwrap();
+    world.set_tables(Tables {
+        levels: parse_levels(levels).unwrap(),
+        monsters: parse_monsters(monsters).unwrap(),
+        ..Tables::default()
+    }, 1);
+    let mut h = Harness::new(world);
+    h.server.spawner = Spawner::new(&spawns, Duration::from_secs(1), 1);
+    let a = h.connect("Philar");
+    h.send(a, Packet::Walk { x: 21, y: 20 });
+    h.step();
+    let rat = match h.client_mut(a).take_new().last() {
+        Some(&Packet::Spawn { id, x: 22, y: 20, .. }) => id,
+        other => panic!("unexpected packet: {:?}", other),
+    };
+
+    // the odd miss aside, the first hit kills it
+    let mut died = None;
+    for _ in 0..20 {
+        h.send(a, Packet::Attack { target: rat });
+        h.step();
+        if h.client_mut(a).take_new().contains(&Packet::Despawn { id: rat }) {
+            died = Some(h.server.tick);
+            break;
+        }
+    }
+    let died = died.expect("the rat never died");
+    assert!(h.server.world.entity(rat).is_none());
+    assert_eq!(h.server.spawner.counts()[0].waiting, 1);
+
+    h.run(2);
+    assert!(h.client_mut(a).take_new().is_empty());
+    h.step();
+    let packets = h.client_mut(a).take_new();
+    assert!(matches!(packets[..], [Packet::Spawn { id, x: 22, y: 20, hp: 1, .. }] if id != rat));
+    assert_eq!(h.server.tick, died + 3);
+    assert_eq!(h.server.spawner.counts()[0].alive, 1);
+}
+
+/// Rats die to the first hit and drop 5 of item 7; drops are owned for 10
+/// ticks and lie around for 30.
+fn rat_world_harness() -> Harness {
+    let levels = "level\texp\thp\tattack\tdefense\taccuracy\tevasion\n1\t0\t100\t10\t0\t100\t5\n";
+    let monsters = "id\tname\tlevel\thp\texp\tattack\tdefense\taccuracy\tevasion\taggressive\twander\n\
+                    1\tRat\t1\t1\t5\t4\t0\t0\t0\t0\t0\n";
+    let drops = "monster\titem\tchance\tmin\tmax\n1\t7\t1000\t5\t5\n";
+    let mut world = World::new(WorldConfig { start_map: 3, start_x: 20, start_y: 20 });
+    world.set_tables(Tables {
+        levels: parse_levels(levels).unwrap(),
+        monsters: parse_monsters(monsters).unwrap(),
+        drops: parse_drops(drops).unwrap(),
+        ..Tables::default()
+    }, 1);
+    let mut h = Harness::new(world);
+    h.server.ground = GroundItems::new(DropConfig { ownership: 10, lifetime: 30 }, 1);
+    h
+}
+
+/// A rat next to the start
+fn rat_harness() -> (Harness, u32) {
+    let mut h = rat_world_harness();
+    let rat = h.server.spawn_monster(1, 3, 21, 20).unwrap();
+    (h, rat)
+}
+
+/// Attacks until the target is gone; returns the id of the drop it left
+fn kill(h: &mut Harness, client: ClientId, target: u32) -> u32 {
+    for _ in 0..20 {
+        h.send(client, Packet::Attack { target });
+        h.step();
+        if h.server.world.entity(target).is_none() {
+            return h.client_mut(client).take_new().iter()
+                .filter_map(|packet| match *packet {
+                    Packet::GroundItem { id, item: 7, count: 5, x: 21, y: 20 } => Some(id),
+                    _ => None,
+                })
+                .next()
+                .expect("no drop");
+        }
+    }
+    panic!("the target never died");
+}
+
+#[test]
+fn test_drop_ownership() {
+    let (mut h, rat) = rat_harness();
+    let a = h.connect("Philar");
+    let b = h.connect("Azlar");
+    h.step();
+    let (id_a, id_b) = (h.id(a), h.id(b));
+    let drop = kill(&mut h, a, rat);
+    assert!(h.client_mut(b).take_new().iter().any(|p| matches!(*p, Packet::GroundItem { id, .. } if id == drop)));
+
+    // b didn't kill it
+    h.send(b, Packet::PickUp { id: drop });
+    h.step();
+    assert!(h.client_mut(b).take_new().is_empty());
+    assert!(h.server.world.entity(drop).is_some());
+
+    h.send(a, Packet::PickUp { id: drop });
+    h.step();
+    assert_eq!(h.client_mut(a).take_new(), vec![Packet::Despawn { id: drop }, Packet::Inventory { items: vec![(7, 5)] }]);
+    assert_eq!(h.client_mut(b).take_new(), vec![Packet::Despawn { id: drop }]);
+    assert_eq!(h.server.world.entity(id_a).unwrap().inventory.count(7), 5);
+    assert_eq!(h.server.world.entity(id_b).unwrap().inventory.count(7), 0);
+    assert!(h.server.ground.is_empty());
+}
+
+#[test]
+fn test_audit() {
+    let (mut h, rat) = rat_harness();
+    let a = h.connect("Philar");
+    let b = h.connect("Azlar");
+    h.step();
+    let id_b = h.id(b);
+    let drop = kill(&mut h, a, rat);
+    h.send(a, Packet::PickUp { id: drop });
+    h.send(a, Packet::TradeRequest { target: id_b });
+    h.step();
+    h.send(b, Packet::TradeAccept { from: h.id(a) });
+    h.step();
+    h.send(a, Packet::TradeOffer { item: 7, count: 2 });
+    h.send(a, Packet::TradeConfirm);
+    h.send(b, Packet::TradeConfirm);
+    h.step();
+    admin::execute(&mut h.server, " weather rain ");
+
+    let events: Vec<AuditEvent> = h.server.take_audit().into_iter().map(|record| record.event).collect();
+    assert_eq!(events, vec![
+        AuditEvent::Death { victim: "Rat".into(), killer: "Philar".into() },
+        AuditEvent::Pickup { player: "Philar".into(), item: 7, count: 5 },
+        // nothing went the other way
+        AuditEvent::Trade { from: "Philar".into(), to: "Azlar".into(), items: vec![(7, 2)] },
+        AuditEvent::Command { line: "weather rain".into() },
+    ]);
+    assert!(h.server.take_audit().is_empty());
+}
+
+#[test]
+fn test_drop_party_owns() {
+    let (mut h, rat) = rat_harness();
+    let a = h.connect("Philar");
+    let b = h.connect("Azlar");
+    h.step();
+    let (id_a, id_b) = (h.id(a), h.id(b));
+    h.send(a, Packet::PartyInvite { target: id_b });
+    h.step();
+    h.send(b, Packet::PartyAccept { leader: id_a });
+    h.step();
+    let drop = kill(&mut h, a, rat);
+
+    h.send(b, Packet::PickUp { id: drop });
+    h.step();
+    assert!(h.client_mut(b).take_new().contains(&Packet::Inventory { items: vec![(7, 5)] }));
+}
+
+#[test]
+fn test_pick_up_race() {
+    let (mut h, rat) = rat_harness();
+    let a = h.connect("Philar");
+    let b = h.connect("Azlar");
+    let c = h.connect("Lavita");
+    h.step();
+    let drop = kill(&mut h, c, rat);
+    h.run(10);
+    h.client_mut(a).take_new();
+    h.client_mut(b).take_new();
+
+    // once anyone may take it, both ask in the same tick; a's session comes
+    // first, whoever sent first
+    h.send(b, Packet::PickUp { id: drop });
+    h.send(a, Packet::PickUp { id: drop });
+    h.step();
+    assert_eq!(h.client_mut(a).take_new(), vec![Packet::Despawn { id: drop }, Packet::Inventory { items: vec![(7, 5)] }]);
+    assert_eq!(h.client_mut(b).take_new(), vec![Packet::Despawn { id: drop }]);
+    let (id_a, id_b) = (h.id(a), h.id(b));
+    assert_eq!(h.server.world.entity(id_a).unwrap().inventory.count(7), 5);
+    assert_eq!(h.server.world.entity(id_b).unwrap().inventory.count(7), 0);
+}
+
+#[test]
+fn test_shut_down_and_restart() {
+    let (mut h, rat) = rat_harness();
+    let a = h.connect("Philar");
+    let b = h.connect("Azlar");
+    h.step();
+    let drop = kill(&mut h, a, rat);
+    h.send(a, Packet::PickUp { id: drop });
+    h.step();
+    let rat = h.server.spawn_monster(1, 3, 21, 20).unwrap();
+    kill(&mut h, a, rat);
+    h.server.spawn_monster(1, 3, 24, 22).unwrap();
+    h.send(a, Packet::Walk { x: 20, y: 21 });
+    h.step();
+    h.client_mut(a).take_new();
+
+    // everyone is told, and nobody gets in anymore
+    h.server.shut_down("back in a minute");
+    let c = h.connect("Lavita");
+    h.step();
+    assert_eq!(h.client_mut(a).take_new(), vec![Packet::Shutdown { message: "back in a minute".into() }]);
+    assert!(h.client_mut(b).take_new().contains(&Packet::Shutdown { message: "back in a minute".into() }));
+    assert!(h.client(c).id.is_none());
+    let tick = h.server.tick;
+    let characters = h.server.characters.to_text();
+    let snapshot = h.server.snapshot().to_text();
+    assert_eq!(snapshot, format!("tick {}\nweather clear\nmonster 1 3 24 22 1\nground 7 5 3 21 20\n", tick));
+
+    // the players come back as they were, to the world as it was
+    let mut h = rat_world_harness();
+    h.server.characters = Characters::parse(&characters).unwrap();
+    h.server.restore(&Snapshot::parse(&snapshot).unwrap());
+    let a = h.connect("Philar");
+    h.step();
+    let packets = h.client_mut(a).take_new();
+    assert!(matches!(packets[0], Packet::LoginOk { map: 3, x: 20, y: 21, .. }));
+    assert!(packets.contains(&Packet::Inventory { items: vec![(7, 5)] }));
+    assert!(packets.iter().any(|p| matches!(*p, Packet::GroundItem { item: 7, count: 5, x: 21, y: 20, .. })));
+    assert!(packets.iter().any(|p| matches!(*p, Packet::Spawn { x: 24, y: 22, hp: 1, .. })));
+    assert_eq!(h.server.tick, tick + 1);
+}
+
+#[test]
+fn test_reload_tables() {
+    let levels = "level\texp\thp\tattack\tdefense\taccuracy\tevasion\n1\t0\t100\t10\t0\t100\t5\n";
+    let monsters = "id\tname\tlevel\thp\texp\tattack\tdefense\taccuracy\tevasion\n1\tRat\t1\t5\t5\t4\t0\t0\t0\n";
+    let spawns = "map\tleft\ttop\tright\tbottom\tmonster\tcount\trespawn\tjitter\n3\t22\t20\t24\t20\t1\t1\t3\t0\n";
+    let tables = Tables {
+        levels: parse_levels(levels).unwrap(),
+        monsters: parse_monsters(monsters).unwrap(),
+        spawns: parse_spawns(spawns).unwrap(),
+        ..Tables::default()
+    };
+    let mut world = World::new(WorldConfig { start_map: 3, start_x: 20, start_y: 20 });
+    world.set_tables(tables.clone(), 1);
+    let mut h = Harness::new(world);
+    h.server.spawner = Spawner::new(&tables.spawns, Duration::from_secs(1), 1);
+    h.step();
+    let rat = h.server.world.entities().map(|e| e.id).next().unwrap();
+
+    // tougher rats, two of them now
+    let reloaded = Tables {
+        monsters: parse_monsters(&monsters.replace("\t5\t5\t4", "\t9\t5\t4")).unwrap(),
+        spawns: parse_spawns(&spawns.replace("\t1\t1\t3", "\t1\t2\t3")).unwrap(),
+        ..tables
+    };
+    let changes: Vec<String> = h.server.reload_tables(reloaded).iter().map(|change| change.to_string()).collect();
+    assert_eq!(changes, vec!["monsters 1 (Rat): hp 5 -> 9", "spawns monster 1 on map 3 at 22,20-24,20: count 1 -> 2"]);
+    assert_eq!(h.server.spawner.counts()[0].alive, 1);
+    h.step();
+    let hps: Vec<(u32, u16)> = h.server.world.entities().map(|e| (e.id, e.hp)).collect();
+    assert_eq!(hps.len(), 2);
+    assert_eq!(hps[0], (rat, 5));
+    assert_eq!(hps[1].1, 9);
+    assert_eq!(h.server.spawner.counts()[0].alive, 2);
+}
+
+#[test]
+fn test_drop_expires() {
+    let (mut h, rat) = rat_harness();
+    let a = h.connect("Philar");
+    let b = h.connect("Azlar");
+    h.step();
+    let drop = kill(&mut h, a, rat);
+    let dropped = h.server.tick - 1;
+    h.client_mut(b).take_new();
+
+    // picked up in the very tick it expires
+    h.run((dropped + 30 - h.server.tick) as usize);
+    assert!(h.client_mut(b).take_new().is_empty());
+    h.send(a, Packet::PickUp { id: drop });
+    h.step();
+    assert_eq!(h.client_mut(b).take_new(), vec![Packet::Despawn { id: drop }]);
+    assert_eq!(h.server.world.entity(h.id(a)).unwrap().inventory.count(7), 5);
+
+    // and left alone
+    let rat = h.server.spawn_monster(1, 3, 21, 20).unwrap();
+    let drop = kill(&mut h, a, rat);
+    h.run(29);
+    assert!(h.client_mut(b).take_new().iter().all(|p| *p != Packet::Despawn { id: drop }));
+    h.step();
+    assert!(h.client_mut(b).take_new().contains(&Packet::Despawn { id: drop }));
+    assert!(h.server.world.entity(drop).is_none());
+    assert!(h.server.ground.is_empty());
+}